@@ -0,0 +1,42 @@
+#version 450
+
+layout(location=0) in vec4 inColor;
+layout(location=1) in vec2 inCorner;
+layout(location=2) flat in float inShape;
+layout(location=3) flat in float inSeed;
+
+layout(location=0) out vec4 outColor;
+
+// Where the rocks are lit from, in the camera's right, up and backwards directions
+const vec3 LIGHT = vec3(-0.508, 0.609, 0.609);
+
+void main() {
+    float dist = length(inCorner);
+
+    // Stars are soft dots
+    if (inShape < 0.5) {
+        if (dist > 1.0) {
+            discard;
+        }
+        float glow = 1.0 - dist;
+        outColor = vec4(inColor.rgb, inColor.a * glow * glow);
+        return;
+    }
+
+    // Rocks are lumpy discs that are lit like spheres, with the lumps and a crater picked
+    // by their seed
+    float angle = atan(inCorner.y, inCorner.x);
+    float edge = 0.8
+        + 0.1 * sin(angle * 3.0 + inSeed * 6.283)
+        + 0.06 * sin(angle * 5.0 + inSeed * 17.0);
+    if (dist > edge) {
+        discard;
+    }
+    vec2 point = inCorner / edge;
+    vec3 normal = normalize(vec3(point, sqrt(max(1.0 - dot(point, point), 0.0))));
+    float light = 0.2 + 0.8 * max(dot(normal, LIGHT), 0.0);
+    vec2 craterPos = vec2(sin(inSeed * 40.0), cos(inSeed * 23.0)) * 0.4;
+    float crater = smoothstep(0.25, 0.0, length(point - craterPos));
+
+    outColor = vec4(inColor.rgb * light * (1.0 - 0.4 * crater), inColor.a);
+}
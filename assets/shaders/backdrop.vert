@@ -0,0 +1,64 @@
+#version 450
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+layout(location=0) in vec3 pos;
+layout(location=1) in float size;
+layout(location=2) in vec4 color;
+layout(location=3) in float speed;
+layout(location=4) in float follow;
+layout(location=5) in float shape;
+layout(location=6) in float seed;
+
+layout(location=0) out vec4 fragColor;
+layout(location=1) out vec2 fragCorner;
+layout(location=2) flat out float fragShape;
+layout(location=3) flat out float fragSeed;
+
+layout(set = 0, binding = 0) uniform Transforms {
+    mat4 viewProjMatrix;
+};
+
+layout(set = 1, binding = 0) uniform Backdrop {
+    // The seconds since the backdrop was created are in w
+    vec4 cameraPos;
+    vec4 cameraRight;
+    vec4 cameraUp;
+};
+
+// The drifting sprites wrap around at -SPAN..SPAN in x (see Backdrop::ROCK_SPAN), and fade
+// out near the ends, so they don't pop in
+const float SPAN = 300.0;
+const float FADE = 0.2;
+
+const vec2 CORNERS[6] = vec2[6](
+    vec2(-1.0, -1.0),
+    vec2(-1.0, 1.0),
+    vec2(1.0, -1.0),
+    vec2(1.0, -1.0),
+    vec2(-1.0, 1.0),
+    vec2(1.0, 1.0)
+);
+
+void main() {
+    vec3 center = pos;
+    float fade = 1.0;
+    if (speed != 0.0) {
+        center.x = mod(pos.x + speed * cameraPos.w + SPAN, 2.0 * SPAN) - SPAN;
+        fade = clamp((SPAN - abs(center.x)) / (SPAN * FADE), 0.0, 1.0);
+    }
+
+    vec2 corner = CORNERS[gl_VertexIndex];
+    vec3 worldPos = center + cameraPos.xyz * follow
+        + (cameraRight.xyz * corner.x + cameraUp.xyz * corner.y) * size;
+    gl_Position = viewProjMatrix * vec4(worldPos, 1.0);
+    // Puts the sprite just in front of the far plane, so it is never cut off by it
+    gl_Position.z = gl_Position.w * 0.99999;
+
+    fragColor = vec4(color.rgb, color.a * fade);
+    fragCorner = corner;
+    fragShape = shape;
+    fragSeed = seed;
+}
@@ -3,6 +3,7 @@
 layout(location = 0) in vec3 inColor;
 layout(location = 1) in vec3 position;
 layout(location = 2) in vec3 normal;
+layout(location = 3) in float alpha;
 layout(location = 0) out vec4 outColor;
 
 void main() {
@@ -14,5 +15,5 @@ void main() {
     float diffuseStrength = max(dot(normal, lightDirection), 0.0);
 
     vec3 color = inColor * clamp(diffuseStrength + ambientStrength, 0.4, 1.0);
-    outColor = vec4(color, 1.0);
+    outColor = vec4(color, alpha);
 }
\ No newline at end of file
@@ -7,15 +7,20 @@ out gl_PerVertex {
 layout(location = 0) in vec3 pos;
 layout(location = 1) in vec3 normal;
 layout(location = 2) in vec3 color;
+layout(location = 3) in float vein;
 
-layout(location = 3) in vec4 model0;
-layout(location = 4) in vec4 model1;
-layout(location = 5) in vec4 model2;
-layout(location = 6) in vec4 model3;
+layout(location = 4) in vec4 model0;
+layout(location = 5) in vec4 model1;
+layout(location = 6) in vec4 model2;
+layout(location = 7) in vec4 model3;
+layout(location = 8) in vec4 primaryTint;
+layout(location = 9) in vec4 secondaryTint;
+layout(location = 10) in vec4 paint;
 
 layout(location = 0) out vec3 fragColor;
 layout(location = 1) out vec3 fPosition;
 layout(location = 2) out vec3 fNormal;
+layout(location = 3) out float fAlpha;
 
 
 layout(set = 0, binding = 0) uniform Transforms {
@@ -28,7 +33,8 @@ void main() {
     vec4 position = modelMatrix * vec4(pos, 1.0);
     
     gl_Position = viewProjMatrix * position;
-    fragColor = color;
+    fragColor = color * mix(primaryTint.rgb, secondaryTint.rgb, vein) * paint.rgb;
     fPosition = vec3(position);
     fNormal = normalMatrix * normal;
+    fAlpha = mix(primaryTint.a, secondaryTint.a, vein) * paint.a;
 }
\ No newline at end of file
@@ -0,0 +1,11 @@
+#version 450
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 color;
+
+layout(set = 0, binding = 0) uniform texture2D scene;
+layout(set = 0, binding = 1) uniform sampler sampler_;
+
+void main() {
+    color = texture(sampler2D(scene, sampler_), uv);
+}
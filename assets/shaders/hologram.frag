@@ -0,0 +1,28 @@
+#version 450
+
+layout(location = 0) in vec3 inColor;
+layout(location = 1) in vec3 position;
+layout(location = 2) in vec3 normal;
+layout(location = 3) in float alpha;
+layout(location = 0) out vec4 outColor;
+
+// Every other pair of pixel rows is dimmed by this much
+const float SCANLINE_DIM = 0.45;
+// How much brighter the edges that face sideways are, like the rim of a hologram
+const float RIM_GLOW = 0.6;
+
+void main() {
+    float ambientStrength = 0.25;
+    vec3 lightPos = vec3(3.0, 3.0, 3.0);
+
+    vec3 normal = normalize(normal);
+    vec3 lightDirection = normalize(lightPos - position);
+    float diffuseStrength = max(dot(normal, lightDirection), 0.0);
+    float light = clamp(diffuseStrength + ambientStrength, 0.4, 1.0);
+
+    float rim = 1.0 - abs(normal.z);
+    float scanline = mod(floor(gl_FragCoord.y / 2.0), 2.0) < 1.0 ? 1.0 : 1.0 - SCANLINE_DIM;
+
+    vec3 color = inColor * (light + rim * RIM_GLOW);
+    outColor = vec4(color, alpha * scanline);
+}
@@ -5,8 +5,9 @@ out gl_PerVertex {
 };
 
 layout(location=0) in vec3 pos1;
-layout(location=1) in vec3 pos2;
-layout(location=2) in vec3 color;
+layout(location=1) in vec3 color1;
+layout(location=2) in vec3 pos2;
+layout(location=3) in vec3 color2;
 
 layout(location=0) out vec3 fragColor;
 
@@ -17,9 +18,9 @@ layout(set = 0, binding = 0) uniform Transforms {
 void main() {
     if (gl_VertexIndex == 0) {
         gl_Position = viewProjMatrix * vec4(pos1, 1.0);
+        fragColor = color1;
     } else {
         gl_Position = viewProjMatrix * vec4(pos2, 1.0);
+        fragColor = color2;
     }
-
-    fragColor = color;
-}
\ No newline at end of file
+}
@@ -0,0 +1,18 @@
+#version 450
+
+// A rectangle of the loading screen, from its min to its max corner in clip space
+layout(location=0) in vec4 rect;
+layout(location=1) in vec4 color;
+
+layout(location=0) out vec4 fragColor;
+
+void main() {
+    // Two triangles: corners 0-1-2 and 3-2-1
+    int corner = gl_VertexIndex < 3 ? gl_VertexIndex : 6 - gl_VertexIndex;
+    vec2 pos = vec2(
+        (corner & 1) == 0 ? rect.x : rect.z,
+        (corner & 2) == 0 ? rect.y : rect.w
+    );
+    gl_Position = vec4(pos, 0.0, 1.0);
+    fragColor = color;
+}
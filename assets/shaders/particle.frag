@@ -0,0 +1,12 @@
+#version 450
+
+layout(location = 0) in vec4 inColor;
+layout(location = 1) in vec2 offset;
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    // Round, soft edged particles. The alpha is used as the fade, because the
+    // particles are rendered with additive blending
+    float falloff = max(1.0 - dot(offset, offset), 0.0);
+    outColor = vec4(inColor.rgb * inColor.a * falloff, 0.0);
+}
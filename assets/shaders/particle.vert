@@ -0,0 +1,39 @@
+#version 450
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+layout(location=0) in vec3 pos;
+layout(location=1) in float size;
+layout(location=2) in vec4 color;
+
+layout(location=0) out vec4 fragColor;
+layout(location=1) out vec2 fragOffset;
+
+layout(set = 0, binding = 0) uniform Transforms {
+    mat4 viewProjMatrix;
+};
+
+layout(set = 1, binding = 0) uniform Billboard {
+    vec4 cameraRight;
+    vec4 cameraUp;
+};
+
+const vec2 CORNERS[6] = vec2[6](
+    vec2(-1.0, -1.0),
+    vec2(-1.0, 1.0),
+    vec2(1.0, -1.0),
+    vec2(1.0, -1.0),
+    vec2(-1.0, 1.0),
+    vec2(1.0, 1.0)
+);
+
+void main() {
+    vec2 corner = CORNERS[gl_VertexIndex];
+    vec3 worldPos = pos + (cameraRight.xyz * corner.x + cameraUp.xyz * corner.y) * size;
+
+    gl_Position = viewProjMatrix * vec4(worldPos, 1.0);
+    fragColor = color;
+    fragOffset = corner;
+}
@@ -0,0 +1,8 @@
+#version 450
+
+layout(location = 0) flat in uint pickId;
+layout(location = 0) out uint outId;
+
+void main() {
+    outId = pickId;
+}
@@ -0,0 +1,25 @@
+#version 450
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+layout(location = 0) in vec3 pos;
+
+layout(location = 4) in vec4 model0;
+layout(location = 5) in vec4 model1;
+layout(location = 6) in vec4 model2;
+layout(location = 7) in vec4 model3;
+layout(location = 11) in uint pickId;
+
+layout(location = 0) flat out uint fPickId;
+
+layout(set = 0, binding = 0) uniform Transforms {
+    mat4 viewProjMatrix;
+};
+
+void main() {
+    mat4 modelMatrix = mat4(model0, model1, model2, model3);
+    gl_Position = viewProjMatrix * modelMatrix * vec4(pos, 1.0);
+    fPickId = pickId;
+}
@@ -0,0 +1,45 @@
+#version 450
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+layout(location=0) in vec3 pos1;
+layout(location=1) in vec3 color1;
+layout(location=2) in vec3 pos2;
+layout(location=3) in vec3 color2;
+layout(location=4) in float width;
+
+layout(location=0) out vec3 fragColor;
+
+layout(set = 0, binding = 0) uniform Transforms {
+    mat4 viewProjMatrix;
+};
+
+layout(set = 1, binding = 0) uniform Billboard {
+    vec4 cameraRight;
+    vec4 cameraUp;
+};
+
+// x is how far along the line the corner is, and y is which side of the line it is on
+const vec2 CORNERS[6] = vec2[6](
+    vec2(0.0, -1.0),
+    vec2(0.0, 1.0),
+    vec2(1.0, -1.0),
+    vec2(1.0, -1.0),
+    vec2(0.0, 1.0),
+    vec2(1.0, 1.0)
+);
+
+void main() {
+    vec2 corner = CORNERS[gl_VertexIndex];
+    // The quad is widened perpendicular to both the line and the camera's direction,
+    // so it always faces the camera
+    vec3 forward = cross(cameraUp.xyz, cameraRight.xyz);
+    vec3 side = cross(pos2 - pos1, forward);
+    side = length(side) > 0.0001 ? normalize(side) : cameraUp.xyz;
+    vec3 worldPos = mix(pos1, pos2, corner.x) + side * corner.y * width / 2.0;
+
+    gl_Position = viewProjMatrix * vec4(worldPos, 1.0);
+    fragColor = mix(color1, color2, corner.x);
+}
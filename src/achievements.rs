@@ -0,0 +1,353 @@
+use crate::entity::{damage::DamageSource, heat::ShipHeat, hud::HudVerbosity, Time};
+use crate::stats::{LifetimeStats, RunStats, SaveFiles, Stat, StatCounters};
+use specs::prelude::*;
+use std::collections::{BTreeSet, VecDeque};
+use std::fs;
+
+pub const ACHIEVEMENTS_PATH: &str = "saves/achievements.txt";
+
+/// The stats that the achievements are checked against
+pub struct Progress<'a> {
+    /// The current run
+    pub run: &'a StatCounters,
+    /// Every run, including the current one
+    pub lifetime: &'a StatCounters,
+}
+
+pub struct Achievement {
+    /// Saved in the achievements file, so it must never change
+    pub id: &'static str,
+    pub name_key: &'static str,
+    pub description_key: &'static str,
+    pub unlocked: fn(&Progress) -> bool,
+}
+
+pub const ACHIEVEMENTS: &[Achievement] = &[
+    Achievement {
+        id: "first_asteroid",
+        name_key: "achievement.first_asteroid",
+        description_key: "achievement.first_asteroid_description",
+        unlocked: |progress| progress.lifetime.total("asteroids_destroyed.") >= 1,
+    },
+    Achievement {
+        id: "asteroid_hunter",
+        name_key: "achievement.asteroid_hunter",
+        description_key: "achievement.asteroid_hunter_description",
+        unlocked: |progress| progress.lifetime.total("asteroids_destroyed.") >= 100,
+    },
+    Achievement {
+        id: "demolition",
+        name_key: "achievement.demolition",
+        description_key: "achievement.demolition_description",
+        unlocked: |progress| {
+            let source = DamageSource::MissileExplosion;
+            progress.lifetime.get(Stat::AsteroidsDestroyedBy(source)) >= 25
+        },
+    },
+    Achievement {
+        id: "prospector",
+        name_key: "achievement.prospector",
+        description_key: "achievement.prospector_description",
+        unlocked: |progress| progress.lifetime.total("items_mined.") >= 1000,
+    },
+    Achievement {
+        id: "builder",
+        name_key: "achievement.builder",
+        description_key: "achievement.builder_description",
+        unlocked: |progress| progress.lifetime.total("blocks_placed.") >= 100,
+    },
+    Achievement {
+        id: "overheated",
+        name_key: "achievement.overheated",
+        description_key: "achievement.overheated_description",
+        unlocked: |progress| progress.lifetime.get(Stat::PeakHeat) >= ShipHeat::MAX as u64,
+    },
+    Achievement {
+        id: "untouchable",
+        name_key: "achievement.untouchable",
+        description_key: "achievement.untouchable_description",
+        unlocked: |progress| {
+            progress.run.get(Stat::PeakWave) >= 20 && progress.run.get(Stat::BlocksLost) == 0
+        },
+    },
+    Achievement {
+        id: "survivor",
+        name_key: "achievement.survivor",
+        description_key: "achievement.survivor_description",
+        unlocked: |progress| progress.lifetime.get(Stat::Wins) >= 1,
+    },
+];
+
+/// An achievement that was just unlocked, which is shown until its lifetime runs out
+pub struct Toast {
+    pub achievement: &'static Achievement,
+    pub age: f32,
+}
+
+impl Toast {
+    /// How many seconds the toast is shown at Full HudVerbosity
+    pub const LIFETIME: f32 = 5.0;
+
+    /// The seconds until the toast goes away
+    pub fn remaining(&self, verbosity: HudVerbosity) -> f32 {
+        verbosity.toast_lifetime(Toast::LIFETIME) - self.age
+    }
+}
+
+/// The achievements that were unlocked, in any run. They are saved separately from the ships
+/// and the profile, and are never unlocked again.
+#[derive(Default)]
+pub struct Achievements {
+    /// Ids that are no longer defined are kept, so they aren't lost when the file is saved
+    unlocked: BTreeSet<String>,
+    /// Shown one at a time, oldest first
+    toasts: VecDeque<Toast>,
+}
+
+impl Achievements {
+    /// Loads the unlocked achievements. A missing file is treated as a new player, and lines
+    /// that can't be read are skipped, like in the profile.
+    pub fn load(path: &str) -> Self {
+        let mut unlocked = BTreeSet::new();
+        if let Ok(text) = fs::read_to_string(path) {
+            for line in text.lines() {
+                match line.split_whitespace().collect::<Vec<_>>()[..] {
+                    ["unlocked", id] => {
+                        unlocked.insert(id.to_string());
+                    }
+                    [] => {}
+                    _ => println!("[Achievements] Ignoring invalid line: {}", line),
+                }
+            }
+        }
+
+        Self {
+            unlocked,
+            toasts: VecDeque::new(),
+        }
+    }
+
+    pub fn save(&self, path: &str) {
+        let mut text = String::new();
+        for id in &self.unlocked {
+            text.push_str(&format!("unlocked {}\n", id));
+        }
+        if let Err(error) = crate::stats::write_profile_file(path, &text) {
+            println!("[Achievements] Unable to save achievements: {}", error);
+        }
+    }
+
+    pub fn is_unlocked(&self, achievement: &Achievement) -> bool {
+        self.unlocked.contains(achievement.id)
+    }
+
+    /// Unlocks the achievements that the progress now meets, and returns them. Achievements
+    /// that are already unlocked aren't checked.
+    pub fn evaluate(&mut self, progress: &Progress) -> Vec<&'static Achievement> {
+        let mut unlocked = Vec::new();
+        for achievement in ACHIEVEMENTS {
+            if !self.is_unlocked(achievement) && (achievement.unlocked)(progress) {
+                self.unlocked.insert(achievement.id.to_string());
+                unlocked.push(achievement);
+            }
+        }
+        unlocked
+    }
+
+    /// The toast that is being shown
+    pub fn toast(&self) -> Option<&Toast> {
+        self.toasts.front()
+    }
+}
+
+/// Checks the achievements against the stats, and ages the toasts
+#[derive(Default)]
+pub struct AchievementSystem {
+    /// The seconds since the achievements were last checked
+    since_check: f32,
+    /// The lifetime stats with the run merged, kept so they are not allocated every check
+    combined: StatCounters,
+}
+
+impl AchievementSystem {
+    /// The stats don't change much between updates, so they are checked less often
+    const CHECK_TIME: f32 = 1.0;
+}
+
+impl<'a> System<'a> for AchievementSystem {
+    type SystemData = (
+        ReadExpect<'a, Time>,
+        ReadExpect<'a, HudVerbosity>,
+        ReadExpect<'a, SaveFiles>,
+        ReadExpect<'a, RunStats>,
+        ReadExpect<'a, LifetimeStats>,
+        WriteExpect<'a, Achievements>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (time, verbosity, save_files, run, lifetime, mut achievements) = data;
+        if let Some(toast) = achievements.toasts.front_mut() {
+            toast.age += time.delta;
+            if toast.remaining(*verbosity) <= 0.0 {
+                achievements.toasts.pop_front();
+            }
+        }
+
+        if !time.count_up(&mut self.since_check, AchievementSystem::CHECK_TIME) {
+            return;
+        }
+        self.since_check = 0.0;
+
+        // The profile is only merged every so often, so the run is merged into a copy of it
+        lifetime.merged_counters(&run, &mut self.combined);
+        let progress = Progress {
+            run: &run.counters,
+            lifetime: &self.combined,
+        };
+        let unlocked = achievements.evaluate(&progress);
+        if unlocked.is_empty() {
+            return;
+        }
+        for achievement in unlocked {
+            println!("[Achievements] Unlocked {}", achievement.id);
+            achievements.toasts.push_back(Toast {
+                achievement,
+                age: 0.0,
+            });
+        }
+        if let Some(path) = save_files.achievements {
+            achievements.save(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::GameItem;
+    use crate::stats::{record, StatEvent};
+
+    fn achievement(id: &str) -> &'static Achievement {
+        ACHIEVEMENTS
+            .iter()
+            .find(|achievement| achievement.id == id)
+            .unwrap()
+    }
+
+    /// The ids of the achievements that the stats unlock
+    fn evaluate(
+        achievements: &mut Achievements,
+        run: &StatCounters,
+        lifetime: &StatCounters,
+    ) -> Vec<&'static str> {
+        let progress = Progress { run, lifetime };
+        let unlocked = achievements.evaluate(&progress);
+        unlocked.iter().map(|achievement| achievement.id).collect()
+    }
+
+    fn destroy_asteroids(counters: &mut StatCounters, amount: u32, source: DamageSource) {
+        for _ in 0..amount {
+            let item = GameItem::Iron;
+            record(counters, StatEvent::AsteroidDestroyed { item, source });
+        }
+    }
+
+    #[test]
+    fn achievements_unlock_once() {
+        let mut achievements = Achievements::default();
+        let run = StatCounters::default();
+        let mut lifetime = StatCounters::default();
+        assert!(evaluate(&mut achievements, &run, &lifetime).is_empty());
+
+        destroy_asteroids(&mut lifetime, 1, DamageSource::Laser);
+        let unlocked = evaluate(&mut achievements, &run, &lifetime);
+        assert_eq!(unlocked, vec!["first_asteroid"]);
+        assert!(achievements.is_unlocked(achievement("first_asteroid")));
+        assert!(evaluate(&mut achievements, &run, &lifetime).is_empty());
+
+        // Only the missiles count for demolition, and each threshold is only crossed once
+        destroy_asteroids(&mut lifetime, 98, DamageSource::Laser);
+        destroy_asteroids(&mut lifetime, 1, DamageSource::MissileExplosion);
+        let unlocked = evaluate(&mut achievements, &run, &lifetime);
+        assert_eq!(unlocked, vec!["asteroid_hunter"]);
+        destroy_asteroids(&mut lifetime, 24, DamageSource::MissileExplosion);
+        let unlocked = evaluate(&mut achievements, &run, &lifetime);
+        assert_eq!(unlocked, vec!["demolition"]);
+        destroy_asteroids(&mut lifetime, 100, DamageSource::MissileExplosion);
+        assert!(evaluate(&mut achievements, &run, &lifetime).is_empty());
+    }
+
+    #[test]
+    fn untouchable_needs_wave_20_without_losing_a_block() {
+        let lifetime = StatCounters::default();
+        let mut run = StatCounters::default();
+        for level in 1..=19 {
+            record(&mut run, StatEvent::WaveStarted(level));
+        }
+        let reached = |run: &StatCounters| {
+            let mut achievements = Achievements::default();
+            evaluate(&mut achievements, run, &lifetime) == vec!["untouchable"]
+        };
+        assert!(!reached(&run));
+
+        record(&mut run, StatEvent::WaveStarted(20));
+        assert!(reached(&run));
+        record(&mut run, StatEvent::BlockLost);
+        assert!(!reached(&run));
+
+        // Losing a block in another run doesn't matter
+        let mut run = StatCounters::default();
+        record(&mut run, StatEvent::WaveStarted(20));
+        assert!(reached(&run));
+    }
+
+    #[test]
+    fn peak_heat_and_wins_come_from_the_lifetime_stats() {
+        let mut achievements = Achievements::default();
+        let run = StatCounters::default();
+        let mut lifetime = StatCounters::default();
+        record(&mut lifetime, StatEvent::Heat(ShipHeat::MAX - 1.0));
+        record(&mut lifetime, StatEvent::FinalWaveSurvived);
+        let unlocked = evaluate(&mut achievements, &run, &lifetime);
+        assert_eq!(unlocked, vec!["survivor"]);
+
+        record(&mut lifetime, StatEvent::Heat(ShipHeat::MAX));
+        let unlocked = evaluate(&mut achievements, &run, &lifetime);
+        assert_eq!(unlocked, vec!["overheated"]);
+    }
+
+    #[test]
+    fn unlocks_are_saved_and_never_trigger_again() {
+        let dir =
+            std::env::temp_dir().join(format!("spaceship-achievements-{}", std::process::id()));
+        let path = dir.join("achievements.txt").to_string_lossy().into_owned();
+        let mut counters = StatCounters::default();
+        destroy_asteroids(&mut counters, 1, DamageSource::Laser);
+
+        let mut achievements = Achievements::load(&path);
+        let unlocked = evaluate(&mut achievements, &counters, &counters);
+        assert_eq!(unlocked, vec!["first_asteroid"]);
+        achievements.save(&path);
+
+        let mut achievements = Achievements::load(&path);
+        assert!(achievements.is_unlocked(achievement("first_asteroid")));
+        assert!(evaluate(&mut achievements, &counters, &counters).is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn corrupt_lines_are_skipped() {
+        let dir = std::env::temp_dir().join(format!("spaceship-corrupt-{}", std::process::id()));
+        let path = dir.join("achievements.txt").to_string_lossy().into_owned();
+        let text = "unlocked first_asteroid\nunlocked\nunlocked a b\nlocked untouchable\n";
+        crate::stats::write_profile_file(&path, text).unwrap();
+
+        let achievements = Achievements::load(&path);
+        assert!(achievements.is_unlocked(achievement("first_asteroid")));
+        assert!(!achievements.is_unlocked(achievement("untouchable")));
+        assert_eq!(achievements.unlocked.len(), 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
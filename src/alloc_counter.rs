@@ -0,0 +1,124 @@
+//! Counts heap allocations, so systems that allocate every update can be found. Only
+//! compiled with the `count-allocations` feature:
+//!
+//! `cargo run --features count-allocations`
+//!
+//! Every update that allocates is printed. Once the game has warmed up (the buffers
+//! reached their usual sizes), updates should not allocate, except for:
+//! - Updates where entities are spawned (asteroids, mining missles), since their
+//!   components are created through the LazyUpdate
+//! - Updates that add a message to the GameLog, since the messages are formatted
+//! - Updates that count a stat for some content (ex. an item mined), since its key is
+//!   formatted
+//! - Updates where a new contact starts in the PhysicsWorld, since its damage is pushed
+//!
+//! The collision worlds allocate inside ncollide whenever they are updated or raycast, which
+//! the game can't avoid. Those allocations are left out of the count (see `Exempt`).
+//!
+//! `cargo test --features count-allocations` checks that an idle ship doesn't allocate.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Whether the allocations of the thread are left out of the count
+    static EXEMPT: Cell<bool> = const { Cell::new(false) };
+}
+
+fn count() {
+    if !EXEMPT.try_with(Cell::get).unwrap_or(false) {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Leaves the allocations of the current thread out of the count while it is alive. Only
+/// for allocations that the game can't avoid (ex. inside ncollide).
+pub struct Exempt {
+    /// Whether the thread was already exempt, so nested exemptions can be dropped
+    previous: bool,
+}
+
+impl Exempt {
+    pub fn begin() -> Self {
+        Exempt {
+            previous: EXEMPT.with(|exempt| exempt.replace(true)),
+        }
+    }
+}
+
+impl Drop for Exempt {
+    fn drop(&mut self) {
+        let previous = self.previous;
+        EXEMPT.with(|exempt| exempt.set(previous));
+    }
+}
+
+/// The amount of allocations since the game started
+pub fn allocations() -> usize {
+    ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+/// Prints the allocations made since `start` (from allocations()), if there were any
+pub fn report_update(start: usize) {
+    let count = allocations() - start;
+    if count > 0 {
+        println!("[Allocations] {} during the update", count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::headless::HeadlessEcs;
+    use std::process::Command;
+
+    /// Set when the test runs the scene, in its own process
+    const SCENE_VAR: &str = "COUNT_ALLOCATIONS_SCENE";
+
+    #[test]
+    fn idle_ship_does_not_allocate() {
+        // Every thread is counted, including the ones running other tests, so the test
+        // binary runs only this test again in a new process
+        if std::env::var_os(SCENE_VAR).is_none() {
+            let name = module_path!().splitn(2, "::").nth(1).unwrap();
+            let status = Command::new(std::env::current_exe().unwrap())
+                .arg(format!("{}::idle_ship_does_not_allocate", name))
+                .args(["--exact", "--nocapture", "--test-threads=1"])
+                .env(SCENE_VAR, "1")
+                .status()
+                .unwrap();
+            assert!(status.success(), "The idle ship allocated");
+            return;
+        }
+
+        let mut headless = HeadlessEcs::with_seed(1);
+        headless.pause_asteroid_field();
+        // The buffers grow to their usual sizes while warming up
+        headless.step(600);
+        for tick in 0..300 {
+            let start = allocations();
+            headless.step(1);
+            assert_eq!(allocations() - start, 0, "Update {} allocated", tick);
+        }
+    }
+}
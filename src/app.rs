@@ -1,17 +1,47 @@
-use cgmath::Point2;
+use crate::assets::AssetError;
+use crate::audio::AudioSettings;
+use crate::content::ContentErrors;
+use crate::entity::{hazards::HazardSettings, KeyAction, KeyBindings};
+use crate::graphics::{GraphicsSettings, LoadingScreen};
+use crate::i18n::Language;
+use crate::loading::{AssetLoader, LoadedAssets, MeshJob};
+use cgmath::{Point2, Vector2};
+use std::fs;
 use std::time::{Duration, Instant};
 use winit::{
     event::{self, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
+    window::{Fullscreen, Icon},
 };
 
 pub trait Application: 'static + Sized {
+    /// What the application declared besides its meshes, which init finalizes with the
+    /// loaded meshes (ex. the blocks)
+    type Declarations;
+
+    /// Lists the meshes that init needs. They are loaded in the background while the
+    /// loading screen is shown, and init is only called once all of them are on the GPU.
+    fn declare_assets(errors: &mut ContentErrors) -> (Self::Declarations, Vec<MeshJob>);
+
+    /// `timestamp_period` is the nanoseconds in each tick of a GPU timestamp, or zero if the
+    /// adapter can't write timestamps
     fn init(
         swap_chain_desc: &wgpu::SwapChainDescriptor,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        timestamp_period: f32,
+        settings: Settings,
+        declarations: Self::Declarations,
+        assets: LoadedAssets,
     ) -> Self;
 
+    /// The graphics settings the application wants to use. The swap chain is recreated if
+    /// they change.
+    fn graphics_settings(&self) -> GraphicsSettings;
+
+    /// The settings that were last applied by the player. Changes are saved in the config.
+    fn applied_settings(&self) -> Settings;
+
     fn resize(
         &mut self,
         sc_desc: &wgpu::SwapChainDescriptor,
@@ -21,10 +51,28 @@ pub trait Application: 'static + Sized {
 
     fn key_event(&mut self, key: event::VirtualKeyCode, state: event::ElementState);
 
+    /// Called with the text that was typed (after the keyboard layout is applied)
+    fn char_event(&mut self, c: char);
+
+    /// Called once the window is closed, before the application exits
+    fn exit(&mut self);
+
     fn scroll_event(&mut self, delta: f32);
 
     fn mouse_moved(&mut self, pos: Point2<f32>);
 
+    /// Called with the raw movement of the mouse, which keeps coming while the cursor is grabbed
+    fn mouse_motion(&mut self, delta: Vector2<f32>);
+
+    /// Whether the cursor should be hidden and kept in the window
+    fn cursor_grabbed(&self) -> bool;
+
+    /// Called when the window loses focus, since the buttons that are held won't be released
+    fn focus_lost(&mut self);
+
+    /// A short status that is added to the window title (ex. the current wave)
+    fn title_status(&self) -> String;
+
     fn click_event(
         &mut self,
         button: event::MouseButton,
@@ -32,17 +80,224 @@ pub trait Application: 'static + Sized {
         pos: Point2<f32>,
     );
 
-    fn fixed_update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue);
+    /// How fast the simulation runs (ex. 0.2 during a slow motion effect). The fixed updates
+    /// still come at the tick rate, but only this share of them simulate.
+    fn time_scale(&self) -> f32;
+
+    /// Called once per frame, before the frame's fixed updates. `delta` is the real seconds
+    /// since the last frame, for whatever should move smoothly at any frame rate (ex. the
+    /// camera).
+    fn frame_update(&mut self, delta: f32);
+
+    /// `simulate` is false for the updates that the time scale skips. They should still
+    /// update whatever follows real time (ex. the UI and the camera).
+    fn fixed_update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, simulate: bool);
 
+    /// `alpha` is how far (0..1) the renderer is between the last
+    /// fixed update and the next one. It is used for interpolation.
     fn render(
         &mut self,
         frame: &wgpu::SwapChainTexture,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        alpha: f32,
     );
 }
 
+/// The most fixed updates that can be run before a frame is rendered. If the simulation
+/// falls further behind than this, the extra time is dropped to prevent a spiral of death.
+const MAX_UPDATES_PER_FRAME: u32 = 5;
+/// How close the time scales need to add up to 1 for an update to simulate. Adding them up
+/// is not exact (ex. 5 * 0.2 can be slightly less than 1).
+const SIMULATED_TICK_SLACK: f32 = 1e-4;
+/// While the window is not focused, the fixed updates keep running but frames are only
+/// rendered this often
+const UNFOCUSED_FRAME_TIME: Duration = Duration::from_millis(100);
+/// How often the status in the window title is updated
+const TITLE_UPDATE_TIME: Duration = Duration::from_secs(1);
+const ICON_ASSET: &str = "ui/icon.png";
+const SWAP_CHAIN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+
+/// The settings that can be changed in the settings menu
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    pub graphics: GraphicsSettings,
+    pub key_bindings: KeyBindings,
+    pub audio: AudioSettings,
+    pub language: Language,
+    pub hazards: HazardSettings,
+}
+
+/// Settings that are remembered between launches
+struct Config {
+    /// Borderless fullscreen on the current monitor (toggled with Alt+Enter)
+    fullscreen: bool,
+    settings: Settings,
+}
+
+impl Config {
+    const PATH: &'static str = "saves/config.txt";
+
+    /// Loads the config. A missing config uses the defaults.
+    fn load() -> Self {
+        match fs::read_to_string(Config::PATH) {
+            Ok(text) => Config::parse(&text),
+            Err(_) => Config::default(),
+        }
+    }
+
+    /// Settings that are missing or invalid keep their defaults
+    fn parse(text: &str) -> Self {
+        let mut config = Config::default();
+        let graphics = &mut config.settings.graphics;
+        let audio = &mut config.settings.audio;
+        let hazards = &mut config.settings.hazards;
+        let mut bindings = Vec::new();
+        for line in text.lines() {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            match parts.as_slice() {
+                ["fullscreen", value] => parse_value(&mut config.fullscreen, value, line),
+                ["vsync", value] => parse_value(&mut graphics.vsync, value, line),
+                ["msaa_samples", value] => parse_value(&mut graphics.msaa_samples, value, line),
+                ["render_scale", value] => parse_value(&mut graphics.render_scale, value, line),
+                ["gpu_culling", value] => parse_value(&mut graphics.gpu_culling, value, line),
+                ["gpu_picking", value] => parse_value(&mut graphics.gpu_picking, value, line),
+                ["backdrop", value] => parse_value(&mut graphics.backdrop, value, line),
+                ["tick_rate", value] => parse_value(&mut graphics.tick_rate, value, line),
+                ["capture_scale", value] => parse_value(&mut graphics.capture_scale, value, line),
+                ["capture_fps", value] => parse_value(&mut graphics.capture_fps, value, line),
+                ["capture_seconds", value] => {
+                    parse_value(&mut graphics.capture_seconds, value, line)
+                }
+                ["master_volume", value] => parse_value(&mut audio.master_volume, value, line),
+                ["effects_volume", value] => parse_value(&mut audio.effects_volume, value, line),
+                ["music_volume", value] => parse_value(&mut audio.music_volume, value, line),
+                ["solar_flares", value] => parse_value(&mut hazards.solar_flares, value, line),
+                ["debris_storms", value] => parse_value(&mut hazards.debris_storms, value, line),
+                ["language", code] => match Language::from_code(code) {
+                    Some(language) => config.settings.language = language,
+                    None => ignore_line(line),
+                },
+                [name, value] if name.starts_with("key_") => {
+                    let action = KeyAction::ALL
+                        .iter()
+                        .find(|action| action.config_name() == *name);
+                    let key = KeyBindings::parse_key(value);
+                    match (action, key) {
+                        (Some(action), Some(key)) => bindings.push((*action, key)),
+                        // Ex. a key that can no longer be bound
+                        _ => println!(
+                            "[Config] Ignoring invalid key binding, keeping the default: {}",
+                            line
+                        ),
+                    }
+                }
+                [] => {}
+                _ => ignore_line(line),
+            }
+        }
+        audio.validate();
+        match KeyBindings::with_keys(&bindings) {
+            Ok(bindings) => config.settings.key_bindings = bindings,
+            Err(key) => println!(
+                "[Config] {} is bound to more than one action, using the default key bindings",
+                KeyBindings::key_name(key)
+            ),
+        }
+
+        config
+    }
+
+    fn save(&self) {
+        let Settings {
+            graphics,
+            key_bindings,
+            audio,
+            language,
+            hazards,
+        } = &self.settings;
+        let mut text = format!(
+            "fullscreen {}\nvsync {}\nmsaa_samples {}\nrender_scale {}\ngpu_culling {}\ngpu_picking {}\nbackdrop {}\ntick_rate {}\n",
+            self.fullscreen,
+            graphics.vsync,
+            graphics.msaa_samples,
+            graphics.render_scale,
+            graphics.gpu_culling,
+            graphics.gpu_picking,
+            graphics.backdrop,
+            graphics.tick_rate
+        );
+        text += &format!(
+            "capture_scale {}\ncapture_fps {}\ncapture_seconds {}\n",
+            graphics.capture_scale, graphics.capture_fps, graphics.capture_seconds
+        );
+        text += &format!(
+            "master_volume {}\neffects_volume {}\nmusic_volume {}\n",
+            audio.master_volume, audio.effects_volume, audio.music_volume
+        );
+        text += &format!("language {}\n", language.code());
+        text += &format!(
+            "solar_flares {}\ndebris_storms {}\n",
+            hazards.solar_flares, hazards.debris_storms
+        );
+        for action in KeyAction::ALL.iter() {
+            text += &format!(
+                "{} {}\n",
+                action.config_name(),
+                KeyBindings::key_name(key_bindings.key(*action))
+            );
+        }
+        let result = std::path::Path::new(Config::PATH)
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(Config::PATH, text));
+        if let Err(error) = result {
+            println!("[Config] Unable to save config: {}", error);
+        }
+    }
+
+    fn fullscreen_mode(&self) -> Option<Fullscreen> {
+        if self.fullscreen {
+            // None uses the monitor the window is currently on
+            Some(Fullscreen::Borderless(None))
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            fullscreen: false,
+            settings: Settings {
+                graphics: GraphicsSettings::default(),
+                key_bindings: KeyBindings::default(),
+                audio: AudioSettings::default(),
+                language: Language::English,
+                hazards: HazardSettings::default(),
+            },
+        }
+    }
+}
+
+/// Sets the setting to the value, or keeps what it was if the value is invalid
+fn parse_value<T: std::str::FromStr>(setting: &mut T, value: &str, line: &str) {
+    match value.parse() {
+        Ok(value) => *setting = value,
+        Err(_) => ignore_line(line),
+    }
+}
+
+/// The config can be edited by hand or come from another version of the game, so lines that
+/// are not understood are skipped instead of failing to start
+fn ignore_line(line: &str) {
+    println!("[Config] Ignoring invalid line: {}", line);
+}
+
 struct Setup {
+    title: String,
+    config: Config,
     window: winit::window::Window,
     event_loop: EventLoop<()>,
     instance: wgpu::Instance,
@@ -54,9 +309,13 @@ struct Setup {
 }
 
 async fn setup<App: Application>(title: &str) -> Setup {
+    let mut config = Config::load();
     let event_loop = EventLoop::new();
     let mut builder = winit::window::WindowBuilder::new();
-    builder = builder.with_title(title);
+    builder = builder
+        .with_title(title)
+        .with_window_icon(load_icon())
+        .with_fullscreen(config.fullscreen_mode());
     let window = builder.build(&event_loop).unwrap();
 
     let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
@@ -74,7 +333,8 @@ async fn setup<App: Application>(title: &str) -> Setup {
         .await
         .unwrap();
 
-    let optional_features = wgpu::Features::NON_FILL_POLYGON_MODE;
+    // Timestamps are only used to profile the passes, which are timed on the CPU without them
+    let optional_features = wgpu::Features::NON_FILL_POLYGON_MODE | wgpu::Features::TIMESTAMP_QUERY;
     let required_features = wgpu::Features::empty();
     let adapter_features = adapter.features();
     assert!(
@@ -98,8 +358,14 @@ async fn setup<App: Application>(title: &str) -> Setup {
         )
         .await
         .unwrap();
+    config
+        .settings
+        .graphics
+        .validate(&adapter, SWAP_CHAIN_FORMAT);
 
     Setup {
+        title: title.to_string(),
+        config,
         window,
         event_loop,
         instance,
@@ -111,8 +377,25 @@ async fn setup<App: Application>(title: &str) -> Setup {
     }
 }
 
+/// The window still opens without its icon
+fn load_icon() -> Option<Icon> {
+    let icon = crate::assets::read_bytes(ICON_ASSET).and_then(|bytes| {
+        let image = image::load_from_memory(&bytes)
+            .map_err(|error| AssetError::invalid(ICON_ASSET, error))?
+            .to_rgba8();
+        let (width, height) = image.dimensions();
+        Icon::from_rgba(image.into_raw(), width, height)
+            .map_err(|error| AssetError::invalid(ICON_ASSET, error))
+    });
+
+    icon.map_err(|error| println!("[Asset Error] {}", error))
+        .ok()
+}
+
 fn start<App: Application>(
     Setup {
+        title,
+        mut config,
         window,
         event_loop,
         instance,
@@ -125,38 +408,222 @@ fn start<App: Application>(
 ) {
     let mut sc_desc = wgpu::SwapChainDescriptor {
         usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
-        format: wgpu::TextureFormat::Bgra8UnormSrgb,
+        format: SWAP_CHAIN_FORMAT,
         width: size.width,
         height: size.height,
-        present_mode: wgpu::PresentMode::Mailbox,
+        present_mode: config.settings.graphics.present_mode(),
     };
     let mut swap_chain = device.create_swap_chain(&surface, &sc_desc);
 
-    let mut app = App::init(&sc_desc, &device, &queue);
-    let mut last_update_inst = Instant::now();
+    let mut errors = ContentErrors::default();
+    let (declarations, jobs) = App::declare_assets(&mut errors);
+    // The application is created once the assets are loaded, so nothing is simulated or
+    // handled while the loading screen is shown
+    let mut loading = Some(Loading {
+        loader: AssetLoader::start(jobs, errors),
+        screen: LoadingScreen::new(&device, SWAP_CHAIN_FORMAT),
+        declarations,
+    });
+    let mut app: Option<App> = None;
+    // The graphics settings are applied as soon as they change, before they are saved
+    let mut graphics = config.settings.graphics;
+    let mut timestep = graphics.timestep();
+    let mut accumulator = Duration::from_secs(0);
+    // How many updates the time scale has let through, which simulate once it reaches 1
+    let mut simulated_ticks = 0.0;
+    let mut time_scale = 1.0;
+    let mut last_frame_inst = Instant::now();
     let mut mouse_pos: Point2<f32> = Point2::new(0.0, 0.0);
-    let fps = 60;
+    let mut modifiers = event::ModifiersState::empty();
+    let mut cursor_grabbed = false;
+    let mut focused = true;
+    let mut last_render = Instant::now();
+    let mut frames = 0;
+    let mut last_title_update = Instant::now();
+    // Resizes are applied once per frame, so multiple resizes in the same frame (ex. when
+    // toggling fullscreen) only recreate the swap chain once
+    let mut pending_size: Option<winit::dpi::PhysicalSize<u32>> = None;
 
     event_loop.run(move |event, _, control_flow| {
         let _ = (&instance, &adapter); // force ownership by the closure
+        if let Some(phase) = &mut loading {
+            match event {
+                event::Event::MainEventsCleared => {
+                    if let Some(size) =
+                        pending_size.filter(|size| size.width > 0 && size.height > 0)
+                    {
+                        pending_size = None;
+                        sc_desc.width = size.width;
+                        sc_desc.height = size.height;
+                        swap_chain = device.create_swap_chain(&surface, &sc_desc);
+                    }
+                    phase.loader.upload(&device);
+                    if phase.loader.is_resident() {
+                        let Loading {
+                            loader,
+                            declarations,
+                            ..
+                        } = loading.take().unwrap();
+                        app = Some(App::init(
+                            &sc_desc,
+                            &device,
+                            &queue,
+                            adapter.get_timestamp_period(),
+                            config.settings,
+                            declarations,
+                            loader.finish(),
+                        ));
+                        // The time spent loading is not caught up on
+                        last_frame_inst = Instant::now();
+                    }
+                    *control_flow = ControlFlow::Poll;
+                    window.request_redraw();
+                }
+                event::Event::WindowEvent {
+                    event: WindowEvent::Resized(size),
+                    ..
+                } => {
+                    pending_size = Some(size);
+                }
+                event::Event::WindowEvent {
+                    event: WindowEvent::CloseRequested,
+                    ..
+                } => {
+                    *control_flow = ControlFlow::Exit;
+                }
+                event::Event::RedrawRequested(_) => {
+                    if sc_desc.width == 0 || sc_desc.height == 0 {
+                        return;
+                    }
+                    let frame = next_frame(&mut swap_chain, &device, &surface, &sc_desc);
+                    let (loaded, total) = phase.loader.progress();
+                    let progress = loaded as f32 / total.max(1) as f32;
+                    phase
+                        .screen
+                        .render(&frame.output, &device, &queue, progress);
+                }
+                _ => {}
+            }
+            return;
+        }
+        let app = app.as_mut().unwrap();
         match event {
             event::Event::MainEventsCleared => {
-                if last_update_inst.elapsed() > Duration::from_nanos(1_000_000_000 / fps) {
-                    app.fixed_update(&device, &queue);
-                    last_update_inst = Instant::now();
+                // The swap chain can't be created while the window is minimized
+                if let Some(size) = pending_size.filter(|size| size.width > 0 && size.height > 0) {
+                    pending_size = None;
+                    sc_desc.width = size.width;
+                    sc_desc.height = size.height;
+                    app.resize(&sc_desc, &device, &queue);
+                    swap_chain = device.create_swap_chain(&surface, &sc_desc);
+                    // The mouse position is stored flipped, so it needs the new window height
+                    app.mouse_moved(mouse_pos);
+                }
+
+                let now = Instant::now();
+                let frame_time = now - last_frame_inst;
+                accumulator += frame_time;
+                last_frame_inst = now;
+                // A long stall (ex. dragging the window) shouldn't fling the camera away
+                app.frame_update(
+                    frame_time
+                        .min(timestep * MAX_UPDATES_PER_FRAME)
+                        .as_secs_f32(),
+                );
+
+                let mut updates = 0;
+                while accumulator >= timestep {
+                    if updates == MAX_UPDATES_PER_FRAME {
+                        accumulator = Duration::from_secs(0);
+                        break;
+                    }
+
+                    time_scale = app.time_scale();
+                    simulated_ticks += time_scale;
+                    let simulate = simulated_ticks >= 1.0 - SIMULATED_TICK_SLACK;
+                    if simulate {
+                        simulated_ticks = (simulated_ticks - 1.0).max(0.0);
+                    }
+                    app.fixed_update(&device, &queue, simulate);
+                    accumulator -= timestep;
+                    updates += 1;
+                }
+
+                // The cursor is released while the window is in the background
+                let grab = focused && app.cursor_grabbed();
+                if grab != cursor_grabbed {
+                    cursor_grabbed = grab;
+                    // Not every platform can grab the cursor, but it is still hidden
+                    if let Err(error) = window.set_cursor_grab(grab) {
+                        println!("[Input] Unable to grab the cursor: {}", error);
+                    }
+                    window.set_cursor_visible(!grab);
+                }
+
+                let settings = app.applied_settings();
+                if settings != config.settings {
+                    config.settings = settings;
+                    config.save();
+                }
+                let graphics_settings = app.graphics_settings();
+                if graphics_settings != graphics {
+                    graphics = graphics_settings;
+                    timestep = graphics_settings.timestep();
+                    let present_mode = graphics_settings.present_mode();
+                    if sc_desc.present_mode != present_mode {
+                        sc_desc.present_mode = present_mode;
+                        swap_chain = device.create_swap_chain(&surface, &sc_desc);
+                    }
+                }
+                let title_age = now - last_title_update;
+                if title_age >= TITLE_UPDATE_TIME {
+                    let fps = frames as f32 / title_age.as_secs_f32();
+                    window.set_title(&format!(
+                        "{} - {:.0} FPS - {}",
+                        title,
+                        fps,
+                        app.title_status()
+                    ));
+                    frames = 0;
+                    last_title_update = now;
+                }
+
+                if focused {
+                    *control_flow = ControlFlow::Poll;
+                    window.request_redraw();
+                } else {
+                    // Sleep until the next fixed update instead of spinning
+                    *control_flow = ControlFlow::WaitUntil(now + (timestep - accumulator));
+                    if now - last_render >= UNFOCUSED_FRAME_TIME {
+                        window.request_redraw();
+                    }
                 }
-                window.request_redraw();
             }
             event::Event::WindowEvent {
                 event: WindowEvent::Resized(size),
                 ..
             } => {
-                sc_desc.width = size.width;
-                sc_desc.height = size.height;
-                app.resize(&sc_desc, &device, &queue);
-                swap_chain = device.create_swap_chain(&surface, &sc_desc);
+                pending_size = Some(size);
             }
             event::Event::WindowEvent { ref event, .. } => match event {
+                WindowEvent::ModifiersChanged(state) => {
+                    modifiers = *state;
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        event::KeyboardInput {
+                            virtual_keycode: Some(event::VirtualKeyCode::Return),
+                            state: event::ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } if modifiers.alt() => {
+                    config.fullscreen = !config.fullscreen;
+                    config.save();
+                    window.set_fullscreen(config.fullscreen_mode());
+                    // Not every platform sends a resize event after changing the fullscreen mode
+                    pending_size = Some(window.inner_size());
+                }
                 WindowEvent::KeyboardInput {
                     input:
                         event::KeyboardInput {
@@ -168,6 +635,9 @@ fn start<App: Application>(
                 } => {
                     app.key_event(*key, *state);
                 }
+                WindowEvent::ReceivedCharacter(c) => {
+                    app.char_event(*c);
+                }
                 WindowEvent::MouseWheel {
                     delta: event::MouseScrollDelta::LineDelta(_, delta),
                     ..
@@ -181,30 +651,123 @@ fn start<App: Application>(
                 WindowEvent::MouseInput { state, button, .. } => {
                     app.click_event(*button, *state, mouse_pos);
                 }
+                WindowEvent::Focused(false) => {
+                    focused = false;
+                    // Alt is still held when switching windows with Alt+Tab
+                    modifiers = event::ModifiersState::empty();
+                    app.focus_lost();
+                }
+                WindowEvent::Focused(true) => {
+                    focused = true;
+                }
                 WindowEvent::CloseRequested => {
+                    app.exit();
                     *control_flow = ControlFlow::Exit;
                 }
                 _ => {}
             },
+            event::Event::DeviceEvent {
+                event: event::DeviceEvent::MouseMotion { delta },
+                ..
+            } => {
+                app.mouse_motion(Vector2::new(delta.0 as f32, delta.1 as f32));
+            }
             event::Event::RedrawRequested(_) => {
-                let frame = match swap_chain.get_current_frame() {
-                    Ok(frame) => frame,
-                    Err(_) => {
-                        swap_chain = device.create_swap_chain(&surface, &sc_desc);
-                        swap_chain
-                            .get_current_frame()
-                            .expect("Failed to acquire next swap chain texture!")
-                    }
-                };
+                if sc_desc.width == 0 || sc_desc.height == 0 {
+                    return;
+                }
+
+                let frame = next_frame(&mut swap_chain, &device, &surface, &sc_desc);
 
-                app.render(&frame.output, &device, &queue);
+                // Models are interpolated between the simulated updates, which are further
+                // apart while the time is slowed down
+                let alpha = accumulator.as_secs_f32() / timestep.as_secs_f32();
+                let alpha = (simulated_ticks + alpha * time_scale).min(1.0);
+                app.render(&frame.output, &device, &queue, alpha);
+                last_render = Instant::now();
+                frames += 1;
             }
             _ => {}
         }
     });
 }
 
+/// The assets that are loading before the application is created
+struct Loading<Declarations> {
+    loader: AssetLoader,
+    screen: LoadingScreen,
+    declarations: Declarations,
+}
+
+/// Recreates the swap chain if its frame can't be acquired (ex. after a resize)
+fn next_frame(
+    swap_chain: &mut wgpu::SwapChain,
+    device: &wgpu::Device,
+    surface: &wgpu::Surface,
+    sc_desc: &wgpu::SwapChainDescriptor,
+) -> wgpu::SwapChainFrame {
+    match swap_chain.get_current_frame() {
+        Ok(frame) => frame,
+        Err(_) => {
+            *swap_chain = device.create_swap_chain(surface, sc_desc);
+            swap_chain
+                .get_current_frame()
+                .expect("Failed to acquire next swap chain texture!")
+        }
+    }
+}
+
 pub fn run<App: Application>(title: &str) {
     let setup = futures::executor::block_on(setup::<App>(title));
     start::<App>(setup);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_config_lines_keep_the_defaults() {
+        let config = Config::parse(
+            "fullscreen true\nvsync maybe\nrender_scale\nunknown_setting 3\nlanguage xx\n\
+             music_volume 0.5\nmsaa_sam",
+        );
+        let defaults = Config::default();
+
+        assert!(config.fullscreen);
+        assert_eq!(config.settings.graphics, defaults.settings.graphics);
+        assert_eq!(config.settings.language, defaults.settings.language);
+        assert!((config.settings.audio.music_volume - 0.5).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn invalid_key_bindings_keep_the_defaults() {
+        let defaults = KeyBindings::default();
+        let free_key = [
+            event::VirtualKeyCode::Z,
+            event::VirtualKeyCode::Period,
+            event::VirtualKeyCode::Slash,
+            event::VirtualKeyCode::Insert,
+        ]
+        .iter()
+        .copied()
+        .find(|key| defaults.action(*key).is_none())
+        .unwrap();
+        let text = format!(
+            "{} Key1
+{} {}
+key_unknown_action A
+",
+            KeyAction::MoveBack.config_name(),
+            KeyAction::TurnLeft.config_name(),
+            KeyBindings::key_name(free_key)
+        );
+        let bindings = Config::parse(&text).settings.key_bindings;
+
+        assert_eq!(
+            bindings.key(KeyAction::MoveBack),
+            defaults.key(KeyAction::MoveBack)
+        );
+        assert_eq!(bindings.key(KeyAction::TurnLeft), free_key);
+    }
+}
@@ -0,0 +1,279 @@
+//! The game's assets are named by their path under the assets directory, ex.
+//! "models/laser.obj". They are read from the first of these that has them:
+//!
+//! - An `assets.pack` bundle (made with `--pack-assets <path>`) or an `assets` directory next
+//!   to the executable, which is how the game is distributed
+//! - The `assets` directory of the crate, in debug builds
+//! - The `assets` directory of the working directory
+//!
+//! A bundle starts with an index of the name, offset and length of each asset, followed by
+//! the assets themselves, unchanged.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+pub const BUNDLE_NAME: &str = "assets.pack";
+const BUNDLE_MAGIC: &[u8; 8] = b"SSPACK01";
+const DIRECTORY_NAME: &str = "assets";
+
+lazy_static! {
+    static ref ASSETS: Assets = Assets::locate();
+}
+
+#[derive(Debug)]
+pub enum AssetError {
+    /// The asset is not in any of the bundles or directories
+    NotFound(String),
+    /// The asset was found, but could not be read
+    Io(String, io::Error),
+    /// The asset was read, but its content is not valid (ex. an image that does not decode)
+    Invalid(String, String),
+}
+
+impl AssetError {
+    pub fn invalid(name: &str, error: impl fmt::Display) -> Self {
+        AssetError::Invalid(name.to_string(), error.to_string())
+    }
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::NotFound(name) => {
+                write!(f, "Missing asset {} (searched {})", name, ASSETS.describe())
+            }
+            AssetError::Io(name, error) => write!(f, "Unable to read asset {}: {}", name, error),
+            AssetError::Invalid(name, error) => write!(f, "Invalid asset {}: {}", name, error),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+pub fn read_bytes(name: &str) -> Result<Vec<u8>, AssetError> {
+    ASSETS.read_bytes(name)
+}
+
+pub fn read_to_string(name: &str) -> Result<String, AssetError> {
+    String::from_utf8(read_bytes(name)?).map_err(|error| AssetError::invalid(name, error))
+}
+
+pub fn exists(name: &str) -> bool {
+    ASSETS.sources.iter().any(|source| source.contains(name))
+}
+
+/// The assets directory that is used, if the assets are not only in bundles
+pub fn directory() -> Option<&'static Path> {
+    ASSETS.sources.iter().find_map(|source| match source {
+        Source::Directory(path) => Some(path.as_path()),
+        Source::Bundle(_) => None,
+    })
+}
+
+struct Assets {
+    sources: Vec<Source>,
+}
+
+impl Assets {
+    fn locate() -> Self {
+        let mut roots = Vec::new();
+        if let Some(exe_dir) = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        {
+            roots.push(exe_dir);
+        }
+        if cfg!(debug_assertions) {
+            roots.push(PathBuf::from(env!("CARGO_MANIFEST_DIR")));
+        }
+        roots.push(PathBuf::from("."));
+        // The working directory is often one of the others
+        let mut roots: Vec<PathBuf> = roots
+            .into_iter()
+            .map(|root| root.canonicalize().unwrap_or(root))
+            .collect();
+        roots.dedup();
+
+        let mut sources = Vec::new();
+        for root in roots {
+            let bundle_path = root.join(BUNDLE_NAME);
+            if bundle_path.is_file() {
+                match Bundle::open(&bundle_path) {
+                    Ok(bundle) => sources.push(Source::Bundle(bundle)),
+                    Err(error) => println!("[Asset Error] {}: {}", bundle_path.display(), error),
+                }
+            }
+
+            let directory = root.join(DIRECTORY_NAME);
+            if directory.is_dir() {
+                sources.push(Source::Directory(directory));
+            }
+        }
+
+        Assets { sources }
+    }
+
+    fn read_bytes(&self, name: &str) -> Result<Vec<u8>, AssetError> {
+        let source = self
+            .sources
+            .iter()
+            .find(|source| source.contains(name))
+            .ok_or_else(|| AssetError::NotFound(name.to_string()))?;
+        source
+            .read(name)
+            .map_err(|error| AssetError::Io(name.to_string(), error))
+    }
+
+    /// Ex. "bin/assets.pack, bin/assets"
+    fn describe(&self) -> String {
+        if self.sources.is_empty() {
+            return String::from("no asset directories were found");
+        }
+
+        let paths: Vec<String> = self
+            .sources
+            .iter()
+            .map(|source| match source {
+                Source::Directory(path) => path.display().to_string(),
+                Source::Bundle(bundle) => bundle.path.display().to_string(),
+            })
+            .collect();
+        paths.join(", ")
+    }
+}
+
+enum Source {
+    Directory(PathBuf),
+    Bundle(Bundle),
+}
+
+impl Source {
+    fn contains(&self, name: &str) -> bool {
+        match self {
+            Source::Directory(path) => path.join(name).is_file(),
+            Source::Bundle(bundle) => bundle.index.contains_key(name),
+        }
+    }
+
+    fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        match self {
+            Source::Directory(path) => fs::read(path.join(name)),
+            Source::Bundle(bundle) => bundle.read(name),
+        }
+    }
+}
+
+struct Bundle {
+    path: PathBuf,
+    /// The offset and length of each asset, from the start of the file
+    index: HashMap<String, (u64, u64)>,
+}
+
+impl Bundle {
+    fn open(path: &Path) -> io::Result<Bundle> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut file = io::BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 8];
+        file.read_exact(&mut magic)?;
+        if &magic != BUNDLE_MAGIC {
+            return Err(invalid("not an asset bundle"));
+        }
+
+        let count = read_u32(&mut file)?;
+        let mut index = HashMap::new();
+        for _ in 0..count {
+            let mut name = vec![0; read_u32(&mut file)? as usize];
+            file.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|_| invalid("asset name is not UTF-8"))?;
+            let offset = read_u64(&mut file)?;
+            let length = read_u64(&mut file)?;
+            index.insert(name, (offset, length));
+        }
+
+        Ok(Bundle {
+            path: path.to_path_buf(),
+            index,
+        })
+    }
+
+    fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let (offset, length) = self.index[name];
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut bytes = vec![0; length as usize];
+        file.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// Packs every file of the directory into a bundle at `out`, and returns how many were
+/// packed. The shader sources are left out, since only the compiled shaders are loaded.
+pub fn pack(directory: &Path, out: &Path) -> io::Result<usize> {
+    let mut names = Vec::new();
+    list_files(directory, "", &mut names)?;
+    names.retain(|name| !is_shader_source(name) && name != BUNDLE_NAME);
+    names.sort();
+
+    let index_size: u64 = names.iter().map(|name| 4 + name.len() as u64 + 8 + 8).sum();
+    let mut offset = BUNDLE_MAGIC.len() as u64 + 4 + index_size;
+    let mut lengths = Vec::new();
+    for name in &names {
+        lengths.push(fs::metadata(directory.join(name))?.len());
+    }
+
+    let mut file = io::BufWriter::new(File::create(out)?);
+    file.write_all(BUNDLE_MAGIC)?;
+    file.write_all(&(names.len() as u32).to_le_bytes())?;
+    for (name, length) in names.iter().zip(&lengths) {
+        file.write_all(&(name.len() as u32).to_le_bytes())?;
+        file.write_all(name.as_bytes())?;
+        file.write_all(&offset.to_le_bytes())?;
+        file.write_all(&length.to_le_bytes())?;
+        offset += length;
+    }
+    for (name, length) in names.iter().zip(&lengths) {
+        let bytes = fs::read(directory.join(name))?;
+        if bytes.len() as u64 != *length {
+            return Err(io::Error::other(format!("{} changed while packing", name)));
+        }
+        file.write_all(&bytes)?;
+    }
+    file.flush()?;
+
+    Ok(names.len())
+}
+
+/// Adds the files under `directory`, named by their path from the assets directory
+fn list_files(directory: &Path, prefix: &str, names: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            list_files(&entry.path(), &format!("{}/", name), names)?;
+        } else {
+            names.push(name);
+        }
+    }
+    Ok(())
+}
+
+/// The shaders are compiled by build.rs to ".spv" files next to their sources
+fn is_shader_source(name: &str) -> bool {
+    name.starts_with("shaders/") && !name.ends_with(".spv")
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
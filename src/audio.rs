@@ -0,0 +1,35 @@
+/// The volumes (0 to 1) of the game's sounds. They are saved in the config, and can be changed
+/// in the settings menu. The game does not play any sounds yet, so nothing reads them so far.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioSettings {
+    /// Scales every other volume
+    pub master_volume: f32,
+    pub effects_volume: f32,
+    pub music_volume: f32,
+}
+
+impl AudioSettings {
+    /// How much the volume sliders change per arrow key press
+    pub const VOLUME_STEP: f32 = 0.05;
+
+    /// Clamps the volumes that are out of range (ex. from an edited config)
+    pub fn validate(&mut self) {
+        for volume in [
+            &mut self.master_volume,
+            &mut self.effects_volume,
+            &mut self.music_volume,
+        ] {
+            *volume = volume.clamp(0.0, 1.0);
+        }
+    }
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master_volume: 1.0,
+            effects_volume: 0.8,
+            music_volume: 0.6,
+        }
+    }
+}
@@ -1,19 +1,58 @@
+use crate::content::{ContentDefinition, ContentErrors, ContentHash, ContentIndex, ContentKind};
 use crate::entity::{
-    objects::{self, Health, ObjectMeshes},
-    ColliderShape, Hitbox, InputAction, InputManager, Line, RaycastWorld, Transform,
+    animation::{Animation, AnimationClip, AnimationTrack, Keyframe},
+    construction,
+    crew::{self, CrewBonus},
+    damage::{DamageEvent, DamageEvents, DamageSource},
+    faction::{self, Faction},
+    gameplay::{AsteroidField, GameLog, IncomingThreat},
+    heat::{self, BlockHeat, HeatEvents},
+    integrity::Derelict,
+    objects::{self, Asteroid, Health, MiningMissle, ObjectMeshes},
+    power::{self, BlockPower},
+    projectile::{self, ProjectileKind},
+    scout,
+    selection::GadgetTarget,
+    shield::ShieldGenerator,
+    ship_stats::ShipStatsCache,
+    status::{self, StatusEffects},
+    targeting::{MinerTargetingSystem, TargetAssignment},
+    walls::WallMeshes,
+    wear::{self, Wear},
+    BlockEntity, Collider, ColliderShape, GameModule, Hitbox, InputAction, InputManager, Line,
+    LineBatch, ParticleParams, Particles, RaycastBatch, RaycastHandle, RigidBody, Ship, Time,
+    Tooltip, Transform,
 };
-use crate::graphics::{self, Mesh, MeshId, MeshManager};
-use cgmath::{Point2, Vector3};
+use crate::graphics::{GPUBillboard, IconParts, MeshId, MeshManager, MeshNode};
+use crate::i18n::Strings;
+use crate::item::{GameItem, Inventory};
+use crate::loading::{LoadedMeshes, MeshJob};
+use crate::mods::{self, BlockDefinition, ShapeDefinition};
+use crate::profiler::AddProfiled;
+use cgmath::{prelude::*, Matrix4, Point2, Vector2, Vector3, Vector4};
 use specs::{prelude::*, world::LazyBuilder, Component};
+use std::collections::HashSet;
+use std::f32::consts::{FRAC_PI_2, TAU};
+use std::ops::RangeInclusive;
+use std::path::Path;
 
 pub type BlockId = usize;
-pub type OnBlockSetup = fn(LazyBuilder) -> LazyBuilder;
+/// Adds the components of a kind of block to a new entity of it
+pub type OnBlockSetup = for<'a> fn(LazyBuilder<'a>, &Block) -> LazyBuilder<'a>;
 
 // TODO: Currently size is used for collision and grid spaces (but they should seperate)
 pub struct Block {
     pub id: BlockId,
+    /// Identifies the block in the content files (ex. the tech tree) and commands
     pub type_name: &'static str,
+    /// The key of the block's name in the language files. Mods have no language files, so a
+    /// mod block's key is its name, which is shown as it is.
+    pub name_key: &'static str,
     pub mesh_id: MeshId,
+    /// The name of the mesh file
+    pub mesh_name: &'static str,
+    /// The sub-meshes that are animated separately from the mesh (ex. the miner's barrel)
+    pub nodes: Vec<MeshNode>,
     /// The Size of the block in terms of grid spaces (x, y)
     pub size: Point2<u16>,
     /// The height of the block (z)
@@ -21,6 +60,71 @@ pub struct Block {
     pub hitbox: Hitbox,
     pub setup: Option<OnBlockSetup>,
     pub is_gadget: bool,
+    /// How hard and how far the block pushes asteroids, if it is a repulsor
+    pub repulsor: Option<RepulsorStats>,
+    /// The decks that the block can be built on
+    pub decks: RangeInclusive<i16>,
+    /// The items that are removed from the inventory when the player builds the block
+    pub cost: Vec<(GameItem, u32)>,
+    pub heat: BlockHeat,
+    pub power: BlockPower,
+    /// How heavy the block is, which the ship's engines have to push (see ShipStats)
+    pub mass: f32,
+    /// Identifies the block in saved files, even if it is renamed. It is set once the block
+    /// is finished, from its content_definition.
+    pub content_hash: ContentHash,
+}
+
+impl Block {
+    pub const DEFAULT_MASS: f32 = 1.0;
+    /// Everything that defines the block, which its content hash is computed from. The type
+    /// name is left out, so the block can be found in saves after it is renamed.
+    fn content_definition(&self) -> ContentDefinition {
+        let offset = self.hitbox.offset;
+        let hitbox = match self.hitbox.shape {
+            ColliderShape::Cuboid(size) => ContentDefinition::default()
+                .field("hitbox.shape", "cuboid")
+                .numbers("hitbox.size", &[size.x, size.y, size.z]),
+            ColliderShape::Sphere(radius) => ContentDefinition::default()
+                .field("hitbox.shape", "sphere")
+                .number("hitbox.size", radius),
+        };
+        let definition = hitbox
+            .numbers("hitbox.offset", &[offset.x, offset.y, offset.z])
+            .field("mesh", self.mesh_name)
+            .field("size.x", self.size.x)
+            .field("size.y", self.size.y)
+            .number("height", self.height)
+            .field("gadget", self.is_gadget)
+            .field("decks.min", self.decks.start())
+            .field("decks.max", self.decks.end())
+            .list(
+                "cost",
+                self.cost
+                    .iter()
+                    .map(|(item, amount)| format!("{:?}:{}", item, amount)),
+            )
+            .number("heat.per_use", self.heat.per_use)
+            .number("heat.idle", self.heat.idle)
+            .number("heat.dissipation", self.heat.dissipation)
+            .number("power.production", self.power.production)
+            .number("power.consumption", self.power.consumption)
+            .field("power.priority", self.power.priority)
+            .number("mass", self.mass);
+        match self.repulsor {
+            Some(stats) => definition
+                .number("repulsor.force", stats.force)
+                .number("repulsor.range", stats.range),
+            None => definition,
+        }
+    }
+
+    /// The mesh and sub-meshes that the block is drawn with, for rendering its icon
+    pub fn icon_parts(&self) -> IconParts {
+        std::iter::once((self.mesh_id, Matrix4::identity()))
+            .chain(self.nodes.iter().map(|node| (node.mesh_id, node.local)))
+            .collect()
+    }
 }
 
 pub struct Blocks {
@@ -30,6 +134,16 @@ pub struct Blocks {
     pub cube: BlockId,
     pub miner: BlockId,
     pub laser: BlockId,
+    pub cannon: BlockId,
+    /// Connects a deck to the deck above it, whose tile above the stairs is left open
+    pub stairs: BlockId,
+    /// A wall that can be opened, which the walls next to it connect to
+    pub door: BlockId,
+    pub cooler: BlockId,
+    pub shield_generator: BlockId,
+    pub generator: BlockId,
+    /// The meshes of the wall block, which depend on its neighbours
+    pub wall_meshes: WallMeshes,
 }
 
 impl Blocks {
@@ -38,64 +152,364 @@ impl Blocks {
             .get(id)
             .unwrap_or_else(|| panic!("Invalid block ID:  {}", id))
     }
+
+    pub fn find(&self, type_name: &str) -> Option<BlockId> {
+        self.blocks
+            .iter()
+            .find(|block| block.type_name == type_name)
+            .map(|block| block.id)
+    }
+
+    /// Finds the block that uses the mesh (ex. to find which gadget a model belongs to)
+    pub fn find_by_mesh(&self, mesh_id: MeshId) -> Option<BlockId> {
+        self.blocks
+            .iter()
+            .find(|block| block.mesh_id == mesh_id)
+            .map(|block| block.id)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Block> {
+        self.blocks.iter()
+    }
+
+    pub fn content_index(&self) -> ContentIndex<BlockId> {
+        let mut index = ContentIndex::new(ContentKind::Block);
+        for block in &self.blocks {
+            index.add(block.type_name, block.content_hash, block.id);
+        }
+        index
+    }
+}
+
+/// The meshes of the built-in blocks, which are loaded before the blocks are created (see
+/// crate::loading). create_blocks can only use the meshes in here.
+const BLOCK_MESHES: &[&str] = &[
+    "wall",
+    "wall_end",
+    "wall_straight",
+    "wall_corner",
+    "wall_tee",
+    "wall_cross",
+    "engine",
+    "box",
+    "miner",
+    "laser",
+    "repulsor",
+    "fabricator",
+    "repair_bay",
+    "drone_bay",
+    "stairs",
+    "door",
+    "cooler",
+    "shield_generator",
+    "generator",
+    "cannon",
+];
+
+/// The blocks before their meshes are loaded, which are created by finalize_blocks once
+/// the meshes are on the GPU
+pub struct BlockDeclarations {
+    mods: Vec<(String, BlockDefinition)>,
+}
+
+impl BlockDeclarations {
+    /// The meshes of the built-in blocks and of the mod blocks
+    pub fn mesh_jobs(&self) -> Vec<MeshJob> {
+        let mut names: Vec<&str> = BLOCK_MESHES.to_vec();
+        for (_, definition) in &self.mods {
+            if !names.contains(&definition.mesh.as_str()) {
+                names.push(&definition.mesh);
+            }
+        }
+        names.into_iter().map(MeshJob::mesh).collect()
+    }
+}
+
+/// Reads the mod blocks, so their meshes can be loaded with the built-in ones
+pub fn declare_blocks(errors: &mut ContentErrors) -> BlockDeclarations {
+    BlockDeclarations {
+        mods: mods::load_definitions(mods::BLOCKS_DIR, errors),
+    }
 }
 
-pub fn load_blocks(device: &wgpu::Device, mesh_manager: &mut MeshManager) -> Blocks {
+pub fn finalize_blocks(
+    declarations: BlockDeclarations,
+    mesh_manager: &mut MeshManager,
+    meshes: &LoadedMeshes,
+    errors: &mut ContentErrors,
+) -> Blocks {
+    let mut add_mesh = |name: &str| {
+        let mesh = meshes.get(name);
+        mesh_manager.set_mesh_visisble(mesh.id, crate::RENDER_BLOCKS);
+        for node in &mesh.nodes {
+            mesh_manager.set_mesh_visisble(node.mesh_id, crate::RENDER_BLOCKS);
+        }
+        (mesh.id, mesh.nodes.clone())
+    };
+    let mut blocks = create_blocks(&mut add_mesh);
+    add_mod_blocks(&mut blocks, declarations.mods, errors, add_mesh);
+    blocks
+}
+
+/// Registers the blocks with placeholder meshes, for a simulation without a GPU. The
+/// placeholders have no sub-meshes, so nothing is animated.
+pub fn load_blocks_headless(mesh_manager: &mut MeshManager) -> Blocks {
+    let mut blocks = create_blocks(|name| (mesh_manager.add_placeholder(name), Vec::new()));
+    // The errors are printed, and there is no UI to show them in
+    let mut errors = ContentErrors::default();
+    let declarations = declare_blocks(&mut errors);
+    add_mod_blocks(&mut blocks, declarations.mods, &mut errors, |name| {
+        (mesh_manager.add_placeholder(name), Vec::new())
+    });
+    blocks
+}
+
+/// `add_mesh` returns the MeshId and sub-meshes of the mesh with the name, which is one of
+/// BLOCK_MESHES
+fn create_blocks(mut add_mesh: impl FnMut(&'static str) -> (MeshId, Vec<MeshNode>)) -> Blocks {
     let mut blocks = Vec::new();
-    let mut register_mesh = |mesh: &Mesh| {
-        let id = mesh_manager.add(device, mesh);
-        mesh_manager.set_mesh_visisble(id, crate::RENDER_BLOCKS);
-        id
+    let mut mesh_nodes = Vec::new();
+    let mut register_mesh = |name: &'static str| {
+        let (id, nodes) = add_mesh(name);
+        mesh_nodes.push((id, nodes));
+        (name, id)
     };
 
+    let wall_mesh = register_mesh("wall");
+    let wall_meshes = WallMeshes {
+        single: wall_mesh.1,
+        end: register_mesh("wall_end").1,
+        straight: register_mesh("wall_straight").1,
+        corner: register_mesh("wall_corner").1,
+        tee: register_mesh("wall_tee").1,
+        cross: register_mesh("wall_cross").1,
+    };
     let wall = create_block(
         &mut blocks,
-        register_mesh(&graphics::load_mesh("wall")),
+        wall_mesh,
         (1, 1, 3.0),
         None,
-        "wall",
+        ("wall", "block.wall"),
         None,
         false,
+        &[(GameItem::Iron, 1)],
     );
     let engine = create_block(
         &mut blocks,
-        register_mesh(&graphics::load_mesh("engine")),
+        register_mesh("engine"),
         (1, 1, 1.0),
         None,
-        "engine",
+        ("engine", "block.engine"),
         None,
         false,
+        &[(GameItem::Iron, 3), (GameItem::Copper, 2)],
     );
     let cube = create_block(
         &mut blocks,
-        register_mesh(&graphics::load_mesh("box")),
+        register_mesh("box"),
         (1, 1, 1.0),
         None,
-        "Box",
+        ("Box", "block.box"),
         None,
         false,
+        &[(GameItem::Iron, 1)],
     );
     let miner = create_block(
         &mut blocks,
-        register_mesh(&graphics::load_mesh("miner")),
+        register_mesh("miner"),
         (1, 1, 1.0),
         None,
-        "Miner",
+        ("Miner", "block.miner"),
         Some(setup_miner),
         false,
+        &[(GameItem::Iron, 2), (GameItem::Copper, 1)],
     );
     let laser = create_block(
         &mut blocks,
-        register_mesh(&graphics::load_mesh("laser")),
+        register_mesh("laser"),
         (1, 1, 0.2),
         Some(Hitbox::new(
             ColliderShape::Cuboid(Vector3::new(0.6, 0.6, 0.525)),
             Vector3::new(0.0, 0.0, 0.525 / 2.0),
         )),
-        "Laser",
+        ("Laser", "block.laser"),
         Some(setup_laser),
         true,
+        &[(GameItem::Iron, 2), (GameItem::Copper, 3)],
+    );
+    let repulsor = create_block(
+        &mut blocks,
+        register_mesh("repulsor"),
+        (1, 1, 0.5),
+        Some(Hitbox::new(
+            ColliderShape::Cuboid(Vector3::new(0.6, 0.6, 0.5)),
+            Vector3::new(0.0, 0.0, 0.25),
+        )),
+        ("Repulsor", "block.repulsor"),
+        Some(setup_repulsor),
+        true,
+        &[(GameItem::Iron, 3), (GameItem::Copper, 3)],
+    );
+    let fabricator = create_block(
+        &mut blocks,
+        register_mesh("fabricator"),
+        (1, 1, 1.0),
+        None,
+        ("Fabricator", "block.fabricator"),
+        Some(setup_fabricator),
+        false,
+        &[(GameItem::Iron, 5), (GameItem::Copper, 3)],
+    );
+    let repair_bay = create_block(
+        &mut blocks,
+        register_mesh("repair_bay"),
+        (1, 1, 0.5),
+        Some(Hitbox::new(
+            ColliderShape::Cuboid(Vector3::new(0.8, 0.8, 0.5)),
+            Vector3::new(0.0, 0.0, 0.25),
+        )),
+        ("RepairBay", "block.repair_bay"),
+        Some(setup_repair_bay),
+        true,
+        &[(GameItem::Iron, 4), (GameItem::Copper, 2)],
+    );
+    let drone_bay = create_block(
+        &mut blocks,
+        register_mesh("drone_bay"),
+        (1, 1, 0.3),
+        Some(Hitbox::new(
+            ColliderShape::Cuboid(Vector3::new(0.8, 0.8, 0.3)),
+            Vector3::new(0.0, 0.0, 0.15),
+        )),
+        ("DroneBay", "block.drone_bay"),
+        Some(setup_drone_bay),
+        true,
+        &[(GameItem::Iron, 3), (GameItem::Copper, 4)],
     );
+    let stairs = create_block(
+        &mut blocks,
+        register_mesh("stairs"),
+        (1, 1, Ship::DECK_HEIGHT),
+        None,
+        ("Stairs", "block.stairs"),
+        None,
+        false,
+        &[(GameItem::Iron, 2)],
+    );
+    let door = create_block(
+        &mut blocks,
+        register_mesh("door"),
+        (1, 1, 3.0),
+        None,
+        ("Door", "block.door"),
+        Some(setup_door),
+        false,
+        &[(GameItem::Iron, 2), (GameItem::Copper, 1)],
+    );
+    let cooler = create_block(
+        &mut blocks,
+        register_mesh("cooler"),
+        (1, 1, 0.6),
+        None,
+        ("Cooler", "block.cooler"),
+        Some(setup_cooler),
+        false,
+        &[(GameItem::Iron, 2), (GameItem::Copper, 3)],
+    );
+    let shield_generator = create_block(
+        &mut blocks,
+        register_mesh("shield_generator"),
+        (1, 1, 0.6),
+        Some(Hitbox::new(
+            ColliderShape::Cuboid(Vector3::new(0.7, 0.7, 0.6)),
+            Vector3::new(0.0, 0.0, 0.3),
+        )),
+        ("ShieldGenerator", "block.shield_generator"),
+        Some(setup_shield_generator),
+        true,
+        &[(GameItem::Iron, 3), (GameItem::Copper, 5)],
+    );
+    let generator = create_block(
+        &mut blocks,
+        register_mesh("generator"),
+        (1, 1, 0.8),
+        None,
+        ("Generator", "block.generator"),
+        Some(setup_generator),
+        false,
+        &[(GameItem::Iron, 4), (GameItem::Copper, 2)],
+    );
+    let cannon = create_block(
+        &mut blocks,
+        register_mesh("cannon"),
+        (1, 1, 0.35),
+        Some(Hitbox::new(
+            ColliderShape::Cuboid(Vector3::new(0.8, 0.8, 1.3)),
+            Vector3::new(0.0, 0.0, 0.65),
+        )),
+        ("Cannon", "block.cannon"),
+        Some(setup_cannon),
+        true,
+        &[(GameItem::Iron, 4), (GameItem::Copper, 3)],
+    );
+    // Engines push on the bottom of the ship, and stairs need a deck above them
+    blocks[engine].decks = 0..=0;
+    blocks[stairs].decks = 0..=Ship::DECKS - 2;
+    // Beams heat the ship each second they are on, and miners and cannons with each shot
+    blocks[laser].heat = BlockHeat {
+        per_use: 3.0,
+        idle: 0.1,
+        ..BlockHeat::default()
+    };
+    blocks[repulsor].heat = BlockHeat {
+        per_use: 2.0,
+        idle: 0.1,
+        ..BlockHeat::default()
+    };
+    blocks[miner].heat.per_use = 4.0;
+    blocks[cannon].heat.per_use = 5.0;
+    blocks[fabricator].heat.idle = 0.3;
+    blocks[repair_bay].heat.idle = 0.2;
+    blocks[drone_bay].heat.idle = 0.2;
+    blocks[cooler].heat.dissipation = 3.0;
+    blocks[repulsor].repulsor = Some(RepulsorStats {
+        force: 3.0,
+        range: 16.0,
+    });
+    blocks[shield_generator].heat.idle = 0.4;
+    blocks[generator].heat.idle = 0.3;
+    // Lasers are browned out first, and coolers last, so a ship short on power cools down
+    let consumer = |consumption, priority| BlockPower {
+        consumption,
+        priority,
+        ..BlockPower::default()
+    };
+    blocks[laser].power = consumer(3.0, 0);
+    blocks[repulsor].power = consumer(2.0, 1);
+    blocks[miner].power = consumer(2.0, 2);
+    blocks[cannon].power = consumer(1.5, 2);
+    blocks[shield_generator].power = consumer(4.0, 3);
+    blocks[cooler].power = consumer(1.0, 4);
+    blocks[generator].power.production = 8.0;
+    // The hull and the heavy machines weigh the most, and the small gadgets the least
+    blocks[wall].mass = 2.0;
+    blocks[door].mass = 1.5;
+    blocks[engine].mass = 2.0;
+    blocks[cube].mass = 0.5;
+    blocks[laser].mass = 0.5;
+    blocks[repulsor].mass = 0.8;
+    blocks[fabricator].mass = 3.0;
+    blocks[stairs].mass = 1.5;
+    blocks[shield_generator].mass = 2.5;
+    blocks[generator].mass = 3.0;
+    blocks[cannon].mass = 1.5;
+    for block in &mut blocks {
+        if let Some((_, nodes)) = mesh_nodes.iter().find(|(id, _)| *id == block.mesh_id) {
+            block.nodes = nodes.clone();
+        }
+        block.content_hash = block.content_definition().hash();
+        print_registered(block);
+    }
 
     Blocks {
         blocks,
@@ -104,60 +518,311 @@ pub fn load_blocks(device: &wgpu::Device, mesh_manager: &mut MeshManager) -> Blo
         cube,
         miner,
         laser,
+        cannon,
+        stairs,
+        door,
+        cooler,
+        shield_generator,
+        generator,
+        wall_meshes,
     }
 }
 
+/// `mesh` is the name of the mesh file and its MeshId. The block is named by its type name
+/// internally (ex. in the tech tree), and shown with the text of its name key.
+#[allow(clippy::too_many_arguments)]
 fn create_block(
     blocks: &mut Vec<Block>,
-    mesh_id: MeshId,
+    (mesh_name, mesh_id): (&'static str, MeshId),
     size: (u16, u16, f32),
     hitbox: Option<Hitbox>,
-    type_name: &'static str,
+    (type_name, name_key): (&'static str, &'static str),
     setup: Option<OnBlockSetup>,
     is_gadget: bool,
+    cost: &[(GameItem, u32)],
 ) -> BlockId {
     let id = blocks.len();
     let block = Block {
         id,
         mesh_id,
+        mesh_name,
+        nodes: Vec::new(),
         type_name,
+        name_key,
         setup,
         is_gadget,
+        decks: 0..=Ship::DECKS - 1,
+        cost: cost.to_vec(),
+        heat: BlockHeat::default(),
+        power: BlockPower::default(),
+        mass: Block::DEFAULT_MASS,
         hitbox: hitbox.unwrap_or(Hitbox::new(
             ColliderShape::Cuboid(Vector3::new(size.0 as f32, size.1 as f32, size.2)),
             Vector3::new(0.0, 0.0, size.2 / 2.0),
         )),
         size: Point2::new(size.0, size.1),
         height: size.2,
+        repulsor: None,
+        content_hash: ContentHash::default(),
     };
-
-    println!("[Registered Block] {}={}", &block.type_name, id);
     blocks.push(block);
     id
 }
 
-pub fn register_components(world: &mut World) {
-    world.register::<Miner>();
-    world.register::<Laser>();
+fn print_registered(block: &Block) {
+    println!(
+        "[Registered Block] {}={} ({})",
+        &block.type_name, block.id, block.content_hash
+    );
 }
 
-pub fn setup_systems(dispatcher: &mut DispatcherBuilder) {
-    dispatcher.add(MinerSystem, "", &[]);
-    dispatcher.add(LaserSystem, "", &[]);
+/// The behaviors that mod blocks can have, with the built-in block that has the behavior.
+/// A mod block gets the setup, heat and power of that block.
+fn mod_behaviors(blocks: &Blocks) -> [(&'static str, Option<BlockId>); 6] {
+    [
+        ("none", None),
+        ("miner", Some(blocks.miner)),
+        ("laser", Some(blocks.laser)),
+        ("cannon", Some(blocks.cannon)),
+        ("cooler", Some(blocks.cooler)),
+        ("generator", Some(blocks.generator)),
+    ]
 }
 
-fn setup_miner(builder: LazyBuilder) -> LazyBuilder {
-    builder.with(Miner::default())
+/// Adds the blocks that the mods declared (see crate::mods) after the built-in blocks, so the
+/// built-in blocks keep their ids. `add_mesh` is like in create_blocks. Blocks that aren't
+/// valid are added to the errors and left out.
+fn add_mod_blocks(
+    blocks: &mut Blocks,
+    definitions: Vec<(String, BlockDefinition)>,
+    errors: &mut ContentErrors,
+    mut add_mesh: impl FnMut(&'static str) -> (MeshId, Vec<MeshNode>),
+) {
+    // The blocks are kept until the game closes, so their names can be leaked
+    let leak = |text: String| -> &'static str { Box::leak(text.into_boxed_str()) };
+    for (path, definition) in definitions {
+        let parts = match check_mod_block(blocks, &definition) {
+            Ok(checked) => checked,
+            Err(problems) => {
+                for problem in problems {
+                    mods::add_error(errors, &path, &problem);
+                }
+                continue;
+            }
+        };
+
+        let type_name = leak(definition.type_name);
+        let name_key = definition.name.map_or(type_name, leak);
+        let mesh_name = leak(definition.mesh);
+        let (mesh_id, nodes) = add_mesh(mesh_name);
+        let size = (definition.size.0, definition.size.1, definition.height);
+        let hitbox = definition.hitbox.map(|hitbox| {
+            let shape = match hitbox.shape {
+                ShapeDefinition::Cuboid(x, y, z) => ColliderShape::Cuboid(Vector3::new(x, y, z)),
+                ShapeDefinition::Sphere(radius) => ColliderShape::Sphere(radius),
+            };
+            Hitbox::new(shape, hitbox.offset.into())
+        });
+        let id = create_block(
+            &mut blocks.blocks,
+            (mesh_name, mesh_id),
+            size,
+            hitbox,
+            (type_name, name_key),
+            parts.setup,
+            definition.is_gadget,
+            &parts.cost,
+        );
+
+        let block = &mut blocks.blocks[id];
+        block.nodes = nodes;
+        block.heat = parts.heat;
+        block.power = parts.power;
+        block.mass = definition.mass.unwrap_or(Block::DEFAULT_MASS);
+        // The mod's file is part of the hash, so a saved block from a missing mod isn't
+        // loaded as a built-in block that looks the same
+        let mod_file = mods::hash_name(Path::new(&path));
+        block.content_hash = block.content_definition().field("mod", mod_file).hash();
+        print_registered(block);
+    }
 }
 
-#[derive(Component, Default)]
+/// The fields of a mod block that its definition names by text
+struct ModBlockParts {
+    cost: Vec<(GameItem, u32)>,
+    setup: Option<OnBlockSetup>,
+    heat: BlockHeat,
+    power: BlockPower,
+}
+
+/// Finds the parts of a mod block, or lists each field that isn't valid
+fn check_mod_block(
+    blocks: &Blocks,
+    definition: &BlockDefinition,
+) -> Result<ModBlockParts, Vec<String>> {
+    let mut problems = Vec::new();
+    let type_name = &definition.type_name;
+    // Saved files separate their fields with whitespace
+    if type_name.is_empty() || type_name.contains(char::is_whitespace) {
+        problems.push(format!("type_name: \"{}\" has to be one word", type_name));
+    } else if blocks.find(type_name).is_some() {
+        problems.push(format!("type_name: {} is already defined", type_name));
+    }
+    if definition.size.0 == 0 || definition.size.1 == 0 || definition.height <= 0.0 {
+        problems.push("size: the block has to take up space".to_string());
+    }
+    if matches!(definition.mass, Some(mass) if mass <= 0.0) {
+        problems.push("mass: the block has to weigh something".to_string());
+    }
+
+    let mut cost = Vec::new();
+    for (name, count) in &definition.cost {
+        match GameItem::from_name(name) {
+            Some(item) => cost.push((item, *count)),
+            None => problems.push(format!("cost: unknown item {}", name)),
+        }
+    }
+
+    let behaviors = mod_behaviors(blocks);
+    let behavior = definition.behavior.as_deref().unwrap_or("none");
+    let template = match behaviors.iter().find(|(name, _)| *name == behavior) {
+        Some((_, template)) => template.map(|block| blocks.get_block(block)),
+        None => {
+            let names: Vec<&str> = behaviors.iter().map(|(name, _)| *name).collect();
+            problems.push(format!(
+                "behavior: unknown behavior {} (expected one of {})",
+                behavior,
+                names.join(", ")
+            ));
+            None
+        }
+    };
+    let setup = template.and_then(|block| block.setup);
+    let mut heat = template.map_or(BlockHeat::default(), |block| block.heat);
+    let power = template.map_or(BlockPower::default(), |block| block.power);
+    if let Some(overrides) = &definition.heat {
+        heat.per_use = overrides.per_use.unwrap_or(heat.per_use);
+        heat.idle = overrides.idle.unwrap_or(heat.idle);
+        heat.dissipation = overrides.dissipation.unwrap_or(heat.dissipation);
+    }
+
+    if problems.is_empty() {
+        Ok(ModBlockParts {
+            cost,
+            setup,
+            heat,
+            power,
+        })
+    } else {
+        Err(problems)
+    }
+}
+
+/// The gadgets' components and systems
+pub struct BlockModule;
+
+impl GameModule for BlockModule {
+    fn register(&self, world: &mut World) {
+        world.register::<Miner>();
+        world.register::<Laser>();
+        world.register::<Cannon>();
+        world.register::<Repulsor>();
+        world.register::<Repairer>();
+        world.register::<Door>();
+        world.register::<Health>();
+        world.insert(TargetAssignment::default());
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(
+            MinerTargetingSystem::default(),
+            "miner_targeting_system",
+            &[],
+        );
+        builder.add_profiled(MinerSystem, "miner_system", &["miner_targeting_system"]);
+        builder.add_profiled(CannonSystem, "cannon_system", &[]);
+        builder.add_profiled(EngineSystem, "engine_system", &[]);
+        builder.add_profiled(LaserTargetingSystem, "laser_targeting_system", &[]);
+        builder.add_profiled(LaserSystem, "laser_system", &["laser_targeting_system"]);
+        builder.add_profiled(RepulsorSystem, "repulsor_system", &[]);
+        builder.add_profiled(RepairerSystem, "repairer_system", &[]);
+        builder.add_profiled(DoorSystem, "door_system", &[]);
+    }
+}
+
+fn setup_miner<'a>(builder: LazyBuilder<'a>, _: &Block) -> LazyBuilder<'a> {
+    builder
+        .with(Miner::new())
+        .with(Wear::default())
+        .with(Tooltip(Miner::tooltip))
+}
+
+/// Fires mining missiles at the player's target, or (in auto mode, toggled by clicking the
+/// miner) at the target assigned by the MinerTargetingSystem. A target that the miner was
+/// ordered to fire at (see GadgetTarget) comes before both.
+#[derive(Component)]
 #[storage(HashMapStorage)]
 pub struct Miner {
-    shoot_time: u16,
+    /// The seconds left until the miner can fire again
+    reload: f32,
+    auto: bool,
 }
 
 impl Miner {
-    const TOTAL_TIME: u16 = 120;
+    /// The seconds between shots
+    const RELOAD_TIME: f32 = 2.0;
+    const PREDICTION_COLOR: Vector3<f32> = Vector3::new(0.2, 0.8, 1.0);
+    const WARNING_COLOR: Vector3<f32> = Vector3::new(1.0, 0.6, 0.0);
+    /// Kicks the barrel back into the miner when it fires
+    const RECOIL: AnimationClip = AnimationClip {
+        name: "miner_recoil",
+        tracks: &[AnimationTrack {
+            node: "barrel",
+            keyframes: &[
+                Keyframe::rest(0.0),
+                Keyframe::translated(0.05, Vector3::new(0.0, 0.0, -0.2)),
+                Keyframe::rest(0.45),
+            ],
+        }],
+        looping: false,
+    };
+
+    fn new() -> Self {
+        Self {
+            reload: Miner::RELOAD_TIME,
+            auto: false,
+        }
+    }
+
+    pub fn reload(&self) -> f32 {
+        self.reload
+    }
+
+    pub fn is_auto(&self) -> bool {
+        self.auto
+    }
+
+    pub fn set_auto(&mut self, auto: bool) {
+        self.auto = auto;
+    }
+
+    pub fn tooltip(entity: Entity, world: &World) -> String {
+        let miners = world.read_component::<Miner>();
+        let miner = match miners.get(entity) {
+            Some(miner) => miner,
+            None => return String::new(),
+        };
+        let strings = world.fetch::<Strings>();
+        let mode = strings.get(if miner.auto {
+            "laser.auto"
+        } else {
+            "laser.manual"
+        });
+        strings.format(
+            "miner.tooltip",
+            &[("mode", &mode), ("wear", &Wear::tooltip(entity, world))],
+        )
+    }
 }
 
 pub struct MinerSystem;
@@ -166,96 +831,1616 @@ impl<'a> System<'a> for MinerSystem {
     type SystemData = (
         Entities<'a>,
         Read<'a, LazyUpdate>,
+        ReadExpect<'a, Time>,
         ReadExpect<'a, InputManager>,
         ReadExpect<'a, ObjectMeshes>,
+        Write<'a, LineBatch>,
         WriteStorage<'a, Miner>,
         WriteStorage<'a, Transform>,
+        ReadStorage<'a, RigidBody>,
+        ReadStorage<'a, AsteroidField>,
+        ReadStorage<'a, StatusEffects>,
+        WriteStorage<'a, Wear>,
+        WriteExpect<'a, HeatEvents>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, Derelict>,
+        ReadStorage<'a, CrewBonus>,
+        WriteStorage<'a, Animation>,
+        ReadStorage<'a, GadgetTarget>,
+        ReadExpect<'a, TargetAssignment>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, lazy_update, input, meshes, mut miners, mut transforms) = data;
+        let (
+            entities,
+            lazy_update,
+            time,
+            input,
+            meshes,
+            mut line_batch,
+            mut miners,
+            mut transforms,
+            bodies,
+            fields,
+            statuses,
+            mut wears,
+            mut heat_events,
+            ships,
+            derelicts,
+            bonuses,
+            mut animations,
+            gadget_targets,
+            assignment,
+        ) = data;
+        let overheated = heat::overheated_blocks(&ships);
+        let browned_out = power::browned_out_blocks(&ships);
+        let x_range = (&fields)
+            .join()
+            .map(|field| field.x_range)
+            .next()
+            .unwrap_or(f32::INFINITY);
+        let locate = |target: Entity| {
+            let velocity = bodies
+                .get(target)
+                .map(|body| body.velocity)
+                .unwrap_or_else(Vector3::zero);
+            transforms
+                .get(target)
+                .map(|transform| (target, transform.position, velocity))
+        };
+        let target = input
+            .target
+            .filter(|_| input.action == InputAction::Mining)
+            .and_then(&locate);
+        // The miners that were ordered to fire at a target (see GadgetTarget)
+        let ordered: Vec<_> = (&entities, &miners, &gadget_targets)
+            .join()
+            .filter_map(|(entity, _, ordered)| Some((entity, locate(ordered.0)?)))
+            .collect();
+        // The targets of the miners in auto mode (see TargetAssignment)
+        let assigned: Vec<_> = (&entities, &miners)
+            .join()
+            .filter(|(_, miner)| miner.auto)
+            .filter_map(|(entity, _)| Some((entity, locate(assignment.target_of(entity)?)?)))
+            .collect();
 
-        for (transform, miner) in (&mut transforms, &mut miners).join() {
+        for (entity, transform, miner, _) in
+            (&entities, &mut transforms, &mut miners, !&derelicts).join()
+        {
             transform.set_rotation_z(crate::PI);
+            if status::is_emped(&statuses, entity) || wear::is_servicing(&wears, entity) {
+                continue;
+            }
+            let position = transform.position + Vector3::new(0.0, 0.0, 0.5);
+            let target = ordered
+                .iter()
+                .find(|(miner, _)| *miner == entity)
+                .map(|(_, ordered)| *ordered)
+                .or(target)
+                .or_else(|| {
+                    assigned
+                        .iter()
+                        .find(|(miner, _)| *miner == entity)
+                        .map(|(_, assigned)| *assigned)
+                });
+
+            let reachable = if let Some((_, target_pos, velocity)) = target {
+                let impact = MiningMissle::predict_impact(position, target_pos, velocity)
+                    .filter(|impact| impact.x.abs() <= x_range);
+                let (end, color) = match impact {
+                    Some(impact) => (impact, Miner::PREDICTION_COLOR),
+                    None => (target_pos, Miner::WARNING_COLOR),
+                };
+
+                line_batch.add_dashed(Line::new(position, end, color), 0.4);
+                line_batch.add_marker(end, 0.5, color);
+                impact.is_some()
+            } else {
+                false
+            };
 
-            if miner.shoot_time > Miner::TOTAL_TIME {
-                if input.action == InputAction::Mining {
-                    if let Some(target) = input.target {
-                        let position = transform.position + Vector3::new(0.0, 0.0, 0.5);
+            if time.count_down(&mut miner.reload) {
+                if let Some((target, _, _)) = target {
+                    if !overheated.contains(&entity)
+                        && !browned_out.contains(&entity)
+                        && (reachable || !crate::SUPPRESS_UNREACHABLE_SHOTS)
+                    {
                         let builder = lazy_update.create_entity(&entities);
                         objects::build_mining_missle(&meshes, builder, target, position);
-                        miner.shoot_time = 0;
+                        // Worn miners take longer to reload, and operated ones reload faster
+                        miner.reload = Miner::RELOAD_TIME
+                            / (wear::efficiency(&wears, entity) * crew::speedup(&bonuses, entity));
+                        if let Some(wear) = wears.get_mut(entity) {
+                            wear.add(Wear::PER_SHOT);
+                        }
+                        heat_events.push(entity, 1.0);
+                        if let Some(animation) = animations.get_mut(entity) {
+                            animation.play(&Miner::RECOIL);
+                        }
                     }
                 }
+            }
+        }
+    }
+}
+
+fn setup_cannon<'a>(builder: LazyBuilder<'a>, _: &Block) -> LazyBuilder<'a> {
+    builder
+        .with(Cannon::new())
+        .with(Wear::default())
+        .with(Tooltip(Cannon::tooltip))
+}
+
+/// Fires unguided slugs at the point under the cursor (see crate::entity::projectile). Each
+/// shot uses up slugs from the inventory.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct Cannon {
+    /// The seconds left until the cannon can fire again
+    reload: f32,
+    /// How long the last reload takes, which worn cannons take longer for
+    reload_time: f32,
+}
+
+impl Cannon {
+    /// The seconds between shots
+    const RELOAD_TIME: f32 = 1.5;
+    /// What each shot uses up
+    const AMMO: [(GameItem, u32); 1] = [(GameItem::Slug, 1)];
+    /// Where the slugs leave the barrel, above the cannon
+    const MUZZLE_HEIGHT: f32 = 1.3;
+    const RECOIL: AnimationClip = AnimationClip {
+        name: "cannon_recoil",
+        tracks: &[AnimationTrack {
+            node: "barrel",
+            keyframes: &[
+                Keyframe::rest(0.0),
+                Keyframe::translated(0.04, Vector3::new(0.0, 0.0, -0.3)),
+                Keyframe::rest(0.6),
+            ],
+        }],
+        looping: false,
+    };
+    /// The reload indicator is a ring of squares above the cannon
+    const RING_SEGMENTS: usize = 12;
+    const RING_RADIUS: f32 = 0.3;
+    const SEGMENT_SIZE: f32 = 0.08;
+    const RELOADED_COLOR: Vector4<f32> = Vector4::new(1.0, 0.6, 0.15, 0.9);
+    const RELOADING_COLOR: Vector4<f32> = Vector4::new(0.2, 0.2, 0.2, 0.6);
+
+    fn new() -> Self {
+        Self {
+            reload: 0.0,
+            reload_time: Cannon::RELOAD_TIME,
+        }
+    }
+
+    /// How far along the reload is (0 to 1), or None if the cannon is loaded
+    fn reload_progress(&self) -> Option<f32> {
+        if self.reload > 0.0 {
+            Some(1.0 - self.reload / self.reload_time)
+        } else {
+            None
+        }
+    }
+
+    pub fn tooltip(entity: Entity, world: &World) -> String {
+        let cannons = world.read_component::<Cannon>();
+        let cannon = match cannons.get(entity) {
+            Some(cannon) => cannon,
+            None => return String::new(),
+        };
+        let strings = world.fetch::<Strings>();
+        let state = match cannon.reload_progress() {
+            Some(progress) => strings.format(
+                "cannon.reloading",
+                &[("percent", &format!("{:.0}", progress * 100.0))],
+            ),
+            None if !world.fetch::<Inventory>().has_items(&Cannon::AMMO) => {
+                strings.get("cannon.no_slugs").to_string()
+            }
+            None => strings.get("cannon.ready").to_string(),
+        };
+        strings.format(
+            "cannon.tooltip",
+            &[("state", &state), ("wear", &Wear::tooltip(entity, world))],
+        )
+    }
+}
+
+/// Fires the cannons while InputAction::Cannon is held. There is no leading or homing, so
+/// the shots land where the player aimed them.
+pub struct CannonSystem;
+
+impl<'a> System<'a> for CannonSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, LazyUpdate>,
+        ReadExpect<'a, Time>,
+        ReadExpect<'a, InputManager>,
+        ReadExpect<'a, ObjectMeshes>,
+        WriteExpect<'a, Inventory>,
+        WriteStorage<'a, Cannon>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, StatusEffects>,
+        WriteStorage<'a, Wear>,
+        WriteExpect<'a, HeatEvents>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, Derelict>,
+        WriteStorage<'a, Animation>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            lazy_update,
+            time,
+            input,
+            meshes,
+            mut inventory,
+            mut cannons,
+            transforms,
+            statuses,
+            mut wears,
+            mut heat_events,
+            ships,
+            derelicts,
+            mut animations,
+        ) = data;
+        let overheated = heat::overheated_blocks(&ships);
+        let browned_out = power::browned_out_blocks(&ships);
+
+        for (entity, transform, cannon, _) in
+            (&entities, &transforms, &mut cannons, !&derelicts).join()
+        {
+            if status::is_emped(&statuses, entity) || wear::is_servicing(&wears, entity) {
+                continue;
+            }
+            if !time.count_down(&mut cannon.reload) {
+                continue;
+            }
+            let aim = match input.aim {
+                Some(aim) => aim,
+                None => continue,
+            };
+            if overheated.contains(&entity)
+                || browned_out.contains(&entity)
+                || !inventory.has_items(&Cannon::AMMO)
+            {
+                continue;
+            }
+
+            let muzzle = transform.position + Vector3::new(0.0, 0.0, Cannon::MUZZLE_HEIGHT);
+            if (aim - muzzle).magnitude2() < f32::EPSILON {
+                continue;
+            }
+            inventory.remove_items(&Cannon::AMMO);
+            let builder = lazy_update.create_entity(&entities);
+            projectile::build_projectile(
+                &meshes,
+                builder,
+                ProjectileKind::SLUG,
+                muzzle,
+                aim - muzzle,
+            );
+            // Worn cannons take longer to reload
+            cannon.reload_time = Cannon::RELOAD_TIME / wear::efficiency(&wears, entity);
+            cannon.reload = cannon.reload_time;
+            if let Some(wear) = wears.get_mut(entity) {
+                wear.add(Wear::PER_SHOT);
+            }
+            heat_events.push(entity, 1.0);
+            if let Some(animation) = animations.get_mut(entity) {
+                animation.play(&Cannon::RECOIL);
+            }
+        }
+    }
+}
+
+/// Creates a ring above each reloading cannon, which fills up clockwise from the top as the
+/// cannon reloads. The ring is drawn like the health bars (see objects::build_health_bars).
+pub fn build_reload_indicators(world: &World) -> Vec<GPUBillboard> {
+    let entities = world.entities();
+    let cannons = world.read_component::<Cannon>();
+    let transforms = world.read_component::<Transform>();
+    let colliders = world.read_component::<Collider>();
+    let derelicts = world.read_component::<Derelict>();
+    let size = Cannon::SEGMENT_SIZE;
+    let mut segments = Vec::new();
+
+    for (entity, cannon, transform, _) in (&entities, &cannons, &transforms, !&derelicts).join() {
+        let progress = match cannon.reload_progress() {
+            Some(progress) => progress,
+            None => continue,
+        };
+        let top = colliders
+            .get(entity)
+            .map(|collider| collider.hitbox.top(transform))
+            .unwrap_or(transform.position.z);
+        let pos = Vector3::new(transform.position.x, transform.position.y, top + 0.5);
+        let reloaded = (progress * Cannon::RING_SEGMENTS as f32) as usize;
+
+        for i in 0..Cannon::RING_SEGMENTS {
+            let angle = FRAC_PI_2 - i as f32 / Cannon::RING_SEGMENTS as f32 * TAU;
+            let center = Vector2::new(angle.cos(), angle.sin()) * Cannon::RING_RADIUS;
+            segments.push(GPUBillboard {
+                pos,
+                offset: center - Vector2::new(size / 2.0, size / 2.0),
+                size: Vector2::new(size, size),
+                color: if i < reloaded {
+                    Cannon::RELOADED_COLOR
+                } else {
+                    Cannon::RELOADING_COLOR
+                },
+            });
+        }
+    }
+
+    segments
+}
+
+/// Engines thrust while they work, which flickers their thruster
+pub struct EngineSystem;
+
+impl EngineSystem {
+    const THRUST: AnimationClip = AnimationClip {
+        name: "engine_thrust",
+        tracks: &[AnimationTrack {
+            node: "thruster",
+            keyframes: &[
+                Keyframe::rest(0.0),
+                Keyframe::scaled(0.08, Vector3::new(1.4, 0.9, 0.9)),
+                Keyframe::scaled(0.15, Vector3::new(0.8, 1.05, 1.05)),
+                Keyframe::scaled(0.24, Vector3::new(1.2, 0.95, 0.95)),
+                Keyframe::rest(0.3),
+            ],
+        }],
+        looping: true,
+    };
+}
+
+impl<'a> System<'a> for EngineSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Blocks>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, StatusEffects>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, Derelict>,
+        WriteStorage<'a, Animation>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, blocks, block_entities, statuses, ships, derelicts, mut animations) = data;
+        let overheated = heat::overheated_blocks(&ships);
+
+        for (entity, block_entity, animation) in
+            (&entities, &block_entities, &mut animations).join()
+        {
+            if block_entity.block_id() != blocks.engine {
+                continue;
+            }
+            let thrusting = !derelicts.contains(entity)
+                && !status::is_emped(&statuses, entity)
+                && !overheated.contains(&entity);
+            if thrusting {
+                animation.keep_playing(&EngineSystem::THRUST);
             } else {
-                miner.shoot_time += 1;
+                animation.stop();
             }
         }
     }
 }
 
-fn setup_laser(builder: LazyBuilder) -> LazyBuilder {
-    builder.with(Laser)
+fn setup_laser<'a>(builder: LazyBuilder<'a>, _: &Block) -> LazyBuilder<'a> {
+    builder
+        .with(Laser::new(12.0))
+        .with(Wear::default())
+        .with(Tooltip(Laser::tooltip))
 }
 
+/// Whether a laser can fire right away
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LaserState {
+    Hot,
+    /// The laser has not fired for a while, and has to spin up before it can fire again
+    Standby,
+    /// The seconds until the laser can fire
+    SpinningUp(f32),
+}
+
+impl LaserState {
+    /// How far (0..1) a spinning up laser is
+    pub fn spin_up_progress(self) -> Option<f32> {
+        match self {
+            LaserState::SpinningUp(time_left) => Some(1.0 - time_left / Laser::SPIN_UP_TIME),
+            _ => None,
+        }
+    }
+}
+
+/// Fires at the player's target, or (in auto mode, toggled by clicking the laser)
+/// at the target picked by the LaserTargetingSystem. A target that the laser was ordered to
+/// fire at (see GadgetTarget) comes before both.
 #[derive(Component)]
 #[storage(HashMapStorage)]
-pub struct Laser;
+pub struct Laser {
+    auto: bool,
+    /// How far the laser can fire, which is also how far away auto mode picks targets
+    range: f32,
+    auto_target: Option<Entity>,
+    /// The fraction of a point of damage that has not been dealt yet, since health is
+    /// only damaged by whole points
+    damage: f32,
+    state: LaserState,
+    /// The seconds since the laser last had a target
+    idle: f32,
+    /// Keeps the laser from going on standby, so it can always fire right away
+    pub keep_hot: bool,
+    /// The target whose line of sight was checked in the last update
+    sight: Option<(Entity, RaycastHandle)>,
+}
+
+impl Laser {
+    pub fn new(range: f32) -> Self {
+        Self {
+            auto: false,
+            range,
+            auto_target: None,
+            damage: 0.0,
+            state: LaserState::Hot,
+            idle: 0.0,
+            keep_hot: false,
+            sight: None,
+        }
+    }
+
+    pub fn state(&self) -> LaserState {
+        self.state
+    }
+
+    pub fn is_auto(&self) -> bool {
+        self.auto
+    }
+
+    pub fn set_auto(&mut self, auto: bool) {
+        self.auto = auto;
+        self.auto_target = None;
+    }
+
+    /// Updates the standby state, and returns whether the laser can fire at its target.
+    /// A laser without a target goes on standby after a while (unless it is kept hot), and
+    /// a laser on standby spins up once it has a target again.
+    fn warm_up(&mut self, time: &Time, has_target: bool) -> bool {
+        if !has_target && !self.keep_hot {
+            match self.state {
+                LaserState::Hot => {
+                    if time.count_up(&mut self.idle, Laser::STANDBY_DELAY) {
+                        self.state = LaserState::Standby;
+                    }
+                }
+                // The target was lost before the laser was ready
+                LaserState::SpinningUp(_) => self.state = LaserState::Standby,
+                LaserState::Standby => {}
+            }
+            return false;
+        }
+
+        self.idle = 0.0;
+        match &mut self.state {
+            LaserState::Hot => {}
+            LaserState::Standby => self.state = LaserState::SpinningUp(Laser::SPIN_UP_TIME),
+            LaserState::SpinningUp(time_left) => {
+                if time.count_down(time_left) {
+                    self.state = LaserState::Hot;
+                }
+            }
+        }
+        has_target && self.state == LaserState::Hot
+    }
+
+    pub fn tooltip(entity: Entity, world: &World) -> String {
+        let lasers = world.read_component::<Laser>();
+        let laser = match lasers.get(entity) {
+            Some(laser) => laser,
+            None => return String::new(),
+        };
+        let strings = world.fetch::<Strings>();
+        let state = match laser.state {
+            LaserState::Hot if laser.keep_hot => strings.get("laser.kept_hot").to_string(),
+            LaserState::Hot => strings.get("laser.hot").to_string(),
+            LaserState::Standby => strings.get("laser.standby").to_string(),
+            LaserState::SpinningUp(_) => strings.format(
+                "laser.spinning_up",
+                &[(
+                    "percent",
+                    &format!("{:.0}", laser.state.spin_up_progress().unwrap() * 100.0),
+                )],
+            ),
+        };
+        let mode = strings.get(if laser.auto {
+            "laser.auto"
+        } else {
+            "laser.manual"
+        });
+        strings.format(
+            "laser.tooltip",
+            &[
+                ("mode", &mode),
+                ("state", &state),
+                ("wear", &Wear::tooltip(entity, world)),
+            ],
+        )
+    }
+
+    /// The seconds without a target before the laser goes on standby
+    const STANDBY_DELAY: f32 = 5.0;
+    /// The seconds a laser on standby takes before it can fire
+    const SPIN_UP_TIME: f32 = 1.0;
+    const DAMAGE_PER_SECOND: f32 = 60.0;
+    /// The particles emitted for each point of damage
+    const PARTICLES_PER_DAMAGE: usize = 2;
+
+    /// A new target has to be this much closer than the current one before the laser
+    /// switches, so the beam does not flicker between two targets at the same distance
+    const SWITCH_MARGIN: f32 = 1.0;
+    const BEAM_WIDTH: f32 = 0.06;
+    const BEAM_COLOR: Vector3<f32> = Vector3::new(1.0, 0.0, 0.0);
+    const BEAM_END_COLOR: Vector3<f32> = Vector3::new(1.0, 0.5, 0.2);
+    /// How bright the beam is drawn while something is in the way of its target
+    const OBSTRUCTED_BRIGHTNESS: f32 = 0.35;
+
+    const IMPACT_PARTICLES: ParticleParams = ParticleParams {
+        color: Vector3::new(1.0, 0.2, 0.1),
+        speed: 1.5,
+        lifetime: 0.3,
+        size: 0.08,
+    };
+}
 
 pub struct LaserSystem;
 
 impl<'a> System<'a> for LaserSystem {
     type SystemData = (
         Entities<'a>,
+        ReadExpect<'a, Time>,
         ReadExpect<'a, InputManager>,
-        ReadExpect<'a, RaycastWorld>,
+        WriteExpect<'a, RaycastBatch>,
+        WriteExpect<'a, Particles>,
+        WriteExpect<'a, DamageEvents>,
         WriteStorage<'a, Laser>,
         WriteStorage<'a, Line>,
-        WriteStorage<'a, Health>,
         WriteStorage<'a, Transform>,
+        ReadStorage<'a, StatusEffects>,
+        ReadStorage<'a, Faction>,
+        WriteStorage<'a, Wear>,
+        WriteExpect<'a, HeatEvents>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, Derelict>,
+        ReadStorage<'a, GadgetTarget>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, input, raycaster, lasers, mut lines, mut healths, mut transforms) = data;
+        let (
+            entities,
+            time,
+            input,
+            mut raycasts,
+            mut particles,
+            mut damage_events,
+            mut lasers,
+            mut lines,
+            mut transforms,
+            statuses,
+            factions,
+            mut wears,
+            mut heat_events,
+            ships,
+            derelicts,
+            gadget_targets,
+        ) = data;
+        let overheated = heat::overheated_blocks(&ships);
+        let browned_out = power::browned_out_blocks(&ships);
 
-        if input.action != InputAction::Laser {
-            return;
-        }
+        for (entity, laser, _) in (&entities, &mut lasers, !&derelicts).join() {
+            let target = if overheated.contains(&entity)
+                || browned_out.contains(&entity)
+                || status::is_emped(&statuses, entity)
+                || wear::is_servicing(&wears, entity)
+            {
+                None
+            } else if let Some(ordered) = gadget_targets.get(entity) {
+                Some(ordered.0)
+            } else if laser.auto {
+                laser.auto_target
+            } else if input.action == InputAction::Laser {
+                input.target
+            } else {
+                None
+            };
+            // Lasers don't fire at their own faction
+            let faction = faction::faction_of(&factions, entity);
+            let position = transforms.get(entity).unwrap().position;
+            let target = target
+                .filter(|target| faction.can_damage(faction::faction_of(&factions, *target)))
+                .filter(|target| match transforms.get(*target) {
+                    Some(transform) => (transform.position - position).magnitude() <= laser.range,
+                    None => false,
+                });
+            let ready = laser.warm_up(&time, target.is_some());
+            let target = target.filter(|_| ready);
+            if target.is_none() {
+                laser.sight = None;
+            }
 
-        for (entity, _) in (&entities, &lasers).join() {
-            if let Some(target) = input.target {
-                let target_pos = transforms.get(target).unwrap().position;
+            if let Some(target) = target {
+                let target_transform = transforms.get(target).unwrap();
+                let target_pos = target_transform.position;
                 let transform = transforms.get_mut(entity).unwrap();
-                let mut start_pos = transform.position + Vector3::new(0.0, 0.0, 0.4);
+                let start_pos = transform.position + Vector3::new(0.0, 0.0, 0.4);
                 let angle_xy = (start_pos.y - target_pos.y).atan2(start_pos.x - target_pos.x);
-                let radius = 0.35;
-                start_pos -= radius * Vector3::new(angle_xy.cos(), angle_xy.sin(), 0.0);
 
-                let raycast = raycaster.raycast(Vec::with_capacity(0), start_pos, target_pos);
+                // The line of sight is checked in the RaycastBatch, so the laser fires once
+                // the target was in sight in the last update. The beam starts inside the
+                // laser's own hitbox, so the laser is excluded from the raycast.
+                let hit = laser
+                    .sight
+                    .filter(|(checked, _)| *checked == target)
+                    .and_then(|(_, handle)| raycasts.hit(handle));
+                laser.sight = Some((
+                    target,
+                    raycasts.request(&[], start_pos, target_pos, Some(entity)),
+                ));
 
-                if Some(target) == raycast {
+                if let Some(hit) = hit {
                     transform.set_rotation_z(angle_xy);
 
+                    // An obstructed beam stops at whatever is in the way, dimmed, and does
+                    // no damage
+                    let obstructed = hit.entity != target;
+                    let brightness = if obstructed {
+                        Laser::OBSTRUCTED_BRIGHTNESS
+                    } else {
+                        1.0
+                    };
                     lines
                         .insert(
                             entity,
-                            Line {
-                                pt: start_pos,
-                                pt2: target_pos,
-                                color: Vector3::new(1.0, 0.0, 0.0),
-                            },
+                            Line::new(start_pos, hit.point, Laser::BEAM_COLOR * brightness)
+                                .with_end_color(Laser::BEAM_END_COLOR * brightness)
+                                .with_width(Laser::BEAM_WIDTH),
                         )
                         .expect("Unable to set line component for laser!");
+                    if obstructed {
+                        laser.damage = 0.0;
+                        continue;
+                    }
 
-                    if let Some(health) = healths.get_mut(target) {
-                        health.damage(1);
+                    let efficiency = wear::efficiency(&wears, entity);
+                    laser.damage += Laser::DAMAGE_PER_SECOND * efficiency * time.delta;
+                    if let Some(wear) = wears.get_mut(entity) {
+                        wear.add(Wear::PER_BEAM_SECOND * time.delta);
                     }
+                    heat_events.push(entity, time.delta);
+                    let amount = (laser.damage + Time::SLACK).floor();
+                    laser.damage -= amount;
+                    let amount = amount as u32;
+                    if amount == 0 {
+                        continue;
+                    }
+
+                    damage_events.push(
+                        DamageEvent::new(target, amount, DamageSource::Laser, hit.point)
+                            .confirmed(),
+                    );
+
+                    particles.emit_burst(
+                        hit.point,
+                        amount as usize * Laser::PARTICLES_PER_DAMAGE,
+                        &Laser::IMPACT_PARTICLES,
+                    );
 
                     continue;
                 }
             }
 
+            laser.damage = 0.0;
             lines.remove(entity);
         }
     }
 }
+
+/// Toggles the auto mode of clicked lasers, and picks the targets of the lasers in auto
+/// mode. The lasers target asteroids and hostile entities. Threats to the ship are targeted
+/// first, then the closest targets.
+pub struct LaserTargetingSystem;
+
+impl<'a> System<'a> for LaserTargetingSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, InputManager>,
+        ReadExpect<'a, Strings>,
+        WriteExpect<'a, GameLog>,
+        WriteStorage<'a, Laser>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Asteroid>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, IncomingThreat>,
+        ReadStorage<'a, Faction>,
+        ReadStorage<'a, Derelict>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            input,
+            strings,
+            mut log,
+            mut lasers,
+            transforms,
+            asteroids,
+            healths,
+            threats,
+            factions,
+            derelicts,
+        ) = data;
+
+        if let Some(laser) = input.clicked_block.and_then(|block| lasers.get_mut(block)) {
+            laser.set_auto(!laser.auto);
+            log.add(strings.format("log.laser_auto", &[("state", &strings.on_off(laser.auto))]));
+        }
+
+        for (entity, laser, transform, _) in
+            (&entities, &mut lasers, &transforms, !&derelicts).join()
+        {
+            if !laser.auto {
+                continue;
+            }
+            let faction = faction::faction_of(&factions, entity);
+
+            // Threats are scored as if they were closer than anything else in range
+            let score = |target: Entity| {
+                let target_faction = faction::faction_of(&factions, target);
+                let wanted = asteroids.contains(target) || faction.is_hostile_to(target_faction);
+                if !wanted || !faction.can_damage(target_faction) {
+                    return None;
+                }
+                let target_transform = transforms.get(target)?;
+                let distance = (target_transform.position - transform.position).magnitude();
+                let alive = matches!(healths.get(target), Some(health) if health.health() > 0);
+                if !alive || distance > laser.range {
+                    return None;
+                }
+                Some(if threats.contains(target) {
+                    distance - laser.range
+                } else {
+                    distance
+                })
+            };
+
+            let best = (&entities, &healths)
+                .join()
+                .filter_map(|(target, _)| Some((target, score(target)?)))
+                .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+            let current = laser
+                .auto_target
+                .and_then(|target| Some((target, score(target)?)));
+
+            laser.auto_target = match (current, best) {
+                (Some((current, current_score)), Some((_, best_score)))
+                    if best_score + Laser::SWITCH_MARGIN >= current_score =>
+                {
+                    Some(current)
+                }
+                (_, best) => best.map(|(target, _)| target),
+            };
+        }
+    }
+}
+
+fn setup_repulsor<'a>(builder: LazyBuilder<'a>, block: &Block) -> LazyBuilder<'a> {
+    let stats = block
+        .repulsor
+        .unwrap_or_else(|| panic!("No repulsor stats for block: {}", block.type_name));
+    builder
+        .with(Repulsor::new(stats))
+        .with(Wear::default())
+        .with(Tooltip(Wear::tooltip))
+}
+
+/// How a kind of repulsor pushes asteroids. It is part of the block's registration.
+#[derive(Clone, Copy, Debug)]
+pub struct RepulsorStats {
+    /// The force applied to the target each second
+    pub force: f32,
+    pub range: f32,
+}
+
+/// Pushes the target away (or pulls it closer with the tractor action) without damaging it.
+/// Without a target, the repulsor pushes the closest asteroid that would hit the ship.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct Repulsor {
+    /// The force applied to the target each second
+    force: f32,
+    range: f32,
+    heat: f32,
+    /// Set once the repulsor reaches MAX_HEAT, and cleared once it has fully cooled down
+    overheated: bool,
+}
+
+impl Repulsor {
+    /// Light asteroids (ex. ones that were mostly mined) are limited to this acceleration
+    const MAX_ACCELERATION: f32 = 2.0;
+    /// Targets are never pushed or pulled faster than this
+    const MAX_SPEED: f32 = 4.0;
+    const BEAM_WIDTH: f32 = 0.12;
+    const MAX_HEAT: f32 = 100.0;
+    /// The heat generated each second for every unit of force applied to a unit of mass
+    const HEAT_PER_FORCE: f32 = 6.0;
+    /// The heat lost each second
+    const COOLING: f32 = 6.0;
+    const REPULSOR_COLOR: Vector3<f32> = Vector3::new(0.2, 0.5, 1.0);
+    const TRACTOR_COLOR: Vector3<f32> = Vector3::new(0.3, 1.0, 0.6);
+
+    fn new(stats: RepulsorStats) -> Self {
+        Self {
+            force: stats.force,
+            range: stats.range,
+            heat: 0.0,
+            overheated: false,
+        }
+    }
+}
+
+/// Runs before the physics system (which moves the rigid bodies), so the force is applied
+/// in the same update the beam is drawn in.
+pub struct RepulsorSystem;
+
+impl<'a> System<'a> for RepulsorSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Time>,
+        ReadExpect<'a, InputManager>,
+        WriteStorage<'a, Repulsor>,
+        WriteStorage<'a, Line>,
+        WriteStorage<'a, RigidBody>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Asteroid>,
+        ReadStorage<'a, IncomingThreat>,
+        ReadStorage<'a, StatusEffects>,
+        WriteStorage<'a, Wear>,
+        WriteExpect<'a, HeatEvents>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, Derelict>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            time,
+            input,
+            mut repulsors,
+            mut lines,
+            mut bodies,
+            transforms,
+            asteroids,
+            threats,
+            statuses,
+            mut wears,
+            mut heat_events,
+            ships,
+            derelicts,
+        ) = data;
+        let dt = time.delta;
+        let overheated = heat::overheated_blocks(&ships);
+        let browned_out = power::browned_out_blocks(&ships);
+
+        for (entity, repulsor, transform, _) in
+            (&entities, &mut repulsors, &transforms, !&derelicts).join()
+        {
+            repulsor.heat = (repulsor.heat - Repulsor::COOLING * dt).max(0.0);
+            if repulsor.heat == 0.0 {
+                repulsor.overheated = false;
+            }
+            if let Some(wear) = wears.get_mut(entity) {
+                wear.add(repulsor.heat / Repulsor::MAX_HEAT * Wear::PER_HEAT_SECOND * dt);
+            }
+
+            let start_pos = transform.position + Vector3::new(0.0, 0.0, 0.5);
+            let in_range = |target: Entity| {
+                matches!(transforms.get(target), Some(target)
+                    if (target.position - start_pos).magnitude() <= repulsor.range)
+            };
+            let target = match input.action {
+                _ if status::is_emped(&statuses, entity) => None,
+                _ if wear::is_servicing(&wears, entity) => None,
+                _ if overheated.contains(&entity) || browned_out.contains(&entity) => None,
+                InputAction::Repulsor | InputAction::Tractor if input.target.is_some() => {
+                    input.target.filter(|target| in_range(*target))
+                }
+                InputAction::Repulsor => (&entities, &threats, &transforms)
+                    .join()
+                    .map(|(threat, _, threat_transform)| {
+                        (threat, (threat_transform.position - start_pos).magnitude())
+                    })
+                    .filter(|(_, distance)| *distance <= repulsor.range)
+                    .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+                    .map(|(threat, _)| threat),
+                _ => None,
+            };
+
+            let target = target
+                .filter(|_| !repulsor.overheated)
+                .and_then(|target| Some((target, transforms.get(target)?, asteroids.get(target)?)));
+            let (target, target_transform, asteroid) = match target {
+                Some(target) => target,
+                None => {
+                    lines.remove(entity);
+                    continue;
+                }
+            };
+
+            // Only the horizontal direction is used, so targets stay at their height
+            let mut direction = target_transform.position - start_pos;
+            direction.z = 0.0;
+            if direction.magnitude2() == 0.0 {
+                continue;
+            }
+            let (direction, color) = if input.action == InputAction::Tractor {
+                (-direction.normalize(), Repulsor::TRACTOR_COLOR)
+            } else {
+                (direction.normalize(), Repulsor::REPULSOR_COLOR)
+            };
+
+            let mass = asteroid.mass(target_transform);
+            let force = repulsor.force * wear::efficiency(&wears, entity);
+            let acceleration = (force / mass).min(Repulsor::MAX_ACCELERATION);
+            if let Some(body) = bodies.get_mut(target) {
+                body.velocity += direction * acceleration * dt;
+                if body.velocity.magnitude() > Repulsor::MAX_SPEED {
+                    body.velocity = body.velocity.normalize_to(Repulsor::MAX_SPEED);
+                }
+            }
+
+            repulsor.heat += force * mass * Repulsor::HEAT_PER_FORCE * dt;
+            if let Some(wear) = wears.get_mut(entity) {
+                wear.add(Wear::PER_BEAM_SECOND * dt);
+            }
+            heat_events.push(entity, dt);
+            if repulsor.heat >= Repulsor::MAX_HEAT {
+                repulsor.heat = Repulsor::MAX_HEAT;
+                repulsor.overheated = true;
+            }
+
+            lines
+                .insert(
+                    entity,
+                    Line::new(start_pos, target_transform.position, color)
+                        .with_width(Repulsor::BEAM_WIDTH),
+                )
+                .expect("Unable to set line component for repulsor!");
+        }
+    }
+}
+
+fn setup_fabricator<'a>(builder: LazyBuilder<'a>, _: &Block) -> LazyBuilder<'a> {
+    builder.with(construction::Fabricator::new(3.0))
+}
+
+fn setup_repair_bay<'a>(builder: LazyBuilder<'a>, _: &Block) -> LazyBuilder<'a> {
+    builder
+        .with(Repairer::default())
+        .with(Tooltip(Repairer::tooltip))
+}
+
+fn setup_drone_bay<'a>(builder: LazyBuilder<'a>, _: &Block) -> LazyBuilder<'a> {
+    builder.with(scout::DroneBay::default())
+}
+
+/// Heals the most damaged block in range on its ship. Each heal uses up items from the inventory.
+/// Clicking the repairer toggles servicing, where it also restores the wear of the gadgets in
+/// range while there is nothing to heal.
+#[derive(Component, Default)]
+#[storage(HashMapStorage)]
+pub struct Repairer {
+    heal_time: f32,
+    service_wear: bool,
+}
+
+impl Repairer {
+    const RANGE: f32 = 4.0;
+    /// The seconds between each heal
+    const HEAL_TIME: f32 = 1.0;
+    const HEAL_AMOUNT: u32 = 1;
+    const HEAL_COST: [(GameItem, u32); 1] = [(GameItem::Iron, 1)];
+    /// Servicing is slower and costs more than healing
+    const SERVICE_TIME: f32 = 2.0;
+    const SERVICE_AMOUNT: f32 = 5.0;
+    const SERVICE_COST: [(GameItem, u32); 2] = [(GameItem::Iron, 1), (GameItem::Copper, 1)];
+    const BEAM_WIDTH: f32 = 0.05;
+    const BEAM_COLOR: Vector3<f32> = Vector3::new(0.2, 1.0, 0.3);
+    const SERVICE_COLOR: Vector3<f32> = Vector3::new(1.0, 0.8, 0.2);
+
+    pub fn tooltip(entity: Entity, world: &World) -> String {
+        let strings = world.fetch::<Strings>();
+        match world.read_component::<Repairer>().get(entity) {
+            Some(repairer) if repairer.service_wear => {
+                strings.get("repair_bay.servicing").to_string()
+            }
+            Some(_) => strings.get("repair_bay.tooltip").to_string(),
+            None => String::new(),
+        }
+    }
+}
+
+pub struct RepairerSystem;
+
+impl<'a> System<'a> for RepairerSystem {
+    type SystemData = (
+        ReadExpect<'a, Time>,
+        ReadExpect<'a, InputManager>,
+        ReadExpect<'a, Strings>,
+        WriteExpect<'a, GameLog>,
+        WriteExpect<'a, Inventory>,
+        WriteExpect<'a, ShipStatsCache>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        WriteStorage<'a, Repairer>,
+        WriteStorage<'a, Health>,
+        WriteStorage<'a, Line>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, StatusEffects>,
+        WriteStorage<'a, Wear>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            time,
+            input,
+            strings,
+            mut log,
+            mut inventory,
+            mut stats_cache,
+            ships,
+            block_entities,
+            mut repairers,
+            mut healths,
+            mut lines,
+            transforms,
+            statuses,
+            mut wears,
+        ) = data;
+        // Each block is only healed by one repairer, so several repairers spread out
+        let mut claimed = HashSet::new();
+
+        if let Some(repairer) = input
+            .clicked_block
+            .and_then(|block| repairers.get_mut(block))
+        {
+            repairer.service_wear = !repairer.service_wear;
+            log.add(strings.format(
+                "log.repair_servicing",
+                &[("state", &strings.on_off(repairer.service_wear))],
+            ));
+        }
+
+        for ship in (&ships).join() {
+            for gadget in ship.gadgets() {
+                let (repairer, transform) =
+                    match (repairers.get_mut(gadget), transforms.get(gadget)) {
+                        (Some(repairer), Some(transform)) => (repairer, transform),
+                        _ => continue,
+                    };
+                if status::is_emped(&statuses, gadget) {
+                    lines.remove(gadget);
+                    continue;
+                }
+                let start_pos = transform.position + Vector3::new(0.0, 0.0, 0.5);
+
+                // Ties are broken by the entity ID, so the same block is picked every update
+                let patient = ship
+                    .blocks()
+                    .filter(|block| block_entities.contains(*block) && !claimed.contains(block))
+                    .filter_map(|block| {
+                        let health = healths.get(block)?;
+                        let position = transforms.get(block)?.position;
+                        let damaged = health.health() > 0 && health.health() < health.max_health();
+                        let in_range =
+                            (position - transform.position).magnitude() <= Repairer::RANGE;
+                        if damaged && in_range {
+                            Some((block, health.health(), position))
+                        } else {
+                            None
+                        }
+                    })
+                    .min_by_key(|(block, health, _)| (*health, block.id()))
+                    .map(|(block, _, position)| (block, position, false));
+                // Once nothing needs healing, the most worn gadget in range is serviced
+                let patient = patient.or_else(|| {
+                    if !repairer.service_wear {
+                        return None;
+                    }
+                    ship.gadgets()
+                        .filter(|other| !claimed.contains(other))
+                        .filter_map(|other| {
+                            let wear = wears.get(other).filter(|wear| !wear.is_servicing())?;
+                            let position = transforms.get(other)?.position;
+                            let in_range =
+                                (position - transform.position).magnitude() <= Repairer::RANGE;
+                            if wear.amount() > 0.0 && in_range {
+                                Some((other, wear.amount(), position))
+                            } else {
+                                None
+                            }
+                        })
+                        .max_by(|(a, a_wear, _), (b, b_wear, _)| {
+                            a_wear
+                                .partial_cmp(b_wear)
+                                .unwrap()
+                                .then(b.id().cmp(&a.id()))
+                        })
+                        .map(|(other, _, position)| (other, position, true))
+                });
+                let (patient, patient_pos, servicing) = match patient {
+                    Some(patient) => patient,
+                    None => {
+                        repairer.heal_time = 0.0;
+                        lines.remove(gadget);
+                        continue;
+                    }
+                };
+                claimed.insert(patient);
+
+                let (duration, cost, color) = if servicing {
+                    (
+                        Repairer::SERVICE_TIME,
+                        &Repairer::SERVICE_COST[..],
+                        Repairer::SERVICE_COLOR,
+                    )
+                } else {
+                    (
+                        Repairer::HEAL_TIME,
+                        &Repairer::HEAL_COST[..],
+                        Repairer::BEAM_COLOR,
+                    )
+                };
+                if time.count_up(&mut repairer.heal_time, duration) {
+                    // Without the items, the repairer waits until they are collected
+                    if !inventory.has_items(cost) {
+                        lines.remove(gadget);
+                        continue;
+                    }
+
+                    inventory.remove_items(cost);
+                    if servicing {
+                        wears
+                            .get_mut(patient)
+                            .unwrap()
+                            .restore(Repairer::SERVICE_AMOUNT);
+                    } else {
+                        healths
+                            .get_mut(patient)
+                            .unwrap()
+                            .heal(Repairer::HEAL_AMOUNT);
+                        stats_cache.mark_health_changed();
+                    }
+                    repairer.heal_time = 0.0;
+                }
+
+                lines
+                    .insert(
+                        gadget,
+                        Line::new(start_pos, patient_pos + Vector3::new(0.0, 0.0, 0.5), color)
+                            .with_width(Repairer::BEAM_WIDTH),
+                    )
+                    .expect("Unable to set line component for repairer!");
+            }
+        }
+    }
+}
+
+fn setup_door<'a>(builder: LazyBuilder<'a>, _: &Block) -> LazyBuilder<'a> {
+    builder.with(Door::default()).with(Tooltip(Door::tooltip))
+}
+
+/// A wall that opens and closes when it is clicked. Opening slides the panel down into the
+/// floor, and asteroids can pass through once it is fully open.
+#[derive(Component, Default)]
+#[storage(HashMapStorage)]
+pub struct Door {
+    open: bool,
+    /// How long (in seconds) the door has been opening. Closing counts it back down, so
+    /// toggling the door while it moves turns it around from where it is.
+    progress: f32,
+    /// Whether the collider was switched to let asteroids through
+    passable: bool,
+}
+
+impl Door {
+    /// The seconds it takes to open or close the door
+    const MOVE_TIME: f32 = 20.0 / Time::DEFAULT_RATE as f32;
+    /// How much of the panel sticks out of the floor while the door is open, so it can still
+    /// be clicked to close it
+    const OPEN_HEIGHT: f32 = 0.2;
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn tooltip(entity: Entity, world: &World) -> String {
+        let strings = world.fetch::<Strings>();
+        match world.read_component::<Door>().get(entity) {
+            Some(door) if door.is_open() => strings.get("door.open").to_string(),
+            Some(_) => strings.get("door.closed").to_string(),
+            None => String::new(),
+        }
+    }
+
+    /// Whether the doorway can be moved through (ex. by crew). Only a door that has fully
+    /// opened is passable.
+    pub fn is_passable(&self) -> bool {
+        self.open && self.progress >= Door::MOVE_TIME - Time::SLACK
+    }
+}
+
+/// Toggles the clicked door, and moves the doors that are opening or closing
+pub struct DoorSystem;
+
+impl<'a> System<'a> for DoorSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Time>,
+        ReadExpect<'a, InputManager>,
+        ReadExpect<'a, Blocks>,
+        WriteStorage<'a, Door>,
+        WriteStorage<'a, Transform>,
+        WriteStorage<'a, Collider>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Derelict>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            time,
+            input,
+            blocks,
+            mut doors,
+            mut transforms,
+            mut colliders,
+            block_entities,
+            derelicts,
+        ) = data;
+
+        if let Some(door) = input.clicked_block.and_then(|block| doors.get_mut(block)) {
+            door.open = !door.open;
+        }
+
+        for (entity, door, block_entity, _) in
+            (&entities, &mut doors, &block_entities, !&derelicts).join()
+        {
+            let previous = door.progress;
+            if door.open {
+                time.count_up(&mut door.progress, Door::MOVE_TIME);
+            } else {
+                time.count_down(&mut door.progress);
+            }
+            // Resting doors don't touch their transform, so their models are not updated
+            if door.progress != previous {
+                let height = blocks.get_block(block_entity.block_id()).height;
+                let lowered = (height - Door::OPEN_HEIGHT) * door.progress / Door::MOVE_TIME;
+                if let Some(transform) = transforms.get_mut(entity) {
+                    transform.position.z = Ship::deck_z(block_entity.root().z) - lowered;
+                }
+            }
+
+            // The door blocks asteroids again as soon as it starts closing
+            let passable = door.is_passable();
+            if passable != door.passable {
+                door.passable = passable;
+                if let Some(collider) = colliders.get_mut(entity) {
+                    collider.set_whitelist(if passable { &[] } else { &[Collider::ASTEROID] });
+                }
+            }
+        }
+    }
+}
+
+fn setup_cooler<'a>(builder: LazyBuilder<'a>, _: &Block) -> LazyBuilder<'a> {
+    builder.with(Tooltip(heat::cooler_tooltip))
+}
+
+fn setup_generator<'a>(builder: LazyBuilder<'a>, _: &Block) -> LazyBuilder<'a> {
+    builder.with(Tooltip(power::generator_tooltip))
+}
+
+fn setup_shield_generator<'a>(builder: LazyBuilder<'a>, _: &Block) -> LazyBuilder<'a> {
+    builder
+        .with(ShieldGenerator)
+        .with(Tooltip(ShieldGenerator::tooltip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::headless::{load_blocks, HeadlessEcs};
+    use cgmath::{Point3, Vector2};
+
+    #[test]
+    fn content_hash_covers_costs_and_stats() {
+        let mut blocks = load_blocks();
+        let block = &mut blocks.blocks[blocks.laser];
+        let hash = block.content_hash;
+        assert_eq!(block.content_definition().hash(), hash);
+
+        // The order of the cost is not part of the definition
+        block.cost.reverse();
+        assert_eq!(block.content_definition().hash(), hash);
+
+        block.cost[0].1 += 1;
+        assert_ne!(block.content_definition().hash(), hash);
+        block.cost[0].1 -= 1;
+        block.heat.per_use += 1.0;
+        assert_ne!(block.content_definition().hash(), hash);
+        block.heat.per_use -= 1.0;
+        block.power.priority += 1;
+        assert_ne!(block.content_definition().hash(), hash);
+        block.power.priority -= 1;
+        block.mass *= 2.0;
+        assert_ne!(block.content_definition().hash(), hash);
+    }
+
+    #[test]
+    fn built_in_blocks_have_distinct_hashes() {
+        let blocks = load_blocks();
+        let mut hashes: Vec<ContentHash> = blocks.iter().map(|block| block.content_hash).collect();
+        hashes.sort();
+        hashes.dedup();
+        assert_eq!(hashes.len(), blocks.iter().count());
+    }
+
+    #[test]
+    fn repulsor_stats_come_from_the_registration() {
+        let mut blocks = load_blocks();
+        let repulsor = blocks.find("Repulsor").unwrap();
+        let block = &mut blocks.blocks[repulsor];
+        let hash = block.content_hash;
+        let stats = block.repulsor.as_mut().expect("The repulsor has no stats");
+        stats.range += 1.0;
+        assert_ne!(block.content_definition().hash(), hash);
+    }
+
+    /// Spawns an asteroid flying at the side of the ship that a repulsor is built on, and runs
+    /// for `seconds`. Returns whether the asteroid is still threatening the ship, and whether
+    /// it still exists (it is destroyed when it crashes into the ship).
+    fn asteroid_at_repulsor(repulse: bool, seconds: u32) -> (bool, bool) {
+        let mut headless = HeadlessEcs::new();
+        headless.pause_asteroid_field();
+        headless.build_block(Point3::new(0, 5, 0), "Repulsor");
+        let asteroid = headless.spawn_asteroid(
+            Vector3::new(-12.0, 5.0, 0.5),
+            Vector3::new(Asteroid::VELOCITY, 0.0, 0.0),
+            100.0,
+        );
+        headless.step(1);
+        assert!(headless
+            .ecs
+            .world
+            .read_storage::<IncomingThreat>()
+            .contains(asteroid));
+
+        if repulse {
+            headless.ecs.get_resource_mut::<InputManager>().action = InputAction::Repulsor;
+        }
+        headless.step(seconds * Time::DEFAULT_RATE);
+        let world = &headless.ecs.world;
+        let threatening = world.read_storage::<IncomingThreat>().contains(asteroid);
+        let exists =
+            world.is_alive(asteroid) && world.read_storage::<Asteroid>().contains(asteroid);
+        (threatening, exists)
+    }
+
+    #[test]
+    fn repulsor_deflects_a_threatening_asteroid_to_a_miss() {
+        let (threatening, exists) = asteroid_at_repulsor(true, 20);
+        assert!(exists);
+        assert!(!threatening);
+    }
+
+    #[test]
+    fn asteroid_crashes_without_the_repulsor() {
+        let (_, exists) = asteroid_at_repulsor(false, 20);
+        assert!(!exists);
+    }
+
+    /// Updates the laser for as many ticks as make up the seconds, and returns how many
+    /// of them it could fire in
+    fn warm_up_for(laser: &mut Laser, time: &Time, seconds: f32, has_target: bool) -> u32 {
+        let ticks = (seconds * Time::DEFAULT_RATE as f32).round() as u32;
+        (0..ticks)
+            .filter(|_| laser.warm_up(time, has_target))
+            .count() as u32
+    }
+
+    #[test]
+    fn spin_up_gates_the_first_shot() {
+        let time = Time::new(Time::DEFAULT_RATE);
+        let mut laser = Laser::new(12.0);
+        assert!(laser.warm_up(&time, true));
+
+        warm_up_for(&mut laser, &time, Laser::STANDBY_DELAY, false);
+        assert_eq!(laser.state(), LaserState::Standby);
+
+        // No shot until the spin up is done, and the progress only goes up
+        let spin_up_ticks = (Laser::SPIN_UP_TIME * Time::DEFAULT_RATE as f32).round() as u32;
+        let mut progress = 0.0;
+        for _ in 0..spin_up_ticks {
+            assert!(!laser.warm_up(&time, true));
+            let next = laser.state().spin_up_progress().unwrap();
+            assert!(next >= progress);
+            progress = next;
+        }
+        assert!(progress < 1.0);
+        assert!(laser.warm_up(&time, true));
+        assert_eq!(laser.state(), LaserState::Hot);
+    }
+
+    #[test]
+    fn losing_the_target_cancels_the_spin_up() {
+        let time = Time::new(Time::DEFAULT_RATE);
+        let mut laser = Laser::new(12.0);
+        warm_up_for(&mut laser, &time, Laser::STANDBY_DELAY, false);
+        warm_up_for(&mut laser, &time, Laser::SPIN_UP_TIME / 2.0, true);
+        assert!(matches!(laser.state(), LaserState::SpinningUp(_)));
+
+        assert!(!laser.warm_up(&time, false));
+        assert_eq!(laser.state(), LaserState::Standby);
+        // The spin up starts over
+        assert_eq!(warm_up_for(&mut laser, &time, Laser::SPIN_UP_TIME, true), 0);
+    }
+
+    #[test]
+    fn a_short_pause_keeps_the_laser_hot() {
+        let time = Time::new(Time::DEFAULT_RATE);
+        let mut laser = Laser::new(12.0);
+        warm_up_for(&mut laser, &time, Laser::STANDBY_DELAY / 2.0, false);
+        assert!(laser.warm_up(&time, true));
+        // Firing again resets the delay
+        warm_up_for(&mut laser, &time, Laser::STANDBY_DELAY / 2.0, false);
+        assert_eq!(laser.state(), LaserState::Hot);
+    }
+
+    #[test]
+    fn keep_hot_bypasses_the_spin_up() {
+        let time = Time::new(Time::DEFAULT_RATE);
+        let mut laser = Laser::new(12.0);
+        laser.keep_hot = true;
+        assert_eq!(
+            warm_up_for(&mut laser, &time, Laser::STANDBY_DELAY * 4.0, false),
+            0
+        );
+        assert_eq!(laser.state(), LaserState::Hot);
+        assert!(laser.warm_up(&time, true));
+
+        // A laser that is already on standby spins up without a target
+        let mut laser = Laser::new(12.0);
+        warm_up_for(&mut laser, &time, Laser::STANDBY_DELAY, false);
+        assert_eq!(laser.state(), LaserState::Standby);
+        laser.keep_hot = true;
+        warm_up_for(&mut laser, &time, Laser::SPIN_UP_TIME + 0.1, false);
+        assert_eq!(laser.state(), LaserState::Hot);
+        assert!(laser.warm_up(&time, true));
+    }
+
+    /// Runs the laser at (0, 0, 0) for 5 seconds against a stationary asteroid of the faction,
+    /// either in auto mode or ordered to fire at it. Returns the damage the asteroid took.
+    fn laser_damage_to(faction: Faction, ordered: bool) -> u32 {
+        let mut headless = HeadlessEcs::with_seed(1);
+        headless.pause_asteroid_field();
+        let laser = headless
+            .with_ship(|ship| {
+                ship.tile(Point3::new(0, 0, 0))
+                    .and_then(|tile| tile.gadget())
+            })
+            .flatten()
+            .unwrap();
+        // Tough enough to outlast the test, and above the miner so it isn't mined
+        let asteroid = headless.spawn_asteroid(
+            Vector3::new(-5.0, -5.0, 2.0),
+            Vector3::new(0.0, 0.0, 0.0),
+            100.0,
+        );
+        {
+            let world = &headless.ecs.world;
+            world
+                .write_storage::<Faction>()
+                .insert(asteroid, faction)
+                .unwrap();
+            if ordered {
+                world
+                    .write_storage::<GadgetTarget>()
+                    .insert(laser, GadgetTarget(asteroid))
+                    .unwrap();
+            } else {
+                world
+                    .write_storage::<Laser>()
+                    .get_mut(laser)
+                    .unwrap()
+                    .set_auto(true);
+            }
+        }
+        let health = |headless: &HeadlessEcs| {
+            let healths = headless.ecs.world.read_storage::<Health>();
+            healths.get(asteroid).map(Health::health).unwrap()
+        };
+        let start = health(&headless);
+        headless.step(5 * Time::DEFAULT_RATE);
+        start - health(&headless)
+    }
+
+    /// Orders the laser at (0, 0, 0) to fire at an asteroid beside the ship for 5 seconds,
+    /// with or without a wall on the tile a third of the way. Returns the damage the
+    /// asteroid took, and where the beam ended.
+    fn laser_damage_past_wall(wall: bool) -> (u32, Vector3<f32>) {
+        let mut headless = HeadlessEcs::with_seed(1);
+        headless.pause_asteroid_field();
+        if wall {
+            headless.build_block(Point3::new(-2, -1, 0), "wall");
+        }
+        let laser = headless
+            .with_ship(|ship| {
+                ship.tile(Point3::new(0, 0, 0))
+                    .and_then(|tile| tile.gadget())
+            })
+            .flatten()
+            .unwrap();
+        let asteroid = headless.spawn_asteroid(
+            Vector3::new(-6.0, -3.0, 0.5),
+            Vector3::new(0.0, 0.0, 0.0),
+            100.0,
+        );
+        headless
+            .ecs
+            .world
+            .write_storage::<GadgetTarget>()
+            .insert(laser, GadgetTarget(asteroid))
+            .unwrap();
+        let health = |headless: &HeadlessEcs| {
+            let healths = headless.ecs.world.read_storage::<Health>();
+            healths.get(asteroid).map(Health::health).unwrap()
+        };
+        let start = health(&headless);
+        headless.step(5 * Time::DEFAULT_RATE);
+        let beam_end = headless
+            .ecs
+            .world
+            .read_storage::<Line>()
+            .get(laser)
+            .unwrap()
+            .pt2;
+        (start - health(&headless), beam_end)
+    }
+
+    #[test]
+    fn lasers_do_no_damage_through_walls() {
+        let (damage, beam_end) = laser_damage_past_wall(false);
+        assert!(damage > 0);
+        assert!((beam_end.truncate() - Vector2::new(-6.0, -3.0)).magnitude() < 1.5);
+
+        // The beam is still drawn, but it stops at the wall
+        let (damage, beam_end) = laser_damage_past_wall(true);
+        assert_eq!(damage, 0);
+        assert!((beam_end.truncate() - Vector2::new(-2.0, -1.0)).magnitude() < 1.0);
+    }
+
+    #[test]
+    fn lasers_never_fire_at_their_own_faction() {
+        for ordered in [false, true].iter().copied() {
+            // A neutral asteroid shows the laser can reach it
+            assert!(laser_damage_to(Faction::Neutral, ordered) > 0);
+            assert!(laser_damage_to(Faction::Hostile, ordered) > 0);
+            assert_eq!(laser_damage_to(Faction::Player, ordered), 0);
+        }
+    }
+}
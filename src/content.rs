@@ -0,0 +1,382 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Identifies a definition (ex. a block) by its content instead of its name. The name is
+/// not part of the hash, so saved content can still be found after it was renamed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ContentHash(u64);
+
+impl ContentHash {
+    pub fn parse(text: &str) -> Option<ContentHash> {
+        u64::from_str_radix(text, 16).ok().map(ContentHash)
+    }
+}
+
+impl fmt::Display for ContentHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// The canonical form of a definition, used to compute its ContentHash. Every value is
+/// written the same way whatever it was defined with, so only a change to the definition
+/// itself changes the hash.
+#[derive(Default)]
+pub struct ContentDefinition {
+    fields: BTreeMap<&'static str, String>,
+}
+
+impl ContentDefinition {
+    /// Adds text, integers and flags
+    pub fn field(mut self, key: &'static str, value: impl fmt::Display) -> Self {
+        self.fields.insert(key, value.to_string());
+        self
+    }
+
+    /// Numbers are written with their shortest exact form (ex. 3.0 and 3.00 are both "3.0"),
+    /// and -0.0 is the same as 0.0
+    pub fn number(self, key: &'static str, value: f32) -> Self {
+        self.field(key, canonical_number(value))
+    }
+
+    /// Adds numbers whose order matters (ex. the components of a vector)
+    pub fn numbers(self, key: &'static str, values: &[f32]) -> Self {
+        let values: Vec<String> = values.iter().copied().map(canonical_number).collect();
+        self.field(key, values.join(","))
+    }
+
+    /// Adds a list whose order doesn't matter (ex. the items of a cost). The entries are
+    /// sorted, so the hash doesn't depend on the order they were defined in.
+    pub fn list(self, key: &'static str, entries: impl IntoIterator<Item = String>) -> Self {
+        let mut entries: Vec<String> = entries.into_iter().collect();
+        entries.sort();
+        self.field(key, entries.join(","))
+    }
+
+    /// The fields are hashed sorted by key with FNV-1a, so the hash does not depend on
+    /// the order they were added in or on the platform. Each key and value is prefixed with
+    /// its length, so text in one field can't be mistaken for another field.
+    pub fn hash(&self) -> ContentHash {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for (key, value) in &self.fields {
+            let field = format!("{}:{}{}:{}", key.len(), key, value.len(), value);
+            for byte in field.bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+
+        ContentHash(hash)
+    }
+}
+
+/// Writes the number the same way however it was defined (see ContentDefinition::number)
+pub fn canonical_number(value: f32) -> String {
+    let value = if value == 0.0 { 0.0 } else { value };
+    format!("{:?}", value)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ContentKind {
+    Block,
+    Item,
+}
+
+impl ContentKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContentKind::Block => "block",
+            ContentKind::Item => "item",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ContentKind> {
+        match name {
+            "block" => Some(ContentKind::Block),
+            "item" => Some(ContentKind::Item),
+            _ => None,
+        }
+    }
+}
+
+/// The loaded content of one kind, which saved references are resolved against
+pub struct ContentIndex<T> {
+    kind: ContentKind,
+    entries: Vec<(String, ContentHash, T)>,
+}
+
+impl<T: Copy> ContentIndex<T> {
+    pub fn new(kind: ContentKind) -> Self {
+        Self {
+            kind,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, name: &str, hash: ContentHash, value: T) {
+        self.entries.push((name.to_string(), hash, value));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, ContentHash)> {
+        self.entries
+            .iter()
+            .map(|(name, hash, _)| (name.as_str(), *hash))
+    }
+
+    /// Finds saved content by its name first, and falls back to its hash (which finds renamed
+    /// content). If multiple definitions have the name, the one with the same hash is used.
+    /// Content that can't be found is added to the report.
+    pub fn resolve(&self, name: &str, hash: ContentHash, report: &mut LoadReport) -> Option<T> {
+        let named: Vec<&(String, ContentHash, T)> = self
+            .entries
+            .iter()
+            .filter(|(entry_name, _, _)| entry_name == name)
+            .collect();
+        if let Some((_, _, value)) = named
+            .iter()
+            .find(|(_, entry_hash, _)| *entry_hash == hash)
+            .or_else(|| {
+                if named.len() == 1 {
+                    named.first()
+                } else {
+                    None
+                }
+            })
+        {
+            return Some(*value);
+        }
+
+        let mut hashed = self
+            .entries
+            .iter()
+            .filter(|(_, entry_hash, _)| *entry_hash == hash);
+        match (hashed.next(), hashed.next()) {
+            (Some((new_name, _, value)), None) => {
+                report
+                    .renamed
+                    .push((self.kind, name.to_string(), new_name.clone()));
+                Some(*value)
+            }
+            _ => {
+                report.unresolved.push(UnresolvedContent {
+                    kind: self.kind,
+                    name: name.to_string(),
+                    hash,
+                    ambiguous: named.len() > 1,
+                });
+                None
+            }
+        }
+    }
+}
+
+pub struct UnresolvedContent {
+    pub kind: ContentKind,
+    /// The last known name of the content
+    pub name: String,
+    pub hash: ContentHash,
+    /// Multiple definitions have the name, but none of them have the hash
+    pub ambiguous: bool,
+}
+
+/// What happened to the saved content references while loading a file
+#[derive(Default)]
+pub struct LoadReport {
+    pub renamed: Vec<(ContentKind, String, String)>,
+    pub unresolved: Vec<UnresolvedContent>,
+}
+
+impl LoadReport {
+    /// The names of the content that couldn't be found, sorted and without duplicates
+    pub fn unresolved_names(&self) -> Vec<&str> {
+        let names: BTreeSet<&str> = self
+            .unresolved
+            .iter()
+            .map(|content| content.name.as_str())
+            .collect();
+        names.into_iter().collect()
+    }
+
+    pub fn print(&self, source: &str) {
+        for (kind, old_name, new_name) in &self.renamed {
+            println!(
+                "[{}] {} {} was renamed to {}",
+                source,
+                kind.name(),
+                old_name,
+                new_name
+            );
+        }
+        for content in &self.unresolved {
+            let reason = if content.ambiguous {
+                "multiple definitions have this name, but none match"
+            } else {
+                "the mod that adds it may be missing"
+            };
+            println!(
+                "[{}] Unresolved {} {} (hash {}): {}",
+                source,
+                content.kind.name(),
+                content.name,
+                content.hash,
+                reason
+            );
+        }
+    }
+}
+
+/// Assets that failed to load and were replaced by a placeholder, and mod content that
+/// isn't valid and was left out
+#[derive(Default)]
+pub struct ContentErrors {
+    errors: Vec<(String, String)>,
+}
+
+impl ContentErrors {
+    pub fn add(&mut self, asset: String, error: String) {
+        self.errors.push((asset, error));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.errors
+            .iter()
+            .map(|(asset, error)| (asset.as_str(), error.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// A block from a mod, which has the mod's file in its definition like the real mod blocks
+    fn mod_block(mod_path: &str, mesh: &str, cost: &[&str]) -> ContentHash {
+        ContentDefinition::default()
+            .field("mesh", mesh)
+            .list("cost", cost.iter().map(|entry| entry.to_string()))
+            .field("mod", crate::mods::hash_name(Path::new(mod_path)))
+            .hash()
+    }
+
+    #[test]
+    fn hash_does_not_depend_on_field_order() {
+        let a = ContentDefinition::default()
+            .field("mesh", "box")
+            .number("mass", 2.0)
+            .list(
+                "cost",
+                vec![String::from("Iron:1"), String::from("Copper:2")],
+            );
+        let b = ContentDefinition::default()
+            .list(
+                "cost",
+                vec![String::from("Copper:2"), String::from("Iron:1")],
+            )
+            .number("mass", "2.00".parse().unwrap())
+            .field("mesh", "box");
+
+        assert_eq!(a.hash(), b.hash());
+        assert_eq!(
+            ContentDefinition::default().number("heat", -0.0).hash(),
+            ContentDefinition::default().number("heat", 0.0).hash()
+        );
+    }
+
+    #[test]
+    fn hash_changes_with_any_field() {
+        let hash = mod_block("mods/blocks/a.ron", "drill", &["Iron:2"]);
+
+        assert_ne!(hash, mod_block("mods/blocks/a.ron", "drill", &["Iron:3"]));
+        assert_ne!(hash, mod_block("mods/blocks/a.ron", "drill2", &["Iron:2"]));
+        // A field's text can't run into the next field
+        assert_ne!(
+            ContentDefinition::default().field("a", "1b").hash(),
+            ContentDefinition::default()
+                .field("a", "1")
+                .field("b", "")
+                .hash()
+        );
+    }
+
+    #[test]
+    fn renamed_content_is_found_by_its_hash() {
+        let hash = mod_block("mods/blocks/a.ron", "drill", &["Iron:2"]);
+        let mut index = ContentIndex::new(ContentKind::Block);
+        index.add("Box", mod_block("mods/blocks/a.ron", "box", &["Iron:1"]), 0);
+        index.add("Drill", hash, 1);
+        let mut report = LoadReport::default();
+
+        assert_eq!(index.resolve("Drill", hash, &mut report), Some(1));
+        assert!(report.renamed.is_empty());
+
+        assert_eq!(index.resolve("OldDrill", hash, &mut report), Some(1));
+        assert_eq!(
+            report.renamed,
+            [(
+                ContentKind::Block,
+                String::from("OldDrill"),
+                String::from("Drill")
+            )]
+        );
+        assert!(report.unresolved.is_empty());
+    }
+
+    #[test]
+    fn colliding_names_from_two_mods_are_told_apart_by_hash() {
+        // Both mods define a "Drill" that looks the same
+        let first = mod_block("mods/blocks/first.ron", "drill", &["Iron:2"]);
+        let second = mod_block("mods/blocks/second.ron", "drill", &["Iron:2"]);
+        assert_ne!(first, second);
+
+        let mut index = ContentIndex::new(ContentKind::Block);
+        index.add("Drill", first, 10);
+        index.add("Drill", second, 20);
+        let mut report = LoadReport::default();
+
+        assert_eq!(index.resolve("Drill", first, &mut report), Some(10));
+        assert_eq!(index.resolve("Drill", second, &mut report), Some(20));
+        assert!(report.unresolved.is_empty());
+
+        // A third mod's drill can't be told apart from either, so it isn't guessed
+        let third = mod_block("mods/blocks/third.ron", "drill", &["Iron:2"]);
+        assert_eq!(index.resolve("Drill", third, &mut report), None);
+        assert_eq!(report.unresolved.len(), 1);
+        assert!(report.unresolved[0].ambiguous);
+        assert_eq!(report.unresolved[0].hash, third);
+        assert_eq!(report.unresolved_names(), ["Drill"]);
+    }
+
+    #[test]
+    fn mod_hashes_do_not_depend_on_where_the_game_is() {
+        let hash = mod_block("mods/blocks/first.ron", "drill", &["Iron:2"]);
+        assert_eq!(hash, mod_block("first.ron", "drill", &["Iron:2"]));
+        assert_eq!(
+            hash,
+            mod_block(
+                "/home/player/spaceship/mods/blocks/first.ron",
+                "drill",
+                &["Iron:2"]
+            )
+        );
+        assert_ne!(
+            hash,
+            mod_block("mods/blocks/second.ron", "drill", &["Iron:2"])
+        );
+    }
+
+    #[test]
+    fn missing_content_is_reported_with_its_last_name() {
+        let index: ContentIndex<usize> = ContentIndex::new(ContentKind::Item);
+        let hash = mod_block("mods/blocks/gone.ron", "ore", &[]);
+        let mut report = LoadReport::default();
+
+        assert_eq!(index.resolve("Gold", hash, &mut report), None);
+        assert_eq!(report.unresolved[0].name, "Gold");
+        assert_eq!(report.unresolved[0].hash, hash);
+        assert!(!report.unresolved[0].ambiguous);
+    }
+}
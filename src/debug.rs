@@ -0,0 +1,733 @@
+use crate::block::{Blocks, Laser};
+use crate::entity::{
+    blueprint::{self, Blueprint, BlueprintTool},
+    construction::ConstructionQueue,
+    faction::Faction,
+    ship::{self, BuildAction, SelectedShip},
+    HitboxOverlay, InputAction, InputManager, Particles, Ship, ECS,
+};
+use crate::floor::Floors;
+use crate::graphics::{ColorCalibration, MeshManager};
+use crate::i18n::Strings;
+use crate::item::{GameItem, Inventory};
+use crate::tech::TechTree;
+use cgmath::{Point2, Point3};
+use specs::prelude::*;
+
+/// Debug commands are only available in debug builds
+pub const DEBUG_COMMANDS_ENABLED: bool = cfg!(debug_assertions);
+
+pub enum ArgType {
+    Int,
+    Float,
+    Bool,
+    /// A single word
+    Text,
+    Choice(&'static [&'static str]),
+}
+
+pub struct ArgSchema {
+    pub name: &'static str,
+    pub arg_type: ArgType,
+}
+
+impl ArgSchema {
+    pub fn describe(&self) -> String {
+        match self.arg_type {
+            ArgType::Int => format!("<{}: int>", self.name),
+            ArgType::Float => format!("<{}: number>", self.name),
+            ArgType::Bool => format!("<{}: true|false>", self.name),
+            ArgType::Text => format!("<{}: text>", self.name),
+            ArgType::Choice(choices) => format!("<{}: {}>", self.name, choices.join("|")),
+        }
+    }
+
+    fn parse(&self, input: &str) -> Result<ArgValue, String> {
+        let invalid = || format!("Invalid value for {}: {}", self.describe(), input);
+
+        match self.arg_type {
+            ArgType::Int => input.parse().map(ArgValue::Int).map_err(|_| invalid()),
+            ArgType::Float => input
+                .parse()
+                .ok()
+                .filter(|value: &f32| value.is_finite())
+                .map(ArgValue::Float)
+                .ok_or_else(invalid),
+            ArgType::Bool => match input {
+                "true" | "on" | "1" => Ok(ArgValue::Bool(true)),
+                "false" | "off" | "0" => Ok(ArgValue::Bool(false)),
+                _ => Err(invalid()),
+            },
+            ArgType::Text => Ok(ArgValue::Text(input.to_string())),
+            ArgType::Choice(choices) => choices
+                .iter()
+                .find(|choice| choice.eq_ignore_ascii_case(input))
+                .map(|choice| ArgValue::Choice(choice))
+                .ok_or_else(invalid),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ArgValue {
+    Int(i64),
+    Float(f32),
+    Bool(bool),
+    Text(String),
+    Choice(&'static str),
+}
+
+impl ArgValue {
+    pub fn as_int(&self) -> i64 {
+        match self {
+            ArgValue::Int(value) => *value,
+            _ => panic!("Argument is not an int: {:?}", self),
+        }
+    }
+
+    pub fn as_float(&self) -> f32 {
+        match self {
+            ArgValue::Float(value) => *value,
+            _ => panic!("Argument is not a number: {:?}", self),
+        }
+    }
+
+    pub fn as_bool(&self) -> bool {
+        match self {
+            ArgValue::Bool(value) => *value,
+            _ => panic!("Argument is not a bool: {:?}", self),
+        }
+    }
+
+    pub fn as_text(&self) -> &str {
+        match self {
+            ArgValue::Text(value) => value,
+            _ => panic!("Argument is not text: {:?}", self),
+        }
+    }
+
+    pub fn as_choice(&self) -> &'static str {
+        match self {
+            ArgValue::Choice(value) => value,
+            _ => panic!("Argument is not a choice: {:?}", self),
+        }
+    }
+}
+
+pub type CommandState = fn(&ECS) -> String;
+pub type CommandAction = fn(&mut ECS, &[ArgValue]) -> Result<(), String>;
+
+pub struct DebugCommand {
+    pub name: &'static str,
+    /// A one line description that is shown in the command palette and the console's help
+    pub description: &'static str,
+    pub args: Vec<ArgSchema>,
+    /// Returns the current value of whatever the command changes
+    pub state: Option<CommandState>,
+    pub action: CommandAction,
+}
+
+impl DebugCommand {
+    pub fn usage(&self) -> String {
+        let args: Vec<String> = self.args.iter().map(ArgSchema::describe).collect();
+        format!("{} {}", self.name, args.join(" "))
+    }
+
+    /// Parses whitespace separated arguments using the command's argument schema
+    pub fn parse_args(&self, input: &str) -> Result<Vec<ArgValue>, String> {
+        let inputs: Vec<&str> = input.split_whitespace().collect();
+        if inputs.len() != self.args.len() {
+            return Err(format!("Usage: {}", self.usage()));
+        }
+
+        self.args
+            .iter()
+            .zip(inputs)
+            .map(|(schema, input)| schema.parse(input))
+            .collect()
+    }
+}
+
+pub struct DebugCommands {
+    commands: Vec<DebugCommand>,
+}
+
+impl DebugCommands {
+    /// Creates the built in commands, and the commands of the ECS's modules
+    pub fn new(ecs: &ECS) -> Self {
+        let mut commands = Self {
+            commands: Vec::new(),
+        };
+        register_commands(&mut commands);
+        ecs.register_commands(&mut commands);
+
+        commands
+    }
+
+    pub fn register(&mut self, command: DebugCommand) {
+        assert!(
+            self.commands.iter().all(|other| other.name != command.name),
+            "Duplicate debug command: {}",
+            command.name
+        );
+        self.commands.push(command);
+    }
+
+    pub fn get(&self, index: usize) -> &DebugCommand {
+        &self.commands[index]
+    }
+
+    pub fn find(&self, name: &str) -> Option<&DebugCommand> {
+        self.commands.iter().find(|command| command.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &DebugCommand> {
+        self.commands.iter()
+    }
+
+    /// Returns the indices of the commands that match the query, best match first.
+    /// Commands in `recent` (most recent first) are always sorted before the other commands.
+    pub fn search(&self, query: &str, recent: &[&str]) -> Vec<usize> {
+        let mut matches: Vec<(usize, usize, i32)> = self
+            .commands
+            .iter()
+            .enumerate()
+            .filter_map(|(index, command)| {
+                let score = fuzzy_score(query, command.name)?;
+                let recent_rank = recent
+                    .iter()
+                    .position(|name| *name == command.name)
+                    .unwrap_or(recent.len());
+                Some((index, recent_rank, score))
+            })
+            .collect();
+
+        matches.sort_by(|a, b| a.1.cmp(&b.1).then(b.2.cmp(&a.2)).then(a.0.cmp(&b.0)));
+        matches.into_iter().map(|(index, _, _)| index).collect()
+    }
+}
+
+/// Scores how well `query` matches `text` when the characters of the query are found
+/// in order (but not necessarily next to each other). Consecutive characters and
+/// characters at the start of a word score higher. Returns None if there is no match.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i32> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut last_match: Option<usize> = None;
+
+    for c in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = next + text[next..].iter().position(|other| *other == c)?;
+
+        score += 1;
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 8;
+        }
+        match last_match {
+            Some(last) if last + 1 == found => score += 5,
+            Some(last) => score -= (found - last) as i32,
+            None => {}
+        }
+
+        last_match = Some(found);
+        next = found + 1;
+    }
+
+    // Prefer shorter commands when everything else is equal
+    Some(score * 4 - text.len() as i32)
+}
+
+fn on_off(value: bool) -> String {
+    String::from(if value { "on" } else { "off" })
+}
+
+fn register_commands(commands: &mut DebugCommands) {
+    commands.register(DebugCommand {
+        name: "render_hitboxes",
+        description: "Draws the hitbox of every collider as a wireframe (also toggled with F6)",
+        args: vec![ArgSchema {
+            name: "enabled",
+            arg_type: ArgType::Bool,
+        }],
+        state: Some(|ecs| on_off(ecs.get_resource::<HitboxOverlay>().visible)),
+        action: |ecs, args| {
+            ecs.get_resource_mut::<HitboxOverlay>().visible = args[0].as_bool();
+            Ok(())
+        },
+    });
+
+    commands.register(DebugCommand {
+        name: "show_string_keys",
+        description: "Shows the keys of the text instead of the text, to find what isn't localized",
+        args: vec![ArgSchema {
+            name: "enabled",
+            arg_type: ArgType::Bool,
+        }],
+        state: Some(|ecs| on_off(ecs.get_resource::<Strings>().show_keys())),
+        action: |ecs, args| {
+            ecs.get_resource_mut::<Strings>()
+                .set_show_keys(args[0].as_bool());
+            Ok(())
+        },
+    });
+
+    commands.register(DebugCommand {
+        name: "construction_time",
+        description: "Builds the player's blocks with construction drones instead of instantly",
+        args: vec![ArgSchema {
+            name: "enabled",
+            arg_type: ArgType::Bool,
+        }],
+        state: Some(|ecs| on_off(ecs.get_resource::<ConstructionQueue>().enabled)),
+        action: |ecs, args| {
+            ecs.get_resource_mut::<ConstructionQueue>().enabled = args[0].as_bool();
+            Ok(())
+        },
+    });
+
+    commands.register(DebugCommand {
+        name: "render_blocks",
+        description: "Draws the blocks of the ship",
+        args: vec![ArgSchema {
+            name: "enabled",
+            arg_type: ArgType::Bool,
+        }],
+        state: Some(|ecs| {
+            let blocks = ecs.get_resource::<Blocks>();
+            let mesh_manager = ecs.get_resource::<MeshManager>();
+            on_off(
+                blocks
+                    .iter()
+                    .any(|block| mesh_manager.is_mesh_visible(block.mesh_id)),
+            )
+        }),
+        action: |ecs, args| {
+            let blocks = ecs.get_resource::<Blocks>();
+            let mut mesh_manager = ecs.get_resource_mut::<MeshManager>();
+            for block in blocks.iter() {
+                mesh_manager.set_mesh_visisble(block.mesh_id, args[0].as_bool());
+            }
+            Ok(())
+        },
+    });
+
+    commands.register(DebugCommand {
+        name: "set_action",
+        description: "Changes what happens when the world is clicked",
+        args: vec![ArgSchema {
+            name: "action",
+            arg_type: ArgType::Choice(&[
+                "none",
+                "mining",
+                "laser",
+                "repulsor",
+                "tractor",
+                "cannon",
+                "copy_blueprint",
+                "stamp_blueprint",
+                "paint",
+            ]),
+        }],
+        state: Some(|ecs| format!("{:?}", ecs.get_resource::<InputManager>().action)),
+        action: |ecs, args| {
+            ecs.get_resource_mut::<InputManager>().action = match args[0].as_choice() {
+                "mining" => InputAction::Mining,
+                "laser" => InputAction::Laser,
+                "repulsor" => InputAction::Repulsor,
+                "tractor" => InputAction::Tractor,
+                "cannon" => InputAction::Cannon,
+                "copy_blueprint" => InputAction::CopyBlueprint,
+                "stamp_blueprint" => InputAction::StampBlueprint,
+                // The first color that isn't white
+                "paint" => InputAction::Paint(1),
+                _ => InputAction::None,
+            };
+            Ok(())
+        },
+    });
+
+    commands.register(DebugCommand {
+        name: "set_faction",
+        description: "Changes the faction of the entity under the cursor",
+        args: vec![ArgSchema {
+            name: "faction",
+            arg_type: ArgType::Choice(&["player", "hostile", "neutral"]),
+        }],
+        state: None,
+        action: |ecs, args| {
+            let entity = ecs
+                .get_resource::<InputManager>()
+                .hovered
+                .ok_or_else(|| String::from("Nothing is under the cursor"))?;
+            let faction = Faction::from_name(args[0].as_choice()).unwrap();
+            ecs.world
+                .write_component::<Faction>()
+                .insert(entity, faction)
+                .map_err(|error| error.to_string())?;
+            Ok(())
+        },
+    });
+
+    commands.register(DebugCommand {
+        name: "keep_hot",
+        description: "Keeps the laser under the cursor from going on standby",
+        args: vec![ArgSchema {
+            name: "enabled",
+            arg_type: ArgType::Bool,
+        }],
+        state: None,
+        action: |ecs, args| {
+            let entity = ecs.get_resource::<InputManager>().hovered;
+            let mut lasers = ecs.world.write_component::<Laser>();
+            let laser = entity
+                .and_then(|entity| lasers.get_mut(entity))
+                .ok_or_else(|| String::from("There is no laser under the cursor"))?;
+            laser.keep_hot = args[0].as_bool();
+            Ok(())
+        },
+    });
+
+    commands.register(DebugCommand {
+        name: "give_item",
+        description: "Adds items to the inventory",
+        args: vec![
+            ArgSchema {
+                name: "item",
+                arg_type: ArgType::Choice(&["iron", "copper"]),
+            },
+            ArgSchema {
+                name: "amount",
+                arg_type: ArgType::Int,
+            },
+        ],
+        state: Some(|ecs| {
+            let inventory = ecs.get_resource::<Inventory>();
+            let amounts: Vec<String> = GameItem::iter()
+                .map(|item| format!("{:?}: {}", item, inventory.amount(item)))
+                .collect();
+            amounts.join(", ")
+        }),
+        action: |ecs, args| {
+            let amount = args[1].as_int();
+            if amount < 0 || amount > u32::MAX as i64 {
+                return Err(format!("Amount must be positive: {}", amount));
+            }
+
+            let item = GameItem::from_name(args[0].as_choice()).unwrap();
+            let mut inventory = ecs.get_resource_mut::<Inventory>();
+            if inventory.amount(&item).checked_add(amount as u32).is_none() {
+                return Err(format!(
+                    "The inventory can't hold {} more {:?}",
+                    amount, item
+                ));
+            }
+            inventory.change_amount(item, amount as u32);
+            Ok(())
+        },
+    });
+
+    commands.register(DebugCommand {
+        name: "spawn_drone_ship",
+        description: "Deploys a drone ship with its corner at the tile (ex. 0 -10), and selects it",
+        args: vec![
+            ArgSchema {
+                name: "x",
+                arg_type: ArgType::Int,
+            },
+            ArgSchema {
+                name: "y",
+                arg_type: ArgType::Int,
+            },
+        ],
+        state: None,
+        action: |ecs, args| {
+            let origin = Point2::new(args[0].as_int() as i16, args[1].as_int() as i16);
+            let ship = ship::create_drone_ship(&mut ecs.world, origin)?;
+            ecs.get_resource_mut::<SelectedShip>().0 = Some(ship);
+            Ok(())
+        },
+    });
+
+    commands.register(DebugCommand {
+        name: "build_block",
+        description: "Builds a block on the active deck of the selected ship (if it is unlocked)",
+        args: vec![
+            ArgSchema {
+                name: "type",
+                arg_type: ArgType::Text,
+            },
+            ArgSchema {
+                name: "x",
+                arg_type: ArgType::Int,
+            },
+            ArgSchema {
+                name: "y",
+                arg_type: ArgType::Int,
+            },
+        ],
+        state: None,
+        action: |ecs, args| {
+            let block_id = ecs
+                .get_resource::<Blocks>()
+                .find(args[0].as_text())
+                .ok_or_else(|| format!("Unknown block type: {}", args[0].as_text()))?;
+            if let Some(node) = ecs.get_resource::<TechTree>().required_node(block_id) {
+                return Err(format!("{} requires {}", args[0].as_text(), node.name));
+            }
+
+            let pos = Point3::new(
+                args[1].as_int() as i16,
+                args[2].as_int() as i16,
+                ecs.get_resource::<InputManager>().deck,
+            );
+            let ship = {
+                let ships = ecs.world.read_component::<Ship>();
+                let selected = ecs.get_resource::<SelectedShip>();
+                let (ship_entity, ship) = selected
+                    .find(&ecs.world.entities(), &ships)
+                    .ok_or("There is no ship")?;
+                if !ship.has_tile(pos) {
+                    return Err(format!("({}, {}) is outside of the ship", pos.x, pos.y));
+                }
+                ship_entity
+            };
+
+            ship::execute_build_actions(
+                &mut ecs.world,
+                ship,
+                &[BuildAction::BuildBlock(pos, block_id)],
+            );
+            Ok(())
+        },
+    });
+
+    commands.register(DebugCommand {
+        name: "build_floor",
+        description: "Builds a floor on the active deck of the selected ship, ex. a conveyor",
+        args: vec![
+            ArgSchema {
+                name: "type",
+                arg_type: ArgType::Text,
+            },
+            ArgSchema {
+                name: "x",
+                arg_type: ArgType::Int,
+            },
+            ArgSchema {
+                name: "y",
+                arg_type: ArgType::Int,
+            },
+        ],
+        state: None,
+        action: |ecs, args| {
+            let floors = ecs.get_resource::<Floors>();
+            let floor = floors.find(args[0].as_text()).ok_or_else(|| {
+                format!(
+                    "Unknown floor type: {} (one of {})",
+                    args[0].as_text(),
+                    floors.names().join(", ")
+                )
+            })?;
+            std::mem::drop(floors);
+
+            let pos = Point3::new(
+                args[1].as_int() as i16,
+                args[2].as_int() as i16,
+                ecs.get_resource::<InputManager>().deck,
+            );
+            let ship = {
+                let ships = ecs.world.read_component::<Ship>();
+                let selected = ecs.get_resource::<SelectedShip>();
+                let (ship_entity, ship) = selected
+                    .find(&ecs.world.entities(), &ships)
+                    .ok_or("There is no ship")?;
+                if !ship.has_tile(pos) {
+                    return Err(format!("({}, {}) is outside of the ship", pos.x, pos.y));
+                }
+                ship_entity
+            };
+
+            ship::execute_build_actions(
+                &mut ecs.world,
+                ship,
+                &[BuildAction::BuildFloor(pos, floor)],
+            );
+            Ok(())
+        },
+    });
+
+    commands.register(DebugCommand {
+        name: "export_report",
+        description: "Saves a JSON report of the current run (also saved at game over)",
+        args: Vec::new(),
+        state: None,
+        action: |ecs, _| {
+            crate::report::export_report(&ecs.world);
+            Ok(())
+        },
+    });
+
+    commands.register(DebugCommand {
+        name: "save_blueprint",
+        description: "Saves the copied blueprint, so it can be shared",
+        args: vec![ArgSchema {
+            name: "name",
+            arg_type: ArgType::Text,
+        }],
+        state: Some(|ecs| match &ecs.get_resource::<BlueprintTool>().blueprint {
+            Some(blueprint) => format!("{} blocks copied", blueprint.block_count()),
+            None => String::from("Nothing copied"),
+        }),
+        action: |ecs, args| {
+            let tool = ecs.get_resource::<BlueprintTool>();
+            let blueprint = tool.blueprint.as_ref().ok_or("Nothing has been copied")?;
+            blueprint.save(
+                &format!("{}/{}.txt", blueprint::BLUEPRINT_DIR, args[0].as_text()),
+                &ecs.get_resource::<Blocks>(),
+                &ecs.get_resource::<Floors>(),
+            )
+        },
+    });
+
+    commands.register(DebugCommand {
+        name: "load_blueprint",
+        description: "Loads a saved blueprint, so it can be stamped",
+        args: vec![ArgSchema {
+            name: "name",
+            arg_type: ArgType::Text,
+        }],
+        state: None,
+        action: |ecs, args| {
+            let blueprint = Blueprint::load(
+                &format!("{}/{}.txt", blueprint::BLUEPRINT_DIR, args[0].as_text()),
+                &ecs.get_resource::<Blocks>(),
+                &ecs.get_resource::<Floors>(),
+                &ecs.get_resource::<Strings>(),
+            )?;
+            ecs.get_resource_mut::<BlueprintTool>().blueprint = Some(blueprint);
+            ecs.get_resource_mut::<InputManager>().action = InputAction::StampBlueprint;
+            Ok(())
+        },
+    });
+
+    commands.register(DebugCommand {
+        name: "clear_particles",
+        description: "Removes every particle",
+        args: Vec::new(),
+        state: Some(|ecs| format!("{} particles", ecs.get_resource::<Particles>().count())),
+        action: |ecs, _| {
+            ecs.get_resource_mut::<Particles>().clear();
+            Ok(())
+        },
+    });
+
+    commands.register(DebugCommand {
+        name: "color_calibration",
+        description: "Shows color swatches for checking the color pipeline",
+        args: vec![ArgSchema {
+            name: "enabled",
+            arg_type: ArgType::Bool,
+        }],
+        state: Some(|ecs| on_off(ecs.get_resource::<ColorCalibration>().visible)),
+        action: |ecs, args| {
+            ecs.get_resource_mut::<ColorCalibration>().visible = args[0].as_bool();
+            Ok(())
+        },
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::headless::HeadlessEcs;
+
+    fn commands(names: &[&'static str]) -> DebugCommands {
+        let mut commands = DebugCommands {
+            commands: Vec::new(),
+        };
+        for name in names {
+            commands.register(DebugCommand {
+                name,
+                description: "",
+                args: Vec::new(),
+                state: None,
+                action: |_, _| Ok(()),
+            });
+        }
+
+        commands
+    }
+
+    fn search<'a>(commands: &'a DebugCommands, query: &str, recent: &[&str]) -> Vec<&'a str> {
+        commands
+            .search(query, recent)
+            .into_iter()
+            .map(|index| commands.get(index).name)
+            .collect()
+    }
+
+    #[test]
+    fn query_must_be_an_ordered_subsequence() {
+        assert!(fuzzy_score("rh", "render_hitboxes").is_some());
+        assert!(fuzzy_score("RENDER", "render_hitboxes").is_some());
+        assert!(fuzzy_score("hr", "render_hitboxes").is_none());
+        assert!(fuzzy_score("renderz", "render_hitboxes").is_none());
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        let commands = commands(&["spawn_asteroid", "clear_particles"]);
+        assert_eq!(search(&commands, "", &[]).len(), 2);
+    }
+
+    #[test]
+    fn word_starts_rank_above_letters_inside_words() {
+        let commands = commands(&["ammo_set", "mine_all"]);
+        assert_eq!(search(&commands, "m", &[]), ["mine_all", "ammo_set"]);
+        assert!(fuzzy_score("hud", "toggle_hud") > fuzzy_score("hud", "shudder_on"));
+    }
+
+    #[test]
+    fn consecutive_letters_rank_above_scattered_ones() {
+        let commands = commands(&["set_paint_area", "paint"]);
+        assert_eq!(search(&commands, "pai", &[]), ["paint", "set_paint_area"]);
+        assert!(fuzzy_score("part", "particles") > fuzzy_score("part", "pause_rate"));
+    }
+
+    #[test]
+    fn shorter_commands_win_ties() {
+        assert!(fuzzy_score("heat", "heat") > fuzzy_score("heat", "heat_overlay"));
+    }
+
+    #[test]
+    fn recent_commands_sort_first() {
+        let commands = commands(&["clear_particles", "color_calibration", "spawn_wave"]);
+        assert_eq!(
+            search(&commands, "a", &["spawn_wave", "color_calibration"]),
+            ["spawn_wave", "color_calibration", "clear_particles"]
+        );
+        // Recent commands are still filtered by the query
+        assert_eq!(
+            search(&commands, "clear", &["spawn_wave"]),
+            ["clear_particles"]
+        );
+    }
+
+    #[test]
+    fn give_item_rejects_amounts_that_overflow() {
+        let mut headless = HeadlessEcs::with_seed(1);
+        let commands = DebugCommands::new(&headless.ecs);
+        let give_item = commands.find("give_item").unwrap();
+        let give = |headless: &mut HeadlessEcs, args: &str| {
+            let args = give_item.parse_args(args).unwrap();
+            (give_item.action)(&mut headless.ecs, &args)
+        };
+
+        let before = headless.inventory().amount(&GameItem::Iron);
+        assert!(give(&mut headless, "iron 5").is_ok());
+        assert!(give(&mut headless, "iron -1").is_err());
+        let too_many = format!("iron {}", u32::MAX);
+        assert!(give(&mut headless, &too_many).is_err());
+        assert_eq!(headless.inventory().amount(&GameItem::Iron), before + 5);
+    }
+}
@@ -0,0 +1,299 @@
+use super::{paint::Paint, Model, Time, Transform};
+use crate::graphics::{MeshId, MeshManager, MeshNode, ModelId, ModelTint};
+use cgmath::{prelude::*, Matrix4, Quaternion, Vector3};
+use specs::{prelude::*, world::LazyBuilder, Component};
+
+/// The pose of a node at a point in a track, relative to where the node rests
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    /// The seconds since the start of the clip
+    pub time: f32,
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Keyframe {
+    /// The node where it rests
+    pub const fn rest(time: f32) -> Keyframe {
+        Keyframe {
+            time,
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion {
+                s: 1.0,
+                v: Vector3::new(0.0, 0.0, 0.0),
+            },
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub const fn translated(time: f32, translation: Vector3<f32>) -> Keyframe {
+        Keyframe {
+            translation,
+            ..Keyframe::rest(time)
+        }
+    }
+
+    pub const fn scaled(time: f32, scale: Vector3<f32>) -> Keyframe {
+        Keyframe {
+            scale,
+            ..Keyframe::rest(time)
+        }
+    }
+}
+
+/// Moves one node of the mesh through its keyframes, which are sorted by time
+pub struct AnimationTrack {
+    /// The name of the sub-mesh (ex. "barrel" for the "node_barrel" object)
+    pub node: &'static str,
+    pub keyframes: &'static [Keyframe],
+}
+
+impl AnimationTrack {
+    /// Blends between the keyframes around the time. Before the first keyframe and after
+    /// the last one, the node holds their pose.
+    fn sample(&self, time: f32) -> Matrix4<f32> {
+        let next = self
+            .keyframes
+            .iter()
+            .position(|keyframe| keyframe.time > time)
+            .unwrap_or(self.keyframes.len());
+        let (from, to) = match next {
+            0 => (self.keyframes[0], self.keyframes[0]),
+            next if next == self.keyframes.len() => {
+                (self.keyframes[next - 1], self.keyframes[next - 1])
+            }
+            next => (self.keyframes[next - 1], self.keyframes[next]),
+        };
+
+        let span = to.time - from.time;
+        let t = if span > 0.0 {
+            (time - from.time) / span
+        } else {
+            0.0
+        };
+        let translation = from.translation.lerp(to.translation, t);
+        let rotation = from.rotation.nlerp(to.rotation, t);
+        let scale = from.scale.lerp(to.scale, t);
+
+        Matrix4::from_translation(translation)
+            * Matrix4::from(rotation)
+            * Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z)
+    }
+}
+
+/// Keyframe tracks that are played together. Nodes without a track stay where they rest.
+pub struct AnimationClip {
+    /// Tells clips apart, so a looping clip is not restarted by playing it again
+    pub name: &'static str,
+    pub tracks: &'static [AnimationTrack],
+    pub looping: bool,
+}
+
+impl AnimationClip {
+    fn duration(&self) -> f32 {
+        self.tracks
+            .iter()
+            .filter_map(|track| track.keyframes.last())
+            .map(|keyframe| keyframe.time)
+            .fold(0.0, f32::max)
+    }
+}
+
+/// A sub-mesh that is drawn as its own model, which follows the entity's Transform
+struct AnimatedNode {
+    name: String,
+    mesh_id: MeshId,
+    /// Places the node relative to the entity (ex. at its pivot)
+    local: Matrix4<f32>,
+    pose: Matrix4<f32>,
+    model_id: Option<ModelId>,
+}
+
+/// Plays animations on the sub-meshes of an entity's mesh. It is only added to entities
+/// whose mesh has sub-meshes (see with_animation), so other models are not touched.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct Animation {
+    nodes: Vec<AnimatedNode>,
+    clip: Option<&'static AnimationClip>,
+    /// The seconds since the clip started
+    time: f32,
+    /// The Transform, tint and paint that the node models were last updated with
+    base: Matrix4<f32>,
+    tint: ModelTint,
+    paint: Paint,
+    /// Whether the poses changed since the node models were last updated
+    dirty: bool,
+}
+
+impl Animation {
+    /// The meshes of the node models, and where they were last drawn (see AnimationSystem)
+    pub fn node_models(&self) -> impl Iterator<Item = (MeshId, Matrix4<f32>)> + '_ {
+        self.nodes
+            .iter()
+            .map(move |node| (node.mesh_id, self.base * node.local * node.pose))
+    }
+
+    pub fn new(nodes: &[MeshNode]) -> Self {
+        Self {
+            nodes: nodes
+                .iter()
+                .map(|node| AnimatedNode {
+                    name: node.name.clone(),
+                    mesh_id: node.mesh_id,
+                    local: node.local,
+                    pose: Matrix4::identity(),
+                    model_id: None,
+                })
+                .collect(),
+            clip: None,
+            time: 0.0,
+            base: Matrix4::identity(),
+            tint: ModelTint::NONE,
+            paint: Paint::WHITE,
+            dirty: true,
+        }
+    }
+
+    /// Starts the clip from the beginning, even if it is already playing
+    pub fn play(&mut self, clip: &'static AnimationClip) {
+        self.clip = Some(clip);
+        self.time = 0.0;
+    }
+
+    /// Starts the clip unless it is already playing, for loops that are kept going while
+    /// something is true
+    pub fn keep_playing(&mut self, clip: &'static AnimationClip) {
+        if !self.is_playing(clip) {
+            self.play(clip);
+        }
+    }
+
+    pub fn is_playing(&self, clip: &AnimationClip) -> bool {
+        self.clip.map(|playing| playing.name) == Some(clip.name)
+    }
+
+    /// Stops the clip, and puts the nodes back where they rest
+    pub fn stop(&mut self) {
+        if self.clip.take().is_some() {
+            for node in &mut self.nodes {
+                node.pose = Matrix4::identity();
+            }
+            self.dirty = true;
+        }
+    }
+
+    fn sample(&mut self, clip: &AnimationClip) {
+        let time = self.time;
+        for node in &mut self.nodes {
+            node.pose = clip
+                .tracks
+                .iter()
+                .find(|track| track.node == node.name)
+                .map(|track| track.sample(time))
+                .unwrap_or_else(Matrix4::identity);
+        }
+        self.dirty = true;
+    }
+}
+
+/// Adds an Animation if the mesh has sub-meshes
+pub fn with_animation<'a>(builder: LazyBuilder<'a>, nodes: &[MeshNode]) -> LazyBuilder<'a> {
+    if nodes.is_empty() {
+        builder
+    } else {
+        builder.with(Animation::new(nodes))
+    }
+}
+
+/// Advances the clips, and moves the node models with their poses and the entity's
+/// Transform. Node models are only updated when something changed.
+pub struct AnimationSystem;
+
+impl<'a> System<'a> for AnimationSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Time>,
+        WriteExpect<'a, MeshManager>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Model>,
+        ReadStorage<'a, Paint>,
+        WriteStorage<'a, Animation>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, time, mut mesh_manager, transforms, models, paints, mut animations) = data;
+
+        for (entity, animation, transform, model, paint) in (
+            &entities,
+            &mut animations,
+            &transforms,
+            models.maybe(),
+            paints.maybe(),
+        )
+            .join()
+        {
+            if let Some(clip) = animation.clip {
+                animation.time += time.delta;
+                let duration = clip.duration();
+                if clip.looping && duration > 0.0 {
+                    animation.time %= duration;
+                }
+                animation.sample(clip);
+                if !clip.looping && animation.time >= duration - Time::SLACK {
+                    animation.clip = None;
+                }
+            }
+
+            let base = transform.as_matrix();
+            let tint = model.map(|model| model.tint).unwrap_or(ModelTint::NONE);
+            let paint = paint.copied().unwrap_or(Paint::WHITE);
+            let created = animation.nodes.iter().all(|node| node.model_id.is_some());
+            if created
+                && !animation.dirty
+                && base == animation.base
+                && tint == animation.tint
+                && paint == animation.paint
+            {
+                continue;
+            }
+
+            let tint_changed = tint != animation.tint;
+            let paint_changed = paint != animation.paint;
+            animation.base = base;
+            animation.tint = tint;
+            animation.paint = paint;
+            animation.dirty = false;
+            for node in &mut animation.nodes {
+                let matrix = base * node.local * node.pose;
+                match node.model_id {
+                    Some(model_id) => {
+                        mesh_manager.update_model(node.mesh_id, model_id, matrix);
+                        if tint_changed {
+                            mesh_manager.update_tint(node.mesh_id, model_id, tint);
+                        }
+                        if paint_changed {
+                            mesh_manager.update_paint(node.mesh_id, model_id, paint.color());
+                        }
+                    }
+                    None => {
+                        let model_id = mesh_manager.new_model(node.mesh_id, matrix, tint);
+                        mesh_manager.update_paint(node.mesh_id, model_id, paint.color());
+                        mesh_manager.set_model_entity(node.mesh_id, model_id, entity);
+                        node.model_id = Some(model_id);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Removes the node models of the entities that are removed
+pub(super) fn remove_models(mesh_manager: &mut MeshManager, animation: &mut Animation) {
+    for node in &mut animation.nodes {
+        if let Some(model_id) = node.model_id.take() {
+            mesh_manager.remove_model(node.mesh_id, model_id);
+        }
+    }
+}
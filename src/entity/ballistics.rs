@@ -0,0 +1,37 @@
+use cgmath::{prelude::*, Vector3};
+
+/// Calculates how long it takes a projectile fired from `origin` at a constant `speed`
+/// to hit a target at `target` that is moving with a constant `velocity`.
+/// Returns None if the projectile can never catch the target.
+pub fn intercept_time(
+    origin: Vector3<f32>,
+    target: Vector3<f32>,
+    velocity: Vector3<f32>,
+    speed: f32,
+) -> Option<f32> {
+    // Solve |offset + velocity * t| = speed * t for the smallest positive t
+    let offset = target - origin;
+    let a = velocity.magnitude2() - speed * speed;
+    let b = 2.0 * offset.dot(velocity);
+    let c = offset.magnitude2();
+
+    if a.abs() < f32::EPSILON {
+        return if b < 0.0 { Some(-c / b) } else { None };
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrt = discriminant.sqrt();
+    let t1 = (-b - sqrt) / (2.0 * a);
+    let t2 = (-b + sqrt) / (2.0 * a);
+
+    match (t1 > 0.0, t2 > 0.0) {
+        (true, true) => Some(t1.min(t2)),
+        (true, false) => Some(t1),
+        (false, true) => Some(t2),
+        (false, false) => None,
+    }
+}
@@ -0,0 +1,1255 @@
+use super::{
+    gameplay::GameLog,
+    hologram::BuildHologram,
+    input::{Gamepad, PadButton},
+    paint::Paint,
+    ship::{self, BuildAction, BuildSource, SelectedShip},
+    undo::{UndoCommand, UndoStack},
+    BlockEntity, InputAction, InputManager, Line, LineBatch, Model, Ship, SimpleStorage, Time,
+    WindowSize,
+};
+use crate::block::{BlockId, Blocks};
+use crate::content::{ContentHash, LoadReport};
+use crate::floor::{Floor, Floors};
+use crate::graphics::{Camera, Projection};
+use crate::i18n::Strings;
+use crate::item::{self, GameItem, Inventory};
+use crate::save;
+use crate::tech::TechTree;
+use cgmath::{InnerSpace, Point2, Point3, Vector2, Vector3, Zero};
+use specs::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use winit::event::VirtualKeyCode;
+
+pub const BLUEPRINT_DIR: &str = "saves/blueprints";
+
+/// A copied area of the ship, which can be stamped somewhere else. Positions are relative
+/// to the corner of the area with the lowest x and y, and keep the deck they were copied from.
+#[derive(Clone)]
+pub struct Blueprint {
+    pub size: Point2<i16>,
+    floors: Vec<(Point3<i16>, Floor)>,
+    /// The blocks and gadgets. Gadgets are built after the blocks, since they are built on them.
+    blocks: Vec<(Point3<i16>, BlockId)>,
+    /// The blocks that are painted. Blocks that aren't listed are white.
+    paints: Vec<(Point3<i16>, Paint)>,
+}
+
+impl Blueprint {
+    /// Copies the floors, blocks, gadgets and paint of every deck between the corners
+    /// (inclusive)
+    #[allow(clippy::too_many_arguments)]
+    pub fn capture(
+        ship: &Ship,
+        block_entities: &SimpleStorage<'_, BlockEntity>,
+        paints: &SimpleStorage<'_, Paint>,
+        models: &SimpleStorage<'_, Model>,
+        blocks: &Blocks,
+        corner: Point2<i16>,
+        other_corner: Point2<i16>,
+    ) -> Blueprint {
+        let min = Point2::new(corner.x.min(other_corner.x), corner.y.min(other_corner.y));
+        let max = Point2::new(corner.x.max(other_corner.x), corner.y.max(other_corner.y));
+        let mut blueprint = Blueprint {
+            size: Point2::new(max.x - min.x + 1, max.y - min.y + 1),
+            floors: Vec::new(),
+            blocks: Vec::new(),
+            paints: Vec::new(),
+        };
+
+        let positions = (0..Ship::DECKS).flat_map(|deck| {
+            (min.x..=max.x).flat_map(move |x| (min.y..=max.y).map(move |y| Point3::new(x, y, deck)))
+        });
+        for ship_pos in positions {
+            let tile = match ship.tile(ship_pos) {
+                Some(tile) => tile,
+                None => continue,
+            };
+            let pos = Point3::new(ship_pos.x - min.x, ship_pos.y - min.y, ship_pos.z);
+            let mesh = |entity: Entity| models.get(entity).map(|model| model.mesh_id);
+
+            if let Some(floor) = tile.floor_type() {
+                blueprint.floors.push((pos, floor));
+            }
+            if let Some(block) = tile.block() {
+                if let Some(block_entity) = block_entities.get(block) {
+                    blueprint.blocks.push((pos, block_entity.block_id()));
+                }
+                if let Some(paint) = paints.get(block) {
+                    blueprint.paints.push((pos, *paint));
+                }
+            }
+            // Gadgets don't have a BlockEntity, so they are found by their mesh
+            if let Some(gadget) = tile
+                .gadget()
+                .and_then(mesh)
+                .and_then(|mesh| blocks.find_by_mesh(mesh))
+            {
+                blueprint.blocks.push((pos, gadget));
+            }
+        }
+        // Saved blueprints load with their gadgets last, so the order is the same once loaded
+        blueprint
+            .blocks
+            .sort_by_key(|(_, block_id)| blocks.get_block(*block_id).is_gadget);
+
+        blueprint
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// The block (not the gadget) at the position, relative to the corner of the blueprint
+    pub fn block_at(&self, pos: Point3<i16>, blocks: &Blocks) -> Option<BlockId> {
+        self.blocks
+            .iter()
+            .find(|(other, block_id)| *other == pos && !blocks.get_block(*block_id).is_gadget)
+            .map(|(_, block_id)| *block_id)
+    }
+
+    /// The actions that build the blueprint with its corner at `origin`, without checking
+    /// the tiles (ex. for a new ship). The gadgets are returned separately, like `plan`.
+    pub fn build_actions(
+        &self,
+        origin: Point2<i16>,
+        blocks: &Blocks,
+    ) -> (Vec<BuildAction>, Vec<BuildAction>) {
+        let to_ship = |pos: Point3<i16>| Point3::new(origin.x + pos.x, origin.y + pos.y, pos.z);
+        let mut actions: Vec<BuildAction> = self
+            .floors
+            .iter()
+            .map(|(pos, floor)| BuildAction::BuildFloor(to_ship(*pos), *floor))
+            .collect();
+        let mut gadgets = Vec::new();
+        for (pos, block_id) in &self.blocks {
+            let action = BuildAction::BuildBlock(to_ship(*pos), *block_id);
+            if blocks.get_block(*block_id).is_gadget {
+                gadgets.push(action);
+            } else {
+                actions.push(action);
+            }
+        }
+        actions.sort_by_key(|action| action.pos().z);
+
+        (actions, gadgets)
+    }
+
+    /// The total cost of every block and floor in the blueprint
+    pub fn cost(&self, blocks: &Blocks, floors: &Floors) -> Vec<(GameItem, u32)> {
+        GameItem::iter()
+            .map(|item| {
+                let amount = self
+                    .blocks
+                    .iter()
+                    .flat_map(|(_, block_id)| &blocks.get_block(*block_id).cost)
+                    .chain(
+                        self.floors
+                            .iter()
+                            .flat_map(|(_, floor)| &floors.get(*floor).cost),
+                    )
+                    .filter(|(other, _)| other == item)
+                    .map(|(_, amount)| amount)
+                    .sum();
+                (*item, amount)
+            })
+            .filter(|(_, amount)| *amount > 0)
+            .collect()
+    }
+
+    /// Checks every tile the blueprint covers, and returns the actions that build it. The
+    /// blocks and floors are returned first (from the lowest deck up), and the gadgets second.
+    /// Tiles that already have a floor keep it, but blocks and gadgets can only be built on
+    /// empty tiles.
+    fn plan(
+        &self,
+        ship: &Ship,
+        block_entities: &SimpleStorage<'_, BlockEntity>,
+        blocks: &Blocks,
+        tech_tree: &TechTree,
+        origin: Point2<i16>,
+        strings: &Strings,
+    ) -> Result<(Vec<BuildAction>, Vec<BuildAction>), String> {
+        let to_ship = |pos: Point3<i16>| Point3::new(origin.x + pos.x, origin.y + pos.y, pos.z);
+        let tile = |pos: Point3<i16>| {
+            let pos = to_ship(pos);
+            ship.tile(pos).map(|tile| (pos, tile)).ok_or_else(|| {
+                strings.format("error.outside_ship", &[("x", &pos.x), ("y", &pos.y)])
+            })
+        };
+        // The blueprint's own walls can hold up its upper decks
+        let planned: HashMap<_, _> = self
+            .blocks
+            .iter()
+            .filter(|(_, block_id)| !blocks.get_block(*block_id).is_gadget)
+            .map(|(pos, block_id)| (to_ship(*pos), *block_id))
+            .collect();
+        let block_at = |pos: Point3<i16>| {
+            planned.get(&pos).copied().or_else(|| {
+                let block = ship.tile(pos)?.block()?;
+                block_entities.get(block).map(BlockEntity::block_id)
+            })
+        };
+        let mut actions = Vec::new();
+        let mut gadgets = Vec::new();
+
+        for (pos, floor) in &self.floors {
+            let (pos, tile) = tile(*pos)?;
+            if tile.floor().is_none() {
+                ship.check_placement(pos, None, blocks, block_at, strings)?;
+                actions.push(BuildAction::BuildFloor(pos, *floor));
+            }
+        }
+
+        for (relative_pos, block_id) in &self.blocks {
+            let (pos, tile) = tile(*relative_pos)?;
+            let block = blocks.get_block(*block_id);
+            let name = strings.get(block.name_key);
+            if let Some(node) = tech_tree.required_node(*block_id) {
+                return Err(strings.format(
+                    "error.requires_tech",
+                    &[("block", &name), ("tech", &node.display_name(strings))],
+                ));
+            }
+            ship.check_placement(pos, Some(block), blocks, block_at, strings)?;
+
+            if block.is_gadget {
+                let has_base = matches!(tile.block(), Some(base) if block_entities.contains(base))
+                    || self.blocks.iter().any(|(other_pos, other)| {
+                        other_pos == relative_pos && !blocks.get_block(*other).is_gadget
+                    });
+                if tile.gadget().is_some() || !has_base {
+                    return Err(strings.format(
+                        "error.cant_build_at",
+                        &[("block", &name), ("x", &pos.x), ("y", &pos.y)],
+                    ));
+                }
+                gadgets.push(BuildAction::BuildBlock(pos, *block_id));
+            } else {
+                actions.push(BuildAction::BuildBlock(pos, *block_id));
+            }
+        }
+        // Upper decks are checked against the walls below them when they are built
+        actions.sort_by_key(|action| action.pos().z);
+
+        Ok((actions, gadgets))
+    }
+
+    /// Builds the blueprint with its lowest corner at `origin`. Nothing is built unless every
+    /// tile can be built on and the inventory can pay for the whole blueprint. Returns what
+    /// was built, with the gadgets last.
+    pub fn stamp(
+        &self,
+        world: &mut World,
+        ship: Entity,
+        origin: Point2<i16>,
+    ) -> Result<Vec<BuildAction>, String> {
+        let (actions, gadgets) = {
+            let ships = world.read_component::<Ship>();
+            let blocks = world.fetch::<Blocks>();
+            let strings = world.fetch::<Strings>();
+            let plan = self.plan(
+                ships
+                    .get(ship)
+                    .ok_or_else(|| strings.get("error.no_ship").to_string())?,
+                &world.read_component::<BlockEntity>(),
+                &blocks,
+                &world.fetch::<TechTree>(),
+                origin,
+                &strings,
+            )?;
+
+            let cost = self.cost(&blocks, &world.fetch::<Floors>());
+            let mut inventory = world.write_resource::<Inventory>();
+            if !inventory.has_items(&cost) {
+                return Err(strings.format(
+                    "error.blueprint_cost",
+                    &[("cost", &item::describe_cost(&cost, &strings))],
+                ));
+            }
+            inventory.remove_items(&cost);
+
+            plan
+        };
+
+        ship::build(world, ship, &actions, BuildSource::Prepaid);
+        // build adds the entities lazily, and the gadgets need the BlockEntity of their base
+        world.maintain();
+        ship::build(world, ship, &gadgets, BuildSource::Prepaid);
+        self.paint(world, ship, origin);
+
+        Ok(actions.into_iter().chain(gadgets).collect())
+    }
+
+    /// Paints the blocks that were built from the blueprint
+    pub fn paint(&self, world: &mut World, ship: Entity, origin: Point2<i16>) {
+        let ships = world.read_component::<Ship>();
+        let ship = match ships.get(ship) {
+            Some(ship) => ship,
+            None => return,
+        };
+        let mut paints = world.write_component::<Paint>();
+        for (pos, paint) in &self.paints {
+            let pos = Point3::new(origin.x + pos.x, origin.y + pos.y, pos.z);
+            if let Some(block) = ship.tile(pos).and_then(|tile| tile.block()) {
+                paints.insert(block, *paint).unwrap();
+            }
+        }
+    }
+
+    /// Saves the blueprint in the same format as the profile. The blocks are saved with their
+    /// content hash, so blueprints still load after a block is renamed.
+    pub fn save(&self, path: &str, blocks: &Blocks, floors: &Floors) -> Result<(), String> {
+        let text = self.to_text(blocks, floors);
+        std::path::Path::new(path)
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, text))
+            .map_err(|error| format!("Unable to save blueprint: {}", error))
+    }
+
+    /// The blueprint in its saved format, with the header of the current save format
+    pub fn to_text(&self, blocks: &Blocks, floors: &Floors) -> String {
+        let mut text = save::header();
+        text.push_str(&format!("size {} {}\n", self.size.x, self.size.y));
+        let content: BTreeMap<&str, ContentHash> = self
+            .blocks
+            .iter()
+            .map(|(_, block_id)| {
+                let block = blocks.get_block(*block_id);
+                (block.type_name, block.content_hash)
+            })
+            .collect();
+        for (name, hash) in &content {
+            text.push_str(&format!("content block {} {}\n", name, hash));
+        }
+        for (pos, floor) in &self.floors {
+            text.push_str(&format!(
+                "floor {} {} {} {}\n",
+                pos.x,
+                pos.y,
+                pos.z,
+                floors.name(*floor)
+            ));
+        }
+        for (pos, block_id) in &self.blocks {
+            let type_name = blocks.get_block(*block_id).type_name;
+            text.push_str(&format!(
+                "block {} {} {} {}\n",
+                pos.x, pos.y, pos.z, type_name
+            ));
+        }
+        for (pos, paint) in &self.paints {
+            text.push_str(&format!(
+                "paint {} {} {} {}\n",
+                pos.x,
+                pos.y,
+                pos.z,
+                paint.to_hex()
+            ));
+        }
+        text
+    }
+
+    /// The amount of each block type in the blueprint
+    pub fn block_counts(&self, blocks: &Blocks) -> BTreeMap<&'static str, u32> {
+        let mut counts = BTreeMap::new();
+        for (_, block_id) in &self.blocks {
+            *counts
+                .entry(blocks.get_block(*block_id).type_name)
+                .or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Loads a saved blueprint. A blueprint with blocks that can't be found (ex. from a mod
+    /// that is no longer installed) isn't loaded, and the error lists the missing blocks.
+    pub fn load(
+        path: &str,
+        blocks: &Blocks,
+        floors: &Floors,
+        strings: &Strings,
+    ) -> Result<Blueprint, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("Unable to load blueprint {}: {}", path, error))?;
+        let (blueprint, report) = Blueprint::parse(&text, blocks, floors, strings)?;
+        report.print("Blueprint");
+        if !report.unresolved.is_empty() {
+            return Err(format!(
+                "Unable to load blueprint {}, it uses blocks that are missing: {}",
+                path,
+                report.unresolved_names().join(", ")
+            ));
+        }
+        Ok(blueprint)
+    }
+
+    /// Reads a blueprint from its saved format, after migrating it from the format it was
+    /// saved in (see save::read). The blocks that can't be found are left out, and listed in
+    /// the report. Tiles outside of the blueprint's size are refused.
+    pub fn parse(
+        text: &str,
+        blocks: &Blocks,
+        floors: &Floors,
+        strings: &Strings,
+    ) -> Result<(Blueprint, LoadReport), String> {
+        let save = save::read(text)?;
+        let blocks_index = blocks.content_index();
+        let mut report = LoadReport::default();
+        let mut hashes: BTreeMap<&str, ContentHash> = BTreeMap::new();
+        let mut blueprint = Blueprint {
+            size: Point2::new(0, 0),
+            floors: Vec::new(),
+            blocks: Vec::new(),
+            paints: Vec::new(),
+        };
+
+        let invalid = |line: &str| format!("Invalid line in blueprint: {}", line);
+        let parse_pos = |x: &str, y: &str, line: &str| -> Result<Point2<i16>, String> {
+            match (x.parse(), y.parse()) {
+                (Ok(x), Ok(y)) => Ok(Point2::new(x, y)),
+                _ => Err(invalid(line)),
+            }
+        };
+        let parse_tile = |x: &str, y: &str, deck: &str, line: &str| {
+            let pos = parse_pos(x, y, line)?;
+            match deck.parse() {
+                Ok(deck) if (0..Ship::DECKS).contains(&deck) => Ok(Point3::new(pos.x, pos.y, deck)),
+                _ => Err(invalid(line)),
+            }
+        };
+
+        for record in &save.records {
+            let words: Vec<&str> = record.iter().map(String::as_str).collect();
+            let line = &words.join(" ");
+            match words[..] {
+                ["size", x, y] => blueprint.size = parse_pos(x, y, line)?,
+                ["content", "block", name, hash] => {
+                    hashes.insert(name, ContentHash::parse(hash).ok_or_else(|| invalid(line))?);
+                }
+                ["floor", x, y, deck, name] => blueprint.floors.push((
+                    parse_tile(x, y, deck, line)?,
+                    floors.find(name).ok_or_else(|| invalid(line))?,
+                )),
+                ["block", x, y, deck, name] => {
+                    let hash = *hashes.get(name).ok_or_else(|| invalid(line))?;
+                    if let Some(block_id) = blocks_index.resolve(name, hash, &mut report) {
+                        blueprint
+                            .blocks
+                            .push((parse_tile(x, y, deck, line)?, block_id));
+                    }
+                }
+                ["paint", x, y, deck, hex] => blueprint.paints.push((
+                    parse_tile(x, y, deck, line)?,
+                    Paint::parse(hex).ok_or_else(|| invalid(line))?,
+                )),
+                _ => return Err(invalid(line)),
+            }
+        }
+
+        // Every tile of a block has to fit, and a corner past i16::MAX can't
+        let size = blueprint.size;
+        let inside = |pos: Point3<i16>, extent: Point2<u16>| {
+            let far_x = pos.x.checked_add(extent.x as i16 - 1);
+            let far_y = pos.y.checked_add(extent.y as i16 - 1);
+            pos.x >= 0
+                && pos.y >= 0
+                && matches!((far_x, far_y), (Some(x), Some(y)) if x < size.x && y < size.y)
+        };
+        let one_tile = Point2::new(1, 1);
+        let outside = blueprint
+            .floors
+            .iter()
+            .map(|(pos, _)| (*pos, one_tile))
+            .chain(
+                blueprint
+                    .blocks
+                    .iter()
+                    .map(|(pos, block_id)| (*pos, blocks.get_block(*block_id).size)),
+            )
+            .chain(blueprint.paints.iter().map(|(pos, _)| (*pos, one_tile)))
+            .find(|(pos, extent)| !inside(*pos, *extent));
+        if let Some((pos, _)) = outside {
+            return Err(strings.format("error.outside_ship", &[("x", &pos.x), ("y", &pos.y)]));
+        }
+
+        // Gadgets need their base to be built first
+        blueprint
+            .blocks
+            .sort_by_key(|(_, block_id)| blocks.get_block(*block_id).is_gadget);
+
+        Ok((blueprint, report))
+    }
+}
+
+/// The blueprint tool. An area of the ship is copied by dragging over it with the
+/// CopyBlueprint action, and then stamped by clicking with the StampBlueprint action. Single
+/// blocks are placed the same way with the PlaceBlock action.
+#[derive(Default)]
+pub struct BlueprintTool {
+    pub blueprint: Option<Blueprint>,
+    /// The corners of the area being copied
+    selection: Option<(Point2<i16>, Point2<i16>)>,
+    was_pressed: bool,
+    /// Where the player clicked to stamp the blueprint, which is built at the end of the update
+    stamp_at: Option<Point2<i16>>,
+    /// Where the player clicked to place a block, which is built at the end of the update
+    place_at: Option<(Point3<i16>, BlockId)>,
+    /// The direction the tile cursor is being moved in with the arrow keys, how long it has
+    /// been held, and the time until the cursor moves again
+    nudge: Option<(Vector2<i16>, f32, f32)>,
+    pub picker: RadialPicker,
+}
+
+/// The ring of blocks that is shown while the gamepad's picker button is held. The stick
+/// points at a block, which is picked for placement when the button is let go. The blocks
+/// are in the order of the block toolbar, starting at the top and going clockwise.
+#[derive(Clone, Copy, Default)]
+pub struct RadialPicker {
+    pub open: bool,
+    /// The block the stick last pointed at
+    pub pointed: Option<usize>,
+}
+
+impl RadialPicker {
+    /// How far the stick needs to be pushed to point at a block
+    const DEAD_ZONE: f32 = 0.4;
+
+    /// The slice of the ring that the direction points at, if it is far enough from the
+    /// center
+    pub fn slice(direction: Vector2<f32>, slices: usize) -> Option<usize> {
+        if slices == 0 || direction.magnitude() < RadialPicker::DEAD_ZONE {
+            return None;
+        }
+        let turn = std::f32::consts::PI * 2.0;
+        // Clockwise from the top
+        let angle = direction.x.atan2(direction.y).rem_euclid(turn);
+        Some((angle / turn * slices as f32).round() as usize % slices)
+    }
+
+    /// Opens the picker while the button is held. Returns the block that was pointed at when
+    /// it is let go.
+    fn update(&mut self, pad: &Gamepad, slices: usize) -> Option<usize> {
+        if pad.is_down(PadButton::Picker) {
+            if !self.open {
+                *self = RadialPicker {
+                    open: true,
+                    pointed: None,
+                };
+            }
+            if let Some(slice) = RadialPicker::slice(pad.pointing(), slices) {
+                self.pointed = Some(slice);
+            }
+            None
+        } else {
+            self.open = false;
+            self.pointed.take()
+        }
+    }
+}
+
+impl BlueprintTool {
+    const SELECTION_COLOR: Vector3<f32> = Vector3::new(0.3, 0.8, 1.0);
+    const STAMP_COLOR: Vector3<f32> = Vector3::new(0.3, 1.0, 0.5);
+    const WARNING_COLOR: Vector3<f32> = Vector3::new(1.0, 0.6, 0.0);
+    const CONFIRM_KEY: VirtualKeyCode = VirtualKeyCode::Return;
+    const CANCEL_KEY: VirtualKeyCode = VirtualKeyCode::Escape;
+    /// How long an arrow key is held before the cursor starts repeating
+    const REPEAT_DELAY: f32 = 0.35;
+    /// The seconds between steps when the repeat starts, which shrinks the longer the key
+    /// is held, down to the fastest interval
+    const REPEAT_INTERVAL: f32 = 0.12;
+    const FASTEST_INTERVAL: f32 = 0.03;
+    const REPEAT_ACCELERATION: f32 = 0.05;
+
+    /// Stamps the blueprint with its corner on the tile at the end of the update. Returns
+    /// false if nothing has been copied.
+    pub fn request_stamp(&mut self, origin: Point2<i16>) -> bool {
+        if self.blueprint.is_some() {
+            self.stamp_at = Some(origin);
+        }
+        self.blueprint.is_some()
+    }
+
+    /// Moves the tile cursor with the arrow keys, or the d-pad and stick of a gamepad. The
+    /// arrows follow the screen, since the build view is always turned to a grid axis.
+    /// Holding a key repeats faster over time.
+    fn move_cursor(
+        &mut self,
+        input: &mut InputManager,
+        camera: &Camera,
+        time: &Time,
+        hovered: Option<Point2<i16>>,
+    ) {
+        let key_direction = |key, x, y| {
+            if input.keys.is_key_down(key) {
+                Vector2::new(x, y)
+            } else {
+                Vector2::zero()
+            }
+        };
+        let keys: Vector2<f32> = key_direction(VirtualKeyCode::Up, 0.0, 1.0)
+            + key_direction(VirtualKeyCode::Down, 0.0, -1.0)
+            + key_direction(VirtualKeyCode::Left, -1.0, 0.0)
+            + key_direction(VirtualKeyCode::Right, 1.0, 0.0);
+        // The stick points at a block instead while the radial picker is open
+        let pad = if self.picker.open {
+            Vector2::zero()
+        } else {
+            input.pad.direction()
+        };
+        let screen = Vector2::new(
+            (keys.x + pad.x).clamp(-1.0, 1.0),
+            (keys.y + pad.y).clamp(-1.0, 1.0),
+        );
+        let (yaw_sin, yaw_cos) = camera.yaw.sin_cos();
+        // Up is the way the camera faces, like W when panning
+        let direction = Vector2::new(
+            (screen.y * yaw_cos + screen.x * yaw_sin).round() as i16,
+            (screen.y * yaw_sin - screen.x * yaw_cos).round() as i16,
+        );
+        if direction == Vector2::zero() {
+            self.nudge = None;
+            return;
+        }
+
+        let step = match &mut self.nudge {
+            Some((held, time_held, until_step)) if *held == direction => {
+                *time_held += time.delta;
+                let interval = (BlueprintTool::REPEAT_INTERVAL
+                    - *time_held * BlueprintTool::REPEAT_ACCELERATION)
+                    .max(BlueprintTool::FASTEST_INTERVAL);
+                if time.count_down(until_step) {
+                    *until_step += interval;
+                    true
+                } else {
+                    false
+                }
+            }
+            _ => {
+                self.nudge = Some((direction, 0.0, BlueprintTool::REPEAT_DELAY));
+                true
+            }
+        };
+        if step {
+            // The cursor starts where the mouse points, or at the core
+            let tile = input
+                .tile_cursor
+                .or(hovered)
+                .unwrap_or_else(|| Point2::new(Ship::CORE_POS.x, Ship::CORE_POS.y));
+            input.tile_cursor = Some(tile + direction);
+        }
+    }
+}
+
+/// Finds the ship tile under the cursor, by intersecting the cursor's ray with the floor of
+/// the active deck at the height `floor`. Other decks are ignored, even if they are in front
+/// of it.
+fn hovered_tile(
+    input: &InputManager,
+    camera: &Camera,
+    window_size: &WindowSize,
+    floor: f32,
+) -> Option<Point2<i16>> {
+    let near = camera.unproject(
+        Vector3::new(input.mouse_pos.x, input.mouse_pos.y, 0.0),
+        window_size.as_point(),
+    );
+    let far = camera.unproject(
+        Vector3::new(input.mouse_pos.x, input.mouse_pos.y, 1.0),
+        window_size.as_point(),
+    );
+    let direction = far - near;
+    if direction.z == 0.0 {
+        return None;
+    }
+
+    let t = (floor - near.z) / direction.z;
+    if !(0.0..=1.0).contains(&t) {
+        return None;
+    }
+    let point = near + direction * t;
+
+    Some(Point2::new(point.x.round() as i16, point.y.round() as i16))
+}
+
+/// Whether the block can be built on the tile of the ship, not counting its cost
+pub(super) fn check_block_placement(
+    ship: &Ship,
+    block_entities: &ReadStorage<BlockEntity>,
+    blocks: &Blocks,
+    pos: Point3<i16>,
+    block_id: BlockId,
+    strings: &Strings,
+) -> Result<(), String> {
+    let block_at = |pos| {
+        let block = ship.tile(pos)?.block()?;
+        block_entities.get(block).map(BlockEntity::block_id)
+    };
+    ship.check_placement(
+        pos,
+        Some(blocks.get_block(block_id)),
+        blocks,
+        block_at,
+        strings,
+    )
+}
+
+/// Outlines the tiles between the corners (inclusive) on the deck at the height `floor`
+fn add_tile_outline(
+    line_batch: &mut LineBatch,
+    min: Point2<i16>,
+    max: Point2<i16>,
+    floor: f32,
+    color: Vector3<f32>,
+) {
+    let z = floor + 0.15;
+    let (x1, y1) = (min.x.min(max.x) as f32 - 0.5, min.y.min(max.y) as f32 - 0.5);
+    let (x2, y2) = (min.x.max(max.x) as f32 + 0.5, min.y.max(max.y) as f32 + 0.5);
+    let corners = [
+        Vector3::new(x1, y1, z),
+        Vector3::new(x2, y1, z),
+        Vector3::new(x2, y2, z),
+        Vector3::new(x1, y2, z),
+    ];
+
+    for i in 0..corners.len() {
+        let next = corners[(i + 1) % corners.len()];
+        line_batch.add(
+            Line::new(corners[i], next, color)
+                .with_width(0.05)
+                .as_overlay(),
+        );
+    }
+}
+
+/// Handles dragging out the copied area, and clicking to stamp the blueprint or place a block
+pub struct BlueprintSystem;
+
+impl<'a> System<'a> for BlueprintSystem {
+    type SystemData = (
+        WriteExpect<'a, InputManager>,
+        WriteExpect<'a, BlueprintTool>,
+        WriteExpect<'a, GameLog>,
+        ReadExpect<'a, Camera>,
+        ReadExpect<'a, WindowSize>,
+        ReadExpect<'a, Time>,
+        ReadExpect<'a, Blocks>,
+        ReadExpect<'a, Floors>,
+        ReadExpect<'a, Inventory>,
+        ReadExpect<'a, Strings>,
+        Write<'a, LineBatch>,
+        Read<'a, SelectedShip>,
+        Entities<'a>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Paint>,
+        ReadStorage<'a, Model>,
+        Option<Read<'a, BuildHologram>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut input,
+            mut tool,
+            mut log,
+            camera,
+            window_size,
+            time,
+            blocks,
+            floors,
+            inventory,
+            strings,
+            mut line_batch,
+            selected,
+            entities,
+            ships,
+            block_entities,
+            paints,
+            models,
+            hologram,
+        ) = data;
+        // While a block is placed, the tiles are picked on the hologram above the ship
+        let lift = match (&input.action, &hologram) {
+            (InputAction::PlaceBlock(_), Some(hologram)) => hologram.lift().unwrap_or(0.0),
+            _ => 0.0,
+        };
+        let floor = Ship::deck_z(input.deck) + lift;
+        let mouse_tile = hovered_tile(&input, &camera, &window_size, floor);
+        if let Some(picked) = tool.picker.update(&input.pad, blocks.iter().len()) {
+            if let Some(block) = blocks.iter().nth(picked) {
+                input.action = InputAction::PlaceBlock(block.id);
+            }
+        }
+        if matches!(camera.projection, Projection::Orthographic { .. }) {
+            tool.move_cursor(&mut input, &camera, &time, mouse_tile);
+        } else {
+            tool.nudge = None;
+        }
+        if input.keys.was_pressed(BlueprintTool::CANCEL_KEY) {
+            input.tile_cursor = None;
+        }
+        if input.pad.was_pressed(PadButton::Cancel) {
+            input.tile_cursor = None;
+            input.action = InputAction::None;
+        }
+        let hovered = input.tile_cursor.or(mouse_tile);
+        let pressed = input.left_mb && !tool.was_pressed;
+        tool.was_pressed = input.left_mb;
+        let confirmed = input.tile_cursor.is_some()
+            && (input.keys.was_pressed(BlueprintTool::CONFIRM_KEY)
+                || input.pad.was_pressed(PadButton::Confirm));
+
+        match input.action {
+            InputAction::CopyBlueprint => {
+                if let Some(hovered) = hovered {
+                    tool.selection = match tool.selection {
+                        Some((start, _)) if !pressed => Some((start, hovered)),
+                        _ if input.left_mb => Some((hovered, hovered)),
+                        selection => selection,
+                    };
+                }
+
+                match tool.selection {
+                    Some((start, end)) if input.left_mb => add_tile_outline(
+                        &mut line_batch,
+                        start,
+                        end,
+                        floor,
+                        BlueprintTool::SELECTION_COLOR,
+                    ),
+                    Some((start, end)) => {
+                        tool.selection = None;
+                        let ship = match selected.find(&entities, &ships) {
+                            Some((_, ship)) => ship,
+                            None => return,
+                        };
+                        let blueprint = Blueprint::capture(
+                            ship,
+                            &block_entities,
+                            &paints,
+                            &models,
+                            &blocks,
+                            start,
+                            end,
+                        );
+                        log.add(strings.format(
+                            "log.blueprint_copied",
+                            &[
+                                ("count", &blueprint.block_count()),
+                                (
+                                    "cost",
+                                    &item::describe_cost(
+                                        &blueprint.cost(&blocks, &floors),
+                                        &strings,
+                                    ),
+                                ),
+                            ],
+                        ));
+                        tool.blueprint = Some(blueprint);
+                        input.action = InputAction::StampBlueprint;
+                    }
+                    None => {}
+                }
+            }
+            InputAction::StampBlueprint => {
+                tool.selection = None;
+                if let (Some(blueprint), Some(origin)) = (&tool.blueprint, hovered) {
+                    let end = Point2::new(
+                        origin.x + blueprint.size.x - 1,
+                        origin.y + blueprint.size.y - 1,
+                    );
+                    let color = if inventory.has_items(&blueprint.cost(&blocks, &floors)) {
+                        BlueprintTool::STAMP_COLOR
+                    } else {
+                        BlueprintTool::WARNING_COLOR
+                    };
+                    add_tile_outline(&mut line_batch, origin, end, floor, color);
+
+                    if pressed || confirmed {
+                        tool.request_stamp(origin);
+                    }
+                }
+            }
+            InputAction::PlaceBlock(block_id) => {
+                tool.selection = None;
+                if let Some(tile) = hovered {
+                    let block = blocks.get_block(block_id);
+                    let pos = Point3::new(tile.x, tile.y, input.deck);
+                    let end = Point2::new(
+                        tile.x + block.size.x as i16 - 1,
+                        tile.y + block.size.y as i16 - 1,
+                    );
+                    let placeable = match selected.find(&entities, &ships) {
+                        Some((_, ship)) => check_block_placement(
+                            ship,
+                            &block_entities,
+                            &blocks,
+                            pos,
+                            block_id,
+                            &strings,
+                        )
+                        .is_ok(),
+                        None => false,
+                    };
+                    let cost = inventory.block_cost(block_id, &block.cost);
+                    let color = if placeable && inventory.has_items(&cost) {
+                        BlueprintTool::STAMP_COLOR
+                    } else {
+                        BlueprintTool::WARNING_COLOR
+                    };
+                    add_tile_outline(&mut line_batch, tile, end, floor, color);
+
+                    if pressed || confirmed {
+                        tool.place_at = Some((pos, block_id));
+                    }
+                }
+            }
+            _ => {
+                tool.selection = None;
+                if let Some(cursor) = input.tile_cursor {
+                    add_tile_outline(
+                        &mut line_batch,
+                        cursor,
+                        cursor,
+                        floor,
+                        BlueprintTool::SELECTION_COLOR,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Stamps the blueprint where the player clicked. Building needs the whole world, so this is
+/// called after the systems have run.
+pub fn stamp_requested(world: &mut World) {
+    let (blueprint, origin) = {
+        let mut tool = world.write_resource::<BlueprintTool>();
+        match (tool.stamp_at.take(), &tool.blueprint) {
+            (Some(origin), Some(blueprint)) => (blueprint.clone(), origin),
+            _ => return,
+        }
+    };
+    let ship = match ship::selected_ship(world) {
+        Some(ship) => ship,
+        None => return,
+    };
+
+    let message = match blueprint.stamp(world, ship, origin) {
+        Ok(built) => {
+            let paid = blueprint.cost(&world.fetch::<Blocks>(), &world.fetch::<Floors>());
+            let message = world.fetch::<Strings>().format(
+                "log.blueprint_stamped",
+                &[("count", &blueprint.block_count())],
+            );
+            world.write_resource::<UndoStack>().push(
+                ship,
+                UndoCommand::Stamp {
+                    blueprint,
+                    origin,
+                    built,
+                    paid,
+                },
+            );
+            message
+        }
+        Err(error) => error,
+    };
+    world.write_resource::<GameLog>().add(message);
+}
+
+/// Builds the block where the player clicked, or tells them why it can't be built. Like
+/// stamping, this is called after the systems have run.
+pub fn place_requested(world: &mut World) {
+    let (pos, block_id) = match world.write_resource::<BlueprintTool>().place_at.take() {
+        Some(placement) => placement,
+        None => return,
+    };
+    let ship = match ship::selected_ship(world) {
+        Some(ship) => ship,
+        None => return,
+    };
+
+    match place_block(world, ship, pos, block_id) {
+        Ok((paid, voucher)) => world.write_resource::<UndoStack>().push(
+            ship,
+            UndoCommand::PlaceBlock {
+                pos,
+                block_id,
+                paid,
+                voucher,
+            },
+        ),
+        Err(error) => world.write_resource::<GameLog>().add(error),
+    }
+}
+
+/// Builds a block for the player, once it is checked that they can build it there and pay for
+/// it. Returns what it was paid with, and whether a voucher was used.
+pub fn place_block(
+    world: &mut World,
+    ship: Entity,
+    pos: Point3<i16>,
+    block_id: BlockId,
+) -> Result<(Vec<(GameItem, u32)>, bool), String> {
+    let (paid, voucher) = {
+        let blocks = world.fetch::<Blocks>();
+        let inventory = world.fetch::<Inventory>();
+        let voucher = inventory.vouchers().any(|(other, _)| other == block_id);
+        (
+            inventory.block_cost(block_id, &blocks.get_block(block_id).cost),
+            voucher,
+        )
+    };
+
+    let checked = {
+        let blocks = world.fetch::<Blocks>();
+        let block = blocks.get_block(block_id);
+        let inventory = world.fetch::<Inventory>();
+        let cost = inventory.block_cost(block_id, &block.cost);
+        let ships = world.read_component::<Ship>();
+        let strings = world.fetch::<Strings>();
+        let name = strings.get(block.name_key);
+        if !world.fetch::<TechTree>().is_unlocked(block_id) {
+            Err(strings.format("error.not_unlocked", &[("block", &name)]))
+        } else if !inventory.has_items(&cost) {
+            Err(strings.format(
+                "error.block_cost",
+                &[
+                    ("block", &name),
+                    ("cost", &item::describe_cost(&cost, &strings)),
+                ],
+            ))
+        } else {
+            ships
+                .get(ship)
+                .map_or(Err(strings.get("error.no_ship").to_string()), |ship| {
+                    let block_entities = world.read_component::<BlockEntity>();
+                    check_block_placement(ship, &block_entities, &blocks, pos, block_id, &strings)
+                })
+        }
+    };
+
+    checked?;
+    ship::execute_build_actions(world, ship, &[BuildAction::BuildBlock(pos, block_id)]);
+    Ok((paid, voucher))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::headless::HeadlessEcs;
+    use crate::graphics::CameraShake;
+    use std::f32::consts::FRAC_PI_2;
+    use winit::event::ElementState;
+
+    /// The build view, looking down with the yaw pointing to the top of the screen
+    fn build_camera(yaw: f32) -> Camera {
+        Camera {
+            position: (0.0, 0.0, 30.0).into(),
+            yaw,
+            pitch: 0.0,
+            aspect: 16.0 / 9.0,
+            fov: 45.0,
+            near: 0.1,
+            far: 100.0,
+            projection: Projection::Orthographic { half_height: 10.0 },
+            shake: CameraShake::NONE,
+        }
+    }
+
+    /// Where the cursor is after tapping the key once, starting from the origin
+    fn tap(camera: &Camera, key: VirtualKeyCode) -> Point2<i16> {
+        let mut tool = BlueprintTool::default();
+        let mut input = InputManager::new();
+        let time = Time::new(Time::DEFAULT_RATE);
+        input.tile_cursor = Some(Point2::new(0, 0));
+        input.keys.update(key, ElementState::Pressed);
+        tool.move_cursor(&mut input, camera, &time, None);
+        input.tile_cursor.unwrap()
+    }
+
+    #[test]
+    fn arrows_follow_the_rotated_camera() {
+        for turns in 0..4 {
+            let camera = build_camera(turns as f32 * FRAC_PI_2);
+            // Up is the way the camera faces, and the other arrows turn clockwise from it
+            let (sin, cos) = camera.yaw.sin_cos();
+            let (sin, cos) = (sin.round() as i16, cos.round() as i16);
+            assert_eq!(tap(&camera, VirtualKeyCode::Up), Point2::new(cos, sin));
+            assert_eq!(tap(&camera, VirtualKeyCode::Right), Point2::new(sin, -cos));
+            assert_eq!(tap(&camera, VirtualKeyCode::Down), Point2::new(-cos, -sin));
+            assert_eq!(tap(&camera, VirtualKeyCode::Left), Point2::new(-sin, cos));
+        }
+    }
+
+    #[test]
+    fn the_gamepad_moves_the_cursor_like_the_arrows() {
+        let pad_tap = |camera: &Camera, press: &dyn Fn(&mut Gamepad)| {
+            let mut tool = BlueprintTool::default();
+            let mut input = InputManager::new();
+            let time = Time::new(Time::DEFAULT_RATE);
+            input.tile_cursor = Some(Point2::new(0, 0));
+            press(&mut input.pad);
+            tool.move_cursor(&mut input, camera, &time, None);
+            input.tile_cursor.unwrap()
+        };
+
+        for turns in 0..4 {
+            let camera = build_camera(turns as f32 * FRAC_PI_2);
+            let up = tap(&camera, VirtualKeyCode::Up);
+            let right = tap(&camera, VirtualKeyCode::Right);
+            let d_pad = pad_tap(&camera, &|pad| pad.update(PadButton::Up, true));
+            assert_eq!(d_pad, up);
+            let stick = pad_tap(&camera, &|pad| pad.stick = Vector2::new(0.8, 0.2));
+            assert_eq!(stick, right);
+            // Pushing the stick a little doesn't move the cursor
+            let nudged = pad_tap(&camera, &|pad| pad.stick = Vector2::new(0.3, -0.2));
+            assert_eq!(nudged, Point2::new(0, 0));
+            // Neither does the stick when it is pushed the same way as the d-pad
+            let both = pad_tap(&camera, &|pad| {
+                pad.update(PadButton::Up, true);
+                pad.stick = Vector2::new(0.0, 1.0);
+            });
+            assert_eq!(both, up);
+        }
+    }
+
+    #[test]
+    fn the_radial_picker_goes_clockwise_from_the_top() {
+        let slice = |x, y| RadialPicker::slice(Vector2::new(x, y), 8);
+        assert_eq!(slice(0.0, 1.0), Some(0));
+        assert_eq!(slice(0.7, 0.7), Some(1));
+        assert_eq!(slice(1.0, 0.0), Some(2));
+        assert_eq!(slice(0.0, -1.0), Some(4));
+        assert_eq!(slice(-1.0, 0.0), Some(6));
+        // Just left of the top is closer to the first slice than the last
+        assert_eq!(slice(-0.1, 1.0), Some(0));
+        assert_eq!(slice(0.1, 0.2), None);
+        assert_eq!(RadialPicker::slice(Vector2::new(0.0, 1.0), 0), None);
+    }
+
+    #[test]
+    fn letting_go_of_the_picker_picks_the_block() {
+        let headless = HeadlessEcs::with_seed(1);
+        *headless.ecs.get_resource_mut::<Camera>() = build_camera(0.0);
+        let block_ids: Vec<BlockId> = headless
+            .ecs
+            .get_resource::<Blocks>()
+            .iter()
+            .map(|block| block.id)
+            .collect();
+        let update = |press: &dyn Fn(&mut InputManager)| {
+            press(&mut headless.ecs.get_resource_mut::<InputManager>());
+            BlueprintSystem.run_now(&headless.ecs.world);
+            headless.ecs.get_resource_mut::<InputManager>().end_update();
+            headless.ecs.get_resource::<BlueprintTool>().picker
+        };
+
+        let picker = update(&|input| {
+            input.tile_cursor = Some(Point2::new(2, 3));
+            input.pad.update(PadButton::Picker, true);
+            input.pad.update(PadButton::Right, true);
+        });
+        assert!(picker.open);
+        let right = RadialPicker::slice(Vector2::new(1.0, 0.0), block_ids.len()).unwrap();
+        assert_eq!(picker.pointed, Some(right));
+        // The d-pad points at a block instead of moving the cursor
+        let input = headless.ecs.get_resource::<InputManager>();
+        assert_eq!(input.tile_cursor, Some(Point2::new(2, 3)));
+        assert_eq!(input.action, InputAction::None);
+        std::mem::drop(input);
+
+        // Letting go of the stick keeps the block it pointed at
+        let picker = update(&|input| input.pad.update(PadButton::Right, false));
+        assert_eq!(picker.pointed, Some(right));
+
+        let picker = update(&|input| input.pad.update(PadButton::Picker, false));
+        assert!(!picker.open);
+        assert_eq!(
+            headless.ecs.get_resource::<InputManager>().action,
+            InputAction::PlaceBlock(block_ids[right])
+        );
+
+        // Cancelling drops the block and the cursor
+        update(&|input| input.pad.update(PadButton::Cancel, true));
+        let input = headless.ecs.get_resource::<InputManager>();
+        assert_eq!(input.action, InputAction::None);
+        assert_eq!(input.tile_cursor, None);
+    }
+
+    #[test]
+    fn holding_an_arrow_repeats_faster() {
+        let camera = build_camera(0.0);
+        let time = Time::new(Time::DEFAULT_RATE);
+        let mut tool = BlueprintTool::default();
+        let mut input = InputManager::new();
+        input.tile_cursor = Some(Point2::new(0, 0));
+
+        // Each tap is one step
+        for _ in 0..3 {
+            input.keys.update(VirtualKeyCode::Up, ElementState::Pressed);
+            tool.move_cursor(&mut input, &camera, &time, None);
+            input
+                .keys
+                .update(VirtualKeyCode::Up, ElementState::Released);
+            tool.move_cursor(&mut input, &camera, &time, None);
+        }
+        assert_eq!(input.tile_cursor, Some(Point2::new(3, 0)));
+
+        // The ticks that the cursor stepped on while the key was held
+        input.keys.update(VirtualKeyCode::Up, ElementState::Pressed);
+        let mut steps = Vec::new();
+        for tick in 0..3 * Time::DEFAULT_RATE {
+            let before = input.tile_cursor;
+            tool.move_cursor(&mut input, &camera, &time, None);
+            if input.tile_cursor != before {
+                steps.push(tick as f32 * time.delta);
+            }
+        }
+        assert_eq!(
+            input.tile_cursor,
+            Some(Point2::new(3 + steps.len() as i16, 0))
+        );
+        assert_eq!(steps[0], 0.0);
+        assert!((steps[1] - BlueprintTool::REPEAT_DELAY).abs() <= time.delta);
+        let intervals: Vec<f32> = steps[1..].windows(2).map(|w| w[1] - w[0]).collect();
+        assert!(intervals.windows(2).all(|w| w[1] <= w[0] + time.delta));
+        assert!(intervals[intervals.len() - 1] < BlueprintTool::REPEAT_INTERVAL);
+        assert!(intervals
+            .iter()
+            .all(|interval| *interval >= BlueprintTool::FASTEST_INTERVAL - time.delta));
+    }
+
+    #[test]
+    fn the_cursor_places_on_the_active_deck() {
+        let headless = HeadlessEcs::with_seed(1);
+        let laser = headless.ecs.get_resource::<Blocks>().laser;
+        *headless.ecs.get_resource_mut::<Camera>() = build_camera(FRAC_PI_2);
+        let place_with_cursor = |deck: i16| {
+            {
+                let mut input = headless.ecs.get_resource_mut::<InputManager>();
+                input.deck = deck;
+                input.action = InputAction::PlaceBlock(laser);
+                input.tile_cursor = Some(Point2::new(2, 3));
+                input.keys.clear();
+                input
+                    .keys
+                    .update(VirtualKeyCode::Return, ElementState::Pressed);
+            }
+            BlueprintSystem.run_now(&headless.ecs.world);
+            headless
+                .ecs
+                .get_resource_mut::<BlueprintTool>()
+                .place_at
+                .take()
+        };
+
+        // The cursor keeps its tile when the deck changes
+        assert_eq!(place_with_cursor(0), Some((Point3::new(2, 3, 0), laser)));
+        assert_eq!(place_with_cursor(1), Some((Point3::new(2, 3, 1), laser)));
+        assert_eq!(place_with_cursor(0), Some((Point3::new(2, 3, 0), laser)));
+    }
+}
@@ -0,0 +1,430 @@
+use super::{
+    faction::Faction,
+    gameplay::GameLog,
+    objects::{Health, ObjectMeshes},
+    ship::{self, BuildAction, BuildSource},
+    status::{self, StatusEffects},
+    BlockEntity, Collider, GameModule, Model, Ship, SimpleStorage, Time, ToBeRemoved, Transform,
+};
+use crate::block::{BlockId, Blocks};
+use crate::graphics::ModelTint;
+use crate::i18n::Strings;
+use crate::item::{GameItem, Inventory};
+use crate::profiler::AddProfiled;
+use cgmath::{prelude::*, Point3, Vector3};
+use specs::{prelude::*, world::EntitiesRes, Component};
+
+/// Blocks that have been paid for, but still need to be built by a construction drone
+pub struct ConstructionQueue {
+    /// Player builds are only queued when this is set. Otherwise they are built instantly.
+    pub enabled: bool,
+    pending: Vec<PendingBuild>,
+    /// The builds that were finished this update. They are built by `complete_builds`.
+    finished: Vec<PendingBuild>,
+    /// Set while there are pending builds, but no fabricator to build them
+    paused: bool,
+}
+
+pub struct PendingBuild {
+    ship: Entity,
+    pos: Point3<i16>,
+    block_id: BlockId,
+    /// Shows where the block will be built. Destroying it cancels the build.
+    ghost: Entity,
+    /// The fabricator working on the build, and the drone it sent
+    fabricator: Option<(Entity, Entity)>,
+    /// The seconds the fabricator has worked on the build
+    progress: f32,
+    /// What the build was paid with (ex. less than the block's cost with a voucher)
+    paid: Vec<(GameItem, u32)>,
+}
+
+impl PendingBuild {
+    pub fn block_id(&self) -> BlockId {
+        self.block_id
+    }
+
+    pub fn pos(&self) -> Point3<i16> {
+        self.pos
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.fabricator.is_some()
+    }
+}
+
+impl ConstructionQueue {
+    /// The ghosts are see-through, so they don't look like built blocks
+    const GHOST_TINT: ModelTint = ModelTint {
+        primary: cgmath::Vector4::new(0.4, 0.7, 1.0, 0.6),
+        secondary: cgmath::Vector4::new(0.4, 0.7, 1.0, 0.6),
+    };
+
+    pub fn new() -> Self {
+        Self {
+            enabled: crate::CONSTRUCTION_TIME,
+            pending: Vec::new(),
+            finished: Vec::new(),
+            paused: false,
+        }
+    }
+
+    pub fn pending(&self) -> &[PendingBuild] {
+        &self.pending
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Queues the block (which has already been paid for with `paid`), and creates its ghost.
+    /// Returns false if the tile already has a pending build.
+    #[allow(clippy::too_many_arguments)]
+    pub fn push(
+        &mut self,
+        lazy_update: &LazyUpdate,
+        entities: &EntitiesRes,
+        blocks: &Blocks,
+        block_entities: &SimpleStorage<BlockEntity>,
+        ship: (Entity, &Ship),
+        pos: Point3<i16>,
+        block_id: BlockId,
+        paid: &[(GameItem, u32)],
+    ) -> bool {
+        let block = blocks.get_block(block_id);
+        let is_gadget = |other: &PendingBuild| blocks.get_block(other.block_id).is_gadget;
+        if self
+            .pending
+            .iter()
+            .any(|other| other.pos == pos && is_gadget(other) == block.is_gadget)
+        {
+            return false;
+        }
+
+        // Gadgets are placed on their base, which may still be pending
+        let height = if block.is_gadget {
+            let built = ship
+                .1
+                .tile(pos)
+                .and_then(|tile| tile.block())
+                .and_then(|base| block_entities.get(base))
+                .map(BlockEntity::block_id);
+            let pending = self
+                .pending
+                .iter()
+                .find(|other| other.pos == pos && !is_gadget(other))
+                .map(|other| other.block_id);
+            built
+                .or(pending)
+                .map_or(0.0, |base| blocks.get_block(base).height)
+        } else {
+            0.0
+        };
+
+        let ghost = lazy_update
+            .create_entity(entities)
+            .with(Model::with_tint(
+                block.mesh_id,
+                ConstructionQueue::GHOST_TINT,
+            ))
+            .with(Transform::from_position(
+                pos.x as f32,
+                pos.y as f32,
+                Ship::deck_z(pos.z) + height,
+            ))
+            .with(Health::new(Ship::BLOCK_HEALTH))
+            .with(Faction::Player)
+            .with(Collider::new(
+                block.hitbox.clone(),
+                Collider::SHIP,
+                &[Collider::ASTEROID],
+            ))
+            .build();
+        self.pending.push(PendingBuild {
+            ship: ship.0,
+            pos,
+            block_id,
+            ghost,
+            fabricator: None,
+            progress: 0.0,
+            paid: paid.to_vec(),
+        });
+
+        true
+    }
+
+    /// The index of the block's pending build on the tile of the ship
+    pub fn find(&self, ship: Entity, pos: Point3<i16>, block_id: BlockId) -> Option<usize> {
+        self.pending
+            .iter()
+            .position(|build| build.ship == ship && build.pos == pos && build.block_id == block_id)
+    }
+
+    /// Moves the build one place closer to the front of the queue
+    pub fn move_up(&mut self, index: usize) {
+        if index > 0 && index < self.pending.len() {
+            self.pending.swap(index - 1, index);
+        }
+    }
+}
+
+/// Removes the ghost and the drone of a build that is no longer pending
+fn remove_build(build: &PendingBuild, to_be_removed: &mut ToBeRemoved) {
+    to_be_removed.add(build.ghost);
+    if let Some((_, drone)) = build.fabricator {
+        to_be_removed.add(drone);
+    }
+}
+
+/// Cancels the build, and refunds what was paid for it
+pub fn cancel(world: &mut World, index: usize) {
+    let mut queue = world.write_resource::<ConstructionQueue>();
+    if index >= queue.pending.len() {
+        return;
+    }
+
+    let build = queue.pending.remove(index);
+    let blocks = world.fetch::<Blocks>();
+    let block = blocks.get_block(build.block_id);
+    world.write_resource::<Inventory>().add_items(&build.paid);
+    remove_build(&build, &mut world.write_resource::<ToBeRemoved>());
+    let strings = world.fetch::<Strings>();
+    world.write_resource::<GameLog>().add(strings.format(
+        "log.build_cancelled",
+        &[("block", &strings.get(block.name_key))],
+    ));
+}
+
+/// Drops the build without refunding it, for when what was paid is refunded some other way
+/// (ex. an undone build)
+pub fn drop_pending(world: &mut World, index: usize) {
+    let mut queue = world.write_resource::<ConstructionQueue>();
+    if index < queue.pending.len() {
+        let build = queue.pending.remove(index);
+        remove_build(&build, &mut world.write_resource::<ToBeRemoved>());
+    }
+}
+
+/// Builds the blocks that the drones finished. Needs to be called after the dispatcher runs.
+pub fn complete_builds(world: &mut World) {
+    let finished = std::mem::take(&mut world.write_resource::<ConstructionQueue>().finished);
+
+    for build in finished {
+        remove_build(&build, &mut world.write_resource::<ToBeRemoved>());
+        ship::build(
+            world,
+            build.ship,
+            &[BuildAction::BuildBlock(build.pos, build.block_id)],
+            BuildSource::Construction,
+        );
+    }
+}
+
+/// Construction time: the queue of pending builds, and the fabricators that build them
+pub struct ConstructionModule;
+
+impl GameModule for ConstructionModule {
+    fn register(&self, world: &mut World) {
+        world.register::<Fabricator>();
+        world.register::<Health>();
+        world.insert(ConstructionQueue::new());
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(ConstructionSystem, "construction_system", &[]);
+    }
+
+    /// Removes the drones of destroyed ghosts, so it needs to run before their cleanup
+    fn death_systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(GhostDestroyedSystem, "ghost_destroyed_system", &[]);
+    }
+}
+
+/// Sends construction drones to the pending builds on its ship. Every fabricator works on a
+/// different build.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct Fabricator {
+    /// The seconds it takes to build a block
+    build_time: f32,
+}
+
+impl Fabricator {
+    pub fn new(build_time: f32) -> Self {
+        Self { build_time }
+    }
+}
+
+pub struct ConstructionSystem;
+
+impl ConstructionSystem {
+    /// How high the drones fly above the fabricator and the build
+    const DRONE_HEIGHT: f32 = 1.5;
+}
+
+impl<'a> System<'a> for ConstructionSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, LazyUpdate>,
+        ReadExpect<'a, Time>,
+        Write<'a, ToBeRemoved>,
+        WriteExpect<'a, ConstructionQueue>,
+        ReadExpect<'a, Strings>,
+        WriteExpect<'a, GameLog>,
+        ReadExpect<'a, ObjectMeshes>,
+        ReadExpect<'a, Blocks>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Fabricator>,
+        WriteStorage<'a, Transform>,
+        ReadStorage<'a, StatusEffects>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            lazy_update,
+            time,
+            mut to_be_removed,
+            mut queue,
+            strings,
+            mut log,
+            meshes,
+            blocks,
+            ships,
+            block_entities,
+            fabricators,
+            mut transforms,
+            statuses,
+        ) = data;
+        let queue = &mut *queue;
+        // Builds restart once their fabricator is destroyed
+        for build in &mut queue.pending {
+            if let Some((fabricator, drone)) = build.fabricator {
+                if !entities.is_alive(fabricator) {
+                    to_be_removed.add(drone);
+                    build.fabricator = None;
+                    build.progress = 0.0;
+                }
+            }
+        }
+
+        let mut has_fabricator = false;
+        for (ship_entity, ship) in (&entities, &ships).join() {
+            for fabricator in ship.blocks().filter(|block| fabricators.contains(*block)) {
+                if !entities.is_alive(fabricator) {
+                    continue;
+                }
+                has_fabricator = true;
+                let busy = queue.pending.iter().any(
+                    |build| matches!(build.fabricator, Some((other, _)) if other == fabricator),
+                );
+                if busy {
+                    continue;
+                }
+
+                // Gadgets wait until the block they are placed on is built
+                let next = queue.pending.iter_mut().find(|build| {
+                    let ready = !blocks.get_block(build.block_id).is_gadget
+                        || matches!(ship.tile(build.pos).and_then(|tile| tile.block()),
+                            Some(base) if block_entities.contains(base));
+                    build.ship == ship_entity && build.fabricator.is_none() && ready
+                });
+                let (build, start) = match (next, transforms.get(fabricator)) {
+                    (Some(build), Some(transform)) => (build, transform.position),
+                    _ => continue,
+                };
+                let drone = lazy_update
+                    .create_entity(&entities)
+                    .with(Model::new(meshes.drone))
+                    .with(Transform::from_position(start.x, start.y, start.z))
+                    .build();
+                build.fabricator = Some((fabricator, drone));
+            }
+        }
+
+        let was_paused = queue.paused;
+        queue.paused = !queue.pending.is_empty() && !has_fabricator;
+        if queue.paused && !was_paused {
+            log.add(strings.get("log.construction_paused").to_string());
+        }
+
+        let mut index = 0;
+        while index < queue.pending.len() {
+            let build = &mut queue.pending[index];
+            let (fabricator, drone) = match build.fabricator {
+                Some(fabricator) => fabricator,
+                None => {
+                    index += 1;
+                    continue;
+                }
+            };
+
+            // Builds wait while their fabricator is disabled by an EMP
+            if status::is_emped(&statuses, fabricator) {
+                index += 1;
+                continue;
+            }
+            let build_time = fabricators.get(fabricator).unwrap().build_time;
+            let built = time.count_up(&mut build.progress, build_time);
+            let start = transforms
+                .get(fabricator)
+                .map(|transform| transform.position);
+            let end = Vector3::new(build.pos.x as f32, build.pos.y as f32, 0.0);
+            if let (Some(start), Some(transform)) = (start, transforms.get_mut(drone)) {
+                // The drone flies in an arc, and waits above the build at the end
+                let t = (build.progress * 2.0 / build_time).min(1.0);
+                let arc = (t * crate::PI).sin() + 1.0;
+                transform.position = start.lerp(end, t)
+                    + Vector3::new(0.0, 0.0, arc * ConstructionSystem::DRONE_HEIGHT);
+            }
+
+            if built {
+                queue.finished.push(queue.pending.remove(index));
+            } else {
+                index += 1;
+            }
+        }
+    }
+}
+
+/// Cancels the pending builds whose ghost was destroyed, and refunds half of what was paid
+pub struct GhostDestroyedSystem;
+
+impl<'a> System<'a> for GhostDestroyedSystem {
+    type SystemData = (
+        Write<'a, ToBeRemoved>,
+        WriteExpect<'a, ConstructionQueue>,
+        WriteExpect<'a, Inventory>,
+        WriteExpect<'a, GameLog>,
+        ReadExpect<'a, Blocks>,
+        ReadExpect<'a, Strings>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut to_be_removed, mut queue, mut inventory, mut log, blocks, strings) = data;
+
+        let mut index = 0;
+        while index < queue.pending.len() {
+            if !to_be_removed
+                .bitset()
+                .contains(queue.pending[index].ghost.id())
+            {
+                index += 1;
+                continue;
+            }
+
+            let build = queue.pending.remove(index);
+            let block = blocks.get_block(build.block_id);
+            // Half of the cost is refunded (rounded down)
+            for (item, amount) in &build.paid {
+                inventory.change_amount(*item, amount / 2);
+            }
+            remove_build(&build, &mut to_be_removed);
+            log.add(strings.format(
+                "log.pending_destroyed",
+                &[("block", &strings.get(block.name_key))],
+            ));
+        }
+    }
+}
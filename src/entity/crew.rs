@@ -0,0 +1,478 @@
+use super::{
+    faction::Faction,
+    gameplay::GameLog,
+    objects::{Health, ObjectMeshes},
+    physics::{Collider, ColliderShape, Hitbox},
+    ship, BlockEntity, GameModule, Model, Ship, Time, ToBeRemoved, Tooltip, Transform,
+};
+use crate::block::{Blocks, Door, Miner};
+use crate::i18n::Strings;
+use crate::profiler::AddProfiled;
+use cgmath::{prelude::*, Point3, Vector3};
+use rand::{seq::SliceRandom, Rng};
+use specs::{prelude::*, Component};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+/// Crew members walk around the ship's floors, and operate the machines (miners and
+/// coolers) that nobody is looking after
+pub struct CrewModule;
+
+impl GameModule for CrewModule {
+    fn register(&self, world: &mut World) {
+        world.register::<CrewMember>();
+        world.register::<CrewBonus>();
+        world.register::<Health>();
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(CrewSystem::default(), "crew_system", &[]);
+    }
+
+    fn death_systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(CrewDeathSystem, "crew_death_system", &[]);
+    }
+
+    fn init(&self, world: &mut World) {
+        for _ in 0..CrewMember::STARTING_CREW {
+            spawn_crew_member(world, Ship::CORE_POS);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum CrewTask {
+    /// Waits for the seconds before looking for something to do
+    Idle { wait: f32 },
+    /// Walks to the tile to stretch their legs
+    Wander(Point3<i16>),
+    /// Walks to the machine and operates it once they are in range
+    Operate(Entity),
+}
+
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct CrewMember {
+    /// The tile the crew member is standing on, or walking away from
+    tile: Point3<i16>,
+    /// The tiles left to walk to, not including `tile`
+    path: VecDeque<Point3<i16>>,
+    /// Where the current step started. This is the center of `tile`, unless the path was
+    /// changed in the middle of a step.
+    from: Vector3<f32>,
+    /// How far (0..1) along the current step the crew member is
+    progress: f32,
+    task: CrewTask,
+    /// The layout revision of the ship that the path was found for
+    revision: u32,
+}
+
+impl CrewMember {
+    pub const RADIUS: f32 = 0.15;
+    pub const HEIGHT: f32 = 0.6;
+    const STARTING_CREW: usize = 2;
+    /// Tiles per second
+    const SPEED: f32 = 2.0;
+    /// Crew operate machines from a console up to this many tiles away (including diagonals),
+    /// so machines outside the walls can be operated from inside
+    const OPERATE_RANGE: i16 = 2;
+    /// How far away (in tiles) crew wander to when there is nothing to do
+    const WANDER_RANGE: i16 = 4;
+    /// The seconds crew stand around between wanders
+    const MIN_WAIT: f32 = 1.0;
+    const MAX_WAIT: f32 = 4.0;
+
+    fn new(tile: Point3<i16>) -> Self {
+        Self {
+            tile,
+            path: VecDeque::new(),
+            from: CrewMember::tile_position(tile),
+            progress: 0.0,
+            task: CrewTask::Idle { wait: 0.0 },
+            revision: 0,
+        }
+    }
+
+    /// Where the crew member stands on the tile
+    fn tile_position(tile: Point3<i16>) -> Vector3<f32> {
+        Vector3::new(
+            tile.x as f32,
+            tile.y as f32,
+            Ship::deck_z(tile.z) + CrewMember::HEIGHT / 2.0,
+        )
+    }
+
+    /// Whether the crew member stands at the end of their path
+    fn has_arrived(&self) -> bool {
+        self.path.is_empty() && self.from == CrewMember::tile_position(self.tile)
+    }
+
+    fn idle(&mut self, rng: &mut impl Rng) {
+        self.task = CrewTask::Idle {
+            wait: rng.gen_range(CrewMember::MIN_WAIT..CrewMember::MAX_WAIT),
+        };
+        self.path.clear();
+    }
+
+    pub fn tooltip(entity: Entity, world: &World) -> String {
+        let crew = world.read_component::<CrewMember>();
+        let key = match crew.get(entity) {
+            Some(member) => match member.task {
+                CrewTask::Operate(_) if member.has_arrived() => "crew.operating",
+                CrewTask::Operate(_) => "crew.walking",
+                CrewTask::Wander(_) | CrewTask::Idle { .. } => "crew.idle",
+            },
+            None => return String::new(),
+        };
+        world.fetch::<Strings>().get(key).to_string()
+    }
+}
+
+/// Added to the machines that a crew member is operating, which makes them work faster
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct CrewBonus {
+    crew: Entity,
+}
+
+impl CrewBonus {
+    /// How much faster an operated machine works (ex. miners reload and coolers dissipate)
+    pub const SPEEDUP: f32 = 1.5;
+}
+
+/// How much faster the machine works. Machines without crew work at their normal speed.
+pub fn speedup(bonuses: &ReadStorage<CrewBonus>, entity: Entity) -> f32 {
+    if bonuses.contains(entity) {
+        CrewBonus::SPEEDUP
+    } else {
+        1.0
+    }
+}
+
+fn spawn_crew_member(world: &mut World, tile: Point3<i16>) {
+    let mesh = world.read_resource::<ObjectMeshes>().crew;
+    let position = CrewMember::tile_position(tile);
+    world
+        .create_entity()
+        .with(Model::new(mesh))
+        .with(Transform::from_position(position.x, position.y, position.z))
+        .with(CrewMember::new(tile))
+        .with(Health::new(1))
+        .with(Faction::Player)
+        .with(Collider::new(
+            Hitbox::with_shape(ColliderShape::Sphere(CrewMember::RADIUS)),
+            Collider::SHIP,
+            &[Collider::ASTEROID],
+        ))
+        .with(Tooltip(CrewMember::tooltip))
+        .build();
+}
+
+/// Whether crew can walk over the tile. Tiles need a floor and no block, except for doors
+/// that are open.
+fn is_walkable(
+    ship: &Ship,
+    pos: Point3<i16>,
+    block_entities: &ReadStorage<BlockEntity>,
+    doors: &ReadStorage<Door>,
+) -> bool {
+    let tile = match ship.tile(pos) {
+        Some(tile) => tile,
+        None => return false,
+    };
+    // Destroyed blocks are left on their tiles, but lose their components
+    match tile.block().filter(|block| block_entities.contains(*block)) {
+        Some(block) => matches!(doors.get(block), Some(door) if door.is_passable()),
+        None => tile.floor().is_some(),
+    }
+}
+
+/// Finds the shortest path over the walkable tiles of the start's deck to the nearest goal,
+/// with A*. The path does not include the start, which may be blocked (ex. crew spawn on the
+/// core).
+pub fn find_path(
+    start: Point3<i16>,
+    goals: &[Point3<i16>],
+    walkable: impl Fn(Point3<i16>) -> bool,
+) -> Option<VecDeque<Point3<i16>>> {
+    let heuristic = |pos: Point3<i16>| {
+        goals
+            .iter()
+            .map(|goal| {
+                u32::from((goal.x - pos.x).unsigned_abs() + (goal.y - pos.y).unsigned_abs())
+            })
+            .min()
+    };
+
+    let mut open = BinaryHeap::new();
+    let mut costs = HashMap::new();
+    let mut came_from = HashMap::new();
+    open.push(Reverse((heuristic(start)?, 0, (start.x, start.y))));
+    costs.insert(start, 0);
+
+    while let Some(Reverse((_, cost, (x, y)))) = open.pop() {
+        let current = Point3::new(x, y, start.z);
+        if goals.contains(&current) {
+            let mut path = VecDeque::new();
+            let mut pos = current;
+            while pos != start {
+                path.push_front(pos);
+                pos = came_from[&pos];
+            }
+            return Some(path);
+        }
+        // The tile was already reached in fewer steps
+        if cost > costs[&current] {
+            continue;
+        }
+
+        for (dx, dy) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let next = Point3::new(x + dx, y + dy, start.z);
+            let next_cost = cost + 1;
+            if !walkable(next) || matches!(costs.get(&next), Some(cost) if *cost <= next_cost) {
+                continue;
+            }
+            costs.insert(next, next_cost);
+            came_from.insert(next, current);
+            open.push(Reverse((
+                next_cost + heuristic(next)?,
+                next_cost,
+                (next.x, next.y),
+            )));
+        }
+    }
+
+    None
+}
+
+/// The tiles that crew can operate the machine on the tile from
+fn operate_spots(station: Point3<i16>, walkable: impl Fn(Point3<i16>) -> bool) -> Vec<Point3<i16>> {
+    let range = -CrewMember::OPERATE_RANGE..=CrewMember::OPERATE_RANGE;
+    range
+        .clone()
+        .flat_map(|dx| range.clone().map(move |dy| (dx, dy)))
+        .map(|(dx, dy)| Point3::new(station.x + dx, station.y + dy, station.z))
+        .filter(|pos| walkable(*pos))
+        .collect()
+}
+
+/// Picks the crew's tasks, walks them along their paths, and gives the bonus to the machines
+/// they operate
+#[derive(Default)]
+struct CrewSystem {
+    // These are reused every update, so they are not allocated every update
+    stations: HashMap<Entity, Point3<i16>>,
+    manned: HashSet<Entity>,
+    operated: HashMap<Entity, Entity>,
+    stale: Vec<Entity>,
+}
+
+impl<'a> System<'a> for CrewSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Time>,
+        ReadExpect<'a, Blocks>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Door>,
+        ReadStorage<'a, Miner>,
+        WriteStorage<'a, CrewMember>,
+        WriteStorage<'a, Transform>,
+        WriteStorage<'a, CrewBonus>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            time,
+            blocks,
+            ships,
+            block_entities,
+            doors,
+            miners,
+            mut crew,
+            mut transforms,
+            mut bonuses,
+        ) = data;
+        // The crew live on the mothership
+        let ship = match ship::mothership(&ships) {
+            Some(ship) => ship,
+            None => return,
+        };
+        let walkable = |pos| is_walkable(ship, pos, &block_entities, &doors);
+        let mut rng = rand::thread_rng();
+
+        // The miners, and the blocks that cool the ship
+        let stations = &mut self.stations;
+        stations.clear();
+        stations.extend(ship.tiles().flat_map(|(pos, tile)| {
+            let cooler = tile.block().filter(|block| {
+                matches!(block_entities.get(*block), Some(block_entity)
+                        if blocks.get_block(block_entity.block_id()).heat.dissipation > 0.0)
+            });
+            let miner = tile
+                .block()
+                .into_iter()
+                .chain(tile.gadget())
+                .filter(|entity| miners.contains(*entity));
+            cooler
+                .into_iter()
+                .chain(miner)
+                .map(move |entity| (entity, pos))
+        }));
+        // The stations that a crew member is walking to or operating
+        let manned = &mut self.manned;
+        manned.clear();
+        manned.extend((&crew).join().filter_map(|member| match member.task {
+            CrewTask::Operate(station) => Some(station),
+            _ => None,
+        }));
+        let operated = &mut self.operated;
+        operated.clear();
+
+        for (entity, member, transform) in (&entities, &mut crew, &mut transforms).join() {
+            if let CrewTask::Operate(station) = member.task {
+                if !stations.contains_key(&station) {
+                    manned.remove(&station);
+                    member.idle(&mut rng);
+                }
+            }
+
+            // The path is found again when the ship is built on, or a tile on it was blocked
+            // (ex. a door was closed)
+            if member.revision != ship.layout_revision()
+                || member.path.iter().any(|pos| !walkable(*pos))
+            {
+                member.revision = ship.layout_revision();
+                member.from = transform.position;
+                member.progress = 0.0;
+                let path = match member.task {
+                    CrewTask::Operate(station) => find_path(
+                        member.tile,
+                        &operate_spots(stations[&station], walkable),
+                        walkable,
+                    ),
+                    CrewTask::Wander(destination) => {
+                        find_path(member.tile, &[destination], walkable)
+                    }
+                    CrewTask::Idle { .. } => Some(VecDeque::new()),
+                };
+                match path {
+                    Some(path) => member.path = path,
+                    None => {
+                        if let CrewTask::Operate(station) = member.task {
+                            manned.remove(&station);
+                        }
+                        member.idle(&mut rng);
+                    }
+                }
+            }
+
+            if let CrewTask::Idle { wait } = &mut member.task {
+                if time.count_down(wait) {
+                    // The nearest station that nobody is looking after
+                    let nearest = stations
+                        .iter()
+                        .filter(|(station, _)| !manned.contains(station))
+                        .filter_map(|(station, pos)| {
+                            let spots = operate_spots(*pos, walkable);
+                            Some((*station, find_path(member.tile, &spots, walkable)?))
+                        })
+                        .min_by_key(|(_, path)| path.len());
+                    let range = -CrewMember::WANDER_RANGE..=CrewMember::WANDER_RANGE;
+                    let wander = range
+                        .clone()
+                        .flat_map(|dx| range.clone().map(move |dy| (dx, dy)))
+                        .map(|(dx, dy)| member.tile + Vector3::new(dx, dy, 0))
+                        .filter(|pos| *pos != member.tile && walkable(*pos))
+                        .collect::<Vec<_>>()
+                        .choose(&mut rng)
+                        .and_then(|destination| {
+                            Some((
+                                *destination,
+                                find_path(member.tile, &[*destination], walkable)?,
+                            ))
+                        });
+
+                    if let Some((station, path)) = nearest {
+                        manned.insert(station);
+                        member.task = CrewTask::Operate(station);
+                        member.path = path;
+                    } else if let Some((destination, path)) = wander {
+                        member.task = CrewTask::Wander(destination);
+                        member.path = path;
+                    } else {
+                        member.idle(&mut rng);
+                    }
+                }
+            }
+
+            // Walks to the next tile of the path. A crew member that was turned around in the
+            // middle of a step walks back to the center of their tile.
+            let next = member.path.front().copied().unwrap_or(member.tile);
+            let end = CrewMember::tile_position(next);
+            let length = (end - member.from).magnitude();
+            if length > 0.0 {
+                member.progress =
+                    (member.progress + CrewMember::SPEED * time.delta / length).min(1.0);
+                transform.position = member.from.lerp(end, member.progress);
+                if member.progress >= 1.0 {
+                    member.tile = next;
+                    member.path.pop_front();
+                    member.from = end;
+                    member.progress = 0.0;
+                }
+            }
+
+            if member.has_arrived() {
+                match member.task {
+                    CrewTask::Operate(station) => {
+                        operated.insert(station, entity);
+                    }
+                    CrewTask::Wander(_) => member.idle(&mut rng),
+                    CrewTask::Idle { .. } => {}
+                }
+            }
+        }
+
+        // Machines lose the bonus once their crew member leaves (or dies)
+        self.stale.clear();
+        self.stale.extend(
+            (&entities, &bonuses)
+                .join()
+                .filter(|(station, bonus)| operated.get(station) != Some(&bonus.crew))
+                .map(|(station, _)| station),
+        );
+        for station in &self.stale {
+            bonuses.remove(*station);
+        }
+        for (station, crew) in operated.iter() {
+            if !bonuses.contains(*station) {
+                bonuses.insert(*station, CrewBonus { crew: *crew }).unwrap();
+            }
+        }
+    }
+}
+
+/// Logs the crew members that were killed
+struct CrewDeathSystem;
+
+impl<'a> System<'a> for CrewDeathSystem {
+    type SystemData = (
+        Read<'a, ToBeRemoved>,
+        ReadExpect<'a, Strings>,
+        WriteExpect<'a, GameLog>,
+        ReadStorage<'a, CrewMember>,
+        ReadStorage<'a, Health>,
+    );
+
+    fn run(&mut self, (to_be_removed, strings, mut log, crew, healths): Self::SystemData) {
+        // Every entity is removed when the game restarts, so only the crew without health
+        // were killed
+        for (_, health, _) in (&crew, &healths, to_be_removed.bitset()).join() {
+            if health.health() == 0 {
+                log.add(strings.get("log.crew_killed").to_string());
+            }
+        }
+    }
+}
@@ -0,0 +1,586 @@
+use super::{
+    gameplay::AsteroidField,
+    hit_markers::{AppliedDamage, HitEvents},
+    objects::{Asteroid, Health, MiningMissle},
+    physics::Collider,
+    ship_stats::ShipStatsCache,
+    time_effects::{TimeEffects, TimeEnvelope},
+    BlockEntity, Particles, Ship, Time, Transform,
+};
+use crate::floor::Floors;
+use crate::graphics::CameraController;
+use crate::stats::{StatEvent, StatEvents};
+use cgmath::{prelude::*, Point3, Vector3};
+use specs::prelude::*;
+use std::collections::HashMap;
+
+/// What caused some damage, which decides what it can hurt (see DamageRules)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DamageSource {
+    Laser,
+    MissileExplosion,
+    /// Unguided projectiles (ex. the cannon's slugs)
+    Projectile,
+    AsteroidImpact,
+    /// Fires and other heat damage (ex. burning blocks)
+    Heat,
+}
+
+/// Damage that is waiting to be applied by the DamageSystem
+#[derive(Clone, Copy, Debug)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: u32,
+    pub source: DamageSource,
+    /// Where the damage landed, which is where its damage number and hit marker are shown
+    pub point: Vector3<f32>,
+    /// Whether a hit marker confirms the hit (for the player's weapons)
+    pub confirm_hit: bool,
+}
+
+impl DamageEvent {
+    pub fn new(target: Entity, amount: u32, source: DamageSource, point: Vector3<f32>) -> Self {
+        Self {
+            target,
+            amount,
+            source,
+            point,
+            confirm_hit: false,
+        }
+    }
+
+    pub fn confirmed(self) -> Self {
+        Self {
+            confirm_hit: true,
+            ..self
+        }
+    }
+}
+
+/// Damage to everything within `radius` of `center`. The damage is the same anywhere in
+/// the radius.
+#[derive(Clone, Copy, Debug)]
+pub struct AreaDamage {
+    pub center: Vector3<f32>,
+    pub radius: f32,
+    pub amount: u32,
+    pub source: DamageSource,
+    /// An entity that is not damaged (ex. the asteroid that a missile destroyed)
+    pub exclude: Option<Entity>,
+}
+
+impl AreaDamage {
+    pub fn excluding(self, entity: Entity) -> Self {
+        Self {
+            exclude: Some(entity),
+            ..self
+        }
+    }
+}
+
+/// The damage queued during the current update. Systems queue damage here instead of
+/// changing the Health of their targets, and the DamageSystem applies all of it at once.
+#[derive(Default)]
+pub struct DamageEvents {
+    events: Vec<DamageEvent>,
+    areas: Vec<AreaDamage>,
+}
+
+impl DamageEvents {
+    pub fn push(&mut self, event: DamageEvent) {
+        if event.amount > 0 {
+            self.events.push(event);
+        }
+    }
+
+    pub fn push_area(&mut self, area: AreaDamage) {
+        if area.amount > 0 {
+            self.areas.push(area);
+        }
+    }
+}
+
+/// Which collider groups (ex. Collider::SHIP) each DamageSource can damage. Damage to
+/// anything else is ignored. Whether the attacker may damage its target's faction is
+/// checked by the attacker, before it queues the damage.
+#[derive(Clone, Debug)]
+pub struct DamageRules {
+    groups: HashMap<DamageSource, Vec<usize>>,
+}
+
+impl DamageRules {
+    /// Rules where nothing can be damaged
+    pub fn none() -> Self {
+        Self {
+            groups: HashMap::new(),
+        }
+    }
+
+    pub fn allow(mut self, source: DamageSource, groups: &[usize]) -> Self {
+        self.groups
+            .entry(source)
+            .or_default()
+            .extend_from_slice(groups);
+        self
+    }
+
+    pub fn affects(&self, source: DamageSource, group: usize) -> bool {
+        matches!(self.groups.get(&source), Some(groups) if groups.contains(&group))
+    }
+}
+
+impl Default for DamageRules {
+    /// Missile explosions hurt the asteroids and raiders around them, so clustered asteroids
+    /// can be destroyed together, but never the ship. Asteroids only hurt what they hit.
+    fn default() -> Self {
+        DamageRules::none()
+            .allow(
+                DamageSource::Laser,
+                &[Collider::ASTEROID, Collider::SHIP, Collider::RAIDER],
+            )
+            .allow(
+                DamageSource::MissileExplosion,
+                &[Collider::ASTEROID, Collider::RAIDER],
+            )
+            .allow(DamageSource::Projectile, &[Collider::ASTEROID])
+            .allow(DamageSource::AsteroidImpact, &[Collider::SHIP])
+            .allow(DamageSource::Heat, &[Collider::SHIP])
+    }
+}
+
+/// The damage that one target takes in an update
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedDamage {
+    pub target: Entity,
+    pub amount: u32,
+    /// Where the last hit landed
+    pub point: Vector3<f32>,
+    pub sources: Vec<DamageSource>,
+    pub confirm_hit: bool,
+}
+
+/// Drops the damage that the rules don't allow, and adds up the hits on each target.
+/// `group_of` gives the collider group of a target, and targets without one are not
+/// damaged. The targets are in the order they were first hit.
+pub fn resolve(
+    rules: &DamageRules,
+    events: &[DamageEvent],
+    group_of: impl Fn(Entity) -> Option<usize>,
+) -> Vec<ResolvedDamage> {
+    let mut resolved: Vec<ResolvedDamage> = Vec::new();
+    for event in events {
+        if !matches!(group_of(event.target), Some(group) if rules.affects(event.source, group)) {
+            continue;
+        }
+
+        match resolved.iter_mut().find(|hit| hit.target == event.target) {
+            Some(hit) => {
+                hit.amount += event.amount;
+                hit.point = event.point;
+                hit.confirm_hit |= event.confirm_hit;
+                if !hit.sources.contains(&event.source) {
+                    hit.sources.push(event.source);
+                }
+            }
+            None => resolved.push(ResolvedDamage {
+                target: event.target,
+                amount: event.amount,
+                point: event.point,
+                sources: vec![event.source],
+                confirm_hit: event.confirm_hit,
+            }),
+        }
+    }
+    resolved
+}
+
+/// Applies the queued damage to the Health of its targets. Asteroids that are destroyed by
+/// an explosion burst, and damage the asteroids around them in the next update.
+#[derive(Default)]
+pub struct DamageSystem {
+    /// The fractions of a point of damage that resisted sources have left on each target,
+    /// so that small hits (ex. a laser's ticks) still add up
+    remainders: HashMap<Entity, f32>,
+}
+
+impl DamageSystem {
+    const CHAIN_RADIUS: f32 = 2.0;
+    const CHAIN_DAMAGE: u32 = 45;
+    /// How much the camera shakes when an asteroid crashes into the ship, and when a missile
+    /// explodes (see CameraController::add_trauma)
+    const IMPACT_TRAUMA: f32 = 0.4;
+    const EXPLOSION_TRAUMA: f32 = 0.15;
+    /// Slows the game down when the last asteroid of a wave is destroyed
+    const WAVE_CLEARED_EFFECT: TimeEnvelope = TimeEnvelope {
+        scale: 0.3,
+        ease_in: 5,
+        hold: 30,
+        recover: 40,
+    };
+}
+
+/// The protection of the floors around the block, on whichever ship it was built on
+fn floor_protection(
+    ships: &ReadStorage<Ship>,
+    floors: &Floors,
+    block: Entity,
+    root: Point3<i16>,
+) -> f32 {
+    ships
+        .join()
+        .find(|ship| ship.has_block(root, block))
+        .map_or(0.0, |ship| ship.floor_protection(root, floors))
+}
+
+impl<'a> System<'a> for DamageSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, DamageRules>,
+        ReadExpect<'a, Floors>,
+        WriteExpect<'a, DamageEvents>,
+        WriteExpect<'a, AppliedDamage>,
+        WriteExpect<'a, HitEvents>,
+        WriteExpect<'a, Particles>,
+        WriteExpect<'a, StatEvents>,
+        WriteExpect<'a, TimeEffects>,
+        WriteExpect<'a, ShipStatsCache>,
+        WriteStorage<'a, Health>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Collider>,
+        ReadStorage<'a, Asteroid>,
+        ReadStorage<'a, AsteroidField>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        Option<Write<'a, CameraController>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            rules,
+            floors,
+            mut events,
+            mut applied,
+            mut hit_events,
+            mut particles,
+            mut stat_events,
+            mut time_effects,
+            mut stats_cache,
+            mut healths,
+            transforms,
+            colliders,
+            asteroids,
+            fields,
+            ships,
+            block_entities,
+            mut camera_controller,
+        ) = data;
+
+        let mut queued = std::mem::take(&mut events.events);
+        if let Some(controller) = camera_controller.as_mut() {
+            for event in &queued {
+                if event.source == DamageSource::AsteroidImpact {
+                    controller.add_trauma(DamageSystem::IMPACT_TRAUMA);
+                }
+            }
+            for area in &events.areas {
+                if area.source == DamageSource::MissileExplosion {
+                    controller.add_trauma(DamageSystem::EXPLOSION_TRAUMA);
+                }
+            }
+        }
+        for area in std::mem::take(&mut events.areas) {
+            for (entity, transform, _) in (&entities, &transforms, &healths).join() {
+                if Some(entity) != area.exclude
+                    && (transform.position - area.center).magnitude() <= area.radius
+                {
+                    queued.push(DamageEvent::new(
+                        entity,
+                        area.amount,
+                        area.source,
+                        transform.position,
+                    ));
+                }
+            }
+        }
+
+        // Asteroids resist some sources of damage, depending on what they are made of, and
+        // reinforced floors protect the blocks on and around them
+        self.remainders
+            .retain(|entity, _| entities.is_alive(*entity));
+        for event in &mut queued {
+            let multiplier = match (
+                asteroids.get(event.target),
+                block_entities.get(event.target),
+            ) {
+                (Some(asteroid), _) => asteroid.damage_multiplier(event.source),
+                (None, Some(block)) => {
+                    1.0 - floor_protection(&ships, &floors, event.target, block.root())
+                }
+                (None, None) => continue,
+            };
+            if (multiplier - 1.0).abs() > f32::EPSILON {
+                let remainder = self.remainders.entry(event.target).or_insert(0.0);
+                let damage = event.amount as f32 * multiplier + *remainder;
+                let amount = (damage + Time::SLACK).floor();
+                *remainder = damage - amount;
+                event.amount = amount as u32;
+            }
+        }
+        queued.retain(|event| event.amount > 0);
+
+        let group_of = |entity: Entity| {
+            Some(entity)
+                .filter(|entity| entities.is_alive(*entity))
+                .and_then(|entity| colliders.get(entity))
+                .map(|collider| collider.group)
+        };
+        for hit in resolve(&rules, &queued, group_of) {
+            let health = match healths.get_mut(hit.target) {
+                Some(health) => health,
+                None => continue,
+            };
+            let was_alive = health.health() > 0;
+            let damage = health.damage(hit.amount, hit.point);
+            let killed = health.health() == 0;
+            applied.push(hit.target, damage, hit.point, killed);
+            if hit.confirm_hit {
+                hit_events.confirm(hit.target, hit.point, damage, killed);
+            }
+
+            let exploded = hit.sources.contains(&DamageSource::MissileExplosion);
+            if group_of(hit.target) == Some(Collider::SHIP) {
+                stats_cache.mark_health_changed();
+                stat_events.push(StatEvent::DamageTaken(damage));
+                if was_alive && killed {
+                    stat_events.push(StatEvent::BlockLost);
+                }
+            }
+            let destroyed = asteroids.get(hit.target).filter(|_| was_alive && killed);
+            if let Some(asteroid) = destroyed {
+                // A missile gets the credit for anything it helped destroy
+                let source = if exploded {
+                    DamageSource::MissileExplosion
+                } else {
+                    hit.sources[0]
+                };
+                stat_events.push(StatEvent::AsteroidDestroyed {
+                    item: asteroid.primary,
+                    source,
+                });
+
+                let destroyed = |other: Entity| {
+                    !entities.is_alive(other)
+                        || matches!(healths.get(other), Some(health) if health.health() == 0)
+                };
+                if fields
+                    .join()
+                    .any(|field| field.is_cleared_by(hit.target, destroyed))
+                {
+                    time_effects.request(DamageSystem::WAVE_CLEARED_EFFECT);
+                }
+            }
+            if was_alive && killed && exploded && asteroids.contains(hit.target) {
+                particles.emit_burst(hit.point, 32, &MiningMissle::EXPLOSION_PARTICLES);
+                events.push_area(AreaDamage {
+                    center: hit.point,
+                    radius: DamageSystem::CHAIN_RADIUS,
+                    amount: DamageSystem::CHAIN_DAMAGE,
+                    source: DamageSource::MissileExplosion,
+                    exclude: Some(hit.target),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::headless::HeadlessEcs;
+
+    fn event(target: Entity, amount: u32, source: DamageSource) -> DamageEvent {
+        DamageEvent::new(
+            target,
+            amount,
+            source,
+            Vector3::new(amount as f32, 0.0, 0.0),
+        )
+    }
+
+    #[test]
+    fn missiles_never_damage_the_ship() {
+        let mut world = World::new();
+        let (block, asteroid, raider) = (
+            world.create_entity().build(),
+            world.create_entity().build(),
+            world.create_entity().build(),
+        );
+        let group_of = |entity: Entity| {
+            Some(if entity == block {
+                Collider::SHIP
+            } else if entity == asteroid {
+                Collider::ASTEROID
+            } else {
+                Collider::RAIDER
+            })
+        };
+        let events = [
+            event(block, 50, DamageSource::MissileExplosion),
+            event(asteroid, 50, DamageSource::MissileExplosion),
+            event(raider, 50, DamageSource::MissileExplosion),
+        ];
+        let resolved = resolve(&DamageRules::default(), &events, group_of);
+        let targets: Vec<Entity> = resolved.iter().map(|hit| hit.target).collect();
+        assert_eq!(targets, vec![asteroid, raider]);
+
+        // The ship is still hurt by what hits it
+        for source in [DamageSource::AsteroidImpact, DamageSource::Heat]
+            .iter()
+            .copied()
+        {
+            let resolved = resolve(
+                &DamageRules::default(),
+                &[event(block, 5, source)],
+                group_of,
+            );
+            assert_eq!(resolved.len(), 1);
+        }
+    }
+
+    #[test]
+    fn hits_on_a_target_add_up() {
+        let mut world = World::new();
+        let (first, second, ungrouped) = (
+            world.create_entity().build(),
+            world.create_entity().build(),
+            world.create_entity().build(),
+        );
+        let group_of = |entity: Entity| Some(Collider::ASTEROID).filter(|_| entity != ungrouped);
+        let events = [
+            event(second, 10, DamageSource::Laser),
+            event(first, 5, DamageSource::MissileExplosion).confirmed(),
+            event(ungrouped, 99, DamageSource::Laser),
+            event(second, 20, DamageSource::MissileExplosion),
+            event(second, 30, DamageSource::Laser),
+        ];
+        let resolved = resolve(&DamageRules::default(), &events, group_of);
+        assert_eq!(
+            resolved,
+            vec![
+                ResolvedDamage {
+                    target: second,
+                    amount: 60,
+                    point: Vector3::new(30.0, 0.0, 0.0),
+                    sources: vec![DamageSource::Laser, DamageSource::MissileExplosion],
+                    confirm_hit: false,
+                },
+                ResolvedDamage {
+                    target: first,
+                    amount: 5,
+                    point: Vector3::new(5.0, 0.0, 0.0),
+                    sources: vec![DamageSource::MissileExplosion],
+                    confirm_hit: true,
+                },
+            ]
+        );
+        assert!(resolve(&DamageRules::none(), &events, group_of).is_empty());
+    }
+
+    /// The health of the entity, which is 0 once it was removed
+    fn health_of(headless: &HeadlessEcs, entity: Entity) -> u32 {
+        let healths = headless.ecs.world.read_storage::<Health>();
+        healths.get(entity).map_or(0, Health::health)
+    }
+
+    #[test]
+    fn exploding_asteroids_burst_in_a_chain() {
+        let mut headless = HeadlessEcs::with_seed(1);
+        headless.pause_asteroid_field();
+        // Each asteroid is only within the chain radius of its neighbours, and the last is
+        // out of reach. They are far from the ship, so its gadgets don't fire at them.
+        let asteroids: Vec<Entity> = [30.0, 31.5, 33.0, 36.0]
+            .iter()
+            .map(|x| {
+                let asteroid = headless.spawn_asteroid(
+                    Vector3::new(*x, 0.0, 1.0),
+                    Vector3::new(0.0, 0.0, 0.0),
+                    1.0,
+                );
+                headless
+                    .ecs
+                    .world
+                    .write_storage::<Health>()
+                    .insert(asteroid, Health::new(DamageSystem::CHAIN_DAMAGE))
+                    .unwrap();
+                asteroid
+            })
+            .collect();
+
+        headless
+            .ecs
+            .get_resource_mut::<DamageEvents>()
+            .push(DamageEvent::new(
+                asteroids[0],
+                DamageSystem::CHAIN_DAMAGE,
+                DamageSource::MissileExplosion,
+                Vector3::new(30.0, 0.0, 1.0),
+            ));
+        // The burst of each asteroid reaches the next one in the following update
+        for destroyed in 1..=3 {
+            headless.step(1);
+            for (i, asteroid) in asteroids.iter().enumerate() {
+                let expected = if i < destroyed {
+                    0
+                } else {
+                    DamageSystem::CHAIN_DAMAGE
+                };
+                assert_eq!(health_of(&headless, *asteroid), expected, "{}", i);
+            }
+        }
+        headless.step(2);
+        assert_eq!(
+            health_of(&headless, asteroids[3]),
+            DamageSystem::CHAIN_DAMAGE
+        );
+    }
+
+    #[test]
+    fn explosions_on_the_ship_do_no_damage() {
+        let mut headless = HeadlessEcs::with_seed(1);
+        headless.pause_asteroid_field();
+        let blocks: Vec<Entity> = headless
+            .with_ship(|ship| ship.tiles().filter_map(|(_, tile)| tile.block()).collect())
+            .unwrap();
+        let healths: Vec<u32> = blocks
+            .iter()
+            .map(|block| health_of(&headless, *block))
+            .collect();
+        assert!(healths.iter().all(|health| *health > 0));
+        let mut explode = |source: DamageSource| {
+            headless
+                .ecs
+                .get_resource_mut::<DamageEvents>()
+                .push_area(AreaDamage {
+                    center: Vector3::new(0.0, 0.0, 0.5),
+                    radius: 20.0,
+                    amount: 10,
+                    source,
+                    exclude: None,
+                });
+            headless.step(1);
+            blocks
+                .iter()
+                .map(|block| health_of(&headless, *block))
+                .collect::<Vec<u32>>()
+        };
+
+        assert_eq!(explode(DamageSource::MissileExplosion), healths);
+        // The same blast from a source that may hurt the ship reaches every block
+        let after = explode(DamageSource::Laser);
+        assert!(after
+            .iter()
+            .zip(&healths)
+            .all(|(after, before)| after < before));
+    }
+}
@@ -0,0 +1,194 @@
+use super::{
+    objects::Health, physics::RigidBody, BlockEntity, GameModule, Model, Time, ToBeRemoved,
+    Transform,
+};
+use crate::block::Blocks;
+use crate::profiler::AddProfiled;
+use cgmath::{prelude::*, Quaternion, Rad, Vector3};
+use rand::Rng;
+use specs::{prelude::*, Component};
+
+/// Destroyed blocks burst into chunks, which tumble away from where the block was hit
+pub struct DebrisModule;
+
+impl GameModule for DebrisModule {
+    fn register(&self, world: &mut World) {
+        world.register::<Debris>();
+        world.register::<RigidBody>();
+        world.register::<Health>();
+        world.register::<BlockEntity>();
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(DebrisSystem, "debris_system", &[]);
+    }
+
+    fn death_systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(BlockDebrisSystem, "block_debris_system", &[]);
+    }
+}
+
+/// A chunk of a destroyed block. It has no collider, so it can't hit anything, and it
+/// shrinks away before it is removed.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct Debris {
+    /// The seconds since the chunk was spawned
+    age: f32,
+    /// The axis the chunk tumbles around, with the radians per second as its length
+    spin: Vector3<f32>,
+    /// The scale the chunk spawned with
+    size: f32,
+}
+
+impl Debris {
+    const MIN_CHUNKS: usize = 3;
+    const MAX_CHUNKS: usize = 6;
+    /// The most chunks that exist at once, so a chain of destroyed blocks doesn't flood the
+    /// instance buffers
+    const MAX_LIVE: usize = 96;
+    const LIFETIME: f32 = 2.5;
+    /// The chunks shrink away during the last 30 ticks (at the default tick rate)
+    const FADE_TIME: f32 = 30.0 / Time::DEFAULT_RATE as f32;
+    const MIN_SIZE: f32 = 0.15;
+    const MAX_SIZE: f32 = 0.3;
+    /// How far from the center of the block the chunks spawn
+    const SPREAD: f32 = 0.35;
+    /// How fast the chunks fly away from where the block was hit, on top of the velocity
+    /// of the block (ex. a drifting derelict)
+    const MIN_SPEED: f32 = 1.0;
+    const MAX_SPEED: f32 = 2.5;
+    /// The most radians per second that a chunk tumbles
+    const MAX_SPIN: f32 = 8.0;
+}
+
+/// Bursts the blocks that were destroyed into chunks. Blocks that were removed without
+/// being destroyed (ex. deconstructed) are left alone.
+struct BlockDebrisSystem;
+
+impl<'a> System<'a> for BlockDebrisSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, LazyUpdate>,
+        Read<'a, ToBeRemoved>,
+        ReadExpect<'a, Blocks>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, RigidBody>,
+        ReadStorage<'a, Debris>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            lazy_update,
+            to_be_removed,
+            blocks,
+            block_entities,
+            healths,
+            transforms,
+            bodies,
+            debris,
+        ) = data;
+
+        let mesh = blocks.get_block(blocks.cube).mesh_id;
+        let mut live = debris.join().count();
+        let mut rng = rand::thread_rng();
+
+        for (entity, _, _, health, transform) in (
+            &entities,
+            &block_entities,
+            to_be_removed.bitset(),
+            &healths,
+            &transforms,
+        )
+            .join()
+        {
+            if health.health() > 0 {
+                continue;
+            }
+            let count = rng
+                .gen_range(Debris::MIN_CHUNKS..=Debris::MAX_CHUNKS)
+                .min(Debris::MAX_LIVE.saturating_sub(live));
+            live += count;
+
+            let inherited = bodies
+                .get(entity)
+                .map_or(Vector3::zero(), |body| body.velocity);
+            let impact = health.last_hit().unwrap_or(transform.position);
+            for _ in 0..count {
+                let mut random_vector = |range: f32| {
+                    Vector3::new(
+                        rng.gen_range(-range..range),
+                        rng.gen_range(-range..range),
+                        rng.gen_range(-range..range),
+                    )
+                };
+                let offset = random_vector(Debris::SPREAD);
+                let spin = random_vector(1.0);
+                let position = transform.position + offset;
+                // The chunks fly away from the hit, and chunks at the hit fly up
+                let away = position - impact;
+                let direction = if away.magnitude2() > f32::EPSILON {
+                    away.normalize()
+                } else {
+                    Vector3::unit_z()
+                };
+                let speed = rng.gen_range(Debris::MIN_SPEED..Debris::MAX_SPEED);
+                let size = rng.gen_range(Debris::MIN_SIZE..Debris::MAX_SIZE);
+
+                let mut chunk_transform =
+                    Transform::from_position(position.x, position.y, position.z);
+                chunk_transform.scale = Vector3::new(size, size, size);
+                lazy_update
+                    .create_entity(&entities)
+                    .with(chunk_transform)
+                    .with(Model::new(mesh))
+                    .with(RigidBody {
+                        velocity: inherited + direction * speed,
+                    })
+                    .with(Debris {
+                        age: 0.0,
+                        spin: spin * Debris::MAX_SPIN,
+                        size,
+                    })
+                    .build();
+            }
+        }
+    }
+}
+
+/// Tumbles the chunks, and shrinks them away at the end of their lifetime
+struct DebrisSystem;
+
+impl<'a> System<'a> for DebrisSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Time>,
+        Write<'a, ToBeRemoved>,
+        WriteStorage<'a, Debris>,
+        WriteStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, time, mut to_be_removed, mut debris, mut transforms) = data;
+
+        for (entity, chunk, transform) in (&entities, &mut debris, &mut transforms).join() {
+            chunk.age += time.delta;
+            let time_left = Debris::LIFETIME - chunk.age;
+            if time_left <= Time::SLACK {
+                to_be_removed.add(entity);
+                continue;
+            }
+
+            let angle = chunk.spin.magnitude() * time.delta;
+            if angle > 0.0 {
+                let turn = Quaternion::from_axis_angle(chunk.spin.normalize(), Rad(angle));
+                transform.rotation = (turn * transform.rotation).normalize();
+            }
+            let size = chunk.size * (time_left / Debris::FADE_TIME).min(1.0);
+            transform.scale = Vector3::new(size, size, size);
+        }
+    }
+}
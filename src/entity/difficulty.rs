@@ -0,0 +1,388 @@
+use super::{
+    gameplay::AsteroidField, objects::Health, ship, BlockEntity, GameModule, Model, Ship, Time,
+};
+use crate::block::Blocks;
+use crate::item::{GameItem, Inventory};
+use crate::profiler::AddProfiled;
+use specs::prelude::*;
+
+/// Nudges the asteroid waves by how strong the ship is, so a struggling ship is not
+/// buried and a strong ship is kept busy
+pub struct DifficultyModule;
+
+impl GameModule for DifficultyModule {
+    fn register(&self, world: &mut World) {
+        world.register::<Ship>();
+        world.register::<BlockEntity>();
+        world.register::<Health>();
+        world.register::<Model>();
+        world.insert(DifficultyDirector::new());
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(DifficultyDirectorSystem, "difficulty_director_system", &[]);
+    }
+
+    fn init(&self, world: &mut World) {
+        // The old score is from a ship that is gone, so the new ship is scored right away
+        let mut director = world.write_resource::<DifficultyDirector>();
+        director.timer = 0.0;
+        director.score = None;
+        director.modifiers = DifficultyModifiers::NEUTRAL;
+    }
+}
+
+/// What a ship is scored on (see ShipStrength::score)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ShipStrength {
+    pub blocks: u32,
+    /// The health of the blocks, as a fraction (0..1) of their max health
+    pub health: f32,
+    pub lasers: u32,
+    pub coolers: u32,
+    pub shield_generators: u32,
+    /// How many items are in the inventory
+    pub items: u32,
+}
+
+impl ShipStrength {
+    const BLOCK_SCORE: f32 = 1.0;
+    const LASER_SCORE: f32 = 8.0;
+    const COOLER_SCORE: f32 = 3.0;
+    const SHIELD_GENERATOR_SCORE: f32 = 6.0;
+    const ITEM_SCORE: f32 = 0.1;
+    /// The score of the starting ship, and how much a ship that keeps up adds each wave
+    const START_SCORE: f32 = 50.0;
+    const SCORE_PER_LEVEL: f32 = 6.0;
+
+    /// Scores the blocks and gadgets of the mothership, or returns None if there is none
+    fn measure(
+        ships: &ReadStorage<Ship>,
+        block_entities: &ReadStorage<BlockEntity>,
+        healths: &ReadStorage<Health>,
+        models: &ReadStorage<Model>,
+        blocks: &Blocks,
+        inventory: &Inventory,
+    ) -> Option<ShipStrength> {
+        let ship = ship::mothership(ships)?;
+        let mut strength = ShipStrength {
+            items: GameItem::iter().map(|item| inventory.amount(item)).sum(),
+            ..ShipStrength::default()
+        };
+        let mut health = 0.0;
+        for (_, tile) in ship.tiles() {
+            let block = tile
+                .block()
+                .and_then(|entity| Some((entity, block_entities.get(entity)?.block_id())));
+            if let Some((entity, block_id)) = block {
+                strength.blocks += 1;
+                health += healths.get(entity).map_or(1.0, Health::fraction);
+                if block_id == blocks.cooler {
+                    strength.coolers += 1;
+                }
+            }
+            // Gadgets don't have a BlockEntity, so their block is found by their mesh
+            let gadget = tile
+                .gadget()
+                .and_then(|entity| blocks.find_by_mesh(models.get(entity)?.mesh_id));
+            if gadget == Some(blocks.laser) {
+                strength.lasers += 1;
+            } else if gadget == Some(blocks.shield_generator) {
+                strength.shield_generators += 1;
+            }
+        }
+        strength.health = health / strength.blocks.max(1) as f32;
+
+        Some(strength)
+    }
+
+    /// Damaged blocks count for less, so a ship that is falling apart scores lower
+    pub fn score(&self) -> f32 {
+        self.blocks as f32 * self.health * ShipStrength::BLOCK_SCORE
+            + self.lasers as f32 * ShipStrength::LASER_SCORE
+            + self.coolers as f32 * ShipStrength::COOLER_SCORE
+            + self.shield_generators as f32 * ShipStrength::SHIELD_GENERATOR_SCORE
+            + self.items as f32 * ShipStrength::ITEM_SCORE
+    }
+
+    /// The score of a ship that is keeping up with the waves at the level
+    pub fn expected_score(level: u16) -> f32 {
+        ShipStrength::START_SCORE + ShipStrength::SCORE_PER_LEVEL * (level.max(1) - 1) as f32
+    }
+}
+
+/// Multiplies the parameters of a wave, which otherwise only depend on its level
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DifficultyModifiers {
+    /// The seconds between asteroid spawns
+    pub spawn_interval: f32,
+    /// The chance of an asteroid being aimed at the ship
+    pub stray_chance: f64,
+    pub asteroid_health: f32,
+    /// The chance of an asteroid being volatile
+    pub volatile_chance: f64,
+}
+
+impl DifficultyModifiers {
+    pub const NEUTRAL: DifficultyModifiers = DifficultyModifiers {
+        spawn_interval: 1.0,
+        stray_chance: 1.0,
+        asteroid_health: 1.0,
+        volatile_chance: 1.0,
+    };
+    /// How far the score can be from the expected score (as a fraction of it) before the
+    /// modifiers stop changing
+    const SCORE_RANGE: f32 = 0.5;
+    /// How far each modifier can be from 1, which keeps the waves close to their level
+    const MAX_INTERVAL_NUDGE: f32 = 0.2;
+    const MAX_STRAY_NUDGE: f64 = 0.3;
+    const MAX_HEALTH_NUDGE: f32 = 0.25;
+    const MAX_VOLATILE_NUDGE: f64 = 0.5;
+
+    /// The modifiers for a ship with the score at the level. A stronger ship than expected
+    /// gets more asteroids that are tougher and more often aimed at it or volatile, and a
+    /// weaker ship gets fewer and softer ones.
+    pub fn for_score(score: f32, level: u16) -> DifficultyModifiers {
+        let expected = ShipStrength::expected_score(level);
+        let nudge = ((score / expected - 1.0) / DifficultyModifiers::SCORE_RANGE).clamp(-1.0, 1.0);
+
+        DifficultyModifiers {
+            spawn_interval: 1.0 - nudge * DifficultyModifiers::MAX_INTERVAL_NUDGE,
+            stray_chance: 1.0 + nudge as f64 * DifficultyModifiers::MAX_STRAY_NUDGE,
+            asteroid_health: 1.0 + nudge * DifficultyModifiers::MAX_HEALTH_NUDGE,
+            volatile_chance: 1.0 + nudge as f64 * DifficultyModifiers::MAX_VOLATILE_NUDGE,
+        }
+    }
+
+    /// Ex. "interval x0.90, aimed x1.15, health x1.12, volatile x1.25"
+    pub fn describe(&self) -> String {
+        format!(
+            "interval x{:.2}, aimed x{:.2}, health x{:.2}, volatile x{:.2}",
+            self.spawn_interval, self.stray_chance, self.asteroid_health, self.volatile_chance
+        )
+    }
+}
+
+/// Scores the ship every few seconds, and picks the modifiers that the next wave is planned
+/// with. Turned off (with DIFFICULTY_DIRECTOR or `--fixed-difficulty`), the waves only depend
+/// on the seed and their level, so runs can be replayed exactly.
+pub struct DifficultyDirector {
+    pub enabled: bool,
+    /// The seconds until the ship is scored again
+    timer: f32,
+    /// The last score of the ship, and the score expected at the next wave
+    score: Option<(f32, f32)>,
+    modifiers: DifficultyModifiers,
+}
+
+impl DifficultyDirector {
+    /// Turns off the director when passed on the command line
+    pub const DISABLE_FLAG: &'static str = "--fixed-difficulty";
+    /// The seconds between scoring the ship
+    const INTERVAL: f32 = 4.0;
+
+    fn new() -> Self {
+        Self {
+            enabled: crate::DIFFICULTY_DIRECTOR
+                && !std::env::args().any(|arg| arg == DifficultyDirector::DISABLE_FLAG),
+            timer: 0.0,
+            score: None,
+            modifiers: DifficultyModifiers::NEUTRAL,
+        }
+    }
+
+    /// The modifiers for the next wave that is planned
+    pub fn modifiers(&self) -> DifficultyModifiers {
+        if self.enabled {
+            self.modifiers
+        } else {
+            DifficultyModifiers::NEUTRAL
+        }
+    }
+
+    /// The last score of the ship and the score expected at the next wave, if it was scored
+    pub fn score(&self) -> Option<(f32, f32)> {
+        self.score.filter(|_| self.enabled)
+    }
+}
+
+struct DifficultyDirectorSystem;
+
+impl<'a> System<'a> for DifficultyDirectorSystem {
+    type SystemData = (
+        ReadExpect<'a, Time>,
+        WriteExpect<'a, DifficultyDirector>,
+        ReadExpect<'a, Blocks>,
+        ReadExpect<'a, Inventory>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, Model>,
+        ReadStorage<'a, AsteroidField>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (time, mut director, blocks, inventory, ships, block_entities, healths, models, fields) =
+            data;
+        if !director.enabled {
+            return;
+        }
+        director.timer -= time.delta;
+        if director.timer > Time::SLACK {
+            return;
+        }
+        director.timer = DifficultyDirector::INTERVAL;
+
+        let level = match fields.join().next() {
+            Some(field) => (field.level + 1).min(AsteroidField::MAX_LEVEL),
+            None => return,
+        };
+        if let Some(strength) = ShipStrength::measure(
+            &ships,
+            &block_entities,
+            &healths,
+            &models,
+            &blocks,
+            &inventory,
+        ) {
+            let score = strength.score();
+            director.score = Some((score, ShipStrength::expected_score(level)));
+            director.modifiers = DifficultyModifiers::for_score(score, level);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::headless::HeadlessEcs;
+    use cgmath::Point3;
+
+    fn measure(headless: &HeadlessEcs) -> ShipStrength {
+        let world = &headless.ecs.world;
+        ShipStrength::measure(
+            &world.read_storage(),
+            &world.read_storage(),
+            &world.read_storage(),
+            &world.read_storage(),
+            &world.fetch(),
+            &world.fetch(),
+        )
+        .unwrap()
+    }
+
+    /// A strength that only has the parts that score
+    fn strength(blocks: u32, lasers: u32, coolers: u32, items: u32) -> ShipStrength {
+        ShipStrength {
+            blocks,
+            health: 1.0,
+            lasers,
+            coolers,
+            shield_generators: 0,
+            items,
+        }
+    }
+
+    fn assert_modifiers(modifiers: DifficultyModifiers, expected: [f64; 4]) {
+        let actual = [
+            modifiers.spawn_interval as f64,
+            modifiers.stray_chance,
+            modifiers.asteroid_health as f64,
+            modifiers.volatile_chance,
+        ];
+        for (actual, expected) in actual.iter().zip(&expected) {
+            assert!(
+                (actual - expected).abs() < 1e-5,
+                "{:?} != {:?}",
+                modifiers,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn the_starting_ship_scores_the_first_wave() {
+        let mut headless = HeadlessEcs::with_seed(1);
+        headless.step(1);
+        let start = measure(&headless);
+        // The laser is a gadget, which has no BlockEntity
+        assert_eq!(start.lasers, 1);
+        assert_eq!(start.coolers, 1);
+        assert_eq!(start.health, 1.0);
+        // The starting items are random, so they are left out
+        let without_items = ShipStrength { items: 0, ..start }.score();
+        assert!((without_items - ShipStrength::expected_score(1)).abs() < 5.0);
+
+        headless.build_block(Point3::new(7, 3, 0), "Laser");
+        headless.step(1);
+        let strength = measure(&headless);
+        assert_eq!(strength.lasers, 2);
+        assert_eq!(strength.score() - start.score(), ShipStrength::LASER_SCORE);
+
+        // A damaged block counts for the fraction of its health that is left
+        let block = headless
+            .with_ship(|ship| ship.tile(Point3::new(0, 1, 0))?.block())
+            .flatten()
+            .unwrap();
+        let fraction = {
+            let mut healths = headless.ecs.world.write_storage::<Health>();
+            let health = healths.get_mut(block).unwrap();
+            health.damage(1, cgmath::Vector3::new(0.0, 1.0, 0.0));
+            health.fraction()
+        };
+        let damaged = measure(&headless);
+        assert!(damaged.health < 1.0);
+        let lost = strength.score() - damaged.score();
+        let expected = (1.0 - fraction) * ShipStrength::BLOCK_SCORE;
+        assert!((lost - expected).abs() < 1e-3, "{} != {}", lost, expected);
+    }
+
+    #[test]
+    fn scores_add_up_their_parts() {
+        assert_eq!(strength(0, 0, 0, 0).score(), 0.0);
+        assert_eq!(strength(10, 2, 1, 30).score(), 10.0 + 16.0 + 3.0 + 3.0);
+        let half_health = ShipStrength {
+            health: 0.5,
+            ..strength(10, 0, 0, 0)
+        };
+        assert_eq!(half_health.score(), 5.0);
+
+        assert_eq!(ShipStrength::expected_score(0), ShipStrength::START_SCORE);
+        assert_eq!(ShipStrength::expected_score(1), ShipStrength::START_SCORE);
+        let tenth = ShipStrength::START_SCORE + 9.0 * ShipStrength::SCORE_PER_LEVEL;
+        assert_eq!(ShipStrength::expected_score(10), tenth);
+    }
+
+    #[test]
+    fn modifiers_follow_the_score_within_their_bands() {
+        for level in [1, 5, 20].iter().copied() {
+            let expected = ShipStrength::expected_score(level);
+            let at = |fraction: f32| DifficultyModifiers::for_score(expected * fraction, level);
+
+            assert_eq!(at(1.0), DifficultyModifiers::NEUTRAL);
+            assert_modifiers(at(1.25), [0.9, 1.15, 1.125, 1.25]);
+            assert_modifiers(at(0.75), [1.1, 0.85, 0.875, 0.75]);
+            // The modifiers stop changing half the expected score away from it
+            for fraction in [1.5, 2.0, 100.0].iter().copied() {
+                assert_modifiers(at(fraction), [0.8, 1.3, 1.25, 1.5]);
+            }
+            for fraction in [0.5, 0.1, 0.0].iter().copied() {
+                assert_modifiers(at(fraction), [1.2, 0.7, 0.75, 0.5]);
+            }
+        }
+    }
+
+    #[test]
+    fn a_disabled_director_keeps_the_waves_neutral() {
+        let mut director = DifficultyDirector::new();
+        director.modifiers = DifficultyModifiers::for_score(1000.0, 1);
+        director.score = Some((1000.0, ShipStrength::expected_score(1)));
+
+        director.enabled = true;
+        assert_ne!(director.modifiers(), DifficultyModifiers::NEUTRAL);
+        assert!(director.score().is_some());
+        director.enabled = false;
+        assert_eq!(director.modifiers(), DifficultyModifiers::NEUTRAL);
+        assert_eq!(director.score(), None);
+    }
+}
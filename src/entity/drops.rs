@@ -0,0 +1,316 @@
+use super::{
+    faction::Faction,
+    gameplay::{AsteroidField, GameLog, GameStats},
+    hud::HudVerbosity,
+    objects::ObjectMeshes,
+    physics::{Collider, ColliderShape, Hitbox, RigidBody},
+    ship::Bounds,
+    GameModule, InputManager, Model, ParticleParams, Particles, Ship, Time, ToBeRemoved, Transform,
+};
+use crate::floor::Floors;
+use crate::graphics::ModelTint;
+use crate::i18n::Strings;
+use crate::item::{self, GameItem, Inventory};
+use crate::profiler::AddProfiled;
+use crate::stats::{StatEvent, StatEvents};
+use cgmath::{prelude::*, Quaternion, Rad, Vector3};
+use specs::{prelude::*, world::EntitiesRes, Component};
+use std::collections::HashMap;
+
+/// The items that mined asteroids leave behind, and collecting them
+pub struct DropModule;
+
+impl GameModule for DropModule {
+    fn register(&self, world: &mut World) {
+        world.register::<ItemDrop>();
+        world.register::<RigidBody>();
+        world.insert(ItemPickups::default());
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(CollectorSystem::default(), "collector_system", &[]);
+    }
+
+    fn init(&self, world: &mut World) {
+        world.insert(ItemPickups::default());
+    }
+}
+
+/// Items floating in space. They drift until they come close to the ship, which pulls
+/// them in, or until they are clicked.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct ItemDrop {
+    pub item: GameItem,
+    pub amount: u32,
+    /// The seconds until the drop despawns
+    time_left: f32,
+    /// The rotation around z, which slowly spins the drop
+    angle: f32,
+}
+
+impl ItemDrop {
+    /// The seconds a drop floats for before it despawns
+    const LIFETIME: f32 = 30.0;
+    const SCALE: f32 = 0.3;
+    const RADIUS: f32 = 0.3;
+    /// Radians per second
+    const SPIN_SPEED: f32 = 0.8;
+    /// How much of the asteroid's velocity the drops keep
+    const INHERITED_VELOCITY: f32 = 0.3;
+    /// The sideways speed that the drops of an asteroid drift apart with
+    const SPREAD_SPEED: f32 = 0.4;
+    /// How close to the ship a drop has to be before it is pulled in
+    const COLLECT_RANGE: f32 = 5.0;
+    /// Units per second squared
+    const PULL_ACCELERATION: f32 = 6.0;
+    const MAX_PULL_SPEED: f32 = 8.0;
+    /// How close (ignoring the height) to a conveyor floor a drop has to be to be carried
+    const CONVEYOR_RANGE: f32 = 3.0;
+
+    fn pickup_particles(item: GameItem) -> ParticleParams {
+        ParticleParams {
+            color: item.color(),
+            speed: 1.5,
+            lifetime: 0.4,
+            size: 0.08,
+        }
+    }
+
+    /// Creates a drop at the position. `side` (-1 or 1) decides which way it drifts, so the
+    /// drops of an asteroid separate.
+    pub fn spawn(
+        lazy_update: &LazyUpdate,
+        entities: &EntitiesRes,
+        meshes: &ObjectMeshes,
+        (item, amount): (GameItem, u32),
+        position: Vector3<f32>,
+        velocity: Vector3<f32>,
+        side: f32,
+    ) {
+        let (primary, secondary) = item.asteroid_tints();
+        let mut transform = Transform::from_position(position.x, position.y, position.z);
+        transform.scale = Vector3::new(ItemDrop::SCALE, ItemDrop::SCALE, ItemDrop::SCALE);
+
+        lazy_update
+            .create_entity(entities)
+            .with(Model::with_tint(
+                meshes.asteroid,
+                ModelTint::new(primary, secondary),
+            ))
+            .with(transform)
+            .with(RigidBody {
+                velocity: velocity * ItemDrop::INHERITED_VELOCITY
+                    + Vector3::new(0.0, side * ItemDrop::SPREAD_SPEED, 0.0),
+            })
+            // Drops don't collide with anything, the collider is only used to click them
+            .with(Collider::new(
+                Hitbox::with_shape(ColliderShape::Sphere(ItemDrop::RADIUS)),
+                Collider::ITEM,
+                &[],
+            ))
+            .with(Faction::Neutral)
+            .with(ItemDrop {
+                item,
+                amount,
+                time_left: ItemDrop::LIFETIME,
+                angle: 0.0,
+            })
+            .build();
+    }
+}
+
+/// The items collected recently, which the inventory shows next to each item. Pickups in
+/// quick succession are added together.
+#[derive(Default)]
+pub struct ItemPickups {
+    /// The amount of each item collected recently, and the seconds since its last pickup
+    recent: HashMap<GameItem, (u32, f32)>,
+}
+
+impl ItemPickups {
+    /// How long a pickup counts as recent
+    const RECENT_TIME: f32 = 2.0;
+
+    fn add(&mut self, item: GameItem, amount: u32) {
+        let recent = self.recent.entry(item).or_insert((0, 0.0));
+        *recent = (recent.0 + amount, 0.0);
+    }
+
+    /// The amount of the item that was collected recently, if any
+    pub fn recent(&self, item: GameItem) -> Option<u32> {
+        self.recent.get(&item).map(|(amount, _)| *amount)
+    }
+}
+
+/// A conveyor floor of a ship, and where it carries the drops to
+struct Conveyor {
+    core: Vector3<f32>,
+    /// The center of the floor
+    center: Vector3<f32>,
+    speed: f32,
+}
+
+impl Conveyor {
+    fn find<'a>(
+        ship: &'a Ship,
+        floors: &'a Floors,
+        transforms: &WriteStorage<Transform>,
+    ) -> impl Iterator<Item = Conveyor> + 'a {
+        let core = ship
+            .core()
+            .and_then(|core| transforms.get(core))
+            .map(|transform| transform.position);
+        core.into_iter().flat_map(move |core| {
+            ship.tiles()
+                .filter_map(move |(pos, tile)| {
+                    let speed = floors.get(tile.floor_type()?).properties.conveyor_speed;
+                    let center = Vector3::new(pos.x as f32, pos.y as f32, Ship::deck_z(pos.z));
+                    Some(Conveyor {
+                        core,
+                        center,
+                        speed,
+                    })
+                })
+                .filter(|conveyor| conveyor.speed > 0.0)
+        })
+    }
+
+    /// The fastest conveyor in range of the position, and the core it carries to
+    fn carry(conveyors: &[Conveyor], position: Vector3<f32>) -> Option<(Vector3<f32>, f32)> {
+        conveyors
+            .iter()
+            .filter(|conveyor| {
+                let offset = conveyor.center - position;
+                offset.x.hypot(offset.y) <= ItemDrop::CONVEYOR_RANGE
+            })
+            .map(|conveyor| (conveyor.core, conveyor.speed))
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+    }
+}
+
+/// Moves and spins the drops, pulls in the drops close to the ship, and collects them.
+/// Conveyor floors carry the drops near them to their ship's core, on top of the pull.
+#[derive(Default)]
+pub struct CollectorSystem {
+    // These are reused every update, so they are not allocated every update
+    ship_bounds: Vec<Bounds>,
+    conveyors: Vec<Conveyor>,
+}
+
+impl<'a> System<'a> for CollectorSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Time>,
+        ReadExpect<'a, InputManager>,
+        ReadExpect<'a, HudVerbosity>,
+        ReadExpect<'a, Strings>,
+        Write<'a, ToBeRemoved>,
+        WriteExpect<'a, Inventory>,
+        WriteExpect<'a, ItemPickups>,
+        WriteExpect<'a, StatEvents>,
+        WriteExpect<'a, GameStats>,
+        WriteExpect<'a, GameLog>,
+        WriteExpect<'a, Particles>,
+        WriteStorage<'a, ItemDrop>,
+        WriteStorage<'a, RigidBody>,
+        WriteStorage<'a, Transform>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, AsteroidField>,
+        ReadExpect<'a, Floors>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            time,
+            input,
+            verbosity,
+            strings,
+            mut to_be_removed,
+            mut inventory,
+            mut pickups,
+            mut stat_events,
+            mut game_stats,
+            mut log,
+            mut particles,
+            mut drops,
+            mut bodies,
+            mut transforms,
+            ships,
+            fields,
+            floors,
+        ) = data;
+        for (_, since) in pickups.recent.values_mut() {
+            *since += time.delta;
+        }
+        pickups
+            .recent
+            .retain(|_, (_, since)| *since < ItemPickups::RECENT_TIME);
+
+        let ship_bounds = &mut self.ship_bounds;
+        ship_bounds.clear();
+        ship_bounds.extend(ships.join().filter_map(Ship::bounds));
+        let x_range = fields.join().next().map(|field| field.x_range);
+        let conveyors = &mut self.conveyors;
+        conveyors.clear();
+        conveyors.extend(
+            ships
+                .join()
+                .flat_map(|ship| Conveyor::find(ship, &floors, &transforms)),
+        );
+
+        for (entity, drop, body, transform) in
+            (&entities, &mut drops, &mut bodies, &mut transforms).join()
+        {
+            let position = transform.position;
+            // Drops leave the field like asteroids do
+            let outside_field = matches!(x_range, Some(x_range) if position.x > x_range
+                || position.x < -x_range - AsteroidField::FALLBACK_DISTANCE);
+            if time.count_down(&mut drop.time_left) || outside_field {
+                to_be_removed.add(entity);
+                continue;
+            }
+
+            drop.angle += ItemDrop::SPIN_SPEED * time.delta;
+            transform.rotation = Quaternion::from_angle_z(Rad(drop.angle));
+
+            let touching = ship_bounds
+                .iter()
+                .any(|bounds| bounds.intersects_sphere(position, ItemDrop::RADIUS));
+            if !touching && input.clicked_drop != Some(entity) {
+                // Every ship pulls in the drops that come close to it
+                if let Some(bounds) = ship_bounds
+                    .iter()
+                    .find(|bounds| bounds.intersects_sphere(position, ItemDrop::COLLECT_RANGE))
+                {
+                    let direction = ((bounds.min + bounds.max) / 2.0 - position).normalize();
+                    body.velocity += direction * ItemDrop::PULL_ACCELERATION * time.delta;
+                    if body.velocity.magnitude() > ItemDrop::MAX_PULL_SPEED {
+                        body.velocity = body.velocity.normalize() * ItemDrop::MAX_PULL_SPEED;
+                    }
+                }
+                if let Some((core, speed)) = Conveyor::carry(conveyors, position) {
+                    transform.position += (core - position).normalize() * speed * time.delta;
+                }
+                continue;
+            }
+
+            to_be_removed.add(entity);
+            inventory.change_amount(drop.item, drop.amount);
+            stat_events.push(StatEvent::ItemsMined(drop.item, drop.amount));
+            game_stats.items_mined += drop.amount;
+            pickups.add(drop.item, drop.amount);
+            particles.emit_burst(position, 12, &ItemDrop::pickup_particles(drop.item));
+            if *verbosity == HudVerbosity::Full {
+                log.add(strings.format(
+                    "log.collected",
+                    &[(
+                        "items",
+                        &item::describe_amount(drop.item, drop.amount, &strings),
+                    )],
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use cgmath::Vector3;
+use specs::{prelude::*, Component};
+
+/// Who an entity fights for. This is separate from the collider groups, which only decide
+/// what the physics checks for contacts.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+#[storage(VecStorage)]
+pub enum Faction {
+    Player,
+    Hostile,
+    /// Fights no one (ex. asteroids and item drops)
+    Neutral,
+}
+
+impl Faction {
+    pub fn from_name(name: &str) -> Option<Faction> {
+        match name {
+            "player" => Some(Faction::Player),
+            "hostile" => Some(Faction::Hostile),
+            "neutral" => Some(Faction::Neutral),
+            _ => None,
+        }
+    }
+
+    /// Whether the two factions fight each other
+    pub fn is_hostile_to(self, other: Faction) -> bool {
+        matches!(
+            (self, other),
+            (Faction::Player, Faction::Hostile) | (Faction::Hostile, Faction::Player)
+        )
+    }
+
+    /// Whether this faction may damage the other. Neutral entities are fair game (ex.
+    /// mining asteroids), but an entity never damages its own faction.
+    pub fn can_damage(self, other: Faction) -> bool {
+        self != other && self != Faction::Neutral
+    }
+
+    /// The color that marks the faction's health bars
+    pub fn color(self) -> Vector3<f32> {
+        match self {
+            Faction::Player => Vector3::new(0.2, 0.5, 1.0),
+            Faction::Hostile => Vector3::new(1.0, 0.2, 0.2),
+            Faction::Neutral => Vector3::new(0.6, 0.6, 0.6),
+        }
+    }
+}
+
+/// The faction of the entity. Entities without one are neutral.
+pub fn faction_of(factions: &ReadStorage<'_, Faction>, entity: Entity) -> Faction {
+    factions.get(entity).copied().unwrap_or(Faction::Neutral)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FACTIONS: [Faction; 3] = [Faction::Player, Faction::Hostile, Faction::Neutral];
+
+    #[test]
+    fn factions_never_damage_themselves() {
+        for faction in FACTIONS.iter().copied() {
+            assert!(!faction.can_damage(faction), "{:?} damages itself", faction);
+            assert!(!faction.is_hostile_to(faction));
+        }
+    }
+
+    #[test]
+    fn only_neutral_deals_no_damage() {
+        for attacker in FACTIONS.iter().copied() {
+            for target in FACTIONS.iter().copied() {
+                let expected = attacker != target && attacker != Faction::Neutral;
+                assert_eq!(attacker.can_damage(target), expected);
+                // Anything that is hostile can also be damaged
+                if attacker.is_hostile_to(target) {
+                    assert!(attacker.can_damage(target));
+                    assert!(target.is_hostile_to(attacker));
+                }
+            }
+        }
+        assert!(Faction::Player.can_damage(Faction::Neutral));
+        assert!(!Faction::Player.is_hostile_to(Faction::Neutral));
+    }
+
+    #[test]
+    fn names_match_the_factions() {
+        assert_eq!(Faction::from_name("player"), Some(Faction::Player));
+        assert_eq!(Faction::from_name("hostile"), Some(Faction::Hostile));
+        assert_eq!(Faction::from_name("neutral"), Some(Faction::Neutral));
+        assert_eq!(Faction::from_name("Player"), None);
+    }
+}
@@ -1,56 +1,582 @@
 use super::{
-    objects::{Asteroid, Health, ObjectMeshes},
-    physics::{Collider, ColliderShape, Hitbox, RigidBody},
-    Model, ToBeRemoved, Transform,
+    difficulty::{DifficultyDirector, DifficultyModifiers},
+    objects::{self, Asteroid, Health, ObjectMeshes},
+    physics::RigidBody,
+    raider::{self, Raider},
+    share::SharedShip,
+    ship::{self, Bounds, ShipKind},
+    BlockEntity, GameModule, Line, LineBatch, Ship, Time, ToBeRemoved, Transform,
 };
-use crate::item::GameItem;
-use cgmath::Vector3;
-use rand::{seq::IteratorRandom, Rng};
+use crate::debug::{ArgSchema, ArgType, DebugCommand, DebugCommands};
+use crate::graphics::{Camera, CameraController, Frustum, GPUBillboard};
+use crate::i18n::Strings;
+use crate::item::{GameItem, Inventory};
+use crate::profiler::AddProfiled;
+use crate::stats::{StatEvent, StatEvents};
+use crate::tech::TechTree;
+use cgmath::{Point2, Vector2, Vector3};
+use rand::{rngs::StdRng, seq::IteratorRandom, Rng, SeedableRng};
 use specs::{prelude::*, Component};
+use std::collections::VecDeque;
 
-pub fn register_components(world: &mut World) {
-    world.register::<AsteroidField>();
+/// The asteroid field and its waves, and the state of the game
+pub struct GameplayModule;
+
+impl GameModule for GameplayModule {
+    fn register(&self, world: &mut World) {
+        world.register::<AsteroidField>();
+        world.register::<IncomingThreat>();
+        world.register::<Health>();
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(AsteroidFieldSystem::default(), "asteroid_field_system", &[]);
+        builder.add_profiled(GameStateSystem, "game_state_system", &[]);
+        builder.add_profiled(ThreatSystem::default(), "threat_system", &[]);
+    }
+
+    fn init(&self, world: &mut World) {
+        let (seed, level, modifiers) = {
+            let checkpoint = world.read_resource::<WaveCheckpoint>();
+            (checkpoint.seed, checkpoint.level, checkpoint.difficulty)
+        };
+        init_world(world, seed, level, modifiers);
+    }
+
+    fn commands(&self, commands: &mut DebugCommands) {
+        commands.register(DebugCommand {
+            name: "spawn_asteroid",
+            description: "Spawns asteroids of an item in random lanes",
+            args: vec![
+                ArgSchema {
+                    name: "item",
+                    arg_type: ArgType::Choice(&["iron", "copper"]),
+                },
+                ArgSchema {
+                    name: "count",
+                    arg_type: ArgType::Int,
+                },
+            ],
+            state: Some(|ecs| {
+                let fields = ecs.world.read_component::<AsteroidField>();
+                let count = fields
+                    .join()
+                    .map(|field| field.asteroids.len())
+                    .sum::<usize>();
+                format!("{} asteroids", count)
+            }),
+            action: |ecs, args| {
+                let item = GameItem::from_name(args[0].as_choice()).unwrap();
+                let count = args[1].as_int();
+                if !(1..=AsteroidField::MAX_DEBUG_SPAWNS).contains(&count) {
+                    return Err(format!(
+                        "Count must be from 1 to {}: {}",
+                        AsteroidField::MAX_DEBUG_SPAWNS,
+                        count
+                    ));
+                }
+
+                spawn_asteroids(&ecs.world, item, count as usize)?;
+                // The asteroids are only added once the world is maintained, and the field
+                // expects every asteroid in its list to have been added
+                ecs.world.maintain();
+                Ok(())
+            },
+        });
+
+        commands.register(DebugCommand {
+            name: "set_level",
+            description: "Starts the wave of the asteroid field",
+            args: vec![ArgSchema {
+                name: "level",
+                arg_type: ArgType::Int,
+            }],
+            state: Some(|ecs| {
+                let fields = ecs.world.read_component::<AsteroidField>();
+                match fields.join().next() {
+                    Some(field) => format!("wave {}", field.level),
+                    None => String::from("no asteroid field"),
+                }
+            }),
+            action: |ecs, args| {
+                let level = args[0].as_int();
+                if !(1..=AsteroidField::MAX_LEVEL as i64).contains(&level) {
+                    return Err(format!(
+                        "Level must be from 1 to {}: {}",
+                        AsteroidField::MAX_LEVEL,
+                        level
+                    ));
+                }
+
+                let modifiers = ecs.get_resource::<DifficultyDirector>().modifiers();
+                let mut fields = ecs.world.write_component::<AsteroidField>();
+                let field = (&mut fields)
+                    .join()
+                    .next()
+                    .ok_or_else(|| String::from("There is no asteroid field"))?;
+                field.start_wave(level as u16, modifiers);
+                let message = ecs
+                    .world
+                    .fetch::<Strings>()
+                    .format("log.wave_started", &[("wave", &level)]);
+                ecs.world.fetch_mut::<GameLog>().add(message);
+                Ok(())
+            },
+        });
+    }
 }
 
-pub fn setup_systems(builder: &mut DispatcherBuilder) {
-    builder.add(AsteroidFieldSystem, "", &[]);
+/// Spawns asteroids of the item in random lanes of the current wave (ex. for testing)
+fn spawn_asteroids(world: &World, item: GameItem, count: usize) -> Result<(), String> {
+    let entities = world.entities();
+    let lazy_update = world.fetch::<LazyUpdate>();
+    let meshes = world.fetch::<ObjectMeshes>();
+    let frustum = world.fetch::<Camera>().frustum();
+    let ship_bounds: Vec<Bounds> = world
+        .read_component::<Ship>()
+        .join()
+        .filter_map(Ship::bounds)
+        .collect();
+    let mut fields = world.write_component::<AsteroidField>();
+    let field = (&mut fields)
+        .join()
+        .next()
+        .ok_or_else(|| String::from("There is no asteroid field"))?;
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..count {
+        let lane = SpawnLane::pick(&mut rng, AsteroidField::stray_chance_at(field.level));
+        let mut positions = [(0.0, 0.0); AsteroidField::MAX_SPAWN_ATTEMPTS];
+        for position in &mut positions {
+            *position = lane.random_position(&mut rng);
+        }
+        let position =
+            AsteroidField::spawn_position(field.x_range, &positions, &ship_bounds, &frustum);
+        let asteroid = Asteroid {
+            primary: item,
+            secondary: item,
+            volatile: false,
+            toughness: 1.0,
+            debris: false,
+            seed: rng.gen(),
+        };
+        let entity = objects::build_asteroid(
+            &meshes,
+            lazy_update.create_entity(&entities),
+            asteroid,
+            position,
+            rng.gen_range(0.0..crate::PI * 2.0),
+        );
+        field.asteroids.push(entity);
+    }
+    Ok(())
 }
 
-pub fn init_world(world: &mut World) {
+/// Creates the asteroid field, starting at the given wave
+fn init_world(world: &mut World, seed: u64, level: u16, modifiers: DifficultyModifiers) {
     world
         .create_entity()
         .with(AsteroidField {
             asteroids: Vec::new(),
-            tick: 0,
-            spawn_time: 200,
+            level,
+            level_time: 0.0,
             x_range: 30.0,
+            seed,
+            plan: WavePlanner::new(seed, level, modifiers).finish(),
+            next_wave: None,
+            paused: false,
         })
         .build();
 }
 
+/// Where asteroids come from. Every asteroid of a wave is assigned a lane when the wave
+/// is planned, so the next wave can be previewed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpawnLane {
+    /// Passes beside the ship
+    Left,
+    /// Passes beside the ship, on the other side
+    Right,
+    /// Flies low through the ship
+    Stray,
+}
+
+impl SpawnLane {
+    pub const ALL: [SpawnLane; 3] = [SpawnLane::Left, SpawnLane::Right, SpawnLane::Stray];
+    /// The height of the center of the Left and Right lanes
+    pub const HEIGHT: f32 = 7.5;
+
+    fn pick(rng: &mut impl Rng, stray_chance: f64) -> SpawnLane {
+        if rng.gen_bool(stray_chance) {
+            SpawnLane::Stray
+        } else if rng.gen::<bool>() {
+            SpawnLane::Right
+        } else {
+            SpawnLane::Left
+        }
+    }
+
+    /// Returns the y and z position of an asteroid in the lane
+    fn random_position(self, rng: &mut impl Rng) -> (f32, f32) {
+        match self {
+            SpawnLane::Left => (rng.gen_range(-15.0..-5.0), rng.gen_range(5.0..10.0)),
+            SpawnLane::Right => (rng.gen_range(9.0..19.0), rng.gen_range(5.0..10.0)),
+            SpawnLane::Stray => (rng.gen_range(-1.0..8.0), rng.gen_range(0.5..1.5)),
+        }
+    }
+
+    /// The center of the lane at `x`
+    pub fn center(self, x: f32) -> Vector3<f32> {
+        match self {
+            SpawnLane::Left => Vector3::new(x, -10.0, SpawnLane::HEIGHT),
+            SpawnLane::Right => Vector3::new(x, 14.0, SpawnLane::HEIGHT),
+            SpawnLane::Stray => Vector3::new(x, 3.5, 1.0),
+        }
+    }
+}
+
+/// An asteroid of a wave, with everything that was randomly picked for it
+struct PlannedSpawn {
+    /// The seconds into the wave that the asteroid spawns at
+    time: f32,
+    lane: SpawnLane,
+    asteroid: Asteroid,
+    rotation: f32,
+    /// The y and z positions that are tried in order, see AsteroidField::spawn_position
+    positions: [(f32, f32); AsteroidField::MAX_SPAWN_ATTEMPTS],
+}
+
+/// Everything that spawns during a wave. The plan only depends on the seed, the level and
+/// the modifiers it was planned with (see DifficultyDirector), so a retried wave spawns the
+/// same asteroids at the same times.
+struct WavePlan {
+    /// The asteroids that have not spawned yet, in the order they spawn
+    spawns: VecDeque<PlannedSpawn>,
+    /// The lane and seed of the wave's raider
+    raider: Option<(SpawnLane, u64)>,
+    modifiers: DifficultyModifiers,
+}
+
+impl WavePlan {
+    fn lane_counts(&self) -> [u32; 3] {
+        let mut counts = [0; 3];
+        for spawn in &self.spawns {
+            counts[spawn.lane as usize] += 1;
+        }
+        counts
+    }
+}
+
+/// Plans a wave a few asteroids at a time during the wave before it, so the update that
+/// starts the wave does not have to plan all of it at once
+struct WavePlanner {
+    rng: StdRng,
+    level: u16,
+    /// How many asteroids the wave has
+    spawn_count: usize,
+    plan: WavePlan,
+}
+
+impl WavePlanner {
+    /// How many asteroids are planned each update
+    const SPAWNS_PER_UPDATE: usize = 4;
+
+    fn new(seed: u64, level: u16, modifiers: DifficultyModifiers) -> Self {
+        let mut rng = AsteroidField::wave_rng(seed, level);
+        let raider = if level >= Raider::FIRST_LEVEL {
+            let lane = *SpawnLane::ALL.iter().choose(&mut rng).unwrap();
+            Some((lane, rng.gen()))
+        } else {
+            None
+        };
+        let duration = if level == AsteroidField::MAX_LEVEL {
+            AsteroidField::FINAL_WAVE_TIME
+        } else {
+            AsteroidField::LEVEL_TIME
+        };
+        // An asteroid spawns at the start of the wave, and then once every interval
+        let interval = AsteroidField::spawn_interval_at(level) * modifiers.spawn_interval;
+        let spawn_count = ((duration - Time::SLACK) / interval).ceil() as usize;
+
+        Self {
+            rng,
+            level,
+            spawn_count,
+            plan: WavePlan {
+                spawns: VecDeque::with_capacity(spawn_count),
+                raider,
+                modifiers,
+            },
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.plan.spawns.len() >= self.spawn_count
+    }
+
+    /// Plans up to `count` more asteroids
+    fn step(&mut self, count: usize) {
+        let modifiers = self.plan.modifiers;
+        let interval = AsteroidField::spawn_interval_at(self.level) * modifiers.spawn_interval;
+        let stray_chance =
+            (AsteroidField::stray_chance_at(self.level) * modifiers.stray_chance).min(1.0);
+        let volatile_chance = (Asteroid::VOLATILE_CHANCE * modifiers.volatile_chance).min(1.0);
+        let rng = &mut self.rng;
+
+        for _ in 0..count {
+            let index = self.plan.spawns.len();
+            if index >= self.spawn_count {
+                break;
+            }
+
+            let lane = SpawnLane::pick(rng, stray_chance);
+            let asteroid = Asteroid {
+                primary: *GameItem::ores().choose(rng).unwrap(),
+                secondary: *GameItem::ores().choose(rng).unwrap(),
+                volatile: rng.gen_bool(volatile_chance),
+                toughness: modifiers.asteroid_health,
+                debris: false,
+                seed: rng.gen(),
+            };
+            let rotation = rng.gen_range(0.0..crate::PI * 2.0);
+            let mut positions = [(0.0, 0.0); AsteroidField::MAX_SPAWN_ATTEMPTS];
+            for position in &mut positions {
+                *position = lane.random_position(rng);
+            }
+
+            self.plan.spawns.push_back(PlannedSpawn {
+                time: index as f32 * interval,
+                lane,
+                asteroid,
+                rotation,
+                positions,
+            });
+        }
+    }
+
+    /// Plans the rest of the wave at once (ex. when the wave starts before it was planned)
+    fn finish(mut self) -> WavePlan {
+        self.step(self.spawn_count);
+        self.plan
+    }
+}
+
+/// The lanes of the next wave, which is shown during the last seconds of the current wave
+pub struct WavePreview {
+    /// The seconds left until the wave starts
+    pub time_left: f32,
+    counts: [u32; 3],
+}
+
+impl WavePreview {
+    /// How many seconds before the next wave starts it is previewed
+    pub const TIME: f32 = 8.0;
+    /// Where along the lanes the preview is shown, so it is in view of the ship
+    pub const X: f32 = -12.0;
+    const COLOR: Vector3<f32> = Vector3::new(0.3, 0.8, 1.0);
+
+    pub fn count(&self, lane: SpawnLane) -> u32 {
+        self.counts[lane as usize]
+    }
+
+    /// The fraction of the wave's asteroids that come from the lane
+    pub fn share(&self, lane: SpawnLane) -> f32 {
+        let total: u32 = self.counts.iter().sum();
+        self.count(lane) as f32 / total.max(1) as f32
+    }
+}
+
 #[derive(Component)]
 #[storage(HashMapStorage)]
-struct AsteroidField {
+pub struct AsteroidField {
     asteroids: Vec<Entity>,
-    tick: u16,
-    spawn_time: u16,
-    x_range: f32,
+    /// The current wave. Asteroids spawn faster and are more likely to hit the ship
+    /// at higher levels.
+    pub level: u16,
+    /// The seconds since the level started
+    level_time: f32,
+    /// Asteroids are despawned once they are outside of -x_range..x_range
+    pub x_range: f32,
+    seed: u64,
+    /// What is left to spawn in the current wave
+    plan: WavePlan,
+    /// The next wave, which is planned during the current wave
+    next_wave: Option<WavePlanner>,
+    /// Set while a trader visits. The wave's clock is stopped, so nothing spawns.
+    pub paused: bool,
+}
+
+impl AsteroidField {
+    pub const MAX_LEVEL: u16 = 30;
+    /// The seconds each wave lasts
+    const LEVEL_TIME: f32 = 20.0;
+    /// How many seconds the final wave needs to be survived to win
+    pub const FINAL_WAVE_TIME: f32 = 60.0;
+    /// The seconds between asteroid spawns during the first and the final wave
+    const FIRST_SPAWN_INTERVAL: f32 = 3.35;
+    const LAST_SPAWN_INTERVAL: f32 = 0.85;
+    /// The chance of an asteroid being aimed at the ship during the final wave
+    const MAX_STRAY_CHANCE: f64 = 0.5;
+    /// How many positions are tried before an asteroid is spawned farther away
+    const MAX_SPAWN_ATTEMPTS: usize = 8;
+    /// The most asteroids that the spawn_asteroid command spawns at once
+    const MAX_DEBUG_SPAWNS: i64 = 20;
+    /// How much farther away asteroids spawn when every position was visible (asteroids
+    /// past -x_range are only despawned once they are this much farther away)
+    pub const FALLBACK_DISTANCE: f32 = 20.0;
+
+    /// Each wave has its own stream, so a wave spawns the same asteroids no matter how
+    /// the earlier waves went
+    fn wave_rng(seed: u64, level: u16) -> StdRng {
+        StdRng::seed_from_u64(seed ^ (level as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Starts the wave with its planned asteroids. The modifiers are only used if the wave
+    /// was not planned yet.
+    fn start_wave(&mut self, level: u16, modifiers: DifficultyModifiers) {
+        let planner = self
+            .next_wave
+            .take()
+            .filter(|planner| planner.level == level)
+            .unwrap_or_else(|| WavePlanner::new(self.seed, level, modifiers));
+        self.level = level;
+        self.level_time = 0.0;
+        self.plan = planner.finish();
+    }
+
+    fn is_previewing(&self) -> bool {
+        self.level < AsteroidField::MAX_LEVEL
+            && self.level_time >= AsteroidField::LEVEL_TIME - WavePreview::TIME - Time::SLACK
+    }
+
+    /// Returns the preview of the next wave, if it is about to start
+    pub fn wave_preview(&self) -> Option<WavePreview> {
+        let planner = self
+            .next_wave
+            .as_ref()
+            .filter(|planner| self.is_previewing() && planner.is_done())?;
+
+        Some(WavePreview {
+            time_left: (AsteroidField::LEVEL_TIME - self.level_time).max(0.0),
+            counts: planner.plan.lane_counts(),
+        })
+    }
+
+    /// Picks where an asteroid spawns. Positions that overlap a ship or can be seen by the
+    /// camera are skipped, so asteroids don't pop in on screen.
+    fn spawn_position(
+        x_range: f32,
+        positions: &[(f32, f32)],
+        ship_bounds: &[Bounds],
+        frustum: &Frustum,
+    ) -> Vector3<f32> {
+        let radius = Asteroid::COLLIDER_RADIUS;
+        let mut position = Vector3::new(-x_range, 0.0, 0.0);
+
+        for (pos_y, pos_z) in positions.iter().copied() {
+            position = Vector3::new(-x_range, pos_y, pos_z);
+
+            let overlaps_ship = ship_bounds
+                .iter()
+                .any(|bounds| bounds.intersects_sphere(position, radius));
+            if !overlaps_ship && !frustum.intersects_sphere(position, radius) {
+                return position;
+            }
+        }
+
+        position.x -= AsteroidField::FALLBACK_DISTANCE;
+        position
+    }
+
+    fn spawn_interval_at(level: u16) -> f32 {
+        let range = AsteroidField::FIRST_SPAWN_INTERVAL - AsteroidField::LAST_SPAWN_INTERVAL;
+        AsteroidField::FIRST_SPAWN_INTERVAL
+            - range * (level - 1) as f32 / (AsteroidField::MAX_LEVEL - 1) as f32
+    }
+
+    fn stray_chance_at(level: u16) -> f64 {
+        AsteroidField::MAX_STRAY_CHANCE * (level - 1) as f64 / (AsteroidField::MAX_LEVEL - 1) as f64
+    }
+
+    /// Whether destroying the asteroid clears the current wave: every asteroid of the wave
+    /// has spawned, and the others were already destroyed
+    pub fn is_cleared_by(&self, asteroid: Entity, destroyed: impl Fn(Entity) -> bool) -> bool {
+        self.plan.spawns.is_empty()
+            && self.asteroids.contains(&asteroid)
+            && self
+                .asteroids
+                .iter()
+                .all(|other| *other == asteroid || destroyed(*other))
+    }
+
+    /// Returns the seconds left in the final wave, or None if it has not started yet
+    pub fn final_wave_remaining(&self) -> Option<f32> {
+        if self.level == AsteroidField::MAX_LEVEL {
+            Some((AsteroidField::FINAL_WAVE_TIME - self.level_time).max(0.0))
+        } else {
+            None
+        }
+    }
 }
 
-struct AsteroidFieldSystem;
+#[derive(Default)]
+struct AsteroidFieldSystem {
+    /// Reused every update, so the bounds are not allocated every update
+    ship_bounds: Vec<Bounds>,
+}
 
 impl<'a> System<'a> for AsteroidFieldSystem {
     type SystemData = (
         Entities<'a>,
         Read<'a, LazyUpdate>,
+        ReadExpect<'a, Time>,
         Write<'a, ToBeRemoved>,
         ReadStorage<'a, Transform>,
         WriteStorage<'a, AsteroidField>,
+        ReadStorage<'a, Ship>,
+        ReadExpect<'a, Camera>,
         ReadExpect<'a, ObjectMeshes>,
+        ReadExpect<'a, Strings>,
+        WriteExpect<'a, GameLog>,
+        WriteExpect<'a, WaveCheckpoint>,
+        ReadExpect<'a, Inventory>,
+        ReadExpect<'a, TechTree>,
+        ReadExpect<'a, GameStats>,
+        ReadExpect<'a, DifficultyDirector>,
+        Write<'a, LineBatch>,
+        Option<Write<'a, CameraController>>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, lazy_update, mut to_be_removed, transforms, mut fields, meshes) = data;
+        let (
+            entities,
+            lazy_update,
+            time,
+            mut to_be_removed,
+            transforms,
+            mut fields,
+            ships,
+            camera,
+            meshes,
+            strings,
+            mut log,
+            mut checkpoint,
+            inventory,
+            tech_tree,
+            stats,
+            director,
+            mut line_batch,
+            mut camera_controller,
+        ) = data;
+
+        let ship_bounds = &mut self.ship_bounds;
+        ship_bounds.clear();
+        ship_bounds.extend(ships.join().filter_map(Ship::bounds));
+        let ship_bounds = &*ship_bounds;
 
         for field in (&mut fields).join() {
             field
@@ -61,41 +587,670 @@ impl<'a> System<'a> for AsteroidFieldSystem {
                 .asteroids
                 .iter()
                 .filter(|asteroid| {
-                    transforms.get(**asteroid).unwrap().position.x.abs() > field.x_range
+                    // Asteroids spawned between updates (ex. by a command) are only added to
+                    // the world at the end of the next update
+                    let x = transforms
+                        .get(**asteroid)
+                        .map(|transform| transform.position.x);
+                    matches!(x, Some(x) if x > field.x_range
+                        || x < -field.x_range - AsteroidField::FALLBACK_DISTANCE)
                 })
                 .for_each(|asteroid| to_be_removed.add(*asteroid));
 
-            if field.tick > 0 {
-                field.tick -= 1;
-            } else {
-                field.tick = field.spawn_time;
-
-                let mut rng = rand::thread_rng();
-                let item = GameItem::iter().choose(&mut rng).unwrap();
-                let pos_y: f32 =
-                    rng.gen_range(-5.0..5.0) + if rng.gen::<bool>() { 14.0 } else { -10.0 };
-                let mut transform =
-                    Transform::from_position(-field.x_range, pos_y, rng.gen_range(5.0..10.0));
-                transform.set_rotation_z(rng.gen_range(0.0..crate::PI * 2.0));
-                // TODO: Hide Spawning from Camera
-                // TODO: Never Spawn collision with ship!
-                let entity = lazy_update
-                    .create_entity(&entities)
-                    .with(transform)
-                    .with(Model::new(*meshes.asteroids.get(item).unwrap()))
-                    .with(RigidBody {
-                        velocity: Vector3::new(Asteroid::VELOCITY, 0.0, 0.0),
+            // Raiders spawn on the update that passes their spawn time
+            let raider_due = field.level_time < Raider::SPAWN_TIME - Time::SLACK;
+            if !field.paused {
+                field.level_time += time.delta;
+            }
+            let raider_due = raider_due && field.level_time >= Raider::SPAWN_TIME - Time::SLACK;
+            if field.level < AsteroidField::MAX_LEVEL
+                && field.level_time >= AsteroidField::LEVEL_TIME - Time::SLACK
+            {
+                field.start_wave(field.level + 1, director.modifiers());
+                let drone_ships = ships
+                    .join()
+                    .filter_map(|ship| match ship.kind() {
+                        ShipKind::Drone { origin } => Some(origin),
+                        ShipKind::Mothership => None,
                     })
-                    .with(Collider::new(
-                        Hitbox::with_shape(ColliderShape::Sphere(Asteroid::COLLIDER_RADIUS)),
-                        Collider::ASTEROID,
-                        vec![Collider::SHIP, Collider::MISSLE],
-                    ))
-                    .with(Asteroid(*item))
-                    .with(Health(Asteroid::HEALTH))
-                    .build();
+                    .collect();
+                *checkpoint = WaveCheckpoint::new(
+                    field.seed,
+                    field.level,
+                    &inventory,
+                    &tech_tree,
+                    &stats,
+                    drone_ships,
+                );
+                checkpoint.ship_pending = true;
+                checkpoint.difficulty = field.plan.modifiers;
+
+                if field.level == AsteroidField::MAX_LEVEL {
+                    log.add(strings.format(
+                        "log.final_wave",
+                        &[("time", &AsteroidField::FINAL_WAVE_TIME)],
+                    ));
+                } else {
+                    log.add(strings.format("log.wave_started", &[("wave", &field.level)]));
+                }
+            }
+
+            // The next wave is planned a few asteroids at a time, and finished at once if it
+            // is not done by the time it is previewed
+            if field.level < AsteroidField::MAX_LEVEL {
+                let (seed, level) = (field.seed, field.level + 1);
+                let previewing = field.is_previewing();
+                let planner = field
+                    .next_wave
+                    .get_or_insert_with(|| WavePlanner::new(seed, level, director.modifiers()));
+                if previewing {
+                    planner.step(planner.spawn_count);
+                } else {
+                    planner.step(WavePlanner::SPAWNS_PER_UPDATE);
+                }
+            }
+
+            if let Some(preview) = field.wave_preview().filter(|_| crate::WAVE_PREVIEW) {
+                add_wave_preview_arrows(&mut line_batch, &preview);
+            }
+
+            if raider_due {
+                if let Some((lane, seed)) = field.plan.raider.take() {
+                    raider::build_raider(
+                        &meshes,
+                        lazy_update.create_entity(&entities),
+                        lane.center(-field.x_range),
+                        seed,
+                    );
+                    log.add(strings.get("log.raider_approaching").to_string());
+                }
+            }
+
+            // Spawning only carries out the plan. The times are measured from the start of
+            // the wave, so the spawns don't drift at any tick rate.
+            while matches!(field.plan.spawns.front(),
+                Some(spawn) if spawn.time <= field.level_time + Time::SLACK)
+            {
+                let spawn = field.plan.spawns.pop_front().unwrap();
+                let position = AsteroidField::spawn_position(
+                    field.x_range,
+                    &spawn.positions,
+                    ship_bounds,
+                    &camera.frustum(),
+                );
+                let volatile = spawn.asteroid.volatile;
+                let entity = objects::build_asteroid(
+                    &meshes,
+                    lazy_update.create_entity(&entities),
+                    spawn.asteroid,
+                    position,
+                    spawn.rotation,
+                );
                 field.asteroids.push(entity);
+                // Volatile asteroids set the ship on fire, so the camera shows them coming
+                if let Some(controller) = camera_controller.as_mut().filter(|_| volatile) {
+                    controller.focus(entity);
+                }
+            }
+        }
+    }
+}
+
+/// Draws an arrow along each lane of the next wave, which is wider for busier lanes
+fn add_wave_preview_arrows(line_batch: &mut LineBatch, preview: &WavePreview) {
+    for lane in SpawnLane::ALL
+        .iter()
+        .filter(|lane| preview.count(**lane) > 0)
+    {
+        let center = lane.center(WavePreview::X);
+        let tip = center + Vector3::new(1.5, 0.0, 0.0);
+        let width = 0.05 + 0.15 * preview.share(*lane);
+        let arrow = [
+            (center - Vector3::new(1.5, 0.0, 0.0), tip),
+            (tip - Vector3::new(0.8, 0.6, 0.0), tip),
+            (tip - Vector3::new(0.8, -0.6, 0.0), tip),
+        ];
+
+        for (start, end) in arrow.iter() {
+            line_batch.add(Line::new(*start, *end, WavePreview::COLOR).with_width(width));
+        }
+    }
+}
+
+/// Creates translucent portals at the lanes of the next wave. The portals are sized by
+/// how many asteroids come from the lane, and pulse faster as the wave gets closer.
+pub fn build_wave_preview(world: &World) -> Vec<GPUBillboard> {
+    let fields = world.read_component::<AsteroidField>();
+    let preview = match fields.join().next().and_then(AsteroidField::wave_preview) {
+        Some(preview) if crate::WAVE_PREVIEW => preview,
+        _ => return Vec::new(),
+    };
+
+    let elapsed = WavePreview::TIME - preview.time_left.min(WavePreview::TIME);
+    let urgency = elapsed / WavePreview::TIME;
+    let pulse = if crate::REDUCED_MOTION {
+        1.0
+    } else {
+        0.75 + 0.25 * (elapsed * (3.0 + 15.0 * urgency)).sin()
+    };
+
+    SpawnLane::ALL
+        .iter()
+        .filter(|lane| preview.count(**lane) > 0)
+        .map(|lane| {
+            let size = 1.0 + 3.0 * preview.share(*lane);
+            GPUBillboard {
+                pos: lane.center(WavePreview::X),
+                offset: Vector2::new(-size / 2.0, -size / 2.0),
+                size: Vector2::new(size, size),
+                color: WavePreview::COLOR.extend(0.3 * pulse),
+            }
+        })
+        .collect()
+}
+
+/// Marks asteroids that will hit the ship if their velocity does not change
+#[derive(Component, Default)]
+#[storage(NullStorage)]
+pub struct IncomingThreat;
+
+#[derive(Default)]
+struct ThreatSystem {
+    /// Reused every update, so the bounds are not allocated every update
+    ship_bounds: Vec<Bounds>,
+}
+
+impl ThreatSystem {
+    /// Checks if the ray hits the box (using the slab method)
+    fn ray_hits_box(
+        origin: Vector3<f32>,
+        direction: Vector3<f32>,
+        min: Vector3<f32>,
+        max: Vector3<f32>,
+    ) -> bool {
+        let mut enter: f32 = 0.0;
+        let mut exit = f32::INFINITY;
+
+        for axis in 0..3 {
+            if direction[axis] == 0.0 {
+                if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                    return false;
+                }
+                continue;
+            }
+
+            let t1 = (min[axis] - origin[axis]) / direction[axis];
+            let t2 = (max[axis] - origin[axis]) / direction[axis];
+            enter = enter.max(t1.min(t2));
+            exit = exit.min(t1.max(t2));
+        }
+
+        enter <= exit
+    }
+}
+
+impl<'a> System<'a> for ThreatSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, RigidBody>,
+        ReadStorage<'a, Asteroid>,
+        ReadStorage<'a, Ship>,
+        WriteStorage<'a, IncomingThreat>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, transforms, bodies, asteroids, ships, mut threats) = data;
+
+        // Every ship can be hit, from the floor of the lowest deck to the top of the highest
+        let ship_bounds = &mut self.ship_bounds;
+        ship_bounds.clear();
+        ship_bounds.extend(ships.join().filter_map(Ship::bounds).map(|bounds| Bounds {
+            min: Vector3::new(bounds.min.x, bounds.min.y, 0.0),
+            max: Vector3::new(bounds.max.x, bounds.max.y, Ship::TOP),
+        }));
+        let radius = Vector3::new(1.0, 1.0, 1.0) * Asteroid::COLLIDER_RADIUS;
+
+        for (entity, transform, body, _) in (&entities, &transforms, &bodies, &asteroids).join() {
+            let threatening = ship_bounds.iter().any(|bounds| {
+                ThreatSystem::ray_hits_box(
+                    transform.position,
+                    body.velocity,
+                    bounds.min - radius,
+                    bounds.max + radius,
+                )
+            });
+
+            if threatening {
+                threats
+                    .insert(entity, IncomingThreat)
+                    .expect("Unable to mark incoming threat");
+            } else {
+                threats.remove(entity);
+            }
+        }
+    }
+}
+
+/// Messages about what happened in the game (ex. a block being destroyed), shown in the event log
+#[derive(Default)]
+pub struct GameLog {
+    entries: VecDeque<String>,
+}
+
+impl GameLog {
+    /// The oldest messages are removed once there are more than this
+    pub const MAX_ENTRIES: usize = 6;
+
+    pub fn add(&mut self, message: String) {
+        if self.entries.len() == GameLog::MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(message);
+    }
+
+    /// Returns the message `index` messages before the newest one
+    pub fn get_recent(&self, index: usize) -> Option<&str> {
+        self.entries
+            .len()
+            .checked_sub(index + 1)
+            .and_then(|index| self.entries.get(index))
+            .map(String::as_str)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameState {
+    Playing,
+    /// The final wave was survived
+    Won,
+    /// The ship's core, or every block with health, was destroyed
+    Lost,
+}
+
+/// Statistics of the current game, which are shown once it ends
+#[derive(Clone, Default)]
+pub struct GameStats {
+    pub asteroids_destroyed: u32,
+    pub raiders_destroyed: u32,
+    /// Raiders that retreated before they were destroyed
+    pub raiders_driven_off: u32,
+    pub items_mined: u32,
+    /// In seconds
+    pub time_survived: f32,
+    /// The time survived when each wave started, starting with the first wave
+    pub wave_starts: Vec<f32>,
+}
+
+/// The state of the game at the start of the current wave, which is restored to retry it
+#[derive(Clone)]
+pub struct WaveCheckpoint {
+    pub seed: u64,
+    pub level: u16,
+    pub inventory: Inventory,
+    pub tech_tree: TechTree,
+    pub stats: GameStats,
+    /// Where the drone ships were deployed, so they are deployed again
+    pub drone_ships: Vec<Point2<i16>>,
+    /// The mothership at the start of the wave, which is built again when the wave is
+    /// retried. The usual starting ship is built when there is none.
+    pub ship: Option<SharedShip>,
+    /// Set when the wave starts, until the mothership has been copied (see capture_ship)
+    ship_pending: bool,
+    /// The modifiers that the wave was planned with, so a retried wave is the same
+    pub difficulty: DifficultyModifiers,
+}
+
+impl WaveCheckpoint {
+    pub fn new(
+        seed: u64,
+        level: u16,
+        inventory: &Inventory,
+        tech_tree: &TechTree,
+        stats: &GameStats,
+        drone_ships: Vec<Point2<i16>>,
+    ) -> Self {
+        Self {
+            seed,
+            level,
+            inventory: inventory.clone(),
+            tech_tree: tech_tree.clone(),
+            stats: stats.clone(),
+            drone_ships,
+            ship: None,
+            ship_pending: false,
+            difficulty: DifficultyModifiers::NEUTRAL,
+        }
+    }
+}
+
+/// Copies the mothership into the checkpoint of a wave that just started. Copying needs the
+/// whole world, so this is called after the systems have run.
+pub fn capture_ship(world: &mut World) {
+    if !world.fetch::<WaveCheckpoint>().ship_pending {
+        return;
+    }
+    // The retried wave could not be started without a core
+    let ship = SharedShip::capture(world).filter(|ship| ship.has_core(&world.fetch()));
+    let mut checkpoint = world.write_resource::<WaveCheckpoint>();
+    checkpoint.ship = ship;
+    checkpoint.ship_pending = false;
+}
+
+struct GameStateSystem;
+
+impl<'a> System<'a> for GameStateSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Time>,
+        WriteExpect<'a, GameState>,
+        WriteExpect<'a, GameStats>,
+        WriteExpect<'a, StatEvents>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, AsteroidField>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            time,
+            mut state,
+            mut stats,
+            mut stat_events,
+            ships,
+            block_entities,
+            healths,
+            fields,
+        ) = data;
+        if *state != GameState::Playing {
+            return;
+        }
+
+        for field in fields.join() {
+            if stats.wave_starts.len() < field.level as usize {
+                let start = stats.time_survived;
+                stats.wave_starts.push(start);
+                stat_events.push(StatEvent::WaveStarted(field.level));
+            }
+        }
+        stats.time_survived += time.delta;
+
+        let is_alive = |entity: Entity| {
+            entities.is_alive(entity)
+                && matches!(healths.get(entity), Some(health) if health.health() > 0)
+        };
+        // Drone ships can be lost, but the game ends with the mothership
+        let core_destroyed = matches!(ship::mothership(&ships).and_then(Ship::core),
+            Some(core) if !is_alive(core));
+        let no_blocks_left = !(&entities, &block_entities, &healths)
+            .join()
+            .any(|(_, _, health)| health.health() > 0);
+
+        if core_destroyed || no_blocks_left {
+            *state = GameState::Lost;
+        } else if fields
+            .join()
+            .any(|field| matches!(field.final_wave_remaining(), Some(remaining) if remaining <= Time::SLACK))
+        {
+            *state = GameState::Won;
+            stat_events.push(StatEvent::FinalWaveSurvived);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{headless::HeadlessEcs, Tile};
+    use crate::graphics::{CameraShake, Projection};
+    use cgmath::Point3;
+    use std::collections::HashSet;
+
+    /// The director is turned off, so the waves only depend on the seed
+    fn fixed_run(seed: u64) -> HeadlessEcs {
+        let headless = HeadlessEcs::with_seed(seed);
+        headless
+            .ecs
+            .get_resource_mut::<DifficultyDirector>()
+            .enabled = false;
+        headless
+    }
+
+    const X_RANGE: f32 = 5.0;
+
+    /// Looks straight down at the origin, and sees from -10 to 10 on both axes
+    fn top_down_frustum() -> Frustum {
+        Camera {
+            position: (0.0, 0.0, 50.0).into(),
+            yaw: 0.0,
+            pitch: 0.0,
+            aspect: 1.0,
+            fov: 45.0,
+            near: 0.1,
+            far: 100.0,
+            projection: Projection::Orthographic { half_height: 10.0 },
+            shake: CameraShake::NONE,
+        }
+        .frustum()
+    }
+
+    #[test]
+    fn spawn_skips_positions_that_overlap_a_ship() {
+        let ship = Bounds {
+            min: Vector3::new(-7.0, -32.0, -1.0),
+            max: Vector3::new(-3.0, -28.0, 1.0),
+        };
+        let positions = [(-30.0, 0.0), (30.0, 0.0)];
+        let position =
+            AsteroidField::spawn_position(X_RANGE, &positions, &[ship], &top_down_frustum());
+        assert_eq!(position, Vector3::new(-X_RANGE, 30.0, 0.0));
+    }
+
+    #[test]
+    fn spawn_skips_positions_the_camera_sees() {
+        let frustum = top_down_frustum();
+        assert!(frustum.intersects_sphere(Vector3::new(-X_RANGE, 0.0, 0.0), 0.0));
+
+        let positions = [(0.0, 0.0), (30.0, 0.0)];
+        let position = AsteroidField::spawn_position(X_RANGE, &positions, &[], &frustum);
+        assert_eq!(position, Vector3::new(-X_RANGE, 30.0, 0.0));
+        assert!(!frustum.intersects_sphere(position, Asteroid::COLLIDER_RADIUS));
+    }
+
+    #[test]
+    fn spawn_falls_back_farther_out_when_every_position_is_excluded() {
+        let positions = [(0.0, 0.0), (-5.0, 1.0)];
+        let position = AsteroidField::spawn_position(X_RANGE, &positions, &[], &top_down_frustum());
+        let x = -X_RANGE - AsteroidField::FALLBACK_DISTANCE;
+        assert_eq!(position, Vector3::new(x, -5.0, 1.0));
+    }
+
+    /// The seed, resources and candidate positions (in thousandths) of a planned asteroid
+    type PlannedAsteroid = (u64, GameItem, GameItem, bool, Vec<[i32; 2]>);
+
+    fn planned(plan: &WavePlan) -> Vec<PlannedAsteroid> {
+        plan.spawns
+            .iter()
+            .map(|spawn| {
+                let positions = spawn
+                    .positions
+                    .iter()
+                    .map(|(y, z)| [(y * 1000.0) as i32, (z * 1000.0) as i32])
+                    .collect();
+                let asteroid = &spawn.asteroid;
+                (
+                    asteroid.seed,
+                    asteroid.primary,
+                    asteroid.secondary,
+                    asteroid.volatile,
+                    positions,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn planning_in_steps_matches_planning_at_once() {
+        let modifiers = DifficultyModifiers::NEUTRAL;
+        let at_once = WavePlanner::new(9, 4, modifiers).finish();
+
+        let mut planner = WavePlanner::new(9, 4, modifiers);
+        while !planner.is_done() {
+            let before = planner.plan.spawns.len();
+            planner.step(WavePlanner::SPAWNS_PER_UPDATE);
+            assert!(planner.plan.spawns.len() - before <= WavePlanner::SPAWNS_PER_UPDATE);
+        }
+        let in_steps = planner.finish();
+        assert_eq!(planned(&in_steps), planned(&at_once));
+        assert_eq!(in_steps.raider, at_once.raider);
+        let times = |plan: &WavePlan| {
+            plan.spawns
+                .iter()
+                .map(|spawn| spawn.time)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(times(&in_steps), times(&at_once));
+    }
+
+    fn level(headless: &HeadlessEcs) -> u16 {
+        headless.with_asteroid_field(|field| field.level).unwrap()
+    }
+
+    fn step_to_wave(headless: &mut HeadlessEcs, wave: u16) {
+        while level(headless) < wave {
+            headless.step(1);
+        }
+    }
+
+    /// The asteroids that spawn during the first seconds of the current wave, in the order
+    /// they spawn. Each is its seed, resources and where it spawned (in thousandths).
+    fn record_spawns(
+        headless: &mut HeadlessEcs,
+        seconds: u32,
+    ) -> Vec<(u64, GameItem, GameItem, bool, [i32; 3])> {
+        let mut seen: HashSet<Entity> = headless
+            .with_asteroid_field(|field| field.asteroids.iter().copied().collect())
+            .unwrap();
+        let mut spawns = Vec::new();
+        for _ in 0..seconds * Time::DEFAULT_RATE {
+            headless.step(1);
+            let world = &headless.ecs.world;
+            let fields = world.read_storage::<AsteroidField>();
+            let asteroids = world.read_storage::<Asteroid>();
+            let transforms = world.read_storage::<Transform>();
+            for field in fields.join() {
+                for entity in &field.asteroids {
+                    if seen.insert(*entity) {
+                        let position = transforms.get(*entity).unwrap().position * 1000.0;
+                        let position = [position.x, position.y, position.z].map(|x| x as i32);
+                        let asteroid = asteroids.get(*entity).unwrap();
+                        spawns.push((
+                            asteroid.seed,
+                            asteroid.primary,
+                            asteroid.secondary,
+                            asteroid.volatile,
+                            position,
+                        ));
+                    }
+                }
+            }
+        }
+        spawns
+    }
+
+    #[test]
+    fn wave_spawns_do_not_depend_on_earlier_waves() {
+        let seed = 7;
+        let mut idle = fixed_run(seed);
+        step_to_wave(&mut idle, 2);
+
+        // During the first wave, another player builds more lasers and spawns extra
+        // asteroids for them to shoot
+        let mut active = fixed_run(seed);
+        active.build_block(Point3::new(7, 3, 0), "Laser");
+        active.build_block(Point3::new(3, 7, 0), "Laser");
+        spawn_asteroids(&active.ecs.world, GameItem::Copper, 4).unwrap();
+        step_to_wave(&mut active, 2);
+
+        let spawns = record_spawns(&mut idle, 4);
+        assert!(!spawns.is_empty());
+        assert_eq!(spawns, record_spawns(&mut active, 4));
+    }
+
+    #[test]
+    fn retried_wave_rebuilds_the_ship_from_its_start() {
+        let mut headless = fixed_run(3);
+        let kept = Point3::new(7, 3, 0);
+        let removed = Point3::new(3, 7, 0);
+        headless.build_block(kept, "Laser");
+        step_to_wave(&mut headless, 2);
+        headless.step(1);
+        headless.build_block(removed, "Laser");
+
+        headless.ecs.retry_wave();
+        headless.ecs.maintain();
+        assert_eq!(level(&headless), 2);
+        let gadget = |pos| headless.with_ship(|ship| ship.tile(pos).and_then(Tile::gadget));
+        assert!(gadget(kept).flatten().is_some());
+        assert!(gadget(removed).flatten().is_none());
+    }
+
+    #[test]
+    fn wave_spawns_what_was_planned() {
+        let seed = 5;
+        let plan = WavePlanner::new(seed, 1, DifficultyModifiers::NEUTRAL).finish();
+        let planned = planned(&plan);
+        assert!(!planned.is_empty());
+
+        // The second wave starts after LEVEL_TIME, so it is left out
+        let mut headless = fixed_run(seed);
+        let seconds = AsteroidField::LEVEL_TIME as u32 - 1;
+        let spawns = record_spawns(&mut headless, seconds);
+        assert_eq!(spawns.len(), planned.len());
+        for (spawn, plan) in spawns.iter().zip(&planned) {
+            assert_eq!(
+                (spawn.0, spawn.1, spawn.2, spawn.3),
+                (plan.0, plan.1, plan.2, plan.3)
+            );
+            let [_, y, z] = spawn.4;
+            assert!(
+                plan.4
+                    .iter()
+                    .any(|[py, pz]| (py - y).abs() <= 1 && (pz - z).abs() <= 1),
+                "{:?} was not planned at {:?}",
+                spawn,
+                plan.4
+            );
+        }
+    }
+
+    #[test]
+    fn next_wave_is_planned_within_the_update_budget() {
+        let mut headless = fixed_run(2);
+        let planned_count = |headless: &HeadlessEcs| {
+            headless
+                .with_asteroid_field(|field| {
+                    let planner = field.next_wave.as_ref()?;
+                    Some((planner.plan.spawns.len(), field.is_previewing()))
+                })
+                .flatten()
+        };
+
+        let mut previous = 0;
+        loop {
+            headless.step(1);
+            let (count, previewing) = planned_count(&headless).unwrap();
+            if previewing {
+                break;
             }
+            assert!(count - previous <= WavePlanner::SPAWNS_PER_UPDATE);
+            previous = count;
         }
+        // The whole wave was planned before the preview needed it
+        let done =
+            headless.with_asteroid_field(|field| field.next_wave.as_ref().unwrap().is_done());
+        assert_eq!(done, Some(true));
     }
 }
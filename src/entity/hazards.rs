@@ -0,0 +1,369 @@
+use super::{
+    gameplay::{AsteroidField, GameLog},
+    heat::HeatEvents,
+    objects::{self, Asteroid, ObjectMeshes},
+    ship::ShipKind,
+    walls, BlockEntity, GameModule, Ship, Time, ToBeRemoved,
+};
+use crate::block::Blocks;
+use crate::debug::{DebugCommand, DebugCommands};
+use crate::i18n::Strings;
+use crate::item::GameItem;
+use crate::profiler::AddProfiled;
+use cgmath::Vector3;
+use rand::{
+    rngs::StdRng,
+    seq::{IteratorRandom, SliceRandom},
+    Rng, SeedableRng,
+};
+use specs::prelude::*;
+
+/// Hazards that come every few minutes besides the asteroids (ex. solar flares). Each one is
+/// announced a few seconds before it starts, and only one happens at a time.
+pub struct HazardModule;
+
+impl GameModule for HazardModule {
+    fn register(&self, world: &mut World) {
+        world.register::<Ship>();
+        world.register::<BlockEntity>();
+        world.insert(HazardSettings::default());
+        world.insert(EventDirector::new());
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(HazardSystem, "hazard_system", &[]);
+    }
+
+    fn init(&self, world: &mut World) {
+        world.insert(EventDirector::new());
+    }
+
+    fn commands(&self, commands: &mut DebugCommands) {
+        commands.register(DebugCommand {
+            name: "call_hazard",
+            description: "Makes the next hazard start its warning now",
+            args: Vec::new(),
+            state: Some(|ecs| {
+                let director = ecs.get_resource::<EventDirector>();
+                match &director.current {
+                    Some(hazard) => format!("{:?} {:?}", hazard.kind, hazard.phase),
+                    None => format!("next hazard in {}s", director.next_in as u32),
+                }
+            }),
+            action: |ecs, _| {
+                let mut director = ecs.get_resource_mut::<EventDirector>();
+                if director.current.is_some() {
+                    return Err(String::from("A hazard is already going on"));
+                }
+                director.next_in = 0.0;
+                Ok(())
+            },
+        });
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HazardKind {
+    /// Heats the ship through the blocks that no wall covers, and stops the shields from
+    /// recharging
+    SolarFlare,
+    /// A dense wave of small and fast debris from a random direction
+    DebrisStorm,
+}
+
+impl HazardKind {
+    const ALL: [HazardKind; 2] = [HazardKind::SolarFlare, HazardKind::DebrisStorm];
+
+    pub fn name_key(self) -> &'static str {
+        match self {
+            HazardKind::SolarFlare => "hazard.solar_flare",
+            HazardKind::DebrisStorm => "hazard.debris_storm",
+        }
+    }
+
+    /// The seconds the hazard lasts once it has started
+    fn duration(self) -> f32 {
+        match self {
+            HazardKind::SolarFlare => 15.0,
+            HazardKind::DebrisStorm => 10.0,
+        }
+    }
+}
+
+/// Which hazards can happen. They are saved in the config, so the players who just want to
+/// build can turn them off.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HazardSettings {
+    pub solar_flares: bool,
+    pub debris_storms: bool,
+}
+
+impl HazardSettings {
+    pub fn allows(&self, kind: HazardKind) -> bool {
+        match kind {
+            HazardKind::SolarFlare => self.solar_flares,
+            HazardKind::DebrisStorm => self.debris_storms,
+        }
+    }
+}
+
+impl Default for HazardSettings {
+    fn default() -> Self {
+        Self {
+            solar_flares: true,
+            debris_storms: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HazardPhase {
+    /// The seconds until the hazard starts
+    Warning(f32),
+    /// The seconds until the hazard is over
+    Active(f32),
+}
+
+struct Hazard {
+    kind: HazardKind,
+    phase: HazardPhase,
+    /// Picks where the debris of a storm spawns
+    rng: StdRng,
+    /// Where a debris storm comes from, as a direction away from the ship
+    direction: Vector3<f32>,
+    /// The seconds until the next debris of a storm spawns
+    next_spawn: f32,
+}
+
+/// Schedules the hazards. They come more often at the higher waves.
+pub struct EventDirector {
+    /// The seconds of play until the next hazard is announced. The clock stops while a
+    /// trader visits.
+    next_in: f32,
+    /// How many hazards have come during the run, which seeds them
+    hazards: u32,
+    current: Option<Hazard>,
+    /// The debris of the storms, and the seconds until it is removed
+    debris: Vec<(Entity, f32)>,
+}
+
+impl EventDirector {
+    /// The seconds between hazards during the first and the final wave
+    const FIRST_INTERVAL: f32 = 150.0;
+    const LAST_INTERVAL: f32 = 60.0;
+    /// How far the intervals are randomly stretched or shortened
+    const INTERVAL_JITTER: f32 = 0.25;
+    /// The seconds between the announcement and the start of a hazard
+    const WARNING_TIME: f32 = 10.0;
+    /// The heat each exposed tile (see walls::exposed_blocks) adds each second of a flare
+    const FLARE_HEAT: f32 = 1.0;
+    /// The seconds between the spawns of a storm's debris
+    const DEBRIS_INTERVAL: f32 = 0.2;
+    /// How far from the ship the debris spawns, and how far to the sides of its path
+    const DEBRIS_DISTANCE: f32 = 32.0;
+    const DEBRIS_SPREAD: f32 = 8.0;
+    const DEBRIS_SPEED: f32 = 5.0;
+    /// Multiplies the health of the debris, like the toughness of an asteroid
+    const DEBRIS_TOUGHNESS: f32 = 0.15;
+
+    fn new() -> Self {
+        Self {
+            next_in: EventDirector::FIRST_INTERVAL,
+            hazards: 0,
+            current: None,
+            debris: Vec::new(),
+        }
+    }
+
+    /// Each hazard has its own stream, like the waves, so the hazards don't depend on
+    /// anything else that used the rng
+    fn hazard_rng(seed: u64, hazard: u32) -> StdRng {
+        StdRng::seed_from_u64(seed ^ (hazard as u64).wrapping_mul(0xbf58_476d_1ce4_e5b9))
+    }
+
+    fn interval_at(level: u16) -> f32 {
+        let range = EventDirector::FIRST_INTERVAL - EventDirector::LAST_INTERVAL;
+        EventDirector::FIRST_INTERVAL
+            - range * (level - 1) as f32 / (AsteroidField::MAX_LEVEL - 1) as f32
+    }
+
+    /// The hazard that is announced or going on, and how long until it starts or ends
+    pub fn current(&self) -> Option<(HazardKind, HazardPhase)> {
+        self.current
+            .as_ref()
+            .map(|hazard| (hazard.kind, hazard.phase))
+    }
+
+    /// Whether a solar flare is going on, which stops the shields from recharging
+    pub fn is_flaring(&self) -> bool {
+        matches!(
+            self.current(),
+            Some((HazardKind::SolarFlare, HazardPhase::Active(_)))
+        )
+    }
+}
+
+/// Announces, starts and ends the hazards, and carries out the one that is going on
+struct HazardSystem;
+
+impl<'a> System<'a> for HazardSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, LazyUpdate>,
+        ReadExpect<'a, Time>,
+        Write<'a, ToBeRemoved>,
+        ReadExpect<'a, ObjectMeshes>,
+        ReadExpect<'a, Blocks>,
+        ReadExpect<'a, Strings>,
+        ReadExpect<'a, HazardSettings>,
+        WriteExpect<'a, GameLog>,
+        WriteExpect<'a, EventDirector>,
+        WriteExpect<'a, HeatEvents>,
+        ReadStorage<'a, AsteroidField>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            lazy_update,
+            time,
+            mut to_be_removed,
+            meshes,
+            blocks,
+            strings,
+            settings,
+            mut log,
+            mut director,
+            mut heat_events,
+            fields,
+            ships,
+            block_entities,
+        ) = data;
+        let field = match fields.join().next() {
+            Some(field) => field,
+            None => return,
+        };
+
+        let director = &mut *director;
+        director
+            .debris
+            .retain(|(debris, _)| entities.is_alive(*debris));
+        for (debris, time_left) in &mut director.debris {
+            if time.count_down(time_left) {
+                to_be_removed.add(*debris);
+            }
+        }
+
+        let hazard = match &mut director.current {
+            Some(hazard) => hazard,
+            None => {
+                if field.paused || !time.count_down(&mut director.next_in) {
+                    return;
+                }
+                director.hazards += 1;
+                let mut rng = EventDirector::hazard_rng(field.seed(), director.hazards);
+                let jitter = rng.gen_range(
+                    1.0 - EventDirector::INTERVAL_JITTER..1.0 + EventDirector::INTERVAL_JITTER,
+                );
+                director.next_in = EventDirector::interval_at(field.level) * jitter;
+                let kinds: Vec<HazardKind> = HazardKind::ALL
+                    .iter()
+                    .copied()
+                    .filter(|kind| settings.allows(*kind))
+                    .collect();
+                if let Some(kind) = kinds.choose(&mut rng).copied() {
+                    let angle = rng.gen_range(0.0..crate::PI * 2.0);
+                    director.current = Some(Hazard {
+                        kind,
+                        phase: HazardPhase::Warning(EventDirector::WARNING_TIME),
+                        direction: Vector3::new(angle.cos(), angle.sin(), 0.0),
+                        next_spawn: 0.0,
+                        rng,
+                    });
+                    log.add(strings.format(
+                        "log.hazard_warning",
+                        &[
+                            ("hazard", &strings.get(kind.name_key())),
+                            ("time", &EventDirector::WARNING_TIME),
+                        ],
+                    ));
+                }
+                return;
+            }
+        };
+
+        let name = strings.get(hazard.kind.name_key());
+        match &mut hazard.phase {
+            HazardPhase::Warning(time_left) => {
+                if time.count_down(time_left) {
+                    hazard.phase = HazardPhase::Active(hazard.kind.duration());
+                    log.add(strings.format("log.hazard_started", &[("hazard", &name)]));
+                }
+                return;
+            }
+            HazardPhase::Active(time_left) => {
+                if time.count_down(time_left) {
+                    log.add(strings.format("log.hazard_over", &[("hazard", &name)]));
+                    director.current = None;
+                    return;
+                }
+            }
+        }
+
+        match hazard.kind {
+            HazardKind::SolarFlare => {
+                for (entity, ship) in (&entities, &ships).join() {
+                    let exposed = walls::exposed_blocks(ship, &blocks, &block_entities);
+                    let heat = exposed as f32 * EventDirector::FLARE_HEAT * time.delta;
+                    heat_events.push_external(entity, heat);
+                }
+            }
+            HazardKind::DebrisStorm => {
+                if !time.count_down(&mut hazard.next_spawn) {
+                    return;
+                }
+                hazard.next_spawn = EventDirector::DEBRIS_INTERVAL;
+
+                // The debris flies at the mothership from the storm's direction, spread out
+                // to the sides of its path
+                let center = ships
+                    .join()
+                    .filter(|ship| ship.kind() == ShipKind::Mothership)
+                    .find_map(Ship::bounds)
+                    .map_or(Vector3::new(0.0, 0.0, 0.0), |bounds| {
+                        (bounds.min + bounds.max) / 2.0
+                    });
+                let rng = &mut hazard.rng;
+                let side = Vector3::new(-hazard.direction.y, hazard.direction.x, 0.0);
+                let offset = rng.gen_range(-1.0..1.0) * EventDirector::DEBRIS_SPREAD;
+                let height = rng.gen_range(-1.0..3.0);
+                let position = center
+                    + hazard.direction * EventDirector::DEBRIS_DISTANCE
+                    + side * offset
+                    + Vector3::new(0.0, 0.0, height);
+                let item = *GameItem::ores().choose(rng).unwrap();
+                let debris = Asteroid {
+                    primary: item,
+                    secondary: item,
+                    volatile: false,
+                    toughness: EventDirector::DEBRIS_TOUGHNESS,
+                    debris: true,
+                    seed: rng.gen(),
+                };
+                let entity = objects::build_moving_asteroid(
+                    &meshes,
+                    lazy_update.create_entity(&entities),
+                    debris,
+                    position,
+                    rng.gen_range(0.0..crate::PI * 2.0),
+                    -hazard.direction * EventDirector::DEBRIS_SPEED,
+                );
+                // Removed once it has flown as far past the ship
+                let lifetime = EventDirector::DEBRIS_DISTANCE * 2.0 / EventDirector::DEBRIS_SPEED;
+                director.debris.push((entity, lifetime));
+            }
+        }
+    }
+}
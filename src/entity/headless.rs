@@ -0,0 +1,286 @@
+use super::{gameplay::AsteroidField, module, ship, ObjectMeshes, Ship, WindowSize, ECS};
+use crate::content::ContentErrors;
+use crate::graphics::{Camera, CameraShake, MeshManager, Projection};
+use crate::item::Inventory;
+use crate::stats::SaveFiles;
+use crate::{block, floor};
+use specs::prelude::*;
+
+/// Runs the simulation without a window or GPU (ex. for balancing). It has the same
+/// components and systems as the game, but its meshes are placeholders that are never drawn.
+/// It starts as a new player, and never saves the profile or the achievements.
+pub struct HeadlessEcs {
+    pub ecs: ECS<'static>,
+}
+
+impl HeadlessEcs {
+    const WINDOW_SIZE: WindowSize = WindowSize {
+        width: 1280.0,
+        height: 720.0,
+    };
+
+    pub fn new() -> Self {
+        HeadlessEcs::with_seed(rand::random())
+    }
+
+    /// Starts a run whose waves are planned from the seed
+    pub fn with_seed(seed: u64) -> Self {
+        let mut mesh_manager = MeshManager::headless();
+        let blocks = block::load_blocks_headless(&mut mesh_manager);
+        let floors = floor::load_floors_headless(&mut mesh_manager);
+        let meshes = ObjectMeshes::headless(&mut mesh_manager);
+        // Nothing is rendered, but the camera is still used to aim at the cursor
+        let camera = Camera {
+            position: (-18.0, 0.0, 18.0).into(),
+            yaw: 0.0,
+            pitch: -1.3,
+            aspect: HeadlessEcs::WINDOW_SIZE.width / HeadlessEcs::WINDOW_SIZE.height,
+            fov: 45.0,
+            near: 0.1,
+            far: 100.0,
+            projection: Projection::Perspective,
+            shake: CameraShake::NONE,
+        };
+
+        Self {
+            ecs: ECS::with_meshes(
+                mesh_manager,
+                meshes,
+                blocks,
+                floors,
+                camera,
+                HeadlessEcs::WINDOW_SIZE,
+                ContentErrors::default(),
+                module::default_modules(),
+                seed,
+                SaveFiles::NONE,
+            ),
+        }
+    }
+
+    /// Runs the fixed updates. Like in the game, nothing happens once the run is won or lost.
+    /// Time effects don't slow a headless run down, every tick is simulated.
+    pub fn step(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.ecs.update(true);
+        }
+    }
+
+    pub fn inventory(&self) -> specs::shred::Fetch<'_, Inventory> {
+        self.ecs.get_resource::<Inventory>()
+    }
+
+    /// Calls `f` with the mothership, or returns None if it has been destroyed
+    pub fn with_ship<R>(&self, f: impl FnOnce(&Ship) -> R) -> Option<R> {
+        ship::mothership(&self.ecs.world.read_storage::<Ship>()).map(f)
+    }
+
+    pub fn with_asteroid_field<R>(&self, f: impl FnOnce(&AsteroidField) -> R) -> Option<R> {
+        self.ecs
+            .world
+            .read_storage::<AsteroidField>()
+            .join()
+            .next()
+            .map(f)
+    }
+}
+
+/// Helpers for setting up scenes in tests
+#[cfg(test)]
+impl HeadlessEcs {
+    /// Sets how many fixed updates make a simulated second (see GraphicsSettings::TICK_RATES)
+    pub fn set_tick_rate(&mut self, rate: u32) {
+        *self.ecs.get_resource_mut::<super::Time>() = super::Time::new(rate);
+    }
+
+    /// Stops the asteroid field's waves, so only the asteroids a test spawns are in the scene
+    pub fn pause_asteroid_field(&mut self) {
+        for field in (&mut self.ecs.world.write_storage::<AsteroidField>()).join() {
+            field.paused = true;
+        }
+    }
+
+    /// Spawns an iron asteroid flying with the velocity. Its toughness multiplies its health.
+    pub fn spawn_asteroid(
+        &mut self,
+        pos: cgmath::Vector3<f32>,
+        velocity: cgmath::Vector3<f32>,
+        toughness: f32,
+    ) -> Entity {
+        self.spawn_asteroid_of(crate::item::GameItem::Iron, pos, velocity, toughness)
+    }
+
+    /// Spawns an asteroid made of only the item
+    pub fn spawn_asteroid_of(
+        &mut self,
+        item: crate::item::GameItem,
+        pos: cgmath::Vector3<f32>,
+        velocity: cgmath::Vector3<f32>,
+        toughness: f32,
+    ) -> Entity {
+        use super::objects::{self, Asteroid};
+
+        let asteroid = Asteroid {
+            primary: item,
+            secondary: item,
+            volatile: false,
+            toughness,
+            debris: false,
+            seed: 0,
+        };
+        let world = &mut self.ecs.world;
+        let entity = {
+            let lazy_update = world.fetch::<LazyUpdate>();
+            let entities = world.entities();
+            let meshes = world.fetch::<ObjectMeshes>();
+            objects::build_moving_asteroid(
+                &meshes,
+                lazy_update.create_entity(&entities),
+                asteroid,
+                pos,
+                0.0,
+                velocity,
+            )
+        };
+        world.maintain();
+        entity
+    }
+
+    /// The mothership's entity
+    pub fn mothership(&self) -> Entity {
+        let world = &self.ecs.world;
+        let entities = world.entities();
+        let ships = world.read_storage::<Ship>();
+        (&entities, &ships)
+            .join()
+            .find(|(_, ship)| ship.kind() == ship::ShipKind::Mothership)
+            .map(|(entity, _)| entity)
+            .expect("There is no mothership")
+    }
+
+    /// Builds the block on the mothership for free, even if it has not been unlocked
+    pub fn build_block(&mut self, pos: cgmath::Point3<i16>, type_name: &str) -> Entity {
+        let ship = self.mothership();
+        let world = &mut self.ecs.world;
+        let block_id = world
+            .fetch::<block::Blocks>()
+            .find(type_name)
+            .unwrap_or_else(|| panic!("No block named {}", type_name));
+        let action = ship::BuildAction::BuildBlock(pos, block_id);
+        ship::build(world, ship, &[action], ship::BuildSource::InitialShip);
+        world.maintain();
+        let ships = world.read_storage::<Ship>();
+        let tile = ships
+            .get(ship)
+            .and_then(|ship| ship.tile(pos))
+            .expect("The block was built outside the ship");
+        tile.gadget()
+            .or_else(|| tile.block())
+            .expect("The block was not built")
+    }
+}
+
+/// Creates entities for tests that only need their ids. The world they were created in is
+/// dropped, so they are not alive anywhere.
+#[cfg(test)]
+pub fn entities(count: usize) -> Vec<Entity> {
+    let mut world = World::new();
+    (0..count).map(|_| world.create_entity().build()).collect()
+}
+
+/// Loads the blocks with placeholder meshes, for tests that don't need a whole HeadlessEcs
+#[cfg(test)]
+pub fn load_blocks() -> block::Blocks {
+    block::load_blocks_headless(&mut MeshManager::headless())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Laser;
+    use crate::entity::{difficulty::DifficultyDirector, gameplay::GameStats};
+    use crate::item::GameItem;
+    use cgmath::Point3;
+
+    /// What a run ends with. The starting inventory is random, so only what was gained is
+    /// compared.
+    #[derive(Debug)]
+    struct Outcome {
+        /// The heat the ship's blocks generated over the run. The coolers keep the ship itself
+        /// from heating up.
+        heat_generated: f32,
+        asteroids_destroyed: u32,
+        gained: Vec<i64>,
+    }
+
+    fn amounts(headless: &HeadlessEcs) -> Vec<i64> {
+        let inventory = headless.inventory();
+        GameItem::iter()
+            .map(|item| inventory.amount(item) as i64)
+            .collect()
+    }
+
+    /// Runs the first wave of a seed for the simulated seconds at the tick rate, with two
+    /// extra lasers and every laser in auto mode. The miners are left alone, since their
+    /// missiles destroy the asteroids without mining them.
+    fn run_at(rate: u32, seconds: u32) -> Outcome {
+        let mut headless = HeadlessEcs::with_seed(7);
+        headless
+            .ecs
+            .get_resource_mut::<DifficultyDirector>()
+            .enabled = false;
+        headless.set_tick_rate(rate);
+        headless.build_block(Point3::new(7, 3, 0), "Laser");
+        headless.build_block(Point3::new(3, 7, 0), "Laser");
+        for laser in (&mut headless.ecs.world.write_storage::<Laser>()).join() {
+            laser.set_auto(true);
+        }
+
+        let start = amounts(&headless);
+        let delta = crate::entity::Time::new(rate).delta;
+        let mut heat_generated = 0.0;
+        for _ in 0..rate * seconds {
+            headless.step(1);
+            let sources = headless.with_ship(|ship| {
+                ship.heat()
+                    .sources()
+                    .iter()
+                    .map(|(_, heat)| heat)
+                    .sum::<f32>()
+            });
+            heat_generated += sources.unwrap() * delta;
+        }
+        let asteroids_destroyed = headless.ecs.get_resource::<GameStats>().asteroids_destroyed;
+        Outcome {
+            heat_generated,
+            asteroids_destroyed,
+            gained: amounts(&headless)
+                .iter()
+                .zip(&start)
+                .map(|(amount, start)| amount - start)
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn tick_rate_does_not_change_the_outcome() {
+        let slow = run_at(30, 60);
+        let fast = run_at(120, 60);
+
+        assert!(
+            slow.asteroids_destroyed > 0,
+            "Nothing was mined: {:?}",
+            slow
+        );
+        assert_eq!(slow.asteroids_destroyed, fast.asteroids_destroyed);
+        assert_eq!(slow.gained, fast.gained);
+        // Lasers heat their ship while they fire, which can start or stop an update later
+        let tolerance = slow.heat_generated.max(fast.heat_generated) * 0.02;
+        assert!(
+            (slow.heat_generated - fast.heat_generated).abs() <= tolerance,
+            "{:?} != {:?}",
+            slow,
+            fast
+        );
+    }
+}
@@ -0,0 +1,423 @@
+use super::{
+    crew::{self, CrewBonus},
+    ship::{self, SelectedShip},
+    BlockEntity, GameModule, Model, Ship, Time,
+};
+use crate::block::{BlockId, Blocks};
+use crate::debug::{ArgSchema, ArgType, DebugCommand, DebugCommands};
+use crate::floor::Floors;
+use crate::i18n::Strings;
+use crate::profiler::AddProfiled;
+use cgmath::{prelude::*, Point3, Vector3};
+use specs::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+/// The ship's heat. Gadgets heat the ship as they are built and used, and the hull and the
+/// coolers dissipate it. While the ship is overheated, the gadgets that heat it stop.
+pub struct HeatModule;
+
+impl GameModule for HeatModule {
+    fn register(&self, world: &mut World) {
+        world.insert(HeatEvents::default());
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(
+            HeatSystem::default(),
+            "heat_system",
+            &[
+                "miner_system",
+                "cannon_system",
+                "laser_system",
+                "repulsor_system",
+            ],
+        );
+    }
+
+    fn init(&self, world: &mut World) {
+        world.insert(HeatEvents::default());
+    }
+
+    fn commands(&self, commands: &mut DebugCommands) {
+        commands.register(DebugCommand {
+            name: "heat",
+            description: "Sets the heat of the selected ship",
+            args: vec![ArgSchema {
+                name: "heat",
+                arg_type: ArgType::Float,
+            }],
+            state: Some(|ecs| {
+                let ships = ecs.world.read_component::<Ship>();
+                let selected = ecs.get_resource::<SelectedShip>();
+                match selected.find(&ecs.world.entities(), &ships) {
+                    Some((_, ship)) => {
+                        format!("{:.1} / {:.0}", ship.heat().heat(), ShipHeat::MAX)
+                    }
+                    None => String::from("no ship"),
+                }
+            }),
+            action: |ecs, args| {
+                let selected = ship::selected_ship(&ecs.world);
+                let mut ships = ecs.world.write_component::<Ship>();
+                let ship = selected
+                    .and_then(|ship| ships.get_mut(ship))
+                    .ok_or_else(|| String::from("There is no ship"))?;
+                ship.heat_mut().set_heat(args[0].as_float());
+                Ok(())
+            },
+        });
+    }
+}
+
+/// How a kind of block heats (or cools) the ship. It is part of the block's registration.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BlockHeat {
+    /// The heat from each use of the gadget (ex. each second its beam is on, or each shot)
+    pub per_use: f32,
+    /// The heat from each second the block is built, whether or not it is used
+    pub idle: f32,
+    /// The heat a cooler removes each second, when there are no other coolers near it
+    pub dissipation: f32,
+}
+
+/// The heat of a ship, and where it came from in the last update
+#[derive(Clone, Debug, Default)]
+pub struct ShipHeat {
+    heat: f32,
+    /// Set once the heat reaches MAX, and cleared once it has cooled down to RECOVERED
+    overheated: bool,
+    /// The heat each kind of block generated each second, the most first
+    sources: Vec<(BlockId, f32)>,
+    /// The heat the hull and the coolers removed each second
+    dissipation: f32,
+}
+
+impl ShipHeat {
+    pub const MAX: f32 = 100.0;
+    const RECOVERED: f32 = 50.0;
+    /// The heat the hull dissipates each second without any coolers
+    const PASSIVE_DISSIPATION: f32 = 1.5;
+    /// Coolers closer than this many tiles to each other share the heat they can remove
+    const COOLER_SPACING: f32 = 3.0;
+
+    pub fn heat(&self) -> f32 {
+        self.heat
+    }
+
+    /// Sets the heat (ex. from a debug command), which overheats the ship at MAX
+    pub fn set_heat(&mut self, heat: f32) {
+        self.heat = heat.clamp(0.0, ShipHeat::MAX);
+        self.overheated =
+            self.heat >= ShipHeat::MAX || (self.overheated && self.heat > ShipHeat::RECOVERED);
+    }
+
+    pub fn is_overheated(&self) -> bool {
+        self.overheated
+    }
+
+    pub fn sources(&self) -> &[(BlockId, f32)] {
+        &self.sources
+    }
+
+    pub fn dissipation(&self) -> f32 {
+        self.dissipation
+    }
+
+    /// The fraction of its dissipation that the cooler at `pos` keeps. Every other cooler
+    /// within COOLER_SPACING tiles (the decks count as tiles) crowds it, the closer the more.
+    pub fn cooler_efficiency(pos: Point3<i16>, coolers: &[(Point3<i16>, f32)]) -> f32 {
+        let crowding: f32 = coolers
+            .iter()
+            .filter(|(other, _)| *other != pos)
+            .map(|(other, _)| {
+                let offset = other - pos;
+                let distance =
+                    Vector3::new(offset.x as f32, offset.y as f32, offset.z as f32).magnitude();
+                (1.0 - distance / ShipHeat::COOLER_SPACING).max(0.0)
+            })
+            .sum();
+        1.0 / (1.0 + crowding)
+    }
+}
+
+/// Everything that a heat update depends on besides the ship's heat, so it can be simulated
+/// without a world
+#[derive(Clone, Debug, Default)]
+pub struct HeatInputs {
+    pub delta: f32,
+    /// The heat each block generated during the update, with the kind of the block
+    pub sources: Vec<(BlockId, f32)>,
+    /// The tile of each cooler, and the heat it removes each second on its own
+    pub coolers: Vec<(Point3<i16>, f32)>,
+    /// The heat the ship's vent floors remove each second, all together
+    pub vents: f32,
+    /// The heat from outside of the ship during the update (ex. a solar flare)
+    pub external: f32,
+}
+
+/// The heat the hull, the coolers and the vents remove each second, all together
+pub fn total_dissipation(coolers: &[(Point3<i16>, f32)], vents: f32) -> f32 {
+    ShipHeat::PASSIVE_DISSIPATION
+        + vents
+        + coolers
+            .iter()
+            .map(|(pos, dissipation)| dissipation * ShipHeat::cooler_efficiency(*pos, coolers))
+            .sum::<f32>()
+}
+
+/// Heats the ship with the sources, and cools it with the hull, the coolers and the vents.
+/// The heat is updated in place, so its list of sources does not have to be allocated again.
+pub fn simulate(inputs: &HeatInputs, ship_heat: &mut ShipHeat) {
+    let sources = &mut ship_heat.sources;
+    sources.clear();
+    for (block, heat) in &inputs.sources {
+        match sources.iter_mut().find(|(source, _)| source == block) {
+            Some((_, total)) => *total += heat,
+            None => sources.push((*block, *heat)),
+        }
+    }
+    let generated = sources.iter().map(|(_, heat)| heat).sum::<f32>() + inputs.external;
+    let dissipation = total_dissipation(&inputs.coolers, inputs.vents);
+
+    let heat = (ship_heat.heat + generated - dissipation * inputs.delta).clamp(0.0, ShipHeat::MAX);
+    ship_heat.overheated = if heat >= ShipHeat::MAX {
+        true
+    } else if heat <= ShipHeat::RECOVERED {
+        false
+    } else {
+        ship_heat.overheated
+    };
+    ship_heat.heat = heat;
+    ship_heat.dissipation = dissipation;
+
+    for (_, heat) in sources.iter_mut() {
+        *heat /= inputs.delta;
+    }
+    // The unstable sort doesn't allocate, and the ids keep the order of equal sources
+    sources.sort_unstable_by(|(a_block, a), (b_block, b)| {
+        b.partial_cmp(a).unwrap().then(a_block.cmp(b_block))
+    });
+}
+
+/// The blocks and gadgets of the overheated ships. The ones that heat their ship are
+/// stopped, while the other ships keep working.
+pub fn overheated_blocks(ships: &ReadStorage<Ship>) -> HashSet<Entity> {
+    ships
+        .join()
+        .filter(|ship| ship.heat().is_overheated())
+        .flat_map(|ship| ship.blocks().chain(ship.gadgets()))
+        .collect()
+}
+
+/// The tile and the dissipation of each of the ship's coolers. Coolers operated by crew
+/// dissipate more, and browned out coolers dissipate less.
+pub fn ship_coolers<'a>(
+    ship: &'a Ship,
+    blocks: &'a Blocks,
+    block_entities: &'a ReadStorage<BlockEntity>,
+    bonuses: &'a ReadStorage<CrewBonus>,
+) -> impl Iterator<Item = (Point3<i16>, f32)> + 'a {
+    ship.tiles()
+        .filter_map(move |(pos, tile)| {
+            let entity = tile.block()?;
+            let block = block_entities.get(entity)?.block_id();
+            let dissipation = blocks.get_block(block).heat.dissipation;
+            let power = ship.power().fraction(entity);
+            Some((pos, dissipation * crew::speedup(bonuses, entity) * power))
+        })
+        .filter(|(_, dissipation)| *dissipation > 0.0)
+}
+
+/// How much each gadget was used during the current update. The uses are turned into heat
+/// with the BlockHeat of the gadget's block by the HeatSystem.
+#[derive(Default)]
+pub struct HeatEvents {
+    uses: HashMap<Entity, f32>,
+    /// The heat added to each ship from outside during the update
+    external: HashMap<Entity, f32>,
+}
+
+impl HeatEvents {
+    pub fn push(&mut self, entity: Entity, uses: f32) {
+        *self.uses.entry(entity).or_insert(0.0) += uses;
+    }
+
+    /// Heats the ship itself, instead of one of its gadgets (ex. a solar flare)
+    pub fn push_external(&mut self, ship: Entity, heat: f32) {
+        *self.external.entry(ship).or_insert(0.0) += heat;
+    }
+}
+
+/// Adds up the heat of every block on the ship, after the gadgets were used
+#[derive(Default)]
+pub struct HeatSystem {
+    /// Reused for every ship, so the inputs are not allocated every update
+    inputs: HeatInputs,
+}
+
+impl<'a> System<'a> for HeatSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Time>,
+        ReadExpect<'a, Blocks>,
+        ReadExpect<'a, Floors>,
+        WriteExpect<'a, HeatEvents>,
+        WriteStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Model>,
+        ReadStorage<'a, CrewBonus>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            time,
+            blocks,
+            floors,
+            mut events,
+            mut ships,
+            block_entities,
+            models,
+            bonuses,
+        ) = data;
+        let inputs = &mut self.inputs;
+
+        for (entity, ship) in (&entities, &mut ships).join() {
+            inputs.delta = time.delta;
+            inputs.coolers.clear();
+            inputs
+                .coolers
+                .extend(ship_coolers(ship, &blocks, &block_entities, &bonuses));
+            inputs.vents = ship
+                .tiles()
+                .filter_map(|(_, tile)| tile.floor_type())
+                .map(|floor| floors.get(floor).properties.dissipation)
+                .sum();
+            inputs.external = events.external.get(&entity).copied().unwrap_or(0.0);
+
+            inputs.sources.clear();
+            for (_, entity, block) in ship.block_kinds(&blocks, &block_entities, &models) {
+                let heat = blocks.get_block(block).heat;
+                let uses = events.uses.get(&entity).copied().unwrap_or(0.0);
+                let generated = heat.idle * time.delta + heat.per_use * uses;
+                if generated > 0.0 {
+                    inputs.sources.push((block, generated));
+                }
+            }
+
+            simulate(inputs, ship.heat_mut());
+        }
+        // Cleared instead of replaced, so the maps keep their capacity
+        events.uses.clear();
+        events.external.clear();
+    }
+}
+
+/// Ex. "Cooler - 2.1 heat/s (70%, crowded by other coolers)"
+pub fn cooler_tooltip(entity: Entity, world: &World) -> String {
+    let blocks = world.fetch::<Blocks>();
+    let block_entities = world.read_component::<BlockEntity>();
+    let bonuses = world.read_component::<CrewBonus>();
+    let pos = match block_entities.get(entity) {
+        Some(block_entity) => block_entity.root(),
+        None => return String::new(),
+    };
+
+    let ships = world.read_component::<Ship>();
+    let coolers = match ships.join().find(|ship| ship.has_block(pos, entity)) {
+        Some(ship) => ship_coolers(ship, &blocks, &block_entities, &bonuses).collect::<Vec<_>>(),
+        None => return String::new(),
+    };
+    let dissipation = coolers
+        .iter()
+        .find(|(cooler, _)| *cooler == pos)
+        .map_or(0.0, |(_, dissipation)| *dissipation);
+    let efficiency = ShipHeat::cooler_efficiency(pos, &coolers);
+    let strings = world.fetch::<Strings>();
+    let key = if efficiency < 1.0 {
+        "cooler.tooltip_crowded"
+    } else {
+        "cooler.tooltip"
+    };
+    strings.format(
+        key,
+        &[
+            ("heat", &format!("{:.1}", dissipation * efficiency)),
+            ("percent", &format!("{:.0}", efficiency * 100.0)),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Laser;
+    use crate::entity::{headless::HeadlessEcs, objects::Health, Tile};
+
+    #[test]
+    fn two_coolers_keep_a_firing_laser_cool() {
+        let mut headless = HeadlessEcs::with_seed(1);
+        headless.pause_asteroid_field();
+        // The starting ship has a cooler at (-1, 5, 0), and this one is far enough from it
+        // that they don't crowd each other
+        headless.build_block(Point3::new(-1, 1, 0), "Cooler");
+        let coolers = headless
+            .with_ship(|ship| {
+                let blocks = headless.ecs.get_resource::<Blocks>();
+                let block_entities = headless.ecs.world.read_storage::<BlockEntity>();
+                ship.tiles()
+                    .filter_map(|(_, tile)| tile.block())
+                    .filter(|block| {
+                        matches!(block_entities.get(*block),
+                            Some(entity) if entity.block_id() == blocks.cooler)
+                    })
+                    .count()
+            })
+            .unwrap();
+        assert_eq!(coolers, 2);
+
+        // Only one laser is in auto mode, and the asteroid outlasts the test
+        let laser = headless
+            .with_ship(|ship| ship.tile(Point3::new(0, 0, 0)).and_then(Tile::gadget))
+            .flatten()
+            .unwrap();
+        headless
+            .ecs
+            .world
+            .write_storage::<Laser>()
+            .get_mut(laser)
+            .unwrap()
+            .set_auto(true);
+        let asteroid = headless.spawn_asteroid(
+            Vector3::new(-5.0, -5.0, 2.0),
+            Vector3::new(0.0, 0.0, 0.0),
+            100.0,
+        );
+        let health = |headless: &HeadlessEcs| {
+            let healths = headless.ecs.world.read_storage::<Health>();
+            healths.get(asteroid).map(Health::health)
+        };
+        let start = health(&headless).unwrap();
+
+        let delta = headless.ecs.get_resource::<Time>().delta;
+        let mut max_heat = 0.0f32;
+        let mut generated = 0.0;
+        for _ in 0..10_000 {
+            headless.step(1);
+            let (heat, sources) = headless
+                .with_ship(|ship| {
+                    let heat = ship.heat();
+                    let sources: f32 = heat.sources().iter().map(|(_, heat)| heat).sum();
+                    (heat.heat(), sources)
+                })
+                .unwrap();
+            max_heat = max_heat.max(heat);
+            generated += sources * delta;
+        }
+        // The laser does 60 damage each second, so it fired for at least half of the run
+        let damage = start - health(&headless).unwrap();
+        assert!(damage >= 5_000, "The laser only did {} damage", damage);
+        assert!(generated > 0.0);
+        assert!(max_heat <= 10.0, "The heat reached {}", max_heat);
+    }
+}
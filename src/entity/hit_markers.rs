@@ -0,0 +1,325 @@
+use super::{hud::HudVerbosity, Time};
+use crate::graphics::GPUBillboard;
+use cgmath::{Vector2, Vector3};
+use specs::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HitKind {
+    /// The hit did not do any damage (ex. the target was already destroyed)
+    Resisted,
+    Damaged,
+    Killed,
+}
+
+/// Damage from one of the player's weapons that was actually applied to a target
+pub struct HitConfirmed {
+    pub target: Entity,
+    pub point: Vector3<f32>,
+    pub damage: u32,
+    pub kind: HitKind,
+}
+
+/// The hits confirmed during the current update. Multiple hits against the same
+/// target are merged, so rapid fire weapons only produce one marker per update.
+#[derive(Default)]
+pub struct HitEvents {
+    events: Vec<HitConfirmed>,
+}
+
+impl HitEvents {
+    pub const MAX_EVENTS: usize = 16;
+
+    /// `damage` is the damage that was applied (after it was limited by the target's health)
+    pub fn confirm(&mut self, target: Entity, point: Vector3<f32>, damage: u32, killed: bool) {
+        let kind = if killed {
+            HitKind::Killed
+        } else if damage == 0 {
+            HitKind::Resisted
+        } else {
+            HitKind::Damaged
+        };
+
+        if let Some(event) = self.events.iter_mut().find(|event| event.target == target) {
+            event.point = point;
+            event.damage += damage;
+            event.kind = event.kind.max(kind);
+        } else if self.events.len() < HitEvents::MAX_EVENTS {
+            self.events.push(HitConfirmed {
+                target,
+                point,
+                damage,
+                kind,
+            });
+        }
+    }
+}
+
+struct HitMarker {
+    point: Vector3<f32>,
+    kind: HitKind,
+    /// The seconds since the marker appeared
+    age: f32,
+}
+
+/// Short flashes drawn where confirmed hits landed
+#[derive(Default)]
+pub struct HitMarkers {
+    markers: Vec<HitMarker>,
+}
+
+impl HitMarkers {
+    /// The seconds a marker is shown for
+    const LIFETIME: f32 = 0.2;
+
+    pub fn as_billboards(&self) -> Vec<GPUBillboard> {
+        self.markers
+            .iter()
+            .map(|marker| {
+                let progress = marker.age / HitMarkers::LIFETIME;
+                let (color, size) = match marker.kind {
+                    HitKind::Resisted => (Vector3::new(0.5, 0.5, 0.55), 0.18),
+                    HitKind::Damaged => (Vector3::new(1.0, 1.0, 1.0), 0.22),
+                    HitKind::Killed => (Vector3::new(1.0, 0.3, 0.2), 0.4),
+                };
+                // Markers briefly grow when they appear, unless motion is reduced
+                let size = if crate::REDUCED_MOTION {
+                    size
+                } else {
+                    size * (1.0 + 0.5 * (1.0 - progress))
+                };
+
+                GPUBillboard {
+                    pos: marker.point,
+                    offset: Vector2::new(-size / 2.0, -size / 2.0),
+                    size: Vector2::new(size, size),
+                    color: color.extend(1.0 - progress),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Damage that was applied to any entity during the current update (by the DamageSystem)
+#[derive(Default)]
+pub struct AppliedDamage {
+    events: Vec<(Entity, u32, Vector3<f32>, bool)>,
+}
+
+impl AppliedDamage {
+    /// `amount` is the damage returned by Health::damage
+    pub fn push(&mut self, target: Entity, amount: u32, position: Vector3<f32>, killed: bool) {
+        if amount > 0 {
+            self.events.push((target, amount, position, killed));
+        }
+    }
+}
+
+pub struct DamageNumber {
+    pub target: Entity,
+    pub position: Vector3<f32>,
+    pub amount: u32,
+    /// The seconds since the number appeared
+    pub age: f32,
+    killed: bool,
+    /// Whether the number is big enough to be shown at the current HudVerbosity
+    shown: bool,
+}
+
+impl DamageNumber {
+    /// The seconds a number is shown for
+    pub const LIFETIME: f32 = 0.65;
+    /// Damage to the same target is added to its number for this many seconds, so damage
+    /// that is applied every update (ex. lasers) does not create a number every update
+    const MERGE_TIME: f32 = 0.15;
+
+    /// How far (0..1) the number is through its lifetime
+    pub fn progress(&self) -> f32 {
+        self.age / DamageNumber::LIFETIME
+    }
+}
+
+/// The damage numbers floating above damaged entities
+#[derive(Default)]
+pub struct DamageNumbers {
+    numbers: Vec<DamageNumber>,
+}
+
+impl DamageNumbers {
+    /// The oldest numbers are removed once there are more than this
+    pub const MAX_NUMBERS: usize = 16;
+
+    /// Gets the shown numbers, numbers hidden by the HudVerbosity are skipped
+    pub fn get(&self, index: usize) -> Option<&DamageNumber> {
+        self.numbers.iter().filter(|number| number.shown).nth(index)
+    }
+}
+
+pub struct DamageNumberSystem;
+
+impl<'a> System<'a> for DamageNumberSystem {
+    type SystemData = (
+        ReadExpect<'a, Time>,
+        WriteExpect<'a, AppliedDamage>,
+        WriteExpect<'a, DamageNumbers>,
+        ReadExpect<'a, HudVerbosity>,
+    );
+
+    fn run(&mut self, (time, mut events, mut numbers, verbosity): Self::SystemData) {
+        for number in &mut numbers.numbers {
+            number.age += time.delta;
+        }
+        numbers
+            .numbers
+            .retain(|number| number.age < DamageNumber::LIFETIME);
+
+        for (target, amount, position, killed) in events.events.drain(..) {
+            if let Some(number) = numbers
+                .numbers
+                .iter_mut()
+                .find(|number| number.target == target && number.age < DamageNumber::MERGE_TIME)
+            {
+                number.amount += amount;
+                number.position = position;
+                number.killed |= killed;
+            } else {
+                numbers.numbers.push(DamageNumber {
+                    target,
+                    position,
+                    amount,
+                    age: 0.0,
+                    killed,
+                    shown: false,
+                });
+            }
+        }
+
+        // Small hits keep adding to their number while hidden, so merged damage is
+        // shown once it adds up to a big hit
+        for number in &mut numbers.numbers {
+            number.shown |= verbosity.shows_damage(number.amount, number.killed);
+        }
+
+        let overflow = numbers
+            .numbers
+            .len()
+            .saturating_sub(DamageNumbers::MAX_NUMBERS);
+        numbers.numbers.drain(..overflow);
+    }
+}
+
+pub struct HitMarkerSystem;
+
+impl<'a> System<'a> for HitMarkerSystem {
+    type SystemData = (
+        ReadExpect<'a, Time>,
+        WriteExpect<'a, HitEvents>,
+        WriteExpect<'a, HitMarkers>,
+        ReadExpect<'a, HudVerbosity>,
+    );
+
+    fn run(&mut self, (time, mut events, mut markers, verbosity): Self::SystemData) {
+        for marker in &mut markers.markers {
+            marker.age += time.delta;
+        }
+        markers
+            .markers
+            .retain(|marker| marker.age < HitMarkers::LIFETIME);
+
+        for event in events.events.drain(..) {
+            if *verbosity == HudVerbosity::Minimal && event.kind != HitKind::Killed {
+                continue;
+            }
+            markers.markers.push(HitMarker {
+                point: event.point,
+                kind: event.kind,
+                age: 0.0,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world_with(verbosity: HudVerbosity) -> World {
+        let mut world = World::new();
+        world.insert(Time::new(Time::DEFAULT_RATE));
+        world.insert(verbosity);
+        world.insert(AppliedDamage::default());
+        world.insert(DamageNumbers::default());
+        world.insert(HitEvents::default());
+        world.insert(HitMarkers::default());
+        world
+    }
+
+    fn shown_numbers(world: &World) -> Vec<u32> {
+        let numbers = world.fetch::<DamageNumbers>();
+        (0..)
+            .map_while(|index| numbers.get(index).map(|number| number.amount))
+            .collect()
+    }
+
+    #[test]
+    fn reduced_verbosity_hides_small_hits_until_they_add_up() {
+        let mut world = world_with(HudVerbosity::Reduced);
+        let target = world.create_entity().build();
+        let position = Vector3::new(0.0, 0.0, 0.0);
+
+        world
+            .fetch_mut::<AppliedDamage>()
+            .push(target, 4, position, false);
+        DamageNumberSystem.run_now(&world);
+        assert!(shown_numbers(&world).is_empty());
+
+        // The next hits are merged into the same number, which is then big enough
+        world
+            .fetch_mut::<AppliedDamage>()
+            .push(target, 8, position, false);
+        DamageNumberSystem.run_now(&world);
+        assert_eq!(shown_numbers(&world), vec![12]);
+    }
+
+    #[test]
+    fn minimal_verbosity_only_shows_kills() {
+        let mut world = world_with(HudVerbosity::Minimal);
+        let damaged = world.create_entity().build();
+        let killed = world.create_entity().build();
+        let position = Vector3::new(0.0, 0.0, 0.0);
+
+        {
+            let mut applied = world.fetch_mut::<AppliedDamage>();
+            applied.push(damaged, 50, position, false);
+            applied.push(killed, 5, position, true);
+            let mut hits = world.fetch_mut::<HitEvents>();
+            hits.confirm(damaged, position, 50, false);
+            hits.confirm(killed, position, 5, true);
+        }
+        DamageNumberSystem.run_now(&world);
+        HitMarkerSystem.run_now(&world);
+
+        assert_eq!(shown_numbers(&world), vec![5]);
+        let markers = world.fetch::<HitMarkers>();
+        assert_eq!(markers.markers.len(), 1);
+        assert_eq!(markers.markers[0].kind, HitKind::Killed);
+    }
+
+    #[test]
+    fn full_verbosity_shows_every_hit() {
+        let mut world = world_with(HudVerbosity::Full);
+        let target = world.create_entity().build();
+        let position = Vector3::new(0.0, 0.0, 0.0);
+
+        world
+            .fetch_mut::<AppliedDamage>()
+            .push(target, 1, position, false);
+        world
+            .fetch_mut::<HitEvents>()
+            .confirm(target, position, 1, false);
+        DamageNumberSystem.run_now(&world);
+        HitMarkerSystem.run_now(&world);
+
+        assert_eq!(shown_numbers(&world), vec![1]);
+        assert_eq!(world.fetch::<HitMarkers>().markers.len(), 1);
+    }
+}
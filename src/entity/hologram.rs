@@ -0,0 +1,352 @@
+//! The hologram that helps with building. While a block from the build menu is being placed,
+//! a see-through copy of the selected ship floats above it, and the free tiles of the deck
+//! that is built on are outlined by whether the block can be placed there. The tiles are
+//! picked on the hologram, so clicking one of its tiles places the block on the same tile of
+//! the ship (see BlueprintSystem).
+
+use super::{
+    animation::Animation,
+    blueprint,
+    ship::{SelectedShip, Tile},
+    BlockEntity, GameModule, InputAction, InputManager, Line, LineBatch, Model, Ship, Transform,
+};
+use crate::block::{BlockId, Blocks};
+use crate::graphics::{MeshId, MeshManager, ModelId, ModelTint};
+use crate::i18n::Strings;
+use crate::profiler::AddProfiled;
+use cgmath::{Matrix4, Point2, Point3, Vector3, Vector4};
+use specs::prelude::*;
+
+pub struct HologramModule;
+
+impl GameModule for HologramModule {
+    fn register(&self, world: &mut World) {
+        world.register::<Model>();
+        world.register::<Animation>();
+        world.insert(BuildHologram::default());
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        // The walls pick their meshes first, so the hologram copies the right ones
+        builder.add_profiled(HologramSystem, "hologram_system", &["wall_system"]);
+    }
+
+    /// The ship of the last run is gone, so its hologram is torn down
+    fn init(&self, world: &mut World) {
+        let visualization = world.write_resource::<BuildHologram>().visualization.take();
+        if let Some(visualization) = visualization {
+            visualization.tear_down(&mut world.write_resource::<MeshManager>());
+        }
+    }
+}
+
+/// The hologram of the selected ship, while a block is being placed
+#[derive(Default)]
+pub struct BuildHologram {
+    visualization: Option<BuildVisualization>,
+}
+
+impl BuildHologram {
+    /// How far above the top of the ship the hologram floats
+    const GAP: f32 = 1.0;
+    /// How far the hologram is moved up from the ship
+    const LIFT: f32 = Ship::TOP + BuildHologram::GAP;
+    const TINT: ModelTint = ModelTint {
+        primary: Vector4::new(0.3, 0.7, 1.0, 0.4),
+        secondary: Vector4::new(0.3, 0.7, 1.0, 0.4),
+    };
+    /// The outlines of the tiles that the block can be placed on glow
+    const VALID_COLOR: Vector3<f32> = Vector3::new(0.4, 0.9, 1.0);
+    const VALID_WIDTH: f32 = 0.05;
+    const INVALID_COLOR: Vector3<f32> = Vector3::new(0.15, 0.25, 0.35);
+    /// How many tiles around the ship are outlined
+    const CELL_MARGIN: i16 = 2;
+
+    /// How far above the ship the hologram is, while there is one
+    pub fn lift(&self) -> Option<f32> {
+        self.visualization.as_ref().map(|_| BuildHologram::LIFT)
+    }
+}
+
+/// The models and tile outlines of the hologram. It is created when placing a block starts,
+/// and torn down when it ends, which removes every model it added to the MeshManager.
+pub struct BuildVisualization {
+    ship: Entity,
+    /// The layout that the models were copied from (see Ship::layout_revision). The
+    /// hologram is built again when it changes.
+    layout_revision: u32,
+    models: Vec<HologramModel>,
+    /// The block, the deck and the layout revision that the cells were checked for
+    checked: Option<(BlockId, i16, u32)>,
+    /// The free tiles of the deck, and whether the block can be placed on them
+    cells: Vec<(Point2<i16>, bool)>,
+}
+
+/// A copy of one of the ship's models, which follows the entity it was copied from
+struct HologramModel {
+    entity: Entity,
+    mesh_id: MeshId,
+    model_id: ModelId,
+    /// The animated node that is copied, or None for the entity's own Model
+    node: Option<usize>,
+}
+
+impl BuildVisualization {
+    fn new(
+        mesh_manager: &mut MeshManager,
+        ship_entity: Entity,
+        ship: &Ship,
+        models: &ReadStorage<Model>,
+        animations: &ReadStorage<Animation>,
+    ) -> Self {
+        // The tiles of a block that is larger than one tile share its entity
+        let mut entities: Vec<Entity> = ship
+            .tiles()
+            .flat_map(|(_, tile)| {
+                tile.block()
+                    .into_iter()
+                    .chain(tile.gadget())
+                    .chain(tile.floor())
+            })
+            .collect();
+        entities.sort();
+        entities.dedup();
+
+        let mut copies = Vec::new();
+        for entity in entities {
+            let own = models.get(entity).map(|model| (model.mesh_id, None));
+            let nodes = animations
+                .get(entity)
+                .into_iter()
+                .flat_map(|animation| animation.node_models().map(|(mesh_id, _)| mesh_id))
+                .enumerate()
+                .map(|(index, mesh_id)| (mesh_id, Some(index)));
+            for (mesh_id, node) in own.into_iter().chain(nodes) {
+                // The copies are placed by update_models, so they grow in from nothing
+                let model_id =
+                    mesh_manager.new_model(mesh_id, Matrix4::from_scale(0.0), BuildHologram::TINT);
+                mesh_manager.set_hologram(mesh_id, model_id, true);
+                copies.push(HologramModel {
+                    entity,
+                    mesh_id,
+                    model_id,
+                    node,
+                });
+            }
+        }
+
+        Self {
+            ship: ship_entity,
+            layout_revision: ship.layout_revision(),
+            models: copies,
+            checked: None,
+            cells: Vec::new(),
+        }
+    }
+
+    /// Moves the copies to where their entities are, lifted above the ship. The meshes are
+    /// followed too, since some blocks change theirs (ex. walls connecting to new walls).
+    fn update_models(
+        &mut self,
+        mesh_manager: &mut MeshManager,
+        models: &ReadStorage<Model>,
+        animations: &ReadStorage<Animation>,
+        transforms: &ReadStorage<Transform>,
+    ) {
+        let lift = Matrix4::from_translation(Vector3::new(0.0, 0.0, BuildHologram::LIFT));
+        for copy in &mut self.models {
+            let placed = match copy.node {
+                None => models
+                    .get(copy.entity)
+                    .zip(transforms.get(copy.entity))
+                    .map(|(model, transform)| (model.mesh_id, transform.as_matrix())),
+                Some(index) => animations
+                    .get(copy.entity)
+                    .and_then(|animation| animation.node_models().nth(index)),
+            };
+            let (mesh_id, matrix) = match placed {
+                Some(placed) => placed,
+                None => continue,
+            };
+
+            if mesh_id != copy.mesh_id {
+                copy.model_id = mesh_manager.change_mesh(copy.mesh_id, copy.model_id, mesh_id);
+                copy.mesh_id = mesh_id;
+            }
+            mesh_manager.update_model(copy.mesh_id, copy.model_id, lift * matrix);
+        }
+    }
+
+    /// Finds the free tiles of the deck near the ship, and checks whether the block can be
+    /// placed on them
+    fn check_cells(
+        &mut self,
+        ship: &Ship,
+        block_entities: &ReadStorage<BlockEntity>,
+        blocks: &Blocks,
+        strings: &Strings,
+        block_id: BlockId,
+        deck: i16,
+    ) {
+        let checked = Some((block_id, deck, ship.layout_revision()));
+        if self.checked == checked {
+            return;
+        }
+        self.checked = checked;
+
+        let block = blocks.get_block(block_id);
+        // Gadgets are built on top of a block
+        let is_free = |tile: &Tile| {
+            if block.is_gadget {
+                tile.block().is_some() && tile.gadget().is_none()
+            } else {
+                tile.block().is_none()
+            }
+        };
+        // The ship can be built out over a much larger area, which would be too many outlines
+        let near = match ship.bounds() {
+            Some(bounds) => {
+                let margin = BuildHologram::CELL_MARGIN as f32;
+                let (min, max) = (bounds.min.truncate(), bounds.max.truncate());
+                move |pos: Point3<i16>| {
+                    let (x, y) = (pos.x as f32, pos.y as f32);
+                    x > min.x - margin
+                        && x < max.x + margin
+                        && y > min.y - margin
+                        && y < max.y + margin
+                }
+            }
+            None => {
+                self.cells.clear();
+                return;
+            }
+        };
+        self.cells = ship
+            .tiles()
+            .filter(|(pos, tile)| pos.z == deck && near(*pos) && is_free(tile))
+            .map(|(pos, _)| {
+                let valid = blueprint::check_block_placement(
+                    ship,
+                    block_entities,
+                    blocks,
+                    pos,
+                    block_id,
+                    strings,
+                )
+                .is_ok();
+                (Point2::new(pos.x, pos.y), valid)
+            })
+            .collect();
+    }
+
+    /// Outlines the free tiles on the deck of the hologram
+    fn add_cell_outlines(&self, line_batch: &mut LineBatch, deck: i16) {
+        let z = Ship::deck_z(deck) + BuildHologram::LIFT + 0.15;
+        for (cell, valid) in &self.cells {
+            let (x1, y1) = (cell.x as f32 - 0.5, cell.y as f32 - 0.5);
+            let (x2, y2) = (cell.x as f32 + 0.5, cell.y as f32 + 0.5);
+            let corners = [
+                Vector3::new(x1, y1, z),
+                Vector3::new(x2, y1, z),
+                Vector3::new(x2, y2, z),
+                Vector3::new(x1, y2, z),
+            ];
+
+            for i in 0..corners.len() {
+                let next = corners[(i + 1) % corners.len()];
+                line_batch.add(if *valid {
+                    Line::new(corners[i], next, BuildHologram::VALID_COLOR)
+                        .with_width(BuildHologram::VALID_WIDTH)
+                        .as_overlay()
+                } else {
+                    Line::new(corners[i], next, BuildHologram::INVALID_COLOR)
+                });
+            }
+        }
+    }
+
+    fn tear_down(self, mesh_manager: &mut MeshManager) {
+        for copy in self.models {
+            mesh_manager.remove_model(copy.mesh_id, copy.model_id);
+        }
+    }
+}
+
+/// Creates the hologram when placing a block starts, keeps it up to date with the ship, and
+/// tears it down when placing ends
+pub struct HologramSystem;
+
+impl<'a> System<'a> for HologramSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, InputManager>,
+        ReadExpect<'a, Blocks>,
+        ReadExpect<'a, Strings>,
+        WriteExpect<'a, MeshManager>,
+        WriteExpect<'a, BuildHologram>,
+        Write<'a, LineBatch>,
+        Read<'a, SelectedShip>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Model>,
+        ReadStorage<'a, Animation>,
+        ReadStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            input,
+            blocks,
+            strings,
+            mut mesh_manager,
+            mut hologram,
+            mut line_batch,
+            selected,
+            ships,
+            block_entities,
+            models,
+            animations,
+            transforms,
+        ) = data;
+
+        let placing = match input.action {
+            InputAction::PlaceBlock(block_id) => Some(block_id),
+            _ => None,
+        };
+        let (block_id, (ship_entity, ship)) = match placing.zip(selected.find(&entities, &ships)) {
+            Some(placing) => placing,
+            None => {
+                if let Some(visualization) = hologram.visualization.take() {
+                    visualization.tear_down(&mut mesh_manager);
+                }
+                return;
+            }
+        };
+
+        let outdated = matches!(
+            &hologram.visualization,
+            Some(visualization) if visualization.ship != ship_entity
+                || visualization.layout_revision != ship.layout_revision()
+        );
+        if outdated {
+            if let Some(visualization) = hologram.visualization.take() {
+                visualization.tear_down(&mut mesh_manager);
+            }
+        }
+
+        let visualization = hologram.visualization.get_or_insert_with(|| {
+            BuildVisualization::new(&mut mesh_manager, ship_entity, ship, &models, &animations)
+        });
+        visualization.update_models(&mut mesh_manager, &models, &animations, &transforms);
+        visualization.check_cells(
+            ship,
+            &block_entities,
+            &blocks,
+            &strings,
+            block_id,
+            input.deck,
+        );
+        visualization.add_cell_outlines(&mut line_batch, input.deck);
+    }
+}
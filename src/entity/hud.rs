@@ -0,0 +1,238 @@
+use super::{gameplay::IncomingThreat, objects::Health, BlockEntity, Time};
+use specs::prelude::*;
+
+/// How much of the HUD is shown. During intense moments the HUD is reduced, so it does
+/// not hide the threats. Every system that adds something to the HUD checks this.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HudVerbosity {
+    Full,
+    /// Small hits and non essential messages are hidden
+    Reduced,
+    /// Only kills and things that need attention are shown
+    Minimal,
+}
+
+impl HudVerbosity {
+    /// The damage a number needs (at Reduced verbosity) to be shown
+    const BIG_HIT: u32 = 10;
+
+    pub fn shows_damage(self, amount: u32, killed: bool) -> bool {
+        match self {
+            HudVerbosity::Full => true,
+            HudVerbosity::Reduced => killed || amount >= HudVerbosity::BIG_HIT,
+            HudVerbosity::Minimal => killed,
+        }
+    }
+
+    /// Scales how long the cursor rests before a tooltip is shown. The tooltips wait longer
+    /// at lower verbosity, so they don't pop up while the cursor crosses the ship in a fight.
+    pub fn tooltip_delay(self, delay: f32) -> f32 {
+        match self {
+            HudVerbosity::Full => delay,
+            HudVerbosity::Reduced => delay * 2.0,
+            HudVerbosity::Minimal => delay * 4.0,
+        }
+    }
+
+    /// Scales how long a toast is shown. The toasts are shorter at lower verbosity, so they
+    /// don't cover the threats for long.
+    pub fn toast_lifetime(self, lifetime: f32) -> f32 {
+        match self {
+            HudVerbosity::Full => lifetime,
+            HudVerbosity::Reduced => lifetime * 0.7,
+            HudVerbosity::Minimal => lifetime * 0.4,
+        }
+    }
+
+    fn from_intensity(intensity: f32) -> Self {
+        if intensity >= HudSystem::MINIMAL_INTENSITY {
+            HudVerbosity::Minimal
+        } else if intensity >= HudSystem::REDUCED_INTENSITY {
+            HudVerbosity::Reduced
+        } else {
+            HudVerbosity::Full
+        }
+    }
+}
+
+/// Updates the HudVerbosity once per second based on how intense the game is. Unless
+/// crate::HUD_VERBOSITY pins it to one level.
+#[derive(Default)]
+pub struct HudSystem {
+    /// The seconds since the verbosity was last updated
+    elapsed: f32,
+}
+
+impl HudSystem {
+    const UPDATE_TIME: f32 = 1.0;
+    const REDUCED_INTENSITY: f32 = 3.0;
+    const MINIMAL_INTENSITY: f32 = 6.0;
+    /// How far the intensity needs to drop below a threshold before more of the HUD
+    /// is shown again, so the HUD does not flicker around the thresholds
+    const HYSTERESIS: f32 = 1.5;
+    /// Blocks that were damaged this many seconds ago count towards the intensity
+    const RECENT_DAMAGE_TIME: f32 = 2.0;
+
+    fn next_verbosity(current: HudVerbosity, intensity: f32) -> HudVerbosity {
+        let raised = HudVerbosity::from_intensity(intensity);
+        let relaxed = HudVerbosity::from_intensity(intensity + HudSystem::HYSTERESIS);
+
+        if raised > current {
+            raised
+        } else if relaxed < current {
+            relaxed
+        } else {
+            current
+        }
+    }
+}
+
+impl<'a> System<'a> for HudSystem {
+    type SystemData = (
+        ReadExpect<'a, Time>,
+        WriteExpect<'a, HudVerbosity>,
+        ReadStorage<'a, IncomingThreat>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Health>,
+    );
+
+    fn run(&mut self, (time, mut verbosity, threats, block_entities, healths): Self::SystemData) {
+        if let Some(pinned) = crate::HUD_VERBOSITY {
+            *verbosity = pinned;
+            return;
+        }
+
+        if !time.count_up(&mut self.elapsed, HudSystem::UPDATE_TIME) {
+            return;
+        }
+        self.elapsed = 0.0;
+
+        let damaged_blocks = (&block_entities, &healths)
+            .join()
+            .filter(|(_, health)| health.time_since_damage() < HudSystem::RECENT_DAMAGE_TIME)
+            .count();
+        let intensity = threats.join().count() as f32 + damaged_blocks as f32 * 0.5;
+
+        *verbosity = HudSystem::next_verbosity(*verbosity, intensity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::achievements::{AchievementSystem, Achievements, Toast};
+    use crate::entity::{damage::DamageSource, headless::load_blocks};
+    use crate::item::GameItem;
+    use crate::stats::{record, LifetimeStats, RunStats, SaveFiles, StatEvent};
+
+    #[test]
+    fn verbosity_is_reduced_at_once_and_restored_with_hysteresis() {
+        let reduced = HudSystem::REDUCED_INTENSITY;
+        let minimal = HudSystem::MINIMAL_INTENSITY;
+        let next = HudSystem::next_verbosity;
+
+        assert_eq!(next(HudVerbosity::Full, reduced), HudVerbosity::Reduced);
+        assert_eq!(next(HudVerbosity::Full, minimal), HudVerbosity::Minimal);
+        // Just below a threshold, the HUD stays reduced
+        assert_eq!(
+            next(HudVerbosity::Reduced, reduced - 0.5),
+            HudVerbosity::Reduced
+        );
+        assert_eq!(
+            next(HudVerbosity::Minimal, minimal - 0.5),
+            HudVerbosity::Minimal
+        );
+        // Far enough below, more of it is shown again
+        let calm = reduced - HudSystem::HYSTERESIS - 0.5;
+        assert_eq!(next(HudVerbosity::Minimal, calm), HudVerbosity::Full);
+        assert_eq!(next(HudVerbosity::Reduced, calm), HudVerbosity::Full);
+    }
+
+    #[test]
+    fn damage_is_shown_by_verbosity() {
+        let big = HudVerbosity::BIG_HIT;
+        assert!(HudVerbosity::Full.shows_damage(1, false));
+        assert!(!HudVerbosity::Reduced.shows_damage(big - 1, false));
+        assert!(HudVerbosity::Reduced.shows_damage(big, false));
+        assert!(HudVerbosity::Reduced.shows_damage(1, true));
+        assert!(!HudVerbosity::Minimal.shows_damage(big * 10, false));
+        assert!(HudVerbosity::Minimal.shows_damage(1, true));
+    }
+
+    #[test]
+    fn tooltips_wait_longer_at_lower_verbosity() {
+        let full = HudVerbosity::Full.tooltip_delay(0.5);
+        let reduced = HudVerbosity::Reduced.tooltip_delay(0.5);
+        let minimal = HudVerbosity::Minimal.tooltip_delay(0.5);
+        assert_eq!(full, 0.5);
+        assert!(full < reduced && reduced < minimal);
+    }
+
+    /// How many ticks the toast of an achievement is shown for at the verbosity
+    fn toast_ticks(verbosity: HudVerbosity) -> u32 {
+        let blocks = load_blocks();
+        let mut run = RunStats::new();
+        let (item, source) = (GameItem::Iron, DamageSource::Laser);
+        record(
+            &mut run.counters,
+            StatEvent::AsteroidDestroyed { item, source },
+        );
+        let mut world = World::new();
+        world.insert(Time::new(Time::DEFAULT_RATE));
+        world.insert(verbosity);
+        world.insert(SaveFiles::NONE);
+        world.insert(run);
+        world.insert(LifetimeStats::new(&blocks));
+        world.insert(Achievements::default());
+
+        let mut system = AchievementSystem::default();
+        while world.fetch::<Achievements>().toast().is_none() {
+            system.run_now(&world);
+        }
+        let mut ticks = 0;
+        while world.fetch::<Achievements>().toast().is_some() {
+            system.run_now(&world);
+            ticks += 1;
+        }
+        ticks
+    }
+
+    #[test]
+    fn toasts_are_shorter_at_lower_verbosity() {
+        let full = toast_ticks(HudVerbosity::Full);
+        assert_eq!(full as f32, Toast::LIFETIME * Time::DEFAULT_RATE as f32);
+        let reduced = toast_ticks(HudVerbosity::Reduced);
+        let minimal = toast_ticks(HudVerbosity::Minimal);
+        assert!(full > reduced && reduced > minimal);
+    }
+
+    /// Runs the system for a second with the number of incoming threats
+    fn verbosity_with_threats(threat_count: usize) -> HudVerbosity {
+        let mut world = World::new();
+        world.register::<IncomingThreat>();
+        world.register::<BlockEntity>();
+        world.register::<Health>();
+        world.insert(Time::new(Time::DEFAULT_RATE));
+        world.insert(HudVerbosity::Full);
+        for _ in 0..threat_count {
+            world.create_entity().with(IncomingThreat).build();
+        }
+
+        let mut system = HudSystem::default();
+        for _ in 0..Time::DEFAULT_RATE {
+            system.run_now(&world);
+        }
+        let verbosity = *world.fetch::<HudVerbosity>();
+        verbosity
+    }
+
+    #[test]
+    fn threats_drive_the_verbosity() {
+        if crate::HUD_VERBOSITY.is_some() {
+            return;
+        }
+        assert_eq!(verbosity_with_threats(0), HudVerbosity::Full);
+        assert_eq!(verbosity_with_threats(3), HudVerbosity::Reduced);
+        assert_eq!(verbosity_with_threats(6), HudVerbosity::Minimal);
+    }
+}
@@ -1,23 +1,75 @@
-use super::{Collider, RaycastWorld, WindowSize};
-use crate::graphics::Camera;
-use cgmath::{InnerSpace, Point2, Vector3};
+use super::{
+    gameplay::SpawnLane, physics::MeshRefinement, selection::Selection, ship::SelectedShip,
+    Collider, FrameTime, Model, RaycastWorld, Ship, Transform, WindowSize,
+};
+use crate::block::{BlockId, Blocks};
+use crate::graphics::{
+    Camera, CameraController, CameraMode, CameraShake, MeshManager, ModelTint, PickRequest,
+    PickResult, Projection,
+};
+use cgmath::{InnerSpace, Point2, Point3, Vector2, Vector3, Vector4, Zero};
 use specs::prelude::*;
 use std::collections::HashSet;
+use std::f32::consts::FRAC_PI_2;
 use winit::event;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum InputAction {
     Mining,
     Laser,
+    /// Pushes the target away, or the closest threat if there is no target
+    Repulsor,
+    /// Pulls the target closer (ex. into the range of the miners)
+    Tractor,
+    /// Fires the cannons at the point under the cursor, without leading the target
+    Cannon,
+    /// Copies the area of the ship that is dragged over into a blueprint
+    CopyBlueprint,
+    /// Builds the copied blueprint where the ship is clicked
+    StampBlueprint,
+    /// Builds the block where the ship is clicked. Picked in the block toolbar, or with the
+    /// number keys.
+    PlaceBlock(BlockId),
+    /// Paints the block that is clicked with a color of Paint::PALETTE
+    Paint(usize),
     None,
 }
 
 pub struct InputManager {
     pub action: InputAction,
     pub left_mb: bool,
+    /// Set when the left mouse button is pressed, and cleared by the InputSystem
+    pub clicked: bool,
     pub mouse_pos: Point2<f32>,
+    /// The lines scrolled since the last update, cleared by the CameraSystem
+    pub scroll: f32,
     pub keys: Keys,
     pub target: Option<Entity>,
+    /// The asteroid under the mouse
+    pub hovered: Option<Entity>,
+    /// Where the cannons fire at, which is set while the left mouse button is held with
+    /// InputAction::Cannon
+    pub aim: Option<Vector3<f32>>,
+    /// The ship block that was clicked during this update
+    pub clicked_block: Option<Entity>,
+    /// The item drop that was clicked during this update
+    pub clicked_drop: Option<Entity>,
+    /// The deck that is built on, picked with Page Up/Down in the build view
+    pub deck: i16,
+    /// The scout drone that the camera is attached to. WASD steers the drone instead of the
+    /// camera while it is set.
+    pub remote_view: Option<Entity>,
+    /// Set while the right or middle mouse button is held
+    rotate_drag: Option<RotateDrag>,
+    /// How far (in pixels) the mouse was dragged to rotate the camera, cleared by the
+    /// CameraSystem
+    pub look_delta: Vector2<f32>,
+    /// The ship tile picked with the arrow keys or a gamepad, which the build tools use
+    /// instead of the tile under the mouse. Cleared when the mouse moves, so the last used
+    /// device wins.
+    pub tile_cursor: Option<Point2<i16>>,
+    /// The buttons and stick of the gamepads (see src/gamepad.rs)
+    pub pad: Gamepad,
 }
 
 impl InputManager {
@@ -25,82 +77,734 @@ impl InputManager {
         Self {
             action: InputAction::None,
             left_mb: false,
+            clicked: false,
             mouse_pos: Point2::new(0.0, 0.0),
+            scroll: 0.0,
             target: None,
-            keys: Keys(HashSet::new()),
+            hovered: None,
+            aim: None,
+            clicked_block: None,
+            clicked_drop: None,
+            deck: 0,
+            remote_view: None,
+            rotate_drag: None,
+            look_delta: Vector2::zero(),
+            tile_cursor: None,
+            pad: Gamepad::default(),
+            keys: Keys {
+                down: HashSet::new(),
+                pressed: HashSet::new(),
+            },
         }
     }
 }
 
-pub struct Keys(HashSet<event::VirtualKeyCode>);
+impl InputManager {
+    /// How far (in pixels) the mouse needs to move before pressing a button becomes a drag
+    const DRAG_THRESHOLD: f32 = 4.0;
+
+    pub fn start_rotate_drag(&mut self, button: event::MouseButton) {
+        if self.rotate_drag.is_none() {
+            self.rotate_drag = Some(RotateDrag {
+                button,
+                moved: 0.0,
+                dragging: false,
+            });
+        }
+    }
+
+    /// Ends the drag of the button. Returns true if the button was only clicked, since
+    /// the mouse did not move far enough to rotate the camera.
+    pub fn end_rotate_drag(&mut self, button: event::MouseButton) -> bool {
+        match self.rotate_drag {
+            Some(drag) if drag.button == button => {
+                self.rotate_drag = None;
+                !drag.dragging
+            }
+            _ => false,
+        }
+    }
+
+    /// Ends any drag without counting it as a click (ex. when the window loses focus)
+    pub fn cancel_rotate_drag(&mut self) {
+        self.rotate_drag = None;
+    }
+
+    /// Forgets the keys and buttons pressed during the update, once every system has seen
+    /// them
+    pub fn end_update(&mut self) {
+        self.keys.pressed.clear();
+        self.pad.pressed.clear();
+    }
+
+    /// Whether the camera is being rotated by dragging the mouse
+    pub fn is_rotating(&self) -> bool {
+        matches!(self.rotate_drag, Some(drag) if drag.dragging)
+    }
+
+    /// Called with the raw mouse movement, which keeps coming when the cursor is grabbed
+    pub fn mouse_motion(&mut self, delta: Vector2<f32>) {
+        let drag = match &mut self.rotate_drag {
+            Some(drag) => drag,
+            None => return,
+        };
+        drag.moved += delta.magnitude();
+        if drag.moved >= InputManager::DRAG_THRESHOLD {
+            drag.dragging = true;
+        }
+        if drag.dragging {
+            self.look_delta += delta;
+        }
+    }
+}
+
+/// A button held to rotate the camera. It only becomes a drag once the mouse moves.
+#[derive(Clone, Copy)]
+struct RotateDrag {
+    button: event::MouseButton,
+    /// The pixels moved since the button was pressed
+    moved: f32,
+    dragging: bool,
+}
+
+pub struct Keys {
+    down: HashSet<event::VirtualKeyCode>,
+    /// The keys pressed during the update, which are cleared at its end (see
+    /// InputManager::end_update)
+    pressed: HashSet<event::VirtualKeyCode>,
+}
 
 impl Keys {
     pub fn update(&mut self, key: event::VirtualKeyCode, state: event::ElementState) {
         match state {
-            event::ElementState::Pressed => self.0.insert(key),
-            event::ElementState::Released => self.0.remove(&key),
+            event::ElementState::Pressed => {
+                if self.down.insert(key) {
+                    self.pressed.insert(key);
+                }
+            }
+            event::ElementState::Released => {
+                self.down.remove(&key);
+            }
         };
     }
 
-    fn is_key_down(&self, key: event::VirtualKeyCode) -> bool {
-        self.0.contains(&key)
+    pub fn is_key_down(&self, key: event::VirtualKeyCode) -> bool {
+        self.down.contains(&key)
+    }
+
+    pub fn is_shift_down(&self) -> bool {
+        self.is_key_down(event::VirtualKeyCode::LShift)
+            || self.is_key_down(event::VirtualKeyCode::RShift)
+    }
+
+    pub fn is_control_down(&self) -> bool {
+        self.is_key_down(event::VirtualKeyCode::LControl)
+            || self.is_key_down(event::VirtualKeyCode::RControl)
+    }
+
+    /// Whether the key was pressed since the last update. Held keys only count once.
+    pub fn was_pressed(&self, key: event::VirtualKeyCode) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    /// Whether the key was pressed since the last update, and handles the press so it is not
+    /// seen again. The camera is updated every frame, which can be more often than the
+    /// InputSystem clears the presses.
+    pub fn take_press(&mut self, key: event::VirtualKeyCode) -> bool {
+        self.pressed.remove(&key)
+    }
+
+    /// Releases every key (ex. when the window loses focus, since it won't get their releases)
+    pub fn clear(&mut self) {
+        self.down.clear();
+        self.pressed.clear();
+    }
+}
+
+/// The gamepad buttons that the build tools use. They are named after what they do, since
+/// gamepads name their buttons differently. The face buttons are where A, B, X and Y are on
+/// an Xbox controller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PadButton {
+    /// A, which stamps or places at the tile cursor
+    Confirm,
+    /// B, which drops the build tool and the tile cursor
+    Cancel,
+    /// X, which undoes the last build
+    Undo,
+    /// Y, which opens the radial block picker while it is held
+    Picker,
+    /// The shoulder buttons, which turn the build view a quarter turn
+    TurnLeft,
+    TurnRight,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// The state of the gamepads, which all act as one. Like the keys, presses are kept until
+/// the end of the update.
+pub struct Gamepad {
+    down: HashSet<PadButton>,
+    pressed: HashSet<PadButton>,
+    /// The left stick, from -1 to 1 with up being positive y
+    pub stick: Vector2<f32>,
+}
+
+impl Default for Gamepad {
+    fn default() -> Self {
+        Self {
+            down: HashSet::new(),
+            pressed: HashSet::new(),
+            stick: Vector2::zero(),
+        }
+    }
+}
+
+impl Gamepad {
+    /// How far the stick is pushed along an axis before it moves the tile cursor
+    const STICK_THRESHOLD: f32 = 0.5;
+
+    /// Only called by the gamepad reader, which is behind the `gamepad` feature
+    #[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+    pub fn update(&mut self, button: PadButton, pressed: bool) {
+        if !pressed {
+            self.down.remove(&button);
+        } else if self.down.insert(button) {
+            self.pressed.insert(button);
+        }
+    }
+
+    pub fn is_down(&self, button: PadButton) -> bool {
+        self.down.contains(&button)
+    }
+
+    /// Whether the button was pressed since the last update. Held buttons only count once.
+    pub fn was_pressed(&self, button: PadButton) -> bool {
+        self.pressed.contains(&button)
+    }
+
+    /// Like Keys::take_press, for the camera which can be updated more often than the presses
+    /// are cleared
+    pub fn take_press(&mut self, button: PadButton) -> bool {
+        self.pressed.remove(&button)
+    }
+
+    /// Where the d-pad and the stick point, with each axis rounded to -1, 0 or 1
+    pub fn direction(&self) -> Vector2<f32> {
+        let axis = |negative, positive, stick: f32| {
+            let pad = self.is_down(positive) as i8 - self.is_down(negative) as i8;
+            let stick = if stick.abs() >= Gamepad::STICK_THRESHOLD {
+                stick.signum() as i8
+            } else {
+                0
+            };
+            (pad + stick).signum() as f32
+        };
+        Vector2::new(
+            axis(PadButton::Left, PadButton::Right, self.stick.x),
+            axis(PadButton::Down, PadButton::Up, self.stick.y),
+        )
+    }
+
+    /// Where the d-pad and the stick point, without rounding the stick (ex. to pick a slice
+    /// of the radial picker)
+    pub fn pointing(&self) -> Vector2<f32> {
+        let pad = |negative, positive| self.is_down(positive) as i8 - self.is_down(negative) as i8;
+        self.stick
+            + Vector2::new(
+                pad(PadButton::Left, PadButton::Right) as f32,
+                pad(PadButton::Down, PadButton::Up) as f32,
+            )
+    }
+
+    /// Releases every button and centers the stick (ex. when a gamepad is disconnected)
+    #[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+    pub fn clear(&mut self) {
+        self.down.clear();
+        self.pressed.clear();
+        self.stick = Vector2::zero();
+    }
+}
+
+/// The controls that can be rebound in the settings menu
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyAction {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    TurnLeft,
+    TurnRight,
+    ToggleBuildView,
+    DeckUp,
+    DeckDown,
+    FollowShip,
+    /// Shows or hides the ship stats panel
+    ShipStats,
+    /// Switches the auto mode of the selected lasers (see Selection)
+    ToggleAuto,
+}
+
+impl KeyAction {
+    pub const ALL: [KeyAction; 12] = [
+        KeyAction::MoveForward,
+        KeyAction::MoveBack,
+        KeyAction::MoveLeft,
+        KeyAction::MoveRight,
+        KeyAction::TurnLeft,
+        KeyAction::TurnRight,
+        KeyAction::ToggleBuildView,
+        KeyAction::DeckUp,
+        KeyAction::DeckDown,
+        KeyAction::FollowShip,
+        KeyAction::ShipStats,
+        KeyAction::ToggleAuto,
+    ];
+
+    /// The key of the action's name in the language files
+    pub fn name_key(self) -> &'static str {
+        match self {
+            KeyAction::MoveForward => "action.move_forward",
+            KeyAction::MoveBack => "action.move_back",
+            KeyAction::MoveLeft => "action.move_left",
+            KeyAction::MoveRight => "action.move_right",
+            KeyAction::TurnLeft => "action.turn_left",
+            KeyAction::TurnRight => "action.turn_right",
+            KeyAction::ToggleBuildView => "action.toggle_build_view",
+            KeyAction::DeckUp => "action.deck_up",
+            KeyAction::DeckDown => "action.deck_down",
+            KeyAction::FollowShip => "action.follow_ship",
+            KeyAction::ShipStats => "action.ship_stats",
+            KeyAction::ToggleAuto => "action.toggle_auto",
+        }
+    }
+
+    /// The name of the binding in the config
+    pub fn config_name(self) -> &'static str {
+        match self {
+            KeyAction::MoveForward => "key_move_forward",
+            KeyAction::MoveBack => "key_move_back",
+            KeyAction::MoveLeft => "key_move_left",
+            KeyAction::MoveRight => "key_move_right",
+            KeyAction::TurnLeft => "key_turn_left",
+            KeyAction::TurnRight => "key_turn_right",
+            KeyAction::ToggleBuildView => "key_toggle_build_view",
+            KeyAction::DeckUp => "key_deck_up",
+            KeyAction::DeckDown => "key_deck_down",
+            KeyAction::FollowShip => "key_follow_ship",
+            KeyAction::ShipStats => "key_ship_stats",
+            KeyAction::ToggleAuto => "key_toggle_auto",
+        }
+    }
+}
+
+/// The key of each KeyAction. They are saved in the config, and can be changed in the
+/// settings menu.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyBindings {
+    keys: [event::VirtualKeyCode; KeyAction::ALL.len()],
+}
+
+impl KeyBindings {
+    /// The keys that can be bound. The keys that the game uses for something else (ex.
+    /// Escape for the settings menu, the arrow keys for the blueprint cursor, or the number
+    /// keys for the block toolbar) are left out.
+    const BINDABLE_KEYS: [event::VirtualKeyCode; 41] = {
+        use event::VirtualKeyCode::*;
+        [
+            A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, Key0,
+            Tab, Space, PageUp, PageDown, Home, End, Insert, Delete, LShift, RShift, Comma, Period,
+            Semicolon, Slash,
+        ]
+    };
+
+    /// The default bindings, with the actions rebound to the keys. Fails with the key that
+    /// ends up bound to more than one action.
+    pub fn with_keys(
+        keys: &[(KeyAction, event::VirtualKeyCode)],
+    ) -> Result<Self, event::VirtualKeyCode> {
+        let mut bindings = KeyBindings::default();
+        for (action, key) in keys {
+            bindings.keys[*action as usize] = *key;
+        }
+
+        for (index, key) in bindings.keys.iter().enumerate() {
+            if bindings.keys[..index].contains(key) {
+                return Err(*key);
+            }
+        }
+        Ok(bindings)
+    }
+
+    pub fn key(&self, action: KeyAction) -> event::VirtualKeyCode {
+        self.keys[action as usize]
+    }
+
+    /// The action that the key is bound to
+    pub fn action(&self, key: event::VirtualKeyCode) -> Option<KeyAction> {
+        KeyAction::ALL
+            .iter()
+            .copied()
+            .find(|action| self.key(*action) == key)
+    }
+
+    /// Binds the key to the action. A key can only be bound to one action, so this fails
+    /// with the action that the key is already bound to.
+    pub fn bind(&mut self, action: KeyAction, key: event::VirtualKeyCode) -> Result<(), KeyAction> {
+        match self.action(key) {
+            Some(bound) if bound != action => Err(bound),
+            _ => {
+                self.keys[action as usize] = key;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn is_bindable(key: event::VirtualKeyCode) -> bool {
+        KeyBindings::BINDABLE_KEYS.contains(&key)
+    }
+
+    /// The name of the key, which is also how it is written in the config
+    pub fn key_name(key: event::VirtualKeyCode) -> String {
+        format!("{:?}", key)
+    }
+
+    /// Finds the bindable key with the name
+    pub fn parse_key(name: &str) -> Option<event::VirtualKeyCode> {
+        KeyBindings::BINDABLE_KEYS
+            .iter()
+            .copied()
+            .find(|key| KeyBindings::key_name(*key) == name)
+    }
+
+    pub fn is_down(&self, keys: &Keys, action: KeyAction) -> bool {
+        keys.is_key_down(self.key(action))
+    }
+
+    pub fn take_press(&self, keys: &mut Keys, action: KeyAction) -> bool {
+        keys.take_press(self.key(action))
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        use event::VirtualKeyCode::*;
+        Self {
+            keys: [W, S, A, D, Q, E, Tab, PageUp, PageDown, F, P, T],
+        }
     }
 }
 
-pub struct CameraSystem;
+/// Moves the camera, and switches between the normal view and the top-down build view. The
+/// CameraController (if there is one) follows the ship and shakes the camera. It runs once
+/// per frame with the real time since the last frame (see ECS::update_camera), so the camera
+/// moves smoothly at any frame rate.
+pub struct CameraSystem {
+    /// Where the perspective camera was before switching to the build view
+    perspective: Option<(Point3<f32>, f32)>,
+    /// The smoothed movement keys (forward and left), which ease towards the keys held
+    movement: Vector2<f32>,
+    /// The smoothed turning keys (left is positive)
+    turning: f32,
+}
+
+impl Default for CameraSystem {
+    fn default() -> Self {
+        Self {
+            perspective: None,
+            movement: Vector2::zero(),
+            turning: 0.0,
+        }
+    }
+}
+
+impl CameraSystem {
+    /// How high above the ship the build view is
+    const ORTHO_HEIGHT: f32 = 30.0;
+    const DEFAULT_HALF_HEIGHT: f32 = 12.0;
+    const MIN_HALF_HEIGHT: f32 = 4.0;
+    const MAX_HALF_HEIGHT: f32 = 40.0;
+    /// How much the view zooms per scrolled line
+    const ZOOM_PER_LINE: f32 = 0.1;
+    /// The pitch is kept between these, so the camera never turns past looking straight
+    /// down or too far up
+    const MIN_PITCH: f32 = -FRAC_PI_2;
+    const MAX_PITCH: f32 = 0.3;
+    /// Units per second
+    const MOVE_SPEED: f32 = 9.6;
+    /// Radians per second
+    const TURN_SPEED: f32 = 1.2;
+    /// The seconds it takes the movement to get about two thirds of the way to the keys held
+    const SMOOTHING_TIME: f32 = 0.08;
+
+    /// How much of the way to the keys held the movement gets in `delta` seconds. This is
+    /// exponential, so it eases the same however the frames are spaced.
+    fn smoothing(delta: f32) -> f32 {
+        if crate::CAMERA_SMOOTHING {
+            1.0 - (-delta / Self::SMOOTHING_TIME).exp()
+        } else {
+            1.0
+        }
+    }
+
+    /// The build view starts above the selected ship
+    fn toggle(&mut self, camera: &mut Camera, ship: Option<&Ship>) {
+        match camera.projection {
+            Projection::Perspective => {
+                let center = ship
+                    .and_then(Ship::bounds)
+                    .map(|bounds| (bounds.min + bounds.max) / 2.0)
+                    .unwrap_or_else(|| Vector3::new(0.0, 0.0, 0.0));
+
+                self.perspective = Some((camera.position, camera.yaw));
+                camera.position = Point3::new(center.x, center.y, Self::ORTHO_HEIGHT);
+                // Snap to the closest grid axis so panning follows the grid
+                camera.yaw = (camera.yaw / FRAC_PI_2).round() * FRAC_PI_2;
+                camera.projection = Projection::Orthographic {
+                    half_height: Self::DEFAULT_HALF_HEIGHT,
+                };
+            }
+            Projection::Orthographic { .. } => {
+                if let Some((position, yaw)) = self.perspective.take() {
+                    camera.position = position;
+                    camera.yaw = yaw;
+                }
+                camera.projection = Projection::Perspective;
+            }
+        }
+    }
+}
 
 impl<'a> System<'a> for CameraSystem {
-    type SystemData = (ReadExpect<'a, InputManager>, WriteExpect<'a, Camera>);
+    type SystemData = (
+        ReadExpect<'a, FrameTime>,
+        ReadExpect<'a, KeyBindings>,
+        WriteExpect<'a, InputManager>,
+        WriteExpect<'a, Camera>,
+        Option<Write<'a, CameraController>>,
+        Read<'a, SelectedShip>,
+        Entities<'a>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, Transform>,
+    );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (input, mut camera) = data;
-        let rotate_speed = 0.02;
-        let move_speed = 0.16;
+        let (
+            time,
+            bindings,
+            mut input,
+            mut camera,
+            mut controller,
+            selected,
+            entities,
+            ships,
+            transforms,
+        ) = data;
+        let ship = selected.find(&entities, &ships).map(|(_, ship)| ship);
+        let toggle = bindings.take_press(&mut input.keys, KeyAction::ToggleBuildView);
+        if toggle && input.remote_view.is_none() {
+            self.toggle(&mut camera, ship);
+        }
+        let follow = bindings.take_press(&mut input.keys, KeyAction::FollowShip);
+        if let Some(controller) = controller.as_mut().filter(|_| follow) {
+            controller.toggle_follow();
+        }
+
+        let mut move_speed = Self::MOVE_SPEED;
+
+        let scroll = std::mem::replace(&mut input.scroll, 0.0);
+        // The build view snaps to the grid, so it can't be rotated by dragging
+        let look_delta = std::mem::replace(&mut input.look_delta, Vector2::zero());
+        if let Projection::Orthographic { half_height } = &mut camera.projection {
+            *half_height = (*half_height * (1.0 - scroll * Self::ZOOM_PER_LINE))
+                .clamp(Self::MIN_HALF_HEIGHT, Self::MAX_HALF_HEIGHT);
+            // Pan at the same speed on the screen, however far the view is zoomed out
+            move_speed *= *half_height / Self::DEFAULT_HALF_HEIGHT;
+
+            if bindings.take_press(&mut input.keys, KeyAction::DeckUp) {
+                input.deck = (input.deck + 1).min(Ship::DECKS - 1);
+            } else if bindings.take_press(&mut input.keys, KeyAction::DeckDown) {
+                input.deck = (input.deck - 1).max(0);
+            }
+            // The shoulder buttons turn the view to the next grid axis
+            if input.pad.take_press(PadButton::TurnLeft) {
+                camera.yaw += FRAC_PI_2;
+            } else if input.pad.take_press(PadButton::TurnRight) {
+                camera.yaw -= FRAC_PI_2;
+            }
+            self.turning = 0.0;
+        } else {
+            // Dragging right turns right, and dragging up looks up
+            camera.yaw -= look_delta.x * crate::CAMERA_DRAG_SENSITIVITY;
+            camera.pitch = (camera.pitch - look_delta.y * crate::CAMERA_DRAG_SENSITIVITY)
+                .clamp(Self::MIN_PITCH, Self::MAX_PITCH);
 
-        if input.keys.is_key_down(event::VirtualKeyCode::Q) {
-            camera.yaw += rotate_speed;
-        } else if input.keys.is_key_down(event::VirtualKeyCode::E) {
-            camera.yaw -= rotate_speed;
+            let turn = if bindings.is_down(&input.keys, KeyAction::TurnLeft) {
+                1.0
+            } else if bindings.is_down(&input.keys, KeyAction::TurnRight) {
+                -1.0
+            } else {
+                0.0
+            };
+            self.turning += (turn - self.turning) * Self::smoothing(time.delta);
+            camera.yaw += self.turning * Self::TURN_SPEED * time.delta;
+        }
+        // The ScoutSystem moves the camera with the drone
+        if input.remote_view.is_some() {
+            self.movement = Vector2::zero();
+            camera.shake = CameraShake::NONE;
+            return;
         }
 
-        let forward_power = if input.keys.is_key_down(event::VirtualKeyCode::W) {
+        let forward_power = if bindings.is_down(&input.keys, KeyAction::MoveForward) {
             1.0
-        } else if input.keys.is_key_down(event::VirtualKeyCode::S) {
+        } else if bindings.is_down(&input.keys, KeyAction::MoveBack) {
             -1.0
         } else {
             0.0
         };
-        let side_power = if input.keys.is_key_down(event::VirtualKeyCode::D) {
+        let side_power = if bindings.is_down(&input.keys, KeyAction::MoveRight) {
             -1.0
-        } else if input.keys.is_key_down(event::VirtualKeyCode::A) {
+        } else if bindings.is_down(&input.keys, KeyAction::MoveLeft) {
             1.0
         } else {
             0.0
         };
 
+        // Moving diagonally is no faster than moving straight
+        let mut keys = Vector2::new(forward_power, side_power);
+        if keys != Vector2::zero() {
+            keys = keys.normalize();
+        }
+        self.movement += (keys - self.movement) * Self::smoothing(time.delta);
+
         let (yaw_sin, yaw_cos) = camera.yaw.sin_cos();
-        let forward = Vector3::new(yaw_cos, yaw_sin, 0.0).normalize() * forward_power * move_speed;
-        let side = Vector3::new(-yaw_sin, yaw_cos, 0.0).normalize() * side_power * move_speed;
-        camera.position += forward + side;
+        let forward = Vector3::new(yaw_cos, yaw_sin, 0.0) * self.movement.x;
+        let side = Vector3::new(-yaw_sin, yaw_cos, 0.0) * self.movement.y;
+        camera.position += (forward + side) * move_speed * time.delta;
+
+        if let Some(controller) = controller.as_mut() {
+            // Moving the camera takes it back from the controller
+            if forward_power != 0.0 || side_power != 0.0 {
+                controller.set_mode(CameraMode::Free);
+            }
+            let target = match controller.mode() {
+                CameraMode::Free => None,
+                CameraMode::FollowShip => ship
+                    .and_then(Ship::bounds)
+                    .map(|bounds| (bounds.min + bounds.max) / 2.0),
+                CameraMode::FocusEntity(entity) => {
+                    transforms.get(entity).map(|transform| transform.position)
+                }
+            };
+            controller.update(&mut camera, target, time.delta);
+        }
+    }
+}
+
+/// Fades the decks that are not being built on while in the build view, and tints the
+/// selected gadgets
+pub struct DeckViewSystem;
+
+impl DeckViewSystem {
+    const FADED_TINT: ModelTint = ModelTint {
+        primary: Vector4::new(0.3, 0.3, 0.35, 1.0),
+        secondary: Vector4::new(0.3, 0.3, 0.35, 1.0),
+    };
+    const SELECTED_TINT: ModelTint = ModelTint {
+        primary: Vector4::new(0.55, 1.0, 0.55, 1.0),
+        secondary: Vector4::new(0.55, 1.0, 0.55, 1.0),
+    };
+}
+
+impl<'a> System<'a> for DeckViewSystem {
+    type SystemData = (
+        ReadExpect<'a, InputManager>,
+        ReadExpect<'a, Camera>,
+        ReadExpect<'a, Selection>,
+        WriteExpect<'a, MeshManager>,
+        ReadStorage<'a, Ship>,
+        WriteStorage<'a, Model>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (input, camera, selection, mut mesh_manager, ships, mut models) = data;
+        let build_view = matches!(camera.projection, Projection::Orthographic { .. });
+
+        for ship in ships.join() {
+            for (pos, tile) in ship.tiles() {
+                let faded = build_view && pos.z != input.deck;
+                let entities = [tile.block(), tile.gadget(), tile.floor()];
+                for entity in entities.iter().flatten() {
+                    let tint = if faded {
+                        DeckViewSystem::FADED_TINT
+                    } else if selection.is_selected(*entity) {
+                        DeckViewSystem::SELECTED_TINT
+                    } else {
+                        ModelTint::NONE
+                    };
+                    let model = match models.get_mut(*entity) {
+                        Some(model) if model.tint != tint => model,
+                        _ => continue,
+                    };
+                    model.tint = tint;
+                    if let Some(model_id) = model.model_id {
+                        mesh_manager.update_tint(model.mesh_id, model_id, tint);
+                    }
+                }
+            }
+        }
     }
 }
 
 pub struct InputSystem;
 
+impl InputSystem {
+    /// The keys that pick the first blocks for placement, in the order of the block toolbar
+    pub const BLOCK_KEYS: [event::VirtualKeyCode; 9] = {
+        use event::VirtualKeyCode::*;
+        [Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9]
+    };
+}
+
 impl<'a> System<'a> for InputSystem {
     type SystemData = (
         WriteExpect<'a, InputManager>,
         ReadExpect<'a, Camera>,
         ReadExpect<'a, WindowSize>,
         ReadExpect<'a, RaycastWorld>,
+        WriteExpect<'a, PickRequest>,
+        ReadExpect<'a, PickResult>,
+        ReadExpect<'a, Blocks>,
+        ReadExpect<'a, MeshManager>,
+        ReadStorage<'a, Collider>,
+        ReadStorage<'a, Model>,
+        ReadStorage<'a, Transform>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut input, camera, window_size, raycaster) = data;
+        let (
+            mut input,
+            camera,
+            window_size,
+            raycaster,
+            mut pick_request,
+            pick_result,
+            blocks,
+            mesh_manager,
+            colliders,
+            models,
+            transforms,
+        ) = data;
 
-        if !input.left_mb {
-            input.target = None;
-            return;
+        let pressed_block = InputSystem::BLOCK_KEYS
+            .iter()
+            .zip(blocks.iter())
+            .find(|(key, _)| input.keys.was_pressed(**key));
+        if let Some((_, block)) = pressed_block {
+            input.action = InputAction::PlaceBlock(block.id);
         }
 
         let near = camera.unproject(
@@ -112,10 +816,69 @@ impl<'a> System<'a> for InputSystem {
             window_size.as_point(),
         );
 
+        // The GPU pick under the cursor is used when there is one, since it matches the
+        // shape of the models. Otherwise the colliders are raycast, and refined against the
+        // collision meshes so the pick is as close as it can be.
+        pick_request.position = Some(input.mouse_pos).filter(|_| !input.is_rotating());
+        let picked = pick_result.at(input.mouse_pos);
+        let refinement = MeshRefinement {
+            meshes: &mesh_manager,
+            models: &models,
+            transforms: &transforms,
+        };
+        let find = |groups: &[usize]| {
+            match picked {
+            Some(entity) => entity.filter(|entity| {
+                matches!(colliders.get(*entity), Some(collider) if groups.contains(&collider.group))
+            }),
+            None => raycaster
+                .raycast(groups, near, far, None, Some(&refinement))
+                .map(|hit| hit.entity),
+        }
+        };
+
+        input.hovered = find(&[Collider::ASTEROID, Collider::RAIDER]);
         input.target = match input.action {
-            InputAction::Mining | InputAction::Laser => Some(vec![Collider::ASTEROID]),
+            InputAction::Mining
+            | InputAction::Laser
+            | InputAction::Repulsor
+            | InputAction::Tractor
+                if input.left_mb =>
+            {
+                input.hovered
+            }
             _ => None,
-        }
-        .and_then(|collider| raycaster.raycast(collider, near, far));
+        };
+        // The cannons fire at what is under the cursor. Past the asteroids, they fire at where
+        // the cursor crosses the lanes, so the shots can be led.
+        input.aim = match input.action {
+            InputAction::Cannon if input.left_mb => raycaster
+                .raycast(
+                    &[Collider::ASTEROID, Collider::RAIDER],
+                    near,
+                    far,
+                    None,
+                    Some(&refinement),
+                )
+                .map(|hit| hit.point)
+                .or_else(|| {
+                    let t = (SpawnLane::HEIGHT - near.z) / (far.z - near.z);
+                    Some(near + (far - near) * t).filter(|_| t.is_finite() && t > 0.0)
+                }),
+            _ => None,
+        };
+
+        input.clicked_drop = if input.clicked {
+            find(&[Collider::ITEM])
+        } else {
+            None
+        };
+        // Asteroids in front of the ship take priority over its blocks
+        input.clicked_block = if input.clicked && input.hovered.is_none() {
+            find(&[Collider::SHIP])
+        } else {
+            None
+        };
+        input.clicked = false;
     }
 }
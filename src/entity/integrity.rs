@@ -0,0 +1,400 @@
+use super::{
+    gameplay::{AsteroidField, GameLog},
+    physics::{Collider, RigidBody},
+    BlockEntity, GameModule, Ship, Time, ToBeRemoved, Transform,
+};
+use crate::i18n::Strings;
+use crate::profiler::AddProfiled;
+use cgmath::{prelude::*, Point3, Vector3};
+use specs::{prelude::*, Component};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+
+/// Parts of the ship that are cut off from the core (ex. the far end of a line of walls
+/// whose middle was destroyed) break off and drift away
+pub struct IntegrityModule;
+
+impl GameModule for IntegrityModule {
+    fn register(&self, world: &mut World) {
+        world.register::<Derelict>();
+        world.register::<RigidBody>();
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(IntegritySystem, "integrity_system", &[]);
+        builder.add_profiled(DerelictSystem, "derelict_system", &[]);
+    }
+
+    fn death_systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(BlockDestroyedSystem, "block_destroyed_system", &[]);
+    }
+}
+
+/// A block, gadget or floor that broke off the ship. It no longer works, and is removed
+/// after a while.
+#[derive(Component, Default)]
+#[storage(HashMapStorage)]
+pub struct Derelict {
+    /// The seconds since it broke off
+    age: f32,
+}
+
+impl Derelict {
+    const LIFETIME: f32 = 20.0;
+    /// How fast the broken off parts drift away from the core
+    const DRIFT_SPEED: f32 = 0.4;
+}
+
+/// The tiles that are not connected to the core. `built` has every tile with something built
+/// on it, with the block that covers it (if any). Tiles are connected to the tiles next to
+/// them on the same deck, the tiles above and below them, and the other tiles of their block,
+/// so a block that covers several tiles is never split.
+pub fn disconnected_tiles<K: Copy + Eq + Hash>(
+    built: &HashMap<Point3<i16>, Option<K>>,
+    core: Point3<i16>,
+) -> Vec<Point3<i16>> {
+    const NEIGHBOURS: [Vector3<i16>; 6] = [
+        Vector3::new(1, 0, 0),
+        Vector3::new(-1, 0, 0),
+        Vector3::new(0, 1, 0),
+        Vector3::new(0, -1, 0),
+        Vector3::new(0, 0, 1),
+        Vector3::new(0, 0, -1),
+    ];
+
+    let mut block_tiles: HashMap<K, Vec<Point3<i16>>> = HashMap::new();
+    for (pos, block) in built {
+        if let Some(block) = block {
+            block_tiles.entry(*block).or_default().push(*pos);
+        }
+    }
+
+    let mut connected = HashSet::new();
+    let mut queue = VecDeque::new();
+    if built.contains_key(&core) {
+        connected.insert(core);
+        queue.push_back(core);
+    }
+    while let Some(pos) = queue.pop_front() {
+        let same_block = built[&pos]
+            .and_then(|block| block_tiles.get(&block))
+            .into_iter()
+            .flatten()
+            .copied();
+        let next_to = NEIGHBOURS
+            .iter()
+            .map(|offset| pos + offset)
+            .filter(|next| built.contains_key(next));
+        for next in same_block.chain(next_to).collect::<Vec<_>>() {
+            if connected.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+
+    built
+        .keys()
+        .filter(|pos| !connected.contains(pos))
+        .copied()
+        .collect()
+}
+
+/// Marks the ships as changed when one of their blocks is destroyed
+struct BlockDestroyedSystem;
+
+impl<'a> System<'a> for BlockDestroyedSystem {
+    type SystemData = (
+        Read<'a, ToBeRemoved>,
+        WriteStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Derelict>,
+    );
+
+    fn run(&mut self, (to_be_removed, mut ships, block_entities, derelicts): Self::SystemData) {
+        let destroyed = (&block_entities, to_be_removed.bitset(), !&derelicts)
+            .join()
+            .next()
+            .is_some();
+        if destroyed {
+            for ship in (&mut ships).join() {
+                ship.mark_structure_changed();
+            }
+        }
+    }
+}
+
+/// Once a block was destroyed, detaches everything that is no longer connected to the core
+struct IntegritySystem;
+
+impl<'a> System<'a> for IntegritySystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Strings>,
+        WriteExpect<'a, GameLog>,
+        WriteStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        WriteStorage<'a, Derelict>,
+        WriteStorage<'a, RigidBody>,
+        WriteStorage<'a, Collider>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            strings,
+            mut log,
+            mut ships,
+            block_entities,
+            mut derelicts,
+            mut bodies,
+            mut colliders,
+        ) = data;
+
+        for ship in (&mut ships).join() {
+            if !ship.take_structure_changed() {
+                continue;
+            }
+            // The game is over once the core is destroyed
+            let core = match ship
+                .core()
+                .filter(|core| entities.is_alive(*core))
+                .and_then(|core| block_entities.get(core))
+            {
+                Some(core) => core.root(),
+                None => continue,
+            };
+
+            let alive = |entity: Option<Entity>| entity.filter(|entity| entities.is_alive(*entity));
+            let built: HashMap<Point3<i16>, Option<Entity>> = ship
+                .tiles()
+                .filter_map(|(pos, tile)| {
+                    let block = alive(tile.block());
+                    if block.is_some() || alive(tile.floor()).is_some() {
+                        Some((pos, block))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+            let disconnected = disconnected_tiles(&built, core);
+            if disconnected.is_empty() {
+                continue;
+            }
+
+            let blocks = disconnected
+                .iter()
+                .filter(|pos| built[pos].is_some())
+                .count();
+            if blocks > 0 {
+                log.add(strings.format("log.blocks_broke_off", &[("count", &blocks)]));
+            }
+
+            for pos in &disconnected {
+                let offset = Vector3::new((pos.x - core.x) as f32, (pos.y - core.y) as f32, 0.0);
+                let velocity = if offset.magnitude2() > 0.0 {
+                    offset.normalize_to(Derelict::DRIFT_SPEED)
+                } else {
+                    Vector3::zero()
+                };
+
+                let tile = ship.tile(*pos).unwrap();
+                for entity in [tile.block(), tile.gadget(), tile.floor()].iter().flatten() {
+                    if !entities.is_alive(*entity) {
+                        continue;
+                    }
+                    derelicts.insert(*entity, Derelict::default()).unwrap();
+                    bodies.insert(*entity, RigidBody { velocity }).unwrap();
+                    if let Some(collider) = colliders.get_mut(*entity) {
+                        collider.set_group(Collider::DERELICT, &[]);
+                    }
+                }
+            }
+            ship.detach(&disconnected);
+        }
+    }
+}
+
+/// Removes the broken off parts once they are old, or have left the asteroid field
+struct DerelictSystem;
+
+impl<'a> System<'a> for DerelictSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Time>,
+        Write<'a, ToBeRemoved>,
+        WriteStorage<'a, Derelict>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, AsteroidField>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, time, mut to_be_removed, mut derelicts, transforms, fields) = data;
+        let x_range = fields.join().next().map(|field| field.x_range);
+
+        for (entity, derelict, transform) in (&entities, &mut derelicts, &transforms).join() {
+            derelict.age += time.delta;
+            let x = transform.position.x;
+            let outside_field = matches!(x_range, Some(x_range)
+                if x > x_range || x < -x_range - AsteroidField::FALLBACK_DISTANCE);
+            if derelict.age >= Derelict::LIFETIME || outside_field {
+                to_be_removed.add(entity);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{
+        damage::{DamageEvent, DamageEvents, DamageSource},
+        headless::HeadlessEcs,
+    };
+
+    /// A map of the tiles from a picture of deck 0, where `#` is a tile with a block of its
+    /// own, `.` a floor, a letter a tile of that (multi-tile) block, and a space is empty.
+    /// The first row is y = 0.
+    fn tile_map(rows: &[&str]) -> HashMap<Point3<i16>, Option<u32>> {
+        let mut built = HashMap::new();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let pos = Point3::new(x as i16, y as i16, 0);
+                let block = match c {
+                    ' ' => continue,
+                    '.' => None,
+                    '#' => Some(1000 + (y * 100 + x) as u32),
+                    c => Some(c as u32),
+                };
+                built.insert(pos, block);
+            }
+        }
+        built
+    }
+
+    fn sorted(mut tiles: Vec<Point3<i16>>) -> Vec<Point3<i16>> {
+        tiles.sort_by_key(|pos| (pos.z, pos.y, pos.x));
+        tiles
+    }
+
+    #[test]
+    fn a_connected_ship_keeps_every_tile() {
+        let built = tile_map(&["####", "#..#", "#.##", "####"]);
+        assert!(disconnected_tiles(&built, Point3::new(0, 0, 0)).is_empty());
+    }
+
+    #[test]
+    fn tiles_past_a_gap_are_cut_off() {
+        let built = tile_map(&["### ##", "     .", "  #  #"]);
+        assert_eq!(
+            sorted(disconnected_tiles(&built, Point3::new(0, 0, 0))),
+            vec![
+                Point3::new(4, 0, 0),
+                Point3::new(5, 0, 0),
+                Point3::new(5, 1, 0),
+                Point3::new(2, 2, 0),
+                Point3::new(5, 2, 0),
+            ]
+        );
+        // Diagonal tiles are not next to each other
+        let built = tile_map(&["#", " #"]);
+        assert_eq!(
+            disconnected_tiles(&built, Point3::new(0, 0, 0)),
+            vec![Point3::new(1, 1, 0)]
+        );
+    }
+
+    #[test]
+    fn a_block_spanning_the_cut_is_not_split() {
+        // The `a` block covers both sides of the gap, so the far side stays connected
+        // through it, while the `b` block is cut off as a whole
+        let mut built = tile_map(&["##a a#", "     b", "     b"]);
+        assert_eq!(
+            sorted(disconnected_tiles(&built, Point3::new(0, 0, 0))),
+            Vec::<Point3<i16>>::new()
+        );
+        built.remove(&Point3::new(5, 0, 0));
+        assert_eq!(
+            sorted(disconnected_tiles(&built, Point3::new(0, 0, 0))),
+            vec![Point3::new(5, 1, 0), Point3::new(5, 2, 0)]
+        );
+        // Removing a tile of the block cuts off the other side of the gap
+        built.remove(&Point3::new(2, 0, 0));
+        assert_eq!(
+            sorted(disconnected_tiles(&built, Point3::new(0, 0, 0))),
+            vec![
+                Point3::new(4, 0, 0),
+                Point3::new(5, 1, 0),
+                Point3::new(5, 2, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn decks_connect_up_and_down() {
+        let mut built = tile_map(&["##", " #"]);
+        built.insert(Point3::new(1, 1, 1), None);
+        built.insert(Point3::new(1, 2, 1), Some(1));
+        built.insert(Point3::new(3, 3, 1), None);
+        assert_eq!(
+            disconnected_tiles(&built, Point3::new(0, 0, 0)),
+            vec![Point3::new(3, 3, 1)]
+        );
+    }
+
+    #[test]
+    fn everything_is_cut_off_without_a_core() {
+        let built = tile_map(&["###"]);
+        assert_eq!(disconnected_tiles(&built, Point3::new(5, 5, 0)).len(), 3);
+    }
+
+    #[test]
+    fn cut_off_blocks_drift_away_and_are_removed() {
+        let mut headless = HeadlessEcs::with_seed(1);
+        headless.pause_asteroid_field();
+        // The engine at (8, -2) is joined to the hull by the cubes at (7, -2) and (7, -1)
+        let block_at = |headless: &HeadlessEcs, pos: Point3<i16>| {
+            headless
+                .with_ship(|ship| ship.tile(pos).and_then(|tile| tile.block()))
+                .flatten()
+        };
+        let link = block_at(&headless, Point3::new(7, -1, 0)).unwrap();
+        let cut_off = [
+            block_at(&headless, Point3::new(7, -2, 0)).unwrap(),
+            block_at(&headless, Point3::new(8, -2, 0)).unwrap(),
+        ];
+        let hull = block_at(&headless, Point3::new(7, 0, 0)).unwrap();
+
+        headless
+            .ecs
+            .get_resource_mut::<DamageEvents>()
+            .push(DamageEvent::new(
+                link,
+                u32::MAX,
+                DamageSource::AsteroidImpact,
+                Vector3::new(7.0, -1.0, 0.5),
+            ));
+        headless.step(3);
+        {
+            let world = &headless.ecs.world;
+            let derelicts = world.read_storage::<Derelict>();
+            let bodies = world.read_storage::<RigidBody>();
+            assert!(!world.is_alive(link));
+            for block in &cut_off {
+                assert!(derelicts.contains(*block));
+                // Away from the core at (4, 4)
+                let velocity = bodies.get(*block).unwrap().velocity;
+                assert!(velocity.y < 0.0 && velocity.x > 0.0);
+            }
+            assert!(!derelicts.contains(hull));
+        }
+        for pos in [Point3::new(7, -2, 0), Point3::new(8, -2, 0)].iter() {
+            assert_eq!(block_at(&headless, *pos), None);
+        }
+        assert_eq!(block_at(&headless, Point3::new(7, 0, 0)), Some(hull));
+
+        headless.step((Derelict::LIFETIME * Time::DEFAULT_RATE as f32) as u32 + 2);
+        let world = &headless.ecs.world;
+        assert!(cut_off.iter().all(|block| !world.is_alive(*block)));
+        assert!(world.is_alive(hull));
+    }
+}
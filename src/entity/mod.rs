@@ -1,22 +1,72 @@
-use crate::graphics::{Camera, MeshId, MeshManager, ModelId};
+use crate::content::ContentErrors;
+use crate::debug::DebugCommands;
+use crate::graphics::{
+    Camera, ColorCalibration, MeshId, MeshManager, ModelId, ModelTint, PickRequest, PickResult,
+};
+use crate::i18n::{Language, Strings};
+use crate::loading::{LoadedMeshes, MeshJob};
+use crate::profiler::{AddProfiled, FrameProfiler, SystemTimings};
+use crate::stats::{LifetimeStats, RunStats, SaveFiles, StatEvents};
 use crate::{block::Blocks, floor::Floors};
 use cgmath::{prelude::*, Matrix4, Point2, Quaternion, Vector3};
-pub use input::{InputAction, InputManager};
+pub use input::{InputAction, InputManager, InputSystem, KeyAction, KeyBindings};
+pub use module::GameModule;
 pub use objects::ObjectMeshes;
-pub use physics::{Collider, ColliderShape, Hitbox, RaycastWorld, RigidBody};
+pub use particles::{ParticleParams, Particles};
+pub use physics::{
+    Collider, ColliderShape, Hitbox, HitboxOverlay, RaycastBatch, RaycastHandle, RaycastWorld,
+    RigidBody,
+};
 pub use ship::{BlockEntity, Ship, Tile};
 use specs::{prelude::*, shred::Fetch, storage::MaskedStorage, Component};
-
+use std::time::Instant;
+
+pub mod animation;
+pub mod ballistics;
+pub mod blueprint;
+pub mod construction;
+pub mod crew;
+pub mod damage;
+pub mod debris;
+pub mod difficulty;
+pub mod drops;
+pub mod faction;
 pub mod gameplay;
+pub mod hazards;
+pub mod headless;
+pub mod heat;
+pub mod hit_markers;
+pub mod hologram;
+pub mod hud;
 pub mod input;
+pub mod integrity;
+pub mod module;
 pub mod objects;
+pub mod paint;
+pub mod particles;
 pub mod physics;
+pub mod power;
+pub mod projectile;
+pub mod raider;
+pub mod scout;
+pub mod selection;
+pub mod share;
+pub mod shield;
 pub mod ship;
+pub mod ship_stats;
+pub mod status;
+pub mod targeting;
+pub mod time_effects;
+pub mod trade;
+pub mod undo;
+pub mod walls;
+pub mod wear;
 
 pub type SimpleStorage<'a, T> = Storage<'a, T, Fetch<'a, MaskedStorage<T>>>;
 
 pub struct Model {
     pub mesh_id: MeshId,
+    pub tint: ModelTint,
     model_id: Option<ModelId>,
 }
 
@@ -26,33 +76,61 @@ impl Component for Model {
 
 impl Model {
     pub fn new(mesh_id: MeshId) -> Model {
+        Self::with_tint(mesh_id, ModelTint::NONE)
+    }
+
+    pub fn with_tint(mesh_id: MeshId, tint: ModelTint) -> Model {
         Self {
             mesh_id,
+            tint,
             model_id: None,
         }
     }
+
+    /// Changes the mesh of the model. If the model was already added to the MeshManager,
+    /// it is moved to the new mesh.
+    pub fn set_mesh(&mut self, mesh_manager: &mut MeshManager, mesh_id: MeshId) {
+        if mesh_id == self.mesh_id {
+            return;
+        }
+        if let Some(model_id) = self.model_id {
+            self.model_id = Some(mesh_manager.change_mesh(self.mesh_id, model_id, mesh_id));
+        }
+        self.mesh_id = mesh_id;
+    }
 }
 
+/// Describes an entity when the cursor rests over it. The text is created every update while
+/// the tooltip is shown, so it can include live values like the entity's health.
+#[derive(Clone, Copy, Component)]
+#[storage(VecStorage)]
+pub struct Tooltip(pub fn(Entity, &World) -> String);
+
 // TODO: Have models automatically deleted using flagged storage.
 // Blocked By: https://github.com/amethyst/specs/issues/720
 pub struct ModelUpdateSystem {
     transform_reader: ReaderId<ComponentEvent>,
     model_reader: ReaderId<ComponentEvent>,
+    paint_reader: ReaderId<ComponentEvent>,
     inserted: BitSet,
     modified: BitSet,
+    painted: BitSet,
 }
 
 impl<'a> System<'a> for ModelUpdateSystem {
     type SystemData = (
+        Entities<'a>,
         WriteExpect<'a, MeshManager>,
         ReadStorage<'a, Transform>,
         WriteStorage<'a, Model>,
+        ReadStorage<'a, paint::Paint>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut mesh_manager, transforms, mut models) = data;
+        let (entities, mut mesh_manager, transforms, mut models, paints) = data;
         self.inserted.clear();
         self.modified.clear();
+        self.painted.clear();
 
         for event in models.channel().read(&mut self.model_reader) {
             match event {
@@ -68,8 +146,28 @@ impl<'a> System<'a> for ModelUpdateSystem {
             };
         }
 
-        for (model, transform, _) in (&mut models, &transforms, &self.inserted).join() {
-            model.model_id = Some(mesh_manager.new_model(model.mesh_id, transform.as_matrix()));
+        // Removed paint is also read, so the model goes back to white
+        for event in paints.channel().read(&mut self.paint_reader) {
+            match event {
+                ComponentEvent::Inserted(id)
+                | ComponentEvent::Modified(id)
+                | ComponentEvent::Removed(id) => self.painted.add(*id),
+            };
+        }
+
+        for (entity, model, transform, _) in
+            (&entities, &mut models, &transforms, &self.inserted).join()
+        {
+            let model_id = mesh_manager.new_model(model.mesh_id, transform.as_matrix(), model.tint);
+            mesh_manager.set_model_entity(model.mesh_id, model_id, entity);
+            model.model_id = Some(model_id);
+        }
+
+        for (model, paint, _) in (&models, paints.maybe(), &self.painted | &self.inserted).join() {
+            if let Some(model_id) = model.model_id {
+                let paint = paint.unwrap_or(&paint::Paint::WHITE);
+                mesh_manager.update_paint(model.mesh_id, model_id, paint.color());
+            }
         }
 
         for (model, transform, _) in (&mut models, &transforms, &self.modified)
@@ -92,10 +190,11 @@ impl<'a> System<'a> for RemoveModelSystem {
         Read<'a, ToBeRemoved>,
         WriteExpect<'a, MeshManager>,
         WriteStorage<'a, Model>,
+        WriteStorage<'a, animation::Animation>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (to_be_removed, mut mesh_manager, mut models) = data;
+        let (to_be_removed, mut mesh_manager, mut models, mut animations) = data;
 
         for (model, _) in (&mut models, to_be_removed.bitset()).join() {
             if let Some(model_id) = model.model_id {
@@ -103,103 +202,473 @@ impl<'a> System<'a> for RemoveModelSystem {
                 model.model_id = None;
             }
         }
+        for (animation, _) in (&mut animations, to_be_removed.bitset()).join() {
+            animation::remove_models(&mut mesh_manager, animation);
+        }
     }
 }
 
 pub struct ECS<'a> {
     pub world: World,
+    /// Runs on every update, even the ones that the time effects skip (see ECS::update)
+    camera_dispatcher: Dispatcher<'a, 'a>,
     dispatcher: Dispatcher<'a, 'a>,
     death_dispatcher: Dispatcher<'a, 'a>,
+    modules: Vec<Box<dyn GameModule>>,
 }
 
 impl<'a> ECS<'a> {
+    /// The meshes that ECS::new needs, besides the meshes of the blocks
+    pub fn mesh_jobs() -> Vec<MeshJob> {
+        ObjectMeshes::mesh_jobs()
+    }
+
+    /// `meshes` are the meshes that were loaded from ECS::mesh_jobs
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         device: &wgpu::Device,
         mut mesh_manager: MeshManager,
+        meshes: &LoadedMeshes,
         blocks: Blocks,
         floors: Floors,
         camera: Camera,
         window_size: WindowSize,
+        content_errors: ContentErrors,
+        modules: Vec<Box<dyn GameModule>>,
+    ) -> Self {
+        let meshes = ObjectMeshes::load(device, &mut mesh_manager, meshes);
+        ECS::with_meshes(
+            mesh_manager,
+            meshes,
+            blocks,
+            floors,
+            camera,
+            window_size,
+            content_errors,
+            modules,
+            rand::random(),
+            SaveFiles::PLAYER,
+        )
+    }
+
+    /// Creates the ECS with meshes that were already registered (ex. the placeholder meshes
+    /// of a headless simulation). The run's waves are planned from the seed, and the profile
+    /// and the achievements are only loaded and saved if the run has save files.
+    #[allow(clippy::too_many_arguments)]
+    fn with_meshes(
+        mesh_manager: MeshManager,
+        meshes: ObjectMeshes,
+        blocks: Blocks,
+        floors: Floors,
+        camera: Camera,
+        window_size: WindowSize,
+        content_errors: ContentErrors,
+        modules: Vec<Box<dyn GameModule>>,
+        seed: u64,
+        save_files: SaveFiles,
     ) -> Self {
-        let meshes = ObjectMeshes::load(device, &mut mesh_manager);
-        let hitbox_meshes = physics::HitboxMeshes::load(device, &mut mesh_manager);
         let inventory = crate::item::Inventory::new();
+        let tech_tree = crate::tech::TechTree::load(&blocks);
+        let checkpoint = gameplay::WaveCheckpoint::new(
+            seed,
+            1,
+            &inventory,
+            &tech_tree,
+            &gameplay::GameStats::default(),
+            Vec::new(),
+        );
+        let lifetime_stats = save_files.profile.map_or_else(
+            || LifetimeStats::new(&blocks),
+            |path| LifetimeStats::load(path, &blocks),
+        );
+        let achievements = save_files.achievements.map_or_else(
+            crate::achievements::Achievements::default,
+            crate::achievements::Achievements::load,
+        );
 
         let mut world = World::new();
         world.register::<Model>();
+        world.register::<animation::Animation>();
         world.register::<Ship>();
         world.register::<BlockEntity>();
         world.register::<Transform>();
         world.register::<RigidBody>();
         world.register::<Collider>();
         world.register::<Line>();
+        world.register::<Tooltip>();
+        world.register::<faction::Faction>();
         world.insert(ToBeRemoved::default());
+        world.insert(FrameProfiler::default());
+        world.insert(ColorCalibration::default());
+        world.insert(SystemTimings::default());
         world.insert(meshes);
-        world.insert(hitbox_meshes);
+        world.insert(physics::HitboxOverlay {
+            visible: crate::RENDER_HITBOXES,
+        });
         world.insert(mesh_manager);
         world.insert(blocks);
         world.insert(floors);
         world.insert(camera);
         world.insert(window_size);
+        world.insert(Time::new(Time::DEFAULT_RATE));
+        world.insert(FrameTime { delta: 0.0 });
         world.insert(inventory);
+        world.insert(tech_tree);
+        world.insert(Strings::load(Language::English));
+        world.insert(RunStats::new());
+        world.insert(StatEvents::default());
+        world.insert(ship_stats::ShipStatsCache::default());
+        world.insert(lifetime_stats);
+        world.insert(achievements);
+        world.insert(save_files);
+        world.insert(physics::PhysicsWorld::new());
         world.insert(RaycastWorld::new());
+        world.insert(physics::RaycastBatch::default());
         world.insert(InputManager::new());
-        objects::register_components(&mut world);
-        gameplay::register_components(&mut world);
-        crate::block::register_components(&mut world);
+        world.insert(KeyBindings::default());
+        world.insert(ship::SelectedShip::default());
+        world.insert(PickRequest::default());
+        world.insert(PickResult::default());
+        world.insert(blueprint::BlueprintTool::default());
+        world.insert(Particles::new());
+        world.insert(LineBatch::default());
+        world.insert(hit_markers::HitEvents::default());
+        world.insert(hit_markers::HitMarkers::default());
+        world.insert(hit_markers::AppliedDamage::default());
+        world.insert(damage::DamageEvents::default());
+        world.insert(damage::DamageRules::default());
+        world.insert(hit_markers::DamageNumbers::default());
+        world.insert(gameplay::GameLog::default());
+        world.insert(hud::HudVerbosity::Full);
+        world.insert(gameplay::GameState::Playing);
+        world.insert(gameplay::GameStats::default());
+        world.insert(checkpoint);
+        world.insert(content_errors);
+        for module in &modules {
+            module.register(&mut world);
+        }
 
         let model_update_system = {
             let transform_reader = world.write_storage::<Transform>().register_reader();
             let model_reader = world.write_storage::<Model>().register_reader();
+            let paint_reader = world.write_storage::<paint::Paint>().register_reader();
             ModelUpdateSystem {
                 transform_reader,
                 model_reader,
+                paint_reader,
                 inserted: BitSet::new(),
                 modified: BitSet::new(),
+                painted: BitSet::new(),
             }
         };
 
+        let physics_system = physics::PhysicsSystem {
+            transform_reader: world.write_storage::<Transform>().register_reader(),
+            modified: BitSet::new(),
+        };
+
+        let raycast_system = physics::RaycastSystem {
+            transform_reader: world.write_storage::<Transform>().register_reader(),
+            modified: BitSet::new(),
+        };
+
+        let camera_dispatcher = DispatcherBuilder::new()
+            .with_profiled(input::CameraSystem::default(), "camera_system", &[])
+            .build();
         let mut dispatcher_builder = DispatcherBuilder::new()
-            .with(input::CameraSystem, "camera_system", &[])
-            .with(input::InputSystem, "input_system", &["camera_system"]);
+            .with_profiled(input::InputSystem, "input_system", &[])
+            .with_profiled(input::DeckViewSystem, "deck_view_system", &[])
+            .with_profiled(
+                selection::SelectionSystem,
+                "selection_system",
+                &["input_system"],
+            )
+            .with_profiled(
+                blueprint::BlueprintSystem,
+                "blueprint_system",
+                &["input_system"],
+            )
+            .with_profiled(
+                ship::ShipSelectSystem,
+                "ship_select_system",
+                &["input_system"],
+            );
         dispatcher_builder.add_barrier();
-        crate::block::setup_systems(&mut dispatcher_builder);
-        objects::setup_systems(&mut dispatcher_builder);
-        gameplay::setup_systems(&mut dispatcher_builder);
+        for module in &modules {
+            module.systems(&mut dispatcher_builder);
+        }
         dispatcher_builder.add_barrier();
         let dispatcher = dispatcher_builder
-            .with(physics::PhysicsSystem, "physics_system", &[])
-            .with(
-                physics::RaycastSystem,
-                "raycast_system",
+            .with_profiled(physics_system, "physics_system", &[])
+            .with_profiled(raycast_system, "raycast_system", &["physics_system"])
+            .with_profiled(
+                physics::RaycastBatchSystem,
+                "raycast_batch_system",
+                &["raycast_system"],
+            )
+            .with_profiled(model_update_system, "update_models", &["raycast_system"])
+            .with_profiled(
+                physics::HitboxOverlaySystem,
+                "hitbox_overlay_system",
+                &["raycast_system"],
+            )
+            .with_profiled(
+                animation::AnimationSystem,
+                "animation_system",
+                &["raycast_system"],
+            )
+            .with_profiled(
+                damage::DamageSystem::default(),
+                "damage_system",
                 &["physics_system"],
             )
-            .with(model_update_system, "update_models", &["raycast_system"])
+            .with_profiled(
+                particles::ParticleSystem,
+                "particle_system",
+                &["physics_system", "damage_system"],
+            )
+            .with_profiled(
+                crate::stats::StatsSystem::default(),
+                "stats_system",
+                &["damage_system"],
+            )
+            .with_profiled(
+                crate::achievements::AchievementSystem::default(),
+                "achievement_system",
+                &["stats_system"],
+            )
+            .with_profiled(hud::HudSystem::default(), "hud_system", &[])
+            .with_profiled(
+                hit_markers::HitMarkerSystem,
+                "hit_marker_system",
+                &["hud_system", "damage_system"],
+            )
+            .with_profiled(
+                hit_markers::DamageNumberSystem,
+                "damage_number_system",
+                &["hud_system", "damage_system"],
+            )
             .build();
 
-        let death_dispatcher = DispatcherBuilder::new()
-            .with(objects::AsteroidMinedSystem, "", &[])
+        let mut death_dispatcher_builder = DispatcherBuilder::new();
+        for module in &modules {
+            module.death_systems(&mut death_dispatcher_builder);
+        }
+        death_dispatcher_builder.add_barrier();
+        let death_dispatcher = death_dispatcher_builder
             .with(RemoveModelSystem, "", &[])
-            .with(physics::RemoveRaycastColliderSystem, "", &[])
+            .with(physics::RemoveColliderSystem, "", &[])
             .build();
 
-        ship::create_ship(&mut world);
-        gameplay::init_world(&mut world);
-
-        ECS {
+        let mut ecs = ECS {
             world,
+            camera_dispatcher,
             dispatcher,
             death_dispatcher,
+            modules,
+        };
+        ecs.init_run();
+        ecs
+    }
+
+    /// Creates the ships, and lets every module set up the run
+    fn init_run(&mut self) {
+        ship::create_ship(&mut self.world);
+        let drone_ships = self
+            .get_resource::<gameplay::WaveCheckpoint>()
+            .drone_ships
+            .clone();
+        for origin in drone_ships {
+            if let Err(error) = ship::create_drone_ship(&mut self.world, origin) {
+                println!("[Ship] {}", error);
+            }
         }
+        for module in &self.modules {
+            module.init(&mut self.world);
+        }
+    }
+
+    /// Passes a click that the UI did not handle to the modules
+    pub fn on_click(
+        &mut self,
+        button: winit::event::MouseButton,
+        state: winit::event::ElementState,
+    ) {
+        for module in &self.modules {
+            module.on_click(&mut self.world, button, state);
+        }
+    }
+
+    pub fn register_commands(&self, commands: &mut DebugCommands) {
+        for module in &self.modules {
+            module.commands(commands);
+        }
+    }
+
+    /// Passes a key that the command palette did not handle to the modules
+    pub fn on_key(&mut self, key: winit::event::VirtualKeyCode, state: winit::event::ElementState) {
+        for module in &self.modules {
+            module.on_key(&mut self.world, key, state);
+        }
+    }
+
+    /// Merges the current run into the profile, and saves it. Called when the game is closed.
+    pub fn end_run(&mut self) {
+        let mut run = self.world.write_resource::<RunStats>();
+        // Some events (ex. the blocks placed) are pushed between updates
+        self.world
+            .write_resource::<StatEvents>()
+            .record_all(&mut run.counters);
+        let mut lifetime = self.world.write_resource::<LifetimeStats>();
+        lifetime.merge(&run);
+        if let Some(path) = self.world.read_resource::<SaveFiles>().profile {
+            lifetime.save(path);
+        }
+    }
+
+    /// Starts a new game with a new seed
+    pub fn restart(&mut self) {
+        let tech_tree = crate::tech::TechTree::load(&self.get_resource::<Blocks>());
+        self.reset(gameplay::WaveCheckpoint::new(
+            rand::random(),
+            1,
+            &crate::item::Inventory::new(),
+            &tech_tree,
+            &gameplay::GameStats::default(),
+            Vec::new(),
+        ));
+    }
+
+    /// Starts a new game with a shared ship instead of the usual starting ship. The ship
+    /// needs its core where the starting ship has it (see SharedShip::has_core).
+    pub fn restart_with_ship(&mut self, shared: share::SharedShip) {
+        let tech_tree = crate::tech::TechTree::load(&self.get_resource::<Blocks>());
+        let mut checkpoint = gameplay::WaveCheckpoint::new(
+            rand::random(),
+            1,
+            &crate::item::Inventory::new(),
+            &tech_tree,
+            &gameplay::GameStats::default(),
+            Vec::new(),
+        );
+        checkpoint.ship = Some(shared);
+        self.reset(checkpoint);
     }
 
-    pub fn update(&mut self) {
-        self.dispatcher.dispatch(&self.world);
+    /// Restarts the current wave with the ship, items, research and stats from its start.
+    /// The wave spawns the same asteroids as before.
+    pub fn retry_wave(&mut self) {
+        let checkpoint = (*self.get_resource::<gameplay::WaveCheckpoint>()).clone();
+        self.reset(checkpoint);
+    }
+
+    /// Removes every entity and starts the game from the checkpoint. The entities are removed
+    /// the same way as any other entity, so their models and raycast colliders are cleaned up.
+    fn reset(&mut self, checkpoint: gameplay::WaveCheckpoint) {
+        self.end_run();
+        {
+            let entities = self.world.entities();
+            let mut to_be_removed = self.world.write_resource::<ToBeRemoved>();
+            for entity in entities.join() {
+                to_be_removed.add(entity);
+            }
+        }
         self.maintain();
+
+        self.world.insert(checkpoint.tech_tree.clone());
+        self.world.insert(checkpoint.inventory.clone());
+        self.world.insert(RunStats::new());
+        self.world.insert(StatEvents::default());
+        self.world.insert(gameplay::GameState::Playing);
+        self.world.insert(checkpoint.stats.clone());
+        self.world.insert(hit_markers::HitEvents::default());
+        self.world.insert(hit_markers::HitMarkers::default());
+        self.world.insert(hit_markers::AppliedDamage::default());
+        self.world.insert(damage::DamageEvents::default());
+        self.world.insert(hit_markers::DamageNumbers::default());
+        self.world.insert(gameplay::GameLog::default());
+        self.world.insert(hud::HudVerbosity::Full);
+        self.world.insert(ship::SelectedShip::default());
+        self.world.write_resource::<Particles>().clear();
+        {
+            let mut input_manager = self.world.write_resource::<InputManager>();
+            input_manager.action = InputAction::None;
+            input_manager.target = None;
+            input_manager.hovered = None;
+            input_manager.clicked_block = None;
+            input_manager.clicked_drop = None;
+            input_manager.remote_view = None;
+        }
+
+        self.world.insert(checkpoint);
+        self.init_run();
+    }
+
+    /// Moves the camera by the real seconds since the last frame. This runs before the frame's
+    /// fixed updates, so clicks pick with the same camera that the frame is rendered with.
+    pub fn update_camera(&mut self, delta: f32) {
+        let playing =
+            *self.world.read_resource::<gameplay::GameState>() == gameplay::GameState::Playing;
+        if playing {
+            self.world.insert(FrameTime { delta });
+            dispatch(&mut self.camera_dispatcher, &self.world);
+        }
+    }
+
+    /// Runs a fixed update. While a time effect slows the game down, some updates don't
+    /// `simulate` (see app::run). They still advance the time effects, which follow real
+    /// time.
+    pub fn update(&mut self, simulate: bool) {
+        self.world
+            .write_resource::<time_effects::TimeEffects>()
+            .advance();
+        let playing =
+            *self.world.read_resource::<gameplay::GameState>() == gameplay::GameState::Playing;
+        if !simulate {
+            return;
+        }
+
+        self.world.write_resource::<LineBatch>().clear();
+        self.world
+            .write_resource::<MeshManager>()
+            .store_previous_models();
+
+        // The simulation is paused once the game has been won or lost
+        if playing {
+            #[cfg(feature = "count-allocations")]
+            let allocations = crate::alloc_counter::allocations();
+
+            let start = Instant::now();
+            dispatch(&mut self.dispatcher, &self.world);
+            let dispatch_time = start.elapsed();
+            gameplay::capture_ship(&mut self.world);
+            blueprint::stamp_requested(&mut self.world);
+            blueprint::place_requested(&mut self.world);
+            undo::apply_requested(&mut self.world);
+            construction::complete_builds(&mut self.world);
+            self.world.write_resource::<InputManager>().end_update();
+            let start = Instant::now();
+            self.maintain();
+            let maintain_time = start.elapsed();
+
+            {
+                let mut profiler = self.world.write_resource::<FrameProfiler>();
+                profiler.record("update/systems", dispatch_time);
+                profiler.record("update/maintain", maintain_time);
+                profiler.collect(&self.world.read_resource::<SystemTimings>());
+            }
+
+            if *self.world.read_resource::<gameplay::GameState>() != gameplay::GameState::Playing {
+                crate::report::export_report(&self.world);
+            }
+
+            #[cfg(feature = "count-allocations")]
+            crate::alloc_counter::report_update(allocations);
+        }
     }
 
     pub fn maintain(&mut self) {
-        self.death_dispatcher.dispatch(&self.world);
+        dispatch(&mut self.death_dispatcher, &self.world);
         {
             let mut to_be_removed = self.world.fetch_mut::<ToBeRemoved>();
             for entity in to_be_removed.as_slice() {
@@ -222,6 +691,14 @@ impl<'a> ECS<'a> {
     }
 }
 
+/// Runs the systems on rayon's threads. Handing them over allocates now and then inside
+/// rayon, which is left out of the allocation count.
+fn dispatch(dispatcher: &mut Dispatcher, world: &World) {
+    #[cfg(feature = "count-allocations")]
+    let _exempt = crate::alloc_counter::Exempt::begin();
+    dispatcher.dispatch(world);
+}
+
 #[derive(Default)]
 pub struct ToBeRemoved {
     vec: Vec<Entity>,
@@ -285,18 +762,98 @@ impl Transform {
     }
 }
 
-/// TODO: Create seperate lines for component and GPU
-/// and then add a 'visible' flag here
+/// A line between two points, which fades from `color` to `color2`. Lines with a width
+/// are drawn as quads facing the camera, and overlay lines are drawn on top of everything.
+/// A line is hidden by removing the component.
 #[derive(Clone, Copy, Component)]
 #[storage(HashMapStorage)]
 pub struct Line {
     pub pt: Vector3<f32>,
     pub pt2: Vector3<f32>,
     pub color: Vector3<f32>,
+    pub color2: Vector3<f32>,
+    pub width: f32,
+    pub overlay: bool,
+}
+
+impl Line {
+    pub fn new(pt: Vector3<f32>, pt2: Vector3<f32>, color: Vector3<f32>) -> Self {
+        Self {
+            pt,
+            pt2,
+            color,
+            color2: color,
+            width: 0.0,
+            overlay: false,
+        }
+    }
+
+    pub fn with_width(self, width: f32) -> Self {
+        Self { width, ..self }
+    }
+
+    pub fn with_end_color(self, color2: Vector3<f32>) -> Self {
+        Self { color2, ..self }
+    }
+
+    pub fn as_overlay(self) -> Self {
+        Self {
+            overlay: true,
+            ..self
+        }
+    }
 }
 
-unsafe impl bytemuck::Pod for Line {}
-unsafe impl bytemuck::Zeroable for Line {}
+/// Lines that are only visible until the next update. Unlike the [`Line`] component,
+/// any amount of lines can be added, so this is used for things like indicators.
+#[derive(Default)]
+pub struct LineBatch {
+    lines: Vec<Line>,
+}
+
+impl LineBatch {
+    pub fn add(&mut self, line: Line) {
+        self.lines.push(line);
+    }
+
+    /// Splits the line into dashes of `dash_length`, with an equal gap between them
+    pub fn add_dashed(&mut self, line: Line, dash_length: f32) {
+        let delta = line.pt2 - line.pt;
+        let length = delta.magnitude();
+        if length <= 0.0 {
+            return;
+        }
+
+        let direction = delta / length;
+        let mut distance = 0.0;
+        while distance < length {
+            let end = (distance + dash_length).min(length);
+            self.lines.push(Line {
+                pt: line.pt + direction * distance,
+                pt2: line.pt + direction * end,
+                ..line
+            });
+            distance += dash_length * 2.0;
+        }
+    }
+
+    /// Adds a small 3D cross centered at `pos`, which is drawn on top of everything
+    /// (ex. markers on the far side of an asteroid)
+    pub fn add_marker(&mut self, pos: Vector3<f32>, size: f32, color: Vector3<f32>) {
+        let half = size / 2.0;
+        for axis in &[Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+            self.add(Line::new(pos - axis * half, pos + axis * half, color).as_overlay());
+        }
+    }
+
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    fn clear(&mut self) {
+        self.lines.clear();
+    }
+}
 
 pub struct WindowSize {
     pub width: f32,
@@ -308,3 +865,49 @@ impl WindowSize {
         Point2::new(self.width, self.height)
     }
 }
+
+/// How much time each fixed update simulates. Speeds, damage, heating and cooling are per
+/// second and scaled by `delta`, so the game plays the same at every tick rate.
+///
+/// A few things can only happen during an update, so they are quantized to the tick rate:
+/// - Timers (ex. a miner reloading) run out on the first update at or after their duration,
+///   so shots, heals and spawns are up to one update late
+/// - Asteroid spawns keep the leftover time of their interval, so a wave does not drift
+/// - Lasers deal whole points of damage, and carry the fraction over to the next update
+#[derive(Clone, Copy)]
+pub struct Time {
+    /// The seconds simulated by each update
+    pub delta: f32,
+}
+
+impl Time {
+    pub const DEFAULT_RATE: u32 = 60;
+    /// How close a timer needs to be to its duration to run out. Adding up the deltas is not
+    /// exact (ex. 60 * (1.0 / 60.0) can be slightly less than 1.0).
+    pub const SLACK: f32 = 1e-4;
+
+    pub fn new(rate: u32) -> Self {
+        Self {
+            delta: 1.0 / rate as f32,
+        }
+    }
+
+    /// Counts the timer down by an update, stopping at 0. Returns true once it has run out.
+    pub fn count_down(&self, timer: &mut f32) -> bool {
+        *timer = (*timer - self.delta).max(0.0);
+        *timer <= Time::SLACK
+    }
+
+    /// Counts the timer up by an update, stopping at the duration. Returns true once it has
+    /// reached the duration.
+    pub fn count_up(&self, timer: &mut f32, duration: f32) -> bool {
+        *timer = (*timer + self.delta).min(duration);
+        *timer >= duration - Time::SLACK
+    }
+}
+
+/// The real time between frames, which the camera moves by
+pub struct FrameTime {
+    /// The seconds since the last frame
+    pub delta: f32,
+}
@@ -0,0 +1,73 @@
+use crate::debug::DebugCommands;
+use specs::prelude::*;
+use winit::event;
+
+/// A self-contained part of the game (ex. the blocks, or the asteroid field). ECS::new sets
+/// up every module in the order they are given, so a module's systems can depend on the
+/// named systems of the modules before it.
+pub trait GameModule {
+    /// Registers the module's components and inserts its resources. Registering a component
+    /// more than once is harmless, so every module registers the components it uses.
+    fn register(&self, world: &mut World);
+
+    /// Adds the module's systems to the update dispatcher. They run after the input is
+    /// handled and before the physics. Systems that others depend on need a unique name.
+    /// Adding them with `add_profiled` shows their timings in the profiler overlay.
+    fn systems(&self, builder: &mut DispatcherBuilder);
+
+    /// Adds systems that see the entities marked for removal, before their models and
+    /// colliders are cleaned up
+    fn death_systems(&self, _builder: &mut DispatcherBuilder) {}
+
+    /// Called at the start of every run (and retried wave), once the ship was created and
+    /// the WaveCheckpoint was inserted
+    fn init(&self, _world: &mut World) {}
+
+    /// Called with the clicks that were not handled by the UI
+    fn on_click(
+        &self,
+        _world: &mut World,
+        _button: event::MouseButton,
+        _state: event::ElementState,
+    ) {
+    }
+
+    /// Called with the keys that were not handled by the command palette
+    fn on_key(&self, _world: &mut World, _key: event::VirtualKeyCode, _state: event::ElementState) {
+    }
+
+    /// Registers the module's debug commands, which are shown in the command palette and
+    /// the console
+    fn commands(&self, _commands: &mut DebugCommands) {}
+}
+
+/// The modules of the base game, in the order their systems are added
+pub fn default_modules() -> Vec<Box<dyn GameModule>> {
+    vec![
+        Box::new(crate::block::BlockModule),
+        Box::new(super::objects::ObjectsModule),
+        Box::new(super::projectile::ProjectileModule),
+        Box::new(super::drops::DropModule),
+        Box::new(super::gameplay::GameplayModule),
+        Box::new(super::difficulty::DifficultyModule),
+        Box::new(super::raider::RaiderModule),
+        Box::new(super::hazards::HazardModule),
+        Box::new(super::status::StatusModule),
+        Box::new(super::walls::WallModule),
+        Box::new(super::construction::ConstructionModule),
+        Box::new(super::scout::ScoutModule),
+        Box::new(super::wear::WearModule),
+        Box::new(super::heat::HeatModule),
+        Box::new(super::power::PowerModule),
+        Box::new(super::shield::ShieldModule),
+        Box::new(super::time_effects::TimeEffectModule),
+        Box::new(super::integrity::IntegrityModule),
+        Box::new(super::debris::DebrisModule),
+        Box::new(super::crew::CrewModule),
+        Box::new(super::trade::TradeModule),
+        Box::new(super::paint::PaintModule),
+        Box::new(super::selection::SelectionModule),
+        Box::new(super::undo::UndoModule),
+        Box::new(super::hologram::HologramModule),
+    ]
+}
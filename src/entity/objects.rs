@@ -1,62 +1,209 @@
 use super::{
+    crew::CrewMember,
+    damage::{AreaDamage, DamageSource},
+    drops::ItemDrop,
+    faction::{self, Faction},
+    gameplay::{GameLog, GameStats},
+    hud::HudVerbosity,
     physics::{Collider, ColliderShape, Hitbox, RigidBody},
-    Model, ToBeRemoved, Transform,
+    targeting::TargetAssignment,
+    BlockEntity, GameModule, Model, ParticleParams, Particles, Time, ToBeRemoved, Tooltip,
+    Transform,
 };
-use crate::graphics::{MeshId, MeshManager};
-use crate::item::{GameItem, Inventory};
-use cgmath::{prelude::*, Vector3};
+use crate::block::Blocks;
+use crate::graphics::{GPUBillboard, Mesh, MeshId, MeshManager, ModelTint};
+use crate::i18n::Strings;
+use crate::item::GameItem;
+use crate::loading::{LoadedMeshes, MeshJob, ParsedMesh};
+use crate::profiler::AddProfiled;
+use cgmath::{prelude::*, Point3, Vector2, Vector3, Vector4};
+use rand::{rngs::StdRng, SeedableRng};
 use specs::{prelude::*, world::LazyBuilder, Component};
-use std::collections::HashMap;
 
 /// Stores miscellaneous meshes (these are usually entities)
 pub struct ObjectMeshes {
-    pub asteroids: HashMap<GameItem, MeshId>,
+    /// Asteroids are tinted per instance based on their composition
+    pub asteroid: MeshId,
     pub mining_missle: MeshId,
+    /// The cannon's slugs (see crate::entity::projectile)
+    pub slug: MeshId,
+    pub raider: MeshId,
+    pub drone: MeshId,
+    pub crew: MeshId,
+    pub trader: MeshId,
+    /// A unit sphere, which is scaled to the shield bubbles and the flashes of their hits
+    pub shield: MeshId,
 }
 
 impl ObjectMeshes {
-    pub fn load(device: &wgpu::Device, mesh_manager: &mut MeshManager) -> ObjectMeshes {
-        let asteroid_base = crate::graphics::load_mesh("asteroid");
-
-        let asteroids: HashMap<GameItem, MeshId> = GameItem::asteroid_info()
-            .iter()
-            .map(|(item, color)| {
-                let mut mesh = asteroid_base.clone();
-                mesh.recolor(*color);
-                (*item, mesh_manager.add(device, &mesh))
-            })
-            .collect();
+    /// The meshes that are loaded in the background (see crate::loading). The asteroid's
+    /// levels of detail are prepared on the loading thread, since decimating them is slow.
+    pub fn mesh_jobs() -> Vec<MeshJob> {
+        let asteroid = MeshJob::new("asteroid", |errors| {
+            let mut asteroid_base = crate::graphics::load_mesh("asteroid", errors);
+            asteroid_base.mark_veins(Asteroid::VEIN_FREQUENCY, Asteroid::VEIN_THRESHOLD);
+            // The levels that were not modeled are generated from the base mesh
+            let authored_lods =
+                crate::graphics::load_mesh_lods("asteroid", Asteroid::LOD_CELL_SIZES.len(), errors);
+            let asteroid_lods: Vec<Mesh> = std::iter::once(asteroid_base.clone())
+                .chain(Asteroid::LOD_CELL_SIZES.iter().zip(authored_lods).map(
+                    |(cell_size, authored)| match authored {
+                        Some(mut mesh) => {
+                            mesh.mark_veins(Asteroid::VEIN_FREQUENCY, Asteroid::VEIN_THRESHOLD);
+                            mesh
+                        }
+                        None => asteroid_base.decimate(*cell_size),
+                    },
+                ))
+                .collect();
+            // Asteroids are lumpy, so clicks are refined against the base mesh instead of
+            // the sphere
+            ParsedMesh::LodChain(asteroid_lods, Asteroid::LOD_DISTANCES.to_vec())
+        });
+        vec![asteroid, MeshJob::mesh("mining_missle")]
+    }
 
+    pub fn load(
+        device: &wgpu::Device,
+        mesh_manager: &mut MeshManager,
+        meshes: &LoadedMeshes,
+    ) -> ObjectMeshes {
         Self {
-            asteroids,
-            mining_missle: mesh_manager.add(device, &crate::graphics::load_mesh("mining_missle")),
+            asteroid: meshes.get("asteroid").id,
+            mining_missle: meshes.get("mining_missle").id,
+            slug: mesh_manager.add(
+                device,
+                &Mesh::rectangular_prism(0.16, 0.16, 0.16, Point3::new(1.0, 0.7, 0.3)),
+            ),
+            // There is no raider model yet
+            raider: mesh_manager.add(
+                device,
+                &Mesh::rectangular_prism(1.4, 0.9, 0.4, Point3::new(0.7, 0.1, 0.1)),
+            ),
+            // Construction drones are only visual, so a small box is enough
+            drone: mesh_manager.add(
+                device,
+                &Mesh::rectangular_prism(0.3, 0.3, 0.15, Point3::new(1.0, 0.8, 0.2)),
+            ),
+            crew: mesh_manager.add(
+                device,
+                &Mesh::capsule(
+                    CrewMember::RADIUS,
+                    CrewMember::HEIGHT - CrewMember::RADIUS * 2.0,
+                    Point3::new(0.2, 0.5, 0.9),
+                ),
+            ),
+            // There is no trader model yet either
+            trader: mesh_manager.add(
+                device,
+                &Mesh::rectangular_prism(2.4, 1.2, 0.8, Point3::new(0.2, 0.7, 0.3)),
+            ),
+            shield: ObjectMeshes::load_shield(device, mesh_manager),
         }
     }
-}
 
-pub fn register_components(world: &mut World) {
-    world.register::<Asteroid>();
-    world.register::<Health>();
-    world.register::<MiningMissle>();
+    /// The bubbles are colored by their tints. The whole mesh is transparent, so a bubble never
+    /// hides the ship or catches its clicks, whatever the alpha of its tint.
+    fn load_shield(device: &wgpu::Device, mesh_manager: &mut MeshManager) -> MeshId {
+        let shield = mesh_manager.add(device, &Mesh::capsule(1.0, 0.0, Point3::new(1.0, 1.0, 1.0)));
+        mesh_manager.set_mesh_transparent(shield, true);
+        shield
+    }
+
+    pub fn headless(mesh_manager: &mut MeshManager) -> ObjectMeshes {
+        Self {
+            asteroid: mesh_manager.add_placeholder("asteroid"),
+            mining_missle: mesh_manager.add_placeholder("mining_missle"),
+            slug: mesh_manager.add_placeholder("slug"),
+            raider: mesh_manager.add_placeholder("raider"),
+            drone: mesh_manager.add_placeholder("drone"),
+            crew: mesh_manager.add_placeholder("crew"),
+            trader: mesh_manager.add_placeholder("trader"),
+            shield: mesh_manager.add_placeholder("shield"),
+        }
+    }
 }
 
-pub fn setup_systems(builder: &mut DispatcherBuilder) {
-    builder.add(MiningMissleSystem, "", &[]);
-    builder.add(NoMoreHealthSystem, "", &[]);
-    builder.add(AsteroidShrinkSystem, "", &[]);
+/// Asteroids, mining missiles, and the health of every entity
+pub struct ObjectsModule;
+
+impl GameModule for ObjectsModule {
+    fn register(&self, world: &mut World) {
+        world.register::<Asteroid>();
+        world.register::<Health>();
+        world.register::<MiningMissle>();
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(MiningMissleSystem, "mining_missle_system", &[]);
+        builder.add_profiled(NoMoreHealthSystem, "no_more_health_system", &[]);
+        builder.add_profiled(AsteroidShrinkSystem, "asteroid_shrink_system", &[]);
+    }
+
+    fn death_systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(AsteroidMinedSystem, "asteroid_mined_system", &[]);
+    }
 }
 
 #[derive(Component)]
 #[storage(HashMapStorage)]
-pub struct Health(pub u32);
+pub struct Health {
+    health: u32,
+    max_health: u32,
+    /// The seconds since the entity was last damaged
+    time_since_damage: f32,
+    /// Where the entity was last damaged (ex. where an asteroid crashed into a block)
+    last_hit: Option<Vector3<f32>>,
+}
 
 impl Health {
-    pub fn damage(&mut self, amount: u32) {
-        self.0 -= amount.min(self.0);
+    pub fn new(max_health: u32) -> Self {
+        Self {
+            health: max_health,
+            max_health,
+            time_since_damage: f32::INFINITY,
+            last_hit: None,
+        }
+    }
+
+    /// Returns the damage that was applied (it is limited by the remaining health). Damage
+    /// is queued in the DamageEvents, and applied by the DamageSystem.
+    pub(super) fn damage(&mut self, amount: u32, point: Vector3<f32>) -> u32 {
+        let applied = amount.min(self.health);
+        self.health -= applied;
+        self.time_since_damage = 0.0;
+        self.last_hit = Some(point);
+
+        applied
+    }
+
+    /// Returns the health that was restored (it is limited by the max health)
+    pub fn heal(&mut self, amount: u32) -> u32 {
+        let applied = amount.min(self.max_health - self.health);
+        self.health += applied;
+
+        applied
     }
 
     pub fn health(&self) -> u32 {
-        self.0
+        self.health
+    }
+
+    pub fn max_health(&self) -> u32 {
+        self.max_health
+    }
+
+    /// The health as a fraction of the max health (0..1)
+    pub fn fraction(&self) -> f32 {
+        self.health as f32 / self.max_health as f32
+    }
+
+    pub fn last_hit(&self) -> Option<Vector3<f32>> {
+        self.last_hit
+    }
+
+    pub fn time_since_damage(&self) -> f32 {
+        self.time_since_damage
     }
 }
 
@@ -65,29 +212,236 @@ pub struct NoMoreHealthSystem;
 impl<'a> System<'a> for NoMoreHealthSystem {
     type SystemData = (
         Entities<'a>,
+        ReadExpect<'a, Time>,
         Write<'a, ToBeRemoved>,
-        ReadStorage<'a, Health>,
+        WriteExpect<'a, GameLog>,
+        ReadExpect<'a, Blocks>,
+        ReadExpect<'a, Strings>,
+        ReadStorage<'a, BlockEntity>,
+        WriteStorage<'a, Health>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, mut to_be_removed, healths) = data;
+        let (
+            entities,
+            time,
+            mut to_be_removed,
+            mut log,
+            blocks,
+            strings,
+            block_entities,
+            mut healths,
+        ) = data;
+
+        for (entity, health) in (&entities, &mut healths).join() {
+            health.time_since_damage += time.delta;
 
-        for (entity, health) in (&entities, &healths).join() {
             if health.health() == 0 {
                 to_be_removed.add(entity);
+
+                if let Some(block_entity) = block_entities.get(entity) {
+                    let block = blocks.get_block(block_entity.block_id());
+                    log.add(strings.format(
+                        "log.block_destroyed",
+                        &[("block", &strings.get(block.name_key))],
+                    ));
+                }
             }
         }
     }
 }
 
+/// The seconds a health bar is fully visible for after the entity was damaged
+const HEALTH_BAR_VISIBLE_TIME: f32 = 1.0;
+/// The seconds the health bar takes to fade out
+const HEALTH_BAR_FADE_TIME: f32 = 0.5;
+
+/// Creates the health bars of all the damaged entities. The bars are placed above
+/// the entity's hitbox and fade out shortly after the entity was last damaged, and their
+/// background is tinted by the entity's faction. At Minimal HudVerbosity only the ship's
+/// blocks have health bars.
+pub fn build_health_bars(world: &World) -> Vec<GPUBillboard> {
+    let entities = world.entities();
+    let block_entities = world.read_component::<BlockEntity>();
+    let ships_only = *world.fetch::<HudVerbosity>() == HudVerbosity::Minimal;
+    let healths = world.read_component::<Health>();
+    let transforms = world.read_component::<Transform>();
+    let colliders = world.read_component::<Collider>();
+    let factions = world.read_component::<Faction>();
+    let to_be_removed = world.fetch::<ToBeRemoved>();
+    let width = 1.0;
+    let height = 0.12;
+    let mut bars = Vec::new();
+
+    for (entity, health, transform) in (&entities, &healths, &transforms).join() {
+        let time = health.time_since_damage();
+        if health.health() == 0
+            || health.health() >= health.max_health()
+            || time > HEALTH_BAR_VISIBLE_TIME + HEALTH_BAR_FADE_TIME
+            || to_be_removed.bitset().contains(entity.id())
+            || (ships_only && !block_entities.contains(entity))
+        {
+            continue;
+        }
+
+        let alpha = if time > HEALTH_BAR_VISIBLE_TIME {
+            1.0 - (time - HEALTH_BAR_VISIBLE_TIME) / HEALTH_BAR_FADE_TIME
+        } else {
+            1.0
+        };
+        let top = colliders
+            .get(entity)
+            .map(|collider| collider.hitbox.top(transform))
+            .unwrap_or(transform.position.z);
+        let pos = Vector3::new(transform.position.x, transform.position.y, top + 0.3);
+        let fraction = health.fraction();
+        let background = faction::faction_of(&factions, entity).color() * 0.25;
+
+        bars.push(GPUBillboard {
+            pos,
+            offset: Vector2::new(-width / 2.0, 0.0),
+            size: Vector2::new(width, height),
+            color: background.extend(0.8 * alpha),
+        });
+        bars.push(GPUBillboard {
+            pos,
+            offset: Vector2::new(-width / 2.0, 0.0),
+            size: Vector2::new(width * fraction, height),
+            color: Vector4::new(1.0 - fraction, fraction, 0.1, alpha),
+        });
+    }
+
+    bars
+}
+
+/// The resources an asteroid is made of. The primary resource makes up the body of
+/// the asteroid, and the secondary resource is found in its veins.
 #[derive(Component)]
 #[storage(HashMapStorage)]
-pub struct Asteroid(pub GameItem);
+pub struct Asteroid {
+    pub primary: GameItem,
+    pub secondary: GameItem,
+    /// Volatile asteroids set the blocks they crash into on fire. Their veins glow, so
+    /// they can be shot down first.
+    pub volatile: bool,
+    /// Multiplies the health of the asteroid (see DifficultyModifiers)
+    pub toughness: f32,
+    /// The debris of a debris storm, which is smaller than an asteroid and drops nothing
+    pub debris: bool,
+    /// Seeds the roll of how many items the asteroid drops, so the asteroids of a planned
+    /// wave always drop the same items
+    pub seed: u64,
+}
 
 impl Asteroid {
     pub const HEALTH: u32 = 180;
     pub const COLLIDER_RADIUS: f32 = 0.8;
     pub const VELOCITY: f32 = 1.3;
+    /// The size of debris compared to a full size asteroid
+    pub const DEBRIS_SCALE: f32 = 0.45;
+    /// The damage done to a ship block that the asteroid crashes into
+    pub const IMPACT_DAMAGE: u32 = 1;
+    /// The mass of a full size asteroid
+    const MASS: f32 = 1.5;
+    /// The grid sizes used to generate the lower levels of detail that have no model
+    const LOD_CELL_SIZES: [f32; 2] = [0.2, 0.4];
+    const LOD_DISTANCES: [f32; 2] = [22.0, 34.0];
+    const VEIN_FREQUENCY: f32 = 4.0;
+    const VEIN_THRESHOLD: f32 = 0.15;
+    /// The chance of a spawned asteroid being volatile
+    pub const VOLATILE_CHANCE: f64 = 0.1;
+    /// Brighter than the mesh, so the veins look like they glow
+    const VOLATILE_TINT: Vector3<f32> = Vector3::new(1.8, 0.6, 0.2);
+    /// The fire that a volatile asteroid starts on the block it crashes into
+    pub const BURN_TIME: f32 = 6.0;
+    pub const BURN_DAMAGE_PER_SECOND: f32 = 1.0;
+
+    pub fn tint(&self) -> ModelTint {
+        let veins = if self.volatile {
+            Asteroid::VOLATILE_TINT
+        } else {
+            self.secondary.asteroid_tints().1
+        };
+        ModelTint::new(self.primary.asteroid_tints().0, veins)
+    }
+
+    /// Harder resources make for asteroids that take longer to mine
+    pub fn max_health(&self) -> u32 {
+        (Asteroid::HEALTH as f32 * self.primary.mining().hardness * self.toughness).round() as u32
+    }
+
+    /// How much of the damage from the source the asteroid takes, which depends on its
+    /// primary resource
+    pub fn damage_multiplier(&self, source: DamageSource) -> f32 {
+        self.primary.mining().damage_multiplier(source)
+    }
+
+    /// The amount of each resource that the asteroid drops once it is mined. There is less
+    /// of the secondary resource, since it is only found in the veins.
+    pub fn roll_drops(&self) -> [(GameItem, u32); 2] {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let primary = self.primary.mining().roll_yield(&mut rng);
+        let secondary = self.secondary.mining().roll_yield(&mut rng);
+        [
+            (self.primary, primary),
+            (self.secondary, (secondary / 2).max(1)),
+        ]
+    }
+
+    /// Asteroids lose mass as they are mined (and shrink)
+    pub fn mass(&self, transform: &Transform) -> f32 {
+        Asteroid::MASS * transform.scale.x.powi(3)
+    }
+
+    pub fn describe(&self, strings: &Strings) -> String {
+        let key = if self.volatile {
+            "asteroid.volatile"
+        } else {
+            "asteroid.describe"
+        };
+        strings.format(
+            key,
+            &[
+                ("primary", &strings.get(self.primary.name_key())),
+                ("secondary", &strings.get(self.secondary.name_key())),
+            ],
+        )
+    }
+
+    /// The text of the asteroid's tooltip, which includes its remaining health
+    pub fn tooltip(entity: Entity, world: &World) -> String {
+        let asteroids = world.read_component::<Asteroid>();
+        let healths = world.read_component::<Health>();
+        match (asteroids.get(entity), healths.get(entity)) {
+            (Some(asteroid), Some(health)) => {
+                let strings = world.fetch::<Strings>();
+                let tooltip = strings.format(
+                    "asteroid.tooltip",
+                    &[
+                        ("asteroid", &asteroid.describe(&strings)),
+                        ("health", &health.health()),
+                        ("max", &health.max_health()),
+                    ],
+                );
+                // The miners in auto mode leave it to the missiles that are on their way
+                if world.fetch::<TargetAssignment>().incoming_damage(entity) > 0 {
+                    strings.format("asteroid.targeted", &[("tooltip", &tooltip)])
+                } else {
+                    tooltip
+                }
+            }
+            _ => String::new(),
+        }
+    }
+
+    pub fn debris_particles(&self) -> ParticleParams {
+        ParticleParams {
+            color: self.primary.color(),
+            speed: 3.0,
+            lifetime: 1.2,
+            size: 0.12,
+        }
+    }
 }
 
 pub struct AsteroidShrinkSystem;
@@ -102,30 +456,81 @@ impl<'a> System<'a> for AsteroidShrinkSystem {
     fn run(&mut self, data: Self::SystemData) {
         let (mut transforms, asteroids, healths) = data;
 
-        for (transform, _, health) in (&mut transforms, &asteroids, &healths).join() {
-            let scale = 0.5 + (health.health() as f32 / Asteroid::HEALTH as f32) / 2.0;
+        for (transform, asteroid, health) in (&mut transforms, &asteroids, &healths).join() {
+            let size = if asteroid.debris {
+                Asteroid::DEBRIS_SCALE
+            } else {
+                1.0
+            };
+            let scale = size * (0.5 + health.fraction() / 2.0);
             transform.scale = Vector3::new(scale, scale, scale);
         }
     }
 }
 
+/// Leaves the resources of the mined asteroids as item drops, which are added to the
+/// inventory once they are collected
 pub struct AsteroidMinedSystem;
 
 impl<'a> System<'a> for AsteroidMinedSystem {
     type SystemData = (
+        Entities<'a>,
+        Read<'a, LazyUpdate>,
         Read<'a, ToBeRemoved>,
-        WriteExpect<'a, Inventory>,
+        ReadExpect<'a, ObjectMeshes>,
+        WriteExpect<'a, Particles>,
+        WriteExpect<'a, GameStats>,
         ReadStorage<'a, Asteroid>,
         ReadStorage<'a, Health>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, RigidBody>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (to_be_removed, mut inventory, asteroids, healths) = data;
-
-        for (_, asteroid, health) in (to_be_removed.bitset(), &asteroids, &healths).join() {
+        let (
+            entities,
+            lazy_update,
+            to_be_removed,
+            meshes,
+            mut particles,
+            mut game_stats,
+            asteroids,
+            healths,
+            transforms,
+            bodies,
+        ) = data;
+
+        for (entity, _, asteroid, health, transform) in (
+            &entities,
+            to_be_removed.bitset(),
+            &asteroids,
+            &healths,
+            &transforms,
+        )
+            .join()
+        {
             if health.health() == 0 {
                 // Need to make sure it was actually mined (and not just removed)
-                inventory.change_amount(asteroid.0, 5);
+                let velocity = bodies
+                    .get(entity)
+                    .map_or(Vector3::zero(), |body| body.velocity);
+                let [primary, secondary] = asteroid.roll_drops();
+                let drops = [(primary, 1.0), (secondary, -1.0)];
+                // Debris has nothing in it
+                for (drop, side) in drops.iter().filter(|_| !asteroid.debris) {
+                    ItemDrop::spawn(
+                        &lazy_update,
+                        &entities,
+                        &meshes,
+                        *drop,
+                        transform.position,
+                        velocity,
+                        *side,
+                    );
+                }
+
+                game_stats.asteroids_destroyed += 1;
+                particles.emit_burst(transform.position, 48, &asteroid.debris_particles());
             }
         }
     }
@@ -138,7 +543,110 @@ pub struct MiningMissle {
 }
 
 impl MiningMissle {
-    const SPEED: f32 = 6.5;
+    /// The damage done to a raider. Asteroids are always destroyed by a missile.
+    pub const RAIDER_DAMAGE: u32 = 25;
+    /// The damage done to the asteroids and raiders around a missile when it explodes
+    const EXPLOSION_DAMAGE: u32 = 60;
+    const EXPLOSION_RADIUS: f32 = 2.5;
+
+    pub const SPEED: f32 = 6.5;
+    /// The missile homes by flying straight at the target (pure pursuit) instead of
+    /// leading it, so it always takes longer than the ideal intercept. Against a target
+    /// crossing at a right angle pure pursuit takes `s^2 / (s^2 - v^2)` times longer,
+    /// which is used as the correction, capped so that the estimate stays reasonable.
+    const MAX_PURSUIT_CORRECTION: f32 = 1.5;
+
+    /// Estimates where a missile fired from `origin` will hit a target at `target` moving
+    /// with a constant `velocity`. Returns None if the missile will never catch the target.
+    pub fn predict_impact(
+        origin: Vector3<f32>,
+        target: Vector3<f32>,
+        velocity: Vector3<f32>,
+    ) -> Option<Vector3<f32>> {
+        let speed = MiningMissle::SPEED;
+        if velocity.magnitude() >= speed {
+            return None;
+        }
+
+        // The missile flies straight up until it reaches the height of the target
+        let climb_time = (target.z - origin.z).max(0.0) / speed;
+        let target_after_climb = target + velocity * climb_time;
+        let horizontal_time = super::ballistics::intercept_time(
+            Vector3::new(origin.x, origin.y, 0.0),
+            Vector3::new(target_after_climb.x, target_after_climb.y, 0.0),
+            Vector3::new(velocity.x, velocity.y, 0.0),
+            speed,
+        )?;
+
+        let correction = (speed.powi(2) / (speed.powi(2) - velocity.magnitude2()))
+            .min(MiningMissle::MAX_PURSUIT_CORRECTION);
+        let time = climb_time + horizontal_time * correction;
+
+        Some(target + velocity * time)
+    }
+
+    pub fn target(&self) -> Entity {
+        self.target
+    }
+
+    /// The area damage of a missile exploding at `center`
+    pub fn explosion(center: Vector3<f32>) -> AreaDamage {
+        AreaDamage {
+            center,
+            radius: MiningMissle::EXPLOSION_RADIUS,
+            amount: MiningMissle::EXPLOSION_DAMAGE,
+            source: DamageSource::MissileExplosion,
+            exclude: None,
+        }
+    }
+
+    pub const EXPLOSION_PARTICLES: ParticleParams = ParticleParams {
+        color: Vector3::new(1.0, 0.55, 0.15),
+        speed: 4.5,
+        lifetime: 0.6,
+        size: 0.18,
+    };
+}
+
+/// Builds an asteroid flying along the field. Returns the entity, which is added to the
+/// world once it is maintained.
+pub fn build_asteroid(
+    meshes: &ObjectMeshes,
+    builder: LazyBuilder,
+    asteroid: Asteroid,
+    pos: Vector3<f32>,
+    rotation: f32,
+) -> Entity {
+    let velocity = Vector3::new(Asteroid::VELOCITY, 0.0, 0.0);
+    build_moving_asteroid(meshes, builder, asteroid, pos, rotation, velocity)
+}
+
+/// Builds an asteroid flying with the velocity instead of along the field (ex. the debris of
+/// a debris storm)
+pub fn build_moving_asteroid(
+    meshes: &ObjectMeshes,
+    builder: LazyBuilder,
+    asteroid: Asteroid,
+    pos: Vector3<f32>,
+    rotation: f32,
+    velocity: Vector3<f32>,
+) -> Entity {
+    let mut transform = Transform::from_position(pos.x, pos.y, pos.z);
+    transform.set_rotation_z(rotation);
+    builder
+        .with(transform)
+        .with(Model::with_tint(meshes.asteroid, asteroid.tint()))
+        .with(RigidBody { velocity })
+        .with(Collider::new(
+            Hitbox::with_shape(ColliderShape::Sphere(Asteroid::COLLIDER_RADIUS)),
+            Collider::ASTEROID,
+            &[Collider::SHIP, Collider::MISSLE, Collider::PROJECTILE],
+        ))
+        .with(Health::new(asteroid.max_health()))
+        .with(asteroid)
+        .with(Faction::Neutral)
+        .with(Tooltip(Asteroid::tooltip))
+        .build()
 }
 
 pub fn build_mining_missle(
@@ -156,9 +664,10 @@ pub fn build_mining_missle(
         .with(Collider::new(
             Hitbox::with_shape(ColliderShape::Sphere(0.2)),
             Collider::MISSLE,
-            vec![Collider::ASTEROID],
+            &[Collider::ASTEROID, Collider::RAIDER],
         ))
         .with(MiningMissle { target })
+        .with(Faction::Player)
         .build();
 }
 
@@ -0,0 +1,133 @@
+use super::{
+    undo::{UndoCommand, UndoStack},
+    BlockEntity, GameModule, InputAction, InputManager, Ship,
+};
+use crate::profiler::AddProfiled;
+use cgmath::{Vector3, Vector4};
+use specs::prelude::*;
+
+/// Lets the player paint the blocks of their ship with the Paint action
+pub struct PaintModule;
+
+impl GameModule for PaintModule {
+    fn register(&self, world: &mut World) {
+        world.register::<Paint>();
+        world.register::<BlockEntity>();
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(PaintSystem, "paint_system", &[]);
+    }
+}
+
+/// The color a block is painted in, which its model is multiplied by (see
+/// ModelUpdateSystem). Blocks without paint keep the colors of their mesh, like white paint.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Paint(pub Vector3<f32>);
+
+impl Component for Paint {
+    type Storage = FlaggedStorage<Self, HashMapStorage<Self>>;
+}
+
+impl Paint {
+    pub const WHITE: Paint = Paint(Vector3::new(1.0, 1.0, 1.0));
+    /// The colors shown in the paint palette, in sRGB. White is first, which removes the paint.
+    pub const PALETTE: [Paint; 8] = [
+        Paint::WHITE,
+        Paint(Vector3::new(0.6, 0.6, 0.6)),
+        Paint(Vector3::new(0.3, 0.3, 0.32)),
+        Paint(Vector3::new(0.85, 0.25, 0.2)),
+        Paint(Vector3::new(0.95, 0.55, 0.15)),
+        Paint(Vector3::new(0.95, 0.85, 0.3)),
+        Paint(Vector3::new(0.35, 0.75, 0.35)),
+        Paint(Vector3::new(0.3, 0.5, 0.9)),
+    ];
+
+    /// The color that the model is multiplied by
+    pub fn color(&self) -> Vector4<f32> {
+        self.0.extend(1.0)
+    }
+
+    /// The paint as `rrggbb`, which is how it is saved in blueprints
+    pub fn to_hex(self) -> String {
+        let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        format!(
+            "{:02x}{:02x}{:02x}",
+            channel(self.0.x),
+            channel(self.0.y),
+            channel(self.0.z)
+        )
+    }
+
+    pub fn parse(hex: &str) -> Option<Paint> {
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |index: usize| {
+            let value = u8::from_str_radix(hex.get(index..index + 2)?, 16).ok()?;
+            Some(value as f32 / 255.0)
+        };
+        Some(Paint(Vector3::new(channel(0)?, channel(2)?, channel(4)?)))
+    }
+}
+
+/// Paints the block that was clicked with the Paint action. Painting a block white removes
+/// its paint.
+struct PaintSystem;
+
+impl<'a> System<'a> for PaintSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, InputManager>,
+        WriteExpect<'a, UndoStack>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        WriteStorage<'a, Paint>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, input, mut undo_stack, ships, block_entities, mut paints) = data;
+        let index = match input.action {
+            InputAction::Paint(index) => index,
+            _ => return,
+        };
+        let block = match input
+            .clicked_block
+            .filter(|block| block_entities.contains(*block))
+        {
+            Some(block) => block,
+            None => return,
+        };
+
+        let paint = Some(Paint::PALETTE[index]).filter(|paint| *paint != Paint::WHITE);
+        let before = paints.get(block).copied();
+        if paint == before {
+            return;
+        }
+        match paint {
+            Some(paint) => {
+                paints.insert(block, paint).unwrap();
+            }
+            None => {
+                paints.remove(block);
+            }
+        }
+
+        let block_entity = block_entities.get(block).unwrap();
+        let pos = block_entity.root();
+        let ship = (&entities, &ships)
+            .join()
+            .find(|(_, ship)| matches!(ship.tile(pos), Some(tile) if tile.block() == Some(block)));
+        if let Some((ship, _)) = ship {
+            undo_stack.push(
+                ship,
+                UndoCommand::Paint {
+                    pos,
+                    block_id: block_entity.block_id(),
+                    before,
+                    after: paint,
+                },
+            );
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use super::Time;
+use crate::graphics::{GPUParticle, ParticleRenderer};
+use cgmath::{prelude::*, Vector3, Vector4};
+use rand::Rng;
+use specs::prelude::*;
+
+/// Describes how the particles of a single burst look and move.
+#[derive(Clone, Copy)]
+pub struct ParticleParams {
+    pub color: Vector3<f32>,
+    /// The maximum speed of a particle (the actual speed is randomized)
+    pub speed: f32,
+    /// The lifetime of a particle in seconds
+    pub lifetime: f32,
+    pub size: f32,
+}
+
+struct Particle {
+    position: Vector3<f32>,
+    velocity: Vector3<f32>,
+    lifetime: f32,
+    max_lifetime: f32,
+    size: f32,
+    color: Vector3<f32>,
+}
+
+/// Stores all of the CPU simulated particles. The amount of particles is capped
+/// by the size of the GPU instance buffer, so new particles are dropped once the pool is full.
+pub struct Particles {
+    particles: Vec<Particle>,
+}
+
+impl Particles {
+    pub const MAX_PARTICLES: usize = ParticleRenderer::MAX_PARTICLES as usize;
+    /// The fraction of its velocity that a particle keeps after a second
+    const DRAG: f32 = 0.086;
+
+    pub fn new() -> Self {
+        Self {
+            particles: Vec::with_capacity(Self::MAX_PARTICLES),
+        }
+    }
+
+    pub fn emit_burst(&mut self, position: Vector3<f32>, count: usize, params: &ParticleParams) {
+        let mut rng = rand::thread_rng();
+        let count = count.min(Self::MAX_PARTICLES - self.particles.len());
+
+        for _ in 0..count {
+            let direction = Vector3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
+            let direction = if direction.magnitude2() > 0.0 {
+                direction.normalize()
+            } else {
+                Vector3::unit_z()
+            };
+            let lifetime = params.lifetime * rng.gen_range(0.5..1.0);
+
+            self.particles.push(Particle {
+                position,
+                velocity: direction * params.speed * rng.gen_range(0.2..1.0),
+                lifetime,
+                max_lifetime: lifetime,
+                size: params.size,
+                color: params.color,
+            });
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    /// Converts the particles to their GPU representation. Particles fade out
+    /// over their lifetime (the renderer uses additive blending).
+    pub fn as_gpu_particles(&self) -> Vec<GPUParticle> {
+        self.particles
+            .iter()
+            .map(|particle| {
+                let fade = particle.lifetime / particle.max_lifetime;
+                GPUParticle {
+                    pos: particle.position,
+                    size: particle.size * (0.5 + fade / 2.0),
+                    color: Vector4::new(particle.color.x, particle.color.y, particle.color.z, fade),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Simulates the particles. This is part of the regular dispatcher so
+/// particles stop moving whenever the world is not being updated.
+pub struct ParticleSystem;
+
+impl<'a> System<'a> for ParticleSystem {
+    type SystemData = (ReadExpect<'a, Time>, WriteExpect<'a, Particles>);
+
+    fn run(&mut self, (time, mut particles): Self::SystemData) {
+        let dt = time.delta;
+        let drag = Particles::DRAG.powf(dt);
+
+        for particle in &mut particles.particles {
+            particle.position += particle.velocity * dt;
+            particle.velocity *= drag;
+            particle.lifetime -= dt;
+        }
+
+        particles
+            .particles
+            .retain(|particle| particle.lifetime > 0.0);
+    }
+}
@@ -1,19 +1,29 @@
-use super::{SimpleStorage, ToBeRemoved, Transform};
-use crate::graphics::{Mesh, MeshId, MeshManager, ModelId, Vertex};
-use cgmath::{prelude::*, Matrix4, Point3, Vector3};
+use super::{
+    damage::{DamageEvent, DamageEvents, DamageSource},
+    faction,
+    objects::{Asteroid, MiningMissle},
+    projectile::Projectile,
+    selection::Selection,
+    status::{StatusEffect, StatusEvents, StatusKind},
+    InputManager, Line, LineBatch, Model, Particles, SimpleStorage, Time, ToBeRemoved, Transform,
+};
+use crate::graphics::MeshManager;
+use cgmath::{prelude::*, Point3, Transform as _, Vector3};
 use nalgebra::{
     base::Vector3 as NVector3,
     geometry::Point3 as NPoint3,
     geometry::{Isometry3, Quaternion, Translation3, UnitQuaternion},
+    Unit,
 };
 use ncollide3d::{
     pipeline::narrow_phase::ContactEvent,
     pipeline::object::{CollisionGroups, CollisionObjectSlabHandle},
-    query::Ray,
+    query::{Ray, RayCast, TOIStatus},
     shape,
     world::CollisionWorld,
 };
-use specs::{prelude::*, Component};
+use specs::{prelude::*, rayon::prelude::*, Component};
+use winit::event::VirtualKeyCode;
 
 #[derive(Component)]
 #[storage(VecStorage)]
@@ -21,53 +31,227 @@ pub struct RigidBody {
     pub velocity: Vector3<f32>,
 }
 
-pub struct PhysicsSystem;
+/// The colliders used for collisions between entities. Like the RaycastWorld, colliders
+/// are only added once and then moved, so the world is not rebuilt every update.
+pub struct PhysicsWorld(CollisionWorld<f32, Entity>);
+
+impl PhysicsWorld {
+    pub fn new() -> Self {
+        Self(CollisionWorld::new(0.02))
+    }
+}
+
+pub struct PhysicsSystem {
+    pub transform_reader: ReaderId<ComponentEvent>,
+    pub modified: BitSet,
+}
+
+impl PhysicsSystem {
+    /// The groups of the bodies that are fast enough to skip over thin hitboxes (ex. a laser
+    /// gadget) in a single update, so their path is swept instead
+    const SWEPT_GROUPS: [usize; 3] = [Collider::MISSLE, Collider::ASTEROID, Collider::PROJECTILE];
+    /// How much of its thickness a body can move in an update before its path is swept
+    const SWEEP_FRACTION: f32 = 0.5;
+    /// How far past the time of impact a swept body is stopped, so it overlaps what it hit
+    /// and the contact is started
+    const SWEEP_OVERLAP: f32 = 0.01;
+
+    /// How far the body moves this update. A fast body stops where its path first touches a
+    /// collider it can hit, so the contact is found even if the collider is thinner than the
+    /// step. Other colliders are swept against where they were in the last update.
+    fn step(
+        world: &CollisionWorld<f32, Entity>,
+        transform: &Transform,
+        body: &RigidBody,
+        collider: Option<&Collider>,
+        delta: f32,
+    ) -> Vector3<f32> {
+        let step = body.velocity * delta;
+        let distance = step.magnitude();
+        let (collider, physics_id) = match collider {
+            Some(collider) => match collider.physics_id {
+                Some(physics_id) => (collider, physics_id),
+                None => return step,
+            },
+            None => return step,
+        };
+        let fast = distance > collider.hitbox.thickness() * PhysicsSystem::SWEEP_FRACTION;
+        if !fast || !PhysicsSystem::SWEPT_GROUPS.contains(&collider.group) {
+            return step;
+        }
+
+        let position = to_nalgebra_pos(transform, &collider.hitbox.offset);
+        let direction = Unit::new_normalize(NVector3::new(step.x, step.y, step.z));
+        let shape = collider.hitbox.as_shape_handle();
+        // Colliders that it already overlaps were hit before, and are moved through
+        let first_impact = world
+            .sweep_test(
+                shape.as_ref(),
+                &position,
+                &direction,
+                distance,
+                &collider.groups,
+            )
+            .filter(|(handle, toi)| *handle != physics_id && toi.status != TOIStatus::Penetrating)
+            .map(|(_, toi)| toi.toi)
+            .filter(|toi| *toi <= distance)
+            .fold(None, |first: Option<f32>, toi| {
+                Some(first.map_or(toi, |first| first.min(toi)))
+            });
+
+        match first_impact {
+            Some(toi) => step * ((toi + PhysicsSystem::SWEEP_OVERLAP).min(distance) / distance),
+            None => step,
+        }
+    }
+}
+
+/// Where two colliders touched, in world space
+#[derive(Clone, Copy, Debug)]
+struct Impact {
+    point: Vector3<f32>,
+    /// Points out of the collider that was hit. It is zero if the contact was not found.
+    normal: Vector3<f32>,
+}
+
+impl Impact {
+    /// How far outside of the hit collider the effects of an impact start
+    const EFFECT_OFFSET: f32 = 0.1;
+
+    /// Finds where the colliders of the contact touch, with the normal pointing out of `hit`.
+    /// Falls back to the position of `hit` if the contact is gone.
+    fn find(
+        world: &CollisionWorld<f32, Entity>,
+        (h1, h2): (CollisionObjectSlabHandle, CollisionObjectSlabHandle),
+        hit: Entity,
+        transforms: &WriteStorage<Transform>,
+    ) -> Option<Impact> {
+        let contact = world
+            .contact_pair(h1, h2, true)
+            .and_then(|(first, _, _, manifold)| {
+                let contact = manifold.deepest_contact()?.contact;
+                Some((*world.collision_object(first)?.data(), contact))
+            });
+
+        match contact {
+            Some((first, contact)) => {
+                let point = nalgebra::center(&contact.world1, &contact.world2);
+                // The contact's normal points out of the first collider
+                let normal = if first == hit {
+                    contact.normal.into_inner()
+                } else {
+                    -contact.normal.into_inner()
+                };
+                Some(Impact {
+                    point: Vector3::new(point.x, point.y, point.z),
+                    normal: Vector3::new(normal.x, normal.y, normal.z),
+                })
+            }
+            None => transforms.get(hit).map(|transform| Impact {
+                point: transform.position,
+                normal: Vector3::zero(),
+            }),
+        }
+    }
+
+    /// A point just outside of the hit collider, so effects (ex. explosions) are not hidden
+    /// inside of it
+    fn effect_point(&self) -> Vector3<f32> {
+        self.point + self.normal * Impact::EFFECT_OFFSET
+    }
+}
 
 impl<'a> System<'a> for PhysicsSystem {
     type SystemData = (
         Entities<'a>,
+        ReadExpect<'a, Time>,
         Write<'a, ToBeRemoved>,
+        WriteExpect<'a, Particles>,
+        WriteExpect<'a, PhysicsWorld>,
         WriteStorage<'a, Transform>,
-        ReadStorage<'a, Collider>,
+        WriteStorage<'a, Collider>,
         ReadStorage<'a, RigidBody>,
         ReadStorage<'a, super::BlockEntity>,
-        ReadStorage<'a, super::objects::Asteroid>,
-        ReadStorage<'a, super::objects::MiningMissle>,
+        ReadStorage<'a, Asteroid>,
+        ReadStorage<'a, MiningMissle>,
+        ReadStorage<'a, Projectile>,
+        ReadStorage<'a, super::raider::Raider>,
+        ReadStorage<'a, super::crew::CrewMember>,
+        WriteExpect<'a, DamageEvents>,
+        ReadStorage<'a, faction::Faction>,
+        Write<'a, StatusEvents>,
+        WriteStorage<'a, super::Ship>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
         let (
             entities,
+            time,
             mut to_be_removed,
+            mut particles,
+            mut world,
             mut transforms,
-            colliders,
+            mut colliders,
             bodies,
             blocks,
             asteroids,
             missles,
+            projectiles,
+            raiders,
+            crew,
+            mut damage_events,
+            factions,
+            mut status_events,
+            mut ships,
         ) = data;
-        let mut world: CollisionWorld<f32, Entity> = CollisionWorld::new(0.02);
-        let dt = 1.0 / 60.0;
+        let world = &mut world.0;
         let contact_query = ncollide3d::pipeline::object::GeometricQueryType::Contacts(0.0, 0.0);
 
         // Update Rigid Bodies
-        for (transform, body) in (&mut transforms, &bodies).join() {
-            transform.position += body.velocity * dt;
+        for (transform, body, collider) in (&mut transforms, &bodies, colliders.maybe()).join() {
+            transform.position += PhysicsSystem::step(world, transform, body, collider, time.delta);
+        }
+
+        // Moving the bodies marks their transforms as modified, so this is read afterwards
+        self.modified.clear();
+        for event in transforms.channel().read(&mut self.transform_reader) {
+            match event {
+                ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) => {
+                    self.modified.add(*id)
+                }
+                _ => false,
+            };
         }
 
         // Setup Collision
-        for (entity, transform, collider) in (&entities, &transforms, &colliders).join() {
+        for (entity, transform, collider) in (&entities, &transforms, &mut colliders).join() {
             let position = to_nalgebra_pos(&transform, &collider.hitbox.offset);
-            let shape = collider.hitbox.as_shape_handle();
-            let mut group = CollisionGroups::new()
-                .with_membership(&[collider.group])
-                .with_whitelist(&collider.whitelist);
-            group.disable_self_interaction();
-
-            world.add(position, shape, group, contact_query, entity);
+            if let (Some(id), true) = (collider.physics_id, collider.groups_changed) {
+                world
+                    .get_mut(id)
+                    .expect("Physics ID does not exist in collision world!")
+                    .set_collision_groups(collider.groups);
+            }
+            collider.groups_changed = false;
+            match collider.physics_id {
+                Some(id) if self.modified.contains(entity.id()) => world
+                    .get_mut(id)
+                    .expect("Physics ID does not exist in collision world!")
+                    .set_position(position),
+                Some(_) => {}
+                None => {
+                    let shape = collider.hitbox.as_shape_handle();
+                    let id = world.add(position, shape, collider.groups, contact_query, entity);
+                    collider.physics_id = Some(id.0);
+                }
+            }
         }
         // crate::print_time("PhysicsStart");
-        world.update();
+        {
+            #[cfg(feature = "count-allocations")]
+            let _exempt = crate::alloc_counter::Exempt::begin();
+            world.update();
+        }
         // crate::print_time("PhysicsEnd");
 
         //Process Collisions
@@ -79,17 +263,58 @@ impl<'a> System<'a> for PhysicsSystem {
             component.contains(e1) || component.contains(e2)
         }
 
+        // Contacts only start once, since the world is kept between updates
         for event in world.contact_events() {
             match event {
                 ContactEvent::Started(h1, h2) => {
                     let entity1 = *world.collision_object(*h1).unwrap().data();
                     let entity2 = *world.collision_object(*h2).unwrap().data();
 
-                    if has_component(entity1, entity2, &blocks) {
-                        if asteroids.contains(entity1) {
-                            to_be_removed.add(entity1);
-                        } else if asteroids.contains(entity2) {
-                            to_be_removed.add(entity2);
+                    // Asteroids hit the blocks, and the crew on the tiles they reach
+                    if has_component(entity1, entity2, &blocks)
+                        || has_component(entity1, entity2, &crew)
+                    {
+                        let (asteroid, target) = if asteroids.contains(entity1) {
+                            (entity1, entity2)
+                        } else {
+                            (entity2, entity1)
+                        };
+
+                        if asteroids.contains(asteroid) {
+                            to_be_removed.add(asteroid);
+                            let impact = Impact::find(world, (*h1, *h2), target, &transforms);
+                            // The ShieldSystem stops asteroids at the edge of the bubble, but
+                            // one can still be inside when the bubble comes up around it
+                            let shielded = match &impact {
+                                Some(impact) => (&mut ships).join().any(|ship| {
+                                    ship.shield().covers(impact.point, 0.0)
+                                        && ship
+                                            .shield_mut()
+                                            .absorb(Asteroid::IMPACT_DAMAGE as f32, impact.point)
+                                }),
+                                None => false,
+                            };
+                            if shielded {
+                                continue;
+                            }
+                            if let Some(impact) = impact {
+                                damage_events.push(DamageEvent::new(
+                                    target,
+                                    Asteroid::IMPACT_DAMAGE,
+                                    DamageSource::AsteroidImpact,
+                                    impact.point,
+                                ));
+                            }
+                            if asteroids.get(asteroid).unwrap().volatile {
+                                status_events.push(
+                                    target,
+                                    StatusEffect::new(
+                                        StatusKind::Burning,
+                                        Asteroid::BURN_TIME,
+                                        Asteroid::BURN_DAMAGE_PER_SECOND,
+                                    ),
+                                );
+                            }
                         }
                     }
 
@@ -98,6 +323,87 @@ impl<'a> System<'a> for PhysicsSystem {
                     {
                         to_be_removed.add(entity1);
                         to_be_removed.add(entity2);
+
+                        let asteroid = if missles.contains(entity1) {
+                            entity2
+                        } else {
+                            entity1
+                        };
+                        if let Some(impact) = Impact::find(world, (*h1, *h2), asteroid, &transforms)
+                        {
+                            particles.emit_burst(
+                                impact.effect_point(),
+                                32,
+                                &MiningMissle::EXPLOSION_PARTICLES,
+                            );
+                            // The asteroid that was hit is destroyed, and the explosion
+                            // damages the asteroids around it
+                            damage_events.push_area(
+                                MiningMissle::explosion(impact.point).excluding(asteroid),
+                            );
+                        }
+                    }
+
+                    // Projectiles are spent on the first asteroid they hit
+                    if has_component(entity1, entity2, &projectiles)
+                        && has_component(entity1, entity2, &asteroids)
+                    {
+                        let (projectile, asteroid) = if projectiles.contains(entity1) {
+                            (entity1, entity2)
+                        } else {
+                            (entity2, entity1)
+                        };
+                        to_be_removed.add(projectile);
+                        let kind = projectiles.get(projectile).unwrap().kind;
+                        if let Some(impact) = Impact::find(world, (*h1, *h2), asteroid, &transforms)
+                        {
+                            damage_events.push(
+                                DamageEvent::new(
+                                    asteroid,
+                                    kind.damage,
+                                    DamageSource::Projectile,
+                                    impact.point,
+                                )
+                                .confirmed(),
+                            );
+                            particles.emit_burst(
+                                impact.effect_point(),
+                                16,
+                                &Projectile::IMPACT_PARTICLES,
+                            );
+                        }
+                    }
+
+                    if has_component(entity1, entity2, &missles)
+                        && has_component(entity1, entity2, &raiders)
+                    {
+                        let (missle, raider) = if missles.contains(entity1) {
+                            (entity1, entity2)
+                        } else {
+                            (entity2, entity1)
+                        };
+                        to_be_removed.add(missle);
+
+                        // Missiles fizzle out on the raiders of their own faction
+                        let faction = faction::faction_of(&factions, missle);
+                        if !faction.can_damage(faction::faction_of(&factions, raider)) {
+                            continue;
+                        }
+                        if let Some(impact) = Impact::find(world, (*h1, *h2), raider, &transforms) {
+                            damage_events.push(DamageEvent::new(
+                                raider,
+                                MiningMissle::RAIDER_DAMAGE,
+                                DamageSource::MissileExplosion,
+                                impact.point,
+                            ));
+                            damage_events
+                                .push_area(MiningMissle::explosion(impact.point).excluding(raider));
+                            particles.emit_burst(
+                                impact.effect_point(),
+                                32,
+                                &MiningMissle::EXPLOSION_PARTICLES,
+                            );
+                        }
                     }
                 }
                 ContactEvent::Stopped(_, _) => {}
@@ -129,6 +435,9 @@ pub struct Hitbox {
 }
 
 impl Hitbox {
+    /// How many lines each circle of a sphere's wireframe is made of
+    const CIRCLE_SEGMENTS: usize = 12;
+
     pub fn new(shape: ColliderShape, offset: Vector3<f32>) -> Hitbox {
         Hitbox { shape, offset }
     }
@@ -153,29 +462,74 @@ impl Hitbox {
         }
     }
 
-    pub fn to_hitbox_model(&self, transform: &Transform) -> Matrix4<f32> {
-        let mut hb_transform = transform.clone();
-        hb_transform.position += self.offset;
-
+    /// The size of the hitbox along its thinnest side, which is how far something can move
+    /// through it
+    pub fn thickness(&self) -> f32 {
         match self.shape {
-            ColliderShape::Cuboid(size) => hb_transform.scale = size,
-            ColliderShape::Sphere(radius) => {
-                hb_transform.scale = Vector3::new(radius, radius, radius);
-            }
+            ColliderShape::Cuboid(size) => size.x.min(size.y).min(size.z),
+            ColliderShape::Sphere(radius) => radius * 2.0,
         }
+    }
 
-        hb_transform.scale.x *= transform.scale.x;
-        hb_transform.scale.y *= transform.scale.z;
-        hb_transform.scale.z *= transform.scale.y;
+    /// The height of the top of the hitbox in world space
+    pub fn top(&self, transform: &Transform) -> f32 {
+        let half_height = match self.shape {
+            ColliderShape::Cuboid(size) => size.z / 2.0,
+            ColliderShape::Sphere(radius) => radius,
+        };
 
-        hb_transform.as_matrix()
+        transform.position.z + self.offset.z + half_height * transform.scale.z
     }
 
-    pub fn to_hitbox_mesh(&self, meshes: &HitboxMeshes) -> MeshId {
+    /// The edges of the hitbox in world space. They are placed with the same position and
+    /// rotation as the shape that the PhysicsSystem and the raycasts use (see
+    /// to_nalgebra_pos), so the transform's scale is not applied. Cuboids have 12 edges,
+    /// and spheres are drawn as a circle around each of their axes.
+    pub fn wireframe(&self, transform: &Transform) -> Vec<(Vector3<f32>, Vector3<f32>)> {
+        let position = to_nalgebra_pos(transform, &self.offset);
+        let to_world = |x: f32, y: f32, z: f32| {
+            let point = position * NPoint3::new(x, y, z);
+            Vector3::new(point.x, point.y, point.z)
+        };
+        let mut edges = Vec::new();
+
         match self.shape {
-            ColliderShape::Cuboid(_) => meshes.unit_cube,
-            ColliderShape::Sphere(_) => meshes.unit_sphere,
+            ColliderShape::Cuboid(size) => {
+                // Each bit of the index picks the side of an axis, so the corners that
+                // differ by one bit share an edge
+                let corner = |index: usize| {
+                    let side = |bit: usize, half: f32| if index & bit == 0 { -half } else { half };
+                    to_world(
+                        side(1, size.x / 2.0),
+                        side(2, size.y / 2.0),
+                        side(4, size.z / 2.0),
+                    )
+                };
+                for index in 0..8 {
+                    for bit in &[1, 2, 4] {
+                        if index & bit == 0 {
+                            edges.push((corner(index), corner(index | bit)));
+                        }
+                    }
+                }
+            }
+            ColliderShape::Sphere(radius) => {
+                let point = |step: usize| {
+                    let angle = crate::PI * 2.0 * step as f32 / Hitbox::CIRCLE_SEGMENTS as f32;
+                    let (sin, cos) = angle.sin_cos();
+                    (sin * radius, cos * radius)
+                };
+                for step in 0..Hitbox::CIRCLE_SEGMENTS {
+                    let (sin, cos) = point(step);
+                    let (next_sin, next_cos) = point(step + 1);
+                    edges.push((to_world(cos, sin, 0.0), to_world(next_cos, next_sin, 0.0)));
+                    edges.push((to_world(cos, 0.0, sin), to_world(next_cos, 0.0, next_sin)));
+                    edges.push((to_world(0.0, cos, sin), to_world(0.0, next_cos, next_sin)));
+                }
+            }
         }
+
+        edges
     }
 }
 
@@ -191,9 +545,13 @@ pub enum ColliderShape {
 pub struct Collider {
     pub hitbox: Hitbox,
     pub group: usize,
-    pub whitelist: Vec<usize>,
+    /// The group and the groups it collides with. These are stored as bit masks, so
+    /// building colliders does not allocate.
+    groups: CollisionGroups,
+    /// Set when the groups change, so the PhysicsSystem updates the collision world
+    groups_changed: bool,
+    physics_id: Option<CollisionObjectSlabHandle>,
     raycast_id: Option<CollisionObjectSlabHandle>,
-    model_id: Option<ModelId>,
 }
 
 impl Collider {
@@ -203,18 +561,96 @@ impl Collider {
     pub const ASTEROID: usize = 1;
     pub const SHIP: usize = 2;
     pub const MISSLE: usize = 3;
+    pub const RAIDER: usize = 4;
+    /// Item drops, which are only raycast (to click them)
+    pub const ITEM: usize = 5;
+    /// Blocks that broke off the ship, which drift away without colliding
+    pub const DERELICT: usize = 6;
+    /// Unguided projectiles (ex. the cannon's slugs)
+    pub const PROJECTILE: usize = 7;
+
+    pub fn new(hitbox: Hitbox, group: usize, whitelist: &[usize]) -> Self {
+        let mut groups = CollisionGroups::new()
+            .with_membership(&[group])
+            .with_whitelist(whitelist);
+        groups.disable_self_interaction();
 
-    pub fn new(hitbox: Hitbox, group: usize, whitelist: Vec<usize>) -> Self {
         Self {
             hitbox,
             group,
-            whitelist,
+            groups,
+            groups_changed: false,
+            physics_id: None,
             raycast_id: None,
-            model_id: None,
         }
     }
+
+    /// Changes which groups the collider collides with (ex. an open door lets asteroids
+    /// through). Raycasts are not affected.
+    pub fn set_whitelist(&mut self, whitelist: &[usize]) {
+        let mut groups = CollisionGroups::new()
+            .with_membership(&[self.group])
+            .with_whitelist(whitelist);
+        groups.disable_self_interaction();
+        self.groups = groups;
+        self.groups_changed = true;
+    }
+
+    /// Moves the collider to another group (ex. a block that broke off the ship). Like
+    /// set_whitelist, raycasts are not affected.
+    pub fn set_group(&mut self, group: usize, whitelist: &[usize]) {
+        self.group = group;
+        self.set_whitelist(whitelist);
+    }
+}
+
+/// The closest collider hit by a raycast
+#[derive(Debug, Clone, Copy)]
+pub struct RaycastHit {
+    pub entity: Entity,
+    /// The distance from the start of the ray to the hit point
+    pub distance: f32,
+    pub point: Vector3<f32>,
+}
+
+impl RaycastHit {
+    fn new(entity: Entity, ray: &Ray<f32>, toi: f32) -> Self {
+        let point = ray.point_at(toi);
+        Self {
+            entity,
+            distance: toi,
+            point: Vector3::new(point.x, point.y, point.z),
+        }
+    }
+}
+
+/// The collision meshes of the models, which a raycast can refine its collider hits with.
+/// Only picking with the cursor needs the precision, so the other raycasts don't refine.
+pub struct MeshRefinement<'a, 'b> {
+    pub meshes: &'b MeshManager,
+    pub models: &'b ReadStorage<'a, Model>,
+    pub transforms: &'b ReadStorage<'a, Transform>,
+}
+
+impl MeshRefinement<'_, '_> {
+    /// The time of impact of the ray with the entity's collision mesh. Returns None if the
+    /// entity has no collision mesh, and Some(None) if the ray misses it.
+    fn toi(&self, entity: Entity, ray: &Ray<f32>) -> Option<Option<f32>> {
+        let model = self.models.get(entity)?;
+        let mesh = self.meshes.collision_mesh(model.mesh_id)?;
+        let inverse = self.transforms.get(entity)?.as_matrix().invert()?;
+        // The ray is moved into the space of the model, instead of moving every vertex
+        let origin = Point3::new(ray.origin.x, ray.origin.y, ray.origin.z);
+        let direction = Vector3::new(ray.dir.x, ray.dir.y, ray.dir.z);
+        Some(mesh.raycast(
+            inverse.transform_point(origin),
+            inverse.transform_vector(direction),
+        ))
+    }
 }
 
+/// The colliders are stored in a bounding volume tree (the broad phase of the collision
+/// world), so a raycast only tests the colliders whose bounding boxes are along the ray.
 pub struct RaycastWorld(CollisionWorld<f32, Entity>);
 
 impl RaycastWorld {
@@ -224,12 +660,18 @@ impl RaycastWorld {
 
     /// Note: If the whitelist is empty,
     /// then the whitelist is set to ALL groups.
+    /// The excluded entity is never hit (ex. a gadget raycasting from inside its own hitbox).
+    /// With a refinement, the hits are refined against the collision meshes of the models.
     pub fn raycast(
         &self,
-        whitelist: Vec<usize>,
+        whitelist: &[usize],
         near: Vector3<f32>,
         far: Vector3<f32>,
-    ) -> Option<Entity> {
+        exclude: Option<Entity>,
+        refinement: Option<&MeshRefinement>,
+    ) -> Option<RaycastHit> {
+        #[cfg(feature = "count-allocations")]
+        let _exempt = crate::alloc_counter::Exempt::begin();
         let origin = NPoint3::new(near.x, near.y, near.z);
         let dir = NVector3::new(far.x - near.x, far.y - near.y, far.z - near.z).normalize();
         let ray = Ray::new(origin, dir);
@@ -237,16 +679,179 @@ impl RaycastWorld {
         let mut groups = CollisionGroups::new();
 
         if !whitelist.is_empty() {
-            groups.set_whitelist(&whitelist);
+            groups.set_whitelist(whitelist);
+        }
+
+        if let Some(refinement) = refinement {
+            return self.raycast_refined(&ray, toi, &groups, exclude, refinement);
+        }
+
+        // The groups are checked while the tree is traversed, so filtered colliders can't hide a
+        // hit. The excluded entity is not a group, so every hit has to be checked to skip it.
+        let hit = match exclude {
+            None => self
+                .0
+                .first_interference_with_ray(&ray, toi, &groups)
+                .map(|result| RaycastHit::new(*result.co.data(), &ray, result.inter.toi)),
+            Some(exclude) => self
+                .0
+                .interferences_with_ray(&ray, toi, &groups)
+                .filter(|(_, object, _)| *object.data() != exclude)
+                .map(|(_, object, inter)| RaycastHit::new(*object.data(), &ray, inter.toi))
+                .min_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap()),
+        };
+
+        if crate::VERIFY_RAYCASTS {
+            let expected = self.raycast_brute_force(&ray, toi, &groups, exclude);
+            if hit.map(|hit| hit.entity) != expected.map(|hit| hit.entity) {
+                println!(
+                    "[Raycast] Mismatch: found {:?}, expected {:?}",
+                    hit, expected
+                );
+            }
         }
 
+        hit
+    }
+
+    /// Refines each collider hit along the ray with the entity's collision mesh, so a hit
+    /// on the collider that misses the mesh is passed through. Entities without a collision
+    /// mesh keep their collider hit.
+    fn raycast_refined(
+        &self,
+        ray: &Ray<f32>,
+        max_toi: f32,
+        groups: &CollisionGroups,
+        exclude: Option<Entity>,
+        refinement: &MeshRefinement,
+    ) -> Option<RaycastHit> {
+        self.0
+            .interferences_with_ray(ray, max_toi, groups)
+            .filter(|(_, object, _)| Some(*object.data()) != exclude)
+            .filter_map(|(_, object, inter)| {
+                let entity = *object.data();
+                let toi = refinement.toi(entity, ray).unwrap_or(Some(inter.toi))?;
+                Some(RaycastHit::new(entity, ray, toi))
+            })
+            .min_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap())
+    }
+
+    /// Tests the ray against every collider. Only used to verify the results of `raycast`.
+    fn raycast_brute_force(
+        &self,
+        ray: &Ray<f32>,
+        max_toi: f32,
+        groups: &CollisionGroups,
+        exclude: Option<Entity>,
+    ) -> Option<RaycastHit> {
         self.0
-            .first_interference_with_ray(&ray, toi, &groups)
-            .map(|result| *result.co.data())
+            .collision_objects()
+            .filter(|(_, object)| object.collision_groups().can_interact_with_groups(groups))
+            .filter(|(_, object)| Some(*object.data()) != exclude)
+            .filter_map(|(_, object)| {
+                let toi = object
+                    .shape()
+                    .toi_with_ray(object.position(), ray, max_toi, true)?;
+                Some(RaycastHit::new(*object.data(), ray, toi))
+            })
+            .min_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap())
+    }
+}
+
+/// A handle to the hit of a batched raycast. It can only be read in the update after the
+/// raycast was requested.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RaycastHandle {
+    index: usize,
+    batch: u32,
+}
+
+struct RaycastRequest {
+    whitelist: &'static [usize],
+    near: Vector3<f32>,
+    far: Vector3<f32>,
+    exclude: Option<Entity>,
+}
+
+/// Raycasts that are done together, in parallel, once the RaycastWorld was updated at the
+/// end of the update. Systems that raycast every update request their raycasts while they
+/// run, and read the hits in the next update, so the raycasts are not done one after the
+/// other. One-off raycasts (ex. picking with the cursor) use RaycastWorld::raycast.
+#[derive(Default)]
+pub struct RaycastBatch {
+    requests: Vec<RaycastRequest>,
+    hits: Vec<Option<RaycastHit>>,
+    /// The batch that the new requests are added to
+    batch: u32,
+    /// The batch that the hits are from
+    hits_batch: Option<u32>,
+}
+
+impl RaycastBatch {
+    /// Requests a raycast like RaycastWorld::raycast, whose hit can be read in the next update
+    pub fn request(
+        &mut self,
+        whitelist: &'static [usize],
+        near: Vector3<f32>,
+        far: Vector3<f32>,
+        exclude: Option<Entity>,
+    ) -> RaycastHandle {
+        self.requests.push(RaycastRequest {
+            whitelist,
+            near,
+            far,
+            exclude,
+        });
+        RaycastHandle {
+            index: self.requests.len() - 1,
+            batch: self.batch,
+        }
+    }
+
+    /// The hit of the requested raycast. None if nothing was hit, or if the raycast was not
+    /// requested in the last update.
+    pub fn hit(&self, handle: RaycastHandle) -> Option<RaycastHit> {
+        if self.hits_batch != Some(handle.batch) {
+            return None;
+        }
+        self.hits.get(handle.index).copied().flatten()
+    }
+
+    fn run(&mut self, world: &RaycastWorld) {
+        self.requests
+            .par_iter()
+            .map(|request| {
+                world.raycast(
+                    request.whitelist,
+                    request.near,
+                    request.far,
+                    request.exclude,
+                    None,
+                )
+            })
+            .collect_into_vec(&mut self.hits);
+        self.requests.clear();
+        self.hits_batch = Some(self.batch);
+        self.batch = self.batch.wrapping_add(1);
+    }
+}
+
+/// Does the requested raycasts of the RaycastBatch
+pub struct RaycastBatchSystem;
+
+impl<'a> System<'a> for RaycastBatchSystem {
+    type SystemData = (ReadExpect<'a, RaycastWorld>, WriteExpect<'a, RaycastBatch>);
+
+    fn run(&mut self, (world, mut batch): Self::SystemData) {
+        batch.run(&world);
     }
 }
 
-pub struct RaycastSystem;
+/// Adds new colliders to the RaycastWorld, and moves the colliders whose transforms changed
+pub struct RaycastSystem {
+    pub transform_reader: ReaderId<ComponentEvent>,
+    pub modified: BitSet,
+}
 
 impl<'a> System<'a> for RaycastSystem {
     type SystemData = (
@@ -254,14 +859,21 @@ impl<'a> System<'a> for RaycastSystem {
         WriteExpect<'a, RaycastWorld>,
         ReadStorage<'a, Transform>,
         WriteStorage<'a, Collider>,
-        WriteExpect<'a, MeshManager>,
-        ReadExpect<'a, HitboxMeshes>,
     );
 
-    fn run(&mut self, data: Self::SystemData) {
-        let (entities, mut world, transforms, mut colliders, mut meshes, hitbox_meshes) = data;
+    fn run(&mut self, (entities, mut world, transforms, mut colliders): Self::SystemData) {
         let world = &mut world.0;
         let contact_query = ncollide3d::pipeline::object::GeometricQueryType::Contacts(0.8, 0.8);
+        self.modified.clear();
+
+        for event in transforms.channel().read(&mut self.transform_reader) {
+            match event {
+                ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) => {
+                    self.modified.add(*id)
+                }
+                _ => false,
+            };
+        }
 
         for (entity, transform, mut colliders) in
             (&entities, &transforms, &mut colliders.restrict_mut()).join()
@@ -269,17 +881,18 @@ impl<'a> System<'a> for RaycastSystem {
             // Safety: We can use get_unchecked here because we know the entity is alive
             // from joining the Entities resource
             let collider = colliders.get_unchecked();
+            if collider.raycast_id.is_some() && !self.modified.contains(entity.id()) {
+                continue;
+            }
+
             let position = to_nalgebra_pos(&transform, &collider.hitbox.offset);
-            let hitbox_mesh = collider.hitbox.to_hitbox_mesh(&hitbox_meshes);
-            let hitbox_matrix = collider.hitbox.to_hitbox_model(&transform);
 
-            if let (Some(id), Some(model)) = (collider.raycast_id, collider.model_id) {
+            if let Some(id) = collider.raycast_id {
                 let collider_object = world
                     .get_mut(id)
                     .expect("Raycast ID does not exist in collision world!");
+                // The hitbox's shape does not change, so only the position is updated
                 collider_object.set_position(position);
-                collider_object.set_shape(collider.hitbox.as_shape_handle());
-                meshes.update_model(hitbox_mesh, model, hitbox_matrix);
             } else {
                 let collider = colliders.get_mut_unchecked();
                 let shape = collider.hitbox.as_shape_handle();
@@ -289,100 +902,293 @@ impl<'a> System<'a> for RaycastSystem {
 
                 collider.raycast_id =
                     Some(world.add(position, shape, group, contact_query, entity).0);
-
-                // TODO: Rendering happens in the raycast update system? This either should be renamed
-                // or needs to happen in a different system.
-                collider.model_id = Some(meshes.new_model(hitbox_mesh, hitbox_matrix));
             }
         }
+        #[cfg(feature = "count-allocations")]
+        let _exempt = crate::alloc_counter::Exempt::begin();
         world.update();
     }
 }
 
-pub struct RemoveRaycastColliderSystem;
+/// Removes the colliders of removed entities from the PhysicsWorld and RaycastWorld
+pub struct RemoveColliderSystem;
 
-impl<'a> System<'a> for RemoveRaycastColliderSystem {
+impl<'a> System<'a> for RemoveColliderSystem {
     type SystemData = (
         Read<'a, ToBeRemoved>,
+        WriteExpect<'a, PhysicsWorld>,
         WriteExpect<'a, RaycastWorld>,
-        WriteExpect<'a, MeshManager>,
-        ReadExpect<'a, HitboxMeshes>,
         WriteStorage<'a, Collider>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (to_be_removed, mut raycast_world, mut mesh_manager, hitbox_meshes, mut colliders) =
-            data;
+        let (to_be_removed, mut physics_world, mut raycast_world, mut colliders) = data;
 
         for (collider, _) in (&mut colliders, to_be_removed.bitset()).join() {
+            if let Some(id) = collider.physics_id {
+                physics_world.0.remove(&[id]);
+                collider.physics_id = None;
+            }
+
             if let Some(id) = collider.raycast_id {
                 raycast_world.0.remove(&[id]);
                 collider.raycast_id = None;
             }
-
-            if let Some(id) = collider.model_id {
-                mesh_manager.remove_model(collider.hitbox.to_hitbox_mesh(&hitbox_meshes), id);
-                collider.model_id = None;
-            }
         }
     }
 }
 
-pub struct HitboxMeshes {
-    pub unit_cube: MeshId,
-    pub unit_sphere: MeshId,
+/// Whether the hitboxes are drawn, which shows where they don't match the models. It is
+/// toggled with F6 or the render_hitboxes command.
+pub struct HitboxOverlay {
+    pub visible: bool,
 }
 
-impl HitboxMeshes {
-    pub fn load(device: &wgpu::Device, mesh_manager: &mut MeshManager) -> Self {
-        let mut register_mesh = |mesh: &Mesh| {
-            let id = mesh_manager.add(device, mesh);
-            mesh_manager.set_mesh_visisble(id, crate::RENDER_HITBOXES);
-            id
-        };
-        let unit_cube = Mesh::rectangular_prism(1.0, 1.0, 1.0, Point3::new(1.0, 0.0, 0.0));
+impl HitboxOverlay {
+    pub const TOGGLE_KEY: VirtualKeyCode = VirtualKeyCode::F6;
+    /// The hovered and selected entities are drawn in white with thicker lines, so they
+    /// stand out among the other hitboxes
+    const HIGHLIGHT_WIDTH: f32 = 0.04;
 
-        Self {
-            unit_cube: register_mesh(&unit_cube),
-            unit_sphere: register_mesh(&create_sphere_mesh()),
+    fn color(group: usize) -> Vector3<f32> {
+        match group {
+            Collider::ASTEROID => Vector3::new(1.0, 0.2, 0.2),
+            Collider::SHIP => Vector3::new(0.2, 1.0, 0.2),
+            Collider::MISSLE => Vector3::new(1.0, 1.0, 0.2),
+            _ => Vector3::new(0.3, 0.6, 1.0),
         }
     }
 }
 
-fn create_sphere_mesh() -> Mesh {
-    let color = Point3::new(1.0, 0.0, 0.0);
-    let mut vertices = Vec::new();
-    let mut indices = Vec::new();
-    let slices = 10;
-    let chunks = 10;
-
-    for slice in 0..slices + 1 {
-        let slice_index = (chunks + 1) * slice;
-        let next_slice_index = (chunks + 1) * (slice + 1);
-        let (z_sin, z_cos) =
-            ((crate::PI / slices as f32 * slice as f32) - (crate::PI / 2.0)).sin_cos();
-
-        for chunk in 0..chunks + 1 {
-            let (xy_sin, xy_cos) = (crate::PI * 2.0 / chunks as f32 * chunk as f32).sin_cos();
-            let pos = Point3::new(z_cos * xy_cos, z_cos * xy_sin, z_sin);
-            vertices.push(Vertex {
-                normal: Point3::from_vec(pos.to_vec().normalize()),
-                color,
-                pos,
-            });
+/// Adds the wireframes of the colliders to the LineBatch while the HitboxOverlay is visible
+pub struct HitboxOverlaySystem;
+
+impl<'a> System<'a> for HitboxOverlaySystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, HitboxOverlay>,
+        ReadExpect<'a, InputManager>,
+        ReadExpect<'a, Selection>,
+        Write<'a, LineBatch>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Collider>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, overlay, input, selection, mut line_batch, transforms, colliders) = data;
+        if !overlay.visible {
+            return;
+        }
+
+        for (entity, transform, collider) in (&entities, &transforms, &colliders).join() {
+            let highlighted = input.hovered == Some(entity) || selection.is_selected(entity);
+            for (start, end) in collider.hitbox.wireframe(transform) {
+                let line = if highlighted {
+                    Line::new(start, end, Vector3::new(1.0, 1.0, 1.0))
+                        .with_width(HitboxOverlay::HIGHLIGHT_WIDTH)
+                } else {
+                    Line::new(start, end, HitboxOverlay::color(collider.group))
+                };
+                line_batch.add(line.as_overlay());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{headless::HeadlessEcs, objects::Health};
 
-            indices.push(slice_index + chunk);
-            indices.push(slice_index + chunk + 1);
-            indices.push(next_slice_index + chunk);
-            indices.push(next_slice_index + chunk);
-            indices.push(slice_index + chunk + 1);
-            indices.push(next_slice_index + chunk + 1);
+    const WHITELISTS: [&[usize]; 3] = [&[], &[Collider::ASTEROID], &[Collider::SHIP]];
+
+    /// Rays across the ship and the asteroids around it, from every side and from above
+    fn rays() -> Vec<(Vector3<f32>, Vector3<f32>)> {
+        let mut rays = Vec::new();
+        for i in 0..64 {
+            let angle = i as f32 / 64.0 * std::f32::consts::PI * 2.0;
+            let side = Vector3::new(angle.cos(), angle.sin(), 0.0) * 40.0;
+            for z in [0.5, 2.0].iter().copied() {
+                let height = Vector3::new(0.0, 0.0, z);
+                rays.push((side + height, -side + height));
+            }
+            let below = Vector3::new(angle.cos() * 4.0, angle.sin() * 4.0, 0.0);
+            rays.push((below + Vector3::new(0.0, 0.0, 20.0), below));
         }
+        rays
+    }
+
+    /// The ship's blocks that have lost health, and where they are
+    fn damaged_blocks(headless: &HeadlessEcs) -> Vec<Point3<i16>> {
+        let healths = headless.ecs.world.read_storage::<Health>();
+        headless
+            .with_ship(|ship| {
+                ship.tiles()
+                    .filter(|(_, tile)| {
+                        matches!(tile.block().and_then(|block| healths.get(block)),
+                            Some(health) if health.health() < health.max_health())
+                    })
+                    .map(|(pos, _)| pos)
+                    .collect()
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn a_fast_asteroid_does_not_tunnel_through_the_ship() {
+        let mut headless = HeadlessEcs::with_seed(1);
+        headless.pause_asteroid_field();
+        // The asteroid is only swept once its collider was added to the physics world
+        let asteroid =
+            headless.spawn_asteroid(Vector3::new(20.0, 4.0, 0.5), Vector3::zero(), 100.0);
+        headless.step(2);
+        assert!(damaged_blocks(&headless).is_empty());
+
+        // In one update it would move from one side of the ship to the other, over the
+        // hull's wall at x = 7
+        let step = 30.0;
+        headless
+            .ecs
+            .world
+            .write_storage::<RigidBody>()
+            .get_mut(asteroid)
+            .unwrap()
+            .velocity = Vector3::new(-step * Time::DEFAULT_RATE as f32, 0.0, 0.0);
+        headless.step(1);
+
+        let world = &headless.ecs.world;
+        assert!(!world.is_alive(asteroid), "The asteroid did not crash");
+        let damaged = damaged_blocks(&headless);
+        assert!(!damaged.is_empty());
+        assert!(
+            damaged.iter().all(|pos| pos.x == 7),
+            "The asteroid hit {:?} past the wall",
+            damaged
+        );
+    }
+
+    #[test]
+    fn a_fast_missile_hits_a_thin_wall() {
+        let mut headless = HeadlessEcs::with_seed(1);
+        headless.pause_asteroid_field();
+        // Far from the ship, and thinner than the missile moves in an update
+        let wall_x = 30.0;
+        let (wall, missile) = {
+            let world = &mut headless.ecs.world;
+            let wall = world
+                .create_entity()
+                .with(Transform::from_position(wall_x, 30.0, 0.5))
+                .with(Collider::new(
+                    Hitbox::with_shape(ColliderShape::Cuboid(Vector3::new(0.05, 2.0, 2.0))),
+                    Collider::RAIDER,
+                    &[Collider::MISSLE],
+                ))
+                .build();
+            let missile = world
+                .create_entity()
+                .with(Transform::from_position(wall_x - 5.0, 30.0, 0.5))
+                .with(RigidBody {
+                    velocity: Vector3::zero(),
+                })
+                .with(Collider::new(
+                    Hitbox::with_shape(ColliderShape::Sphere(0.2)),
+                    Collider::MISSLE,
+                    &[Collider::RAIDER],
+                ))
+                .build();
+            (wall, missile)
+        };
+        // The missile is only swept once its collider was added to the physics world
+        headless.step(1);
+
+        // In one update it would move from 5 in front of the wall to 5 behind it
+        let step = 10.0;
+        headless
+            .ecs
+            .world
+            .write_storage::<RigidBody>()
+            .get_mut(missile)
+            .unwrap()
+            .velocity = Vector3::new(step * Time::DEFAULT_RATE as f32, 0.0, 0.0);
+        headless.step(1);
+
+        let world = &headless.ecs.world;
+        let transforms = world.write_storage::<Transform>();
+        let missile_x = transforms.get(missile).unwrap().position.x;
+        assert!(missile_x < wall_x, "The missile tunneled to {}", missile_x);
+
+        let physics = world.fetch::<PhysicsWorld>();
+        let colliders = world.read_storage::<Collider>();
+        let handle = |entity| colliders.get(entity).unwrap().physics_id.unwrap();
+        let handles = (handle(missile), handle(wall));
+        assert!(
+            physics.0.contact_pair(handles.0, handles.1, true).is_some(),
+            "The missile and the wall are not touching"
+        );
+
+        let impact = Impact::find(&physics.0, handles, wall, &transforms).unwrap();
+        // The missile hit the side of the wall facing where it came from
+        let face = wall_x - 0.025;
+        assert!((impact.point.x - face).abs() < 0.05, "{:?}", impact);
+        assert!((impact.point.y - 30.0).abs() < 0.01, "{:?}", impact);
+        assert!((impact.point.z - 0.5).abs() < 0.01, "{:?}", impact);
+        assert!(
+            (impact.normal - Vector3::new(-1.0, 0.0, 0.0)).magnitude() < 0.01,
+            "{:?}",
+            impact
+        );
+        assert!(impact.effect_point().x < face);
     }
 
-    Mesh {
-        name: "Unit Sphere".to_string(),
-        vertices,
-        indices,
+    #[test]
+    fn batched_raycasts_hit_what_immediate_ones_do() {
+        let mut headless = HeadlessEcs::with_seed(2);
+        headless.step(20 * Time::DEFAULT_RATE);
+        let raycast_world = headless.ecs.get_resource::<RaycastWorld>();
+        let excluded = headless
+            .with_ship(|ship| ship.tiles().find_map(|(_, tile)| tile.block()))
+            .flatten();
+
+        let mut batch = RaycastBatch::default();
+        let mut requests = Vec::new();
+        for (near, far) in rays() {
+            for whitelist in WHITELISTS.iter().copied() {
+                for exclude in [None, excluded].iter().copied() {
+                    let handle = batch.request(whitelist, near, far, exclude);
+                    requests.push((handle, whitelist, near, far, exclude));
+                }
+            }
+        }
+        // The hits are only read once the batch has run
+        assert!(requests
+            .iter()
+            .all(|(handle, ..)| batch.hit(*handle).is_none()));
+        batch.run(&raycast_world);
+
+        let mut hits = 0;
+        for (handle, whitelist, near, far, exclude) in &requests {
+            let batched = batch.hit(*handle);
+            let immediate = raycast_world.raycast(whitelist, *near, *far, *exclude, None);
+            match (batched, immediate) {
+                (None, None) => {}
+                (Some(batched), Some(immediate)) => {
+                    assert_eq!(batched.entity, immediate.entity);
+                    assert_eq!(batched.distance, immediate.distance);
+                    assert_eq!(batched.point, immediate.point);
+                    hits += 1;
+                }
+                (batched, immediate) => panic!(
+                    "The batched hit {:?} isn't the immediate hit {:?}",
+                    batched, immediate
+                ),
+            }
+        }
+        assert!(hits > requests.len() / 4, "Only {} rays hit", hits);
+
+        // The handles are stale once the next batch runs
+        batch.run(&raycast_world);
+        assert!(requests
+            .iter()
+            .all(|(handle, ..)| batch.hit(*handle).is_none()));
     }
 }
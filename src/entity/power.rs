@@ -0,0 +1,350 @@
+use super::{integrity::Derelict, BlockEntity, GameModule, Model, Ship};
+use crate::block::Blocks;
+use crate::i18n::Strings;
+use crate::profiler::AddProfiled;
+use specs::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+/// The ship's electricity. The core and the generators produce power, and the gadgets and
+/// coolers consume it. While a ship needs more power than it produces, its consumers are
+/// browned out one by one, starting with the lowest priority.
+pub struct PowerModule;
+
+impl GameModule for PowerModule {
+    fn register(&self, world: &mut World) {
+        world.register::<Derelict>();
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(PowerSystem::default(), "power_system", &[]);
+    }
+}
+
+/// How a kind of block produces or consumes power. It is part of the block's registration.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BlockPower {
+    /// The power the block produces each second
+    pub production: f32,
+    /// The power the block needs each second to work
+    pub consumption: f32,
+    /// Consumers with a lower priority are browned out first
+    pub priority: i32,
+}
+
+/// The power of a ship in the last update
+#[derive(Clone, Debug, Default)]
+pub struct ShipPower {
+    supply: f32,
+    demand: f32,
+    /// The fraction of its power that each browned out consumer got. Consumers that got all
+    /// of their power are left out.
+    browned_out: HashMap<Entity, f32>,
+}
+
+impl ShipPower {
+    /// The power the core produces each second, which runs the starting ship
+    pub const CORE_PRODUCTION: f32 = 10.0;
+
+    pub fn supply(&self) -> f32 {
+        self.supply
+    }
+
+    pub fn demand(&self) -> f32 {
+        self.demand
+    }
+
+    /// The power that was produced but not used. Batteries would store it, and add what
+    /// they stored to the supply during a brownout.
+    pub fn surplus(&self) -> f32 {
+        (self.supply - self.demand).max(0.0)
+    }
+
+    /// The fraction of its power that the consumer gets, from 0 to 1
+    pub fn fraction(&self, entity: Entity) -> f32 {
+        self.browned_out.get(&entity).copied().unwrap_or(1.0)
+    }
+
+    pub fn is_browned_out(&self, entity: Entity) -> bool {
+        self.browned_out.contains_key(&entity)
+    }
+
+    /// How many consumers are browned out
+    pub fn browned_out_count(&self) -> usize {
+        self.browned_out.len()
+    }
+}
+
+/// Everything that a power update depends on, so brownouts can be worked out without a world
+#[derive(Clone, Debug, Default)]
+pub struct PowerInputs {
+    /// The power each producer makes each second
+    pub producers: Vec<f32>,
+    /// Each consumer, with the power it needs each second and its priority
+    pub consumers: Vec<(Entity, f32, i32)>,
+}
+
+/// Hands the supply out to the consumers, the highest priority first (and in their order
+/// for the same priority). The first consumer that doesn't get all of its power gets what
+/// is left, and the ones after it get nothing. The power is updated in place.
+pub fn distribute(inputs: &PowerInputs, power: &mut ShipPower) {
+    power.supply = inputs.producers.iter().sum();
+    power.demand = inputs
+        .consumers
+        .iter()
+        .map(|(_, consumption, _)| consumption)
+        .sum();
+    power.browned_out.clear();
+
+    // Each priority is handed out in turn instead of sorting the consumers, since there
+    // are only a few of them and nothing has to be allocated
+    let below = |priority: Option<i32>| {
+        inputs
+            .consumers
+            .iter()
+            .map(|(_, _, other)| *other)
+            .filter(|other| !matches!(priority, Some(priority) if *other >= priority))
+            .max()
+    };
+    let mut left = power.supply;
+    let mut priority = below(None);
+    while let Some(current) = priority {
+        for (entity, consumption, _) in inputs
+            .consumers
+            .iter()
+            .filter(|(_, _, other)| *other == current)
+        {
+            if *consumption <= left {
+                left -= consumption;
+            } else {
+                power
+                    .browned_out
+                    .insert(*entity, left.max(0.0) / consumption);
+                left = 0.0;
+            }
+        }
+        priority = below(priority);
+    }
+}
+
+/// The browned out blocks and gadgets of every ship, which stop until there is enough power
+pub fn browned_out_blocks(ships: &ReadStorage<Ship>) -> HashSet<Entity> {
+    ships
+        .join()
+        .flat_map(|ship| ship.power().browned_out.keys().copied())
+        .collect()
+}
+
+/// Ex. "Power 3.0/s (browned out, 40%)", or None if the block doesn't consume power
+pub fn describe(entity: Entity, world: &World) -> Option<String> {
+    let ships = world.read_component::<Ship>();
+    let ship = ships.join().find(|ship| {
+        ship.blocks()
+            .chain(ship.gadgets())
+            .any(|other| other == entity)
+    })?;
+    let blocks = world.fetch::<Blocks>();
+    let block_entities = world.read_component::<BlockEntity>();
+    let models = world.read_component::<Model>();
+    let (_, _, block) = ship
+        .block_kinds(&blocks, &block_entities, &models)
+        .find(|(_, other, _)| *other == entity)?;
+    let consumption = blocks.get_block(block).power.consumption;
+    if consumption <= 0.0 {
+        return None;
+    }
+
+    let strings = world.fetch::<Strings>();
+    let power = ship.power();
+    let text = if power.is_browned_out(entity) {
+        strings.format(
+            "power.browned_out",
+            &[
+                ("power", &format!("{:.1}", consumption)),
+                ("percent", &format!("{:.0}", power.fraction(entity) * 100.0)),
+            ],
+        )
+    } else {
+        strings.format(
+            "power.consumer",
+            &[("power", &format!("{:.1}", consumption))],
+        )
+    };
+    Some(text)
+}
+
+/// Ex. "Generator - 8.0 power/s - Ship: 9.0 / 18.0 power/s used (9.0 spare)"
+pub fn generator_tooltip(entity: Entity, world: &World) -> String {
+    let ships = world.read_component::<Ship>();
+    let block_entities = world.read_component::<BlockEntity>();
+    let blocks = world.fetch::<Blocks>();
+    let (ship, block) = match block_entities.get(entity).and_then(|block_entity| {
+        let ship = ships
+            .join()
+            .find(|ship| ship.has_block(block_entity.root(), entity))?;
+        Some((ship, block_entity.block_id()))
+    }) {
+        Some(found) => found,
+        None => return String::new(),
+    };
+    let power = ship.power();
+    world.fetch::<Strings>().format(
+        "generator.tooltip",
+        &[
+            (
+                "production",
+                &format!("{:.1}", blocks.get_block(block).power.production),
+            ),
+            ("demand", &format!("{:.1}", power.demand())),
+            ("supply", &format!("{:.1}", power.supply())),
+            ("surplus", &format!("{:.1}", power.surplus())),
+        ],
+    )
+}
+
+/// Adds up the power of every ship. The gadgets check the result in the next update, like
+/// they check the heat.
+#[derive(Default)]
+struct PowerSystem {
+    /// Reused for every ship, so the inputs are not allocated every update
+    inputs: PowerInputs,
+}
+
+impl<'a> System<'a> for PowerSystem {
+    type SystemData = (
+        ReadExpect<'a, Blocks>,
+        WriteStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Model>,
+        ReadStorage<'a, Derelict>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (blocks, mut ships, block_entities, models, derelicts) = data;
+
+        for ship in (&mut ships).join() {
+            let inputs = &mut self.inputs;
+            inputs.producers.clear();
+            inputs.consumers.clear();
+            if ship.core().is_some() {
+                inputs.producers.push(ShipPower::CORE_PRODUCTION);
+            }
+            // Blocks that were cut off from the core can't be powered, or power the ship
+            for (_, entity, block) in ship.block_kinds(&blocks, &block_entities, &models) {
+                if derelicts.contains(entity) {
+                    continue;
+                }
+                let power = blocks.get_block(block).power;
+                if power.production > 0.0 {
+                    inputs.producers.push(power.production);
+                }
+                if power.consumption > 0.0 {
+                    inputs
+                        .consumers
+                        .push((entity, power.consumption, power.priority));
+                }
+            }
+            // The tiles are in no particular order, so the same consumers are browned out
+            // each update
+            inputs
+                .consumers
+                .sort_unstable_by_key(|(entity, _, _)| entity.id());
+            distribute(inputs, ship.power_mut());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::headless::{entities, load_blocks};
+
+    fn powered(supply: f32, consumers: &[(Entity, f32, i32)]) -> ShipPower {
+        let inputs = PowerInputs {
+            producers: vec![supply],
+            consumers: consumers.to_vec(),
+        };
+        let mut power = ShipPower::default();
+        distribute(&inputs, &mut power);
+        power
+    }
+
+    #[test]
+    fn brownouts_start_with_the_lowest_priority() {
+        let e = entities(5);
+        // Listed out of order, with the priorities the blocks are registered with
+        let (laser, other_laser, miner, shield, cooler) = (e[0], e[1], e[2], e[3], e[4]);
+        let consumers = [
+            (laser, 3.0, 0),
+            (cooler, 1.0, 4),
+            (miner, 2.0, 2),
+            (other_laser, 3.0, 0),
+            (shield, 4.0, 3),
+        ];
+
+        let power = powered(13.0, &consumers);
+        assert_eq!(power.demand(), 13.0);
+        assert_eq!(power.browned_out_count(), 0);
+        assert_eq!(power.surplus(), 0.0);
+
+        // The cooler, shield and miner are powered first, which leaves 1 for the lasers.
+        // The laser listed first gets what is left.
+        let power = powered(8.0, &consumers);
+        assert_eq!(power.supply(), 8.0);
+        assert_eq!(power.browned_out_count(), 2);
+        for consumer in [cooler, shield, miner].iter() {
+            assert_eq!(power.fraction(*consumer), 1.0);
+        }
+        assert!((power.fraction(laser) - 1.0 / 3.0).abs() < 1e-6);
+        assert!(power.is_browned_out(other_laser));
+        assert_eq!(power.fraction(other_laser), 0.0);
+
+        // Everything below the first consumer that is short gets nothing
+        let power = powered(6.0, &consumers);
+        assert_eq!(power.fraction(shield), 1.0);
+        assert_eq!(power.fraction(miner), 0.5);
+        assert_eq!(power.fraction(laser), 0.0);
+        assert_eq!(power.fraction(other_laser), 0.0);
+
+        let power = powered(0.0, &consumers);
+        assert_eq!(power.browned_out_count(), consumers.len());
+        assert_eq!(power.fraction(cooler), 0.0);
+    }
+
+    #[test]
+    fn brownouts_are_cleared_once_there_is_power() {
+        let e = entities(2);
+        let mut inputs = PowerInputs {
+            producers: vec![ShipPower::CORE_PRODUCTION],
+            consumers: vec![(e[0], 6.0, 0), (e[1], 6.0, 1)],
+        };
+        let mut power = ShipPower::default();
+        distribute(&inputs, &mut power);
+        assert!(power.is_browned_out(e[0]));
+        assert!(!power.is_browned_out(e[1]));
+
+        // A generator is built
+        inputs.producers.push(8.0);
+        distribute(&inputs, &mut power);
+        assert_eq!(power.browned_out_count(), 0);
+        assert_eq!(power.surplus(), 6.0);
+    }
+
+    #[test]
+    fn lasers_brown_out_first_and_coolers_last() {
+        let blocks = load_blocks();
+        let priority = |block| blocks.get_block(block).power.priority;
+        for block in blocks.iter().filter(|block| block.power.consumption > 0.0) {
+            assert!(
+                priority(blocks.laser) <= block.power.priority,
+                "{}",
+                block.type_name
+            );
+            assert!(
+                priority(blocks.cooler) >= block.power.priority,
+                "{}",
+                block.type_name
+            );
+        }
+        assert!(priority(blocks.laser) < priority(blocks.cooler));
+    }
+}
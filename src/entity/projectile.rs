@@ -0,0 +1,149 @@
+//! Unguided projectiles (ex. the cannon's slugs). They fly in a slight arc and are spent on
+//! the first asteroid they hit (see PhysicsSystem), so moving targets have to be led.
+
+use super::{
+    faction::Faction,
+    gameplay::{AsteroidField, GameLog},
+    physics::{Collider, ColliderShape, Hitbox, RigidBody},
+    GameModule, Model, ObjectMeshes, ParticleParams, Time, ToBeRemoved, Transform,
+};
+use crate::i18n::Strings;
+use crate::item::{self, GameItem, Inventory};
+use crate::profiler::AddProfiled;
+use cgmath::{prelude::*, Vector3};
+use specs::{prelude::*, world::LazyBuilder, Component};
+
+pub struct ProjectileModule;
+
+impl GameModule for ProjectileModule {
+    fn register(&self, world: &mut World) {
+        world.register::<Projectile>();
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(ProjectileSystem, "projectile_system", &[]);
+    }
+}
+
+/// How a type of projectile flies, and how hard it hits
+#[derive(Clone, Copy, Debug)]
+pub struct ProjectileKind {
+    pub damage: u32,
+    /// The speed that the projectile leaves the gadget at
+    pub muzzle_velocity: f32,
+    /// How fast the projectile drops. There is no gravity in space, but a little makes the
+    /// shots arc so they feel heavy.
+    pub gravity: f32,
+}
+
+impl ProjectileKind {
+    pub const SLUG: ProjectileKind = ProjectileKind {
+        damage: 150,
+        muzzle_velocity: 26.0,
+        gravity: 1.5,
+    };
+}
+
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct Projectile {
+    pub kind: ProjectileKind,
+}
+
+impl Projectile {
+    pub const IMPACT_PARTICLES: ParticleParams = ParticleParams {
+        color: Vector3::new(1.0, 0.8, 0.5),
+        speed: 3.0,
+        lifetime: 0.3,
+        size: 0.1,
+    };
+    /// How far projectiles fly when there is no asteroid field to bound them
+    const FALLBACK_RANGE: f32 = 100.0;
+    /// The Iron that a batch of slugs is crafted from, and how many slugs it makes
+    const SLUG_RECIPE: [(GameItem, u32); 1] = [(GameItem::Iron, 2)];
+    const SLUGS_PER_BATCH: u32 = 3;
+}
+
+/// Fires a projectile from `pos` in `direction`
+pub fn build_projectile(
+    meshes: &ObjectMeshes,
+    builder: LazyBuilder,
+    kind: ProjectileKind,
+    pos: Vector3<f32>,
+    direction: Vector3<f32>,
+) {
+    builder
+        .with(Transform::from_position(pos.x, pos.y, pos.z))
+        .with(Model::new(meshes.slug))
+        .with(RigidBody {
+            velocity: direction.normalize() * kind.muzzle_velocity,
+        })
+        .with(Collider::new(
+            Hitbox::with_shape(ColliderShape::Sphere(0.1)),
+            Collider::PROJECTILE,
+            &[Collider::ASTEROID],
+        ))
+        .with(Projectile { kind })
+        .with(Faction::Player)
+        .build();
+}
+
+/// Crafts a batch of slugs from Iron, and logs what was crafted (or what it costs)
+pub fn craft_slugs(world: &World) {
+    let strings = world.fetch::<Strings>();
+    let result = world.fetch_mut::<Inventory>().exchange(
+        &Projectile::SLUG_RECIPE,
+        &[(GameItem::Slug, Projectile::SLUGS_PER_BATCH)],
+        &strings,
+    );
+    let message = match result {
+        Ok(()) => strings.format(
+            "log.crafted_slugs",
+            &[
+                ("amount", &Projectile::SLUGS_PER_BATCH),
+                (
+                    "cost",
+                    &item::describe_cost(&Projectile::SLUG_RECIPE, &strings),
+                ),
+            ],
+        ),
+        Err(error) => error,
+    };
+    world.fetch_mut::<GameLog>().add(message);
+}
+
+/// Pulls the projectiles down by their gravity, and removes the ones that missed once they
+/// leave the field. The field is as tall and as deep as it is wide.
+struct ProjectileSystem;
+
+impl<'a> System<'a> for ProjectileSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Time>,
+        Write<'a, ToBeRemoved>,
+        ReadStorage<'a, AsteroidField>,
+        ReadStorage<'a, Projectile>,
+        ReadStorage<'a, Transform>,
+        WriteStorage<'a, RigidBody>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, time, mut to_be_removed, fields, projectiles, transforms, mut bodies) = data;
+        let range = (&fields)
+            .join()
+            .map(|field| field.x_range)
+            .next()
+            .unwrap_or(Projectile::FALLBACK_RANGE);
+
+        for (entity, projectile, transform, body) in
+            (&entities, &projectiles, &transforms, &mut bodies).join()
+        {
+            body.velocity.z -= projectile.kind.gravity * time.delta;
+
+            let position = transform.position;
+            if position.x.abs() > range || position.y.abs() > range || position.z.abs() > range {
+                to_be_removed.add(entity);
+            }
+        }
+    }
+}
@@ -0,0 +1,583 @@
+use super::{
+    ballistics,
+    damage::{DamageEvent, DamageEvents, DamageSource},
+    faction::{self, Faction},
+    gameplay::{AsteroidField, GameLog, GameStats, SpawnLane},
+    objects::{Health, MiningMissle, ObjectMeshes},
+    physics::{Collider, ColliderShape, Hitbox, RigidBody},
+    ship,
+    status::{StatusEffect, StatusEvents, StatusKind},
+    BlockEntity, GameModule, Line, LineBatch, Model, ParticleParams, Particles, Ship, Time,
+    ToBeRemoved, Tooltip, Transform,
+};
+use crate::i18n::Strings;
+use crate::profiler::AddProfiled;
+use cgmath::{prelude::*, Vector3};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use specs::{prelude::*, world::LazyBuilder, Component};
+
+/// The raiders that join the later waves
+pub struct RaiderModule;
+
+impl GameModule for RaiderModule {
+    fn register(&self, world: &mut World) {
+        world.register::<Raider>();
+        world.register::<Health>();
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(RaiderSystem, "raider_system", &[]);
+    }
+
+    fn death_systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(RaiderDestroyedSystem, "raider_destroyed_system", &[]);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RaiderState {
+    /// Flies to the standoff range, leading its movement towards the ship
+    Approach,
+    /// Orbits the ship at the standoff range, and fires at its weakest block
+    Strafe,
+    /// Burns perpendicular to an incoming missile
+    Evade {
+        /// The seconds left until the raider stops evading
+        time_left: f32,
+        direction: Vector3<f32>,
+    },
+    /// Flies back out of the nearest spawn lane once it is badly damaged
+    Retreat,
+}
+
+impl RaiderState {
+    pub fn name_key(&self) -> &'static str {
+        match self {
+            RaiderState::Approach => "raider.approaching",
+            RaiderState::Strafe => "raider.strafing",
+            RaiderState::Evade { .. } => "raider.evading",
+            RaiderState::Retreat => "raider.retreating",
+        }
+    }
+}
+
+/// What a raider reacts to during an update
+struct RaiderStimulus {
+    /// The horizontal distance to the center of the ship
+    distance: f32,
+    health_fraction: f32,
+    /// The velocity of a missile that will hit the raider soon
+    missile_inbound: Option<Vector3<f32>>,
+}
+
+/// An enemy ship that fights the player's ship from a distance. Raiders are deterministic,
+/// since all of their random choices use their own seeded generator.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct Raider {
+    state: RaiderState,
+    /// The seconds left until the raider can fire again
+    fire_cooldown: f32,
+    /// The seconds left until the raider can evade again
+    evade_cooldown: f32,
+    /// 1 when orbiting counterclockwise, -1 when orbiting clockwise
+    orbit_direction: f32,
+    /// The seconds left until the orbit direction changes
+    orbit_time: f32,
+    /// The block being focused. It is kept until it is destroyed or out of range.
+    target_block: Option<Entity>,
+    rng: StdRng,
+}
+
+impl Raider {
+    pub const HEALTH: u32 = 100;
+    /// The first wave that a raider joins
+    pub const FIRST_LEVEL: u16 = 3;
+    /// How many seconds into a wave its raider spawns
+    pub const SPAWN_TIME: f32 = 5.0;
+    const SPEED: f32 = 3.0;
+    /// The height that raiders fly at, above the highest deck
+    const HEIGHT: f32 = Ship::TOP + 2.0;
+    const STANDOFF_RANGE: f32 = 9.0;
+    /// How far from the standoff range a raider can be while strafing
+    const STANDOFF_TOLERANCE: f32 = 1.5;
+    const FIRE_RANGE: f32 = 13.0;
+    /// The seconds between shots
+    const FIRE_COOLDOWN: f32 = 1.5;
+    const DAMAGE: u32 = 1;
+    /// The chance that a shot sets the block on fire
+    const IGNITE_CHANCE: f64 = 0.2;
+    /// The chance that a shot disables the gadgets on the block (and the block itself)
+    const EMP_CHANCE: f64 = 0.1;
+    const BURN_TIME: f32 = 5.0;
+    const BURN_DAMAGE_PER_SECOND: f32 = 0.5;
+    const EMP_TIME: f32 = 4.0;
+    /// The range of seconds between changes of the orbit direction
+    const ORBIT_TIME: std::ops::Range<f32> = 2.0..5.0;
+    /// How soon (in seconds) a missile needs to hit before the raider evades it
+    const MISSILE_WARNING: f32 = 1.0;
+    /// How many seconds the raider evades for, and how long until it can evade again
+    const EVADE_TIME: f32 = 0.65;
+    const EVADE_COOLDOWN: f32 = 5.0;
+    const EVADE_SPEED: f32 = 5.0;
+    /// Raiders retreat once their health drops below this fraction
+    const RETREAT_FRACTION: f32 = 0.3;
+    const BEAM_COLOR: Vector3<f32> = Vector3::new(1.0, 0.2, 0.2);
+    const HIT_PARTICLES: ParticleParams = ParticleParams {
+        color: Vector3::new(1.0, 0.3, 0.2),
+        speed: 2.5,
+        lifetime: 0.4,
+        size: 0.1,
+    };
+
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        Self {
+            state: RaiderState::Approach,
+            fire_cooldown: Raider::FIRE_COOLDOWN,
+            evade_cooldown: 0.0,
+            orbit_direction: if rng.gen() { 1.0 } else { -1.0 },
+            orbit_time: rng.gen_range(Raider::ORBIT_TIME),
+            target_block: None,
+            rng,
+        }
+    }
+
+    pub fn state(&self) -> RaiderState {
+        self.state
+    }
+
+    /// Retreating takes priority over everything else, since a raider that is about to be
+    /// destroyed does not fight back. Evading takes priority over fighting.
+    fn next_state(&mut self, stimulus: &RaiderStimulus, time: &Time) -> RaiderState {
+        let in_range =
+            |distance: f32| (distance - Raider::STANDOFF_RANGE).abs() <= Raider::STANDOFF_TOLERANCE;
+
+        match (self.state, stimulus.missile_inbound) {
+            (RaiderState::Retreat, _) => RaiderState::Retreat,
+            _ if stimulus.health_fraction < Raider::RETREAT_FRACTION => RaiderState::Retreat,
+            (RaiderState::Evade { time_left, .. }, _) if time_left <= Time::SLACK => {
+                if in_range(stimulus.distance) {
+                    RaiderState::Strafe
+                } else {
+                    RaiderState::Approach
+                }
+            }
+            (
+                RaiderState::Evade {
+                    mut time_left,
+                    direction,
+                },
+                _,
+            ) => {
+                time.count_down(&mut time_left);
+                RaiderState::Evade {
+                    time_left,
+                    direction,
+                }
+            }
+            (_, Some(missile_velocity)) if self.evade_cooldown <= Time::SLACK => {
+                self.evade_cooldown = Raider::EVADE_COOLDOWN;
+                let side = if self.rng.gen() { 1.0 } else { -1.0 };
+                let perpendicular = Vector3::new(-missile_velocity.y, missile_velocity.x, 0.0);
+                RaiderState::Evade {
+                    time_left: Raider::EVADE_TIME,
+                    direction: perpendicular.normalize() * side,
+                }
+            }
+            (RaiderState::Approach, _) if in_range(stimulus.distance) => RaiderState::Strafe,
+            (RaiderState::Strafe, _)
+                if stimulus.distance
+                    > Raider::STANDOFF_RANGE + Raider::STANDOFF_TOLERANCE * 2.0 =>
+            {
+                RaiderState::Approach
+            }
+            (state, _) => state,
+        }
+    }
+
+    pub fn tooltip(entity: Entity, world: &World) -> String {
+        let raiders = world.read_component::<Raider>();
+        let healths = world.read_component::<Health>();
+        match (raiders.get(entity), healths.get(entity)) {
+            (Some(raider), Some(health)) => {
+                let strings = world.fetch::<Strings>();
+                strings.format(
+                    "raider.tooltip",
+                    &[
+                        ("state", &strings.get(raider.state().name_key())),
+                        ("health", &health.health()),
+                        ("max", &health.max_health()),
+                    ],
+                )
+            }
+            _ => String::new(),
+        }
+    }
+}
+
+pub fn build_raider(meshes: &ObjectMeshes, builder: LazyBuilder, pos: Vector3<f32>, seed: u64) {
+    builder
+        .with(Transform::from_position(pos.x, pos.y, Raider::HEIGHT))
+        .with(Model::new(meshes.raider))
+        .with(RigidBody {
+            velocity: Vector3::zero(),
+        })
+        .with(Collider::new(
+            Hitbox::with_shape(ColliderShape::Sphere(0.7)),
+            Collider::RAIDER,
+            &[Collider::MISSLE],
+        ))
+        .with(Health::new(Raider::HEALTH))
+        .with(Tooltip(Raider::tooltip))
+        .with(Raider::new(seed))
+        .with(Faction::Hostile)
+        .build();
+}
+
+struct RaiderSystem;
+
+impl<'a> System<'a> for RaiderSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Time>,
+        Write<'a, ToBeRemoved>,
+        Write<'a, LineBatch>,
+        WriteExpect<'a, Particles>,
+        WriteExpect<'a, DamageEvents>,
+        WriteExpect<'a, StatusEvents>,
+        WriteExpect<'a, GameStats>,
+        ReadExpect<'a, Strings>,
+        WriteExpect<'a, GameLog>,
+        WriteStorage<'a, Raider>,
+        ReadStorage<'a, Health>,
+        WriteStorage<'a, RigidBody>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, MiningMissle>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, AsteroidField>,
+        ReadStorage<'a, Faction>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            time,
+            mut to_be_removed,
+            mut line_batch,
+            mut particles,
+            mut damage_events,
+            mut status_events,
+            mut stats,
+            strings,
+            mut log,
+            mut raiders,
+            healths,
+            mut rigid_bodies,
+            transforms,
+            missles,
+            ships,
+            block_entities,
+            fields,
+            factions,
+        ) = data;
+
+        // Raiders go after the mothership, which the game is lost with
+        let ship = match ship::mothership(&ships) {
+            Some(ship) => ship,
+            None => return,
+        };
+        let center = match ship.bounds() {
+            Some(bounds) => (bounds.min + bounds.max) / 2.0,
+            None => return,
+        };
+        let exit_x = fields.join().next().map_or(0.0, |field| {
+            -field.x_range - AsteroidField::FALLBACK_DISTANCE
+        });
+        // The ship does not move yet, but raiders lead it in case it does
+        let ship_velocity = ship
+            .core()
+            .and_then(|core| rigid_bodies.get(core))
+            .map_or(Vector3::zero(), |body| body.velocity);
+
+        for (entity, raider) in (&entities, &mut raiders).join() {
+            let (pos, health_fraction) = match (transforms.get(entity), healths.get(entity)) {
+                (Some(transform), Some(health)) if health.health() > 0 => {
+                    (transform.position, health.fraction())
+                }
+                _ => continue,
+            };
+            let offset = Vector3::new(pos.x - center.x, pos.y - center.y, 0.0);
+            let missile_inbound = (&missles, &transforms, &rigid_bodies)
+                .join()
+                .filter(|(missle, transform, _)| {
+                    missle.target() == entity
+                        && (transform.position - pos).magnitude() / MiningMissle::SPEED
+                            <= Raider::MISSILE_WARNING
+                })
+                .map(|(_, _, body)| body.velocity)
+                .find(|velocity| velocity.x != 0.0 || velocity.y != 0.0);
+            let stimulus = RaiderStimulus {
+                distance: offset.magnitude(),
+                health_fraction,
+                missile_inbound,
+            };
+
+            let previous_state = raider.state;
+            raider.state = raider.next_state(&stimulus, &time);
+            let can_fire = time.count_down(&mut raider.fire_cooldown);
+            time.count_down(&mut raider.evade_cooldown);
+            if raider.state == RaiderState::Retreat && previous_state != RaiderState::Retreat {
+                log.add(strings.get("log.raider_retreating").to_string());
+            }
+
+            let mut velocity = match raider.state {
+                RaiderState::Approach => {
+                    let direction = if offset.magnitude2() > 0.0 {
+                        offset.normalize()
+                    } else {
+                        Vector3::unit_x()
+                    };
+                    let standoff = Vector3::new(center.x, center.y, Raider::HEIGHT)
+                        + direction * Raider::STANDOFF_RANGE;
+                    let aim =
+                        ballistics::intercept_time(pos, standoff, ship_velocity, Raider::SPEED)
+                            .map_or(standoff, |time| standoff + ship_velocity * time);
+                    (aim - pos).normalize_to(Raider::SPEED)
+                }
+                RaiderState::Strafe => {
+                    if time.count_down(&mut raider.orbit_time) {
+                        raider.orbit_direction = -raider.orbit_direction;
+                        raider.orbit_time = raider.rng.gen_range(Raider::ORBIT_TIME);
+                    }
+
+                    let radial = offset.normalize();
+                    let tangent = Vector3::new(-radial.y, radial.x, 0.0) * raider.orbit_direction;
+                    let correction = Raider::STANDOFF_RANGE - stimulus.distance;
+                    tangent * Raider::SPEED + radial * correction
+                }
+                RaiderState::Evade { direction, .. } => direction * Raider::EVADE_SPEED,
+                RaiderState::Retreat => {
+                    let exit = SpawnLane::ALL
+                        .iter()
+                        .map(|lane| lane.center(exit_x))
+                        .min_by(|a, b| {
+                            let a = (a - pos).magnitude2();
+                            let b = (b - pos).magnitude2();
+                            a.partial_cmp(&b).unwrap()
+                        })
+                        .unwrap();
+                    let exit = Vector3::new(exit.x, exit.y, Raider::HEIGHT);
+                    if pos.x <= exit_x + 0.5 {
+                        to_be_removed.add(entity);
+                        stats.raiders_driven_off += 1;
+                        log.add(strings.get("log.raider_driven_off").to_string());
+                    }
+                    (exit - pos).normalize_to(Raider::SPEED)
+                }
+            };
+            velocity.z = Raider::HEIGHT - pos.z;
+            if let Some(body) = rigid_bodies.get_mut(entity) {
+                body.velocity = velocity;
+            }
+
+            if raider.state != RaiderState::Strafe || !can_fire {
+                continue;
+            }
+
+            // Focus on one block until it is destroyed, so the damage is not spread out
+            let faction = faction::faction_of(&factions, entity);
+            let reachable = |block: Entity| {
+                faction.can_damage(faction::faction_of(&factions, block))
+                    && matches!(
+                        (healths.get(block), transforms.get(block)),
+                        (Some(health), Some(transform))
+                            if health.health() > 0
+                                && (transform.position - pos).magnitude() <= Raider::FIRE_RANGE
+                    )
+            };
+            if !matches!(raider.target_block, Some(block) if reachable(block)) {
+                raider.target_block = ship
+                    .blocks()
+                    .filter(|block| reachable(*block))
+                    .min_by_key(|block| (healths.get(*block).unwrap().health(), block.id()));
+            }
+
+            if let Some(block) = raider.target_block {
+                let block_pos = transforms.get(block).unwrap().position;
+                damage_events.push(DamageEvent::new(
+                    block,
+                    Raider::DAMAGE,
+                    DamageSource::Laser,
+                    block_pos,
+                ));
+                if raider.rng.gen_bool(Raider::IGNITE_CHANCE) {
+                    status_events.push(
+                        block,
+                        StatusEffect::new(
+                            StatusKind::Burning,
+                            Raider::BURN_TIME,
+                            Raider::BURN_DAMAGE_PER_SECOND,
+                        ),
+                    );
+                }
+                if raider.rng.gen_bool(Raider::EMP_CHANCE) {
+                    let emp = StatusEffect::new(StatusKind::Emp, Raider::EMP_TIME, 1.0);
+                    status_events.push(block, emp);
+                    let root = block_entities.get(block).map(BlockEntity::root);
+                    if let Some(gadget) = root.and_then(|root| ship.tile(root)?.gadget()) {
+                        status_events.push(gadget, emp);
+                    }
+                }
+                line_batch.add(
+                    Line::new(pos, block_pos, Raider::BEAM_COLOR)
+                        .with_width(0.08)
+                        .as_overlay(),
+                );
+                particles.emit_burst(block_pos, 12, &Raider::HIT_PARTICLES);
+                raider.fire_cooldown = Raider::FIRE_COOLDOWN;
+            }
+        }
+    }
+}
+
+/// Counts the raiders that were destroyed (raiders that retreated are counted when they leave)
+pub struct RaiderDestroyedSystem;
+
+impl<'a> System<'a> for RaiderDestroyedSystem {
+    type SystemData = (
+        Read<'a, ToBeRemoved>,
+        WriteExpect<'a, GameStats>,
+        ReadExpect<'a, Strings>,
+        WriteExpect<'a, GameLog>,
+        ReadStorage<'a, Raider>,
+        ReadStorage<'a, Health>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (to_be_removed, mut stats, strings, mut log, raiders, healths) = data;
+
+        for (_, _, health) in (to_be_removed.bitset(), &raiders, &healths).join() {
+            if health.health() == 0 {
+                stats.raiders_destroyed += 1;
+                log.add(strings.get("log.raider_destroyed").to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IN_RANGE: f32 = Raider::STANDOFF_RANGE;
+    const FAR: f32 = 30.0;
+
+    fn stimulus(distance: f32, missile_inbound: Option<Vector3<f32>>) -> RaiderStimulus {
+        RaiderStimulus {
+            distance,
+            health_fraction: 1.0,
+            missile_inbound,
+        }
+    }
+
+    /// Updates the raider like the RaiderSystem does
+    fn tick(raider: &mut Raider, stimulus: &RaiderStimulus, time: &Time) {
+        raider.state = raider.next_state(stimulus, time);
+        time.count_down(&mut raider.evade_cooldown);
+    }
+
+    #[test]
+    fn approaches_until_in_range_and_strafes() {
+        let time = Time::new(Time::DEFAULT_RATE);
+        let mut raider = Raider::new(1);
+
+        tick(&mut raider, &stimulus(FAR, None), &time);
+        assert_eq!(raider.state(), RaiderState::Approach);
+
+        tick(&mut raider, &stimulus(IN_RANGE, None), &time);
+        assert_eq!(raider.state(), RaiderState::Strafe);
+        // A little out of range still strafes, so it doesn't flicker at the edge
+        tick(&mut raider, &stimulus(IN_RANGE + 2.5, None), &time);
+        assert_eq!(raider.state(), RaiderState::Strafe);
+
+        tick(&mut raider, &stimulus(FAR, None), &time);
+        assert_eq!(raider.state(), RaiderState::Approach);
+    }
+
+    #[test]
+    fn evades_an_inbound_missile_once_per_cooldown() {
+        let time = Time::new(Time::DEFAULT_RATE);
+        let mut raider = Raider::new(1);
+        let missile = Vector3::new(4.0, 3.0, 0.0);
+
+        tick(&mut raider, &stimulus(IN_RANGE, Some(missile)), &time);
+        let direction = match raider.state() {
+            RaiderState::Evade { direction, .. } => direction,
+            state => panic!("Expected Evade, was {:?}", state),
+        };
+        assert!((direction.magnitude() - 1.0).abs() < 1e-5);
+        assert!(direction.dot(missile).abs() < 1e-5);
+
+        let evade_ticks = (Raider::EVADE_TIME / time.delta).ceil() as usize + 1;
+        for _ in 0..evade_ticks {
+            tick(&mut raider, &stimulus(IN_RANGE, None), &time);
+        }
+        assert_eq!(raider.state(), RaiderState::Strafe);
+
+        // The cooldown has not run out yet
+        tick(&mut raider, &stimulus(IN_RANGE, Some(missile)), &time);
+        assert_eq!(raider.state(), RaiderState::Strafe);
+
+        let cooldown_ticks = (Raider::EVADE_COOLDOWN / time.delta).ceil() as usize;
+        for _ in 0..cooldown_ticks {
+            tick(&mut raider, &stimulus(IN_RANGE, None), &time);
+        }
+        tick(&mut raider, &stimulus(IN_RANGE, Some(missile)), &time);
+        assert!(matches!(raider.state(), RaiderState::Evade { .. }));
+    }
+
+    #[test]
+    fn evading_out_of_range_returns_to_approach() {
+        let time = Time::new(Time::DEFAULT_RATE);
+        let mut raider = Raider::new(2);
+
+        tick(&mut raider, &stimulus(FAR, Some(Vector3::unit_x())), &time);
+        assert!(matches!(raider.state(), RaiderState::Evade { .. }));
+        let evade_ticks = (Raider::EVADE_TIME / time.delta).ceil() as usize + 1;
+        for _ in 0..evade_ticks {
+            tick(&mut raider, &stimulus(FAR, None), &time);
+        }
+        assert_eq!(raider.state(), RaiderState::Approach);
+    }
+
+    #[test]
+    fn retreat_interrupts_evading_and_is_final() {
+        let time = Time::new(Time::DEFAULT_RATE);
+        let mut raider = Raider::new(3);
+
+        tick(
+            &mut raider,
+            &stimulus(IN_RANGE, Some(Vector3::unit_y())),
+            &time,
+        );
+        assert!(matches!(raider.state(), RaiderState::Evade { .. }));
+
+        let damaged = RaiderStimulus {
+            health_fraction: Raider::RETREAT_FRACTION / 2.0,
+            ..stimulus(IN_RANGE, None)
+        };
+        tick(&mut raider, &damaged, &time);
+        assert_eq!(raider.state(), RaiderState::Retreat);
+
+        // Even repaired, with a missile inbound and the cooldown over
+        raider.evade_cooldown = 0.0;
+        tick(
+            &mut raider,
+            &stimulus(IN_RANGE, Some(Vector3::unit_x())),
+            &time,
+        );
+        assert_eq!(raider.state(), RaiderState::Retreat);
+    }
+}
@@ -0,0 +1,260 @@
+use super::{
+    faction::Faction, gameplay::GameLog, objects::ObjectMeshes, status, GameModule, InputManager,
+    KeyAction, KeyBindings, Model, ParticleParams, Particles, RigidBody, Time, ToBeRemoved,
+    Transform,
+};
+use crate::graphics::{Camera, ModelTint, Projection};
+use crate::i18n::Strings;
+use crate::profiler::AddProfiled;
+use cgmath::{prelude::*, Point3, Vector3};
+use specs::{prelude::*, Component};
+use winit::event::VirtualKeyCode;
+
+/// Drone bays, and the scout drones they launch to look around the asteroid field
+pub struct ScoutModule;
+
+impl GameModule for ScoutModule {
+    fn register(&self, world: &mut World) {
+        world.register::<DroneBay>();
+        world.register::<ScoutDrone>();
+        world.register::<RigidBody>();
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(ScoutSystem::default(), "scout_system", &[]);
+    }
+}
+
+/// Launches a scout drone when it is clicked. Clicking it again recalls the drone.
+#[derive(Component, Default)]
+#[storage(HashMapStorage)]
+pub struct DroneBay {
+    drone: Option<Entity>,
+}
+
+/// A drone that is steered with WASD while the camera is attached to it (remote view).
+/// Its signal gets weaker the further it is from its bay, and once the signal is lost it
+/// flies back on its own.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct ScoutDrone {
+    bay: Entity,
+    /// The seconds of flight left. The drone self-destructs once it runs out.
+    pub fuel: f32,
+    /// How strong (0..1) the connection to the bay is
+    pub signal: f32,
+    /// Set once the drone is recalled or loses its signal
+    returning: bool,
+}
+
+impl ScoutDrone {
+    const FUEL: f32 = 45.0;
+    /// Units per second
+    const SPEED: f32 = 6.0;
+    const RETURN_SPEED: f32 = 8.0;
+    /// The signal is lost at this distance from the bay
+    const SIGNAL_RANGE: f32 = 60.0;
+    /// How high above the bay the drone flies
+    const HEIGHT: f32 = 2.0;
+    /// How close to the bay a returning drone needs to be to land
+    const LANDING_DISTANCE: f32 = 0.5;
+    const TINT: Vector3<f32> = Vector3::new(0.4, 0.7, 1.0);
+    const EXPLOSION_PARTICLES: ParticleParams = ParticleParams {
+        color: Vector3::new(1.0, 0.6, 0.2),
+        speed: 2.0,
+        lifetime: 0.5,
+        size: 0.1,
+    };
+
+    fn signal_at(distance: f32) -> f32 {
+        (1.0 - distance / ScoutDrone::SIGNAL_RANGE).max(0.0)
+    }
+}
+
+/// Launches, steers and recalls the scout drones, and attaches the camera to the drone
+/// that is being remote viewed
+#[derive(Default)]
+pub struct ScoutSystem {
+    /// Where the camera was before the remote view started, which it returns to after
+    camera_before: Option<(Point3<f32>, f32)>,
+}
+
+impl ScoutSystem {
+    /// Leaves the remote view, leaving the drone to fly back on its own
+    const EXIT_KEY: VirtualKeyCode = VirtualKeyCode::Back;
+    /// How far behind the drone the camera is
+    const FOLLOW_DISTANCE: f32 = 8.0;
+}
+
+impl<'a> System<'a> for ScoutSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Time>,
+        ReadExpect<'a, ObjectMeshes>,
+        Read<'a, LazyUpdate>,
+        Write<'a, ToBeRemoved>,
+        ReadExpect<'a, KeyBindings>,
+        ReadExpect<'a, Strings>,
+        WriteExpect<'a, InputManager>,
+        WriteExpect<'a, Camera>,
+        WriteExpect<'a, GameLog>,
+        WriteExpect<'a, Particles>,
+        WriteStorage<'a, DroneBay>,
+        WriteStorage<'a, ScoutDrone>,
+        WriteStorage<'a, RigidBody>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, status::StatusEffects>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            time,
+            meshes,
+            lazy_update,
+            mut to_be_removed,
+            bindings,
+            strings,
+            mut input,
+            mut camera,
+            mut log,
+            mut particles,
+            mut bays,
+            mut drones,
+            mut bodies,
+            transforms,
+            statuses,
+        ) = data;
+
+        let clicked = input.clicked_block.filter(|block| bays.contains(*block));
+        if let Some(bay_entity) = clicked {
+            let bay = bays.get_mut(bay_entity).unwrap();
+            match bay.drone.and_then(|drone| drones.get_mut(drone)) {
+                Some(drone) => {
+                    drone.returning = true;
+                    log.add(strings.get("log.scout_recalled").to_string());
+                }
+                None if status::is_emped(&statuses, bay_entity) => {
+                    log.add(strings.get("log.drone_bay_emp").to_string());
+                }
+                // The camera can not follow a drone from the build view
+                None if camera.projection != Projection::Perspective => {
+                    log.add(strings.get("log.scout_build_view").to_string());
+                }
+                None => {
+                    let position = transforms.get(bay_entity).unwrap().position
+                        + Vector3::new(0.0, 0.0, ScoutDrone::HEIGHT);
+                    let drone = lazy_update
+                        .create_entity(&entities)
+                        .with(Model::with_tint(
+                            meshes.drone,
+                            ModelTint::new(ScoutDrone::TINT, ScoutDrone::TINT),
+                        ))
+                        .with(Transform::from_position(position.x, position.y, position.z))
+                        .with(RigidBody {
+                            velocity: Vector3::new(0.0, 0.0, 0.0),
+                        })
+                        .with(Faction::Player)
+                        .with(ScoutDrone {
+                            bay: bay_entity,
+                            fuel: ScoutDrone::FUEL,
+                            signal: 1.0,
+                            returning: false,
+                        })
+                        .build();
+                    bay.drone = Some(drone);
+                    input.remote_view = Some(drone);
+                    if self.camera_before.is_none() {
+                        self.camera_before = Some((camera.position, camera.yaw));
+                    }
+                    log.add(strings.get("log.scout_launched").to_string());
+                }
+            }
+        }
+
+        if input.keys.was_pressed(ScoutSystem::EXIT_KEY) {
+            input.remote_view = None;
+        }
+
+        for (entity, drone, body, transform) in
+            (&entities, &mut drones, &mut bodies, &transforms).join()
+        {
+            let bay_position = match transforms.get(drone.bay) {
+                Some(bay) => bay.position + Vector3::new(0.0, 0.0, ScoutDrone::HEIGHT),
+                // Without a bay, the drone has nowhere to return to, so it self-destructs
+                None => {
+                    drone.fuel = 0.0;
+                    transform.position
+                }
+            };
+            let to_bay = bay_position - transform.position;
+            drone.signal = ScoutDrone::signal_at(to_bay.magnitude());
+
+            if time.count_down(&mut drone.fuel) {
+                to_be_removed.add(entity);
+                particles.emit_burst(transform.position, 16, &ScoutDrone::EXPLOSION_PARTICLES);
+                log.add(strings.get("log.scout_destroyed").to_string());
+                continue;
+            }
+            if drone.signal == 0.0 && !drone.returning {
+                drone.returning = true;
+                log.add(strings.get("log.scout_returning").to_string());
+            }
+
+            let controlled = input.remote_view == Some(entity) && !drone.returning;
+            body.velocity = if controlled {
+                let forward = if bindings.is_down(&input.keys, KeyAction::MoveForward) {
+                    1.0
+                } else if bindings.is_down(&input.keys, KeyAction::MoveBack) {
+                    -1.0
+                } else {
+                    0.0
+                };
+                let side = if bindings.is_down(&input.keys, KeyAction::MoveLeft) {
+                    1.0
+                } else if bindings.is_down(&input.keys, KeyAction::MoveRight) {
+                    -1.0
+                } else {
+                    0.0
+                };
+                let (yaw_sin, yaw_cos) = camera.yaw.sin_cos();
+                let direction = Vector3::new(yaw_cos, yaw_sin, 0.0) * forward
+                    + Vector3::new(-yaw_sin, yaw_cos, 0.0) * side;
+                if direction.magnitude2() > 0.0 {
+                    direction.normalize_to(ScoutDrone::SPEED)
+                } else {
+                    direction
+                }
+            } else if drone.returning {
+                if to_bay.magnitude() <= ScoutDrone::LANDING_DISTANCE {
+                    to_be_removed.add(entity);
+                    continue;
+                }
+                let speed = (to_bay.magnitude() / time.delta).min(ScoutDrone::RETURN_SPEED);
+                to_bay.normalize_to(speed)
+            } else {
+                // A drone that is not being controlled waits where it is
+                Vector3::new(0.0, 0.0, 0.0)
+            };
+
+            if input.remote_view == Some(entity) {
+                // The drone moves during the physics update, so the camera leads it by an update
+                let position = transform.position + body.velocity * time.delta;
+                camera.position =
+                    Point3::from_vec(position) - camera.direction() * ScoutSystem::FOLLOW_DISTANCE;
+            }
+        }
+
+        let viewed_alive = matches!(input.remote_view, Some(drone)
+            if entities.is_alive(drone) && !to_be_removed.bitset().contains(drone.id()));
+        if !viewed_alive {
+            input.remote_view = None;
+        }
+        if input.remote_view.is_none() {
+            if let Some((position, yaw)) = self.camera_before.take() {
+                camera.position = position;
+                camera.yaw = yaw;
+            }
+        }
+    }
+}
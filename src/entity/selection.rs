@@ -0,0 +1,281 @@
+//! Lets the player give orders to many gadgets at once, like the units of a strategy game.
+//! Gadgets are selected by dragging a box around them (or by shift-clicking them), and
+//! Ctrl+1..9 stores the selection in a group that 1..9 selects again. Right-clicking an
+//! asteroid then orders every selected gadget to fire at it.
+
+use super::{
+    faction::{self, Faction},
+    gameplay::GameLog,
+    input::InputSystem,
+    GameModule, InputAction, InputManager, KeyAction, KeyBindings, Transform, WindowSize,
+};
+use crate::block::{Laser, Miner};
+use crate::graphics::Camera;
+use crate::i18n::Strings;
+use cgmath::{InnerSpace, Point2};
+use specs::{prelude::*, Component};
+use winit::event;
+
+pub struct SelectionModule;
+
+impl GameModule for SelectionModule {
+    fn register(&self, world: &mut World) {
+        world.register::<GadgetTarget>();
+        world.register::<Laser>();
+        world.register::<Miner>();
+        world.insert(Selection::default());
+    }
+
+    // The SelectionSystem runs before the module systems (see ECS::new), since it takes the
+    // shift-clicks before the clicked lasers toggle their auto mode
+    fn systems(&self, _builder: &mut DispatcherBuilder) {}
+
+    /// The selection and groups are not saved, so a new run (or retried wave) starts
+    /// without any
+    fn init(&self, world: &mut World) {
+        world.insert(Selection::default());
+    }
+
+    fn on_click(&self, world: &mut World, button: event::MouseButton, state: event::ElementState) {
+        match (button, state) {
+            (event::MouseButton::Left, event::ElementState::Pressed) => {
+                let input = world.read_resource::<InputManager>();
+                // The other actions use the held button (ex. mining)
+                if input.action == InputAction::None {
+                    world.write_resource::<Selection>().drag_start = Some(input.mouse_pos);
+                }
+            }
+            (event::MouseButton::Left, event::ElementState::Released) => {
+                let mouse_pos = world.read_resource::<InputManager>().mouse_pos;
+                let corners = {
+                    let mut selection = world.write_resource::<Selection>();
+                    let corners = selection.drag_box(mouse_pos);
+                    selection.drag_start = None;
+                    corners
+                };
+                if let Some((min, max)) = corners {
+                    select_in_box(world, min, max);
+                }
+            }
+            (event::MouseButton::Right, event::ElementState::Released) => {
+                order_target(world);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_key(&self, world: &mut World, key: event::VirtualKeyCode, state: event::ElementState) {
+        if state != event::ElementState::Pressed {
+            return;
+        }
+        let group = match InputSystem::BLOCK_KEYS
+            .iter()
+            .position(|other| *other == key)
+        {
+            Some(group) => group,
+            None => return,
+        };
+
+        let mut input = world.write_resource::<InputManager>();
+        let mut selection = world.write_resource::<Selection>();
+        if input.keys.is_control_down() {
+            selection.groups[group] = selection.selected.clone();
+            input.keys.take_press(key);
+        } else if !selection.groups[group].is_empty() {
+            // The number keys only pick blocks from the toolbar while their group is empty
+            selection.selected = selection.groups[group].clone();
+            input.keys.take_press(key);
+        }
+    }
+}
+
+/// The selected gadgets, and the groups they can be stored in. Dead gadgets are dropped by
+/// the SelectionSystem.
+#[derive(Default)]
+pub struct Selection {
+    selected: Vec<Entity>,
+    groups: [Vec<Entity>; 9],
+    /// Where the left mouse button was pressed, while a box may be dragged
+    drag_start: Option<Point2<f32>>,
+}
+
+impl Selection {
+    /// How far (in pixels) the mouse needs to move before pressing the button drags a box
+    const DRAG_THRESHOLD: f32 = 6.0;
+
+    pub fn is_selected(&self, entity: Entity) -> bool {
+        self.selected.contains(&entity)
+    }
+
+    /// The lower left and upper right corners of the box that is dragged, once the mouse
+    /// moved far enough from where the button was pressed
+    pub fn drag_box(&self, mouse_pos: Point2<f32>) -> Option<(Point2<f32>, Point2<f32>)> {
+        let start = self.drag_start?;
+        if (mouse_pos - start).magnitude() < Selection::DRAG_THRESHOLD {
+            return None;
+        }
+        Some((
+            Point2::new(start.x.min(mouse_pos.x), start.y.min(mouse_pos.y)),
+            Point2::new(start.x.max(mouse_pos.x), start.y.max(mouse_pos.y)),
+        ))
+    }
+
+    fn toggle(&mut self, entity: Entity) {
+        match self.selected.iter().position(|other| *other == entity) {
+            Some(index) => {
+                self.selected.remove(index);
+            }
+            None => self.selected.push(entity),
+        }
+    }
+}
+
+/// The target that a gadget was ordered to fire at by right-clicking it. The gadget fires at
+/// it instead of the player's target (or its auto target) until it is destroyed.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct GadgetTarget(pub Entity);
+
+/// Whether the entity is one of the player's gadgets that can be given orders
+fn is_selectable(world: &World, entity: Entity) -> bool {
+    let gadget = world.read_component::<Laser>().contains(entity)
+        || world.read_component::<Miner>().contains(entity);
+    gadget && faction::faction_of(&world.read_component(), entity) == Faction::Player
+}
+
+/// Selects the gadgets whose centers are inside the box on screen. Holding shift adds them to
+/// the selection instead.
+fn select_in_box(world: &mut World, min: Point2<f32>, max: Point2<f32>) {
+    let inside: Vec<Entity> = {
+        let camera = world.read_resource::<Camera>();
+        let window_size = world.read_resource::<WindowSize>().as_point();
+        let transforms = world.read_component::<Transform>();
+        (&world.entities(), &transforms)
+            .join()
+            .filter(|(entity, _)| is_selectable(world, *entity))
+            .filter(
+                |(_, transform)| match camera.project(transform.position, window_size) {
+                    Some(pos) => {
+                        pos.x >= min.x && pos.x <= max.x && pos.y >= min.y && pos.y <= max.y
+                    }
+                    None => false,
+                },
+            )
+            .map(|(entity, _)| entity)
+            .collect()
+    };
+
+    let adding = world.read_resource::<InputManager>().keys.is_shift_down();
+    let mut selection = world.write_resource::<Selection>();
+    if !adding {
+        selection.selected.clear();
+    }
+    for entity in inside {
+        if !selection.is_selected(entity) {
+            selection.selected.push(entity);
+        }
+    }
+}
+
+/// Orders the selected gadgets to fire at the asteroid (or raider) under the cursor
+fn order_target(world: &mut World) {
+    let target = match world.read_resource::<InputManager>().hovered {
+        Some(target) => target,
+        None => return,
+    };
+    let selected = world.read_resource::<Selection>().selected.clone();
+    if selected.is_empty() {
+        return;
+    }
+
+    let mut targets = world.write_component::<GadgetTarget>();
+    for entity in &selected {
+        targets.insert(*entity, GadgetTarget(target)).unwrap();
+    }
+    let strings = world.read_resource::<Strings>();
+    world
+        .write_resource::<GameLog>()
+        .add(strings.format("log.selection_target", &[("count", &selected.len())]));
+}
+
+/// Drops the dead gadgets from the selection and the groups, and the orders whose targets
+/// were destroyed. Shift-clicking a gadget selects (or unselects) it instead of clicking it,
+/// and the ToggleAuto key switches the auto mode of every selected laser.
+pub struct SelectionSystem;
+
+impl<'a> System<'a> for SelectionSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteExpect<'a, InputManager>,
+        ReadExpect<'a, KeyBindings>,
+        ReadExpect<'a, Strings>,
+        WriteExpect<'a, GameLog>,
+        WriteExpect<'a, Selection>,
+        WriteStorage<'a, GadgetTarget>,
+        WriteStorage<'a, Laser>,
+        ReadStorage<'a, Miner>,
+        ReadStorage<'a, Faction>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            mut input,
+            bindings,
+            strings,
+            mut log,
+            mut selection,
+            mut targets,
+            mut lasers,
+            miners,
+            factions,
+        ) = data;
+
+        let selection = &mut *selection;
+        selection
+            .selected
+            .retain(|entity| entities.is_alive(*entity));
+        for group in &mut selection.groups {
+            group.retain(|entity| entities.is_alive(*entity));
+        }
+        let finished: Vec<Entity> = (&entities, &targets)
+            .join()
+            .filter(|(_, target)| !entities.is_alive(target.0))
+            .map(|(entity, _)| entity)
+            .collect();
+        for entity in finished {
+            targets.remove(entity);
+        }
+
+        if input.keys.is_shift_down() {
+            let clicked = input.clicked_block.filter(|block| {
+                (lasers.contains(*block) || miners.contains(*block))
+                    && faction::faction_of(&factions, *block) == Faction::Player
+            });
+            if let Some(block) = clicked {
+                selection.toggle(block);
+                input.clicked_block = None;
+            }
+        }
+
+        if bindings.take_press(&mut input.keys, KeyAction::ToggleAuto) {
+            let selected = || {
+                selection
+                    .selected
+                    .iter()
+                    .filter_map(|entity| lasers.get(*entity))
+            };
+            if selected().next().is_none() {
+                return;
+            }
+            // Mixed selections all switch to auto first
+            let auto = !selected().all(Laser::is_auto);
+            for entity in &selection.selected {
+                if let Some(laser) = lasers.get_mut(*entity) {
+                    laser.set_auto(auto);
+                }
+            }
+            log.add(strings.format("log.laser_auto", &[("state", &strings.on_off(auto))]));
+        }
+    }
+}
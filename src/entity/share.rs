@@ -0,0 +1,266 @@
+use super::{blueprint::Blueprint, paint::Paint, ship, BlockEntity, Model, Ship};
+use crate::block::Blocks;
+use crate::floor::Floors;
+use crate::i18n::Strings;
+use cgmath::{Point2, Point3};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use specs::prelude::*;
+use std::io::{Read, Write};
+
+/// A whole ship, which can be shared as a code (see SharedShip::export). The code is the
+/// blueprint of the ship in its saved format, deflated and then base64 encoded.
+#[derive(Clone)]
+pub struct SharedShip {
+    pub blueprint: Blueprint,
+    /// Where the corner of the blueprint was on the ship it was copied from. A new game is
+    /// started with the ship built there, so its core is on Ship::CORE_POS again.
+    pub origin: Point2<i16>,
+}
+
+impl SharedShip {
+    /// Changed whenever codes from older versions can't be read anymore
+    const VERSION: u32 = 1;
+    /// The most text that a code can expand to, so a crafted code can't use up the memory
+    const MAX_TEXT_LEN: u64 = 1 << 20;
+
+    /// Copies every deck of the mothership
+    pub fn capture(world: &World) -> Option<SharedShip> {
+        let ships = world.read_component::<Ship>();
+        let ship = ship::mothership(&ships)?;
+        let (min, max) = ship
+            .tiles()
+            .filter(|(_, tile)| tile.block().is_some() || tile.floor().is_some())
+            .fold(
+                None,
+                |bounds: Option<(Point2<i16>, Point2<i16>)>, (pos, _)| {
+                    Some(match bounds {
+                        Some((min, max)) => (
+                            Point2::new(min.x.min(pos.x), min.y.min(pos.y)),
+                            Point2::new(max.x.max(pos.x), max.y.max(pos.y)),
+                        ),
+                        None => (Point2::new(pos.x, pos.y), Point2::new(pos.x, pos.y)),
+                    })
+                },
+            )?;
+
+        let blueprint = Blueprint::capture(
+            ship,
+            &world.read_component::<BlockEntity>(),
+            &world.read_component::<Paint>(),
+            &world.read_component::<Model>(),
+            &world.fetch::<Blocks>(),
+            min,
+            max,
+        );
+        Some(SharedShip {
+            blueprint,
+            origin: min,
+        })
+    }
+
+    /// The ship as a code, which only uses the characters of base64
+    pub fn export(&self, blocks: &Blocks, floors: &Floors) -> String {
+        let text = format!(
+            "version {}\norigin {} {}\n{}",
+            SharedShip::VERSION,
+            self.origin.x,
+            self.origin.y,
+            self.blueprint.to_text(blocks, floors)
+        );
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder
+            .write_all(text.as_bytes())
+            .and_then(|_| encoder.finish())
+            .map(|compressed| base64::encode(&compressed))
+            .expect("Writing to a Vec does not fail")
+    }
+
+    /// Reads a code made by `export`. Every block has to be found, since the ship would be
+    /// missing parts otherwise. The errors are shown to the player.
+    pub fn import(
+        code: &str,
+        blocks: &Blocks,
+        floors: &Floors,
+        strings: &Strings,
+    ) -> Result<SharedShip, String> {
+        let corrupt = || strings.get("share.corrupt").to_string();
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        if code.is_empty() {
+            return Err(strings.get("share.empty").to_string());
+        }
+
+        let compressed = base64::decode(&code).map_err(|_| corrupt())?;
+        let mut text = String::new();
+        DeflateDecoder::new(&compressed[..])
+            .take(SharedShip::MAX_TEXT_LEN)
+            .read_to_string(&mut text)
+            .map_err(|_| corrupt())?;
+
+        let mut lines = text.splitn(3, '\n');
+        match lines.next().and_then(|line| line.strip_prefix("version ")) {
+            Some(version) if version.parse() == Ok(SharedShip::VERSION) => {}
+            Some(version) => return Err(strings.format("share.version", &[("version", &version)])),
+            None => return Err(corrupt()),
+        }
+        let origin = match lines
+            .next()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>())
+            .as_deref()
+        {
+            Some(["origin", x, y]) => match (x.parse(), y.parse()) {
+                (Ok(x), Ok(y)) => Point2::new(x, y),
+                _ => return Err(corrupt()),
+            },
+            _ => return Err(corrupt()),
+        };
+
+        let (blueprint, report) =
+            Blueprint::parse(lines.next().unwrap_or_default(), blocks, floors, strings)
+                .map_err(|error| strings.format("share.invalid", &[("error", &error)]))?;
+        if !report.unresolved.is_empty() {
+            let names = report.unresolved_names();
+            return Err(strings.format("share.missing_blocks", &[("blocks", &names.join(", "))]));
+        }
+
+        Ok(SharedShip { blueprint, origin })
+    }
+
+    /// Whether a new game can be started with the ship, which needs the core where the
+    /// starting ship has it
+    pub fn has_core(&self, blocks: &Blocks) -> bool {
+        let core = Ship::CORE_POS;
+        let pos = Point3::new(core.x - self.origin.x, core.y - self.origin.y, core.z);
+        self.blueprint.block_at(pos, blocks) == Some(blocks.cube)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::headless::HeadlessEcs;
+    use crate::i18n::Language;
+
+    fn encode(text: &str) -> String {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(text.as_bytes()).unwrap();
+        base64::encode(encoder.finish().unwrap())
+    }
+
+    fn decode(code: &str) -> String {
+        let mut text = String::new();
+        let compressed = base64::decode(code).unwrap();
+        DeflateDecoder::new(&compressed[..])
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    /// The code of the starting ship, and what is needed to import it
+    fn starting_ship_code() -> (String, HeadlessEcs) {
+        let headless = HeadlessEcs::with_seed(1);
+        let world = &headless.ecs.world;
+        let shared = SharedShip::capture(world).unwrap();
+        let code = shared.export(&world.fetch::<Blocks>(), &world.fetch::<Floors>());
+        (code, headless)
+    }
+
+    fn import(code: &str, headless: &HeadlessEcs) -> Result<SharedShip, String> {
+        let world = &headless.ecs.world;
+        SharedShip::import(
+            code,
+            &world.fetch::<Blocks>(),
+            &world.fetch::<Floors>(),
+            &Strings::load(Language::English),
+        )
+    }
+
+    #[test]
+    fn export_then_import_gives_the_same_code() {
+        let (code, headless) = starting_ship_code();
+        let shared = import(&code, &headless).unwrap();
+        let world = &headless.ecs.world;
+        let blocks = world.fetch::<Blocks>();
+        assert!(shared.has_core(&blocks));
+        assert_eq!(shared.export(&blocks, &world.fetch::<Floors>()), code);
+
+        // Pasted codes are often wrapped over several lines
+        let wrapped: String = code
+            .chars()
+            .enumerate()
+            .flat_map(|(i, c)| if i % 40 == 39 { vec![c, '\n'] } else { vec![c] })
+            .collect();
+        let shared = import(&format!("  {}\n", wrapped), &headless).unwrap();
+        assert_eq!(shared.export(&blocks, &world.fetch::<Floors>()), code);
+    }
+
+    #[test]
+    fn truncated_codes_are_refused() {
+        let (code, headless) = starting_ship_code();
+        // The padding at the end can be left out, since the whole code is still there
+        for len in 0..code.trim_end_matches('=').len() {
+            assert!(
+                import(&code[..len], &headless).is_err(),
+                "The code cut at {} was imported",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn corrupt_codes_never_panic() {
+        let (code, headless) = starting_ship_code();
+        let alphabet: Vec<char> =
+            "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/=!é"
+                .chars()
+                .collect();
+        // A deflated stream has no checksum, so some changes still give a ship. They only
+        // need to be handled without panicking.
+        for (i, replacement) in (0..code.len()).zip(alphabet.iter().cycle()) {
+            let mut corrupt: Vec<char> = code.chars().collect();
+            corrupt[i] = *replacement;
+            let corrupt: String = corrupt.into_iter().collect();
+            let _ = import(&corrupt, &headless);
+        }
+
+        let strings = Strings::load(Language::English);
+        let corrupt = strings.get("share.corrupt");
+        assert_eq!(
+            import("", &headless).err().unwrap(),
+            strings.get("share.empty")
+        );
+        assert_eq!(import("not a code!", &headless).err().unwrap(), corrupt);
+        assert_eq!(
+            import(&base64::encode("ship"), &headless).err().unwrap(),
+            corrupt
+        );
+        assert_eq!(import(&encode("ship"), &headless).err().unwrap(), corrupt);
+    }
+
+    #[test]
+    fn invalid_ships_explain_why() {
+        let (code, headless) = starting_ship_code();
+        let text = decode(&code);
+        let version = format!("version {}\n", SharedShip::VERSION);
+        assert!(text.starts_with(&version));
+
+        let newer = text.replacen(&version, "version 99\n", 1);
+        let error = import(&encode(&newer), &headless).err().unwrap();
+        assert!(error.contains("99"), "{}", error);
+
+        let origin = text.lines().nth(1).unwrap();
+        let no_origin = text.replacen(origin, "origin here", 1);
+        assert!(import(&encode(&no_origin), &headless).is_err());
+
+        // Only the block lines end with the block's name
+        let missing = text.replace(" wall\n", " hull_plating\n");
+        assert_ne!(missing, text);
+        let error = import(&encode(&missing), &headless).err().unwrap();
+        assert!(error.contains("hull_plating"), "{}", error);
+
+        // Every tile has to be inside of the blueprint's size
+        let size = text.lines().find(|line| line.starts_with("size ")).unwrap();
+        let shrunk = text.replacen(size, "size 1 1", 1);
+        let error = import(&encode(&shrunk), &headless).err().unwrap();
+        assert!(error.contains("outside of the ship"), "{}", error);
+    }
+}
@@ -0,0 +1,479 @@
+use super::{
+    hazards::EventDirector,
+    heat::ShipHeat,
+    objects::Asteroid,
+    ship::{self, SelectedShip},
+    status::{self, StatusEffects},
+    time_effects::{TimeEffects, TimeEnvelope},
+    GameModule, Model, ObjectMeshes, ParticleParams, Particles, Ship, Time, ToBeRemoved, Transform,
+};
+use crate::debug::{ArgSchema, ArgType, DebugCommand, DebugCommands};
+use crate::graphics::{MeshManager, ModelTint};
+use crate::i18n::Strings;
+use crate::profiler::AddProfiled;
+use cgmath::{prelude::*, Vector3};
+use specs::{prelude::*, Component};
+use std::collections::{HashMap, HashSet};
+
+/// Shield generators project a bubble around their ship, which stops asteroids before they
+/// hit it. The bubble has a pool of points that each stopped asteroid drains, and that
+/// recharges while the ship is not too hot (and no solar flare is going on).
+pub struct ShieldModule;
+
+impl GameModule for ShieldModule {
+    fn register(&self, world: &mut World) {
+        world.register::<ShieldGenerator>();
+        world.register::<ShieldBubble>();
+        world.register::<Asteroid>();
+        world.register::<StatusEffects>();
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(ShieldSystem::default(), "shield_system", &["heat_system"]);
+        builder.add_profiled(
+            ShieldBubbleSystem,
+            "shield_bubble_system",
+            &["shield_system"],
+        );
+    }
+
+    fn commands(&self, commands: &mut DebugCommands) {
+        commands.register(DebugCommand {
+            name: "shield",
+            description: "Sets the shield points of the selected ship",
+            args: vec![ArgSchema {
+                name: "points",
+                arg_type: ArgType::Float,
+            }],
+            state: Some(|ecs| {
+                let ships = ecs.world.read_component::<Ship>();
+                let selected = ecs.get_resource::<SelectedShip>();
+                match selected.find(&ecs.world.entities(), &ships) {
+                    Some((_, ship)) => {
+                        let shield = ship.shield();
+                        format!("{:.1} / {:.0}", shield.points(), shield.max())
+                    }
+                    None => String::from("no ship"),
+                }
+            }),
+            action: |ecs, args| {
+                let selected = ship::selected_ship(&ecs.world);
+                let mut ships = ecs.world.write_component::<Ship>();
+                let ship = selected
+                    .and_then(|ship| ships.get_mut(ship))
+                    .ok_or_else(|| String::from("There is no ship"))?;
+                ship.shield_mut().set_points(args[0].as_float());
+                Ok(())
+            },
+        });
+    }
+}
+
+/// A gadget that adds to the shield of its ship. Each one adds points, recharges faster and
+/// widens the bubble.
+#[derive(Component, Default)]
+#[storage(HashMapStorage)]
+pub struct ShieldGenerator;
+
+impl ShieldGenerator {
+    /// Ex. "Shield generator - 14 / 20 points"
+    pub fn tooltip(entity: Entity, world: &World) -> String {
+        let ships = world.read_component::<Ship>();
+        let shield = match ships
+            .join()
+            .find(|ship| ship.gadgets().any(|gadget| gadget == entity))
+        {
+            Some(ship) => ship.shield(),
+            None => return String::new(),
+        };
+        let strings = world.fetch::<Strings>();
+        let key = if shield.is_broken() {
+            "shield.tooltip_broken"
+        } else {
+            "shield.tooltip"
+        };
+        strings.format(
+            key,
+            &[
+                ("points", &format!("{:.0}", shield.points())),
+                ("max", &format!("{:.0}", shield.max())),
+            ],
+        )
+    }
+}
+
+/// A recent hit on the bubble, which flashes where it was hit
+#[derive(Clone, Copy, Debug)]
+pub struct ShieldHit {
+    /// Tells the hits apart while they flash, since older hits are removed before them
+    id: u32,
+    /// Where the bubble was hit, on its surface
+    pub point: Vector3<f32>,
+    pub time_left: f32,
+}
+
+/// The shield of a ship, which is part of the ship like its heat
+#[derive(Clone, Debug, Default)]
+pub struct ShipShield {
+    points: f32,
+    max: f32,
+    /// Set once the points run out, and cleared once they recharge to RESTORED of the max.
+    /// The shield does not stop anything until then.
+    broken: bool,
+    /// The center and the radius of the bubble, or None without any working generators
+    bubble: Option<(Vector3<f32>, f32)>,
+    hits: Vec<ShieldHit>,
+    next_hit_id: u32,
+}
+
+impl ShipShield {
+    const POINTS_PER_GENERATOR: f32 = 10.0;
+    /// The points each generator recharges each second
+    const RECHARGE_PER_GENERATOR: f32 = 0.5;
+    /// The shield only recharges while the ship's heat is below this
+    pub const RECHARGE_HEAT: f32 = ShipHeat::MAX * 0.6;
+    /// The fraction of the max points a broken shield recharges to before it works again
+    const RESTORED: f32 = 0.5;
+    /// How far the bubble reaches past the corners of the ship
+    const MARGIN: f32 = 1.0;
+    /// How much each generator after the first widens the bubble
+    const RADIUS_PER_GENERATOR: f32 = 0.5;
+    /// The seconds a hit flashes for (12 ticks at the default tick rate)
+    const FLASH_TIME: f32 = 12.0 / Time::DEFAULT_RATE as f32;
+    /// The most hits that flash at once
+    const MAX_HITS: usize = 8;
+
+    pub fn points(&self) -> f32 {
+        self.points
+    }
+
+    pub fn max(&self) -> f32 {
+        self.max
+    }
+
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// Whether the shield stops asteroids. A ship without generators has no shield.
+    pub fn is_active(&self) -> bool {
+        self.bubble.is_some() && !self.broken && self.points > 0.0
+    }
+
+    pub fn bubble(&self) -> Option<(Vector3<f32>, f32)> {
+        self.bubble
+    }
+
+    pub fn hits(&self) -> &[ShieldHit] {
+        &self.hits
+    }
+
+    /// Sets the points (ex. from a debug command), which breaks the shield at 0
+    pub fn set_points(&mut self, points: f32) {
+        self.points = points.clamp(0.0, self.max);
+        self.broken = self.points <= 0.0 || (self.broken && !self.is_restored());
+    }
+
+    fn is_restored(&self) -> bool {
+        self.points >= self.max * ShipShield::RESTORED
+    }
+
+    /// Whether the point is inside of an active shield's bubble
+    pub fn covers(&self, point: Vector3<f32>, radius: f32) -> bool {
+        match self.bubble {
+            Some((center, bubble_radius)) if self.is_active() => {
+                (point - center).magnitude() < bubble_radius + radius
+            }
+            _ => false,
+        }
+    }
+
+    /// Drains the points instead of letting the damage through, if the shield is active.
+    /// The bubble flashes where the line from its center to `point` crosses it.
+    pub fn absorb(&mut self, damage: f32, point: Vector3<f32>) -> bool {
+        let (center, radius) = match self.bubble {
+            Some(bubble) if self.is_active() => bubble,
+            _ => return false,
+        };
+        self.points = (self.points - damage).max(0.0);
+        if self.points <= 0.0 {
+            self.broken = true;
+        }
+
+        let offset = point - center;
+        let direction = if offset.magnitude2() > f32::EPSILON {
+            offset.normalize()
+        } else {
+            Vector3::unit_z()
+        };
+        if self.hits.len() >= ShipShield::MAX_HITS {
+            self.hits.remove(0);
+        }
+        self.hits.push(ShieldHit {
+            id: self.next_hit_id,
+            point: center + direction * radius,
+            time_left: ShipShield::FLASH_TIME,
+        });
+        self.next_hit_id = self.next_hit_id.wrapping_add(1);
+        true
+    }
+
+    /// Sizes the bubble around the ship, and recharges the points unless `flaring`
+    fn update(&mut self, ship: &Ship, generators: usize, delta: f32, flaring: bool) {
+        for hit in &mut self.hits {
+            hit.time_left -= delta;
+        }
+        self.hits.retain(|hit| hit.time_left > 0.0);
+
+        self.max = generators as f32 * ShipShield::POINTS_PER_GENERATOR;
+        self.bubble = ship.bounds().filter(|_| generators > 0).map(|bounds| {
+            let radius = (bounds.max - bounds.min).magnitude() / 2.0
+                + ShipShield::MARGIN
+                + (generators - 1) as f32 * ShipShield::RADIUS_PER_GENERATOR;
+            ((bounds.min + bounds.max) / 2.0, radius)
+        });
+
+        if ship.heat().heat() < ShipShield::RECHARGE_HEAT && !flaring {
+            self.points += generators as f32 * ShipShield::RECHARGE_PER_GENERATOR * delta;
+        }
+        self.points = self.points.min(self.max);
+        if self.broken && self.max > 0.0 && self.is_restored() {
+            self.broken = false;
+        }
+    }
+}
+
+/// Recharges the shields, and stops the asteroids that enter their bubbles. Asteroids that
+/// still reach a shielded ship (ex. because the bubble came up around them) are stopped by
+/// the collision response instead.
+#[derive(Default)]
+struct ShieldSystem {
+    /// The ships whose shields were broken during the last update, so a shield that breaks
+    /// (here or in the collision response) slows the game down once
+    broken: HashSet<Entity>,
+}
+
+impl ShieldSystem {
+    const PARTICLES: ParticleParams = ParticleParams {
+        color: Vector3::new(0.5, 0.8, 1.0),
+        speed: 3.0,
+        lifetime: 0.4,
+        size: 0.15,
+    };
+    const BREAK_EFFECT: TimeEnvelope = TimeEnvelope {
+        scale: 0.25,
+        ease_in: 3,
+        hold: 15,
+        recover: 30,
+    };
+}
+
+impl<'a> System<'a> for ShieldSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Time>,
+        WriteExpect<'a, TimeEffects>,
+        Write<'a, ToBeRemoved>,
+        WriteExpect<'a, Particles>,
+        ReadExpect<'a, EventDirector>,
+        WriteStorage<'a, Ship>,
+        ReadStorage<'a, ShieldGenerator>,
+        ReadStorage<'a, StatusEffects>,
+        ReadStorage<'a, Asteroid>,
+        ReadStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            time,
+            mut time_effects,
+            mut to_be_removed,
+            mut particles,
+            director,
+            mut ships,
+            generators,
+            statuses,
+            asteroids,
+            transforms,
+        ) = data;
+
+        let broken: HashSet<Entity> = (&entities, &ships)
+            .join()
+            .filter(|(_, ship)| ship.shield().is_broken())
+            .map(|(entity, _)| entity)
+            .collect();
+        if broken.difference(&self.broken).next().is_some() {
+            time_effects.request(ShieldSystem::BREAK_EFFECT);
+        }
+        self.broken = broken;
+
+        for ship in (&mut ships).join() {
+            // EMPed and browned out generators stop holding up the shield
+            let working = ship
+                .gadgets()
+                .filter(|gadget| {
+                    generators.contains(*gadget)
+                        && !status::is_emped(&statuses, *gadget)
+                        && !ship.power().is_browned_out(*gadget)
+                })
+                .count();
+            let mut shield = std::mem::take(ship.shield_mut());
+            shield.update(ship, working, time.delta, director.is_flaring());
+            *ship.shield_mut() = shield;
+        }
+
+        for (entity, _, transform) in (&entities, &asteroids, &transforms).join() {
+            if to_be_removed.bitset().contains(entity.id()) {
+                continue;
+            }
+            let radius = Asteroid::COLLIDER_RADIUS * transform.scale.x;
+            if let Some(ship) = (&mut ships)
+                .join()
+                .find(|ship| ship.shield().covers(transform.position, radius))
+            {
+                let damage = Asteroid::IMPACT_DAMAGE as f32;
+                if ship.shield_mut().absorb(damage, transform.position) {
+                    to_be_removed.add(entity);
+                    particles.emit_burst(transform.position, 12, &ShieldSystem::PARTICLES);
+                }
+            }
+        }
+    }
+}
+
+/// A model of a shield: its bubble, or the flash of a hit on it
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct ShieldBubble {
+    ship: Entity,
+    /// The id of the hit that is flashing, or None for the bubble
+    hit: Option<u32>,
+}
+
+/// Keeps a translucent model of each active bubble, and of each hit that flashes on it.
+/// The hits are models of their own, so they can be brighter than the rest of the bubble.
+struct ShieldBubbleSystem;
+
+impl ShieldBubbleSystem {
+    const COLOR: Vector3<f32> = Vector3::new(0.45, 0.75, 1.0);
+    /// The bubble is fainter the fewer points it has left
+    const MIN_ALPHA: f32 = 0.08;
+    const MAX_ALPHA: f32 = 0.2;
+    /// How much the newest hit brightens the whole bubble
+    const HIT_ALPHA: f32 = 0.15;
+    const FLASH_COLOR: Vector3<f32> = Vector3::new(0.8, 0.95, 1.0);
+    const FLASH_ALPHA: f32 = 0.7;
+    const FLASH_RADIUS: f32 = 0.9;
+
+    fn tint(color: Vector3<f32>, alpha: f32) -> ModelTint {
+        let color = color.extend(alpha);
+        ModelTint {
+            primary: color,
+            secondary: color,
+        }
+    }
+}
+
+impl<'a> System<'a> for ShieldBubbleSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, ObjectMeshes>,
+        WriteExpect<'a, MeshManager>,
+        Write<'a, ToBeRemoved>,
+        ReadStorage<'a, Ship>,
+        WriteStorage<'a, ShieldBubble>,
+        WriteStorage<'a, Model>,
+        WriteStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            meshes,
+            mut mesh_manager,
+            mut to_be_removed,
+            ships,
+            mut bubbles,
+            mut models,
+            mut transforms,
+        ) = data;
+
+        let mut existing: HashMap<(Entity, Option<u32>), Entity> = (&entities, &bubbles)
+            .join()
+            .filter(|(entity, _)| !to_be_removed.bitset().contains(entity.id()))
+            .map(|(entity, bubble)| ((bubble.ship, bubble.hit), entity))
+            .collect();
+
+        for (ship_entity, ship) in (&entities, &ships).join() {
+            let shield = ship.shield();
+            let (center, radius) = match shield.bubble() {
+                Some(bubble) if shield.is_active() => bubble,
+                _ => continue,
+            };
+            let fraction = shield.points() / shield.max();
+            let newest_hit = shield.hits().last().map_or(0.0, |hit| {
+                hit.time_left / ShipShield::FLASH_TIME * ShieldBubbleSystem::HIT_ALPHA
+            });
+            let alpha = ShieldBubbleSystem::MIN_ALPHA
+                + (ShieldBubbleSystem::MAX_ALPHA - ShieldBubbleSystem::MIN_ALPHA) * fraction
+                + newest_hit;
+
+            let bubble = (None, center, radius, ShieldBubbleSystem::COLOR, alpha);
+            let flashes = shield.hits().iter().map(|hit| {
+                let fade = hit.time_left / ShipShield::FLASH_TIME;
+                (
+                    Some(hit.id),
+                    hit.point,
+                    ShieldBubbleSystem::FLASH_RADIUS * (0.5 + fade * 0.5),
+                    ShieldBubbleSystem::FLASH_COLOR,
+                    ShieldBubbleSystem::FLASH_ALPHA * fade,
+                )
+            });
+            for (hit, position, scale, color, alpha) in std::iter::once(bubble).chain(flashes) {
+                let tint = ShieldBubbleSystem::tint(color, alpha);
+                let mut transform = Transform::from_position(position.x, position.y, position.z);
+                transform.scale = Vector3::new(scale, scale, scale);
+
+                match existing.remove(&(ship_entity, hit)) {
+                    Some(entity) => {
+                        if let Some(model) = models.get_mut(entity) {
+                            model.tint = tint;
+                            if let Some(model_id) = model.model_id {
+                                mesh_manager.update_tint(model.mesh_id, model_id, tint);
+                            }
+                        }
+                        // The transform is only touched when it moves, so the model is not
+                        // written to the MeshManager every update
+                        if let Some(current) = transforms.get_mut(entity) {
+                            if current.position != transform.position
+                                || current.scale != transform.scale
+                            {
+                                *current = transform;
+                            }
+                        }
+                    }
+                    None => {
+                        entities
+                            .build_entity()
+                            .with(Model::with_tint(meshes.shield, tint), &mut models)
+                            .with(transform, &mut transforms)
+                            .with(
+                                ShieldBubble {
+                                    ship: ship_entity,
+                                    hit,
+                                },
+                                &mut bubbles,
+                            )
+                            .build();
+                    }
+                }
+            }
+        }
+
+        // The bubbles of shields that went down, and the hits that stopped flashing
+        for entity in existing.values() {
+            to_be_removed.add(*entity);
+        }
+    }
+}
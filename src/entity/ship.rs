@@ -1,46 +1,630 @@
-use super::{Collider, Model, Transform};
-use crate::block::{BlockId, Blocks};
+use super::{
+    animation, construction::ConstructionQueue, faction::Faction, heat::ShipHeat, objects::Health,
+    power::ShipPower, shield::ShipShield, Collider, Model, ToBeRemoved, Transform,
+};
+use super::{
+    gameplay::{GameLog, WaveCheckpoint},
+    InputManager,
+};
+use crate::block::{Block, BlockId, Blocks};
 use crate::floor::{Floor, Floors};
-use cgmath::Point2;
-use specs::{prelude::*, world::EntitiesRes, Component};
+use crate::i18n::Strings;
+use crate::item::{self, Inventory};
+use crate::stats::{StatEvent, StatEvents};
+use crate::tech::TechTree;
+use cgmath::{InnerSpace, Point2, Point3, Vector2, Vector3};
+use specs::{prelude::*, storage::MaskedStorage, world::EntitiesRes, Component};
 use std::collections::HashMap;
+use std::ops::Deref;
+
+/// The game is lost with the mothership. Drone ships are smaller ships that can be deployed
+/// next to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShipKind {
+    Mothership,
+    /// The origin is the corner of the drone's hull, which is where it was deployed
+    Drone {
+        origin: Point2<i16>,
+    },
+}
+
+impl ShipKind {
+    /// The key of the kind's name in the language files
+    pub fn name_key(&self) -> &'static str {
+        match self {
+            ShipKind::Mothership => "ship.mothership",
+            ShipKind::Drone { .. } => "ship.drone",
+        }
+    }
+}
 
 #[derive(Component)]
 #[storage(VecStorage)]
 pub struct Ship {
-    tiles: HashMap<Point2<i16>, Tile>,
+    kind: ShipKind,
+    /// The tiles of every deck. The z of a position is its deck.
+    tiles: HashMap<Point3<i16>, Tile>,
+    /// The game is lost once the core is destroyed
+    core: Option<Entity>,
+    /// The bounds of every built tile, which are updated whenever the ship is built on
+    bounds: Option<Bounds>,
+    /// The tiles whose block was built or removed since the walls were last updated
+    changed_tiles: Vec<Point3<i16>>,
+    /// Set when a block was destroyed, until the IntegritySystem checks what is still
+    /// connected to the core
+    structure_changed: bool,
+    /// Counts the changes to the layout (blocks, gadgets and floors), so crew know when to
+    /// find new paths
+    layout_revision: u32,
+    heat: ShipHeat,
+    power: ShipPower,
+    shield: ShipShield,
+}
+
+impl Ship {
+    pub const BLOCK_HEALTH: u32 = 3;
+    pub const CORE_HEALTH: u32 = 10;
+    /// The height of the tallest block (walls)
+    pub const HEIGHT: f32 = 3.0;
+    /// How many decks can be built on. Deck 0 is the lowest.
+    pub const DECKS: i16 = 2;
+    /// Each deck is built on top of the walls of the deck below
+    pub const DECK_HEIGHT: f32 = Ship::HEIGHT;
+    /// The height of the top of the highest deck
+    pub const TOP: f32 = Ship::DECKS as f32 * Ship::DECK_HEIGHT;
+    /// How many tiles an upper deck can reach out from the walls holding it up
+    pub const MAX_SPAN: i16 = 3;
+    /// Where the core of the starting ship is
+    pub const CORE_POS: Point3<i16> = Point3::new(4, 4, 0);
+    /// The size of a drone ship's hull, which has a wall at each end
+    const DRONE_SIZE: i16 = 3;
+    /// How many tiles a drone ship can be built out from its hull
+    const DRONE_MARGIN: i16 = 2;
+
+    /// The height of the floor of the deck
+    pub fn deck_z(deck: i16) -> f32 {
+        deck as f32 * Ship::DECK_HEIGHT
+    }
+
+    pub fn kind(&self) -> ShipKind {
+        self.kind
+    }
+
+    pub fn core(&self) -> Option<Entity> {
+        self.core
+    }
+
+    pub fn heat(&self) -> &ShipHeat {
+        &self.heat
+    }
+
+    pub fn heat_mut(&mut self) -> &mut ShipHeat {
+        &mut self.heat
+    }
+
+    pub fn power(&self) -> &ShipPower {
+        &self.power
+    }
+
+    pub fn power_mut(&mut self) -> &mut ShipPower {
+        &mut self.power
+    }
+
+    pub fn shield(&self) -> &ShipShield {
+        &self.shield
+    }
+
+    pub fn shield_mut(&mut self) -> &mut ShipShield {
+        &mut self.shield
+    }
+
+    /// The entities of every built block
+    pub fn blocks(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.tiles.values().filter_map(|tile| tile.block)
+    }
+
+    /// The entities of every built gadget
+    pub fn gadgets(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.tiles.values().filter_map(|tile| tile.gadget)
+    }
+
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.bounds
+    }
+
+    fn update_bounds(&mut self) {
+        self.bounds = self
+            .tiles
+            .iter()
+            .filter(|(_, tile)| {
+                tile.block.is_some() || tile.gadget.is_some() || tile.floor.is_some()
+            })
+            .map(|(pos, _)| {
+                let z = Ship::deck_z(pos.z);
+                Bounds {
+                    min: Vector3::new(pos.x as f32 - 0.5, pos.y as f32 - 0.5, z),
+                    max: Vector3::new(pos.x as f32 + 0.5, pos.y as f32 + 0.5, z + Ship::HEIGHT),
+                }
+            })
+            .fold(None, |bounds: Option<Bounds>, tile| match bounds {
+                Some(bounds) => Some(Bounds {
+                    min: Vector3::new(
+                        bounds.min.x.min(tile.min.x),
+                        bounds.min.y.min(tile.min.y),
+                        bounds.min.z.min(tile.min.z),
+                    ),
+                    max: Vector3::new(
+                        bounds.max.x.max(tile.max.x),
+                        bounds.max.y.max(tile.max.y),
+                        bounds.max.z.max(tile.max.z),
+                    ),
+                }),
+                None => Some(tile),
+            });
+    }
+
+    pub fn has_tile(&self, pos: Point3<i16>) -> bool {
+        self.tiles.contains_key(&pos)
+    }
+
+    pub fn tile(&self, pos: Point3<i16>) -> Option<&Tile> {
+        self.tiles.get(&pos)
+    }
+
+    /// Whether the block is built on the tile of this ship (ex. to find the ship of a block
+    /// from its root)
+    pub fn has_block(&self, pos: Point3<i16>, block: Entity) -> bool {
+        matches!(self.tile(pos), Some(tile) if tile.block == Some(block))
+    }
+
+    /// The fraction of damage that the block at `pos` doesn't take, from the most protective
+    /// floor on its tile or next to it on the same deck
+    pub fn floor_protection(&self, pos: Point3<i16>, floors: &Floors) -> f32 {
+        [(0, 0), (1, 0), (-1, 0), (0, 1), (0, -1)]
+            .iter()
+            .filter_map(|(x, y)| self.tile(pos + Vector3::new(*x, *y, 0))?.floor_type())
+            .map(|floor| floors.get(floor).properties.damage_reduction)
+            .fold(0.0, f32::max)
+    }
+
+    /// The tiles of every deck, including the empty ones
+    pub fn tiles(&self) -> impl Iterator<Item = (Point3<i16>, &Tile)> + '_ {
+        self.tiles.iter().map(|(pos, tile)| (*pos, tile))
+    }
+
+    /// The tile, the entity and the kind of every block and gadget. Gadgets don't have a
+    /// BlockEntity, so their kind is found by their mesh.
+    pub fn block_kinds<'a>(
+        &'a self,
+        blocks: &'a Blocks,
+        block_entities: &'a ReadStorage<BlockEntity>,
+        models: &'a ReadStorage<Model>,
+    ) -> impl Iterator<Item = (Point3<i16>, Entity, BlockId)> + 'a {
+        self.tiles().flat_map(move |(pos, tile)| {
+            let block = tile
+                .block()
+                .and_then(|entity| Some((entity, block_entities.get(entity)?.block_id())));
+            let gadget = tile.gadget().and_then(|entity| {
+                Some((entity, blocks.find_by_mesh(models.get(entity)?.mesh_id)?))
+            });
+            block
+                .into_iter()
+                .chain(gadget)
+                .map(move |(entity, block)| (pos, entity, block))
+        })
+    }
+
+    /// Marks that the block of the tile was built or removed
+    pub fn mark_changed(&mut self, pos: Point3<i16>) {
+        self.changed_tiles.push(pos);
+        self.layout_revision += 1;
+    }
+
+    pub fn layout_revision(&self) -> u32 {
+        self.layout_revision
+    }
+
+    pub fn take_changed_tiles(&mut self) -> Vec<Point3<i16>> {
+        std::mem::take(&mut self.changed_tiles)
+    }
+
+    /// Marks that a block was destroyed, which may have cut off part of the ship
+    pub fn mark_structure_changed(&mut self) {
+        self.structure_changed = true;
+    }
+
+    pub fn take_structure_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.structure_changed, false)
+    }
+
+    /// Removes the blocks, gadgets and floors of the tiles from the ship. Their entities are
+    /// left in the world.
+    pub fn detach(&mut self, positions: &[Point3<i16>]) {
+        for pos in positions {
+            if let Some(tile) = self.tiles.get_mut(pos) {
+                tile.block = None;
+                tile.gadget = None;
+                tile.floor = None;
+                self.changed_tiles.push(*pos);
+            }
+        }
+        self.layout_revision += 1;
+
+        self.update_bounds();
+    }
+
+    /// Checks the rules for building on the decks: the block has to be allowed on the deck,
+    /// the tile above stairs is kept open, and upper decks need a wall on the deck below
+    /// within MAX_SPAN tiles to hold them up. `block` is None for floors. `block_at` finds
+    /// the block that is built (or about to be built) on a tile.
+    pub fn check_placement(
+        &self,
+        pos: Point3<i16>,
+        block: Option<&Block>,
+        blocks: &Blocks,
+        block_at: impl Fn(Point3<i16>) -> Option<BlockId>,
+        strings: &Strings,
+    ) -> Result<(), String> {
+        let (x, y, deck) = (&pos.x, &pos.y, &pos.z);
+        let tile = self
+            .tile(pos)
+            .ok_or_else(|| strings.format("error.outside_ship", &[("x", x), ("y", y)]))?;
+        if let Some(block) = block {
+            let name = strings.get(block.name_key);
+            if !block.decks.contains(&pos.z) {
+                return Err(strings.format("error.wrong_deck", &[("block", &name), ("deck", deck)]));
+            }
+            // Gadgets are checked by the block they are built on
+            if block.is_gadget {
+                return Ok(());
+            }
+        }
+
+        let below = pos + Vector3::new(0, 0, -1);
+        let above = pos + Vector3::new(0, 0, 1);
+        if block_at(below) == Some(blocks.stairs) {
+            return Err(strings.format("error.above_stairs", &[("x", x), ("y", y), ("deck", deck)]));
+        }
+        if let Some(block) = block.filter(|block| block.id == blocks.stairs) {
+            let covered = block_at(above).is_some()
+                || matches!(self.tile(above), Some(tile) if tile.floor.is_some());
+            if covered {
+                return Err(strings.format(
+                    "error.stairs_covered",
+                    &[("block", &strings.get(block.name_key))],
+                ));
+            }
+        }
+        if block.is_some() && tile.block.is_some() {
+            return Err(strings.format("error.tile_has_block", &[("x", x), ("y", y)]));
+        }
+
+        let holds_deck = |pos: Point3<i16>| {
+            let height = block_at(pos).map(|block| blocks.get_block(block).height);
+            matches!(height, Some(height) if height >= Ship::DECK_HEIGHT)
+        };
+        let supported = pos.z == 0
+            || (0..=Ship::MAX_SPAN).any(|distance| {
+                [(1, 0), (-1, 0), (0, 1), (0, -1)]
+                    .iter()
+                    .any(|(x, y)| holds_deck(below + Vector3::new(x * distance, y * distance, 0)))
+            });
+        if !supported {
+            return Err(strings.format("error.unsupported", &[("x", x), ("y", y), ("deck", deck)]));
+        }
+
+        Ok(())
+    }
+}
+
+/// Finds the mothership, which the ships that are not selected (ex. the crew's ship) mean
+pub fn mothership<'s, D>(ships: &'s Storage<'_, Ship, D>) -> Option<&'s Ship>
+where
+    D: Deref<Target = MaskedStorage<Ship>>,
+{
+    ships.join().find(|ship| ship.kind == ShipKind::Mothership)
+}
+
+/// The ship that building, the camera and the heat HUD refer to. It is selected by clicking
+/// a ship's core, or with the ship tabs of the HUD. Falls back to the mothership when nothing
+/// (or a ship that is gone) is selected.
+#[derive(Clone, Copy, Default)]
+pub struct SelectedShip(pub Option<Entity>);
+
+impl SelectedShip {
+    pub fn find<'s, D>(
+        &self,
+        entities: &EntitiesRes,
+        ships: &'s Storage<'_, Ship, D>,
+    ) -> Option<(Entity, &'s Ship)>
+    where
+        D: Deref<Target = MaskedStorage<Ship>>,
+    {
+        self.0
+            .filter(|entity| entities.is_alive(*entity))
+            .and_then(|entity| Some((entity, ships.get(entity)?)))
+            .or_else(|| {
+                (entities, ships)
+                    .join()
+                    .find(|(_, ship)| ship.kind == ShipKind::Mothership)
+            })
+    }
+}
+
+/// The entity of the selected ship
+pub fn selected_ship(world: &World) -> Option<Entity> {
+    world
+        .fetch::<SelectedShip>()
+        .find(&world.entities(), &world.read_component::<Ship>())
+        .map(|(entity, _)| entity)
+}
+
+/// Selects the ship after the selected one, going back to the first ship after the last
+pub fn select_next_ship(world: &World) {
+    let entities = world.entities();
+    let ships = world.read_component::<Ship>();
+    let mut selected = world.write_resource::<SelectedShip>();
+    let all: Vec<Entity> = (&entities, &ships)
+        .join()
+        .map(|(entity, _)| entity)
+        .collect();
+    let current = selected
+        .find(&entities, &ships)
+        .and_then(|(current, _)| all.iter().position(|entity| *entity == current));
+    let next = current.map_or(0, |index| (index + 1) % all.len());
+    selected.0 = all.get(next).copied();
+}
+
+/// Selects the ship whose core was clicked
+pub struct ShipSelectSystem;
+
+impl<'a> System<'a> for ShipSelectSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, InputManager>,
+        Write<'a, SelectedShip>,
+        ReadExpect<'a, Strings>,
+        WriteExpect<'a, GameLog>,
+        ReadStorage<'a, Ship>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, input, mut selected, strings, mut log, ships) = data;
+        let clicked = match input.clicked_block {
+            Some(block) => block,
+            None => return,
+        };
+        let ship = (&entities, &ships)
+            .join()
+            .find(|(_, ship)| ship.core == Some(clicked));
+        if let Some((entity, ship)) = ship.filter(|(entity, _)| selected.0 != Some(*entity)) {
+            selected.0 = Some(entity);
+            log.add(strings.format(
+                "log.ship_selected",
+                &[("ship", &strings.get(ship.kind.name_key()))],
+            ));
+        }
+    }
+}
+
+/// An axis aligned bounding box
+#[derive(Clone, Copy, Debug)]
+pub struct Bounds {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Bounds {
+    pub fn intersects_sphere(&self, center: Vector3<f32>, radius: f32) -> bool {
+        let closest = Vector3::new(
+            center.x.max(self.min.x).min(self.max.x),
+            center.y.max(self.min.y).min(self.max.y),
+            center.z.max(self.min.z).min(self.max.z),
+        );
+        (closest - center).magnitude2() <= radius * radius
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Tile {
     block: Option<Entity>,
     gadget: Option<Entity>,
-    floor: Option<Entity>,
+    /// The floor's entity, and which kind of floor it is
+    floor: Option<(Entity, Floor)>,
+}
+
+impl Tile {
+    pub fn block(&self) -> Option<Entity> {
+        self.block
+    }
+
+    /// Gadgets are built on top of a block
+    pub fn gadget(&self) -> Option<Entity> {
+        self.gadget
+    }
+
+    pub fn floor(&self) -> Option<Entity> {
+        self.floor.map(|(entity, _)| entity)
+    }
+
+    pub fn floor_type(&self) -> Option<Floor> {
+        self.floor.map(|(_, floor)| floor)
+    }
 }
 
 #[derive(Component)]
 #[storage(VecStorage)]
 pub struct BlockEntity {
     block_id: BlockId,
-    root: Point2<i16>,
+    root: Point3<i16>,
 }
 
+impl BlockEntity {
+    pub fn block_id(&self) -> BlockId {
+        self.block_id
+    }
+
+    /// The tile the block was built on
+    pub fn root(&self) -> Point3<i16> {
+        self.root
+    }
+}
+
+/// The z of the positions is the deck. Removing a block removes its gadget instead, if it has
+/// one.
+#[derive(Clone, Copy)]
 pub enum BuildAction {
-    BuildBlock(Point2<i16>, BlockId),
-    RemoveBlock(Point2<i16>),
-    BuildFloor(Point2<i16>, Floor),
-    RemoveFloor(Point2<i16>),
+    BuildBlock(Point3<i16>, BlockId),
+    RemoveBlock(Point3<i16>),
+    BuildFloor(Point3<i16>, Floor),
+    RemoveFloor(Point3<i16>),
 }
 
+impl BuildAction {
+    pub fn pos(&self) -> Point3<i16> {
+        match self {
+            BuildAction::BuildBlock(pos, _)
+            | BuildAction::RemoveBlock(pos)
+            | BuildAction::BuildFloor(pos, _)
+            | BuildAction::RemoveFloor(pos) => *pos,
+        }
+    }
+}
+
+/// What placed the build actions, which decides what is checked before building
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BuildSource {
+    /// The starting ship, which may use locked blocks and is free
+    InitialShip,
+    /// Blocks placed one at a time, which need to be unlocked and are paid for when built
+    Player,
+    /// Blocks that were already checked and paid for as a whole (ex. a stamped blueprint)
+    Prepaid,
+    /// Blocks finished by a construction drone. They were paid for (and counted) when queued.
+    Construction,
+}
+
+/// Builds the actions on the ship. Blocks that have not been unlocked in the TechTree,
+/// and blocks or floors that the inventory can't pay for, are refused. Removing is free, and
+/// refunds nothing (see undo).
 pub fn execute_build_actions(world: &mut World, ship: Entity, actions: &[BuildAction]) {
+    build(world, ship, actions, BuildSource::Player);
+}
+
+pub fn build(world: &mut World, ship: Entity, actions: &[BuildAction], source: BuildSource) {
     let lazy_update = world.fetch::<LazyUpdate>();
     let entities = world.fetch::<EntitiesRes>();
     let mut ships = world.write_component::<Ship>();
+    let ship_entity = ship;
     let ship = ships.get_mut(ship).unwrap();
     let blocks = world.fetch::<Blocks>();
+    let floors = world.fetch::<Floors>();
     let block_entities = world.read_component::<BlockEntity>();
+    let tech_tree = world.fetch::<TechTree>();
+    let strings = world.fetch::<Strings>();
+    let mut inventory = world.fetch_mut::<Inventory>();
+    let mut stat_events = world.fetch_mut::<StatEvents>();
+    let mut queue = world.fetch_mut::<ConstructionQueue>();
+    let mut to_be_removed = world.fetch_mut::<ToBeRemoved>();
+    let queued = queue.enabled && matches!(source, BuildSource::Player | BuildSource::Prepaid);
+    // The blocks built by these actions. They don't have a BlockEntity until the world is
+    // maintained.
+    let mut placed = HashMap::new();
 
     for action in actions {
+        // Construction drones finish builds that were already checked when they were queued
+        let placement = match action {
+            BuildAction::BuildBlock(pos, block_id) => {
+                Some((*pos, Some(blocks.get_block(*block_id))))
+            }
+            BuildAction::BuildFloor(pos, _) => Some((*pos, None)),
+            _ => None,
+        };
+        if let Some((pos, block)) = placement.filter(|_| source != BuildSource::Construction) {
+            let block_at = |pos| {
+                placed.get(&pos).copied().or_else(|| {
+                    let block = ship.tile(pos)?.block?;
+                    block_entities.get(block).map(BlockEntity::block_id)
+                })
+            };
+            if let Err(error) = ship.check_placement(pos, block, &blocks, block_at, &strings) {
+                println!("[Build] {}", error);
+                continue;
+            }
+        }
+
+        // Floors are built right away, even when blocks are built by construction drones
+        if let (BuildAction::BuildFloor(pos, floor), BuildSource::Player) = (action, source) {
+            let floor = floors.get(*floor);
+            if matches!(ship.tile(*pos), Some(tile) if tile.floor.is_some()) {
+                println!("[Build] ({}, {}) already has a floor", pos.x, pos.y);
+                continue;
+            }
+            if !inventory.has_items(&floor.cost) {
+                println!(
+                    "[Build] The {} floor costs {}",
+                    floor.name,
+                    item::describe_cost(&floor.cost, &strings)
+                );
+                continue;
+            }
+            inventory.remove_items(&floor.cost);
+        }
+
+        if let BuildAction::BuildBlock(pos, block_id) = action {
+            let block = blocks.get_block(*block_id);
+            // What the block was paid with, which is refunded if it can't be queued
+            let mut paid = block.cost.clone();
+            let mut used_voucher = false;
+            if source == BuildSource::Player {
+                if !tech_tree.is_unlocked(*block_id) {
+                    println!("[Build] {} has not been unlocked", block.type_name);
+                    continue;
+                }
+                paid = inventory.block_cost(*block_id, &block.cost);
+                if !inventory.has_items(&paid) {
+                    println!(
+                        "[Build] {} costs {}",
+                        block.type_name,
+                        item::describe_cost(&paid, &strings)
+                    );
+                    continue;
+                }
+                inventory.remove_items(&paid);
+                used_voucher = inventory.use_voucher(*block_id);
+            }
+
+            // In construction time mode, the block is built later by a construction drone
+            if queued {
+                let pushed = queue.push(
+                    &lazy_update,
+                    &entities,
+                    &blocks,
+                    &block_entities,
+                    (ship_entity, ship),
+                    *pos,
+                    *block_id,
+                    &paid,
+                );
+                if !pushed {
+                    println!("[Build] ({}, {}) already has a pending build", pos.x, pos.y);
+                    inventory.add_items(&paid);
+                    if used_voucher {
+                        inventory.add_voucher(*block_id);
+                    }
+                    continue;
+                }
+            }
+
+            // Only count the blocks that the player built
+            if matches!(source, BuildSource::Player | BuildSource::Prepaid) {
+                stat_events.push(StatEvent::BlockPlaced(block.type_name));
+            }
+            if queued {
+                continue;
+            }
+        }
+
         match action {
             BuildAction::BuildBlock(pos, block_id) if blocks.get_block(*block_id).is_gadget => {
                 let block = blocks.get_block(*block_id);
@@ -67,15 +651,17 @@ pub fn execute_build_actions(world: &mut World, ship: Entity, actions: &[BuildAc
                     .with(Transform::from_position(
                         pos.x as f32,
                         pos.y as f32,
-                        base.height,
+                        Ship::deck_z(pos.z) + base.height,
                     ))
+                    .with(Faction::Player)
                     .with(Collider::new(
                         block.hitbox.clone(),
                         Collider::SHIP,
-                        vec![Collider::ASTEROID],
+                        &[Collider::ASTEROID],
                     ));
+                let entity_builder = animation::with_animation(entity_builder, &block.nodes);
                 let entity = if let Some(setup) = block.setup {
-                    (setup)(entity_builder).build()
+                    (setup)(entity_builder, block).build()
                 } else {
                     entity_builder.build()
                 };
@@ -84,6 +670,7 @@ pub fn execute_build_actions(world: &mut World, ship: Entity, actions: &[BuildAc
                     .get_mut(pos)
                     .expect("Placed block outside ship boundries")
                     .gadget = Some(entity);
+                ship.layout_revision += 1;
             }
             BuildAction::BuildBlock(pos, block_id) => {
                 let block = blocks.get_block(*block_id);
@@ -99,14 +686,21 @@ pub fn execute_build_actions(world: &mut World, ship: Entity, actions: &[BuildAc
                         block_id: *block_id,
                         root: *pos,
                     })
-                    .with(Transform::from_position(pos.x as f32, pos.y as f32, 0.0))
+                    .with(Transform::from_position(
+                        pos.x as f32,
+                        pos.y as f32,
+                        Ship::deck_z(pos.z),
+                    ))
+                    .with(Health::new(Ship::BLOCK_HEALTH))
+                    .with(Faction::Player)
                     .with(Collider::new(
                         block.hitbox.clone(),
                         Collider::SHIP,
-                        vec![Collider::ASTEROID],
+                        &[Collider::ASTEROID],
                     ));
+                let entity_builder = animation::with_animation(entity_builder, &block.nodes);
                 let block_entity = if let Some(setup) = block.setup {
-                    (setup)(entity_builder).build()
+                    (setup)(entity_builder, block).build()
                 } else {
                     entity_builder.build()
                 };
@@ -115,48 +709,178 @@ pub fn execute_build_actions(world: &mut World, ship: Entity, actions: &[BuildAc
                     .get_mut(pos)
                     .expect("Placed block outside ship boundries")
                     .block = Some(block_entity);
+                placed.insert(*pos, *block_id);
+                ship.mark_changed(*pos);
             }
             BuildAction::BuildFloor(pos, floor) => {
                 let tile_entity = lazy_update
                     .create_entity(&entities)
-                    .with(Model::new((*floor).into()))
-                    .with(Transform::from_position(pos.x as f32, pos.y as f32, 0.0))
+                    .with(Model::new(floors.get(*floor).mesh_id))
+                    .with(Transform::from_position(
+                        pos.x as f32,
+                        pos.y as f32,
+                        Ship::deck_z(pos.z),
+                    ))
                     .build();
 
                 ship.tiles
                     .get_mut(pos)
                     .expect("Placed floor outside ship boundries")
-                    .block = Some(tile_entity);
+                    .floor = Some((tile_entity, *floor));
+                ship.layout_revision += 1;
+            }
+            BuildAction::RemoveBlock(pos) => {
+                let tile = ship
+                    .tiles
+                    .get_mut(pos)
+                    .expect("Removed block outside ship boundries");
+                // The gadget is built on the block, so it goes first
+                if let Some(gadget) = tile.gadget.take() {
+                    to_be_removed.add(gadget);
+                    ship.layout_revision += 1;
+                } else if let Some(block) = tile.block.take() {
+                    to_be_removed.add(block);
+                    ship.mark_changed(*pos);
+                }
+            }
+            BuildAction::RemoveFloor(pos) => {
+                let tile = ship
+                    .tiles
+                    .get_mut(pos)
+                    .expect("Removed floor outside ship boundries");
+                if let Some((floor, _)) = tile.floor.take() {
+                    to_be_removed.add(floor);
+                    ship.layout_revision += 1;
+                    // The floor may have held part of the ship to the core
+                    ship.mark_structure_changed();
+                }
             }
-            _ => unimplemented!(),
         }
     }
+
+    ship.update_bounds();
 }
 
-pub fn create_ship(world: &mut World) {
+/// The tiles of every deck, from `min` up to (but not including) `max`
+fn empty_tiles(min: Point2<i16>, max: Point2<i16>) -> HashMap<Point3<i16>, Tile> {
     let mut tiles = HashMap::new();
+    for deck in 0..Ship::DECKS {
+        for x in min.x..max.x {
+            for y in min.y..max.y {
+                tiles.insert(
+                    Point3::new(x, y, deck),
+                    Tile {
+                        block: None,
+                        gadget: None,
+                        floor: None,
+                    },
+                );
+            }
+        }
+    }
+    tiles
+}
+
+pub fn create_ship(world: &mut World) {
     let initial_size = 32;
-    for x in -initial_size..initial_size {
-        for y in -initial_size..initial_size {
-            tiles.insert(
-                (x, y).into(),
-                Tile {
-                    block: None,
-                    gadget: None,
-                    floor: None,
-                },
-            );
+    let tiles = empty_tiles(
+        Point2::new(-initial_size, -initial_size),
+        Point2::new(initial_size, initial_size),
+    );
+    let checkpoint_ship = world.fetch::<WaveCheckpoint>().ship.clone();
+    match checkpoint_ship {
+        Some(shared) => {
+            let actions = shared
+                .blueprint
+                .build_actions(shared.origin, &world.fetch::<Blocks>());
+            let ship = spawn_ship(world, ShipKind::Mothership, tiles, Ship::CORE_POS, actions);
+            shared.blueprint.paint(world, ship, shared.origin);
+        }
+        None => {
+            let actions = build_initial_ship(world);
+            spawn_ship(world, ShipKind::Mothership, tiles, Ship::CORE_POS, actions);
+        }
+    }
+}
+
+/// Deploys a drone ship with the corner of its hull at `origin`. The tiles it can be built
+/// on are taken from the other ships, so they have to be empty.
+pub fn create_drone_ship(world: &mut World, origin: Point2<i16>) -> Result<Entity, String> {
+    let margin = Vector2::new(Ship::DRONE_MARGIN, Ship::DRONE_MARGIN);
+    let size = Vector2::new(Ship::DRONE_SIZE + 1, Ship::DRONE_SIZE + 1);
+    let tiles = empty_tiles(origin - margin, origin + size + margin);
+    {
+        let mut ships = world.write_component::<Ship>();
+        for ship in (&ships).join() {
+            let built = tiles.keys().find(|pos| {
+                matches!(ship.tile(**pos), Some(tile)
+                    if tile.block.is_some() || tile.gadget.is_some() || tile.floor.is_some())
+            });
+            if let Some(pos) = built {
+                return Err(format!(
+                    "The drone ship needs open space, but ({}, {}) is built on",
+                    pos.x, pos.y
+                ));
+            }
+        }
+        for ship in (&mut ships).join() {
+            ship.tiles.retain(|pos, _| !tiles.contains_key(pos));
         }
     }
 
-    let ship = world.create_entity().with(Ship { tiles }).build();
-    let (ship_build_actions, ship_build_gadgets) = build_initial_ship(&world);
+    let actions = build_drone_ship(world, origin);
+    let core_pos = Point3::new(origin.x + 1, origin.y + 1, 0);
+    Ok(spawn_ship(
+        world,
+        ShipKind::Drone { origin },
+        tiles,
+        core_pos,
+        actions,
+    ))
+}
+
+/// Creates a ship, and builds its blocks and then its gadgets. The block at `core_pos` is
+/// its core.
+fn spawn_ship(
+    world: &mut World,
+    kind: ShipKind,
+    tiles: HashMap<Point3<i16>, Tile>,
+    core_pos: Point3<i16>,
+    (actions, gadgets): (Vec<BuildAction>, Vec<BuildAction>),
+) -> Entity {
+    let ship = world
+        .create_entity()
+        .with(Ship {
+            kind,
+            tiles,
+            core: None,
+            bounds: None,
+            changed_tiles: Vec::new(),
+            structure_changed: false,
+            layout_revision: 0,
+            heat: ShipHeat::default(),
+            power: ShipPower::default(),
+            shield: ShipShield::default(),
+        })
+        .build();
 
-    execute_build_actions(world, ship, &ship_build_actions);
-    // execute_build_actions adds the entities lazily, so we need to maintain the world
+    // Spawned ships are free, and may use blocks that have not been researched
+    build(world, ship, &actions, BuildSource::InitialShip);
+    // build adds the entities lazily, so we need to maintain the world
     // in order to add the block entities
     world.maintain();
-    execute_build_actions(world, ship, &ship_build_gadgets);
+
+    let core = world.read_component::<Ship>().get(ship).unwrap().tiles[&core_pos]
+        .block
+        .expect("The ship has no core");
+    world
+        .write_component::<Health>()
+        .insert(core, Health::new(Ship::CORE_HEALTH))
+        .unwrap();
+    world.write_component::<Ship>().get_mut(ship).unwrap().core = Some(core);
+
+    build(world, ship, &gadgets, BuildSource::InitialShip);
+    ship
 }
 
 fn build_initial_ship(world: &World) -> (Vec<BuildAction>, Vec<BuildAction>) {
@@ -170,39 +894,78 @@ fn build_initial_ship(world: &World) -> (Vec<BuildAction>, Vec<BuildAction>) {
     for x in 0..=size {
         for y in 0..=size {
             if x == 0 || y == 0 || x == size || y == size {
-                ship.push(BuildAction::BuildBlock(Point2::new(x, y), blocks.wall));
+                ship.push(BuildAction::BuildBlock(Point3::new(x, y, 0), blocks.wall));
+            } else if Point3::new(x, y, 0) == Ship::CORE_POS {
+                ship.push(BuildAction::BuildBlock(Ship::CORE_POS, blocks.cube));
             } else if x == 3 && y == 3 {
-                ship.push(BuildAction::BuildFloor(Point2::new(3, 3), floors.dirt));
+                ship.push(BuildAction::BuildFloor(Point3::new(3, 3, 0), floors.dirt));
             } else {
-                ship.push(BuildAction::BuildFloor(Point2::new(x, y), floors.metal));
+                ship.push(BuildAction::BuildFloor(Point3::new(x, y, 0), floors.metal));
             }
         }
     }
     ship.push(BuildAction::BuildBlock(
-        Point2::new(size + 1, -2),
+        Point3::new(size + 1, -2, 0),
         blocks.engine,
     ));
     ship.push(BuildAction::BuildBlock(
-        Point2::new(size + 1, size + 2),
+        Point3::new(size + 1, size + 2, 0),
         blocks.engine,
     ));
-    ship.push(BuildAction::BuildBlock(Point2::new(size, -2), blocks.cube));
-    ship.push(BuildAction::BuildBlock(Point2::new(size, -1), blocks.cube));
     ship.push(BuildAction::BuildBlock(
-        Point2::new(size, size + 1),
+        Point3::new(size, -2, 0),
         blocks.cube,
     ));
     ship.push(BuildAction::BuildBlock(
-        Point2::new(size, size + 2),
+        Point3::new(size, -1, 0),
         blocks.cube,
     ));
     ship.push(BuildAction::BuildBlock(
-        Point2::new(-1, size / 2),
+        Point3::new(size, size + 1, 0),
+        blocks.cube,
+    ));
+    ship.push(BuildAction::BuildBlock(
+        Point3::new(size, size + 2, 0),
+        blocks.cube,
+    ));
+    ship.push(BuildAction::BuildBlock(
+        Point3::new(-1, size / 2, 0),
         blocks.miner,
     ));
+    ship.push(BuildAction::BuildBlock(
+        Point3::new(-1, size / 2 + 2, 0),
+        blocks.cooler,
+    ));
 
-    gadgets.push(BuildAction::BuildBlock(Point2::new(-5, 5), blocks.laser));
-    gadgets.push(BuildAction::BuildBlock(Point2::new(0, 0), blocks.laser));
+    gadgets.push(BuildAction::BuildBlock(Point3::new(-5, 5, 0), blocks.laser));
+    gadgets.push(BuildAction::BuildBlock(Point3::new(0, 0, 0), blocks.laser));
+
+    (ship, gadgets)
+}
+
+/// A hull of walls around the core and a floor, with a laser on one corner
+fn build_drone_ship(world: &World, origin: Point2<i16>) -> (Vec<BuildAction>, Vec<BuildAction>) {
+    let blocks = world.fetch::<Blocks>();
+    let floors = world.fetch::<Floors>();
+    let size = Ship::DRONE_SIZE;
+
+    let mut ship = Vec::new();
+    for x in 0..=size {
+        for y in 0..=size {
+            let pos = Point3::new(origin.x + x, origin.y + y, 0);
+            if x == 0 || y == 0 || x == size || y == size {
+                ship.push(BuildAction::BuildBlock(pos, blocks.wall));
+            } else if x == 1 && y == 1 {
+                ship.push(BuildAction::BuildBlock(pos, blocks.cube));
+            } else {
+                ship.push(BuildAction::BuildFloor(pos, floors.metal));
+            }
+        }
+    }
+    let gadgets = vec![BuildAction::BuildBlock(
+        Point3::new(origin.x, origin.y, 0),
+        blocks.laser,
+    )];
 
     (ship, gadgets)
 }
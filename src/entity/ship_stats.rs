@@ -0,0 +1,286 @@
+//! What a ship's layout adds up to (ex. its mass or how much heat it can get rid of), for the
+//! ship stats panel. The stats only depend on the built blocks and their health, so they are
+//! cached until either changes.
+
+use super::{heat, objects::Health, power::ShipPower, BlockEntity, Model, Ship};
+use crate::block::{BlockId, Blocks};
+use crate::floor::Floors;
+use crate::item::GameItem;
+use cgmath::Point3;
+use specs::prelude::*;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ShipStats {
+    /// How many of each kind of block and gadget are built, the most first
+    pub block_counts: Vec<(BlockId, u32)>,
+    pub health: u32,
+    pub max_health: u32,
+    /// The heat generated each second if every gadget were used all the time
+    pub worst_case_heat: f32,
+    /// The heat removed each second while every cooler is powered
+    pub dissipation: f32,
+    /// The power produced and needed each second while every block works
+    pub power_supply: f32,
+    pub power_demand: f32,
+    pub mass: f32,
+    /// The force of all of the engines together
+    pub thrust: f32,
+    /// What the blocks, gadgets and floors of the ship cost to build, all together
+    pub cost: Vec<(GameItem, u32)>,
+}
+
+impl ShipStats {
+    /// The force each engine pushes with
+    pub const ENGINE_THRUST: f32 = 12.0;
+
+    /// Adds up the blocks, gadgets and floors of the ship, or returns None if there is no
+    /// such ship. Gadgets are treated as if each use lasted a second (ex. a beam on for a
+    /// second, or a shot each second).
+    pub fn measure(world: &World, ship: Entity) -> Option<ShipStats> {
+        let ships = world.read_component::<Ship>();
+        let ship = ships.get(ship)?;
+        let blocks = world.fetch::<Blocks>();
+        let floors = world.fetch::<Floors>();
+        let block_entities = world.read_component::<BlockEntity>();
+        let models = world.read_component::<Model>();
+        let healths = world.read_component::<Health>();
+
+        let mut stats = ShipStats {
+            power_supply: ship.core().map_or(0.0, |_| ShipPower::CORE_PRODUCTION),
+            ..ShipStats::default()
+        };
+        let mut coolers: Vec<(Point3<i16>, f32)> = Vec::new();
+        for (pos, entity, block_id) in ship.block_kinds(&blocks, &block_entities, &models) {
+            let block = blocks.get_block(block_id);
+            match stats
+                .block_counts
+                .iter_mut()
+                .find(|(id, _)| *id == block_id)
+            {
+                Some((_, count)) => *count += 1,
+                None => stats.block_counts.push((block_id, 1)),
+            }
+            if let Some(health) = healths.get(entity) {
+                stats.health += health.health();
+                stats.max_health += health.max_health();
+            }
+
+            stats.worst_case_heat += block.heat.idle + block.heat.per_use;
+            if block.heat.dissipation > 0.0 {
+                coolers.push((pos, block.heat.dissipation));
+            }
+            stats.power_supply += block.power.production;
+            stats.power_demand += block.power.consumption;
+            stats.mass += block.mass;
+            if block_id == blocks.engine {
+                stats.thrust += ShipStats::ENGINE_THRUST;
+            }
+            add_cost(&mut stats.cost, &block.cost);
+        }
+
+        let mut vents = 0.0;
+        for floor in ship.tiles().filter_map(|(_, tile)| tile.floor_type()) {
+            let floor = floors.get(floor);
+            vents += floor.properties.dissipation;
+            add_cost(&mut stats.cost, &floor.cost);
+        }
+        stats.dissipation = heat::total_dissipation(&coolers, vents);
+
+        stats
+            .block_counts
+            .sort_by(|(a_id, a), (b_id, b)| b.cmp(a).then(a_id.cmp(b_id)));
+        stats.cost.sort_by_key(|(item, _)| *item as usize);
+        Some(stats)
+    }
+
+    /// The thrust for each unit of mass, which is how fast the ship could speed up
+    pub fn thrust_to_mass(&self) -> f32 {
+        if self.mass > 0.0 {
+            self.thrust / self.mass
+        } else {
+            0.0
+        }
+    }
+}
+
+fn add_cost(total: &mut Vec<(GameItem, u32)>, cost: &[(GameItem, u32)]) {
+    for (item, amount) in cost {
+        match total.iter_mut().find(|(other, _)| other == item) {
+            Some((_, total)) => *total += amount,
+            None => total.push((*item, *amount)),
+        }
+    }
+}
+
+/// The stats of the last ship that was measured. They are measured again once the ship is
+/// built on, or once a block on any ship is damaged or repaired.
+#[derive(Default)]
+pub struct ShipStatsCache {
+    /// The ship, its layout revision when it was measured, and its stats
+    cached: Option<(Entity, u32, ShipStats)>,
+    health_changed: bool,
+}
+
+impl ShipStatsCache {
+    /// Called by whatever changes the health of a block (ex. the DamageSystem)
+    pub fn mark_health_changed(&mut self) {
+        self.health_changed = true;
+    }
+
+    /// The stats of the ship, which are only measured if they are out of date
+    pub fn stats(world: &World, ship: Entity) -> Option<ShipStats> {
+        let revision = world.read_component::<Ship>().get(ship)?.layout_revision();
+        {
+            let cache = world.fetch::<ShipStatsCache>();
+            match &cache.cached {
+                Some((cached, cached_revision, stats))
+                    if *cached == ship && *cached_revision == revision && !cache.health_changed =>
+                {
+                    return Some(stats.clone());
+                }
+                _ => {}
+            }
+        }
+
+        let stats = ShipStats::measure(world, ship)?;
+        let mut cache = world.fetch_mut::<ShipStatsCache>();
+        cache.cached = Some((ship, revision, stats.clone()));
+        cache.health_changed = false;
+        Some(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::damage::{DamageEvent, DamageEvents, DamageSource};
+    use crate::entity::headless::HeadlessEcs;
+    use cgmath::Vector3;
+
+    fn measure(headless: &HeadlessEcs) -> ShipStats {
+        ShipStats::measure(&headless.ecs.world, headless.mothership()).unwrap()
+    }
+
+    fn count(stats: &ShipStats, block_id: BlockId) -> u32 {
+        stats
+            .block_counts
+            .iter()
+            .find(|(id, _)| *id == block_id)
+            .map_or(0, |(_, count)| *count)
+    }
+
+    fn max_health_of(headless: &HeadlessEcs, entity: Entity) -> u32 {
+        let healths = headless.ecs.world.read_storage::<Health>();
+        healths.get(entity).map_or(0, Health::max_health)
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn stats_add_up_the_built_blocks() {
+        let mut headless = HeadlessEcs::with_seed(1);
+        headless.pause_asteroid_field();
+        // The starting gadgets are only created once the world is maintained
+        headless.step(1);
+        let before = measure(&headless);
+        assert!(before.max_health > 0);
+        assert_eq!(before.health, before.max_health);
+
+        let built = [
+            headless.build_block(Point3::new(7, 3, 0), "Laser"),
+            // Far enough from the starting ship's cooler that they don't crowd each other
+            headless.build_block(Point3::new(-1, 1, 0), "Cooler"),
+            headless.build_block(Point3::new(-2, -1, 0), "engine"),
+        ];
+        let after = measure(&headless);
+        let blocks = headless.ecs.get_resource::<Blocks>();
+        let added = [blocks.laser, blocks.cooler, blocks.engine];
+
+        for block_id in added.iter() {
+            assert_eq!(count(&after, *block_id), count(&before, *block_id) + 1);
+        }
+        let total =
+            |stats: &ShipStats| -> u32 { stats.block_counts.iter().map(|(_, count)| count).sum() };
+        assert_eq!(total(&after), total(&before) + 3);
+        assert!(after
+            .block_counts
+            .windows(2)
+            .all(|pair| pair[0].1 >= pair[1].1));
+
+        let added_health: u32 = built
+            .iter()
+            .map(|entity| max_health_of(&headless, *entity))
+            .sum();
+        assert_eq!(after.max_health, before.max_health + added_health);
+        assert_eq!(after.health, after.max_health);
+
+        let added = added.iter().map(|id| blocks.get_block(*id));
+        let heat: f32 = added
+            .clone()
+            .map(|block| block.heat.idle + block.heat.per_use)
+            .sum();
+        assert_close(after.worst_case_heat, before.worst_case_heat + heat);
+        let cooling = blocks.get_block(blocks.cooler).heat.dissipation;
+        assert_close(after.dissipation, before.dissipation + cooling);
+
+        let mass: f32 = added.map(|block| block.mass).sum();
+        assert_close(after.mass, before.mass + mass);
+        assert_close(after.thrust, before.thrust + ShipStats::ENGINE_THRUST);
+        assert_close(after.thrust_to_mass(), after.thrust / after.mass);
+        assert!(after.thrust_to_mass() > before.thrust_to_mass());
+    }
+
+    #[test]
+    fn cache_is_measured_again_after_building_or_damage() {
+        let mut headless = HeadlessEcs::with_seed(1);
+        headless.pause_asteroid_field();
+        headless.step(1);
+        let ship = headless.mothership();
+        let stats = |headless: &HeadlessEcs| ShipStatsCache::stats(&headless.ecs.world, ship);
+        let first = stats(&headless).unwrap();
+        assert_eq!(first, measure(&headless));
+
+        // A change the cache isn't told about keeps the cached stats
+        let block = headless
+            .with_ship(|ship| ship.tile(Point3::new(0, 1, 0))?.block())
+            .flatten()
+            .unwrap();
+        headless
+            .ecs
+            .world
+            .write_storage::<Health>()
+            .get_mut(block)
+            .unwrap()
+            .damage(1, Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(stats(&headless).unwrap(), first);
+
+        // Damage from the DamageSystem measures the ship again
+        headless
+            .ecs
+            .get_resource_mut::<DamageEvents>()
+            .push(DamageEvent::new(
+                block,
+                10,
+                DamageSource::AsteroidImpact,
+                Vector3::new(0.0, 1.0, 0.5),
+            ));
+        headless.step(1);
+        let damaged = stats(&headless).unwrap();
+        assert!(damaged.health < first.health - 1, "{:?}", damaged);
+        assert_eq!(damaged, measure(&headless));
+
+        // So does building on the ship
+        headless.build_block(Point3::new(7, 3, 0), "Laser");
+        let built = stats(&headless).unwrap();
+        let laser = headless.ecs.get_resource::<Blocks>().laser;
+        assert_eq!(count(&built, laser), count(&damaged, laser) + 1);
+        assert_eq!(built, measure(&headless));
+    }
+}
@@ -0,0 +1,459 @@
+use super::{
+    damage::{DamageEvent, DamageEvents, DamageSource},
+    gameplay::GameLog,
+    hud::HudVerbosity,
+    objects::Health,
+    BlockEntity, GameModule, Model, ParticleParams, Particles, Ship, Time, Transform,
+};
+use crate::block::Blocks;
+use crate::graphics::{MeshManager, ModelTint};
+use crate::i18n::Strings;
+use crate::profiler::AddProfiled;
+use cgmath::Vector3;
+use specs::{prelude::*, Component};
+
+/// The status effects of the ship's blocks and gadgets
+pub struct StatusModule;
+
+impl GameModule for StatusModule {
+    fn register(&self, world: &mut World) {
+        world.register::<StatusEffects>();
+        world.register::<Health>();
+        world.insert(StatusEvents::default());
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(StatusSystem, "status_system", &["raider_system"]);
+    }
+
+    fn init(&self, world: &mut World) {
+        world.insert(StatusEvents::default());
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusKind {
+    /// Damages the block every second, and spreads to a neighbouring block if it is not
+    /// put out in time
+    Burning,
+    /// The gadget acts as if it was unpowered
+    Emp,
+}
+
+impl StatusKind {
+    pub fn name_key(self) -> &'static str {
+        match self {
+            StatusKind::Burning => "status.burning",
+            StatusKind::Emp => "status.emp",
+        }
+    }
+
+    /// Stacking never raises the magnitude above this
+    fn max_magnitude(self) -> f32 {
+        match self {
+            StatusKind::Burning => 2.0,
+            StatusKind::Emp => 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    /// The seconds until the effect wears off
+    pub time_left: f32,
+    /// How strong the effect is (ex. the damage per second of Burning)
+    pub magnitude: f32,
+    /// The seconds since the effect was first applied. Refreshing the effect keeps its age.
+    age: f32,
+    /// Burning only spreads once, so a fire grows one block at a time
+    spread: bool,
+    /// The fraction of a point of damage that has not been dealt yet
+    damage: f32,
+}
+
+impl StatusEffect {
+    pub fn new(kind: StatusKind, duration: f32, magnitude: f32) -> Self {
+        Self {
+            kind,
+            time_left: duration,
+            magnitude: magnitude.min(kind.max_magnitude()),
+            age: 0.0,
+            spread: false,
+            damage: 0.0,
+        }
+    }
+}
+
+/// The active status effects of an entity. Each kind is only active once, applying it
+/// again stacks onto the active effect.
+#[derive(Component, Default)]
+#[storage(HashMapStorage)]
+pub struct StatusEffects {
+    effects: [Option<StatusEffect>; StatusEffects::MAX_EFFECTS],
+}
+
+impl StatusEffects {
+    const MAX_EFFECTS: usize = 2;
+
+    /// Applies the effect. If the kind is already active, its duration is refreshed (to the
+    /// longer of the two) and the magnitudes are added, up to the kind's max magnitude.
+    pub fn apply(&mut self, effect: StatusEffect) {
+        if let Some(active) = self.get_mut(effect.kind) {
+            active.time_left = active.time_left.max(effect.time_left);
+            active.magnitude =
+                (active.magnitude + effect.magnitude).min(effect.kind.max_magnitude());
+            return;
+        }
+
+        // Without a free slot, the effect that would wear off first is replaced
+        let slot = self
+            .effects
+            .iter_mut()
+            .min_by(|a, b| {
+                let a = a.map_or(f32::NEG_INFINITY, |effect| effect.time_left);
+                let b = b.map_or(f32::NEG_INFINITY, |effect| effect.time_left);
+                a.partial_cmp(&b).unwrap()
+            })
+            .unwrap();
+        *slot = Some(effect);
+    }
+
+    pub fn get(&self, kind: StatusKind) -> Option<&StatusEffect> {
+        self.iter().find(|effect| effect.kind == kind)
+    }
+
+    fn get_mut(&mut self, kind: StatusKind) -> Option<&mut StatusEffect> {
+        self.effects
+            .iter_mut()
+            .flatten()
+            .find(|effect| effect.kind == kind)
+    }
+
+    pub fn has(&self, kind: StatusKind) -> bool {
+        self.get(kind).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &StatusEffect> {
+        self.effects.iter().flatten()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+}
+
+/// Whether the gadget is disabled by an EMP. Gadget systems skip disabled gadgets, as
+/// if they were unpowered.
+pub fn is_emped(statuses: &ReadStorage<StatusEffects>, entity: Entity) -> bool {
+    matches!(statuses.get(entity), Some(effects) if effects.has(StatusKind::Emp))
+}
+
+/// The status effects applied by weapons and hazards during the current update. They
+/// are applied by the StatusSystem, like damage is applied before its DamageEvents.
+#[derive(Default)]
+pub struct StatusEvents {
+    events: Vec<(Entity, StatusEffect)>,
+}
+
+impl StatusEvents {
+    pub fn push(&mut self, target: Entity, effect: StatusEffect) {
+        self.events.push((target, effect));
+    }
+}
+
+/// Applies the new status effects, burns the burning blocks, and tints the affected models
+pub struct StatusSystem;
+
+impl StatusSystem {
+    /// The seconds a block needs to burn before the fire spreads to a neighbour
+    const SPREAD_TIME: f32 = 4.0;
+    /// The effect that a spreading fire starts with. It is shorter than the SPREAD_TIME, so
+    /// fires only keep spreading while the ship is being hit.
+    const SPREAD_DURATION: f32 = 3.0;
+    const SPREAD_MAGNITUDE: f32 = 0.5;
+    /// The seconds between the fire particles of a burning block
+    const FIRE_PARTICLE_TIME: f32 = 0.15;
+    const FIRE_PARTICLES: ParticleParams = ParticleParams {
+        color: Vector3::new(1.0, 0.5, 0.1),
+        speed: 0.8,
+        lifetime: 0.6,
+        size: 0.12,
+    };
+    /// Brighter than the mesh, so burning blocks look like they glow
+    const BURNING_TINT: Vector3<f32> = Vector3::new(1.6, 0.8, 0.4);
+    const EMP_TINT: Vector3<f32> = Vector3::new(0.5, 0.7, 1.6);
+    /// How many times a second the EMP tint flickers
+    const EMP_FLICKER_RATE: f32 = 6.0;
+    /// Fires spread along their deck
+    const NEIGHBOURS: [Vector3<i16>; 4] = [
+        Vector3::new(1, 0, 0),
+        Vector3::new(-1, 0, 0),
+        Vector3::new(0, 1, 0),
+        Vector3::new(0, -1, 0),
+    ];
+
+    /// The tint of a model with the effects, or None if the effects do not change it
+    fn tint(effects: &StatusEffects) -> Option<ModelTint> {
+        let emp = effects.get(StatusKind::Emp).filter(|effect| {
+            (effect.age * StatusSystem::EMP_FLICKER_RATE * crate::PI * 2.0).sin() > 0.0
+        });
+        if emp.is_some() {
+            Some(ModelTint::new(
+                StatusSystem::EMP_TINT,
+                StatusSystem::EMP_TINT,
+            ))
+        } else if effects.has(StatusKind::Burning) {
+            Some(ModelTint::new(
+                StatusSystem::BURNING_TINT,
+                StatusSystem::BURNING_TINT,
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> System<'a> for StatusSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Time>,
+        ReadExpect<'a, Blocks>,
+        ReadExpect<'a, HudVerbosity>,
+        ReadExpect<'a, Strings>,
+        WriteExpect<'a, StatusEvents>,
+        WriteExpect<'a, DamageEvents>,
+        WriteExpect<'a, Particles>,
+        WriteExpect<'a, MeshManager>,
+        WriteExpect<'a, GameLog>,
+        WriteStorage<'a, StatusEffects>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, Model>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, BlockEntity>,
+        ReadStorage<'a, Ship>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            time,
+            blocks,
+            verbosity,
+            strings,
+            mut events,
+            mut damage_events,
+            mut particles,
+            mut mesh_manager,
+            mut log,
+            mut statuses,
+            healths,
+            models,
+            transforms,
+            block_entities,
+            ships,
+        ) = data;
+
+        for (target, effect) in events.events.drain(..) {
+            if !entities.is_alive(target) {
+                continue;
+            }
+            let started =
+                !matches!(statuses.get(target), Some(effects) if effects.has(effect.kind));
+            if let Ok(entry) = statuses.entry(target) {
+                entry.or_insert_with(StatusEffects::default).apply(effect);
+            }
+
+            let block = block_entities
+                .get(target)
+                .map(|block_entity| blocks.get_block(block_entity.block_id()));
+            if let Some(block) = block.filter(|_| started && *verbosity != HudVerbosity::Minimal) {
+                let key = match effect.kind {
+                    StatusKind::Burning => "log.block_burning",
+                    StatusKind::Emp => "log.block_emp",
+                };
+                log.add(strings.format(key, &[("block", &strings.get(block.name_key))]));
+            }
+        }
+
+        let mut spreading = Vec::new();
+        let mut cleared = Vec::new();
+        for (entity, effects) in (&entities, &mut statuses).join() {
+            let position = transforms.get(entity).map(|transform| transform.position);
+
+            for slot in &mut effects.effects {
+                let effect = match slot {
+                    Some(effect) => effect,
+                    None => continue,
+                };
+                effect.age += time.delta;
+
+                if effect.kind == StatusKind::Burning {
+                    effect.damage += effect.magnitude * time.delta;
+                    let amount = (effect.damage + Time::SLACK).floor();
+                    effect.damage -= amount;
+                    if let Some(position) = position {
+                        damage_events.push(DamageEvent::new(
+                            entity,
+                            amount as u32,
+                            DamageSource::Heat,
+                            position,
+                        ));
+                    }
+
+                    let previous_age = effect.age - time.delta;
+                    let particle_time = StatusSystem::FIRE_PARTICLE_TIME;
+                    if let Some(position) = position {
+                        if (effect.age / particle_time).floor()
+                            > (previous_age / particle_time).floor()
+                        {
+                            let top = position + Vector3::new(0.0, 0.0, 0.8);
+                            particles.emit_burst(top, 2, &StatusSystem::FIRE_PARTICLES);
+                        }
+                    }
+
+                    if !effect.spread && effect.age >= StatusSystem::SPREAD_TIME {
+                        effect.spread = true;
+                        spreading.push(entity);
+                    }
+                }
+
+                if time.count_down(&mut effect.time_left) {
+                    *slot = None;
+                }
+            }
+
+            // Once the effects wear off, the model's own tint is restored
+            if let Some(model) = models.get(entity) {
+                if let Some(model_id) = model.model_id {
+                    let tint = StatusSystem::tint(effects).unwrap_or(model.tint);
+                    mesh_manager.update_tint(model.mesh_id, model_id, tint);
+                }
+            }
+            if effects.is_empty() {
+                cleared.push(entity);
+            }
+        }
+        for entity in cleared {
+            statuses.remove(entity);
+        }
+
+        // Fires spread to the first neighbour on the same ship that is not burning yet
+        for entity in spreading {
+            let root = match block_entities.get(entity) {
+                Some(block_entity) => block_entity.root(),
+                None => continue,
+            };
+            let ship = match ships.join().find(|ship| ship.has_block(root, entity)) {
+                Some(ship) => ship,
+                None => continue,
+            };
+            let can_burn = |block: Entity| {
+                let burning = statuses
+                    .get(block)
+                    .map(|effects| effects.has(StatusKind::Burning));
+                matches!(healths.get(block), Some(health) if health.health() > 0)
+                    && burning != Some(true)
+            };
+            let neighbour = StatusSystem::NEIGHBOURS
+                .iter()
+                .filter_map(|offset| ship.tile(root + offset)?.block())
+                .find(|block| can_burn(*block));
+
+            if let Some(neighbour) = neighbour {
+                events.push(
+                    neighbour,
+                    StatusEffect::new(
+                        StatusKind::Burning,
+                        StatusSystem::SPREAD_DURATION,
+                        StatusSystem::SPREAD_MAGNITUDE,
+                    ),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{headless::HeadlessEcs, Tile};
+    use cgmath::Point3;
+
+    #[test]
+    fn stacking_adds_magnitudes_up_to_the_cap() {
+        let mut effects = StatusEffects::default();
+        effects.apply(StatusEffect::new(StatusKind::Burning, 5.0, 1.5));
+        effects.apply(StatusEffect::new(StatusKind::Burning, 2.0, 1.5));
+        let burning = effects.get(StatusKind::Burning).unwrap();
+        assert_eq!(burning.magnitude, StatusKind::Burning.max_magnitude());
+        assert_eq!(effects.iter().count(), 1);
+
+        // A single effect is capped too
+        effects.apply(StatusEffect::new(StatusKind::Emp, 5.0, 3.0));
+        let emp = effects.get(StatusKind::Emp).unwrap();
+        assert_eq!(emp.magnitude, StatusKind::Emp.max_magnitude());
+        assert_eq!(effects.iter().count(), 2);
+    }
+
+    #[test]
+    fn refreshing_keeps_the_longer_duration_and_the_age() {
+        let mut effects = StatusEffects::default();
+        effects.apply(StatusEffect::new(StatusKind::Burning, 2.0, 0.5));
+        effects.get_mut(StatusKind::Burning).unwrap().age = 1.0;
+
+        effects.apply(StatusEffect::new(StatusKind::Burning, 6.0, 0.5));
+        let burning = effects.get(StatusKind::Burning).unwrap();
+        assert_eq!(burning.time_left, 6.0);
+        assert_eq!(burning.age, 1.0);
+
+        effects.apply(StatusEffect::new(StatusKind::Burning, 1.0, 0.5));
+        assert_eq!(effects.get(StatusKind::Burning).unwrap().time_left, 6.0);
+    }
+
+    fn burning_blocks(headless: &HeadlessEcs) -> Vec<Entity> {
+        let world = &headless.ecs.world;
+        (&world.entities(), &world.read_storage::<StatusEffects>())
+            .join()
+            .filter(|(_, effects)| effects.has(StatusKind::Burning))
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+
+    #[test]
+    fn fire_spreads_once_to_a_neighbour() {
+        let mut headless = HeadlessEcs::with_seed(1);
+        headless.pause_asteroid_field();
+        let wall = Point3::new(0, 3, 0);
+        let block_at = |headless: &HeadlessEcs, pos: Point3<i16>| {
+            headless
+                .with_ship(|ship| ship.tile(pos).and_then(Tile::block))
+                .flatten()
+        };
+        let burning = block_at(&headless, wall).unwrap();
+        // Weak enough that the wall survives, and long enough to spread twice
+        let fire = StatusEffect::new(StatusKind::Burning, 20.0, 0.1);
+        headless
+            .ecs
+            .get_resource_mut::<StatusEvents>()
+            .push(burning, fire);
+
+        let ticks = |seconds: f32| (seconds * Time::DEFAULT_RATE as f32) as u32;
+        headless.step(ticks(StatusSystem::SPREAD_TIME) - 1);
+        assert_eq!(burning_blocks(&headless), vec![burning]);
+
+        // The spread fire is applied in the next update
+        headless.step(3);
+        let spread = burning_blocks(&headless);
+        assert_eq!(spread.len(), 2);
+        let neighbours: Vec<_> = StatusSystem::NEIGHBOURS
+            .iter()
+            .filter_map(|offset| block_at(&headless, wall + offset))
+            .collect();
+        assert!(spread.iter().any(|block| neighbours.contains(block)));
+
+        // The first fire already spread, and the spread fire goes out before it can
+        headless.step(ticks(StatusSystem::SPREAD_TIME));
+        assert_eq!(burning_blocks(&headless), vec![burning]);
+    }
+}
@@ -0,0 +1,408 @@
+//! Shares the targets of the miners in auto mode, so they don't all fire at the same asteroid.
+//! The damage of the missiles in flight is counted against their targets, and each miner is
+//! assigned a target that still needs more of it.
+
+use super::{
+    faction::{self, Faction},
+    gameplay::{AsteroidField, GameLog},
+    heat,
+    integrity::Derelict,
+    objects::{Asteroid, Health, MiningMissle},
+    power,
+    raider::Raider,
+    status::{self, StatusEffects},
+    wear::Wear,
+    InputManager, RigidBody, Ship, Transform,
+};
+use crate::block::Miner;
+use crate::i18n::Strings;
+use cgmath::{InnerSpace, Vector3, Zero};
+use specs::prelude::*;
+use std::collections::HashMap;
+
+/// The targets assigned to the miners in auto mode, and the damage that the missiles in
+/// flight will do to each target. Both are worked out again every update (see
+/// assign_targets), so missiles that hit, miss or die, and targets that die, drop out on
+/// their own.
+#[derive(Default)]
+pub struct TargetAssignment {
+    assigned: HashMap<Entity, Entity>,
+    incoming: HashMap<Entity, u32>,
+    /// The health of each target that is not used up yet, while the targets are assigned.
+    /// Kept between updates so it is not allocated every update.
+    remaining: HashMap<Entity, u32>,
+}
+
+impl TargetAssignment {
+    /// The target of a miner in auto mode
+    pub fn target_of(&self, miner: Entity) -> Option<Entity> {
+        self.assigned.get(&miner).copied()
+    }
+
+    /// The damage that the missiles in flight will do to the target
+    pub fn incoming_damage(&self, target: Entity) -> u32 {
+        self.incoming.get(&target).copied().unwrap_or(0)
+    }
+}
+
+/// A miner in auto mode that can fire
+#[derive(Clone, Copy, Debug)]
+pub struct MinerSnapshot {
+    pub entity: Entity,
+    /// Where its missiles are launched from
+    pub position: Vector3<f32>,
+    /// The seconds until it can fire again
+    pub reload: f32,
+}
+
+/// Something that the miners can fire at
+#[derive(Clone, Copy, Debug)]
+pub struct TargetSnapshot {
+    pub entity: Entity,
+    pub position: Vector3<f32>,
+    pub velocity: Vector3<f32>,
+    pub health: u32,
+    /// The damage each missile does to it. Asteroids are destroyed by any missile, so
+    /// theirs is their health.
+    pub missile_damage: u32,
+}
+
+/// How much each second until a target leaves the field adds to its score, like a distance.
+/// The targets that are about to leave score lower, so they are mined before they are lost.
+const LEAVING_WEIGHT: f32 = 0.5;
+/// The most seconds until leaving the field that counts for the score, so the targets that
+/// are far from the end of the field are only scored by their distance
+const MAX_LEAVING_TIME: f32 = 20.0;
+
+/// Assigns each miner a target whose health is not used up by the missiles in flight (and the
+/// ones the miners before it were assigned). The miners that are ready to fire are assigned
+/// first. Closer targets are preferred, and so are the ones that will leave the field soon,
+/// since they can't be mined afterwards. The damage of the missiles in flight is kept in the
+/// assignment too. The miners are sorted in the order they were assigned.
+pub fn assign_targets(
+    miners: &mut [MinerSnapshot],
+    targets: &[TargetSnapshot],
+    missiles: &[Entity],
+    x_range: f32,
+    assignment: &mut TargetAssignment,
+) {
+    let TargetAssignment {
+        assigned,
+        incoming,
+        remaining,
+    } = assignment;
+    assigned.clear();
+    incoming.clear();
+    remaining.clear();
+
+    for target in missiles {
+        if let Some(snapshot) = targets.iter().find(|other| other.entity == *target) {
+            *incoming.entry(*target).or_insert(0) += snapshot.missile_damage;
+        }
+    }
+    remaining.extend(targets.iter().map(|target| {
+        let incoming = incoming.get(&target.entity).copied().unwrap_or(0);
+        (target.entity, target.health.saturating_sub(incoming))
+    }));
+
+    // Ties are broken by the entity ID, so the same targets are picked every update
+    miners.sort_unstable_by(|a, b| {
+        a.reload
+            .partial_cmp(&b.reload)
+            .unwrap()
+            .then(a.entity.id().cmp(&b.entity.id()))
+    });
+
+    for miner in miners.iter() {
+        let score = |target: &TargetSnapshot| {
+            let impact =
+                MiningMissle::predict_impact(miner.position, target.position, target.velocity)?;
+            if impact.x.abs() > x_range {
+                return None;
+            }
+            let leaving_time = if target.velocity.x > 0.0 {
+                (x_range - target.position.x) / target.velocity.x
+            } else {
+                f32::INFINITY
+            };
+            let distance = (target.position - miner.position).magnitude();
+            Some(distance + leaving_time.min(MAX_LEAVING_TIME) * LEAVING_WEIGHT)
+        };
+        let best = targets
+            .iter()
+            .filter(|target| remaining[&target.entity] > 0)
+            .filter_map(|target| Some((target, score(target)?)))
+            .min_by(|(a, a_score), (b, b_score)| {
+                a_score
+                    .partial_cmp(b_score)
+                    .unwrap()
+                    .then(a.entity.id().cmp(&b.entity.id()))
+            });
+        if let Some((target, _)) = best {
+            assigned.insert(miner.entity, target.entity);
+            let health = remaining.get_mut(&target.entity).unwrap();
+            *health = health.saturating_sub(target.missile_damage);
+        }
+    }
+}
+
+/// Toggles the auto mode of clicked miners, and keeps the TargetAssignment up to date. The
+/// miners target asteroids and the raiders that are hostile to them.
+#[derive(Default)]
+pub struct MinerTargetingSystem {
+    // These are reused every update, so they are not allocated every update
+    miner_snapshots: Vec<MinerSnapshot>,
+    target_snapshots: Vec<TargetSnapshot>,
+    in_flight: Vec<Entity>,
+}
+
+impl<'a> System<'a> for MinerTargetingSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, InputManager>,
+        ReadExpect<'a, Strings>,
+        WriteExpect<'a, GameLog>,
+        WriteExpect<'a, TargetAssignment>,
+        WriteStorage<'a, Miner>,
+        ReadStorage<'a, MiningMissle>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, RigidBody>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, Asteroid>,
+        ReadStorage<'a, Raider>,
+        ReadStorage<'a, Faction>,
+        ReadStorage<'a, AsteroidField>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, StatusEffects>,
+        ReadStorage<'a, Wear>,
+        ReadStorage<'a, Derelict>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            input,
+            strings,
+            mut log,
+            mut assignment,
+            mut miners,
+            missiles,
+            transforms,
+            bodies,
+            healths,
+            asteroids,
+            raiders,
+            factions,
+            fields,
+            ships,
+            statuses,
+            wears,
+            derelicts,
+        ) = data;
+
+        if let Some(miner) = input.clicked_block.and_then(|block| miners.get_mut(block)) {
+            miner.set_auto(!miner.is_auto());
+            log.add(strings.format(
+                "log.miner_auto",
+                &[("state", &strings.on_off(miner.is_auto()))],
+            ));
+        }
+
+        let x_range = (&fields)
+            .join()
+            .map(|field| field.x_range)
+            .next()
+            .unwrap_or(f32::INFINITY);
+        let overheated = heat::overheated_blocks(&ships);
+        let browned_out = power::browned_out_blocks(&ships);
+        let miner_snapshots = &mut self.miner_snapshots;
+        miner_snapshots.clear();
+        miner_snapshots.extend(
+            (&entities, &miners, &transforms, !&derelicts)
+                .join()
+                .filter(|(entity, miner, _, _)| {
+                    miner.is_auto()
+                        && !status::is_emped(&statuses, *entity)
+                        && !matches!(wears.get(*entity), Some(wear) if wear.is_servicing())
+                        && !overheated.contains(entity)
+                        && !browned_out.contains(entity)
+                })
+                .map(|(entity, miner, transform, _)| MinerSnapshot {
+                    entity,
+                    position: transform.position + Vector3::new(0.0, 0.0, 0.5),
+                    reload: miner.reload(),
+                }),
+        );
+
+        let target_snapshots = &mut self.target_snapshots;
+        target_snapshots.clear();
+        target_snapshots.extend(
+            (&entities, &healths, &transforms)
+                .join()
+                .filter(|(_, health, _)| health.health() > 0)
+                .filter_map(|(entity, health, transform)| {
+                    let missile_damage = if asteroids.contains(entity) {
+                        health.health()
+                    } else if raiders.contains(entity)
+                        && Faction::Player.is_hostile_to(faction::faction_of(&factions, entity))
+                    {
+                        MiningMissle::RAIDER_DAMAGE
+                    } else {
+                        return None;
+                    };
+                    Some(TargetSnapshot {
+                        entity,
+                        position: transform.position,
+                        velocity: bodies
+                            .get(entity)
+                            .map_or_else(Vector3::zero, |body| body.velocity),
+                        health: health.health(),
+                        missile_damage,
+                    })
+                }),
+        );
+        let in_flight = &mut self.in_flight;
+        in_flight.clear();
+        in_flight.extend(missiles.join().map(MiningMissle::target));
+
+        assign_targets(
+            miner_snapshots,
+            target_snapshots,
+            in_flight,
+            x_range,
+            &mut assignment,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::headless::entities;
+
+    const X_RANGE: f32 = 50.0;
+
+    /// Miners in a row along the ship, the first one being the last to be ready
+    fn miners(entities: &[Entity]) -> Vec<MinerSnapshot> {
+        entities
+            .iter()
+            .enumerate()
+            .map(|(i, entity)| MinerSnapshot {
+                entity: *entity,
+                position: Vector3::new(i as f32, 0.0, 0.5),
+                reload: (entities.len() - i) as f32 * 0.1,
+            })
+            .collect()
+    }
+
+    fn target(entity: Entity, x: f32, y: f32, health: u32, missile_damage: u32) -> TargetSnapshot {
+        TargetSnapshot {
+            entity,
+            position: Vector3::new(x, y, 0.5),
+            velocity: Vector3::zero(),
+            health,
+            missile_damage,
+        }
+    }
+
+    fn assigned(miners: &[MinerSnapshot], assignment: &TargetAssignment) -> Vec<Option<Entity>> {
+        miners
+            .iter()
+            .map(|miner| assignment.target_of(miner.entity))
+            .collect()
+    }
+
+    #[test]
+    fn one_weak_asteroid_only_gets_one_miner() {
+        let e = entities(6);
+        let mut miners = miners(&e[..5]);
+        let asteroid = target(e[5], 2.0, 10.0, 1, 1);
+        let mut assignment = TargetAssignment::default();
+
+        assign_targets(&mut miners, &[asteroid], &[], X_RANGE, &mut assignment);
+        // The miner that is ready first fires
+        assert_eq!(miners[0].entity, e[4]);
+        assert_eq!(
+            assigned(&miners, &assignment),
+            vec![Some(asteroid.entity), None, None, None, None]
+        );
+
+        // Once its missile is in flight, no one else fires at it
+        assign_targets(
+            &mut miners,
+            &[asteroid],
+            &[asteroid.entity],
+            X_RANGE,
+            &mut assignment,
+        );
+        assert_eq!(assignment.incoming_damage(asteroid.entity), 1);
+        assert!(assigned(&miners, &assignment).iter().all(Option::is_none));
+
+        // The missile missed, so the asteroid is assigned again
+        assign_targets(&mut miners, &[asteroid], &[], X_RANGE, &mut assignment);
+        assert_eq!(assignment.incoming_damage(asteroid.entity), 0);
+        assert_eq!(assignment.target_of(e[4]), Some(asteroid.entity));
+    }
+
+    #[test]
+    fn tough_targets_get_as_many_miners_as_they_need() {
+        let e = entities(7);
+        let mut miners = miners(&e[..5]);
+        let asteroid = target(e[5], 2.0, 8.0, 1, 1);
+        // Further away, and takes three missiles
+        let raider = target(e[6], 2.0, 20.0, 60, MiningMissle::RAIDER_DAMAGE);
+        let mut assignment = TargetAssignment::default();
+
+        assign_targets(
+            &mut miners,
+            &[raider, asteroid],
+            &[],
+            X_RANGE,
+            &mut assignment,
+        );
+        let targets = assigned(&miners, &assignment);
+        assert_eq!(targets[0], Some(asteroid.entity));
+        assert!(targets[1..4]
+            .iter()
+            .all(|target| *target == Some(raider.entity)));
+        assert_eq!(targets[4], None);
+
+        // A missile in flight at the raider frees up one miner
+        let in_flight = [raider.entity];
+        assign_targets(
+            &mut miners,
+            &[raider, asteroid],
+            &in_flight,
+            X_RANGE,
+            &mut assignment,
+        );
+        let raiders = assigned(&miners, &assignment)
+            .iter()
+            .filter(|target| **target == Some(raider.entity))
+            .count();
+        assert_eq!(raiders, 2);
+    }
+
+    #[test]
+    fn targets_leaving_the_field_are_mined_first() {
+        let e = entities(3);
+        let mut miners = miners(&e[..1]);
+        miners[0].position.x = 40.0;
+        let near = target(e[1], 40.0, 10.0, 1, 1);
+        let mut leaving = target(e[2], 40.0, 14.0, 1, 1);
+        let mut assignment = TargetAssignment::default();
+
+        assign_targets(&mut miners, &[near, leaving], &[], X_RANGE, &mut assignment);
+        assert_eq!(assignment.target_of(e[0]), Some(near.entity));
+
+        // It leaves the field in 5 seconds, which makes up for being further away
+        leaving.velocity.x = 2.0;
+        assign_targets(&mut miners, &[near, leaving], &[], X_RANGE, &mut assignment);
+        assert_eq!(assignment.target_of(e[0]), Some(leaving.entity));
+
+        // Past the edge of the field, it can't be mined at all
+        leaving.position.x = X_RANGE + 1.0;
+        assign_targets(&mut miners, &[leaving], &[], X_RANGE, &mut assignment);
+        assert_eq!(assignment.target_of(e[0]), None);
+    }
+}
@@ -0,0 +1,246 @@
+use super::{
+    objects::{Asteroid, Health},
+    GameModule, Ship, Transform,
+};
+use crate::debug::{ArgSchema, ArgType, DebugCommand, DebugCommands};
+use crate::profiler::AddProfiled;
+use cgmath::{prelude::*, Vector3};
+use specs::prelude::*;
+use std::collections::HashMap;
+
+/// Slows the game down for a moment during dramatic events (ex. a shield breaking). Only the
+/// simulation is slowed down, the camera and the UI keep running at full speed.
+pub struct TimeEffectModule;
+
+impl GameModule for TimeEffectModule {
+    fn register(&self, world: &mut World) {
+        world.register::<Asteroid>();
+        world.register::<Health>();
+        world.insert(TimeEffects::default());
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(CloseCallSystem::default(), "close_call_system", &[]);
+    }
+
+    /// A retried wave starts at full speed
+    fn init(&self, world: &mut World) {
+        world.insert(TimeEffects::default());
+    }
+
+    fn commands(&self, commands: &mut DebugCommands) {
+        commands.register(DebugCommand {
+            name: "slow_motion",
+            description: "Slows the game down to the scale for a second, like a dramatic event",
+            args: vec![ArgSchema {
+                name: "scale",
+                arg_type: ArgType::Float,
+            }],
+            state: Some(|ecs| format!("{:.2}x", ecs.get_resource::<TimeEffects>().scale())),
+            action: |ecs, args| {
+                ecs.get_resource_mut::<TimeEffects>().request(TimeEnvelope {
+                    scale: args[0].as_float(),
+                    ease_in: 5,
+                    hold: 60,
+                    recover: 30,
+                });
+                Ok(())
+            },
+        });
+    }
+}
+
+/// How the time scale changes over an effect, in ticks at the full tick rate. The ticks are
+/// counted in real time, so an effect lasts as long however far it slows the game down.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeEnvelope {
+    /// The scale that the effect slows the game down to
+    pub scale: f32,
+    /// The ticks it takes to slow down
+    pub ease_in: u32,
+    /// The ticks that the scale is held for
+    pub hold: u32,
+    /// The ticks it takes to get back to full speed
+    pub recover: u32,
+}
+
+impl TimeEnvelope {
+    /// The scale `ticks` after the effect started, or None once it is over
+    fn scale_at(&self, ticks: u32) -> Option<f32> {
+        let lerp = |from: f32, to: f32, t: f32| from + (to - from) * t;
+        let recovering = self.ease_in + self.hold;
+        if ticks < self.ease_in {
+            let t = (ticks + 1) as f32 / self.ease_in as f32;
+            Some(lerp(1.0, self.scale, t))
+        } else if ticks < recovering {
+            Some(self.scale)
+        } else if ticks < recovering + self.recover {
+            let t = (ticks - recovering) as f32 / self.recover as f32;
+            Some(lerp(self.scale, 1.0, t))
+        } else {
+            None
+        }
+    }
+}
+
+/// Identifies a requested effect, so it can be cancelled
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TimeEffectId(u32);
+
+struct TimeEffect {
+    id: TimeEffectId,
+    envelope: TimeEnvelope,
+    /// The ticks since the effect started
+    ticks: u32,
+}
+
+impl TimeEffect {
+    fn scale(&self) -> f32 {
+        self.envelope.scale_at(self.ticks).unwrap_or(1.0)
+    }
+}
+
+/// The time effects that gameplay systems requested. While any of them runs, only some of
+/// the fixed updates simulate the game (see app::run). Overlapping effects don't add up, the
+/// slowest one decides the scale.
+#[derive(Default)]
+pub struct TimeEffects {
+    effects: Vec<TimeEffect>,
+    next_id: u32,
+}
+
+impl TimeEffects {
+    /// The slowest that the game runs, however many effects overlap, so the simulation
+    /// never stalls
+    pub const MIN_SCALE: f32 = 0.1;
+    /// The most effects that run at once. The oldest effect is dropped for a new one.
+    const MAX_EFFECTS: usize = 8;
+
+    /// Starts an effect, unless time effects are turned off (see crate::TIME_EFFECTS)
+    pub fn request(&mut self, envelope: TimeEnvelope) -> TimeEffectId {
+        let id = TimeEffectId(self.next_id);
+        self.next_id = self.next_id.wrapping_add(1);
+        if crate::TIME_EFFECTS {
+            if self.effects.len() >= TimeEffects::MAX_EFFECTS {
+                self.effects.remove(0);
+            }
+            self.effects.push(TimeEffect {
+                id,
+                envelope,
+                ticks: 0,
+            });
+        }
+        id
+    }
+
+    /// Ends the effect early (ex. once what triggered it is over). The game gets back to
+    /// full speed from the effect's current scale, as fast as the effect would have
+    /// recovered. Effects that are already over are ignored.
+    pub fn cancel(&mut self, id: TimeEffectId) {
+        let effect = match self.effects.iter_mut().find(|effect| effect.id == id) {
+            Some(effect) => effect,
+            None => return,
+        };
+        let scale = effect.scale();
+        let slowdown = 1.0 - effect.envelope.scale;
+        let recover = if slowdown > f32::EPSILON {
+            (effect.envelope.recover as f32 * (1.0 - scale) / slowdown).ceil() as u32
+        } else {
+            0
+        };
+        effect.envelope = TimeEnvelope {
+            scale,
+            ease_in: 0,
+            hold: 0,
+            recover,
+        };
+        effect.ticks = 0;
+    }
+
+    /// Moves every effect along by a tick of real time, and drops the ones that are over
+    pub fn advance(&mut self) {
+        for effect in &mut self.effects {
+            effect.ticks += 1;
+        }
+        self.effects
+            .retain(|effect| effect.envelope.scale_at(effect.ticks).is_some());
+    }
+
+    /// How fast the game runs right now, from MIN_SCALE to 1
+    pub fn scale(&self) -> f32 {
+        self.effects
+            .iter()
+            .map(TimeEffect::scale)
+            .fold(1.0, f32::min)
+            .max(TimeEffects::MIN_SCALE)
+    }
+}
+
+/// Slows the game down while an asteroid is about to hit a ship's core. The effect is
+/// cancelled once the asteroid is destroyed, and plays out if the asteroid flies past.
+#[derive(Default)]
+struct CloseCallSystem {
+    /// The asteroids that are close to a core, and their effects
+    close_calls: HashMap<Entity, TimeEffectId>,
+    /// Reused every update, so the positions are not allocated every update
+    cores: Vec<Vector3<f32>>,
+}
+
+impl CloseCallSystem {
+    /// How close (from center to center) an asteroid has to get to a core
+    const RANGE: f32 = 3.0;
+    const EFFECT: TimeEnvelope = TimeEnvelope {
+        scale: 0.2,
+        ease_in: 5,
+        hold: 20,
+        recover: 30,
+    };
+}
+
+impl<'a> System<'a> for CloseCallSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteExpect<'a, TimeEffects>,
+        ReadStorage<'a, Ship>,
+        ReadStorage<'a, Asteroid>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut effects, ships, asteroids, healths, transforms) = data;
+        let destroyed = |entity: Entity| {
+            !entities.is_alive(entity)
+                || matches!(healths.get(entity), Some(health) if health.health() == 0)
+        };
+        self.close_calls.retain(|asteroid, id| {
+            if destroyed(*asteroid) {
+                effects.cancel(*id);
+            }
+            !destroyed(*asteroid)
+        });
+
+        let cores = &mut self.cores;
+        cores.clear();
+        cores.extend(
+            ships
+                .join()
+                .filter_map(|ship| transforms.get(ship.core()?))
+                .map(|transform| transform.position),
+        );
+        for (entity, _, transform) in (&entities, &asteroids, &transforms).join() {
+            if destroyed(entity) {
+                continue;
+            }
+            let close = cores
+                .iter()
+                .any(|core| (core - transform.position).magnitude() < CloseCallSystem::RANGE);
+            if close && !self.close_calls.contains_key(&entity) {
+                let id = effects.request(CloseCallSystem::EFFECT);
+                self.close_calls.insert(entity, id);
+            } else if !close {
+                self.close_calls.remove(&entity);
+            }
+        }
+    }
+}
@@ -0,0 +1,429 @@
+use super::{
+    gameplay::{AsteroidField, GameLog},
+    objects::ObjectMeshes,
+    physics::RigidBody,
+    GameModule, Model, Time, ToBeRemoved, Transform,
+};
+use crate::block::{BlockId, Blocks};
+use crate::debug::{DebugCommand, DebugCommands};
+use crate::i18n::Strings;
+use crate::item::{self, GameItem, Inventory};
+use crate::profiler::AddProfiled;
+use cgmath::{prelude::*, Vector3};
+use rand::{rngs::StdRng, seq::IteratorRandom, Rng, SeedableRng};
+use specs::{prelude::*, Component};
+
+/// Traders that stop by every few minutes, to trade the mined items for other items or block
+/// vouchers. The asteroid field is paused while a trader visits.
+pub struct TradeModule;
+
+impl GameModule for TradeModule {
+    fn register(&self, world: &mut World) {
+        world.register::<Trader>();
+        world.register::<RigidBody>();
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(EncounterSystem, "encounter_system", &[]);
+    }
+
+    fn init(&self, world: &mut World) {
+        world.insert(TradeEncounter::new());
+    }
+
+    fn commands(&self, commands: &mut DebugCommands) {
+        commands.register(DebugCommand {
+            name: "call_trader",
+            description: "Makes the next trader arrive now",
+            args: Vec::new(),
+            state: Some(|ecs| {
+                let encounter = ecs.get_resource::<TradeEncounter>();
+                match encounter.visit {
+                    Some(_) => String::from("a trader is visiting"),
+                    None => format!("next trader in {}s", encounter.next_in as u32),
+                }
+            }),
+            action: |ecs, _| {
+                let mut encounter = ecs.get_resource_mut::<TradeEncounter>();
+                if encounter.visit.is_some() {
+                    return Err(String::from("A trader is already visiting"));
+                }
+                encounter.next_in = 0.0;
+                Ok(())
+            },
+        });
+    }
+}
+
+/// A trader's ship. It flies in from the side, and flies off once the visit is over.
+#[derive(Component, Default)]
+#[storage(HashMapStorage)]
+pub struct Trader {
+    departing: bool,
+}
+
+impl Trader {
+    /// Units per second
+    const SPEED: f32 = 6.0;
+    /// Where the trader waits beside the ship while it is open for trades
+    const DOCK: Vector3<f32> = Vector3::new(2.0, -6.0, 1.0);
+}
+
+/// What the trader gives for an offer
+#[derive(Clone, Copy, Debug)]
+pub enum TradeGoods {
+    Items(GameItem, u32),
+    /// A voucher that halves the cost of building the block once
+    Voucher(BlockId),
+}
+
+#[derive(Clone, Debug)]
+pub struct TradeOffer {
+    pub cost: Vec<(GameItem, u32)>,
+    pub goods: TradeGoods,
+    /// Each offer can only be traded once
+    pub taken: bool,
+}
+
+impl TradeOffer {
+    /// Ex. "20 Iron -> 5 Copper"
+    pub fn describe(&self, blocks: &Blocks, strings: &Strings) -> String {
+        let goods = match self.goods {
+            TradeGoods::Items(item, amount) => item::describe_amount(item, amount, strings),
+            TradeGoods::Voucher(block) => strings.format(
+                "trade.voucher",
+                &[("block", &strings.get(blocks.get_block(block).name_key))],
+            ),
+        };
+        format!("{} -> {}", item::describe_cost(&self.cost, strings), goods)
+    }
+}
+
+/// Generates the offers of a trader. They only depend on the rng and the inventory (and the
+/// blocks that vouchers can be for), so a trader with the same seed offers the same trades for
+/// the same inventory.
+pub fn generate_offers(
+    rng: &mut impl Rng,
+    inventory: &Inventory,
+    blocks: &Blocks,
+) -> Vec<TradeOffer> {
+    // Traders mostly ask for what the ship has the most of
+    let most = *GameItem::ores()
+        .max_by_key(|item| inventory.amount(item))
+        .unwrap();
+
+    (0..TradeEncounter::OFFER_COUNT)
+        .map(|_| {
+            let wanted = if rng.gen_bool(TradeEncounter::MOST_WANTED_CHANCE) {
+                most
+            } else {
+                *GameItem::ores().choose(rng).unwrap()
+            };
+
+            let voucher = blocks
+                .iter()
+                .filter(|block| !block.cost.is_empty())
+                .choose(rng);
+            match voucher.filter(|_| rng.gen_bool(TradeEncounter::VOUCHER_CHANCE)) {
+                Some(block) => {
+                    let total: u32 = block.cost.iter().map(|(_, amount)| amount).sum();
+                    TradeOffer {
+                        cost: vec![(wanted, (total / 3).max(1))],
+                        goods: TradeGoods::Voucher(block.id),
+                        taken: false,
+                    }
+                }
+                None => {
+                    // The price is scaled to what the ship can afford
+                    let most_affordable = inventory
+                        .amount(&wanted)
+                        .clamp(TradeEncounter::MIN_PRICE, TradeEncounter::MAX_PRICE);
+                    let price = rng.gen_range(TradeEncounter::MIN_PRICE..=most_affordable);
+                    let item = GameItem::ores()
+                        .filter(|item| **item != wanted)
+                        .choose(rng)
+                        .copied()
+                        .unwrap_or(wanted);
+                    let amount = (price * rng.gen_range(20..=40) / 100).max(1);
+                    TradeOffer {
+                        cost: vec![(wanted, price)],
+                        goods: TradeGoods::Items(item, amount),
+                        taken: false,
+                    }
+                }
+            }
+        })
+        .collect()
+}
+
+struct Visit {
+    trader: Entity,
+    offers: Vec<TradeOffer>,
+    /// The seconds until the trader leaves. None while it is still on its way.
+    time_left: Option<f32>,
+    /// Set when the trade panel is closed, which sends the trader off
+    closed: bool,
+}
+
+/// When the next trader comes, and the trader that is visiting
+pub struct TradeEncounter {
+    /// The seconds of play until the next trader arrives. The final wave is not counted.
+    next_in: f32,
+    /// How many traders have come during the run, which seeds their offers
+    visits: u32,
+    visit: Option<Visit>,
+}
+
+impl TradeEncounter {
+    /// The seconds between trader visits
+    const INTERVAL: f32 = 180.0;
+    /// How long a trader stays once it has arrived
+    const TIMEOUT: f32 = 30.0;
+    const OFFER_COUNT: usize = 3;
+    const MOST_WANTED_CHANCE: f64 = 0.6;
+    const VOUCHER_CHANCE: f64 = 0.3;
+    /// The range of the amount of items that traders ask for
+    const MIN_PRICE: u32 = 5;
+    const MAX_PRICE: u32 = 30;
+
+    fn new() -> Self {
+        Self {
+            next_in: TradeEncounter::INTERVAL,
+            visits: 0,
+            visit: None,
+        }
+    }
+
+    /// Each visit has its own stream, like the waves, so the offers don't depend on
+    /// anything else that used the rng
+    fn visit_rng(seed: u64, visit: u32) -> StdRng {
+        StdRng::seed_from_u64(seed ^ (visit as u64).wrapping_mul(0xd1b5_4a32_d192_ed03))
+    }
+
+    fn docked(&self) -> Option<&Visit> {
+        self.visit
+            .as_ref()
+            .filter(|visit| visit.time_left.is_some() && !visit.closed)
+    }
+
+    /// The offers of the trader, while it is open for trades
+    pub fn offers(&self) -> Option<&[TradeOffer]> {
+        self.docked().map(|visit| visit.offers.as_slice())
+    }
+
+    /// The seconds until the trader leaves, while it is open for trades
+    pub fn time_left(&self) -> Option<f32> {
+        self.docked().and_then(|visit| visit.time_left)
+    }
+
+    /// Sends the trader off
+    pub fn close(&mut self) {
+        if let Some(visit) = &mut self.visit {
+            visit.closed = true;
+        }
+    }
+}
+
+/// Trades the offer of the trader. The items are only exchanged if the inventory can pay for
+/// the whole offer.
+pub fn accept_offer(world: &World, index: usize) -> Result<(), String> {
+    let mut encounter = world.write_resource::<TradeEncounter>();
+    let mut inventory = world.write_resource::<Inventory>();
+    let strings = world.fetch::<Strings>();
+    let offer = encounter
+        .visit
+        .as_mut()
+        .filter(|visit| visit.time_left.is_some() && !visit.closed)
+        .and_then(|visit| visit.offers.get_mut(index))
+        .ok_or_else(|| strings.get("error.no_trader").to_string())?;
+    if offer.taken {
+        return Err(strings.get("error.offer_taken").to_string());
+    }
+
+    match offer.goods {
+        TradeGoods::Items(item, amount) => {
+            inventory.exchange(&offer.cost, &[(item, amount)], &strings)?
+        }
+        TradeGoods::Voucher(block) => {
+            inventory.exchange(&offer.cost, &[], &strings)?;
+            inventory.add_voucher(block);
+        }
+    }
+    offer.taken = true;
+
+    let description = offer.describe(&world.fetch::<Blocks>(), &strings);
+    world
+        .write_resource::<GameLog>()
+        .add(strings.format("log.traded", &[("offer", &description)]));
+    Ok(())
+}
+
+/// Sends traders every few minutes, pausing the asteroid field while they visit
+struct EncounterSystem;
+
+impl<'a> System<'a> for EncounterSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, LazyUpdate>,
+        ReadExpect<'a, Time>,
+        Write<'a, ToBeRemoved>,
+        ReadExpect<'a, ObjectMeshes>,
+        ReadExpect<'a, Blocks>,
+        ReadExpect<'a, Inventory>,
+        ReadExpect<'a, Strings>,
+        WriteExpect<'a, GameLog>,
+        WriteExpect<'a, TradeEncounter>,
+        WriteStorage<'a, AsteroidField>,
+        WriteStorage<'a, Trader>,
+        WriteStorage<'a, Transform>,
+        WriteStorage<'a, RigidBody>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            lazy_update,
+            time,
+            mut to_be_removed,
+            meshes,
+            blocks,
+            inventory,
+            strings,
+            mut log,
+            mut encounter,
+            mut fields,
+            mut traders,
+            mut transforms,
+            mut bodies,
+        ) = data;
+        let field = match (&mut fields).join().next() {
+            Some(field) => field,
+            None => return,
+        };
+
+        // Traders that left are removed once they are out of the field
+        for (entity, trader, transform) in (&entities, &traders, &transforms).join() {
+            if trader.departing && transform.position.x > field.x_range {
+                to_be_removed.add(entity);
+            }
+        }
+
+        let encounter = &mut *encounter;
+        let visit = match &mut encounter.visit {
+            Some(visit) => visit,
+            // Traders don't stop by during the final wave, which has to be survived
+            None if field.level < AsteroidField::MAX_LEVEL => {
+                if time.count_down(&mut encounter.next_in) {
+                    let start = Vector3::new(-field.x_range, Trader::DOCK.y, Trader::DOCK.z);
+                    let trader = lazy_update
+                        .create_entity(&entities)
+                        .with(Model::new(meshes.trader))
+                        .with(Transform::from_position(start.x, start.y, start.z))
+                        .with(RigidBody {
+                            velocity: Vector3::new(Trader::SPEED, 0.0, 0.0),
+                        })
+                        .with(Trader::default())
+                        .build();
+
+                    encounter.visits += 1;
+                    let mut rng = TradeEncounter::visit_rng(field.seed(), encounter.visits);
+                    encounter.visit = Some(Visit {
+                        trader,
+                        offers: generate_offers(&mut rng, &inventory, &blocks),
+                        time_left: None,
+                        closed: false,
+                    });
+                    field.paused = true;
+                    log.add(strings.get("log.trader_approaching").to_string());
+                }
+                return;
+            }
+            None => return,
+        };
+
+        match &mut visit.time_left {
+            // The trader stops once it reaches the dock. It is created lazily, so it may not
+            // have a transform yet.
+            None => {
+                if let Some(transform) = transforms.get_mut(visit.trader) {
+                    if transform.position.x >= Trader::DOCK.x {
+                        transform.position = Trader::DOCK;
+                        if let Some(body) = bodies.get_mut(visit.trader) {
+                            body.velocity = Vector3::zero();
+                        }
+                        visit.time_left = Some(TradeEncounter::TIMEOUT);
+                        log.add(strings.get("log.trader_open").to_string());
+                    }
+                }
+            }
+            Some(time_left) => {
+                if visit.closed || time.count_down(time_left) {
+                    if let Some(trader) = traders.get_mut(visit.trader) {
+                        trader.departing = true;
+                    }
+                    if let Some(body) = bodies.get_mut(visit.trader) {
+                        body.velocity = Vector3::new(Trader::SPEED, 0.0, 0.0);
+                    }
+                    encounter.visit = None;
+                    encounter.next_in = TradeEncounter::INTERVAL;
+                    field.paused = false;
+                    log.add(strings.get("log.trader_left").to_string());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::headless::load_blocks;
+
+    /// The offers as text, since they are compared as a whole
+    fn offers(seed: u64, inventory: &Inventory, blocks: &Blocks) -> String {
+        let mut rng = StdRng::seed_from_u64(seed);
+        format!("{:?}", generate_offers(&mut rng, inventory, blocks))
+    }
+
+    #[test]
+    fn offers_only_depend_on_the_rng_and_inventory() {
+        let blocks = load_blocks();
+        let inventory = Inventory::new();
+        for seed in 0..20 {
+            assert_eq!(
+                offers(seed, &inventory, &blocks),
+                offers(seed, &inventory.clone(), &blocks)
+            );
+        }
+        let distinct: std::collections::HashSet<String> = (0..20)
+            .map(|seed| offers(seed, &inventory, &blocks))
+            .collect();
+        assert!(distinct.len() > 1);
+    }
+
+    #[test]
+    fn prices_fit_the_inventory() {
+        let blocks = load_blocks();
+        let inventory = Inventory::new();
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let offers = generate_offers(&mut rng, &inventory, &blocks);
+            assert_eq!(offers.len(), TradeEncounter::OFFER_COUNT);
+            for offer in &offers {
+                assert!(!offer.taken);
+                assert_eq!(offer.cost.len(), 1);
+                let (wanted, price) = offer.cost[0];
+                assert!(GameItem::ores().any(|ore| *ore == wanted));
+                assert!(price >= 1);
+                if let TradeGoods::Items(item, amount) = offer.goods {
+                    let affordable = inventory
+                        .amount(&wanted)
+                        .clamp(TradeEncounter::MIN_PRICE, TradeEncounter::MAX_PRICE);
+                    assert!((TradeEncounter::MIN_PRICE..=affordable).contains(&price));
+                    assert!(item != wanted);
+                    assert!(amount >= 1 && amount <= price);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,714 @@
+//! Lets the player take back what they did in build mode. Ctrl+Z (or X on a gamepad) undoes
+//! the last placed block, stamped blueprint or paint, and Ctrl+Y does it again. Both go through the same code
+//! as building (see ship::build), and undoing refunds exactly what was paid.
+
+use super::{
+    blueprint::{self, Blueprint},
+    construction::{self, ConstructionQueue},
+    gameplay::{AsteroidField, GameLog},
+    input::PadButton,
+    objects::Health,
+    paint::Paint,
+    ship::{self, BuildAction, BuildSource, Tile},
+    BlockEntity, GameModule, InputManager, Model, Ship,
+};
+use crate::block::{BlockId, Blocks};
+use crate::floor::Floors;
+use crate::i18n::Strings;
+use crate::item::{GameItem, Inventory};
+use crate::profiler::AddProfiled;
+use cgmath::{Point2, Point3};
+use specs::prelude::*;
+use winit::event;
+
+pub struct UndoModule;
+
+impl GameModule for UndoModule {
+    fn register(&self, world: &mut World) {
+        world.register::<AsteroidField>();
+        world.insert(UndoStack::default());
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(UndoSystem, "undo_system", &[]);
+    }
+
+    /// Nothing from before a new run (or a retried wave) can be undone
+    fn init(&self, world: &mut World) {
+        world.insert(UndoStack::default());
+    }
+
+    fn on_key(&self, world: &mut World, key: event::VirtualKeyCode, state: event::ElementState) {
+        if state != event::ElementState::Pressed {
+            return;
+        }
+        let request = match key {
+            event::VirtualKeyCode::Z => UndoRequest::Undo,
+            event::VirtualKeyCode::Y => UndoRequest::Redo,
+            _ => return,
+        };
+
+        let mut input = world.write_resource::<InputManager>();
+        if input.keys.is_control_down() {
+            world.write_resource::<UndoStack>().requested = Some(request);
+            // The keys may also be bound to an action
+            input.keys.take_press(key);
+        }
+    }
+}
+
+/// A build mode operation, with what it takes to undo it and to do it again
+pub enum UndoCommand {
+    /// A block (or gadget) the player placed, and what it was paid with
+    PlaceBlock {
+        pos: Point3<i16>,
+        block_id: BlockId,
+        paid: Vec<(GameItem, u32)>,
+        voucher: bool,
+    },
+    /// A blueprint stamped with its corner at `origin`, and the floors, blocks and gadgets
+    /// it built
+    Stamp {
+        blueprint: Blueprint,
+        origin: Point2<i16>,
+        built: Vec<BuildAction>,
+        paid: Vec<(GameItem, u32)>,
+    },
+    /// The paint of a block before and after it was painted. None is unpainted (white).
+    Paint {
+        pos: Point3<i16>,
+        block_id: BlockId,
+        before: Option<Paint>,
+        after: Option<Paint>,
+    },
+}
+
+struct UndoEntry {
+    ship: Entity,
+    command: UndoCommand,
+}
+
+#[derive(Clone, Copy)]
+enum UndoRequest {
+    Undo,
+    Redo,
+}
+
+/// The operations that can be undone, and the undone ones that can be done again. The
+/// history is cleared when a wave starts, so blocks that went through a wave stay built.
+#[derive(Default)]
+pub struct UndoStack {
+    done: Vec<UndoEntry>,
+    undone: Vec<UndoEntry>,
+    /// Set by Ctrl+Z, Ctrl+Y or the gamepad's undo button, and carried out once the systems
+    /// have run
+    requested: Option<UndoRequest>,
+    /// The wave the history is from
+    level: u16,
+}
+
+impl UndoStack {
+    /// The oldest operations are forgotten past this many
+    const MAX_ENTRIES: usize = 50;
+
+    /// Records an operation. The operations that were undone can't be done again after this.
+    pub fn push(&mut self, ship: Entity, command: UndoCommand) {
+        self.undone.clear();
+        self.done.push(UndoEntry { ship, command });
+        if self.done.len() > UndoStack::MAX_ENTRIES {
+            self.done.remove(0);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.done.clear();
+        self.undone.clear();
+    }
+}
+
+/// Clears the history when a new wave starts, and requests an undo when the gamepad's undo
+/// button is pressed
+struct UndoSystem;
+
+impl<'a> System<'a> for UndoSystem {
+    type SystemData = (
+        WriteExpect<'a, UndoStack>,
+        ReadExpect<'a, InputManager>,
+        ReadStorage<'a, AsteroidField>,
+    );
+
+    fn run(&mut self, (mut stack, input, fields): Self::SystemData) {
+        if input.pad.was_pressed(PadButton::Undo) {
+            stack.requested = Some(UndoRequest::Undo);
+        }
+        if let Some(field) = fields.join().next() {
+            if field.level != stack.level {
+                stack.clear();
+                stack.level = field.level;
+            }
+        }
+    }
+}
+
+/// Undoes or redoes the last operation. Building needs the whole world, so this is called
+/// after the systems have run, like placing blocks. An operation that can't be undone (ex. a
+/// block that was damaged since it was built) is dropped from the history.
+pub fn apply_requested(world: &mut World) {
+    let (request, entry) = {
+        let mut stack = world.write_resource::<UndoStack>();
+        match stack.requested.take() {
+            Some(UndoRequest::Undo) => (UndoRequest::Undo, stack.done.pop()),
+            Some(UndoRequest::Redo) => (UndoRequest::Redo, stack.undone.pop()),
+            None => return,
+        }
+    };
+    let mut entry = match entry {
+        Some(entry) => entry,
+        None => {
+            let key = match request {
+                UndoRequest::Undo => "log.nothing_to_undo",
+                UndoRequest::Redo => "log.nothing_to_redo",
+            };
+            let message = world.fetch::<Strings>().get(key).to_string();
+            world.write_resource::<GameLog>().add(message);
+            return;
+        }
+    };
+
+    let action = describe(world, &entry.command);
+    let result = if world.read_component::<Ship>().contains(entry.ship) {
+        match request {
+            UndoRequest::Undo => undo(world, &entry),
+            UndoRequest::Redo => redo(world, &mut entry),
+        }
+    } else {
+        Err(world.fetch::<Strings>().get("error.no_ship").to_string())
+    };
+
+    let strings = world.fetch::<Strings>();
+    let message = match (request, result) {
+        (UndoRequest::Undo, Ok(())) => {
+            world.write_resource::<UndoStack>().undone.push(entry);
+            strings.format("log.undone", &[("action", &action)])
+        }
+        (UndoRequest::Redo, Ok(())) => {
+            world.write_resource::<UndoStack>().done.push(entry);
+            strings.format("log.redone", &[("action", &action)])
+        }
+        (UndoRequest::Undo, Err(reason)) => strings.format(
+            "log.undo_failed",
+            &[("action", &action), ("reason", &reason)],
+        ),
+        (UndoRequest::Redo, Err(reason)) => strings.format(
+            "log.redo_failed",
+            &[("action", &action), ("reason", &reason)],
+        ),
+    };
+    world.write_resource::<GameLog>().add(message);
+}
+
+/// The operation as it is named in the log
+fn describe(world: &World, command: &UndoCommand) -> String {
+    let strings = world.fetch::<Strings>();
+    let blocks = world.fetch::<Blocks>();
+    let name = |block_id: BlockId| strings.get(blocks.get_block(block_id).name_key);
+    match command {
+        UndoCommand::PlaceBlock { block_id, .. } => {
+            strings.format("undo.place", &[("block", &name(*block_id))])
+        }
+        UndoCommand::Stamp { blueprint, .. } => {
+            strings.format("undo.stamp", &[("count", &blueprint.block_count())])
+        }
+        UndoCommand::Paint { block_id, .. } => {
+            strings.format("undo.paint", &[("block", &name(*block_id))])
+        }
+    }
+}
+
+fn undo(world: &mut World, entry: &UndoEntry) -> Result<(), String> {
+    match &entry.command {
+        UndoCommand::PlaceBlock {
+            pos,
+            block_id,
+            paid,
+            voucher,
+        } => {
+            remove_built(
+                world,
+                entry.ship,
+                &[BuildAction::BuildBlock(*pos, *block_id)],
+            )?;
+            let mut inventory = world.write_resource::<Inventory>();
+            inventory.add_items(paid);
+            if *voucher {
+                inventory.add_voucher(*block_id);
+            }
+        }
+        UndoCommand::Stamp { built, paid, .. } => {
+            remove_built(world, entry.ship, built)?;
+            world.write_resource::<Inventory>().add_items(paid);
+        }
+        UndoCommand::Paint {
+            pos,
+            block_id,
+            before,
+            ..
+        } => set_paint(world, entry.ship, *pos, *block_id, *before)?,
+    }
+    Ok(())
+}
+
+/// Does the operation again. It is paid for again, and checked like it was the first time.
+fn redo(world: &mut World, entry: &mut UndoEntry) -> Result<(), String> {
+    match &mut entry.command {
+        UndoCommand::PlaceBlock {
+            pos,
+            block_id,
+            paid,
+            voucher,
+        } => {
+            let (new_paid, used_voucher) =
+                blueprint::place_block(world, entry.ship, *pos, *block_id)?;
+            *paid = new_paid;
+            *voucher = used_voucher;
+        }
+        UndoCommand::Stamp {
+            blueprint,
+            origin,
+            built,
+            paid,
+        } => {
+            *built = blueprint.stamp(world, entry.ship, *origin)?;
+            *paid = blueprint.cost(&world.fetch::<Blocks>(), &world.fetch::<Floors>());
+        }
+        UndoCommand::Paint {
+            pos,
+            block_id,
+            after,
+            ..
+        } => set_paint(world, entry.ship, *pos, *block_id, *after)?,
+    }
+    Ok(())
+}
+
+/// Removes the floors, blocks and gadgets that were built, once every one of them is checked.
+/// Builds that are still waiting for a construction drone are dropped from the queue.
+fn remove_built(world: &mut World, ship: Entity, built: &[BuildAction]) -> Result<(), String> {
+    let mut pending = Vec::new();
+    let mut removals = Vec::new();
+    {
+        let ships = world.read_component::<Ship>();
+        let ship_data = ships.get(ship).unwrap();
+        let entities = world.entities();
+        let blocks = world.fetch::<Blocks>();
+        let strings = world.fetch::<Strings>();
+        let queue = world.fetch::<ConstructionQueue>();
+        let block_entities = world.read_component::<BlockEntity>();
+        let models = world.read_component::<Model>();
+        let healths = world.read_component::<Health>();
+
+        // Gadgets are taken back before their blocks, and upper decks before the decks below
+        let is_gadget = |block_id: BlockId| blocks.get_block(block_id).is_gadget;
+        let removed = |pos: Point3<i16>, gadget: bool| {
+            built.iter().any(|other| {
+                matches!(other, BuildAction::BuildBlock(other_pos, other_id)
+                    if *other_pos == pos && is_gadget(*other_id) == gadget)
+            })
+        };
+        let built_on = |pos: Point3<i16>| {
+            strings.format("error.undo_built_on", &[("x", &pos.x), ("y", &pos.y)])
+        };
+        for action in built.iter().rev() {
+            match *action {
+                BuildAction::BuildBlock(pos, block_id) => {
+                    if let Some(index) = queue.find(ship, pos, block_id) {
+                        pending.push(index);
+                        continue;
+                    }
+
+                    let name = strings.get(blocks.get_block(block_id).name_key);
+                    let tile = ship_data.tile(pos);
+                    // Gadgets don't have a BlockEntity, so they are found by their mesh
+                    let entity = if is_gadget(block_id) {
+                        tile.and_then(Tile::gadget).filter(|gadget| {
+                            models
+                                .get(*gadget)
+                                .and_then(|model| blocks.find_by_mesh(model.mesh_id))
+                                == Some(block_id)
+                        })
+                    } else {
+                        tile.and_then(Tile::block).filter(|block| {
+                            block_entities.get(*block).map(BlockEntity::block_id) == Some(block_id)
+                        })
+                    };
+                    let entity = entity
+                        .filter(|entity| entities.is_alive(*entity))
+                        .ok_or_else(|| {
+                            strings.format(
+                                "error.undo_missing",
+                                &[("block", &name), ("x", &pos.x), ("y", &pos.y)],
+                            )
+                        })?;
+                    let damaged = matches!(healths.get(entity),
+                        Some(health) if health.health() < health.max_health());
+                    if damaged {
+                        return Err(strings.format("error.undo_damaged", &[("block", &name)]));
+                    }
+                    let has_gadget = matches!(tile, Some(tile) if tile.gadget().is_some());
+                    if !is_gadget(block_id) && has_gadget && !removed(pos, true) {
+                        return Err(built_on(pos));
+                    }
+                    removals.push(BuildAction::RemoveBlock(pos));
+                }
+                // Floors that were lost since (ex. broken off the ship) are skipped
+                BuildAction::BuildFloor(pos, floor) => {
+                    let tile = ship_data.tile(pos);
+                    if tile.and_then(Tile::floor_type) != Some(floor) {
+                        continue;
+                    }
+                    let has_block = matches!(tile, Some(tile) if tile.block().is_some());
+                    if has_block && !removed(pos, false) {
+                        return Err(built_on(pos));
+                    }
+                    removals.push(BuildAction::RemoveFloor(pos));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // The later builds go first, so the indices of the others stay the same
+    pending.sort_unstable();
+    for index in pending.into_iter().rev() {
+        construction::drop_pending(world, index);
+    }
+    ship::build(world, ship, &removals, BuildSource::Prepaid);
+    Ok(())
+}
+
+/// Paints the block that is still on the tile, or removes its paint
+fn set_paint(
+    world: &mut World,
+    ship: Entity,
+    pos: Point3<i16>,
+    block_id: BlockId,
+    paint: Option<Paint>,
+) -> Result<(), String> {
+    let block = world
+        .read_component::<Ship>()
+        .get(ship)
+        .and_then(|ship| ship.tile(pos))
+        .and_then(Tile::block)
+        .filter(|block| world.entities().is_alive(*block))
+        .filter(|block| {
+            world
+                .read_component::<BlockEntity>()
+                .get(*block)
+                .map(BlockEntity::block_id)
+                == Some(block_id)
+        });
+    let block = match block {
+        Some(block) => block,
+        None => {
+            let strings = world.fetch::<Strings>();
+            let name = strings.get(world.fetch::<Blocks>().get_block(block_id).name_key);
+            return Err(strings.format(
+                "error.undo_missing",
+                &[("block", &name), ("x", &pos.x), ("y", &pos.y)],
+            ));
+        }
+    };
+
+    let mut paints = world.write_component::<Paint>();
+    match paint {
+        Some(paint) => {
+            paints.insert(block, paint).unwrap();
+        }
+        None => {
+            paints.remove(block);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::headless::HeadlessEcs;
+    use crate::entity::InputAction;
+    use crate::tech::TechTree;
+
+    /// A run where everything is researched and built right away, and that can pay for
+    /// anything
+    fn build_mode() -> (HeadlessEcs, Entity) {
+        let mut headless = HeadlessEcs::with_seed(1);
+        headless.pause_asteroid_field();
+        headless.step(1);
+        let world = &mut headless.ecs.world;
+        world.write_resource::<ConstructionQueue>().enabled = false;
+        let plenty: Vec<_> = GameItem::iter().map(|item| (*item, 1000)).collect();
+        world.write_resource::<Inventory>().add_items(&plenty);
+        {
+            let mut tech_tree = world.write_resource::<TechTree>();
+            let names: Vec<String> = tech_tree
+                .nodes()
+                .iter()
+                .map(|node| node.name.clone())
+                .collect();
+            for name in names {
+                let mut inventory = world.write_resource::<Inventory>();
+                tech_tree
+                    .purchase(&name, &mut inventory, &world.fetch::<Strings>())
+                    .unwrap();
+            }
+        }
+        let ship = ship::selected_ship(world).unwrap();
+        (headless, ship)
+    }
+
+    fn amounts(world: &World) -> Vec<u32> {
+        let inventory = world.fetch::<Inventory>();
+        GameItem::iter()
+            .map(|item| inventory.amount(item))
+            .collect()
+    }
+
+    /// Places the block like the blueprint tool does, and records it
+    fn place(world: &mut World, ship: Entity, pos: Point3<i16>, type_name: &str) {
+        let block_id = world.fetch::<Blocks>().find(type_name).unwrap();
+        let (paid, voucher) = blueprint::place_block(world, ship, pos, block_id).unwrap();
+        world.maintain();
+        world.write_resource::<UndoStack>().push(
+            ship,
+            UndoCommand::PlaceBlock {
+                pos,
+                block_id,
+                paid,
+                voucher,
+            },
+        );
+    }
+
+    /// Presses Ctrl+Z or Ctrl+Y, and returns what was logged
+    fn request(world: &mut World, request: UndoRequest) -> String {
+        world.write_resource::<UndoStack>().requested = Some(request);
+        apply_requested(world);
+        world.maintain();
+        let log = world.fetch::<GameLog>();
+        log.get_recent(0).unwrap_or_default().to_string()
+    }
+
+    fn block_entity(world: &World, ship: Entity, pos: Point3<i16>) -> Option<Entity> {
+        world.read_component::<Ship>().get(ship)?.tile(pos)?.block()
+    }
+
+    fn block_at(world: &World, ship: Entity, pos: Point3<i16>) -> Option<BlockId> {
+        let block = block_entity(world, ship, pos)?;
+        world
+            .read_component::<BlockEntity>()
+            .get(block)
+            .map(BlockEntity::block_id)
+    }
+
+    #[test]
+    fn placed_blocks_are_undone_and_redone() {
+        let (mut headless, ship) = build_mode();
+        let world = &mut headless.ecs.world;
+        let wall = world.fetch::<Blocks>().wall;
+        let pos = Point3::new(2, 2, 0);
+        let before = amounts(world);
+
+        place(world, ship, pos, "wall");
+        assert_eq!(block_at(world, ship, pos), Some(wall));
+        let placed = amounts(world);
+        assert_ne!(placed, before);
+
+        let message = request(world, UndoRequest::Undo);
+        assert!(message.starts_with("Undid"), "{}", message);
+        assert_eq!(block_at(world, ship, pos), None);
+        assert_eq!(amounts(world), before);
+
+        let message = request(world, UndoRequest::Redo);
+        assert!(message.starts_with("Redid"), "{}", message);
+        assert_eq!(block_at(world, ship, pos), Some(wall));
+        assert_eq!(amounts(world), placed);
+
+        // Placing a block forgets what was undone
+        request(world, UndoRequest::Undo);
+        place(world, ship, Point3::new(3, 2, 0), "wall");
+        request(world, UndoRequest::Redo);
+        assert_eq!(block_at(world, ship, pos), None);
+    }
+
+    #[test]
+    fn stamps_are_undone_as_a_whole() {
+        let (mut headless, ship) = build_mode();
+        let world = &mut headless.ecs.world;
+        let (wall, laser) = {
+            let blocks = world.fetch::<Blocks>();
+            (blocks.wall, blocks.laser)
+        };
+        // The corner of the hull, with the laser on it
+        let blueprint = {
+            let ships = world.read_component::<Ship>();
+            Blueprint::capture(
+                ships.get(ship).unwrap(),
+                &world.read_component::<BlockEntity>(),
+                &world.read_component::<Paint>(),
+                &world.read_component::<Model>(),
+                &world.fetch::<Blocks>(),
+                Point2::new(0, 0),
+                Point2::new(1, 1),
+            )
+        };
+        let laser_at = |world: &World, pos| {
+            let ships = world.read_component::<Ship>();
+            let gadget = ships.get(ship).unwrap().tile(pos).and_then(Tile::gadget);
+            let models = world.read_component::<Model>();
+            let blocks = world.fetch::<Blocks>();
+            gadget.and_then(|gadget| blocks.find_by_mesh(models.get(gadget)?.mesh_id))
+                == Some(laser)
+        };
+        let floor_at = |world: &World, pos| {
+            let ships = world.read_component::<Ship>();
+            let tile = ships.get(ship).unwrap().tile(pos);
+            tile.and_then(Tile::floor).is_some()
+        };
+        let before = amounts(world);
+
+        let mut tool = world.write_resource::<blueprint::BlueprintTool>();
+        tool.blueprint = Some(blueprint);
+        assert!(tool.request_stamp(Point2::new(-2, 0)));
+        std::mem::drop(tool);
+        blueprint::stamp_requested(world);
+        world.maintain();
+        let corner = Point3::new(-2, 0, 0);
+        let floor = Point3::new(-1, 1, 0);
+        assert_eq!(block_at(world, ship, corner), Some(wall));
+        assert!(laser_at(world, corner));
+        assert!(floor_at(world, floor));
+        let stamped = amounts(world);
+        assert_ne!(stamped, before);
+
+        request(world, UndoRequest::Undo);
+        assert_eq!(block_at(world, ship, corner), None);
+        assert!(!laser_at(world, corner));
+        assert!(!floor_at(world, floor));
+        assert_eq!(amounts(world), before);
+
+        request(world, UndoRequest::Redo);
+        assert_eq!(block_at(world, ship, corner), Some(wall));
+        assert!(laser_at(world, corner));
+        assert_eq!(amounts(world), stamped);
+    }
+
+    #[test]
+    fn paint_is_undone_and_redone() {
+        let (mut headless, ship) = build_mode();
+        let world = &mut headless.ecs.world;
+        let pos = Point3::new(0, 1, 0);
+        let block_id = block_at(world, ship, pos).unwrap();
+        let block = block_entity(world, ship, pos).unwrap();
+        let paint_of = |world: &World| world.read_component::<Paint>().get(block).copied();
+
+        // Painted like the PaintSystem does it
+        let gray = Paint::PALETTE[1];
+        world
+            .write_component::<Paint>()
+            .insert(block, gray)
+            .unwrap();
+        world.write_resource::<UndoStack>().push(
+            ship,
+            UndoCommand::Paint {
+                pos,
+                block_id,
+                before: None,
+                after: Some(gray),
+            },
+        );
+
+        request(world, UndoRequest::Undo);
+        assert_eq!(paint_of(world), None);
+        request(world, UndoRequest::Redo);
+        assert_eq!(paint_of(world), Some(gray));
+    }
+
+    #[test]
+    fn only_the_latest_operations_are_kept() {
+        let mut world = World::new();
+        let ship = world.create_entity().build();
+        let mut stack = UndoStack::default();
+        for x in 0..UndoStack::MAX_ENTRIES as i16 + 10 {
+            let command = UndoCommand::Paint {
+                pos: Point3::new(x, 0, 0),
+                block_id: 0,
+                before: None,
+                after: Some(Paint::WHITE),
+            };
+            stack.push(ship, command);
+        }
+
+        assert_eq!(stack.done.len(), UndoStack::MAX_ENTRIES);
+        let oldest = match &stack.done[0].command {
+            UndoCommand::Paint { pos, .. } => pos.x,
+            _ => unreachable!(),
+        };
+        assert_eq!(oldest, 10);
+    }
+
+    #[test]
+    fn damaged_blocks_are_not_undone() {
+        let (mut headless, ship) = build_mode();
+        let world = &mut headless.ecs.world;
+        let pos = Point3::new(2, 2, 0);
+        place(world, ship, pos, "wall");
+        let placed = amounts(world);
+
+        let block = block_entity(world, ship, pos).unwrap();
+        world
+            .write_component::<Health>()
+            .get_mut(block)
+            .unwrap()
+            .damage(1, cgmath::Vector3::new(2.0, 2.0, 0.0));
+        let message = request(world, UndoRequest::Undo);
+        assert!(message.contains("was damaged"), "{}", message);
+        assert!(block_at(world, ship, pos).is_some());
+        assert_eq!(amounts(world), placed);
+
+        // The operation is dropped, so it can't be undone or redone later
+        let stack = world.fetch::<UndoStack>();
+        assert!(stack.done.is_empty() && stack.undone.is_empty());
+    }
+
+    #[test]
+    fn the_gamepad_places_and_undoes_blocks() {
+        let (mut headless, ship) = build_mode();
+        let wall = headless.ecs.get_resource::<Blocks>().wall;
+        let pos = Point3::new(2, 2, 0);
+        let press = |headless: &mut HeadlessEcs, button| {
+            headless
+                .ecs
+                .get_resource_mut::<InputManager>()
+                .pad
+                .update(button, true);
+            headless.step(1);
+            headless
+                .ecs
+                .get_resource_mut::<InputManager>()
+                .pad
+                .update(button, false);
+        };
+
+        {
+            let mut input = headless.ecs.get_resource_mut::<InputManager>();
+            input.action = InputAction::PlaceBlock(wall);
+            input.tile_cursor = Some(Point2::new(pos.x, pos.y));
+        }
+        // The presses last until every system of the update has seen them
+        press(&mut headless, PadButton::Confirm);
+        assert_eq!(block_at(&headless.ecs.world, ship, pos), Some(wall));
+
+        press(&mut headless, PadButton::Undo);
+        assert_eq!(block_at(&headless.ecs.world, ship, pos), None);
+    }
+}
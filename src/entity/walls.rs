@@ -0,0 +1,306 @@
+use super::{BlockEntity, GameModule, Model, Ship, Tile, ToBeRemoved, Transform};
+use crate::block::Blocks;
+use crate::graphics::{MeshId, MeshManager};
+use crate::profiler::AddProfiled;
+use cgmath::{Point3, Quaternion, Rad, Rotation3, Vector3};
+use specs::prelude::*;
+use std::collections::HashSet;
+
+/// Connects the walls of the ship, by picking each wall's mesh based on its neighbours
+pub struct WallModule;
+
+impl GameModule for WallModule {
+    fn register(&self, world: &mut World) {
+        world.register::<Ship>();
+        world.register::<BlockEntity>();
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(WallSystem, "wall_system", &[]);
+    }
+
+    fn death_systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(WallRemovedSystem, "wall_removed_system", &[]);
+    }
+}
+
+/// The neighbours of a tile on its deck, counterclockwise starting at +x. Bit `i` of a
+/// connection mask is set when the neighbour in `DIRECTIONS[i]` is a wall.
+const DIRECTIONS: [Vector3<i16>; 4] = [
+    Vector3::new(1, 0, 0),
+    Vector3::new(0, 1, 0),
+    Vector3::new(-1, 0, 0),
+    Vector3::new(0, -1, 0),
+];
+
+/// The shapes of a wall. The meshes connect to +x first (ex. an end connects to +x, and a
+/// corner to +x and +y), and are rotated to match the neighbours.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WallShape {
+    /// No neighbouring walls
+    Single,
+    End,
+    Straight,
+    Corner,
+    Tee,
+    Cross,
+}
+
+impl WallShape {
+    /// Returns the shape of a wall with the connections, and how many quarter turns
+    /// (counterclockwise) its mesh is rotated by
+    pub fn from_connections(connections: u8) -> (WallShape, u8) {
+        for turns in 0..4 {
+            // Turning the mesh back to how it was modeled
+            let modeled = rotate_connections(connections, (4 - turns) % 4);
+            let shape = match modeled {
+                0b0000 => WallShape::Single,
+                0b0001 => WallShape::End,
+                0b0101 => WallShape::Straight,
+                0b0011 => WallShape::Corner,
+                0b0111 => WallShape::Tee,
+                0b1111 => WallShape::Cross,
+                _ => continue,
+            };
+            return (shape, turns);
+        }
+
+        unreachable!("Every connection mask is a rotation of a wall shape")
+    }
+}
+
+/// Rotates a connection mask counterclockwise by quarter turns
+fn rotate_connections(connections: u8, turns: u8) -> u8 {
+    ((connections << turns) | (connections >> (4 - turns))) & 0b1111
+}
+
+/// Finds which neighbours of the tile are walls. This only looks at the tile map (through
+/// `is_wall`), so it does not depend on the World.
+pub fn wall_connections(pos: Point3<i16>, is_wall: impl Fn(Point3<i16>) -> bool) -> u8 {
+    DIRECTIONS
+        .iter()
+        .enumerate()
+        .filter(|(_, direction)| is_wall(pos + **direction))
+        .fold(0, |connections, (bit, _)| connections | 1 << bit)
+}
+
+/// How many tiles of the ship's blocks have no wall (or door) beside them on their deck.
+/// The walls and doors themselves are not counted.
+pub fn exposed_blocks(
+    ship: &Ship,
+    blocks: &Blocks,
+    block_entities: &ReadStorage<BlockEntity>,
+) -> usize {
+    let block_id = |pos: Point3<i16>| {
+        ship.tile(pos)
+            .and_then(Tile::block)
+            .and_then(|block| block_entities.get(block))
+            .map(BlockEntity::block_id)
+    };
+    let covers = |pos| matches!(block_id(pos), Some(id) if id == blocks.wall || id == blocks.door);
+
+    ship.tiles()
+        .filter(|(pos, _)| block_id(*pos).is_some() && !covers(*pos))
+        .filter(|(pos, _)| wall_connections(*pos, covers) == 0)
+        .count()
+}
+
+pub struct WallMeshes {
+    pub single: MeshId,
+    pub end: MeshId,
+    pub straight: MeshId,
+    pub corner: MeshId,
+    pub tee: MeshId,
+    pub cross: MeshId,
+}
+
+impl WallMeshes {
+    pub fn get(&self, shape: WallShape) -> MeshId {
+        match shape {
+            WallShape::Single => self.single,
+            WallShape::End => self.end,
+            WallShape::Straight => self.straight,
+            WallShape::Corner => self.corner,
+            WallShape::Tee => self.tee,
+            WallShape::Cross => self.cross,
+        }
+    }
+}
+
+/// Updates the walls on the changed tiles and their neighbours
+struct WallSystem;
+
+impl<'a> System<'a> for WallSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Blocks>,
+        WriteExpect<'a, MeshManager>,
+        WriteStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+        WriteStorage<'a, Model>,
+        WriteStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            blocks,
+            mut mesh_manager,
+            mut ships,
+            block_entities,
+            mut models,
+            mut transforms,
+        ) = data;
+
+        for ship in (&mut ships).join() {
+            let changed = ship.take_changed_tiles();
+            if changed.is_empty() {
+                continue;
+            }
+
+            let block_at = |pos: Point3<i16>| {
+                ship.tile(pos)
+                    .and_then(Tile::block)
+                    .filter(|block| entities.is_alive(*block))
+            };
+            let block_id = |pos: Point3<i16>| {
+                block_at(pos)
+                    .and_then(|block| block_entities.get(block))
+                    .map(BlockEntity::block_id)
+            };
+            let is_wall = |pos| block_id(pos) == Some(blocks.wall);
+            // Walls also connect to the doors in them
+            let connects = |pos| is_wall(pos) || block_id(pos) == Some(blocks.door);
+
+            // Blocks are built lazily, so the ones that were not added yet are kept for
+            // the next update
+            let (pending, changed): (Vec<_>, Vec<_>) = changed.into_iter().partition(
+                |pos| matches!(block_at(*pos), Some(block) if !block_entities.contains(block)),
+            );
+
+            let mut updated = HashSet::new();
+            let affected = changed.iter().flat_map(|pos| {
+                std::iter::once(*pos).chain(DIRECTIONS.iter().map(move |direction| pos + direction))
+            });
+            for pos in affected {
+                if !updated.insert(pos) || !is_wall(pos) {
+                    continue;
+                }
+
+                let (shape, turns) = WallShape::from_connections(wall_connections(pos, connects));
+                let wall = block_at(pos).unwrap();
+                if let Some(model) = models.get_mut(wall) {
+                    model.set_mesh(&mut mesh_manager, blocks.wall_meshes.get(shape));
+                }
+                // Getting the transform mutably flags it, so it is only done when it turns
+                let rotation = Quaternion::from_angle_z(Rad(turns as f32 * crate::PI / 2.0));
+                if matches!(transforms.get(wall), Some(transform) if transform.rotation != rotation)
+                {
+                    transforms.get_mut(wall).unwrap().rotation = rotation;
+                }
+            }
+
+            for pos in pending {
+                ship.mark_changed(pos);
+            }
+        }
+    }
+}
+
+/// Updates the neighbours of the walls (and doors) that are removed
+struct WallRemovedSystem;
+
+impl<'a> System<'a> for WallRemovedSystem {
+    type SystemData = (
+        Read<'a, ToBeRemoved>,
+        ReadExpect<'a, Blocks>,
+        WriteStorage<'a, Ship>,
+        ReadStorage<'a, BlockEntity>,
+    );
+
+    fn run(&mut self, (to_be_removed, blocks, mut ships, block_entities): Self::SystemData) {
+        for (block_entity, _) in (&block_entities, to_be_removed.bitset()).join() {
+            if block_entity.block_id() != blocks.wall && block_entity.block_id() != blocks.door {
+                continue;
+            }
+            for ship in (&mut ships).join() {
+                ship.mark_changed(block_entity.root());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The connections of the shape as it was modeled, before it is rotated
+    fn modeled(shape: WallShape) -> u8 {
+        match shape {
+            WallShape::Single => 0b0000,
+            WallShape::End => 0b0001,
+            WallShape::Straight => 0b0101,
+            WallShape::Corner => 0b0011,
+            WallShape::Tee => 0b0111,
+            WallShape::Cross => 0b1111,
+        }
+    }
+
+    #[test]
+    fn every_mask_is_a_rotated_shape() {
+        let mut shapes = Vec::new();
+        for connections in 0..16 {
+            let (shape, turns) = WallShape::from_connections(connections);
+            assert!(turns < 4);
+            assert_eq!(rotate_connections(modeled(shape), turns), connections);
+            shapes.push(shape);
+        }
+        let count = |shape| shapes.iter().filter(|other| **other == shape).count();
+        assert_eq!(count(WallShape::Single), 1);
+        assert_eq!(count(WallShape::End), 4);
+        assert_eq!(count(WallShape::Straight), 2);
+        assert_eq!(count(WallShape::Corner), 4);
+        assert_eq!(count(WallShape::Tee), 4);
+        assert_eq!(count(WallShape::Cross), 1);
+    }
+
+    #[test]
+    fn shapes_turn_counterclockwise() {
+        // +y
+        assert_eq!(WallShape::from_connections(0b0010), (WallShape::End, 1));
+        // +y and -y
+        assert_eq!(
+            WallShape::from_connections(0b1010),
+            (WallShape::Straight, 1)
+        );
+        // -x and -y
+        assert_eq!(WallShape::from_connections(0b1100), (WallShape::Corner, 2));
+        // Everything but -x
+        assert_eq!(WallShape::from_connections(0b1011), (WallShape::Tee, 3));
+    }
+
+    #[test]
+    fn connections_follow_the_walls_on_the_deck() {
+        // An L of walls, and a wall on the deck above the corner
+        let walls: HashSet<Point3<i16>> = [(0, 0, 0), (1, 0, 0), (2, 0, 0), (0, 1, 0), (0, 0, 1)]
+            .iter()
+            .map(|(x, y, z)| Point3::new(*x, *y, *z))
+            .collect();
+        let connections = |x, y| wall_connections(Point3::new(x, y, 0), |pos| walls.contains(&pos));
+
+        assert_eq!(connections(0, 0), 0b0011);
+        assert_eq!(connections(1, 0), 0b0101);
+        assert_eq!(connections(2, 0), 0b0100);
+        assert_eq!(connections(0, 1), 0b1000);
+        assert_eq!(connections(1, 1), 0b1100);
+        assert_eq!(connections(5, 5), 0b0000);
+        assert_eq!(
+            WallShape::from_connections(connections(0, 0)).0,
+            WallShape::Corner
+        );
+        assert_eq!(
+            WallShape::from_connections(connections(2, 0)),
+            (WallShape::End, 2)
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use super::{gameplay::GameLog, GameModule, Ship, Time};
+use crate::i18n::Strings;
+use crate::item::{self, GameItem, Inventory};
+use crate::profiler::AddProfiled;
+use specs::{prelude::*, Component};
+
+/// Wear builds up on gadgets as they are used, and worn gadgets work less well until they
+/// are serviced
+pub struct WearModule;
+
+impl GameModule for WearModule {
+    fn register(&self, world: &mut World) {
+        world.register::<Wear>();
+    }
+
+    fn systems(&self, builder: &mut DispatcherBuilder) {
+        builder.add_profiled(WearSystem, "wear_system", &[]);
+    }
+}
+
+/// How worn down a gadget is (ex. from the seconds its beam has been on). It is serviced
+/// slowly by repair bays, or all at once by hand, which takes the gadget out of action for
+/// a few seconds.
+#[derive(Component, Default)]
+#[storage(HashMapStorage)]
+pub struct Wear {
+    /// From 0 (new) to Wear::MAX (worn out)
+    amount: f32,
+    /// The seconds until the gadget is done being serviced by hand
+    servicing: f32,
+}
+
+impl Wear {
+    pub const MAX: f32 = 100.0;
+    /// Gadgets work at full strength until their wear passes this
+    const THRESHOLD: f32 = 40.0;
+    /// How well a gadget works once it is worn out
+    const MIN_EFFICIENCY: f32 = 0.4;
+    /// The wear from each second a beam is on (lasers and repulsors)
+    pub const PER_BEAM_SECOND: f32 = 0.5;
+    /// The wear from each shot (miners)
+    pub const PER_SHOT: f32 = 1.5;
+    /// The wear from each second a gadget spends at its maximum heat
+    pub const PER_HEAT_SECOND: f32 = 1.0;
+    /// The seconds a gadget is out of action while it is serviced by hand
+    const SERVICE_TIME: f32 = 3.0;
+    /// The cost of servicing a gadget by hand
+    const SERVICE_COST: [(GameItem, u32); 2] = [(GameItem::Iron, 2), (GameItem::Copper, 2)];
+
+    pub fn amount(&self) -> f32 {
+        self.amount
+    }
+
+    pub fn add(&mut self, amount: f32) {
+        if crate::BLOCK_WEAR {
+            self.amount = (self.amount + amount).min(Wear::MAX);
+        }
+    }
+
+    pub fn restore(&mut self, amount: f32) {
+        self.amount = (self.amount - amount).max(0.0);
+    }
+
+    /// How well (0 to 1) the gadget works. It eases from full strength at the threshold
+    /// down to the minimum once the gadget is worn out.
+    pub fn efficiency(&self) -> f32 {
+        let worn =
+            ((self.amount - Wear::THRESHOLD) / (Wear::MAX - Wear::THRESHOLD)).clamp(0.0, 1.0);
+        let eased = worn * worn * (3.0 - 2.0 * worn);
+        1.0 - eased * (1.0 - Wear::MIN_EFFICIENCY)
+    }
+
+    /// Whether the gadget is out of action while it is serviced by hand
+    pub fn is_servicing(&self) -> bool {
+        self.servicing > 0.0
+    }
+
+    pub fn tooltip(entity: Entity, world: &World) -> String {
+        match world.read_component::<Wear>().get(entity) {
+            Some(wear) => wear.describe(&world.fetch::<Strings>()),
+            None => String::new(),
+        }
+    }
+
+    /// Ex. "Servicing", or "Wear 55% (90% efficiency)"
+    pub fn describe(&self, strings: &Strings) -> String {
+        if self.is_servicing() {
+            return strings.get("wear.servicing").to_string();
+        }
+        strings.format(
+            "wear.describe",
+            &[
+                ("wear", &format!("{:.0}", self.amount / Wear::MAX * 100.0)),
+                ("efficiency", &format!("{:.0}", self.efficiency() * 100.0)),
+            ],
+        )
+    }
+}
+
+/// The efficiency of the gadget. Gadgets without wear always work at full strength.
+pub fn efficiency(wears: &WriteStorage<Wear>, entity: Entity) -> f32 {
+    wears.get(entity).map_or(1.0, Wear::efficiency)
+}
+
+/// Whether the gadget is out of action while it is serviced by hand
+pub fn is_servicing(wears: &WriteStorage<Wear>, entity: Entity) -> bool {
+    matches!(wears.get(entity), Some(wear) if wear.is_servicing())
+}
+
+/// Services the gadgets of the ship whose wear affects them, as long as the inventory can
+/// pay for them
+pub fn service_worn_gadgets(world: &World) {
+    let ships = world.read_component::<Ship>();
+    let mut wears = world.write_component::<Wear>();
+    let mut inventory = world.fetch_mut::<Inventory>();
+    let (mut worn, mut serviced) = (0, 0);
+
+    for ship in ships.join() {
+        for gadget in ship.gadgets() {
+            let wear = match wears.get_mut(gadget) {
+                Some(wear) if wear.amount > Wear::THRESHOLD && !wear.is_servicing() => wear,
+                _ => continue,
+            };
+            worn += 1;
+            if !inventory.has_items(&Wear::SERVICE_COST) {
+                continue;
+            }
+            inventory.remove_items(&Wear::SERVICE_COST);
+            wear.servicing = Wear::SERVICE_TIME;
+            serviced += 1;
+        }
+    }
+
+    let strings = world.fetch::<Strings>();
+    let message = if serviced > 0 {
+        strings.format("log.servicing", &[("count", &serviced)])
+    } else if worn == 0 {
+        strings.get("log.nothing_to_service").to_string()
+    } else {
+        strings.format(
+            "error.service_cost",
+            &[("cost", &item::describe_cost(&Wear::SERVICE_COST, &strings))],
+        )
+    };
+    world.fetch_mut::<GameLog>().add(message);
+}
+
+/// Finishes servicing the gadgets, which removes all of their wear
+pub struct WearSystem;
+
+impl<'a> System<'a> for WearSystem {
+    type SystemData = (ReadExpect<'a, Time>, WriteStorage<'a, Wear>);
+
+    fn run(&mut self, (time, mut wears): Self::SystemData) {
+        for wear in (&mut wears).join() {
+            if wear.is_servicing() && time.count_down(&mut wear.servicing) {
+                wear.servicing = 0.0;
+                wear.amount = 0.0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Laser;
+    use crate::entity::{headless::HeadlessEcs, objects::Health, Tile};
+    use cgmath::{Point3, Vector3};
+
+    fn worn(amount: f32) -> Wear {
+        Wear {
+            amount,
+            servicing: 0.0,
+        }
+    }
+
+    #[test]
+    fn efficiency_eases_down_past_the_threshold() {
+        assert_eq!(worn(0.0).efficiency(), 1.0);
+        assert_eq!(worn(Wear::THRESHOLD).efficiency(), 1.0);
+        assert!((worn(Wear::MAX).efficiency() - Wear::MIN_EFFICIENCY).abs() < 1e-6);
+        // Halfway between the threshold and worn out, the efficiency is halfway down
+        let halfway = (Wear::THRESHOLD + Wear::MAX) / 2.0;
+        let expected = 1.0 - (1.0 - Wear::MIN_EFFICIENCY) / 2.0;
+        assert!((worn(halfway).efficiency() - expected).abs() < 1e-6);
+        // Eased, so it drops slowly at both ends
+        assert!(worn(Wear::THRESHOLD + 6.0).efficiency() > 0.98);
+        assert!(worn(Wear::MAX - 6.0).efficiency() < Wear::MIN_EFFICIENCY + 0.02);
+
+        let curve: Vec<f32> = (0..=100)
+            .map(|amount| worn(amount as f32).efficiency())
+            .collect();
+        assert!(curve.windows(2).all(|pair| pair[1] <= pair[0]));
+    }
+
+    #[test]
+    fn wear_stays_in_range() {
+        let mut wear = Wear::default();
+        wear.add(Wear::MAX * 2.0);
+        assert_eq!(wear.amount(), Wear::MAX);
+        wear.restore(Wear::MAX * 2.0);
+        assert_eq!(wear.amount(), 0.0);
+    }
+
+    #[test]
+    fn servicing_takes_the_gadget_out_of_action() {
+        let mut headless = HeadlessEcs::with_seed(1);
+        headless.pause_asteroid_field();
+        let laser = headless
+            .with_ship(|ship| ship.tile(Point3::new(0, 0, 0)).and_then(Tile::gadget))
+            .flatten()
+            .unwrap();
+        let asteroid = headless.spawn_asteroid(
+            Vector3::new(-5.0, -5.0, 2.0),
+            Vector3::new(0.0, 0.0, 0.0),
+            100.0,
+        );
+        {
+            let world = &headless.ecs.world;
+            world
+                .write_storage::<Laser>()
+                .get_mut(laser)
+                .unwrap()
+                .set_auto(true);
+            world.write_storage::<Wear>().get_mut(laser).unwrap().amount = Wear::MAX;
+            world
+                .fetch_mut::<Inventory>()
+                .add_items(&Wear::SERVICE_COST);
+            service_worn_gadgets(world);
+            assert!(world
+                .read_storage::<Wear>()
+                .get(laser)
+                .unwrap()
+                .is_servicing());
+        }
+        let health = |headless: &HeadlessEcs| {
+            let healths = headless.ecs.world.read_storage::<Health>();
+            healths.get(asteroid).map(Health::health).unwrap()
+        };
+        let start = health(&headless);
+
+        // The laser doesn't fire until it has been serviced
+        let rate = Time::DEFAULT_RATE as f32;
+        headless.step(((Wear::SERVICE_TIME - 0.1) * rate) as u32);
+        assert_eq!(health(&headless), start);
+        assert!(headless
+            .ecs
+            .world
+            .read_storage::<Wear>()
+            .get(laser)
+            .unwrap()
+            .is_servicing());
+
+        headless.step((0.2 * rate) as u32);
+        {
+            let wears = headless.ecs.world.read_storage::<Wear>();
+            let wear = wears.get(laser).unwrap();
+            assert!(!wear.is_servicing());
+            // The laser fired again in the ticks since it was serviced
+            assert!(wear.amount() < Wear::PER_BEAM_SECOND * 0.2);
+        }
+        headless.step(Time::DEFAULT_RATE);
+        assert!(health(&headless) < start);
+    }
+}
@@ -1,29 +1,133 @@
 use crate::graphics::{Mesh, MeshId, MeshManager};
+use crate::item::GameItem;
 use cgmath::Point3;
 
-#[derive(Clone, Copy)]
-pub struct Floor(MeshId);
+/// Identifies a kind of floor in the Floors
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Floor(usize);
 
-impl Into<MeshId> for Floor {
-    fn into(self) -> MeshId {
-        self.0
-    }
+/// A kind of floor. Floors without any properties are only cosmetic. The floors that are
+/// only built by players are found by their name.
+pub struct FloorType {
+    /// The name that the floor is saved with (ex. in blueprints) and found by in commands
+    pub name: &'static str,
+    pub mesh_id: MeshId,
+    pub cost: Vec<(GameItem, u32)>,
+    pub properties: FloorProperties,
+}
+
+/// What a floor does for its ship. New kinds of floors only need their values here, the
+/// systems that use them don't know about any particular floor.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FloorProperties {
+    /// The fraction of damage that the blocks on the floor, or next to it on the same deck,
+    /// don't take. A block only gets the most protective floor around it.
+    pub damage_reduction: f32,
+    /// The heat the floor removes from its ship each second (see heat::simulate)
+    pub dissipation: f32,
+    /// How fast the floor carries the item drops near it toward the ship's core, in units
+    /// per second (see drops::CollectorSystem)
+    pub conveyor_speed: f32,
 }
 
 pub struct Floors {
+    floors: Vec<FloorType>,
     pub metal: Floor,
     pub dirt: Floor,
 }
 
-pub fn load_floors(device: &wgpu::Device, mesh_manager: &mut MeshManager) -> Floors {
+impl Floors {
+    pub fn get(&self, floor: Floor) -> &FloorType {
+        &self.floors[floor.0]
+    }
+
+    pub fn name(&self, floor: Floor) -> &'static str {
+        self.get(floor).name
+    }
+
+    pub fn find(&self, name: &str) -> Option<Floor> {
+        self.floors
+            .iter()
+            .position(|floor| floor.name == name)
+            .map(Floor)
+    }
+
+    /// The names of every floor, ex. for the errors of commands
+    pub fn names(&self) -> Vec<&'static str> {
+        self.floors.iter().map(|floor| floor.name).collect()
+    }
+}
+
+/// `add_mesh` adds the mesh of a floor with its name, and returns its MeshId
+fn create_floors(mut add_mesh: impl FnMut(&str, &Mesh) -> MeshId) -> Floors {
+    let mut floors = Vec::new();
+    let mut create_floor = |name: &'static str,
+                            (height, color): (f32, Point3<f32>),
+                            cost: &[(GameItem, u32)],
+                            properties: FloorProperties| {
+        let mesh = Mesh::rectangular_prism(1.0, 1.0, height, color);
+        floors.push(FloorType {
+            name,
+            mesh_id: add_mesh(&format!("{}_floor", name), &mesh),
+            cost: cost.to_vec(),
+            properties,
+        });
+        Floor(floors.len() - 1)
+    };
+
+    let metal = create_floor(
+        "metal",
+        (0.1, Point3::new(0.9, 0.9, 1.0)),
+        &[],
+        FloorProperties::default(),
+    );
+    let dirt = create_floor(
+        "dirt",
+        (0.1, Point3::new(0.14, 0.08, 0.08)),
+        &[],
+        FloorProperties::default(),
+    );
+    // Reinforced floors are thicker, so they can be told apart from metal floors
+    create_floor(
+        "reinforced",
+        (0.15, Point3::new(0.45, 0.47, 0.52)),
+        &[(GameItem::Iron, 3)],
+        FloorProperties {
+            damage_reduction: 0.25,
+            ..FloorProperties::default()
+        },
+    );
+    create_floor(
+        "vent",
+        (0.1, Point3::new(0.3, 0.55, 0.65)),
+        &[(GameItem::Iron, 1), (GameItem::Copper, 2)],
+        FloorProperties {
+            dissipation: 0.3,
+            ..FloorProperties::default()
+        },
+    );
+    create_floor(
+        "conveyor",
+        (0.1, Point3::new(0.9, 0.65, 0.15)),
+        &[(GameItem::Iron, 2), (GameItem::Copper, 1)],
+        FloorProperties {
+            conveyor_speed: 1.5,
+            ..FloorProperties::default()
+        },
+    );
+
     Floors {
-        metal: Floor(mesh_manager.add(
-            device,
-            &Mesh::rectangular_prism(1.0, 1.0, 0.1, Point3::new(0.9, 0.9, 1.0)),
-        )),
-        dirt: Floor(mesh_manager.add(
-            device,
-            &Mesh::rectangular_prism(1.0, 1.0, 0.1, Point3::new(0.14, 0.08, 0.08)),
-        )),
+        floors,
+        metal,
+        dirt,
     }
 }
+
+pub fn load_floors(device: &wgpu::Device, mesh_manager: &mut MeshManager) -> Floors {
+    create_floors(|_, mesh| mesh_manager.add(device, mesh))
+}
+
+/// Registers the floors with placeholder meshes, for a simulation without a GPU
+pub fn load_floors_headless(mesh_manager: &mut MeshManager) -> Floors {
+    create_floors(|name, _| mesh_manager.add_placeholder(name))
+}
@@ -0,0 +1,62 @@
+//! Reads the gamepads with gilrs, and passes their buttons and left stick to the
+//! InputManager. Every connected gamepad can be used, and they all act as one.
+use crate::entity::{input::PadButton, InputManager};
+use gilrs::{Axis, Button, EventType, Gilrs};
+
+pub struct GamepadInput {
+    gilrs: Gilrs,
+}
+
+impl GamepadInput {
+    /// Returns None if the gamepads can't be read on this system
+    pub fn new() -> Option<Self> {
+        match Gilrs::new() {
+            Ok(gilrs) => Some(GamepadInput { gilrs }),
+            Err(error) => {
+                println!("[Gamepad] Unable to read the gamepads: {}", error);
+                None
+            }
+        }
+    }
+
+    /// Handles the events since the last poll
+    pub fn poll(&mut self, input: &mut InputManager) {
+        while let Some(event) = self.gilrs.next_event() {
+            match event.event {
+                EventType::ButtonPressed(button, _) => {
+                    if let Some(button) = pad_button(button) {
+                        input.pad.update(button, true);
+                    }
+                }
+                EventType::ButtonReleased(button, _) => {
+                    if let Some(button) = pad_button(button) {
+                        input.pad.update(button, false);
+                    }
+                }
+                EventType::AxisChanged(Axis::LeftStickX, value, _) => input.pad.stick.x = value,
+                EventType::AxisChanged(Axis::LeftStickY, value, _) => input.pad.stick.y = value,
+                // A button held on the gamepad would otherwise stay down
+                EventType::Disconnected => input.pad.clear(),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// The button as it is used by the build tools. gilrs names the face buttons by where they
+/// are, and the shoulder buttons are its upper triggers.
+fn pad_button(button: Button) -> Option<PadButton> {
+    match button {
+        Button::South => Some(PadButton::Confirm),
+        Button::East => Some(PadButton::Cancel),
+        Button::West => Some(PadButton::Undo),
+        Button::North => Some(PadButton::Picker),
+        Button::LeftTrigger => Some(PadButton::TurnLeft),
+        Button::RightTrigger => Some(PadButton::TurnRight),
+        Button::DPadUp => Some(PadButton::Up),
+        Button::DPadDown => Some(PadButton::Down),
+        Button::DPadLeft => Some(PadButton::Left),
+        Button::DPadRight => Some(PadButton::Right),
+        _ => None,
+    }
+}
@@ -0,0 +1,327 @@
+use super::{color, Camera, Renderer};
+use cgmath::{Vector3, Vector4};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::mem;
+use std::time::Instant;
+use wgpu::util::DeviceExt;
+
+/// A rock or star of the backdrop, drawn as a quad that faces the camera
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct BackdropSprite {
+    pos: Vector3<f32>,
+    /// Half of the quad's width and height
+    size: f32,
+    color: Vector4<f32>,
+    /// How fast the sprite drifts along x, wrapping around at Backdrop::ROCK_SPAN
+    speed: f32,
+    /// How much of the camera's movement the sprite follows. The farther away something
+    /// is, the less it seems to move when the camera moves, so the stars follow all of it.
+    follow: f32,
+    /// 0 for a star (a soft dot), and 1 for a rock (a lumpy disc that is lit like a sphere)
+    shape: f32,
+    /// Picks the lumps of a rock
+    seed: f32,
+}
+
+unsafe impl bytemuck::Pod for BackdropSprite {}
+unsafe impl bytemuck::Zeroable for BackdropSprite {}
+
+/// The camera's position (with the seconds since the backdrop was created in w), and its
+/// right and up vectors
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct BackdropView {
+    camera: Vector4<f32>,
+    right: Vector4<f32>,
+    up: Vector4<f32>,
+}
+
+unsafe impl bytemuck::Pod for BackdropView {}
+unsafe impl bytemuck::Zeroable for BackdropView {}
+
+/// The distant rocks and stars behind the scene. They are only drawn, so nothing in the game
+/// (ex. raycasts or the asteroid field) knows about them. The sprites never change, so each
+/// kind is one instanced draw from a buffer that is filled once, and the drift is done in
+/// the vertex shader.
+pub struct Backdrop {
+    rock_buffer: wgpu::Buffer,
+    star_buffer: wgpu::Buffer,
+    view_buffer: wgpu::Buffer,
+    view_bgl: wgpu::BindGroupLayout,
+    view_bg: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    start: Instant,
+}
+
+impl Backdrop {
+    /// The backdrop looks the same every time
+    const SEED: u64 = 0x6261_636b_6472_6f70;
+    const ROCK_COUNT: usize = 300;
+    /// The rocks drift through -ROCK_SPAN..ROCK_SPAN in x, like the asteroids do through the
+    /// field. Needs to match the vertex shader.
+    const ROCK_SPAN: f32 = 300.0;
+    /// How far the rocks are to the sides of the field, and above or below it
+    const ROCK_DISTANCE: (f32, f32) = (140.0, 260.0);
+    const ROCK_HEIGHT: (f32, f32) = (-50.0, 40.0);
+    const ROCK_SIZE: (f32, f32) = (1.5, 5.0);
+    const ROCK_SPEED: (f32, f32) = (0.5, 2.0);
+    const ROCK_FOLLOW: f32 = 0.9;
+    const STAR_COUNT: usize = 1200;
+    const STAR_DISTANCE: f32 = 400.0;
+    const STAR_SIZE: (f32, f32) = (0.6, 1.6);
+
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bgl: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Backdrop {
+        let mut rng = StdRng::seed_from_u64(Backdrop::SEED);
+        let rocks = Backdrop::generate_rocks(&mut rng);
+        let stars = Backdrop::generate_stars(&mut rng);
+        let rock_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Backdrop Rock Buffer"),
+            contents: bytemuck::cast_slice(&rocks),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+        let star_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Backdrop Star Buffer"),
+            contents: bytemuck::cast_slice(&stars),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+
+        let view_buffer_size = mem::size_of::<BackdropView>() as u64;
+        let view_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Backdrop View Buffer"),
+            size: view_buffer_size,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let view_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: Some(std::num::NonZeroU64::new(view_buffer_size).unwrap()),
+                },
+                count: None,
+            }],
+            label: Some("Backdrop View Bind Group Layout"),
+        });
+
+        let view_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &view_bgl,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer {
+                    buffer: &view_buffer,
+                    offset: 0,
+                    size: Some(std::num::NonZeroU64::new(view_buffer_size).unwrap()),
+                },
+            }],
+            label: Some("Backdrop View Bind Group"),
+        });
+
+        let pipeline = create_pipeline(device, camera_bgl, &view_bgl, format, sample_count);
+
+        Self {
+            rock_buffer,
+            star_buffer,
+            view_buffer,
+            view_bgl,
+            view_bg,
+            pipeline,
+            start: Instant::now(),
+        }
+    }
+
+    /// The rocks are spread through a band on both sides of the field, and sorted from the
+    /// farthest to the nearest, so the nearer ones are blended over the others
+    fn generate_rocks(rng: &mut StdRng) -> Vec<BackdropSprite> {
+        let mut rocks: Vec<BackdropSprite> = (0..Backdrop::ROCK_COUNT)
+            .map(|_| {
+                let side = if rng.gen() { 1.0 } else { -1.0 };
+                let (min, max) = Backdrop::ROCK_DISTANCE;
+                let pos = Vector3::new(
+                    rng.gen_range(-Backdrop::ROCK_SPAN..Backdrop::ROCK_SPAN),
+                    side * rng.gen_range(min..max),
+                    rng.gen_range(Backdrop::ROCK_HEIGHT.0..Backdrop::ROCK_HEIGHT.1),
+                );
+                let shade = rng.gen_range(0.3..0.5);
+                BackdropSprite {
+                    pos,
+                    size: rng.gen_range(Backdrop::ROCK_SIZE.0..Backdrop::ROCK_SIZE.1),
+                    color: color::to_linear_alpha(Vector4::new(
+                        shade,
+                        shade * 0.9,
+                        shade * 0.8,
+                        1.0,
+                    )),
+                    speed: rng.gen_range(Backdrop::ROCK_SPEED.0..Backdrop::ROCK_SPEED.1),
+                    follow: Backdrop::ROCK_FOLLOW,
+                    shape: 1.0,
+                    seed: rng.gen(),
+                }
+            })
+            .collect();
+        let distance = |rock: &BackdropSprite| rock.pos.y.hypot(rock.pos.z);
+        rocks.sort_by(|a, b| distance(b).partial_cmp(&distance(a)).unwrap());
+        rocks
+    }
+
+    /// The stars are spread evenly around a sphere that moves with the camera
+    fn generate_stars(rng: &mut StdRng) -> Vec<BackdropSprite> {
+        (0..Backdrop::STAR_COUNT)
+            .map(|_| {
+                let z: f32 = rng.gen_range(-1.0..1.0);
+                let angle = rng.gen_range(0.0..crate::PI * 2.0);
+                let ring = (1.0 - z * z).sqrt();
+                let direction = Vector3::new(ring * angle.cos(), ring * angle.sin(), z);
+                let brightness = rng.gen_range(0.3..0.9);
+                // Some stars are slightly blue and some slightly yellow
+                let warmth = rng.gen_range(-0.1..0.1);
+                let color = Vector4::new(
+                    brightness * (1.0 + warmth),
+                    brightness,
+                    brightness * (1.0 - warmth),
+                    1.0,
+                );
+                BackdropSprite {
+                    pos: direction * Backdrop::STAR_DISTANCE,
+                    size: rng.gen_range(Backdrop::STAR_SIZE.0..Backdrop::STAR_SIZE.1),
+                    color: color::to_linear_alpha(color),
+                    speed: 0.0,
+                    follow: 1.0,
+                    shape: 0.0,
+                    seed: 0.0,
+                }
+            })
+            .collect()
+    }
+
+    /// Recreates the pipeline for a new MSAA sample count
+    pub fn rebuild_pipeline(
+        &mut self,
+        device: &wgpu::Device,
+        camera_bgl: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) {
+        self.pipeline = create_pipeline(device, camera_bgl, &self.view_bgl, format, sample_count);
+    }
+
+    pub fn prepare(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let (position, _, _) = camera.view();
+        let axes = camera.billboard_axes();
+        let seconds = self.start.elapsed().as_secs_f32();
+        let view = BackdropView {
+            camera: Vector4::new(position.x, position.y, position.z, seconds),
+            right: axes.right,
+            up: axes.up,
+        };
+        queue.write_buffer(&self.view_buffer, 0, bytemuck::cast_slice(&[view]));
+    }
+
+    /// Needs to be drawn before the rest of the scene, which is drawn over it
+    pub fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, camera_bg: &'a wgpu::BindGroup) {
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, camera_bg, &[]);
+        rpass.set_bind_group(1, &self.view_bg, &[]);
+        rpass.set_vertex_buffer(0, self.star_buffer.slice(..));
+        rpass.draw(0..6, 0..Backdrop::STAR_COUNT as u32);
+        rpass.set_vertex_buffer(0, self.rock_buffer.slice(..));
+        rpass.draw(0..6, 0..Backdrop::ROCK_COUNT as u32);
+    }
+}
+
+/// The sprites are blended with their alpha, and always end up behind the rest of the scene
+fn create_pipeline(
+    device: &wgpu::Device,
+    camera_bgl: &wgpu::BindGroupLayout,
+    view_bgl: &wgpu::BindGroupLayout,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    let vertex_bytes = super::read_required_asset("shaders/backdrop.vert.spv");
+    let vertex_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some("Backdrop Vertex Shader"),
+        source: wgpu::util::make_spirv(&vertex_bytes),
+        flags: wgpu::ShaderFlags::VALIDATION,
+    });
+
+    let frag_bytes = super::read_required_asset("shaders/backdrop.frag.spv");
+    let frag_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some("Backdrop Fragment Shader"),
+        source: wgpu::util::make_spirv(&frag_bytes),
+        flags: wgpu::ShaderFlags::VALIDATION,
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Backdrop Pipeline Layout"),
+        bind_group_layouts: &[camera_bgl, view_bgl],
+        push_constant_ranges: &[],
+    });
+
+    let alpha_blend = wgpu::BlendState {
+        src_factor: wgpu::BlendFactor::SrcAlpha,
+        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+        operation: wgpu::BlendOperation::Add,
+    };
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Backdrop Pipeline"),
+        layout: Some(&pipeline_layout),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+        },
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        // The vertex shader puts the sprites at the far plane, and they don't write the
+        // depth, so everything that is drawn after them is drawn over them
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Renderer::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+            clamp_depth: false,
+        }),
+        vertex: wgpu::VertexState {
+            module: &vertex_shader,
+            entry_point: "main",
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: mem::size_of::<BackdropSprite>() as wgpu::BufferAddress,
+                step_mode: wgpu::InputStepMode::Instance,
+                attributes: &wgpu::vertex_attr_array![
+                    0 => Float3,
+                    1 => Float,
+                    2 => Float4,
+                    3 => Float,
+                    4 => Float,
+                    5 => Float,
+                    6 => Float],
+            }],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &frag_shader,
+            entry_point: "main",
+            targets: &[wgpu::ColorTargetState {
+                format,
+                color_blend: alpha_blend.clone(),
+                alpha_blend,
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+        }),
+    })
+}
@@ -0,0 +1,120 @@
+use cgmath::{Vector2, Vector3, Vector4};
+use std::mem;
+
+/// A flat colored rectangle that always faces the camera.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct GPUBillboard {
+    pub pos: Vector3<f32>,
+    /// The offset of the bottom left corner from `pos` (in the camera's right / up directions)
+    pub offset: Vector2<f32>,
+    pub size: Vector2<f32>,
+    pub color: Vector4<f32>,
+}
+
+unsafe impl bytemuck::Pod for GPUBillboard {}
+unsafe impl bytemuck::Zeroable for GPUBillboard {}
+
+pub struct BillboardRenderer {
+    pub instance_buffer: wgpu::Buffer,
+    pub pipeline: wgpu::RenderPipeline,
+}
+
+impl BillboardRenderer {
+    pub const MAX_BILLBOARDS: u64 = 512;
+
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bgl: &wgpu::BindGroupLayout,
+        billboard_bgl: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> BillboardRenderer {
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Billboard Buffer"),
+            size: BillboardRenderer::MAX_BILLBOARDS * mem::size_of::<GPUBillboard>() as u64,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let vertex_bytes = super::read_required_asset("shaders/billboard.vert.spv");
+        let vertex_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Billboard Vertex Shader"),
+            source: wgpu::util::make_spirv(&vertex_bytes),
+            flags: wgpu::ShaderFlags::VALIDATION,
+        });
+
+        let frag_bytes = super::read_required_asset("shaders/billboard.frag.spv");
+        let frag_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Billboard Fragment Shader"),
+            source: wgpu::util::make_spirv(&frag_bytes),
+            flags: wgpu::ShaderFlags::VALIDATION,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Billboard Pipeline Layout"),
+            bind_group_layouts: &[camera_bgl, billboard_bgl],
+            push_constant_ranges: &[],
+        });
+
+        let alpha_blend = wgpu::BlendState {
+            src_factor: wgpu::BlendFactor::SrcAlpha,
+            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+            },
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            // Billboards are indicators, so they are drawn on top of everything in the world
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: super::Renderer::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+                clamp_depth: false,
+            }),
+            vertex: wgpu::VertexState {
+                module: &vertex_shader,
+                entry_point: "main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: mem::size_of::<GPUBillboard>() as wgpu::BufferAddress,
+                    step_mode: wgpu::InputStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![
+                        0 => Float3,
+                        1 => Float2,
+                        2 => Float2,
+                        3 => Float4],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_shader,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    color_blend: alpha_blend.clone(),
+                    alpha_blend,
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+        });
+
+        Self {
+            instance_buffer,
+            pipeline,
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use super::{Camera, Projection};
+use cgmath::{prelude::*, Vector3};
+use specs::Entity;
+
+/// How the CameraController moves the camera
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraMode {
+    /// Only moved by the player
+    Free,
+    /// Keeps the center of the selected ship framed, so the view pans as the ship grows
+    FollowShip,
+    /// Briefly looks at something dramatic (ex. a volatile asteroid that just spawned), and
+    /// then goes back to the previous mode
+    FocusEntity(Entity),
+}
+
+/// Offsets the view without moving the camera, so the shake never adds up. It is applied to
+/// the view matrix, which is also used to pick what is under the cursor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraShake {
+    pub offset: Vector3<f32>,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl CameraShake {
+    pub const NONE: CameraShake = CameraShake {
+        offset: Vector3::new(0.0, 0.0, 0.0),
+        yaw: 0.0,
+        pitch: 0.0,
+    };
+}
+
+/// Moves the camera towards what it follows, and shakes it when something hits the ship.
+/// The headless simulation has no controller, so the camera is only moved by the systems
+/// that already move it (ex. the CameraSystem).
+pub struct CameraController {
+    mode: CameraMode,
+    /// The mode to go back to once the focus ends
+    previous: CameraMode,
+    focus_timer: f32,
+    focus_cooldown: f32,
+    /// The velocity of the spring that pulls the camera to its target
+    velocity: Vector3<f32>,
+    /// How much the camera shakes, from 0 to 1. The shake grows with the square of the
+    /// trauma, so small bumps are subtle.
+    trauma: f32,
+    /// How far along the shake's noise is
+    shake_time: f32,
+}
+
+impl CameraController {
+    /// How quickly the camera catches up with its target (the spring's angular frequency)
+    const STIFFNESS: f32 = 4.0;
+    const FOCUS_TIME: f32 = 2.5;
+    /// The time after a focus ends before the camera can be focused again
+    const FOCUS_COOLDOWN: f32 = 12.0;
+    /// Trauma lost per second
+    const TRAUMA_DECAY: f32 = 1.2;
+    const MAX_SHAKE_OFFSET: f32 = 0.35;
+    /// Radians
+    const MAX_SHAKE_ANGLE: f32 = 0.03;
+    /// How many times per second the shake changes direction
+    const SHAKE_FREQUENCY: f32 = 18.0;
+
+    pub fn mode(&self) -> CameraMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        if let CameraMode::FocusEntity(_) = mode {
+            self.previous = self.mode;
+            self.focus_timer = CameraController::FOCUS_TIME;
+        }
+        self.mode = mode;
+    }
+
+    /// Switches between following the ship and moving freely. Ends any focus.
+    pub fn toggle_follow(&mut self) {
+        let following = match self.mode {
+            CameraMode::FocusEntity(_) => self.previous == CameraMode::FollowShip,
+            mode => mode == CameraMode::FollowShip,
+        };
+        self.mode = if following {
+            CameraMode::Free
+        } else {
+            CameraMode::FollowShip
+        };
+    }
+
+    /// Briefly looks at the entity, unless the camera has focused on something recently
+    pub fn focus(&mut self, entity: Entity) {
+        if self.focus_cooldown <= 0.0 && !matches!(self.mode, CameraMode::FocusEntity(_)) {
+            self.set_mode(CameraMode::FocusEntity(entity));
+        }
+    }
+
+    pub fn add_trauma(&mut self, amount: f32) {
+        if !crate::REDUCED_MOTION {
+            self.trauma = (self.trauma + amount).min(1.0);
+        }
+    }
+
+    /// Moves the camera towards the target of the current mode, and shakes it. The target is
+    /// None when there is nothing to look at (ex. the focused entity has no transform yet).
+    pub fn update(&mut self, camera: &mut Camera, target: Option<Vector3<f32>>, delta: f32) {
+        if let CameraMode::FocusEntity(_) = self.mode {
+            self.focus_timer -= delta;
+            // Focusing would pull the build view away from what is being built
+            let building = matches!(camera.projection, Projection::Orthographic { .. });
+            if self.focus_timer <= 0.0 || building {
+                self.mode = self.previous;
+                self.focus_cooldown = CameraController::FOCUS_COOLDOWN;
+            }
+        } else {
+            self.focus_cooldown -= delta;
+        }
+
+        match (self.mode, target) {
+            (CameraMode::Free, _) | (_, None) => self.velocity = Vector3::zero(),
+            (_, Some(target)) => {
+                // Keeps the camera's height and angle, so only the point it looks at changes
+                let direction = camera.direction();
+                let distance = if direction.z < -0.1 {
+                    (camera.position.z - target.z) / -direction.z
+                } else {
+                    camera.position.z - target.z
+                };
+                let goal = target - direction * distance;
+
+                // A critically damped spring, which settles as fast as it can without
+                // overshooting
+                let stiffness = CameraController::STIFFNESS;
+                let acceleration = (goal - camera.position.to_vec()) * stiffness * stiffness
+                    - self.velocity * 2.0 * stiffness;
+                self.velocity += acceleration * delta;
+                camera.position += self.velocity * delta;
+            }
+        }
+
+        self.trauma = (self.trauma - CameraController::TRAUMA_DECAY * delta).max(0.0);
+        self.shake_time += delta * CameraController::SHAKE_FREQUENCY;
+        let shake = self.trauma * self.trauma;
+        let noise = |channel: u32| smooth_noise(channel, self.shake_time) * shake;
+        camera.shake = CameraShake {
+            offset: Vector3::new(noise(0), noise(1), noise(2)) * CameraController::MAX_SHAKE_OFFSET,
+            yaw: noise(3) * CameraController::MAX_SHAKE_ANGLE,
+            pitch: noise(4) * CameraController::MAX_SHAKE_ANGLE,
+        };
+    }
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::Free,
+            previous: CameraMode::Free,
+            focus_timer: 0.0,
+            focus_cooldown: 0.0,
+            velocity: Vector3::zero(),
+            trauma: 0.0,
+            shake_time: 0.0,
+        }
+    }
+}
+
+/// Noise from -1 to 1 that changes smoothly over time, made by easing between random values
+/// at each whole number. Each channel is a different sequence of values.
+fn smooth_noise(channel: u32, time: f32) -> f32 {
+    let value = |step: i32| {
+        let mut hash = (step as u32).wrapping_mul(0x9E37_79B9) ^ channel.wrapping_mul(0x85EB_CA6B);
+        hash ^= hash >> 15;
+        hash = hash.wrapping_mul(0x2C1B_3C6D);
+        hash ^= hash >> 12;
+        hash as f32 / u32::MAX as f32 * 2.0 - 1.0
+    };
+
+    let step = time.floor();
+    let t = time - step;
+    let eased = t * t * (3.0 - 2.0 * t);
+    value(step as i32) * (1.0 - eased) + value(step as i32 + 1) * eased
+}
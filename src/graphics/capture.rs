@@ -0,0 +1,333 @@
+use super::{culling::Mapping, GraphicsSettings};
+use futures::FutureExt;
+use image::codecs::gif::{GifEncoder, Repeat};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::BufWriter;
+use std::time::{Duration, Instant};
+use winit::event::VirtualKeyCode;
+
+pub const CAPTURE_DIR: &str = "saves/captures";
+
+/// Keeps the last seconds of the scene, so something that just happened can be saved as an
+/// animated GIF. While it is on, every few frames the scene is scaled down on the GPU and
+/// read back into a ring of frames. The UI is drawn straight to the window, so it is not
+/// in the captures.
+pub struct ReplayCapture {
+    enabled: bool,
+    target: Option<CaptureTarget>,
+    /// The oldest frame is first. Once the ring is full, the oldest frame's pixels are
+    /// reused for the newest frame.
+    frames: VecDeque<CapturedFrame>,
+    /// When the last frame was copied, which paces the captures to the frame rate
+    last_capture: Option<Instant>,
+    /// Set when a frame was copied this frame, until its readback is started
+    copied: Option<Instant>,
+    mapping: Option<(Instant, Mapping)>,
+}
+
+/// The texture that the scene is scaled down into, and the buffer it is read back with
+struct CaptureTarget {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    readback_buffer: wgpu::Buffer,
+    size: (u32, u32),
+    /// The rows of the readback buffer are padded to wgpu::COPY_BYTES_PER_ROW_ALIGNMENT
+    padded_row: u32,
+}
+
+struct CapturedFrame {
+    time: Instant,
+    /// RGBA, without any padding between the rows
+    pixels: Vec<u8>,
+}
+
+impl ReplayCapture {
+    pub const TOGGLE_KEY: VirtualKeyCode = VirtualKeyCode::F10;
+    pub const SAVE_KEY: VirtualKeyCode = VirtualKeyCode::F11;
+    /// Trades the quality of the colors for encoding speed (1 is the best quality, 30 the
+    /// fastest). Each frame gets its own pallete either way.
+    const ENCODE_SPEED: i32 = 10;
+
+    pub(super) fn new() -> Self {
+        Self {
+            enabled: false,
+            target: None,
+            frames: VecDeque::new(),
+            last_capture: None,
+            copied: None,
+            mapping: None,
+        }
+    }
+
+    /// The size of the captured frames for a window size
+    fn frame_size(settings: &GraphicsSettings, window_size: (u32, u32)) -> (u32, u32) {
+        let scale = |size: u32| ((size as f32 * settings.capture_scale).round() as u32).max(1);
+        (scale(window_size.0), scale(window_size.1))
+    }
+
+    /// How many frames the ring holds
+    fn capacity(settings: &GraphicsSettings) -> usize {
+        (settings.capture_fps * settings.capture_seconds) as usize
+    }
+
+    /// Starts or stops capturing. Stopping frees the frames.
+    pub(super) fn toggle(&mut self, settings: &GraphicsSettings, window_size: (u32, u32)) {
+        self.enabled = !self.enabled;
+        if self.enabled {
+            let (width, height) = ReplayCapture::frame_size(settings, window_size);
+            let bytes = ReplayCapture::capacity(settings) as u64 * width as u64 * height as u64 * 4;
+            println!(
+                "[Capture] Keeping the last {}s at {} fps ({}x{}), using up to {:.1} MB",
+                settings.capture_seconds,
+                settings.capture_fps,
+                width,
+                height,
+                bytes as f64 / (1024.0 * 1024.0),
+            );
+            println!("[Capture] Press {:?} to save them", ReplayCapture::SAVE_KEY);
+        } else {
+            self.reset();
+            println!("[Capture] Stopped capturing");
+        }
+    }
+
+    /// Drops the frames and the target, ex. when their size changes
+    pub(super) fn reset(&mut self) {
+        self.target = None;
+        self.frames = VecDeque::new();
+        self.last_capture = None;
+        self.copied = None;
+        self.mapping = None;
+    }
+
+    /// Scales the scene down into the capture texture and copies it to the readback buffer,
+    /// if it is time for the next frame. Nothing is copied while the last frame is still
+    /// being read.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn capture(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        blit_pipeline: &wgpu::RenderPipeline,
+        scene_bg: &wgpu::BindGroup,
+        format: wgpu::TextureFormat,
+        settings: &GraphicsSettings,
+        window_size: (u32, u32),
+    ) {
+        if !self.enabled || self.copied.is_some() || self.mapping.is_some() {
+            return;
+        }
+        let now = Instant::now();
+        let interval = Duration::from_secs_f32(1.0 / settings.capture_fps as f32);
+        if matches!(self.last_capture, Some(last) if now - last < interval) {
+            return;
+        }
+
+        let size = ReplayCapture::frame_size(settings, window_size);
+        if self.target.as_ref().map(|target| target.size) != Some(size) {
+            // The frames of a GIF all have the same size, so the old frames can't be kept
+            self.frames.clear();
+            self.target = Some(CaptureTarget::new(device, format, size));
+        }
+        let target = self.target.as_ref().unwrap();
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Capture Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: &target.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_pipeline(blit_pipeline);
+        rpass.set_bind_group(0, scene_bg, &[]);
+        rpass.draw(0..3, 0..1);
+        std::mem::drop(rpass);
+
+        encoder.copy_texture_to_buffer(
+            wgpu::TextureCopyView {
+                texture: &target.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::BufferCopyView {
+                buffer: &target.readback_buffer,
+                layout: wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: target.padded_row,
+                    rows_per_image: size.1,
+                },
+            },
+            wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth: 1,
+            },
+        );
+        self.last_capture = Some(now);
+        self.copied = Some(now);
+    }
+
+    /// Adds the copied frame to the ring once the GPU has finished with it. Needs to be
+    /// called after the commands from `capture` are submitted.
+    pub(super) fn read_back(
+        &mut self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        settings: &GraphicsSettings,
+    ) {
+        let target = match &self.target {
+            Some(target) => target,
+            None => return,
+        };
+        if let Some(time) = self.copied.take() {
+            let mapping = target
+                .readback_buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read);
+            self.mapping = Some((time, Box::pin(mapping)));
+        }
+
+        device.poll(wgpu::Maintain::Poll);
+        let result = match &mut self.mapping {
+            Some((_, mapping)) => match mapping.as_mut().now_or_never() {
+                Some(result) => result,
+                None => return,
+            },
+            None => return,
+        };
+        let (time, _) = self.mapping.take().unwrap();
+        if result.is_err() {
+            println!("[Capture] Unable to read the captured frame");
+            return;
+        }
+
+        let capacity = ReplayCapture::capacity(settings);
+        let mut pixels = if self.frames.len() >= capacity {
+            self.frames.pop_front().unwrap().pixels
+        } else {
+            Vec::new()
+        };
+        pixels.clear();
+
+        let (width, height) = target.size;
+        let slice = target.readback_buffer.slice(..);
+        let data = slice.get_mapped_range();
+        for row in data
+            .chunks(target.padded_row as usize)
+            .take(height as usize)
+        {
+            pixels.extend_from_slice(&row[..width as usize * 4]);
+        }
+        std::mem::drop(data);
+        target.readback_buffer.unmap();
+
+        if matches!(
+            format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        self.frames.push_back(CapturedFrame { time, pixels });
+        while self.frames.len() > capacity {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Encodes the frames into a GIF on another thread, so the game keeps running. The
+    /// frames are moved to the thread, and capturing starts over with an empty ring.
+    pub(super) fn save(&mut self, settings: &GraphicsSettings) {
+        let size = match &self.target {
+            Some(target) if !self.frames.is_empty() => target.size,
+            _ => {
+                println!("[Capture] There are no frames to save");
+                return;
+            }
+        };
+        let frames = std::mem::take(&mut self.frames);
+        let last_delay = Duration::from_secs_f32(1.0 / settings.capture_fps as f32);
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let path = format!("{}/replay_{}.gif", CAPTURE_DIR, timestamp);
+        println!("[Capture] Saving {} frames to {}", frames.len(), path);
+
+        std::thread::spawn(move || match encode_gif(&path, size, frames, last_delay) {
+            Ok(()) => println!("[Capture] Saved {}", path),
+            Err(error) => println!("[Capture] Unable to save {}: {}", path, error),
+        });
+    }
+}
+
+/// Each frame is shown until the next one was captured, so the GIF plays at the speed of
+/// the game even when frames were skipped
+fn encode_gif(
+    path: &str,
+    (width, height): (u32, u32),
+    frames: VecDeque<CapturedFrame>,
+    last_delay: Duration,
+) -> Result<(), String> {
+    fs::create_dir_all(CAPTURE_DIR).map_err(|error| error.to_string())?;
+    let file = fs::File::create(path).map_err(|error| error.to_string())?;
+    let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), ReplayCapture::ENCODE_SPEED);
+    encoder
+        .set_repeat(Repeat::Infinite)
+        .map_err(|error| error.to_string())?;
+
+    let times: Vec<Instant> = frames.iter().map(|frame| frame.time).collect();
+    for (i, frame) in frames.into_iter().enumerate() {
+        let delay = times
+            .get(i + 1)
+            .map_or(last_delay, |next| *next - frame.time);
+        let image = image::RgbaImage::from_raw(width, height, frame.pixels)
+            .ok_or("A frame has the wrong size")?;
+        let delay = image::Delay::from_numer_denom_ms(delay.as_millis() as u32, 1);
+        encoder
+            .encode_frame(image::Frame::from_parts(image, 0, 0, delay))
+            .map_err(|error| error.to_string())?;
+    }
+    Ok(())
+}
+
+impl CaptureTarget {
+    fn new(device: &wgpu::Device, format: wgpu::TextureFormat, size: (u32, u32)) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Capture Texture"),
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row = (size.0 * 4).div_ceil(alignment) * alignment;
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Capture Readback Buffer"),
+            size: padded_row as u64 * size.1 as u64,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            texture,
+            view,
+            readback_buffer,
+            size,
+            padded_row,
+        }
+    }
+}
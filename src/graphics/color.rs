@@ -0,0 +1,99 @@
+//! The colors in the game (the mesh palette, tints, UI colors) are authored in sRGB, like
+//! the colors of an image editor. Lighting and blending only look right in linear space, and
+//! the sRGB swapchain encodes the linear result for the display, so every color is converted
+//! here as it is uploaded to the GPU. Textures are already sampled as sRGB.
+
+use cgmath::{Point3, Vector3, Vector4};
+
+/// Converts one sRGB channel (0 to 1) to linear
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts one linear channel (0 to 1) to sRGB
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        c.powf(1.0 / 2.4) * 1.055 - 0.055
+    }
+}
+
+pub fn to_linear(color: Vector3<f32>) -> Vector3<f32> {
+    color.map(srgb_to_linear)
+}
+
+pub fn point_to_linear(color: Point3<f32>) -> Point3<f32> {
+    color.map(srgb_to_linear)
+}
+
+/// Alpha is a coverage and not a color, so it is left unchanged
+pub fn to_linear_alpha(color: Vector4<f32>) -> Vector4<f32> {
+    to_linear(color.truncate()).extend(color.w)
+}
+
+/// The swatches shown by the `color_calibration` debug command. With correct colors the
+/// grey ramp steps evenly, and the 50% patch matches the stripes beside it from a distance.
+#[derive(Default)]
+pub struct ColorCalibration {
+    pub visible: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions_round_trip() {
+        for i in 0..=255 {
+            let c = i as f32 / 255.0;
+            assert!(
+                (linear_to_srgb(srgb_to_linear(c)) - c).abs() < 1e-5,
+                "{}",
+                c
+            );
+            assert!(
+                (srgb_to_linear(linear_to_srgb(c)) - c).abs() < 1e-5,
+                "{}",
+                c
+            );
+        }
+    }
+
+    #[test]
+    fn conversions_match_known_values() {
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-6);
+        // Middle grey in sRGB is about a fifth of the light
+        assert!((srgb_to_linear(0.5) - 0.214_041).abs() < 1e-5);
+        assert!((linear_to_srgb(0.5) - 0.735_357).abs() < 1e-5);
+        // Both sides of the linear segment meet
+        assert!((srgb_to_linear(0.04045) - 0.04045 / 12.92).abs() < 1e-7);
+        assert!((srgb_to_linear(0.040_46) - srgb_to_linear(0.04045)).abs() < 1e-5);
+        assert!((linear_to_srgb(0.003_131) - linear_to_srgb(0.003_130_8)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn conversions_keep_the_order() {
+        let ramp: Vec<f32> = (0..=1000).map(|i| i as f32 / 1000.0).collect();
+        assert!(ramp
+            .windows(2)
+            .all(|pair| srgb_to_linear(pair[0]) < srgb_to_linear(pair[1])));
+        assert!(ramp
+            .windows(2)
+            .all(|pair| linear_to_srgb(pair[0]) < linear_to_srgb(pair[1])));
+    }
+
+    #[test]
+    fn alpha_is_left_linear() {
+        let color = to_linear_alpha(Vector4::new(0.5, 1.0, 0.0, 0.5));
+        assert!((color.x - srgb_to_linear(0.5)).abs() < 1e-6);
+        assert!((color.y - 1.0).abs() < 1e-6);
+        assert_eq!(color.z, 0.0);
+        assert_eq!(color.w, 0.5);
+    }
+}
@@ -0,0 +1,437 @@
+use super::{read_required_asset, Frustum, GPUMesh, GPUModel, MeshManager};
+use cgmath::{prelude::*, Matrix4, Vector4};
+use futures::FutureExt;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+
+/// Where the models outside of the camera's view are removed before they are drawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CullingPath {
+    /// Only the visible models are written to the GPU
+    Cpu,
+    /// Every model is written to the GPU, and a compute shader compacts the visible ones
+    /// into the buffer that is drawn
+    Gpu,
+}
+
+/// How many models were drawn and culled. On the GPU path the counts are read back from
+/// the GPU, so they are a few frames behind.
+pub struct CullingStats {
+    pub path: CullingPath,
+    pub drawn: u32,
+    pub culled: u32,
+}
+
+/// Checks the sphere around the model (scaled by its largest axis) against the frustum.
+/// This needs to match cull.comp, so both paths keep the same models.
+pub(super) fn is_model_visible(frustum: &Frustum, matrix: &Matrix4<f32>, radius: f32) -> bool {
+    let scale = matrix
+        .x
+        .truncate()
+        .magnitude()
+        .max(matrix.y.truncate().magnitude())
+        .max(matrix.z.truncate().magnitude());
+
+    frustum.intersects_sphere(matrix.w.truncate(), radius * scale)
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CullParams {
+    planes: [Vector4<f32>; 6],
+    instance_count: u32,
+    radius: f32,
+    _padding: [u32; 2],
+}
+
+unsafe impl bytemuck::Pod for CullParams {}
+unsafe impl bytemuck::Zeroable for CullParams {}
+
+/// The buffers a mesh needs to be culled on the GPU
+pub(super) struct CullBuffers {
+    params: wgpu::Buffer,
+    /// The visible models, compacted by the cull shader
+    pub(super) visible_models: wgpu::Buffer,
+    /// The index of each visible model in the models buffer
+    visible_indices: wgpu::Buffer,
+    /// The arguments of the indirect draw. The cull shader counts the visible instances.
+    pub(super) draw_args: wgpu::Buffer,
+    /// The models that pass the CPU culling (only used when verifying the GPU path)
+    expected: Vec<u32>,
+}
+
+impl CullBuffers {
+    const DRAW_ARGS_SIZE: u64 = 5 * mem::size_of::<u32>() as u64;
+    /// The offset of the instance count in the draw arguments
+    const INSTANCE_COUNT_OFFSET: u64 = mem::size_of::<u32>() as u64;
+
+    pub(super) fn new(device: &wgpu::Device, id: usize, model_count: u64) -> Self {
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("CullParams(Mesh={})", id)),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+            size: mem::size_of::<CullParams>() as u64,
+        });
+        let visible_models = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("VisibleModelBuffer(Mesh={})", id)),
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::STORAGE,
+            mapped_at_creation: false,
+            size: mem::size_of::<GPUModel>() as u64 * model_count,
+        });
+        let visible_indices = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("VisibleIndexBuffer(Mesh={})", id)),
+            usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_SRC,
+            mapped_at_creation: false,
+            size: mem::size_of::<u32>() as u64 * model_count,
+        });
+        let draw_args = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("DrawArgs(Mesh={})", id)),
+            usage: wgpu::BufferUsage::INDIRECT
+                | wgpu::BufferUsage::STORAGE
+                | wgpu::BufferUsage::COPY_DST
+                | wgpu::BufferUsage::COPY_SRC,
+            mapped_at_creation: false,
+            size: CullBuffers::DRAW_ARGS_SIZE,
+        });
+
+        Self {
+            params,
+            visible_models,
+            visible_indices,
+            draw_args,
+            expected: Vec::new(),
+        }
+    }
+
+    /// Writes the frustum for the cull shader, and resets the instance count of the draw
+    pub(super) fn prepare(
+        &mut self,
+        queue: &wgpu::Queue,
+        frustum: &Frustum,
+        index_count: u32,
+        radius: f32,
+        models: &[GPUModel],
+    ) {
+        let params = CullParams {
+            planes: frustum.planes,
+            instance_count: models.len() as u32,
+            radius,
+            _padding: [0; 2],
+        };
+        queue.write_buffer(&self.params, 0, bytemuck::cast_slice(&[params]));
+        queue.write_buffer(
+            &self.draw_args,
+            0,
+            bytemuck::cast_slice(&[index_count, 0, 0, 0, 0]),
+        );
+
+        self.expected.clear();
+        if crate::VERIFY_GPU_CULLING {
+            self.expected.extend(
+                models
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, model)| is_model_visible(frustum, &model.matrix, radius))
+                    .map(|(index, _)| index as u32),
+            );
+        }
+    }
+}
+
+pub(super) type Mapping = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+/// Culls the models with a compute shader, and draws the visible ones with indirect draws
+pub(super) struct GpuCuller {
+    pipeline: wgpu::ComputePipeline,
+    bgl: wgpu::BindGroupLayout,
+    /// A bind group for each mesh, in the same order as the meshes
+    bind_groups: Vec<wgpu::BindGroup>,
+    /// The instance counts of the draws, copied so they can be read on the CPU
+    counts_buffer: wgpu::Buffer,
+    counts_capacity: usize,
+    /// How many meshes were copied into the counts buffer, until it is mapped
+    copied_counts: Option<usize>,
+    /// The mapping of the counts buffer. Counts are not copied again until it finishes.
+    counts_mapping: Option<(usize, Mapping)>,
+    /// The instance counts and visible indices of every mesh (only used when verifying)
+    verify_buffer: Option<wgpu::Buffer>,
+}
+
+impl GpuCuller {
+    const WORKGROUP_SIZE: u32 = 64;
+    /// The models, visible models, visible indices and draw arguments
+    const STORAGE_BUFFERS: u32 = 4;
+
+    /// Returns None if the device can not cull on the GPU
+    pub(super) fn new(device: &wgpu::Device) -> Option<GpuCuller> {
+        let limits = device.limits();
+        if limits.max_storage_buffers_per_shader_stage < GpuCuller::STORAGE_BUFFERS {
+            println!(
+                "[Graphics] GPU culling needs {} storage buffers, but the device supports {}",
+                GpuCuller::STORAGE_BUFFERS,
+                limits.max_storage_buffers_per_shader_stage
+            );
+            return None;
+        }
+
+        let storage = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, false),
+                storage(3, false),
+                storage(4, false),
+            ],
+            label: Some("Cull Bind Group Layout"),
+        });
+
+        let shader_bytes = read_required_asset("shaders/cull.comp.spv");
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Cull"),
+            source: wgpu::util::make_spirv(&shader_bytes),
+            flags: wgpu::ShaderFlags::VALIDATION,
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Cull Pipeline Layout"),
+            bind_group_layouts: &[&bgl],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Cull Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "main",
+        });
+
+        Some(GpuCuller {
+            pipeline,
+            bgl,
+            bind_groups: Vec::new(),
+            counts_buffer: GpuCuller::create_counts_buffer(device, 0),
+            counts_capacity: 0,
+            copied_counts: None,
+            counts_mapping: None,
+            verify_buffer: None,
+        })
+    }
+
+    fn create_counts_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Counts Buffer"),
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+            size: (capacity.max(1) * mem::size_of::<u32>()) as u64,
+        })
+    }
+
+    /// Culls the models that were written to the GPU by the mesh manager. Needs to be
+    /// recorded before the meshes are drawn.
+    pub(super) fn cull(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        mesh_manager: &MeshManager,
+    ) {
+        let meshes = &mesh_manager.meshes;
+        for mesh in &meshes[self.bind_groups.len()..] {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.bgl,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: mesh.cull.params.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: mesh.models_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: mesh.cull.visible_models.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: mesh.cull.visible_indices.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: mesh.cull.draw_args.as_entire_binding(),
+                    },
+                ],
+                label: Some("Cull Bind Group"),
+            });
+            self.bind_groups.push(bind_group);
+        }
+
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Cull Pass"),
+        });
+        cpass.set_pipeline(&self.pipeline);
+        for (mesh, bind_group) in meshes.iter().zip(&self.bind_groups) {
+            if mesh.visible && mesh.instances > 0 {
+                cpass.set_bind_group(0, bind_group, &[]);
+                let workgroups = mesh.instances.div_ceil(GpuCuller::WORKGROUP_SIZE);
+                cpass.dispatch(workgroups, 1, 1);
+            }
+        }
+        std::mem::drop(cpass);
+
+        if self.counts_mapping.is_none() {
+            if meshes.len() > self.counts_capacity {
+                self.counts_capacity = meshes.len();
+                self.counts_buffer = GpuCuller::create_counts_buffer(device, meshes.len());
+            }
+
+            for (index, mesh) in meshes.iter().enumerate() {
+                encoder.copy_buffer_to_buffer(
+                    &mesh.cull.draw_args,
+                    CullBuffers::INSTANCE_COUNT_OFFSET,
+                    &self.counts_buffer,
+                    (index * mem::size_of::<u32>()) as u64,
+                    mem::size_of::<u32>() as u64,
+                );
+            }
+            self.copied_counts = Some(meshes.len());
+        }
+
+        if crate::VERIFY_GPU_CULLING {
+            self.copy_for_verification(device, encoder, meshes);
+        }
+    }
+
+    /// Copies the instance count of each mesh, followed by its visible indices
+    fn copy_for_verification(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        meshes: &[GPUMesh],
+    ) {
+        let u32_size = mem::size_of::<u32>() as u64;
+        let size = meshes
+            .iter()
+            .map(|mesh| (mesh.instances as u64 + 1) * u32_size)
+            .sum();
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Verify Buffer"),
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+            size,
+        });
+
+        let mut offset = 0;
+        for mesh in meshes {
+            encoder.copy_buffer_to_buffer(
+                &mesh.cull.draw_args,
+                CullBuffers::INSTANCE_COUNT_OFFSET,
+                &buffer,
+                offset,
+                u32_size,
+            );
+            offset += u32_size;
+
+            if mesh.instances > 0 {
+                let indices_size = mesh.instances as u64 * u32_size;
+                encoder.copy_buffer_to_buffer(
+                    &mesh.cull.visible_indices,
+                    0,
+                    &buffer,
+                    offset,
+                    indices_size,
+                );
+                offset += indices_size;
+            }
+        }
+
+        self.verify_buffer = Some(buffer);
+    }
+
+    /// Reads the instance counts once the GPU has finished with them, and stores them in
+    /// the meshes. Needs to be called after the commands from `cull` are submitted.
+    pub(super) fn read_back(&mut self, device: &wgpu::Device, mesh_manager: &mut MeshManager) {
+        if let Some(mesh_count) = self.copied_counts.take() {
+            let mapping = self.counts_buffer.slice(..).map_async(wgpu::MapMode::Read);
+            self.counts_mapping = Some((mesh_count, Box::pin(mapping)));
+        }
+
+        if let Some(buffer) = self.verify_buffer.take() {
+            GpuCuller::verify(device, &buffer, mesh_manager);
+        }
+
+        device.poll(wgpu::Maintain::Poll);
+        let result = match &mut self.counts_mapping {
+            Some((_, mapping)) => match mapping.as_mut().now_or_never() {
+                Some(result) => result,
+                None => return,
+            },
+            None => return,
+        };
+        let (mesh_count, _) = self.counts_mapping.take().unwrap();
+        if result.is_err() {
+            println!("[Graphics] Unable to read the GPU culling counts");
+            return;
+        }
+
+        let slice = self.counts_buffer.slice(..);
+        let data = slice.get_mapped_range();
+        let counts: &[u32] = bytemuck::cast_slice(&data);
+        for (mesh, count) in mesh_manager.meshes.iter_mut().zip(&counts[..mesh_count]) {
+            mesh.drawn = *count;
+        }
+        std::mem::drop(data);
+        self.counts_buffer.unmap();
+    }
+
+    /// Waits for the GPU, and prints every mesh where the GPU kept different models than the CPU
+    fn verify(device: &wgpu::Device, buffer: &wgpu::Buffer, mesh_manager: &MeshManager) {
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        if futures::executor::block_on(mapping).is_err() {
+            println!("[Culling] Unable to read the GPU culling results");
+            return;
+        }
+
+        let data = slice.get_mapped_range();
+        let values: &[u32] = bytemuck::cast_slice(&data);
+        let mut offset = 0;
+        for (index, mesh) in mesh_manager.meshes.iter().enumerate() {
+            let count = values[offset] as usize;
+            offset += 1;
+            let mut visible = values[offset..offset + count].to_vec();
+            offset += mesh.instances as usize;
+            visible.sort_unstable();
+
+            if !mesh.visible || mesh.instances == 0 {
+                continue;
+            }
+            if visible != mesh.cull.expected {
+                println!(
+                    "[Culling] MeshId={} GPU kept {:?}, but the CPU kept {:?}",
+                    index, visible, mesh.cull.expected
+                );
+            }
+        }
+        std::mem::drop(data);
+        buffer.unmap();
+    }
+}
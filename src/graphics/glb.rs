@@ -0,0 +1,326 @@
+use super::{color, Mesh, SubMesh, Vertex};
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix, Matrix3, Matrix4, Point3, SquareMatrix, Transform, Vector3,
+};
+use gltf::{buffer, mesh::Mode, Gltf, Node};
+
+/// Reads a mesh from a binary glTF (.glb) file, like the ones Blender exports. Every
+/// primitive of the default scene is merged into the mesh, with the transforms of its nodes
+/// applied. Like the objects of an obj file, nodes named "node_<name>" (and their children)
+/// become sub-meshes instead, which pivot around the node's origin.
+pub fn parse_glb(name: &str, bytes: &[u8]) -> Result<(Mesh, Vec<SubMesh>), String> {
+    let gltf = Gltf::from_slice(bytes).map_err(|error| error.to_string())?;
+    check_supported(&gltf)?;
+    let scene = gltf
+        .default_scene()
+        .or_else(|| gltf.scenes().next())
+        .ok_or("The file has no scene")?;
+
+    let mut loader = GlbLoader {
+        name,
+        blob: gltf.blob.as_deref(),
+        sub_meshes: Vec::new(),
+    };
+    let mut mesh = Mesh {
+        name: name.to_string(),
+        vertices: Vec::new(),
+        indices: Vec::new(),
+    };
+    for node in scene.nodes() {
+        loader.add_node(
+            node,
+            GlbLoader::Y_UP_TO_Z_UP,
+            &mut mesh,
+            Vector3::new(0.0, 0.0, 0.0),
+        )?;
+    }
+
+    if mesh.indices.is_empty() {
+        return Err("Mesh has no faces".to_string());
+    }
+    Ok((mesh, loader.sub_meshes))
+}
+
+/// Lists everything in the file that would be lost when it is loaded, so the mesh is not
+/// silently loaded without it
+fn check_supported(gltf: &Gltf) -> Result<(), String> {
+    let mut unsupported: Vec<String> = gltf
+        .extensions_required()
+        .map(|extension| format!("the {} extension", extension))
+        .collect();
+    let primitives: Vec<_> = gltf.meshes().flat_map(|mesh| mesh.primitives()).collect();
+
+    if gltf.skins().next().is_some() {
+        unsupported.push("skins".to_string());
+    }
+    if gltf.animations().next().is_some() {
+        unsupported.push("animations".to_string());
+    }
+    if gltf.textures().next().is_some() {
+        unsupported.push("textures (use vertex colors or a base color)".to_string());
+    }
+    if primitives
+        .iter()
+        .any(|primitive| primitive.morph_targets().next().is_some())
+    {
+        unsupported.push("morph targets".to_string());
+    }
+    if primitives
+        .iter()
+        .any(|primitive| primitive.mode() != Mode::Triangles)
+    {
+        unsupported.push("primitives that are not triangles".to_string());
+    }
+    if gltf
+        .buffers()
+        .any(|buffer| !matches!(buffer.source(), buffer::Source::Bin))
+    {
+        unsupported.push("external buffers (export a single .glb)".to_string());
+    }
+
+    if unsupported.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Unsupported glTF features: {}",
+            unsupported.join(", ")
+        ))
+    }
+}
+
+struct GlbLoader<'a> {
+    name: &'a str,
+    /// The binary chunk of the file, which holds every buffer
+    blob: Option<&'a [u8]>,
+    sub_meshes: Vec<SubMesh>,
+}
+
+impl<'a> GlbLoader<'a> {
+    /// glTF is Y up, and the game is Z up. This undoes how Blender's exporter converts it.
+    #[rustfmt::skip]
+    const Y_UP_TO_Z_UP: Matrix4<f32> = Matrix4::new(
+        1.0, 0.0, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0,
+        0.0, -1.0, 0.0, 0.0,
+        0.0, 0.0, 0.0, 1.0,
+    );
+
+    /// Adds the primitives of the node and its children to the mesh, with their vertices
+    /// moved by -origin
+    fn add_node(
+        &mut self,
+        node: Node,
+        parent: Matrix4<f32>,
+        mesh: &mut Mesh,
+        origin: Vector3<f32>,
+    ) -> Result<(), String> {
+        let transform = parent * Matrix4::from(node.transform().matrix());
+        let node_name = node.name().and_then(|name| name.strip_prefix("node_"));
+
+        match node_name {
+            Some(node_name) => {
+                let pivot = transform.w.truncate();
+                let mut sub_mesh = SubMesh {
+                    name: node_name.to_string(),
+                    pivot,
+                    mesh: Mesh {
+                        name: format!("{}.{}", self.name, node_name),
+                        vertices: Vec::new(),
+                        indices: Vec::new(),
+                    },
+                };
+                self.add_primitives(&node, transform, &mut sub_mesh.mesh, pivot)
+                    .and_then(|_| {
+                        node.children().try_for_each(|child| {
+                            self.add_node(child, transform, &mut sub_mesh.mesh, pivot)
+                        })
+                    })
+                    .map_err(|error| format!("{} (node {})", error, node_name))?;
+                if sub_mesh.mesh.indices.is_empty() {
+                    return Err(format!("Mesh has no faces (node {})", node_name));
+                }
+                self.sub_meshes.push(sub_mesh);
+            }
+            None => {
+                self.add_primitives(&node, transform, mesh, origin)?;
+                for child in node.children() {
+                    self.add_node(child, transform, mesh, origin)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn add_primitives(
+        &self,
+        node: &Node,
+        transform: Matrix4<f32>,
+        mesh: &mut Mesh,
+        origin: Vector3<f32>,
+    ) -> Result<(), String> {
+        let gltf_mesh = match node.mesh() {
+            Some(gltf_mesh) => gltf_mesh,
+            None => return Ok(()),
+        };
+        // Normals are transformed by the inverse transpose, so scaled nodes keep them
+        // perpendicular to their faces
+        let rotation = Matrix3::from_cols(
+            transform.x.truncate(),
+            transform.y.truncate(),
+            transform.z.truncate(),
+        );
+        let normal_matrix = rotation
+            .invert()
+            .ok_or("A node has a transform that can't be inverted")?
+            .transpose();
+        let blob = self.blob;
+
+        for primitive in gltf_mesh.primitives() {
+            let reader = primitive.reader(|buffer| match buffer.source() {
+                buffer::Source::Bin => blob,
+                buffer::Source::Uri(_) => None,
+            });
+            let positions: Vec<[f32; 3]> = reader
+                .read_positions()
+                .ok_or("A primitive has no positions")?
+                .collect();
+            let normals: Vec<[f32; 3]> = reader
+                .read_normals()
+                .ok_or("A primitive has no normals")?
+                .collect();
+            // Vertex colors are used over the material's base color. Both are linear, while
+            // the mesh colors are sRGB like the obj pallete.
+            let colors: Vec<[f32; 3]> = match reader.read_colors(0) {
+                Some(colors) => colors.into_rgb_f32().collect(),
+                None => {
+                    let [r, g, b, _] = primitive
+                        .material()
+                        .pbr_metallic_roughness()
+                        .base_color_factor();
+                    vec![[r, g, b]; positions.len()]
+                }
+            };
+            if normals.len() != positions.len() || colors.len() != positions.len() {
+                return Err("A primitive is missing normals or colors".to_string());
+            }
+
+            let offset = mesh.vertices.len();
+            if offset + positions.len() > u16::MAX as usize + 1 {
+                return Err(format!(
+                    "Mesh has more than {} vertices",
+                    u16::MAX as usize + 1
+                ));
+            }
+            for ((pos, normal), color) in positions.iter().zip(&normals).zip(&colors) {
+                let pos = transform.transform_point(Point3::from(*pos)) - origin;
+                let normal = (normal_matrix * Vector3::from(*normal)).normalize();
+                let color = Vector3::from(*color).map(color::linear_to_srgb);
+                mesh.vertices.push(Vertex {
+                    pos,
+                    normal: Point3::from_vec(normal),
+                    color: Point3::from_vec(color),
+                    vein: 0.0,
+                });
+            }
+
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+            if !indices.len().is_multiple_of(3) {
+                return Err("A primitive has a partial triangle".to_string());
+            }
+            for index in indices {
+                if index as usize >= positions.len() {
+                    return Err(format!("Invalid vertex index: {}", index));
+                }
+                mesh.indices.push((offset + index as usize) as u16);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2 x 0.5 x 2 box named "hull" (24 vertices with colors, 36 indices), moved up by 1.
+    /// Its child "node_barrel" is one triangle without indices, which uses the base color
+    /// (0.5 linear gray) and is moved 1 along x from the hull.
+    const TURRET: &[u8] = include_bytes!("../../tests/fixtures/turret.glb");
+
+    fn bounds(mesh: &Mesh) -> (Point3<f32>, Point3<f32>) {
+        let first = mesh.vertices[0].pos;
+        mesh.vertices
+            .iter()
+            .fold((first, first), |(min, max), vertex| {
+                let pos = vertex.pos;
+                (
+                    Point3::new(min.x.min(pos.x), min.y.min(pos.y), min.z.min(pos.z)),
+                    Point3::new(max.x.max(pos.x), max.y.max(pos.y), max.z.max(pos.z)),
+                )
+            })
+    }
+
+    fn assert_near(actual: Point3<f32>, expected: Point3<f32>) {
+        assert!(
+            (actual - expected).magnitude() < 1e-5,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn glb_meshes_load_z_up() {
+        let (mesh, sub_meshes) = parse_glb("turret", TURRET).unwrap();
+        assert_eq!(mesh.name, "turret");
+        assert_eq!(mesh.vertices.len(), 24);
+        assert_eq!(mesh.indices.len(), 36);
+        assert!(mesh.indices.iter().all(|index| *index < 24));
+
+        // glTF's Y is the game's Z, and glTF's Z is the game's -Y
+        let (min, max) = bounds(&mesh);
+        assert_near(min, Point3::new(-1.0, -2.0, 1.0));
+        assert_near(max, Point3::new(1.0, 0.0, 1.5));
+        let up = mesh
+            .vertices
+            .iter()
+            .filter(|vertex| (vertex.normal - Point3::new(0.0, 0.0, 1.0)).magnitude() < 1e-5)
+            .count();
+        assert_eq!(up, 4);
+        for vertex in &mesh.vertices {
+            assert_near(vertex.color, Point3::new(1.0, 0.0, 0.0));
+        }
+
+        assert_eq!(sub_meshes.len(), 1);
+        let barrel = &sub_meshes[0];
+        assert_eq!(barrel.name, "barrel");
+        assert_eq!(barrel.mesh.name, "turret.barrel");
+        assert_near(Point3::from_vec(barrel.pivot), Point3::new(1.0, 0.0, 1.0));
+        assert_eq!(barrel.mesh.indices, vec![0, 1, 2]);
+        // The vertices pivot around the node's origin
+        let positions: Vec<_> = barrel
+            .mesh
+            .vertices
+            .iter()
+            .map(|vertex| vertex.pos)
+            .collect();
+        assert_near(positions[0], Point3::new(0.0, 0.0, 0.0));
+        assert_near(positions[1], Point3::new(1.0, 0.0, 0.0));
+        assert_near(positions[2], Point3::new(0.0, 0.0, 1.0));
+        let gray = color::linear_to_srgb(0.5);
+        for vertex in &barrel.mesh.vertices {
+            assert_near(vertex.normal, Point3::new(0.0, -1.0, 0.0));
+            assert_near(vertex.color, Point3::new(gray, gray, gray));
+        }
+    }
+
+    #[test]
+    fn truncated_glb_files_are_errors() {
+        for len in (0..TURRET.len()).step_by(7) {
+            assert!(parse_glb("turret", &TURRET[..len]).is_err(), "{}", len);
+        }
+    }
+}
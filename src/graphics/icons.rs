@@ -0,0 +1,284 @@
+use super::{bounding_box, read_required_asset, Camera, CameraMatrix, GPUModel, MeshId};
+use super::{MeshManager, ModelTint, Renderer, MESH_BUFFERS};
+use cgmath::{prelude::*, Matrix4, Point3, Vector3};
+use wgpu::util::DeviceExt;
+
+/// The meshes that are drawn in an icon, and where each one is placed (ex. a block's mesh
+/// and its sub-meshes)
+pub type IconParts = Vec<(MeshId, Matrix4<f32>)>;
+
+/// The width and height of an icon in pixels
+pub const ICON_SIZE: u32 = 64;
+const ICON_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+/// The direction the icons are looked at from. The scene's light is above the origin, so
+/// the faces towards the camera are lit.
+const VIEW_DIRECTION: Vector3<f32> = Vector3::new(-1.0, -0.6, -0.8);
+
+/// Draws each icon into its own image with a transparent background, for packing into the UI
+/// atlas. The icons are drawn side by side into one texture, each with its own camera that
+/// fits the meshes. This only happens while loading, so it waits for the GPU, and the
+/// pipeline is not kept.
+pub(super) fn render_icons(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    camera_bgl: &wgpu::BindGroupLayout,
+    mesh_manager: &MeshManager,
+    icons: &[IconParts],
+) -> Vec<image::RgbaImage> {
+    if icons.is_empty() {
+        return Vec::new();
+    }
+
+    let width = ICON_SIZE * icons.len() as u32;
+    let size = wgpu::Extent3d {
+        width,
+        height: ICON_SIZE,
+        depth: 1,
+    };
+    let color_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Icon Texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: ICON_FORMAT,
+        usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+    });
+    let color_view = color_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let depth = super::create_texture(
+        device,
+        "Icon Depth Texture",
+        (width, ICON_SIZE, 1),
+        Renderer::DEPTH_FORMAT,
+        wgpu::TextureUsage::RENDER_ATTACHMENT,
+    );
+
+    let cameras: Vec<wgpu::BindGroup> = icons
+        .iter()
+        .map(|parts| {
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Icon Camera Buffer"),
+                contents: bytemuck::cast_slice(&[icon_camera(mesh_manager, parts)]),
+                usage: wgpu::BufferUsage::UNIFORM,
+            });
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: camera_bgl,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }],
+                label: Some("Icon Camera Bind Group"),
+            })
+        })
+        .collect();
+    // Every part is one instance, in the order they are drawn
+    let models: Vec<GPUModel> = icons
+        .iter()
+        .flatten()
+        .map(|(_, matrix)| GPUModel {
+            matrix: *matrix,
+            tint: ModelTint::NONE,
+            paint: ModelTint::NONE.primary,
+            pick_id: 0,
+            _padding: [0; 3],
+        })
+        .collect();
+    let models_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Icon Model Buffer"),
+        contents: bytemuck::cast_slice(&models),
+        usage: wgpu::BufferUsage::VERTEX,
+    });
+
+    let pipeline = create_pipeline(device, camera_bgl);
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Icon Encoder"),
+    });
+    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Icon Pass"),
+        color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+            attachment: &color_view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: true,
+            },
+        }],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+            attachment: &depth.view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                store: true,
+            }),
+            stencil_ops: None,
+        }),
+    });
+    rpass.set_pipeline(&pipeline);
+    rpass.set_vertex_buffer(1, models_buffer.slice(..));
+    let mut instance = 0;
+    for (index, (parts, camera_bg)) in icons.iter().zip(&cameras).enumerate() {
+        let x = (index as u32 * ICON_SIZE) as f32;
+        rpass.set_viewport(x, 0.0, ICON_SIZE as f32, ICON_SIZE as f32, 0.0, 1.0);
+        rpass.set_bind_group(0, camera_bg, &[]);
+        for (mesh_id, _) in parts {
+            let mesh = &mesh_manager.meshes[mesh_id.0];
+            rpass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            rpass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            rpass.draw_indexed(0..mesh.index_count, 0, instance..instance + 1);
+            instance += 1;
+        }
+    }
+    std::mem::drop(rpass);
+
+    // The icons are side by side, so a row is a multiple of 256 bytes like copies need
+    let bytes_per_row = width * 4;
+    let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Icon Readback Buffer"),
+        size: (bytes_per_row * ICON_SIZE) as u64,
+        usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+        mapped_at_creation: false,
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::TextureCopyView {
+            texture: &color_texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        wgpu::BufferCopyView {
+            buffer: &readback_buffer,
+            layout: wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row,
+                rows_per_image: ICON_SIZE,
+            },
+        },
+        size,
+    );
+    queue.submit(Some(encoder.finish()));
+
+    let slice = readback_buffer.slice(..);
+    let mapping = slice.map_async(wgpu::MapMode::Read);
+    device.poll(wgpu::Maintain::Wait);
+    if futures::executor::block_on(mapping).is_err() {
+        println!("[Graphics] Unable to read the icons");
+        return icons
+            .iter()
+            .map(|_| image::RgbaImage::new(ICON_SIZE, ICON_SIZE))
+            .collect();
+    }
+
+    let data = slice.get_mapped_range();
+    let images = (0..icons.len() as u32)
+        .map(|index| {
+            image::RgbaImage::from_fn(ICON_SIZE, ICON_SIZE, |x, y| {
+                let offset = (y * bytes_per_row + (index * ICON_SIZE + x) * 4) as usize;
+                image::Rgba([
+                    data[offset],
+                    data[offset + 1],
+                    data[offset + 2],
+                    data[offset + 3],
+                ])
+            })
+        })
+        .collect();
+    std::mem::drop(data);
+    readback_buffer.unmap();
+
+    images
+}
+
+/// An orthographic camera that looks at the parts from the VIEW_DIRECTION. The view fits the
+/// sphere around their bounding box, so the parts are never cut off however they are turned.
+fn icon_camera(mesh_manager: &MeshManager, parts: &[(MeshId, Matrix4<f32>)]) -> CameraMatrix {
+    let corners = parts.iter().flat_map(|(mesh_id, matrix)| {
+        let bounds = mesh_manager.meshes[mesh_id.0].bounds;
+        bounds.into_iter().flat_map(move |(min, max)| {
+            (0..8).map(move |corner| {
+                let corner = Point3::new(
+                    if corner & 1 == 0 { min.x } else { max.x },
+                    if corner & 2 == 0 { min.y } else { max.y },
+                    if corner & 4 == 0 { min.z } else { max.z },
+                );
+                matrix.transform_point(corner)
+            })
+        })
+    });
+    let (center, radius) = match bounding_box(corners) {
+        Some((min, max)) => (min.midpoint(max), ((max - min).magnitude() / 2.0).max(0.01)),
+        None => (Point3::origin(), 1.0),
+    };
+
+    let direction = VIEW_DIRECTION.normalize();
+    let view = Matrix4::look_at_dir(
+        center - direction * radius * 2.0,
+        direction,
+        Vector3::unit_z(),
+    );
+    let projection = cgmath::ortho(-radius, radius, -radius, radius, radius * 0.5, radius * 3.5);
+    CameraMatrix(Camera::OPENGL_TO_WGPU_MATRIX * projection * view)
+}
+
+/// Draws the meshes like the scene, but into a texture that can be read back, so without MSAA
+fn create_pipeline(
+    device: &wgpu::Device,
+    camera_bgl: &wgpu::BindGroupLayout,
+) -> wgpu::RenderPipeline {
+    let vertex_bytes = read_required_asset("shaders/basic.vert.spv");
+    let vertex_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some("Icon Vertex"),
+        source: wgpu::util::make_spirv(&vertex_bytes),
+        flags: wgpu::ShaderFlags::VALIDATION,
+    });
+
+    let frag_bytes = read_required_asset("shaders/basic.frag.spv");
+    let frag_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some("Icon Fragment"),
+        source: wgpu::util::make_spirv(&frag_bytes),
+        flags: wgpu::ShaderFlags::VALIDATION,
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Icon Pipeline Layout"),
+        bind_group_layouts: &[camera_bgl],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Icon Pipeline"),
+        layout: Some(&pipeline_layout),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::Back,
+            polygon_mode: wgpu::PolygonMode::Fill,
+        },
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Renderer::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+            clamp_depth: false,
+        }),
+        vertex: wgpu::VertexState {
+            module: &vertex_shader,
+            entry_point: "main",
+            buffers: &MESH_BUFFERS,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &frag_shader,
+            entry_point: "main",
+            targets: &[wgpu::ColorTargetState {
+                format: ICON_FORMAT,
+                color_blend: wgpu::BlendState::REPLACE,
+                alpha_blend: wgpu::BlendState::REPLACE,
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+        }),
+    })
+}
@@ -1,20 +1,62 @@
 use crate::entity::Line;
+use cgmath::Vector3;
 use std::mem;
+use std::ops::Range;
 
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct GPULine {
+    pos1: Vector3<f32>,
+    color1: Vector3<f32>,
+    pos2: Vector3<f32>,
+    color2: Vector3<f32>,
+    width: f32,
+}
+
+unsafe impl bytemuck::Pod for GPULine {}
+unsafe impl bytemuck::Zeroable for GPULine {}
+
+impl From<&Line> for GPULine {
+    fn from(line: &Line) -> Self {
+        Self {
+            pos1: line.pt,
+            color1: super::color::to_linear(line.color),
+            pos2: line.pt2,
+            color2: super::color::to_linear(line.color2),
+            width: line.width,
+        }
+    }
+}
+
+/// Draws every line with one instance buffer. Lines without a width are drawn with the
+/// line list topology, and the rest are expanded into quads (since line widths are not
+/// supported everywhere). Overlay lines ignore the depth buffer.
 pub struct LineRenderer {
-    pub vertex_buffer: wgpu::Buffer,
-    pub pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    thin_pipeline: wgpu::RenderPipeline,
+    thick_pipeline: wgpu::RenderPipeline,
+    overlay_thin_pipeline: wgpu::RenderPipeline,
+    overlay_thick_pipeline: wgpu::RenderPipeline,
+    /// The instances of each group in the vertex buffer, in the order: thin, thick,
+    /// overlay thin and overlay thick
+    groups: [Range<u32>; 4],
+    /// Reused every frame, so the lines are not allocated every frame
+    gpu_lines: Vec<GPULine>,
 }
 
 impl LineRenderer {
-    const MAX_LINES: u64 = 32;
+    /// Enough for the wireframes of every hitbox (see HitboxOverlay), or the tile outlines
+    /// of the build hologram around a large ship
+    pub const MAX_LINES: u64 = 16384;
 
     pub fn new(
         device: &wgpu::Device,
         camera_bgl: &wgpu::BindGroupLayout,
-        swapchain: &wgpu::SwapChainDescriptor,
+        billboard_bgl: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
     ) -> LineRenderer {
-        let vertex_buffer_size = LineRenderer::MAX_LINES * mem::size_of::<Line>() as u64;
+        let vertex_buffer_size = LineRenderer::MAX_LINES * mem::size_of::<GPULine>() as u64;
         let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Line Buffer"),
             size: vertex_buffer_size,
@@ -22,14 +64,21 @@ impl LineRenderer {
             mapped_at_creation: false,
         });
 
-        let vertex_bytes = super::read_file_bytes("assets/shaders/line.vert.spv");
+        let vertex_bytes = super::read_required_asset("shaders/line.vert.spv");
         let vertex_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
             label: Some("Line Vertex Shader"),
             source: wgpu::util::make_spirv(&vertex_bytes),
             flags: wgpu::ShaderFlags::VALIDATION,
         });
 
-        let frag_bytes = super::read_file_bytes("assets/shaders/line.frag.spv");
+        let thick_vertex_bytes = super::read_required_asset("shaders/thick_line.vert.spv");
+        let thick_vertex_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Thick Line Vertex Shader"),
+            source: wgpu::util::make_spirv(&thick_vertex_bytes),
+            flags: wgpu::ShaderFlags::VALIDATION,
+        });
+
+        let frag_bytes = super::read_required_asset("shaders/line.frag.spv");
         let frag_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
             label: Some("Line Fragment Shader"),
             source: wgpu::util::make_spirv(&frag_bytes),
@@ -38,57 +87,147 @@ impl LineRenderer {
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Line Pipeline Layout"),
-            bind_group_layouts: &[&camera_bgl],
+            bind_group_layouts: &[camera_bgl, billboard_bgl],
             push_constant_ranges: &[],
         });
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::LineList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: wgpu::CullMode::Back,
-                polygon_mode: wgpu::PolygonMode::Fill,
-            },
-            multisample: wgpu::MultisampleState {
-                count: crate::MSAA_SAMPLE,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: super::Renderer::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-                clamp_depth: false,
-            }),
-            vertex: wgpu::VertexState {
-                module: &vertex_shader,
-                entry_point: "main",
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: mem::size_of::<Line>() as wgpu::BufferAddress,
-                    step_mode: wgpu::InputStepMode::Instance,
-                    attributes: &wgpu::vertex_attr_array![0 => Float3, 1 => Float3, 2 => Float3],
-                }],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &frag_shader,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState {
-                    format: swapchain.format,
-                    color_blend: wgpu::BlendState::default(),
-                    alpha_blend: wgpu::BlendState::default(),
-                    write_mask: wgpu::ColorWrite::ALL,
-                }],
-            }),
-        });
+        let create_pipeline = |vertex_shader: &wgpu::ShaderModule, thick: bool, overlay: bool| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                primitive: wgpu::PrimitiveState {
+                    topology: if thick {
+                        wgpu::PrimitiveTopology::TriangleList
+                    } else {
+                        wgpu::PrimitiveTopology::LineList
+                    },
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    // The winding of the quads depends on the line's direction
+                    cull_mode: wgpu::CullMode::None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                },
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: super::Renderer::DEPTH_FORMAT,
+                    depth_write_enabled: !overlay,
+                    depth_compare: if overlay {
+                        wgpu::CompareFunction::Always
+                    } else {
+                        wgpu::CompareFunction::Less
+                    },
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                    clamp_depth: false,
+                }),
+                vertex: wgpu::VertexState {
+                    module: vertex_shader,
+                    entry_point: "main",
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: mem::size_of::<GPULine>() as wgpu::BufferAddress,
+                        step_mode: wgpu::InputStepMode::Instance,
+                        attributes: &wgpu::vertex_attr_array![0 => Float3, 1 => Float3, 2 => Float3, 3 => Float3, 4 => Float],
+                    }],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &frag_shader,
+                    entry_point: "main",
+                    targets: &[wgpu::ColorTargetState {
+                        format,
+                        color_blend: wgpu::BlendState::default(),
+                        alpha_blend: wgpu::BlendState::default(),
+                        write_mask: wgpu::ColorWrite::ALL,
+                    }],
+                }),
+            })
+        };
 
         Self {
+            thin_pipeline: create_pipeline(&vertex_shader, false, false),
+            thick_pipeline: create_pipeline(&thick_vertex_shader, true, false),
+            overlay_thin_pipeline: create_pipeline(&vertex_shader, false, true),
+            overlay_thick_pipeline: create_pipeline(&thick_vertex_shader, true, true),
             vertex_buffer,
-            pipeline,
+            groups: [0..0, 0..0, 0..0, 0..0],
+            gpu_lines: Vec::with_capacity(LineRenderer::MAX_LINES as usize),
+        }
+    }
+
+    /// Sorts the lines into their groups, and writes them to the vertex buffer
+    pub fn prepare(&mut self, queue: &wgpu::Queue, lines: &[Line]) {
+        let group = |line: &Line| (line.overlay as usize) * 2 + (line.width > 0.0) as usize;
+        let gpu_lines = &mut self.gpu_lines;
+        gpu_lines.clear();
+        for (index, range) in self.groups.iter_mut().enumerate() {
+            let start = gpu_lines.len() as u32;
+            gpu_lines.extend(
+                lines
+                    .iter()
+                    .filter(|line| group(line) == index)
+                    .map(GPULine::from),
+            );
+            let end = (gpu_lines.len() as u32).min(LineRenderer::MAX_LINES as u32);
+            *range = start.min(end)..end;
+        }
+        gpu_lines.truncate(LineRenderer::MAX_LINES as usize);
+
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(gpu_lines));
+    }
+
+    /// Draws the lines that are depth tested. The billboard bind group is needed to
+    /// expand thick lines.
+    pub fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, billboard_bg: &'a wgpu::BindGroup) {
+        self.draw_groups(
+            rpass,
+            billboard_bg,
+            &self.thin_pipeline,
+            &self.thick_pipeline,
+            0,
+        );
+    }
+
+    /// Draws the overlay lines. This should be done after everything else was drawn.
+    pub fn draw_overlay<'a>(
+        &'a self,
+        rpass: &mut wgpu::RenderPass<'a>,
+        billboard_bg: &'a wgpu::BindGroup,
+    ) {
+        self.draw_groups(
+            rpass,
+            billboard_bg,
+            &self.overlay_thin_pipeline,
+            &self.overlay_thick_pipeline,
+            2,
+        );
+    }
+
+    fn draw_groups<'a>(
+        &'a self,
+        rpass: &mut wgpu::RenderPass<'a>,
+        billboard_bg: &'a wgpu::BindGroup,
+        thin_pipeline: &'a wgpu::RenderPipeline,
+        thick_pipeline: &'a wgpu::RenderPipeline,
+        first_group: usize,
+    ) {
+        let thin = self.groups[first_group].clone();
+        let thick = self.groups[first_group + 1].clone();
+        if thin.is_empty() && thick.is_empty() {
+            return;
+        }
+
+        rpass.set_bind_group(1, billboard_bg, &[]);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        if !thin.is_empty() {
+            rpass.set_pipeline(thin_pipeline);
+            rpass.draw(0..2, thin);
+        }
+        if !thick.is_empty() {
+            rpass.set_pipeline(thick_pipeline);
+            rpass.draw(0..6, thick);
         }
     }
 }
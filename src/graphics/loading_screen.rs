@@ -0,0 +1,150 @@
+use std::mem;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct LoadingRect {
+    /// The min and max corners in clip space
+    rect: [f32; 4],
+    color: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for LoadingRect {}
+unsafe impl bytemuck::Zeroable for LoadingRect {}
+
+/// Draws a progress bar while the assets load (see crate::loading). It has a pipeline of
+/// its own, since the MeshManager and the UI are only created once the assets are loaded.
+pub struct LoadingScreen {
+    pipeline: wgpu::RenderPipeline,
+    rects: wgpu::Buffer,
+}
+
+impl LoadingScreen {
+    const BACKGROUND: wgpu::Color = wgpu::Color {
+        r: 0.001,
+        g: 0.001,
+        b: 0.001,
+        a: 1.0,
+    };
+    const TRACK_COLOR: [f32; 4] = [0.05, 0.05, 0.06, 1.0];
+    const BAR_COLOR: [f32; 4] = [0.9, 0.55, 0.1, 1.0];
+    /// The size of the bar in clip space, which stretches with the window
+    const BAR_WIDTH: f32 = 1.0;
+    const BAR_HEIGHT: f32 = 0.04;
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let rects = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Loading Screen Buffer"),
+            size: (mem::size_of::<LoadingRect>() * 2) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let vertex_bytes = super::read_required_asset("shaders/loading.vert.spv");
+        let vertex_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Loading Screen Vertex Shader"),
+            source: wgpu::util::make_spirv(&vertex_bytes),
+            flags: wgpu::ShaderFlags::VALIDATION,
+        });
+
+        let frag_bytes = super::read_required_asset("shaders/loading.frag.spv");
+        let frag_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Loading Screen Fragment Shader"),
+            source: wgpu::util::make_spirv(&frag_bytes),
+            flags: wgpu::ShaderFlags::VALIDATION,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Loading Screen Pipeline Layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Loading Screen Pipeline"),
+            layout: Some(&pipeline_layout),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+            },
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            depth_stencil: None,
+            vertex: wgpu::VertexState {
+                module: &vertex_shader,
+                entry_point: "main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: mem::size_of::<LoadingRect>() as wgpu::BufferAddress,
+                    step_mode: wgpu::InputStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![0 => Float4, 1 => Float4],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_shader,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    color_blend: wgpu::BlendState::REPLACE,
+                    alpha_blend: wgpu::BlendState::REPLACE,
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+        });
+
+        Self { pipeline, rects }
+    }
+
+    /// Draws the bar filled up to `progress` (0..1) straight to the frame
+    pub fn render(
+        &self,
+        frame: &wgpu::SwapChainTexture,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        progress: f32,
+    ) {
+        let (left, bottom) = (
+            -LoadingScreen::BAR_WIDTH / 2.0,
+            -LoadingScreen::BAR_HEIGHT / 2.0,
+        );
+        let (right, top) = (-left, -bottom);
+        let filled = left + LoadingScreen::BAR_WIDTH * progress.clamp(0.0, 1.0);
+        let rects = [
+            LoadingRect {
+                rect: [left, bottom, right, top],
+                color: LoadingScreen::TRACK_COLOR,
+            },
+            LoadingRect {
+                rect: [left, bottom, filled, top],
+                color: LoadingScreen::BAR_COLOR,
+            },
+        ];
+        queue.write_buffer(&self.rects, 0, bytemuck::cast_slice(&rects));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Loading Screen Encoder"),
+        });
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Loading Screen Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: &frame.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(LoadingScreen::BACKGROUND),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            rpass.set_pipeline(&self.pipeline);
+            rpass.set_vertex_buffer(0, self.rects.slice(..));
+            rpass.draw(0..6, 0..rects.len() as u32);
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+}
@@ -1,15 +1,44 @@
 use crate::entity::Line;
 use cgmath::{prelude::*, Matrix4, Point2, Point3, Vector3, Vector4};
 use generational_arena::Arena;
+use specs::Entity;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::mem;
+use std::ops::Range;
+use timing::Timestamp;
 use wgpu::util::DeviceExt;
 
+pub use billboard::*;
+pub use camera::{CameraController, CameraMode, CameraShake};
+pub use capture::ReplayCapture;
+pub use color::ColorCalibration;
+pub use culling::{CullingPath, CullingStats};
+pub use icons::IconParts;
 pub use line::*;
+pub use loading_screen::LoadingScreen;
 pub use obj::*;
+pub use particle::*;
+pub use picking::{CollisionMesh, PickRequest, PickResult};
+pub use settings::GraphicsSettings;
+pub use timing::GpuPassTimes;
 pub use ui::*;
 
+mod backdrop;
+mod billboard;
+mod camera;
+mod capture;
+pub mod color;
+mod culling;
+mod glb;
+mod icons;
 mod line;
+mod loading_screen;
 mod obj;
+mod particle;
+mod picking;
+mod settings;
+mod timing;
 mod ui;
 
 #[derive(Clone)]
@@ -76,11 +105,137 @@ impl Mesh {
         }
     }
 
-    pub fn recolor(&mut self, c: Point3<f32>) {
+    /// Creates a capsule standing along z and centered at (0, 0, 0). `height` is the length
+    /// of the cylinder between the two half spheres.
+    pub fn capsule(radius: f32, height: f32, color: Point3<f32>) -> Mesh {
+        const SEGMENTS: u16 = 12;
+        // The rings of each half sphere, from its pole to the cylinder
+        const RINGS: u16 = 4;
+
+        let mut vertices = Vec::new();
+        // The lower half sphere goes from its pole up to the cylinder, and the upper one from
+        // the cylinder up to its pole. The rings at the ends of the cylinder are shared.
+        let lower = (0..=RINGS).map(|ring| (ring as f32 / RINGS as f32 - 1.0, -height / 2.0));
+        let upper = (0..=RINGS).map(|ring| (ring as f32 / RINGS as f32, height / 2.0));
+        for (latitude, offset) in lower.chain(upper) {
+            let phi = latitude * crate::PI / 2.0;
+            for segment in 0..SEGMENTS {
+                let theta = segment as f32 / SEGMENTS as f32 * 2.0 * crate::PI;
+                let normal =
+                    Point3::new(phi.cos() * theta.cos(), phi.cos() * theta.sin(), phi.sin());
+                vertices.push(Vertex::new(
+                    normal.x * radius,
+                    normal.y * radius,
+                    normal.z * radius + offset,
+                    normal,
+                    color,
+                ));
+            }
+        }
+
+        let mut indices = Vec::new();
+        for ring in 0..(RINGS + 1) * 2 - 1 {
+            for segment in 0..SEGMENTS {
+                let a = ring * SEGMENTS;
+                let b = a + SEGMENTS;
+                let next = (segment + 1) % SEGMENTS;
+                indices.extend_from_slice(&[a + segment, a + next, b + next]);
+                indices.extend_from_slice(&[a + segment, b + next, b + segment]);
+            }
+        }
+
+        Mesh {
+            name: format!("Capsule(radius={}, height={})", radius, height),
+            indices,
+            vertices,
+        }
+    }
+
+    /// Creates a simplified copy of the mesh by merging all of the vertices that fall
+    /// within the same cell of a grid (vertex clustering). Triangles that collapse are removed,
+    /// and the normals are recalculated so the mesh stays flat shaded.
+    pub fn decimate(&self, cell_size: f32) -> Mesh {
+        let mut cells: HashMap<(i32, i32, i32), usize> = HashMap::new();
+        let mut clusters: Vec<(Vector3<f32>, Vector3<f32>, f32, f32)> = Vec::new();
+
+        let remap: Vec<usize> = self
+            .vertices
+            .iter()
+            .map(|vertex| {
+                let cell = (
+                    (vertex.pos.x / cell_size).floor() as i32,
+                    (vertex.pos.y / cell_size).floor() as i32,
+                    (vertex.pos.z / cell_size).floor() as i32,
+                );
+                let index = *cells.entry(cell).or_insert_with(|| {
+                    clusters.push((Vector3::zero(), Vector3::zero(), 0.0, 0.0));
+                    clusters.len() - 1
+                });
+                let cluster = &mut clusters[index];
+                cluster.0 += vertex.pos.to_vec();
+                cluster.1 += vertex.color.to_vec();
+                cluster.2 += vertex.vein;
+                cluster.3 += 1.0;
+
+                index
+            })
+            .collect();
+
+        let clusters: Vec<(Point3<f32>, Point3<f32>, f32)> = clusters
+            .into_iter()
+            .map(|(pos, color, vein, count)| {
+                (
+                    Point3::from_vec(pos / count),
+                    Point3::from_vec(color / count),
+                    vein / count,
+                )
+            })
+            .collect();
+
+        let mut vertices = Vec::new();
+        for triangle in self.indices.chunks(3) {
+            let a = remap[triangle[0] as usize];
+            let b = remap[triangle[1] as usize];
+            let c = remap[triangle[2] as usize];
+            if a == b || b == c || a == c {
+                continue;
+            }
+
+            let normal = (clusters[b].0 - clusters[a].0).cross(clusters[c].0 - clusters[a].0);
+            if normal.magnitude2() <= f32::EPSILON {
+                continue;
+            }
+            let normal = Point3::from_vec(normal.normalize());
+
+            for index in &[a, b, c] {
+                let (pos, color, vein) = clusters[*index];
+                vertices.push(Vertex {
+                    pos,
+                    normal,
+                    color,
+                    vein,
+                });
+            }
+        }
+
+        Mesh {
+            name: format!("{}(Decimated={})", self.name, cell_size),
+            indices: (0..vertices.len() as u16).collect(),
+            vertices,
+        }
+    }
+
+    /// Marks patches of the surface as veins using a cheap noise function of the vertex positions.
+    /// `frequency` controls the size of the patches, and `threshold` (-1..1) how much of the
+    /// surface they cover (a higher threshold means smaller veins).
+    pub fn mark_veins(&mut self, frequency: f32, threshold: f32) {
         for vertex in &mut self.vertices {
-            vertex.color.x += c.x;
-            vertex.color.y += c.y;
-            vertex.color.z += c.z;
+            let p = vertex.pos.to_vec() * frequency;
+            let noise = (p.x + (p.y * 1.7).sin()).sin()
+                * (p.y + (p.z * 1.3).sin()).sin()
+                * (p.z + (p.x * 2.1).sin()).sin();
+
+            vertex.vein = if noise > threshold { 1.0 } else { 0.0 };
         }
     }
 }
@@ -91,6 +246,8 @@ pub struct Vertex {
     pub pos: Point3<f32>,
     pub normal: Point3<f32>,
     pub color: Point3<f32>,
+    /// 1.0 if the vertex is part of a vein, which is tinted with the secondary model tint
+    pub vein: f32,
 }
 
 impl Vertex {
@@ -99,6 +256,7 @@ impl Vertex {
             pos: (x, y, z).into(),
             normal,
             color,
+            vein: 0.0,
         }
     }
 }
@@ -106,32 +264,206 @@ impl Vertex {
 unsafe impl bytemuck::Pod for Vertex {}
 unsafe impl bytemuck::Zeroable for Vertex {}
 
+/// Colors that the vertex colors of a model are multiplied by. The vertices that are
+/// marked as veins use the secondary tint, and the rest of the mesh uses the primary tint.
 #[repr(C)]
-#[derive(Clone, Copy)]
-struct ModelMatrix(Matrix4<f32>);
+#[derive(Clone, Copy, PartialEq)]
+pub struct ModelTint {
+    pub primary: Vector4<f32>,
+    pub secondary: Vector4<f32>,
+}
 
-unsafe impl bytemuck::Pod for ModelMatrix {}
-unsafe impl bytemuck::Zeroable for ModelMatrix {}
+impl ModelTint {
+    /// Leaves the colors of the mesh unchanged
+    pub const NONE: ModelTint = ModelTint {
+        primary: Vector4::new(1.0, 1.0, 1.0, 1.0),
+        secondary: Vector4::new(1.0, 1.0, 1.0, 1.0),
+    };
 
+    pub fn new(primary: Vector3<f32>, secondary: Vector3<f32>) -> Self {
+        Self {
+            primary: primary.extend(1.0),
+            secondary: secondary.extend(1.0),
+        }
+    }
+}
+
+#[repr(C)]
 #[derive(Clone, Copy)]
+struct GPUModel {
+    matrix: Matrix4<f32>,
+    tint: ModelTint,
+    /// Multiplies both tints. It is kept apart from them, so the paint of a block stays
+    /// while status effects and highlights change its tint.
+    paint: Vector4<f32>,
+    /// Drawn into the pick target, see MeshManager::pick_entities
+    pick_id: u32,
+    /// The models are also read as a storage buffer by the cull shader, where the struct
+    /// is padded to 16 bytes
+    _padding: [u32; 3],
+}
+
+unsafe impl bytemuck::Pod for GPUModel {}
+unsafe impl bytemuck::Zeroable for GPUModel {}
+
+/// The vertex buffers that meshes are drawn with: the vertices of the mesh, and its models
+const MESH_BUFFERS: [wgpu::VertexBufferLayout<'static>; 2] = [
+    wgpu::VertexBufferLayout {
+        array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::InputStepMode::Vertex,
+        attributes: &wgpu::vertex_attr_array![0 => Float3, 1 => Float3, 2 => Float3, 3 => Float],
+    },
+    wgpu::VertexBufferLayout {
+        array_stride: mem::size_of::<GPUModel>() as wgpu::BufferAddress,
+        step_mode: wgpu::InputStepMode::Instance,
+        attributes: &wgpu::vertex_attr_array![
+            4 => Float4, 5 => Float4, 6 => Float4, 7 => Float4, 8 => Float4, 9 => Float4,
+            10 => Float4, 11 => Uint
+        ],
+    },
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct MeshId(usize);
 
 pub type ModelId = generational_arena::Index;
 
+/// A sub-mesh that was registered on its own, so it can be moved separately from the rest
+/// of its mesh (see entity::animation). `local` places it relative to the whole mesh.
+#[derive(Clone)]
+pub struct MeshNode {
+    pub name: String,
+    pub mesh_id: MeshId,
+    pub local: Matrix4<f32>,
+}
+
+struct ModelInstance {
+    matrix: Matrix4<f32>,
+    /// The matrix from the previous fixed update
+    previous: Matrix4<f32>,
+    /// The currently selected level of detail (only used by meshes with a LOD chain)
+    lod: usize,
+    tint: ModelTint,
+    /// See GPUModel::paint
+    paint: Vector4<f32>,
+    /// The entity that the model belongs to, which is found by picking
+    entity: Option<Entity>,
+    /// Drawn with the hologram look in the transparent phase (see MeshManager::set_hologram)
+    hologram: bool,
+}
+
+impl ModelInstance {
+    /// Linearly interpolates between the previous and current matrix. This is not exact
+    /// for rotations, but the difference between two fixed updates is small enough.
+    fn interpolated(&self, alpha: f32, pick_id: u32) -> GPUModel {
+        let matrix = if crate::INTERPOLATE_MODELS {
+            self.previous + (self.matrix - self.previous) * alpha
+        } else {
+            self.matrix
+        };
+
+        GPUModel {
+            matrix,
+            tint: ModelTint {
+                primary: color::to_linear_alpha(self.tint.primary),
+                secondary: color::to_linear_alpha(self.tint.secondary),
+            },
+            paint: color::to_linear_alpha(self.paint),
+            pick_id,
+            _padding: [0; 3],
+        }
+    }
+
+    /// Whether the model is see-through, because its tint or paint has an alpha below 1 or
+    /// it is a hologram
+    fn is_transparent(&self) -> bool {
+        self.hologram || self.tint.primary.w.min(self.tint.secondary.w) * self.paint.w < 1.0
+    }
+}
+
+/// A model that is drawn in the transparent phase, after every opaque model
+struct TransparentModel {
+    mesh: usize,
+    hologram: bool,
+    /// How far in front of the camera the model is, which the models are sorted by
+    depth: f32,
+    model: GPUModel,
+}
+
+/// A mesh with multiple levels of detail. The models are always stored under
+/// the most detailed mesh, so switching levels does not change the ModelId.
+struct LodChain {
+    levels: Vec<MeshId>,
+    /// The camera distance at which each level switches to the next one
+    distances: Vec<f32>,
+}
+
 pub struct MeshManager {
+    /// Empty in a headless MeshManager, which only keeps the models
     meshes: Vec<GPUMesh>,
-    models: Vec<Arena<Matrix4<f32>>>,
+    models: Vec<Arena<ModelInstance>>,
+    lod_chains: HashMap<usize, LodChain>,
+    /// The meshes that precise raycasts are tested against, which are also kept when headless
+    collision_meshes: HashMap<usize, CollisionMesh>,
+    /// Reused when the models are written to the GPU, so it is not allocated every frame
+    gpu_models: Vec<GPUModel>,
+    /// The entities of the models written to the GPU in the last frame. A model's pick id
+    /// is its index in here plus one, and models without an entity are drawn with 0.
+    pick_entities: Vec<Entity>,
+    /// The path the models were culled with in the last frame
+    culling: CullingPath,
+    /// The transparent models of the last frame, sorted back to front. They are drawn from
+    /// one buffer instead of the buffers of their meshes, with a draw for each run of models
+    /// of the same mesh. Sorting the models within each mesh's buffer would keep a single
+    /// draw per mesh, but models of different meshes could not be ordered between each
+    /// other. Few models are transparent, so the extra draws are cheaper than wrong blending.
+    transparent: Vec<TransparentModel>,
+    /// The runs of `transparent` that are drawn together, as a mesh, whether they are
+    /// holograms and their instances
+    transparent_draws: Vec<(usize, bool, Range<u32>)>,
+    /// The transparent models that were outside of the camera's view in the last frame
+    transparent_culled: u32,
+    headless: bool,
 }
 
 impl MeshManager {
+    /// How far past a LOD distance the camera needs to move before the level changes.
+    /// This prevents models from switching every frame when they are near a boundary.
+    const LOD_HYSTERESIS: f32 = 0.1;
+    /// The most transparent models that are drawn in a frame. The furthest are left out
+    /// past it. This is enough for the hologram of a large ship (see entity::hologram).
+    const MAX_TRANSPARENT: u64 = 2048;
+
     pub fn new() -> MeshManager {
         MeshManager {
             meshes: Vec::new(),
             models: Vec::new(),
+            lod_chains: HashMap::new(),
+            collision_meshes: HashMap::new(),
+            gpu_models: Vec::new(),
+            pick_entities: Vec::new(),
+            culling: CullingPath::Cpu,
+            transparent: Vec::new(),
+            transparent_draws: Vec::new(),
+            transparent_culled: 0,
+            headless: false,
+        }
+    }
+
+    /// A MeshManager without a GPU, for running the simulation without a window. Its meshes
+    /// are placeholders, so models can still be created, moved and removed, but are never drawn.
+    pub fn headless() -> MeshManager {
+        MeshManager {
+            headless: true,
+            ..MeshManager::new()
         }
     }
 
     pub fn add(&mut self, device: &wgpu::Device, mesh: &Mesh) -> MeshId {
+        assert!(
+            !self.headless,
+            "Can not add GPU meshes to a headless MeshManager"
+        );
         let id = self.meshes.len();
         let gpu_mesh = GPUMesh::create(device, mesh, id);
         self.meshes.push(gpu_mesh);
@@ -142,12 +474,72 @@ impl MeshManager {
         MeshId(id)
     }
 
-    pub fn new_model(&mut self, mesh: MeshId, model: Matrix4<f32>) -> ModelId {
+    /// Registers a mesh that only stores models, for a headless MeshManager
+    pub fn add_placeholder(&mut self, name: &str) -> MeshId {
+        assert!(
+            self.headless,
+            "Placeholder meshes are only used when headless"
+        );
+        let id = self.models.len();
+        self.models.push(Arena::new());
+
+        println!("[Registered Placeholder Mesh] {}={}", name, id);
+
+        MeshId(id)
+    }
+
+    /// Registers a mesh with multiple levels of detail, starting with the most detailed
+    /// mesh. `distances` is the camera distance at which each level switches to the next.
+    /// The returned MeshId can be used like any other mesh.
+    pub fn add_lod_chain(
+        &mut self,
+        device: &wgpu::Device,
+        levels: &[Mesh],
+        distances: &[f32],
+    ) -> MeshId {
+        assert_eq!(
+            levels.len(),
+            distances.len() + 1,
+            "A LOD chain needs a distance between each level"
+        );
+
+        let levels: Vec<MeshId> = levels.iter().map(|mesh| self.add(device, mesh)).collect();
+        let base = levels[0];
+        self.lod_chains.insert(
+            base.0,
+            LodChain {
+                levels,
+                distances: distances.to_vec(),
+            },
+        );
+
+        base
+    }
+
+    /// Keeps the triangles of a mesh (or a simpler version of it) on the CPU, so precise
+    /// raycasts can be tested against the shape of its models
+    pub fn set_collision_mesh(&mut self, id: MeshId, mesh: &Mesh) {
+        self.collision_meshes.insert(id.0, CollisionMesh::new(mesh));
+    }
+
+    pub fn collision_mesh(&self, id: MeshId) -> Option<&CollisionMesh> {
+        self.collision_meshes.get(&id.0)
+    }
+
+    pub fn new_model(&mut self, mesh: MeshId, model: Matrix4<f32>, tint: ModelTint) -> ModelId {
         let arena = self
             .models
             .get_mut(mesh.0)
             .unwrap_or_else(|| panic!("Invalid mesh ID: {}", mesh.0));
-        arena.insert(model)
+        arena.insert(ModelInstance {
+            matrix: model,
+            previous: model,
+            lod: 0,
+            tint,
+            paint: ModelTint::NONE.primary,
+            entity: None,
+            hologram: false,
+        })
     }
 
     /// Updates the mesh manager with these updates. Will be pushed to the GPU during the next render
@@ -156,7 +548,68 @@ impl MeshManager {
             .models
             .get_mut(mesh_id.0)
             .unwrap_or_else(|| panic!("Invalid mesh ID: {}", mesh_id.0));
-        (*arena.get_mut(model_id).unwrap()) = model;
+        arena.get_mut(model_id).unwrap().matrix = model;
+    }
+
+    /// Moves the model to another mesh, keeping its matrix and tint. The model gets a new
+    /// ModelId, since the models are stored by mesh.
+    pub fn change_mesh(&mut self, mesh_id: MeshId, model_id: ModelId, new_mesh: MeshId) -> ModelId {
+        let arena = self
+            .models
+            .get_mut(mesh_id.0)
+            .unwrap_or_else(|| panic!("Invalid mesh ID: {}", mesh_id.0));
+        let instance = arena.remove(model_id).expect("Invalid model ID!");
+        let arena = self
+            .models
+            .get_mut(new_mesh.0)
+            .unwrap_or_else(|| panic!("Invalid mesh ID: {}", new_mesh.0));
+        // The level of detail is picked again for the new mesh
+        arena.insert(ModelInstance { lod: 0, ..instance })
+    }
+
+    pub fn update_tint(&mut self, mesh_id: MeshId, model_id: ModelId, tint: ModelTint) {
+        let arena = self
+            .models
+            .get_mut(mesh_id.0)
+            .unwrap_or_else(|| panic!("Invalid mesh ID: {}", mesh_id.0));
+        arena.get_mut(model_id).unwrap().tint = tint;
+    }
+
+    /// Sets the color that the model is painted in, which is white for unpainted models
+    pub fn update_paint(&mut self, mesh_id: MeshId, model_id: ModelId, paint: Vector4<f32>) {
+        let arena = self
+            .models
+            .get_mut(mesh_id.0)
+            .unwrap_or_else(|| panic!("Invalid mesh ID: {}", mesh_id.0));
+        arena.get_mut(model_id).unwrap().paint = paint;
+    }
+
+    /// Sets the entity that is picked when the model is under the cursor
+    pub fn set_model_entity(&mut self, mesh_id: MeshId, model_id: ModelId, entity: Entity) {
+        let arena = self
+            .models
+            .get_mut(mesh_id.0)
+            .unwrap_or_else(|| panic!("Invalid mesh ID: {}", mesh_id.0));
+        arena.get_mut(model_id).unwrap().entity = Some(entity);
+    }
+
+    /// Draws the model with the hologram look, see-through whatever its tint
+    pub fn set_hologram(&mut self, mesh_id: MeshId, model_id: ModelId, hologram: bool) {
+        let arena = self
+            .models
+            .get_mut(mesh_id.0)
+            .unwrap_or_else(|| panic!("Invalid mesh ID: {}", mesh_id.0));
+        arena.get_mut(model_id).unwrap().hologram = hologram;
+    }
+
+    /// Saves the current matrices of all the models, so that they can be interpolated
+    /// with the changes of the next fixed update. Should be called once at the start of each update.
+    pub fn store_previous_models(&mut self) {
+        for arena in &mut self.models {
+            for (_, instance) in arena.iter_mut() {
+                instance.previous = instance.matrix;
+            }
+        }
     }
 
     pub fn remove_model(&mut self, mesh_id: MeshId, model_id: ModelId) {
@@ -167,32 +620,243 @@ impl MeshManager {
         arena.remove(model_id).expect("Invalid model ID!");
     }
 
-    fn push_meshes_to_gpu(&mut self, queue: &wgpu::Queue) {
+    /// Picks the level of detail for every model of a LOD chain based on its distance to the camera
+    fn select_lods(&mut self, camera_pos: Point3<f32>) {
+        for (base, chain) in &self.lod_chains {
+            for (_, instance) in self.models[*base].iter_mut() {
+                let distance = (instance.matrix.w.truncate() - camera_pos.to_vec()).magnitude();
+                let mut lod = instance.lod.min(chain.distances.len());
+
+                while lod < chain.distances.len()
+                    && distance > chain.distances[lod] * (1.0 + MeshManager::LOD_HYSTERESIS)
+                {
+                    lod += 1;
+                }
+                while lod > 0
+                    && distance < chain.distances[lod - 1] * (1.0 - MeshManager::LOD_HYSTERESIS)
+                {
+                    lod -= 1;
+                }
+
+                instance.lod = lod;
+            }
+        }
+    }
+
+    /// Writes the opaque models to the buffers of their meshes, and the transparent models
+    /// to `transparent_buffer`
+    fn push_meshes_to_gpu(
+        &mut self,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        transparent_buffer: &wgpu::Buffer,
+        path: CullingPath,
+        alpha: f32,
+    ) {
+        let frustum = camera.frustum();
+        self.culling = path;
+        let pick_entities = &mut self.pick_entities;
+        pick_entities.clear();
+        self.transparent.clear();
         for (index, mesh) in &mut self.meshes.iter_mut().enumerate() {
+            if self.lod_chains.contains_key(&index) {
+                continue;
+            }
+
             let models = self
                 .models
                 .get(index)
                 .unwrap_or_else(|| panic!("Invalid mesh ID: {}", index));
-            if models.len() > GPUMesh::MODEL_COUNT as usize {
+
+            self.gpu_models.clear();
+            for (_, instance) in models.iter() {
+                let pick_id = picking::pick_id(pick_entities, instance.entity);
+                let model = instance.interpolated(alpha, pick_id);
+                if mesh.transparent || instance.is_transparent() {
+                    self.transparent.push(TransparentModel {
+                        mesh: index,
+                        hologram: instance.hologram,
+                        depth: camera.depth(model.matrix.w.truncate()),
+                        model,
+                    });
+                } else {
+                    self.gpu_models.push(model);
+                }
+            }
+            // Only the opaque models are in the mesh's buffer
+            if self.gpu_models.len() > GPUMesh::MODEL_COUNT as usize {
                 panic!("Too many models of MeshId={}", index);
             }
+            mesh.write_models(queue, &mut self.gpu_models, &frustum, path);
+        }
+
+        for (base, chain) in &self.lod_chains {
+            let models = &self.models[*base];
+            for (lod, level) in chain.levels.iter().enumerate() {
+                let mesh = &mut self.meshes[level.0];
+                self.gpu_models.clear();
+                for (_, instance) in models.iter().filter(|(_, instance)| instance.lod == lod) {
+                    let pick_id = picking::pick_id(pick_entities, instance.entity);
+                    let model = instance.interpolated(alpha, pick_id);
+                    if mesh.transparent || instance.is_transparent() {
+                        self.transparent.push(TransparentModel {
+                            mesh: level.0,
+                            hologram: instance.hologram,
+                            depth: camera.depth(model.matrix.w.truncate()),
+                            model,
+                        });
+                    } else {
+                        self.gpu_models.push(model);
+                    }
+                }
+                if self.gpu_models.len() > GPUMesh::MODEL_COUNT as usize {
+                    panic!("Too many models of MeshId={}", base);
+                }
+                mesh.write_models(queue, &mut self.gpu_models, &frustum, path);
+            }
+        }
+
+        self.push_transparent_to_gpu(queue, &frustum, transparent_buffer);
+    }
+
+    /// Culls the transparent models on the CPU (on both paths, since they are not in the
+    /// buffers the cull shader reads), and sorts them back to front
+    fn push_transparent_to_gpu(
+        &mut self,
+        queue: &wgpu::Queue,
+        frustum: &Frustum,
+        transparent_buffer: &wgpu::Buffer,
+    ) {
+        let meshes = &self.meshes;
+        let total = self.transparent.len();
+        self.transparent.retain(|transparent| {
+            let radius = meshes[transparent.mesh].radius;
+            meshes[transparent.mesh].visible
+                && culling::is_model_visible(frustum, &transparent.model.matrix, radius)
+        });
+        self.transparent_culled = (total - self.transparent.len()) as u32;
+
+        self.transparent
+            .sort_by(|a, b| b.depth.partial_cmp(&a.depth).unwrap_or(Ordering::Equal));
+        let excess = self
+            .transparent
+            .len()
+            .saturating_sub(MeshManager::MAX_TRANSPARENT as usize);
+        self.transparent.drain(..excess);
+
+        self.transparent_draws.clear();
+        for (index, transparent) in self.transparent.iter().enumerate() {
+            let index = index as u32;
+            match self.transparent_draws.last_mut() {
+                Some((mesh, hologram, instances))
+                    if *mesh == transparent.mesh && *hologram == transparent.hologram =>
+                {
+                    instances.end = index + 1
+                }
+                _ => self.transparent_draws.push((
+                    transparent.mesh,
+                    transparent.hologram,
+                    index..index + 1,
+                )),
+            }
+        }
+
+        self.gpu_models.clear();
+        self.gpu_models
+            .extend(self.transparent.iter().map(|transparent| transparent.model));
+        queue.write_buffer(
+            transparent_buffer,
+            0,
+            bytemuck::cast_slice(&self.gpu_models),
+        );
+    }
+
+    /// Draws the transparent models in the order they were sorted in. The holograms are
+    /// drawn with their own pipeline, which is switched to for each run of them.
+    fn draw_transparent<'a>(
+        &'a self,
+        rpass: &mut wgpu::RenderPass<'a>,
+        transparent_buffer: &'a wgpu::Buffer,
+        scene: &'a ScenePipelines,
+    ) {
+        rpass.set_vertex_buffer(1, transparent_buffer.slice(..));
+        let mut drawing_holograms = None;
+        for (mesh, hologram, instances) in &self.transparent_draws {
+            if drawing_holograms != Some(*hologram) {
+                rpass.set_pipeline(if *hologram {
+                    &scene.hologram_pipeline
+                } else {
+                    &scene.transparent_pipeline
+                });
+                drawing_holograms = Some(*hologram);
+            }
+            let mesh = &self.meshes[*mesh];
+            rpass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            rpass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            rpass.draw_indexed(0..mesh.index_count, 0, instances.clone());
+        }
+    }
+
+    /// Draws every model of the mesh in the transparent phase, whatever its tint
+    pub fn set_mesh_transparent(&mut self, mesh_id: MeshId, transparent: bool) {
+        if self.headless {
+            return;
+        }
+        self.meshes
+            .get_mut(mesh_id.0)
+            .unwrap_or_else(|| panic!("Invalid mesh ID: {}", mesh_id.0))
+            .transparent = transparent;
+    }
 
-            //We need to place the matrices in a struct that we can mark as Pod / Zeroable
-            let models: Vec<ModelMatrix> = models
+    /// Returns the amount of instances drawn at each level of detail of the mesh
+    pub fn lod_instance_counts(&self, mesh_id: MeshId) -> Vec<u32> {
+        match self.lod_chains.get(&mesh_id.0) {
+            Some(chain) => chain
+                .levels
                 .iter()
-                .map(|arena_entry| ModelMatrix(*arena_entry.1))
-                .collect();
-            mesh.instances = models.len() as u32;
-            queue.write_buffer(&mesh.models_buffer, 0, bytemuck::cast_slice(&models));
+                .map(|level| self.meshes[level.0].drawn)
+                .collect(),
+            None => vec![self.meshes[mesh_id.0].drawn],
+        }
+    }
+
+    /// The amount of models of every mesh
+    pub fn model_count(&self) -> usize {
+        self.models.iter().map(Arena::len).sum()
+    }
+
+    pub fn culling_stats(&self) -> CullingStats {
+        let visible_meshes = self.meshes.iter().filter(|mesh| mesh.visible);
+        let (drawn, total) = visible_meshes.fold((0, 0), |(drawn, total), mesh| {
+            (drawn + mesh.drawn, total + mesh.instances)
+        });
+
+        CullingStats {
+            path: self.culling,
+            drawn: drawn + self.transparent.len() as u32,
+            culled: total.saturating_sub(drawn) + self.transparent_culled,
         }
     }
 
     pub fn set_mesh_visisble(&mut self, mesh_id: MeshId, visible: bool) {
+        if self.headless {
+            return;
+        }
         self.meshes
             .get_mut(mesh_id.0)
             .unwrap_or_else(|| panic!("Invalid mesh ID: {}", mesh_id.0))
             .visible = visible;
     }
+
+    pub fn is_mesh_visible(&self, mesh_id: MeshId) -> bool {
+        if self.headless {
+            return false;
+        }
+        self.meshes
+            .get(mesh_id.0)
+            .unwrap_or_else(|| panic!("Invalid mesh ID: {}", mesh_id.0))
+            .visible
+    }
 }
 
 struct GPUMesh {
@@ -200,8 +864,18 @@ struct GPUMesh {
     index_buffer: wgpu::Buffer,
     index_count: u32,
     models_buffer: wgpu::Buffer,
+    /// The amount of models in the models buffer
     instances: u32,
+    /// The amount of models that survived culling
+    drawn: u32,
+    /// The distance from the origin of the mesh to its furthest vertex
+    radius: f32,
+    /// The corners of the box around the vertices, or None if the mesh has no vertices
+    bounds: Option<(Point3<f32>, Point3<f32>)>,
+    cull: culling::CullBuffers,
     visible: bool,
+    /// Whether every model of the mesh is drawn in the transparent phase
+    transparent: bool,
 }
 
 impl GPUMesh {
@@ -209,9 +883,17 @@ impl GPUMesh {
     const MODEL_COUNT: u64 = 512;
 
     fn create(device: &wgpu::Device, mesh: &Mesh, id: usize) -> GPUMesh {
+        let vertices: Vec<Vertex> = mesh
+            .vertices
+            .iter()
+            .map(|vertex| Vertex {
+                color: color::point_to_linear(vertex.color),
+                ..*vertex
+            })
+            .collect();
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some(&format!("VertexBuffer(Mesh={})", id)),
-            contents: bytemuck::cast_slice(&mesh.vertices),
+            contents: bytemuck::cast_slice(&vertices),
             usage: wgpu::BufferUsage::VERTEX,
         });
         let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -221,10 +903,18 @@ impl GPUMesh {
         });
         let models_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some(&format!("ModelBuffer(Mesh={})", id)),
-            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            usage: wgpu::BufferUsage::VERTEX
+                | wgpu::BufferUsage::STORAGE
+                | wgpu::BufferUsage::COPY_DST,
             mapped_at_creation: false,
-            size: mem::size_of::<ModelMatrix>() as u64 * GPUMesh::MODEL_COUNT,
+            size: mem::size_of::<GPUModel>() as u64 * GPUMesh::MODEL_COUNT,
         });
+        let radius = mesh
+            .vertices
+            .iter()
+            .map(|vertex| vertex.pos.to_vec().magnitude())
+            .fold(0.0, f32::max);
+        let bounds = bounding_box(mesh.vertices.iter().map(|vertex| vertex.pos));
 
         GPUMesh {
             vertex_buffer,
@@ -232,25 +922,100 @@ impl GPUMesh {
             models_buffer,
             index_count: mesh.indices.len() as u32,
             instances: 0,
+            drawn: 0,
+            radius,
+            bounds,
+            cull: culling::CullBuffers::new(device, id, GPUMesh::MODEL_COUNT),
             visible: true,
+            transparent: false,
+        }
+    }
+
+    /// Writes the models to the GPU. The CPU path only writes the visible models, while the
+    /// GPU path writes all of them for the cull shader.
+    fn write_models(
+        &mut self,
+        queue: &wgpu::Queue,
+        models: &mut Vec<GPUModel>,
+        frustum: &Frustum,
+        path: CullingPath,
+    ) {
+        match path {
+            CullingPath::Cpu => {
+                let radius = self.radius;
+                models.retain(|model| culling::is_model_visible(frustum, &model.matrix, radius));
+                self.drawn = models.len() as u32;
+            }
+            CullingPath::Gpu => {
+                self.cull
+                    .prepare(queue, frustum, self.index_count, self.radius, models)
+            }
+        }
+
+        self.instances = models.len() as u32;
+        queue.write_buffer(&self.models_buffer, 0, bytemuck::cast_slice(models));
+    }
+
+    fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, path: CullingPath) {
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        match path {
+            CullingPath::Cpu => {
+                rpass.set_vertex_buffer(1, self.models_buffer.slice(..));
+                rpass.draw_indexed(0..self.index_count, 0, 0..self.instances);
+            }
+            CullingPath::Gpu => {
+                rpass.set_vertex_buffer(1, self.cull.visible_models.slice(..));
+                rpass.draw_indexed_indirect(&self.cull.draw_args, 0);
+            }
         }
     }
 }
 
+/// The corners of the box around the points, or None if there are no points
+fn bounding_box(points: impl Iterator<Item = Point3<f32>>) -> Option<(Point3<f32>, Point3<f32>)> {
+    points.fold(None, |bounds, point| {
+        let (min, max) = bounds.unwrap_or((point, point));
+        Some((
+            Point3::new(min.x.min(point.x), min.y.min(point.y), min.z.min(point.z)),
+            Point3::new(max.x.max(point.x), max.y.max(point.y), max.z.max(point.z)),
+        ))
+    })
+}
+
 pub struct Renderer {
     pub ui_renderer: UiRenderer,
-    line_renderer: LineRenderer,
-    pipeline: wgpu::RenderPipeline,
+    scene: ScenePipelines,
+    backdrop: backdrop::Backdrop,
+    camera_bgl: wgpu::BindGroupLayout,
     camera_bg: wgpu::BindGroup,
     camera_buffer: wgpu::Buffer,
-    depth_texture: GPUTexture,
-    msaa_texture: GPUTexture,
+    /// The transparent models of the frame, see MeshManager::transparent
+    transparent_buffer: wgpu::Buffer,
+    targets: SceneTargets,
+    blit_pipeline: wgpu::RenderPipeline,
+    blit_bgl: wgpu::BindGroupLayout,
+    blit_sampler: wgpu::Sampler,
+    settings: GraphicsSettings,
+    /// None when the device can not cull on the GPU
+    culler: Option<culling::GpuCuller>,
+    picker: picking::GpuPicker,
+    /// None when the device can not write timestamps
+    timer: Option<timing::GpuTimer>,
+    capture: ReplayCapture,
+    format: wgpu::TextureFormat,
+    window_size: (u32, u32),
 }
 
 impl Renderer {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
-    pub fn new<'a>(device: &wgpu::Device, swapchain: &wgpu::SwapChainDescriptor) -> Renderer {
+    pub fn new(
+        device: &wgpu::Device,
+        swapchain: &wgpu::SwapChainDescriptor,
+        settings: GraphicsSettings,
+        timestamp_period: f32,
+    ) -> Renderer {
         let camera_buffer_size = 16 * mem::size_of::<f32>() as u64;
         let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Camera Buffer"),
@@ -286,123 +1051,236 @@ impl Renderer {
             label: Some("Camera Bind Group"),
         });
 
-        let vertex_bytes = read_file_bytes("assets/shaders/basic.vert.spv");
-        let vertex_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-            label: Some("Vertex"),
-            source: wgpu::util::make_spirv(&vertex_bytes),
-            flags: wgpu::ShaderFlags::VALIDATION,
-        });
-
-        let frag_bytes = read_file_bytes("assets/shaders/basic.frag.spv");
-        let frag_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-            label: Some("Fragment"),
-            source: wgpu::util::make_spirv(&frag_bytes),
-            flags: wgpu::ShaderFlags::VALIDATION,
-        });
-
-        let depth_texture = create_depth_texture(device, swapchain);
-        let msaa_texture = create_msaa_texture(device, swapchain);
-
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Std Pipeline Layout"),
-            bind_group_layouts: &[&camera_bgl],
-            push_constant_ranges: &[],
+        let scene =
+            ScenePipelines::new(device, &camera_bgl, swapchain.format, settings.msaa_samples);
+        let backdrop =
+            backdrop::Backdrop::new(device, &camera_bgl, swapchain.format, settings.msaa_samples);
+        let transparent_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Transparent Model Buffer"),
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+            size: mem::size_of::<GPUModel>() as u64 * MeshManager::MAX_TRANSPARENT,
         });
+        let ui_renderer = UiRenderer::new(device, swapchain);
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: wgpu::CullMode::Back,
-                polygon_mode: if crate::WIREFRAME_MODE {
-                    wgpu::PolygonMode::Line
-                } else {
-                    wgpu::PolygonMode::Fill
+        let blit_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
                 },
-            },
-            multisample: wgpu::MultisampleState {
-                count: crate::MSAA_SAMPLE,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: Renderer::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-                clamp_depth: false,
-            }),
-            vertex: wgpu::VertexState {
-                module: &vertex_shader,
-                entry_point: "main",
-                buffers: &[
-                wgpu::VertexBufferLayout {
-                    array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
-                    step_mode: wgpu::InputStepMode::Vertex,
-                    attributes: &wgpu::vertex_attr_array![0 => Float3, 1 => Float3, 2 => Float3],
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
                 },
-                wgpu::VertexBufferLayout {
-                    array_stride: mem::size_of::<ModelMatrix>() as wgpu::BufferAddress,
-                    step_mode: wgpu::InputStepMode::Instance,
-                    attributes: &wgpu::vertex_attr_array![3 => Float4, 4 => Float4, 5 => Float4, 6 => Float4],
-                }],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &frag_shader,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState {
-                    format: swapchain.format,
-                    color_blend: wgpu::BlendState::default(),
-                    alpha_blend: wgpu::BlendState::default(),
-                    write_mask: wgpu::ColorWrite::ALL,
-                }]
-            }),
+            ],
+            label: Some("Blit Bind Group Layout"),
         });
-
-        let line_renderer = LineRenderer::new(device, &camera_bgl, swapchain);
-        let ui_renderer = UiRenderer::new(device, swapchain);
+        let blit_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Blit Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let blit_pipeline = create_blit_pipeline(device, &blit_bgl, swapchain.format);
+
+        let window_size = (swapchain.width, swapchain.height);
+        let targets = SceneTargets::new(
+            device,
+            &blit_bgl,
+            &blit_sampler,
+            swapchain.format,
+            window_size,
+            &settings,
+        );
+        let culler = culling::GpuCuller::new(device);
+        if culler.is_none() && settings.gpu_culling {
+            println!("[Graphics] GPU culling is not supported, using CPU culling instead");
+        }
+        let picker = picking::GpuPicker::new(device, &camera_bgl);
+        let timer = timing::GpuTimer::new(device, timestamp_period);
+        if timer.is_none() {
+            println!("[Graphics] GPU timestamps are not supported, the passes are not timed");
+        }
 
         Renderer {
-            pipeline,
+            scene,
+            backdrop,
+            camera_bgl,
             camera_bg,
             camera_buffer,
-            depth_texture,
-            msaa_texture,
-            line_renderer,
+            transparent_buffer,
+            targets,
+            blit_pipeline,
+            blit_bgl,
+            blit_sampler,
+            settings,
+            culler,
+            picker,
+            timer,
+            capture: ReplayCapture::new(),
+            format: swapchain.format,
+            window_size,
             ui_renderer,
         }
     }
 
+    pub fn settings(&self) -> GraphicsSettings {
+        self.settings
+    }
+
+    /// Draws the meshes of each icon into a small image (see icons::render_icons). Needs to
+    /// be called after the meshes are added, and before the first frame.
+    pub fn render_icons(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mesh_manager: &MeshManager,
+        icons: &[IconParts],
+    ) -> Vec<image::RgbaImage> {
+        icons::render_icons(device, queue, &self.camera_bgl, mesh_manager, icons)
+    }
+
+    /// Rebuilds the textures and pipelines that depend on the settings that changed
+    pub fn apply_settings(&mut self, device: &wgpu::Device, settings: GraphicsSettings) {
+        if settings.msaa_samples != self.settings.msaa_samples {
+            self.scene =
+                ScenePipelines::new(device, &self.camera_bgl, self.format, settings.msaa_samples);
+            self.backdrop.rebuild_pipeline(
+                device,
+                &self.camera_bgl,
+                self.format,
+                settings.msaa_samples,
+            );
+        }
+
+        let previous = std::mem::replace(&mut self.settings, settings);
+        if settings.msaa_samples != previous.msaa_samples
+            || settings.render_scale != previous.render_scale
+        {
+            self.rebuild_targets(device);
+        }
+        if settings.capture_fps != previous.capture_fps
+            || settings.capture_seconds != previous.capture_seconds
+        {
+            self.capture.reset();
+        }
+    }
+
+    /// The culling path that is used, which is the CPU if the device can not cull on the GPU
+    pub fn culling_path(&self) -> CullingPath {
+        if self.settings.gpu_culling && self.culler.is_some() {
+            CullingPath::Gpu
+        } else {
+            CullingPath::Cpu
+        }
+    }
+
+    fn rebuild_targets(&mut self, device: &wgpu::Device) {
+        self.targets = SceneTargets::new(
+            device,
+            &self.blit_bgl,
+            &self.blit_sampler,
+            self.format,
+            self.window_size,
+            &self.settings,
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn render_world(
         &mut self,
+        device: &wgpu::Device,
         queue: &wgpu::Queue,
         frame: &wgpu::SwapChainTexture,
         encoder: &mut wgpu::CommandEncoder,
         camera: &Camera,
         mesh_manager: &mut MeshManager,
         lines: &[Line],
+        particles: &[GPUParticle],
+        billboards: &[GPUBillboard],
+        pick: &PickRequest,
+        alpha: f32,
     ) {
+        if let Some(timer) = &self.timer {
+            timer.write(encoder, Timestamp::WorldStart);
+        }
+        let particle_count = particles
+            .len()
+            .min(ParticleRenderer::MAX_PARTICLES as usize);
+        let particles: Vec<GPUParticle> = particles[..particle_count]
+            .iter()
+            .map(|particle| GPUParticle {
+                color: color::to_linear_alpha(particle.color),
+                ..*particle
+            })
+            .collect();
+        let billboard_count = billboards
+            .len()
+            .min(BillboardRenderer::MAX_BILLBOARDS as usize);
+        let billboards: Vec<GPUBillboard> = billboards[..billboard_count]
+            .iter()
+            .map(|billboard| GPUBillboard {
+                color: color::to_linear_alpha(billboard.color),
+                ..*billboard
+            })
+            .collect();
+        self.scene.line_renderer.prepare(queue, lines);
         queue.write_buffer(
-            &self.line_renderer.vertex_buffer,
+            &self.scene.particle_renderer.instance_buffer,
             0,
-            bytemuck::cast_slice(lines),
+            bytemuck::cast_slice(&particles),
         );
-        mesh_manager.push_meshes_to_gpu(queue);
+        queue.write_buffer(
+            &self.scene.billboard_renderer.instance_buffer,
+            0,
+            bytemuck::cast_slice(&billboards),
+        );
+        queue.write_buffer(
+            &self.scene.particle_renderer.billboard_buffer,
+            0,
+            bytemuck::cast_slice(&[camera.billboard_axes()]),
+        );
+        // The build view looks straight down at the ship, where the backdrop would only be in
+        // the way
+        let backdrop = self.settings.backdrop && camera.projection == Projection::Perspective;
+        if backdrop {
+            self.backdrop.prepare(queue, camera);
+        }
+        let culling = self.culling_path();
+        mesh_manager.select_lods(camera.position);
+        mesh_manager.push_meshes_to_gpu(queue, camera, &self.transparent_buffer, culling, alpha);
+        if let (CullingPath::Gpu, Some(culler)) = (culling, &mut self.culler) {
+            culler.cull(device, encoder, mesh_manager);
+        }
+        let view_projection = camera.build_view_projection_matrix();
         queue.write_buffer(
             &self.camera_buffer,
             0,
-            bytemuck::cast_slice(&[camera.build_view_projection_matrix()]),
+            bytemuck::cast_slice(&[view_projection]),
         );
 
+        // Without MSAA the scene is drawn straight to the texture that is scaled to the window
+        let (attachment, resolve_target) = match &self.targets.msaa {
+            Some(msaa) => (&msaa.view, Some(&self.targets.color.view)),
+            None => (&self.targets.color.view, None),
+        };
         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
             color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                attachment: &self.msaa_texture.view,
-                resolve_target: Some(&frame.view),
+                attachment,
+                resolve_target,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Clear(wgpu::Color {
                         r: 0.001,
@@ -414,7 +1292,7 @@ impl Renderer {
                 },
             }],
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
-                attachment: &self.depth_texture.view,
+                attachment: &self.targets.depth.view,
                 depth_ops: Some(wgpu::Operations {
                     load: wgpu::LoadOp::Clear(1.0),
                     store: true,
@@ -422,25 +1300,132 @@ impl Renderer {
                 stencil_ops: None,
             }),
         });
-        rpass.set_pipeline(&self.pipeline);
+        if backdrop {
+            self.backdrop.draw(&mut rpass, &self.camera_bg);
+        }
+        rpass.set_pipeline(&self.scene.pipeline);
         rpass.set_bind_group(0, &self.camera_bg, &[]);
 
         mesh_manager
             .meshes
             .iter()
             .filter(|mesh| mesh.visible)
-            .for_each(|mesh| {
-                rpass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-                rpass.set_vertex_buffer(1, mesh.models_buffer.slice(..));
-                rpass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-                rpass.draw_indexed(0..mesh.index_count, 0, 0..mesh.instances);
-            });
+            .for_each(|mesh| mesh.draw(&mut rpass, culling));
+
+        self.scene
+            .line_renderer
+            .draw(&mut rpass, &self.scene.particle_renderer.billboard_bg);
+
+        // The transparent models are blended over everything opaque. They would hide what
+        // is behind them in wireframe, so they are left out.
+        if !crate::WIREFRAME_MODE && !mesh_manager.transparent_draws.is_empty() {
+            rpass.set_bind_group(0, &self.camera_bg, &[]);
+            mesh_manager.draw_transparent(&mut rpass, &self.transparent_buffer, &self.scene);
+        }
+
+        // Particles are additive, so they need to be drawn after all of the opaque geometry
+        if !particles.is_empty() {
+            rpass.set_pipeline(&self.scene.particle_renderer.pipeline);
+            rpass.set_bind_group(1, &self.scene.particle_renderer.billboard_bg, &[]);
+            rpass.set_vertex_buffer(0, self.scene.particle_renderer.instance_buffer.slice(..));
+            rpass.draw(0..6, 0..particles.len() as u32);
+        }
+
+        if !billboards.is_empty() {
+            rpass.set_pipeline(&self.scene.billboard_renderer.pipeline);
+            rpass.set_bind_group(1, &self.scene.particle_renderer.billboard_bg, &[]);
+            rpass.set_vertex_buffer(0, self.scene.billboard_renderer.instance_buffer.slice(..));
+            rpass.draw(0..6, 0..billboards.len() as u32);
+        }
 
-        rpass.set_pipeline(&self.line_renderer.pipeline);
-        rpass.set_vertex_buffer(0, self.line_renderer.vertex_buffer.slice(..));
-        rpass.draw(0..2, 0..lines.len() as u32);
+        self.scene
+            .line_renderer
+            .draw_overlay(&mut rpass, &self.scene.particle_renderer.billboard_bg);
 
         std::mem::drop(rpass);
+
+        if let (true, Some(position)) = (self.settings.gpu_picking, pick.position) {
+            self.picker.pick(
+                queue,
+                encoder,
+                mesh_manager,
+                culling,
+                view_projection,
+                self.window_size,
+                position,
+            );
+        }
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Blit Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: &frame.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_pipeline(&self.blit_pipeline);
+        rpass.set_bind_group(0, &self.targets.blit_bg, &[]);
+        rpass.draw(0..3, 0..1);
+
+        std::mem::drop(rpass);
+
+        self.capture.capture(
+            device,
+            encoder,
+            &self.blit_pipeline,
+            &self.targets.blit_bg,
+            self.format,
+            &self.settings,
+            self.window_size,
+        );
+        if let Some(timer) = &self.timer {
+            timer.write(encoder, Timestamp::WorldEnd);
+        }
+    }
+
+    /// Reads back the results of GPU culling. Needs to be called after the frame is submitted.
+    pub fn read_culling_results(&mut self, device: &wgpu::Device, mesh_manager: &mut MeshManager) {
+        if let Some(culler) = &mut self.culler {
+            culler.read_back(device, mesh_manager);
+        }
+    }
+
+    /// Reads back the entity under the cursor, and returns the latest result. Needs to be
+    /// called after the frame is submitted.
+    pub fn read_pick_result(&mut self, device: &wgpu::Device) -> PickResult {
+        if self.settings.gpu_picking {
+            self.picker.read_back(device)
+        } else {
+            PickResult::default()
+        }
+    }
+
+    /// Reads back how long the GPU spent on the passes of an earlier frame, if the device
+    /// can write timestamps and the times are ready. Needs to be called after the frame is
+    /// submitted.
+    pub fn read_gpu_times(&mut self, device: &wgpu::Device) -> Option<GpuPassTimes> {
+        self.timer.as_mut()?.read_back(device)
+    }
+
+    /// Adds the last captured frame to the replay capture. Needs to be called after the
+    /// frame is submitted.
+    pub fn read_capture(&mut self, device: &wgpu::Device) {
+        self.capture.read_back(device, self.format, &self.settings);
+    }
+
+    /// Starts or stops keeping the last seconds of the scene, see ReplayCapture
+    pub fn toggle_capture(&mut self) {
+        self.capture.toggle(&self.settings, self.window_size);
+    }
+
+    /// Saves the kept seconds as a GIF in the background
+    pub fn save_capture(&mut self) {
+        self.capture.save(&self.settings);
     }
 
     pub fn render_ui(
@@ -449,12 +1434,15 @@ impl Renderer {
         frame: &wgpu::SwapChainTexture,
         encoder: &mut wgpu::CommandEncoder,
     ) {
+        if let Some(timer) = &self.timer {
+            timer.write(encoder, Timestamp::UiStart);
+        }
         let sprites = self.ui_renderer.batch.sprites();
         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
             color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                attachment: &self.msaa_texture.view,
-                resolve_target: Some(&frame.view),
+                attachment: &frame.view,
+                resolve_target: None,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Load,
                     store: true,
@@ -474,6 +1462,11 @@ impl Renderer {
         }
 
         std::mem::drop(rpass);
+        // The UI is the last pass of the frame
+        if let Some(timer) = &mut self.timer {
+            timer.write(encoder, Timestamp::UiEnd);
+            timer.resolve(encoder);
+        }
     }
 
     pub fn resize(
@@ -482,55 +1475,350 @@ impl Renderer {
         queue: &wgpu::Queue,
         swapchain: &wgpu::SwapChainDescriptor,
     ) {
-        self.depth_texture = create_depth_texture(device, swapchain);
-        self.msaa_texture = create_msaa_texture(device, swapchain);
+        self.window_size = (swapchain.width, swapchain.height);
+        self.rebuild_targets(device);
         self.ui_renderer.camera.update(queue, swapchain);
     }
 }
 
+/// The pipelines that draw the 3D scene. They depend on the MSAA sample count, so they are
+/// recreated when it changes.
+struct ScenePipelines {
+    pipeline: wgpu::RenderPipeline,
+    /// Blends the transparent models over the opaque ones. They are still tested against
+    /// the depth, but don't write it, so the models behind them are still drawn.
+    transparent_pipeline: wgpu::RenderPipeline,
+    /// Draws the hologram models like the transparent ones, with a fragment shader that gives
+    /// them scanlines and glowing edges
+    hologram_pipeline: wgpu::RenderPipeline,
+    line_renderer: LineRenderer,
+    particle_renderer: ParticleRenderer,
+    billboard_renderer: BillboardRenderer,
+}
+
+impl ScenePipelines {
+    fn new(
+        device: &wgpu::Device,
+        camera_bgl: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let vertex_bytes = read_required_asset("shaders/basic.vert.spv");
+        let vertex_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Vertex"),
+            source: wgpu::util::make_spirv(&vertex_bytes),
+            flags: wgpu::ShaderFlags::VALIDATION,
+        });
+
+        let frag_bytes = read_required_asset("shaders/basic.frag.spv");
+        let frag_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Fragment"),
+            source: wgpu::util::make_spirv(&frag_bytes),
+            flags: wgpu::ShaderFlags::VALIDATION,
+        });
+
+        let hologram_bytes = read_required_asset("shaders/hologram.frag.spv");
+        let hologram_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Hologram Fragment"),
+            source: wgpu::util::make_spirv(&hologram_bytes),
+            flags: wgpu::ShaderFlags::VALIDATION,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Std Pipeline Layout"),
+            bind_group_layouts: &[camera_bgl],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |frag_shader: &wgpu::ShaderModule, pass: MeshPass| {
+            create_mesh_pipeline(
+                device,
+                &pipeline_layout,
+                &vertex_shader,
+                frag_shader,
+                format,
+                sample_count,
+                pass,
+            )
+        };
+        let pipeline = create_pipeline(&frag_shader, MeshPass::Opaque);
+        let transparent_pipeline = create_pipeline(&frag_shader, MeshPass::Transparent);
+        let hologram_pipeline = create_pipeline(&hologram_shader, MeshPass::Hologram);
+
+        let particle_renderer = ParticleRenderer::new(device, camera_bgl, format, sample_count);
+        let line_renderer = LineRenderer::new(
+            device,
+            camera_bgl,
+            &particle_renderer.billboard_bgl,
+            format,
+            sample_count,
+        );
+        let billboard_renderer = BillboardRenderer::new(
+            device,
+            camera_bgl,
+            &particle_renderer.billboard_bgl,
+            format,
+            sample_count,
+        );
+
+        Self {
+            pipeline,
+            transparent_pipeline,
+            hologram_pipeline,
+            line_renderer,
+            particle_renderer,
+            billboard_renderer,
+        }
+    }
+}
+
+/// What a mesh pipeline draws, which decides its blending and depth writes
+#[derive(Clone, Copy, PartialEq)]
+enum MeshPass {
+    Opaque,
+    Transparent,
+    Hologram,
+}
+
+/// Draws the meshes with their models as instances. Transparent models (and holograms) are
+/// blended with their alpha, and only the opaque models write the depth.
+fn create_mesh_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    vertex_shader: &wgpu::ShaderModule,
+    frag_shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    pass: MeshPass,
+) -> wgpu::RenderPipeline {
+    let transparent = pass != MeshPass::Opaque;
+    let (color_blend, alpha_blend) = if transparent {
+        (
+            wgpu::BlendState {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+            // Keeps the scene opaque where a transparent model is drawn over the background
+            wgpu::BlendState {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+        )
+    } else {
+        (wgpu::BlendState::default(), wgpu::BlendState::default())
+    };
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(match pass {
+            MeshPass::Opaque => "Mesh Pipeline",
+            MeshPass::Transparent => "Transparent Mesh Pipeline",
+            MeshPass::Hologram => "Hologram Mesh Pipeline",
+        }),
+        layout: Some(layout),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::Back,
+            polygon_mode: if crate::WIREFRAME_MODE {
+                wgpu::PolygonMode::Line
+            } else {
+                wgpu::PolygonMode::Fill
+            },
+        },
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Renderer::DEPTH_FORMAT,
+            depth_write_enabled: !transparent,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+            clamp_depth: false,
+        }),
+        vertex: wgpu::VertexState {
+            module: vertex_shader,
+            entry_point: "main",
+            buffers: &MESH_BUFFERS,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: frag_shader,
+            entry_point: "main",
+            targets: &[wgpu::ColorTargetState {
+                format,
+                color_blend,
+                alpha_blend,
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+        }),
+    })
+}
+
+/// The textures the 3D scene is drawn to, which are sized by the render scale. The scene is
+/// then scaled to the window.
+struct SceneTargets {
+    color: GPUTexture,
+    /// Only used with MSAA. It is resolved into the color texture.
+    msaa: Option<GPUTexture>,
+    depth: GPUTexture,
+    blit_bg: wgpu::BindGroup,
+}
+
+impl SceneTargets {
+    fn new(
+        device: &wgpu::Device,
+        blit_bgl: &wgpu::BindGroupLayout,
+        blit_sampler: &wgpu::Sampler,
+        format: wgpu::TextureFormat,
+        window_size: (u32, u32),
+        settings: &GraphicsSettings,
+    ) -> Self {
+        let (width, height) = settings.scene_size(window_size.0, window_size.1);
+        let samples = settings.msaa_samples;
+        let color = create_texture(
+            device,
+            "Scene Texture",
+            (width, height, 1),
+            format,
+            wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        );
+        let msaa = if samples > 1 {
+            Some(create_texture(
+                device,
+                "MSAA Texture",
+                (width, height, samples),
+                format,
+                wgpu::TextureUsage::RENDER_ATTACHMENT,
+            ))
+        } else {
+            None
+        };
+        let depth = create_texture(
+            device,
+            "Depth Texture",
+            (width, height, samples),
+            Renderer::DEPTH_FORMAT,
+            wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        );
+
+        let blit_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: blit_bgl,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&color.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(blit_sampler),
+                },
+            ],
+            label: Some("Blit Bind Group"),
+        });
+
+        Self {
+            color,
+            msaa,
+            depth,
+            blit_bg,
+        }
+    }
+}
+
 struct GPUTexture {
     view: wgpu::TextureView,
 }
 
-fn create_depth_texture(
+/// `size` is the width, height and sample count of the texture
+fn create_texture(
     device: &wgpu::Device,
-    swapchain: &wgpu::SwapChainDescriptor,
+    label: &str,
+    size: (u32, u32, u32),
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsage,
 ) -> GPUTexture {
+    let (width, height, sample_count) = size;
     let texture = device.create_texture(&wgpu::TextureDescriptor {
-        label: Some("Depth Texture"),
+        label: Some(label),
         size: wgpu::Extent3d {
-            width: swapchain.width,
-            height: swapchain.height,
+            width,
+            height,
             depth: 1,
         },
         mip_level_count: 1,
-        sample_count: crate::MSAA_SAMPLE,
+        sample_count,
         dimension: wgpu::TextureDimension::D2,
-        format: Renderer::DEPTH_FORMAT,
-        usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        format,
+        usage,
     });
     let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
 
     GPUTexture { view }
 }
 
-fn create_msaa_texture(device: &wgpu::Device, swapchain: &wgpu::SwapChainDescriptor) -> GPUTexture {
-    let texture = device.create_texture(&wgpu::TextureDescriptor {
-        label: Some("MSAA Texture"),
-        size: wgpu::Extent3d {
-            width: swapchain.width,
-            height: swapchain.height,
-            depth: 1,
-        },
-        mip_level_count: 1,
-        sample_count: crate::MSAA_SAMPLE,
-        dimension: wgpu::TextureDimension::D2,
-        format: swapchain.format,
-        usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+/// Scales the scene to the window
+fn create_blit_pipeline(
+    device: &wgpu::Device,
+    blit_bgl: &wgpu::BindGroupLayout,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    let vertex_bytes = read_required_asset("shaders/blit.vert.spv");
+    let vertex_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some("Blit Vertex"),
+        source: wgpu::util::make_spirv(&vertex_bytes),
+        flags: wgpu::ShaderFlags::VALIDATION,
     });
-    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-    GPUTexture { view }
+    let frag_bytes = read_required_asset("shaders/blit.frag.spv");
+    let frag_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some("Blit Fragment"),
+        source: wgpu::util::make_spirv(&frag_bytes),
+        flags: wgpu::ShaderFlags::VALIDATION,
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Blit Pipeline Layout"),
+        bind_group_layouts: &[blit_bgl],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Blit Pipeline"),
+        layout: Some(&pipeline_layout),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+        },
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        depth_stencil: None,
+        vertex: wgpu::VertexState {
+            module: &vertex_shader,
+            entry_point: "main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &frag_shader,
+            entry_point: "main",
+            targets: &[wgpu::ColorTargetState {
+                format,
+                color_blend: wgpu::BlendState::REPLACE,
+                alpha_blend: wgpu::BlendState::REPLACE,
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+        }),
+    })
 }
 
 #[derive(Clone, Copy)]
@@ -539,14 +1827,41 @@ pub struct CameraMatrix(Matrix4<f32>);
 unsafe impl bytemuck::Pod for CameraMatrix {}
 unsafe impl bytemuck::Zeroable for CameraMatrix {}
 
+/// The planes around the camera's view volume (pointing inwards)
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    pub fn intersects_sphere(&self, center: Vector3<f32>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(center) + plane.w >= -radius)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    Perspective,
+    /// Looks straight down, with the yaw pointing to the top of the screen. The half height
+    /// is how far the view reaches above (and below) the center of the screen.
+    Orthographic {
+        half_height: f32,
+    },
+}
+
 pub struct Camera {
     pub position: Point3<f32>,
     pub yaw: f32,
+    /// Only used by the perspective projection
     pub pitch: f32,
     pub aspect: f32,
     pub fov: f32,
     pub near: f32,
     pub far: f32,
+    pub projection: Projection,
+    /// Set by the CameraController
+    pub shake: CameraShake,
 }
 
 impl Camera {
@@ -562,17 +1877,116 @@ impl Camera {
         self.aspect = swapchain.width as f32 / swapchain.height as f32;
     }
 
+    pub fn direction(&self) -> Vector3<f32> {
+        self.direction_at(self.yaw, self.pitch)
+    }
+
+    fn direction_at(&self, yaw: f32, pitch: f32) -> Vector3<f32> {
+        match self.projection {
+            Projection::Perspective => Vector3::new(yaw.cos(), yaw.sin(), pitch.sin()).normalize(),
+            Projection::Orthographic { .. } => -Vector3::unit_z(),
+        }
+    }
+
+    /// How far the point is from the camera, which the transparent models are sorted by.
+    /// Every orthographic ray is parallel, so only the distance along the view counts.
+    pub fn depth(&self, point: Vector3<f32>) -> f32 {
+        let offset = point - self.position.to_vec();
+        match self.projection {
+            Projection::Perspective => offset.magnitude(),
+            Projection::Orthographic { .. } => offset.dot(self.direction()),
+        }
+    }
+
+    /// The direction that is up on the screen. Looking straight down, the z axis can't be
+    /// used, so the yaw points up instead.
+    fn up_at(&self, yaw: f32) -> Vector3<f32> {
+        match self.projection {
+            Projection::Perspective => Vector3::unit_z(),
+            Projection::Orthographic { .. } => Vector3::new(yaw.cos(), yaw.sin(), 0.0),
+        }
+    }
+
+    /// The position, direction and up direction of the view, which includes the shake
+    fn view(&self) -> (Point3<f32>, Vector3<f32>, Vector3<f32>) {
+        let yaw = self.yaw + self.shake.yaw;
+        let pitch = self.pitch + self.shake.pitch;
+        (
+            self.position + self.shake.offset,
+            self.direction_at(yaw, pitch),
+            self.up_at(yaw),
+        )
+    }
+
+    fn build_projection(&self) -> Matrix4<f32> {
+        match self.projection {
+            Projection::Perspective => {
+                cgmath::perspective(cgmath::Deg(self.fov), self.aspect, self.near, self.far)
+            }
+            Projection::Orthographic { half_height } => {
+                let half_width = half_height * self.aspect;
+                cgmath::ortho(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.near,
+                    self.far,
+                )
+            }
+        }
+    }
+
     fn build_view_projection_matrix(&self) -> CameraMatrix {
-        let view = Matrix4::look_at_dir(
-            self.position,
-            Vector3::new(self.yaw.cos(), self.yaw.sin(), self.pitch.sin()).normalize(),
-            Vector3::unit_z(),
-        );
-        let proj = cgmath::perspective(cgmath::Deg(self.fov), self.aspect, self.near, self.far);
+        let (position, direction, up) = self.view();
+        let view = Matrix4::look_at_dir(position, direction, up);
+
+        CameraMatrix(Self::OPENGL_TO_WGPU_MATRIX * self.build_projection() * view)
+    }
+
+    fn billboard_axes(&self) -> BillboardAxes {
+        let (_, direction, up) = self.view();
+        let right = direction.cross(up).normalize();
+        let up = right.cross(direction).normalize();
+
+        BillboardAxes {
+            right: right.extend(0.0),
+            up: up.extend(0.0),
+        }
+    }
+
+    /// Returns the view volume of the camera
+    pub fn frustum(&self) -> Frustum {
+        // cgmath matrices are column major, so each row is taken from all of the columns
+        let matrix = self.build_view_projection_matrix().0;
+        let row = |i: usize| Vector4::new(matrix.x[i], matrix.y[i], matrix.z[i], matrix.w[i]);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        // The depth is from 0 to 1 in wgpu, so the near plane is just the z row
+        let mut planes = [w + x, w - x, w + y, w - y, z, w - z];
+        for plane in &mut planes {
+            *plane /= plane.truncate().magnitude();
+        }
 
-        CameraMatrix(Self::OPENGL_TO_WGPU_MATRIX * proj * view)
+        Frustum { planes }
     }
 
+    /// Finds where a point in the world is on the screen (with the origin in the bottom left).
+    /// Returns None if the point is behind the camera.
+    pub fn project(&self, point: Vector3<f32>, screen_size: Point2<f32>) -> Option<Point2<f32>> {
+        let clip = self.build_view_projection_matrix().0 * point.extend(1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+
+        Some(Point2::new(
+            (clip.x / clip.w + 1.0) / 2.0 * screen_size.x,
+            (clip.y / clip.w + 1.0) / 2.0 * screen_size.y,
+        ))
+    }
+
+    /// Finds the point in the world at a screen position and depth (0 at the near plane, 1 at
+    /// the far plane). In the orthographic projection, the points at the near and far plane
+    /// make a ray that is parallel to the view direction.
     pub fn unproject(&self, input: Vector3<f32>, screen_size: Point2<f32>) -> Vector3<f32> {
         // See https://stackoverflow.com/questions/23644470/how-to-convert-mouse-coordinate-on-screen-to-3d-coordinate
         let mut normalized_coords = Vector4::new(
@@ -603,10 +2017,7 @@ impl Camera {
     }
 }
 
-pub fn read_file_bytes(path: &str) -> Vec<u8> {
-    if let Ok(bytes) = std::fs::read(path) {
-        bytes
-    } else {
-        panic!("Unable to load file: {}", path);
-    }
+/// Reads an asset that the renderer can't run without (ex. a shader)
+pub fn read_required_asset(name: &str) -> Vec<u8> {
+    crate::assets::read_bytes(name).unwrap_or_else(|error| panic!("{}", error))
 }
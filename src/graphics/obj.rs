@@ -1,6 +1,7 @@
-use super::{Mesh, Vertex};
-use cgmath::{Point2, Point3, Vector4};
-use std::fs;
+use super::{glb, Mesh, Vertex};
+use crate::assets;
+use crate::content::ContentErrors;
+use cgmath::{Point2, Point3, Vector3, Vector4};
 use std::str::FromStr;
 
 lazy_static! {
@@ -87,51 +88,188 @@ lazy_static! {
     ];
 }
 
-/// Loads a mesh from the assets using the default pallete
-pub fn load_mesh(name: &str) -> Mesh {
-    let mut mesh = Mesh {
+/// A named part of a mesh that can be moved on its own (ex. a barrel that recoils). In the
+/// obj file, it is an object named "node_<name>", optionally followed by a
+/// "# pivot <x> <y> <z>" comment. Its vertices are stored relative to the pivot, so it
+/// rotates and scales around it.
+pub struct SubMesh {
+    pub name: String,
+    pub pivot: Vector3<f32>,
+    pub mesh: Mesh,
+}
+
+/// Loads a mesh from the assets using the default pallete. If the mesh can't be loaded,
+/// the failure is added to the errors and a placeholder is returned instead, so the rest
+/// of the content can still be used. Sub-meshes are merged back into the mesh.
+pub fn load_mesh(name: &str, errors: &mut ContentErrors) -> Mesh {
+    let (mut mesh, sub_meshes) = load_mesh_with_nodes(name, errors);
+    for sub_mesh in sub_meshes {
+        let offset = mesh.vertices.len() as u16;
+        mesh.vertices
+            .extend(sub_mesh.mesh.vertices.iter().map(|vertex| Vertex {
+                pos: vertex.pos + sub_mesh.pivot,
+                ..*vertex
+            }));
+        mesh.indices
+            .extend(sub_mesh.mesh.indices.iter().map(|index| index + offset));
+    }
+    mesh
+}
+
+/// Loads a mesh and its sub-meshes, which are left out of the mesh. Meshes without
+/// sub-meshes are loaded exactly like with load_mesh.
+pub fn load_mesh_with_nodes(name: &str, errors: &mut ContentErrors) -> (Mesh, Vec<SubMesh>) {
+    let path = mesh_path(name).unwrap_or_else(|| format!("models/{}.obj", name));
+    let bytes = assets::read_bytes(&path).map_err(|e| e.to_string());
+    parse_or_placeholder(name, path, bytes, errors)
+}
+
+/// Parses the file of the mesh. If it could not be read or parsed, the failure is added to
+/// the errors and a placeholder is returned instead.
+fn parse_or_placeholder(
+    name: &str,
+    path: String,
+    bytes: Result<Vec<u8>, String>,
+    errors: &mut ContentErrors,
+) -> (Mesh, Vec<SubMesh>) {
+    bytes
+        .and_then(|bytes| parse_mesh(name, &path, bytes))
+        .unwrap_or_else(|error| {
+            println!("[Mesh Error] {}: {}", name, error);
+            errors.add(path, error);
+            (placeholder_mesh(name), Vec::new())
+        })
+}
+
+/// Loads the lower levels of detail made for the mesh, which are stored next to it with a
+/// "_lod1", "_lod2", ... suffix. A level without a file is None, so the caller can fall back
+/// to a mesh of its own (ex. the base mesh, or a decimated copy of it).
+pub fn load_mesh_lods(name: &str, levels: usize, errors: &mut ContentErrors) -> Vec<Option<Mesh>> {
+    (1..=levels)
+        .map(|level| {
+            let lod_name = format!("{}_lod{}", name, level);
+            if mesh_path(&lod_name).is_some() {
+                Some(load_mesh(&lod_name, errors))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// A magenta unit cube, which is easy to spot in game
+fn placeholder_mesh(name: &str) -> Mesh {
+    Mesh {
         name: name.to_string(),
+        ..Mesh::rectangular_prism(1.0, 1.0, 1.0, Point3::new(1.0, 0.0, 1.0))
+    }
+}
+
+/// The file of the mesh in the assets, if it has one. Meshes are obj files or binary glTF
+/// files (ex. exported from Blender), and a .glb is used over an .obj with the same name.
+fn mesh_path(name: &str) -> Option<String> {
+    ["glb", "obj"]
+        .iter()
+        .map(|extension| format!("models/{}.{}", name, extension))
+        .find(|path| assets::exists(path))
+}
+
+fn parse_mesh(name: &str, path: &str, bytes: Vec<u8>) -> Result<(Mesh, Vec<SubMesh>), String> {
+    if path.ends_with(".glb") {
+        return glb::parse_glb(name, &bytes);
+    }
+
+    let text = String::from_utf8(bytes)
+        .map_err(|error| assets::AssetError::invalid(path, error).to_string())?;
+    let obj = parse_obj_file(text)?;
+
+    let mesh = build_mesh(
+        name.to_string(),
+        &obj,
+        &obj.faces,
+        Vector3::new(0.0, 0.0, 0.0),
+    )?;
+    let sub_meshes = obj
+        .nodes
+        .iter()
+        .map(|node| {
+            let mesh_name = format!("{}.{}", name, node.name);
+            Ok(SubMesh {
+                name: node.name.clone(),
+                pivot: node.pivot,
+                mesh: build_mesh(mesh_name, &obj, &node.faces, node.pivot)
+                    .map_err(|error| format!("{} (node {})", error, node.name))?,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok((mesh, sub_meshes))
+}
+
+/// Builds a mesh from the faces, with its vertices moved by -origin
+fn build_mesh(
+    name: String,
+    obj: &ObjData,
+    faces: &[Point3<ObjVertex>],
+    origin: Vector3<f32>,
+) -> Result<Mesh, String> {
+    let mut mesh = Mesh {
+        name,
         vertices: Vec::new(),
         indices: Vec::new(),
     };
-    let path = format!("assets/models/{}.obj", name);
-
-    let text = fs::read_to_string(path).expect("Unable to load mesh!");
-    let obj = parse_obj_file(text);
 
-    for face in &obj.faces {
-        let v1 = add_vertex(&mut mesh, &obj, &face.x);
-        let v2 = add_vertex(&mut mesh, &obj, &face.y);
-        let v3 = add_vertex(&mut mesh, &obj, &face.z);
+    for face in faces {
+        let v1 = add_vertex(&mut mesh, obj, &face.x, origin)?;
+        let v2 = add_vertex(&mut mesh, obj, &face.y, origin)?;
+        let v3 = add_vertex(&mut mesh, obj, &face.z, origin)?;
 
         mesh.indices.push(v1 as u16);
         mesh.indices.push(v2 as u16);
         mesh.indices.push(v3 as u16);
     }
 
-    mesh
+    if mesh.indices.is_empty() {
+        return Err("Mesh has no faces".to_string());
+    }
+
+    Ok(mesh)
 }
 
-fn add_vertex(mesh: &mut Mesh, obj_data: &ObjData, vertex: &ObjVertex) -> usize {
+fn add_vertex(
+    mesh: &mut Mesh,
+    obj_data: &ObjData,
+    vertex: &ObjVertex,
+    origin: Vector3<f32>,
+) -> Result<usize, String> {
+    fn lookup<T: Copy>(values: &[T], index: usize, kind: &str) -> Result<T, String> {
+        index
+            .checked_sub(1)
+            .and_then(|i| values.get(i))
+            .copied()
+            .ok_or_else(|| format!("Invalid {} index: {}", kind, index))
+    }
+
     mesh.vertices.push(Vertex {
-        pos: obj_data.vertices[vertex.v - 1],
-        normal: obj_data.normals[vertex.vn - 1],
-        color: get_color(obj_data.colors[vertex.vt - 1]),
+        pos: lookup(&obj_data.vertices, vertex.v, "vertex")? - origin,
+        normal: lookup(&obj_data.normals, vertex.vn, "normal")?,
+        color: get_color(lookup(&obj_data.colors, vertex.vt, "color")?)?,
+        vein: 0.0,
     });
 
-    mesh.vertices.len() - 1
+    Ok(mesh.vertices.len() - 1)
 }
 
-fn get_color(pt: Point2<f32>) -> Point3<f32> {
+fn get_color(pt: Point2<f32>) -> Result<Point3<f32>, String> {
     for element in PALLETE.iter() {
         let (rect, color) = element;
 
         if rect.x < pt.x && rect.y < pt.y && rect.z > pt.x && rect.w > pt.y {
-            return *color;
+            return Ok(*color);
         }
     }
 
-    panic!("Invalid color: {:?}", pt);
+    Err(format!("Invalid color: {:?}", pt))
 }
 
 #[allow(clippy::many_single_char_names)]
@@ -151,6 +289,14 @@ struct ObjData {
     vertices: Vec<Point3<f32>>,
     colors: Vec<Point2<f32>>,
     normals: Vec<Point3<f32>>,
+    /// The faces that are not part of a node
+    faces: Vec<Point3<ObjVertex>>,
+    nodes: Vec<ObjNode>,
+}
+
+struct ObjNode {
+    name: String,
+    pivot: Vector3<f32>,
     faces: Vec<Point3<ObjVertex>>,
 }
 
@@ -160,66 +306,141 @@ struct ObjVertex {
     vn: usize,
 }
 
-fn parse_obj_file(text: String) -> ObjData {
+fn parse_obj_file(text: String) -> Result<ObjData, String> {
     let mut data = ObjData {
         vertices: Vec::new(),
         colors: Vec::new(),
         normals: Vec::new(),
         faces: Vec::new(),
+        nodes: Vec::new(),
     };
+    // The node whose object the lines are in
+    let mut node = None;
 
     for line in text.lines() {
         let mut words = line.split_whitespace();
 
         match words.next() {
-            Some("#") | Some("o") | Some("s") | None => continue,
+            Some("o") => {
+                node = words
+                    .next()
+                    .and_then(|name| name.strip_prefix("node_"))
+                    .map(|name| {
+                        data.nodes.push(ObjNode {
+                            name: name.to_string(),
+                            pivot: Vector3::new(0.0, 0.0, 0.0),
+                            faces: Vec::new(),
+                        });
+                        data.nodes.len() - 1
+                    });
+            }
+            Some("#") => {
+                if let (Some(node), Some("pivot")) = (node, words.next()) {
+                    let x = parse_float(words.next())?;
+                    let y = parse_float(words.next())?;
+                    let z = parse_float(words.next())?;
+
+                    data.nodes[node].pivot = Vector3::new(x, y, z);
+                }
+            }
+            Some("s") | None => continue,
             Some("v") => {
-                let x = parse_float(words.next());
-                let y = parse_float(words.next());
-                let z = parse_float(words.next());
+                let x = parse_float(words.next())?;
+                let y = parse_float(words.next())?;
+                let z = parse_float(words.next())?;
 
                 data.vertices.push(Point3::new(x, y, z));
             }
             Some("vt") => {
-                let u = parse_float(words.next());
-                let v = parse_float(words.next());
+                let u = parse_float(words.next())?;
+                let v = parse_float(words.next())?;
 
                 data.colors.push(Point2::new(u, v));
             }
             Some("vn") => {
-                let x = parse_float(words.next());
-                let y = parse_float(words.next());
-                let z = parse_float(words.next());
+                let x = parse_float(words.next())?;
+                let y = parse_float(words.next())?;
+                let z = parse_float(words.next())?;
 
                 data.normals.push(Point3::new(x, y, z));
             }
             Some("f") => {
-                let v1 = parse_obj_vertex(words.next());
-                let v2 = parse_obj_vertex(words.next());
-                let v3 = parse_obj_vertex(words.next());
+                let v1 = parse_obj_vertex(words.next())?;
+                let v2 = parse_obj_vertex(words.next())?;
+                let v3 = parse_obj_vertex(words.next())?;
 
                 if words.next().is_some() {
-                    panic!("Mesh not triangularized!");
+                    return Err("Mesh not triangularized!".to_string());
                 }
 
-                data.faces.push(Point3::new(v1, v2, v3));
+                let faces = match node {
+                    Some(node) => &mut data.nodes[node].faces,
+                    None => &mut data.faces,
+                };
+                faces.push(Point3::new(v1, v2, v3));
             }
             Some(x) => println!("Unknown line in obj: {}", x),
         }
     }
 
-    data
+    Ok(data)
 }
 
-fn parse_float(input: Option<&str>) -> f32 {
-    FromStr::from_str(input.unwrap()).unwrap()
+fn parse_float(input: Option<&str>) -> Result<f32, String> {
+    let input = input.ok_or("Missing number")?;
+    FromStr::from_str(input).map_err(|_| format!("Invalid number: {}", input))
 }
 
-fn parse_obj_vertex(input: Option<&str>) -> ObjVertex {
-    let mut parts = input.unwrap().split('/');
-    let v = FromStr::from_str(parts.next().unwrap()).unwrap();
-    let vt = FromStr::from_str(parts.next().unwrap()).unwrap();
-    let vn = FromStr::from_str(parts.next().unwrap()).unwrap();
+fn parse_obj_vertex(input: Option<&str>) -> Result<ObjVertex, String> {
+    let input = input.ok_or("Missing face vertex")?;
+    let mut parts = input
+        .split('/')
+        .map(|part| FromStr::from_str(part).map_err(|_| format!("Invalid face vertex: {}", input)));
+    let mut next = || {
+        parts
+            .next()
+            .unwrap_or_else(|| Err(format!("Invalid face vertex: {}", input)))
+    };
+    let v = next()?;
+    let vt = next()?;
+    let vn = next()?;
 
-    ObjVertex { v, vt, vn }
+    Ok(ObjVertex { v, vt, vn })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The mining missle, cut off in the middle of a face
+    const TRUNCATED: &[u8] = include_bytes!("../../tests/fixtures/truncated.obj");
+
+    #[test]
+    fn truncated_mesh_is_replaced_by_a_placeholder() {
+        let mut errors = ContentErrors::default();
+        let path = String::from("models/truncated.obj");
+        let (mesh, sub_meshes) =
+            parse_or_placeholder("truncated", path, Ok(TRUNCATED.to_vec()), &mut errors);
+
+        let placeholder = placeholder_mesh("truncated");
+        assert_eq!(mesh.name, "truncated");
+        assert_eq!(mesh.vertices.len(), placeholder.vertices.len());
+        assert_eq!(mesh.indices, placeholder.indices);
+        assert!(sub_meshes.is_empty());
+
+        let reported: Vec<(&str, &str)> = errors.iter().collect();
+        assert_eq!(reported.len(), 1);
+        assert_eq!(reported[0].0, "models/truncated.obj");
+    }
+
+    #[test]
+    fn whole_mesh_is_not_replaced() {
+        let mut errors = ContentErrors::default();
+        let path = String::from("models/mining_missle.obj");
+        let bytes = assets::read_bytes(&path).map_err(|e| e.to_string());
+        let (mesh, _) = parse_or_placeholder("mining_missle", path, bytes, &mut errors);
+
+        assert!(errors.is_empty());
+        assert!(mesh.vertices.len() > placeholder_mesh("mining_missle").vertices.len());
+    }
 }
@@ -0,0 +1,166 @@
+use cgmath::{Vector3, Vector4};
+use std::mem;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct GPUParticle {
+    pub pos: Vector3<f32>,
+    pub size: f32,
+    pub color: Vector4<f32>,
+}
+
+unsafe impl bytemuck::Pod for GPUParticle {}
+unsafe impl bytemuck::Zeroable for GPUParticle {}
+
+/// The camera's right and up vectors, used to make the particles face the camera
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct BillboardAxes {
+    pub right: Vector4<f32>,
+    pub up: Vector4<f32>,
+}
+
+unsafe impl bytemuck::Pod for BillboardAxes {}
+unsafe impl bytemuck::Zeroable for BillboardAxes {}
+
+pub struct ParticleRenderer {
+    pub instance_buffer: wgpu::Buffer,
+    pub billboard_buffer: wgpu::Buffer,
+    pub billboard_bgl: wgpu::BindGroupLayout,
+    pub billboard_bg: wgpu::BindGroup,
+    pub pipeline: wgpu::RenderPipeline,
+}
+
+impl ParticleRenderer {
+    pub const MAX_PARTICLES: u64 = 2048;
+
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bgl: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> ParticleRenderer {
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle Buffer"),
+            size: ParticleRenderer::MAX_PARTICLES * mem::size_of::<GPUParticle>() as u64,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let billboard_buffer_size = mem::size_of::<BillboardAxes>() as u64;
+        let billboard_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle Billboard Buffer"),
+            size: billboard_buffer_size,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let billboard_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: Some(
+                        std::num::NonZeroU64::new(billboard_buffer_size).unwrap(),
+                    ),
+                },
+                count: None,
+            }],
+            label: Some("Particle Billboard Bind Group Layout"),
+        });
+
+        let billboard_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &billboard_bgl,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer {
+                    buffer: &billboard_buffer,
+                    offset: 0,
+                    size: Some(std::num::NonZeroU64::new(billboard_buffer_size).unwrap()),
+                },
+            }],
+            label: Some("Particle Billboard Bind Group"),
+        });
+
+        let vertex_bytes = super::read_required_asset("shaders/particle.vert.spv");
+        let vertex_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Particle Vertex Shader"),
+            source: wgpu::util::make_spirv(&vertex_bytes),
+            flags: wgpu::ShaderFlags::VALIDATION,
+        });
+
+        let frag_bytes = super::read_required_asset("shaders/particle.frag.spv");
+        let frag_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Particle Fragment Shader"),
+            source: wgpu::util::make_spirv(&frag_bytes),
+            flags: wgpu::ShaderFlags::VALIDATION,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Particle Pipeline Layout"),
+            bind_group_layouts: &[camera_bgl, &billboard_bgl],
+            push_constant_ranges: &[],
+        });
+
+        let additive_blend = wgpu::BlendState {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+            },
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            // Particles are tested against the scene, but do not occlude each other
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: super::Renderer::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+                clamp_depth: false,
+            }),
+            vertex: wgpu::VertexState {
+                module: &vertex_shader,
+                entry_point: "main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: mem::size_of::<GPUParticle>() as wgpu::BufferAddress,
+                    step_mode: wgpu::InputStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![0 => Float3, 1 => Float, 2 => Float4],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_shader,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    color_blend: additive_blend.clone(),
+                    alpha_blend: additive_blend,
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+        });
+
+        Self {
+            instance_buffer,
+            billboard_buffer,
+            billboard_bgl,
+            billboard_bg,
+            pipeline,
+        }
+    }
+}
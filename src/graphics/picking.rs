@@ -0,0 +1,398 @@
+use super::{culling::Mapping, read_required_asset, CameraMatrix, CullingPath, MeshManager};
+use super::{Mesh, Renderer, MESH_BUFFERS};
+use cgmath::{prelude::*, Matrix4, Point2, Point3, Vector3};
+use futures::FutureExt;
+use specs::Entity;
+use std::mem;
+
+/// Asks the renderer for the entity whose model is under a point of the window (in pixels).
+/// The InputSystem sets it every update, and the answer arrives in the PickResult once the
+/// frame has been drawn.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PickRequest {
+    pub position: Option<Point2<f32>>,
+}
+
+/// The entity that was drawn under the point of the last finished PickRequest. It is at
+/// least a frame behind, and is never set when GPU picking is off (or without a window).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PickResult {
+    pub position: Option<Point2<f32>>,
+    pub entity: Option<Entity>,
+}
+
+impl PickResult {
+    /// The picked entity (None if nothing was drawn there), if the pick was made at the
+    /// position. Returns None if there is no result for it, ex. the cursor moved since.
+    pub fn at(&self, position: Point2<f32>) -> Option<Option<Entity>> {
+        Some(self.entity).filter(|_| self.position == Some(position))
+    }
+}
+
+/// The triangles of a mesh, kept on the CPU so rays can be tested against the shape of its
+/// models when there is no GPU pick (ex. without a window, or before the first frame)
+pub struct CollisionMesh {
+    triangles: Vec<[Point3<f32>; 3]>,
+}
+
+impl CollisionMesh {
+    pub fn new(mesh: &Mesh) -> Self {
+        let triangles = mesh
+            .indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let vertex = |i: usize| mesh.vertices[triangle[i] as usize].pos;
+                [vertex(0), vertex(1), vertex(2)]
+            })
+            .collect();
+        Self { triangles }
+    }
+
+    /// The time of impact of the ray with the nearest triangle, in lengths of `direction`.
+    /// Both sides of the triangles are hit.
+    pub fn raycast(&self, origin: Point3<f32>, direction: Vector3<f32>) -> Option<f32> {
+        self.triangles
+            .iter()
+            .filter_map(|triangle| ray_triangle(origin, direction, triangle))
+            .min_by(|a, b| a.partial_cmp(b).unwrap())
+    }
+}
+
+/// The Möller–Trumbore intersection of a ray with a triangle
+fn ray_triangle(
+    origin: Point3<f32>,
+    direction: Vector3<f32>,
+    [a, b, c]: &[Point3<f32>; 3],
+) -> Option<f32> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < f32::EPSILON {
+        // The ray is parallel to the triangle
+        return None;
+    }
+
+    let inverse = 1.0 / determinant;
+    let offset = origin - a;
+    let u = offset.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = offset.cross(edge1);
+    let v = direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    Some(edge2.dot(q) * inverse).filter(|toi| *toi >= 0.0)
+}
+
+/// Gives the model a pick id, which is its index in the entities plus one
+pub(super) fn pick_id(entities: &mut Vec<Entity>, entity: Option<Entity>) -> u32 {
+    match entity {
+        Some(entity) => {
+            entities.push(entity);
+            entities.len() as u32
+        }
+        None => 0,
+    }
+}
+
+struct PendingPick {
+    position: Point2<f32>,
+    /// The entities of the pick ids, from the frame the pick was drawn in
+    entities: Vec<Entity>,
+}
+
+/// Finds the model under the cursor by drawing the pick ids of the models into a single
+/// pixel, and reading it back once the GPU is done. Unlike the collider raycasts, this
+/// matches the shape of the meshes exactly.
+///
+/// The ids are integers, which can't be multisampled and resolved like the scene, so the
+/// pixel is drawn in its own pass without MSAA. Its camera zooms into the pixel under the
+/// cursor, so only a single pixel has to be drawn and copied.
+pub(super) struct GpuPicker {
+    pipeline: wgpu::RenderPipeline,
+    camera_buffer: wgpu::Buffer,
+    camera_bg: wgpu::BindGroup,
+    id_texture: wgpu::Texture,
+    id_view: wgpu::TextureView,
+    depth_view: wgpu::TextureView,
+    /// The pixel is copied here so it can be read on the CPU
+    readback_buffer: wgpu::Buffer,
+    /// The pick that was copied into the readback buffer, until it is mapped
+    copied: Option<PendingPick>,
+    /// The mapping of the readback buffer. Nothing is picked again until it finishes.
+    mapping: Option<(PendingPick, Mapping)>,
+    result: PickResult,
+}
+
+impl GpuPicker {
+    const ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+
+    pub(super) fn new(device: &wgpu::Device, camera_bgl: &wgpu::BindGroupLayout) -> Self {
+        let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pick Camera Buffer"),
+            size: mem::size_of::<CameraMatrix>() as u64,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let camera_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: camera_bgl,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+            label: Some("Pick Camera Bind Group"),
+        });
+
+        let pixel = |label: &str, format: wgpu::TextureFormat, usage: wgpu::TextureUsage| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+            })
+        };
+        let id_texture = pixel(
+            "Pick Texture",
+            GpuPicker::ID_FORMAT,
+            wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+        );
+        let id_view = id_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_view = pixel(
+            "Pick Depth Texture",
+            Renderer::DEPTH_FORMAT,
+            wgpu::TextureUsage::RENDER_ATTACHMENT,
+        )
+        .create_view(&wgpu::TextureViewDescriptor::default());
+
+        // Rows copied out of a texture are aligned to 256 bytes, so the buffer holds a whole
+        // row even though only the first id is read
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pick Readback Buffer"),
+            size: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        GpuPicker {
+            pipeline: GpuPicker::create_pipeline(device, camera_bgl),
+            camera_buffer,
+            camera_bg,
+            id_texture,
+            id_view,
+            depth_view,
+            readback_buffer,
+            copied: None,
+            mapping: None,
+            result: PickResult::default(),
+        }
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        camera_bgl: &wgpu::BindGroupLayout,
+    ) -> wgpu::RenderPipeline {
+        let vertex_bytes = read_required_asset("shaders/pick.vert.spv");
+        let vertex_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Pick Vertex"),
+            source: wgpu::util::make_spirv(&vertex_bytes),
+            flags: wgpu::ShaderFlags::VALIDATION,
+        });
+
+        let frag_bytes = read_required_asset("shaders/pick.frag.spv");
+        let frag_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Pick Fragment"),
+            source: wgpu::util::make_spirv(&frag_bytes),
+            flags: wgpu::ShaderFlags::VALIDATION,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pick Pipeline Layout"),
+            bind_group_layouts: &[camera_bgl],
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Pick Pipeline"),
+            layout: Some(&pipeline_layout),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::Back,
+                polygon_mode: wgpu::PolygonMode::Fill,
+            },
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Renderer::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+                clamp_depth: false,
+            }),
+            vertex: wgpu::VertexState {
+                module: &vertex_shader,
+                entry_point: "main",
+                buffers: &MESH_BUFFERS,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &frag_shader,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: GpuPicker::ID_FORMAT,
+                    color_blend: wgpu::BlendState::REPLACE,
+                    alpha_blend: wgpu::BlendState::REPLACE,
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+        })
+    }
+
+    /// Scales the clip space so the window pixel at `position` fills the whole view
+    fn pick_matrix(position: Point2<f32>, window_size: (u32, u32)) -> Matrix4<f32> {
+        let (width, height) = (window_size.0 as f32, window_size.1 as f32);
+        let center_x = position.x / width * 2.0 - 1.0;
+        let center_y = 1.0 - position.y / height * 2.0;
+        Matrix4::from_nonuniform_scale(width, height, 1.0)
+            * Matrix4::from_translation(Vector3::new(-center_x, -center_y, 0.0))
+    }
+
+    /// Draws the pick ids of the meshes under the position, and copies the pixel so it can
+    /// be read back. Needs to be recorded after the meshes are culled. Nothing is drawn while
+    /// the previous pick is still being read.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn pick(
+        &mut self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        mesh_manager: &MeshManager,
+        culling: CullingPath,
+        view_projection: CameraMatrix,
+        window_size: (u32, u32),
+        position: Point2<f32>,
+    ) {
+        let inside = position.x >= 0.0
+            && position.y >= 0.0
+            && position.x < window_size.0 as f32
+            && position.y < window_size.1 as f32;
+        if !inside || self.copied.is_some() || self.mapping.is_some() {
+            return;
+        }
+
+        let matrix = GpuPicker::pick_matrix(position, window_size) * view_projection.0;
+        queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[CameraMatrix(matrix)]),
+        );
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Pick Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: &self.id_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                attachment: &self.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.camera_bg, &[]);
+        mesh_manager
+            .meshes
+            .iter()
+            .filter(|mesh| mesh.visible)
+            .for_each(|mesh| mesh.draw(&mut rpass, culling));
+        // Transparent models are left out, so the shield bubbles don't cover the ships in them
+        std::mem::drop(rpass);
+
+        encoder.copy_texture_to_buffer(
+            wgpu::TextureCopyView {
+                texture: &self.id_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::BufferCopyView {
+                buffer: &self.readback_buffer,
+                layout: wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT,
+                    rows_per_image: 1,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth: 1,
+            },
+        );
+        self.copied = Some(PendingPick {
+            position,
+            entities: mesh_manager.pick_entities.clone(),
+        });
+    }
+
+    /// Reads the picked id once the GPU has finished with it, and returns the latest result.
+    /// Needs to be called after the commands from `pick` are submitted.
+    pub(super) fn read_back(&mut self, device: &wgpu::Device) -> PickResult {
+        if let Some(pick) = self.copied.take() {
+            let mapping = self
+                .readback_buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read);
+            self.mapping = Some((pick, Box::pin(mapping)));
+        }
+
+        device.poll(wgpu::Maintain::Poll);
+        let result = match &mut self.mapping {
+            Some((_, mapping)) => match mapping.as_mut().now_or_never() {
+                Some(result) => result,
+                None => return self.result,
+            },
+            None => return self.result,
+        };
+        let (pick, _) = self.mapping.take().unwrap();
+        if result.is_err() {
+            println!("[Graphics] Unable to read the picked model");
+            return self.result;
+        }
+
+        let slice = self.readback_buffer.slice(..);
+        let data = slice.get_mapped_range();
+        let id = bytemuck::cast_slice::<u8, u32>(&data)[0];
+        std::mem::drop(data);
+        self.readback_buffer.unmap();
+
+        self.result = PickResult {
+            position: Some(pick.position),
+            entity: (id as usize)
+                .checked_sub(1)
+                .and_then(|index| pick.entities.get(index).copied()),
+        };
+        self.result
+    }
+}
@@ -0,0 +1,192 @@
+use crate::entity::Time;
+use std::time::Duration;
+
+/// Settings that trade visual quality for performance. They are saved in the config, and can
+/// be changed while the game is running.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GraphicsSettings {
+    /// Waits for the display before presenting a frame, which prevents tearing
+    pub vsync: bool,
+    pub msaa_samples: u32,
+    /// The size of the 3D scene relative to the window. The UI is always drawn at full size.
+    pub render_scale: f32,
+    /// Culls the models with a compute shader instead of on the CPU. The renderer falls back
+    /// to the CPU when the device does not support it.
+    pub gpu_culling: bool,
+    /// Finds what is under the cursor by drawing the models' ids, which matches their shape,
+    /// instead of raycasting their colliders
+    pub gpu_picking: bool,
+    /// Draws the distant rocks and stars behind the scene (see Backdrop)
+    pub backdrop: bool,
+    /// The fixed updates per second. The game plays the same at every rate, higher rates
+    /// only make it smoother (and slower to simulate).
+    pub tick_rate: u32,
+    /// The size of the replay capture's frames relative to the window (see ReplayCapture)
+    pub capture_scale: f32,
+    pub capture_fps: u32,
+    /// How many seconds the replay capture keeps
+    pub capture_seconds: u32,
+}
+
+impl GraphicsSettings {
+    /// The sample counts that every adapter supports for the swap chain's format. Other
+    /// counts can't be queried for support, so they are not offered.
+    pub const SAMPLE_COUNTS: [u32; 2] = [1, 4];
+    pub const MIN_RENDER_SCALE: f32 = 0.25;
+    pub const MAX_RENDER_SCALE: f32 = 1.0;
+    pub const RENDER_SCALE_STEP: f32 = 0.25;
+    pub const TICK_RATES: [u32; 3] = [30, 60, 120];
+    pub const MIN_CAPTURE_SCALE: f32 = 0.1;
+    /// GIF delays are in hundredths of a second, so faster frame rates can't be played back
+    pub const MAX_CAPTURE_FPS: u32 = 50;
+    pub const MAX_CAPTURE_SECONDS: u32 = 30;
+
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        if self.vsync {
+            wgpu::PresentMode::Fifo
+        } else {
+            wgpu::PresentMode::Mailbox
+        }
+    }
+
+    /// Replaces the settings that the adapter does not support, instead of failing to create
+    /// the textures later
+    pub fn validate(&mut self, adapter: &wgpu::Adapter, format: wgpu::TextureFormat) {
+        let usages = adapter.get_texture_format_features(format).allowed_usages;
+        if !GraphicsSettings::supports_samples(self.msaa_samples, format, usages) {
+            println!(
+                "[Graphics] MSAA x{} is not supported, using x1 instead",
+                self.msaa_samples
+            );
+            self.msaa_samples = 1;
+        }
+
+        let render_scale = self.render_scale.clamp(
+            GraphicsSettings::MIN_RENDER_SCALE,
+            GraphicsSettings::MAX_RENDER_SCALE,
+        );
+        if (render_scale - self.render_scale).abs() > f32::EPSILON {
+            println!(
+                "[Graphics] Render scale {} is not supported, using {} instead",
+                self.render_scale, render_scale
+            );
+            self.render_scale = render_scale;
+        }
+
+        if !GraphicsSettings::TICK_RATES.contains(&self.tick_rate) {
+            println!(
+                "[Graphics] Tick rate {} is not supported, using {} instead",
+                self.tick_rate,
+                Time::DEFAULT_RATE
+            );
+            self.tick_rate = Time::DEFAULT_RATE;
+        }
+
+        let capture_scale = self
+            .capture_scale
+            .clamp(GraphicsSettings::MIN_CAPTURE_SCALE, 1.0);
+        let capture_fps = self.capture_fps.clamp(1, GraphicsSettings::MAX_CAPTURE_FPS);
+        let capture_seconds = self
+            .capture_seconds
+            .clamp(1, GraphicsSettings::MAX_CAPTURE_SECONDS);
+        if (capture_scale - self.capture_scale).abs() > f32::EPSILON
+            || capture_fps != self.capture_fps
+            || capture_seconds != self.capture_seconds
+        {
+            println!(
+                "[Graphics] The replay capture settings are not supported, using {}s at {} fps \
+                 and scale {} instead",
+                capture_seconds, capture_fps, capture_scale
+            );
+            self.capture_scale = capture_scale;
+            self.capture_fps = capture_fps;
+            self.capture_seconds = capture_seconds;
+        }
+    }
+
+    /// Whether the format can be rendered with the sample count. wgpu can't be asked about
+    /// sample counts, but x4 is guaranteed for the formats that can be rendered to and
+    /// filtered (like the swap chain's), and x1 always works.
+    fn supports_samples(
+        samples: u32,
+        format: wgpu::TextureFormat,
+        usages: wgpu::TextureUsage,
+    ) -> bool {
+        let renderable = usages.contains(wgpu::TextureUsage::RENDER_ATTACHMENT);
+        let filterable = matches!(
+            format.describe().sample_type,
+            wgpu::TextureSampleType::Float { filterable: true }
+        );
+        samples == 1
+            || (GraphicsSettings::SAMPLE_COUNTS.contains(&samples) && renderable && filterable)
+    }
+
+    /// The next supported sample count, wrapping around to the first one
+    pub fn next_msaa_samples(&self) -> u32 {
+        let counts = &GraphicsSettings::SAMPLE_COUNTS;
+        let index = counts
+            .iter()
+            .position(|count| *count == self.msaa_samples)
+            .map_or(0, |index| (index + 1) % counts.len());
+        counts[index]
+    }
+
+    /// The next supported tick rate, wrapping around to the first one
+    pub fn next_tick_rate(&self) -> u32 {
+        let rates = &GraphicsSettings::TICK_RATES;
+        let index = rates
+            .iter()
+            .position(|rate| *rate == self.tick_rate)
+            .map_or(0, |index| (index + 1) % rates.len());
+        rates[index]
+    }
+
+    /// The time between fixed updates
+    pub fn timestep(&self) -> Duration {
+        Duration::from_nanos(1_000_000_000 / self.tick_rate as u64)
+    }
+
+    /// The size of the 3D scene for a window size
+    pub fn scene_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let scale = |size: u32| ((size as f32 * self.render_scale).round() as u32).max(1);
+        (scale(width), scale(height))
+    }
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        Self {
+            vsync: false,
+            msaa_samples: 4,
+            render_scale: 1.0,
+            gpu_culling: false,
+            gpu_picking: false,
+            backdrop: true,
+            tick_rate: Time::DEFAULT_RATE,
+            capture_scale: 0.5,
+            capture_fps: 15,
+            capture_seconds: 10,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::{TextureFormat, TextureUsage};
+
+    #[test]
+    fn msaa_needs_a_renderable_filterable_format() {
+        let renderable = TextureUsage::RENDER_ATTACHMENT | TextureUsage::SAMPLED;
+        let swap_chain = TextureFormat::Bgra8UnormSrgb;
+        let supports = GraphicsSettings::supports_samples;
+
+        assert!(supports(4, swap_chain, renderable));
+        assert!(!supports(4, swap_chain, TextureUsage::SAMPLED));
+        assert!(!supports(4, TextureFormat::R32Uint, renderable));
+        // Counts that can't be checked are not trusted
+        assert!(!supports(8, swap_chain, renderable));
+        assert!(!supports(3, swap_chain, renderable));
+        assert!(supports(1, TextureFormat::R32Uint, TextureUsage::SAMPLED));
+    }
+}
@@ -0,0 +1,109 @@
+use super::culling::Mapping;
+use futures::FutureExt;
+use std::mem;
+use std::time::Duration;
+
+/// How long the GPU spent on the passes of a frame
+#[derive(Clone, Copy, Debug)]
+pub struct GpuPassTimes {
+    /// The culling, scene, picking and blit passes
+    pub world: Duration,
+    pub ui: Duration,
+}
+
+/// Where each pass starts and ends in the query set
+#[derive(Clone, Copy)]
+pub(super) enum Timestamp {
+    WorldStart,
+    WorldEnd,
+    UiStart,
+    UiEnd,
+}
+
+/// Measures the passes on the GPU with timestamp queries. Like the culling counts, the
+/// times are read back without waiting for the GPU, so they are a few frames behind.
+pub(super) struct GpuTimer {
+    queries: wgpu::QuerySet,
+    buffer: wgpu::Buffer,
+    /// The nanoseconds in each tick of a timestamp
+    period: f32,
+    /// Set when the queries were resolved into the buffer in the current frame
+    resolved: bool,
+    /// The mapping of the buffer. Queries are not resolved again until it finishes.
+    mapping: Option<Mapping>,
+}
+
+impl GpuTimer {
+    const QUERY_COUNT: u32 = 4;
+
+    /// Returns None if the device can not write timestamps
+    pub(super) fn new(device: &wgpu::Device, period: f32) -> Option<GpuTimer> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) || period <= 0.0 {
+            return None;
+        }
+
+        let queries = device.create_query_set(&wgpu::QuerySetDescriptor {
+            ty: wgpu::QueryType::Timestamp,
+            count: GpuTimer::QUERY_COUNT,
+        });
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Timestamp Buffer"),
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+            size: GpuTimer::QUERY_COUNT as u64 * mem::size_of::<u64>() as u64,
+        });
+
+        Some(GpuTimer {
+            queries,
+            buffer,
+            period,
+            resolved: false,
+            mapping: None,
+        })
+    }
+
+    pub(super) fn write(&self, encoder: &mut wgpu::CommandEncoder, timestamp: Timestamp) {
+        encoder.write_timestamp(&self.queries, timestamp as u32);
+    }
+
+    /// Copies the timestamps of the frame to the buffer, unless it is still being read
+    pub(super) fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if self.mapping.is_none() {
+            encoder.resolve_query_set(&self.queries, 0..GpuTimer::QUERY_COUNT, &self.buffer, 0);
+            self.resolved = true;
+        }
+    }
+
+    /// Returns the times once the GPU has finished with them. Needs to be called after the
+    /// commands with the queries are submitted.
+    pub(super) fn read_back(&mut self, device: &wgpu::Device) -> Option<GpuPassTimes> {
+        if self.resolved {
+            self.resolved = false;
+            let mapping = self.buffer.slice(..).map_async(wgpu::MapMode::Read);
+            self.mapping = Some(Box::pin(mapping));
+        }
+
+        device.poll(wgpu::Maintain::Poll);
+        let result = self.mapping.as_mut()?.as_mut().now_or_never()?;
+        self.mapping = None;
+        if result.is_err() {
+            println!("[Graphics] Unable to read the GPU timestamps");
+            return None;
+        }
+
+        let slice = self.buffer.slice(..);
+        let data = slice.get_mapped_range();
+        let ticks: &[u64] = bytemuck::cast_slice(&data);
+        let between = |start: Timestamp, end: Timestamp| {
+            let ticks = ticks[end as usize].saturating_sub(ticks[start as usize]);
+            Duration::from_nanos((ticks as f64 * self.period as f64) as u64)
+        };
+        let times = GpuPassTimes {
+            world: between(Timestamp::WorldStart, Timestamp::WorldEnd),
+            ui: between(Timestamp::UiStart, Timestamp::UiEnd),
+        };
+        mem::drop(data);
+        self.buffer.unmap();
+        Some(times)
+    }
+}
@@ -1,3 +1,4 @@
+use crate::assets::{self, AssetError};
 use cgmath::{Point2, Vector4};
 use image::GenericImageView;
 use rusttype::{Font, Scale};
@@ -6,9 +7,11 @@ use std::mem;
 use wgpu::util::DeviceExt;
 
 // The characters that are pre-rendered by the game.
+// The letters after the digits are for the translations (see i18n)
 const FONT_CHARACTERS: &'static str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ\
     abcdefghijklmnopqrstuvwxyz\
     1234567890\
+    ÄÖÜäöüß\
     !`?'.,;:()[]{}<>|/@\\^$-%+=#_&~*";
 // const FONT_CHARACTERS: &'static str = "hello";
 
@@ -41,14 +44,14 @@ impl UiRenderer {
     pub fn new(device: &wgpu::Device, swapchain: &wgpu::SwapChainDescriptor) -> Self {
         let camera = UiCamera::new(device, swapchain);
 
-        let vertex_bytes = super::read_file_bytes("assets/shaders/ui.vert.spv");
+        let vertex_bytes = super::read_required_asset("shaders/ui.vert.spv");
         let vertex_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
             label: Some("UI Vertex Shader"),
             source: wgpu::util::make_spirv(&vertex_bytes),
             flags: wgpu::ShaderFlags::VALIDATION,
         });
 
-        let fragment_bytes = super::read_file_bytes("assets/shaders/ui.frag.spv");
+        let fragment_bytes = super::read_required_asset("shaders/ui.frag.spv");
         let fragment_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
             label: Some("UI Fragment Shader"),
             source: wgpu::util::make_spirv(&fragment_bytes),
@@ -73,7 +76,7 @@ impl UiRenderer {
                 polygon_mode: wgpu::PolygonMode::Fill,
             },
             multisample: wgpu::MultisampleState {
-                count: crate::MSAA_SAMPLE,
+                count: 1,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
@@ -205,7 +208,7 @@ impl UiBatch {
         if let Some(size) = self.atlas.size {
             self.sprites.push(GPUSprite {
                 pos,
-                color,
+                color: super::color::to_linear_alpha(color),
                 uvs: Vector4::new(
                     uvs.pos.x / size.x,
                     uvs.pos.y / size.y,
@@ -247,6 +250,10 @@ impl FontMap {
             .expect(&format!("Invalid character: {}", c))
     }
 
+    pub fn has_char(&self, c: char) -> bool {
+        self.map.contains_key(&c)
+    }
+
     pub fn pair_kerning(&self, last: char, current: char) -> f32 {
         self.font.pair_kerning(self.scale, last, current)
     }
@@ -328,10 +335,13 @@ impl TextureAtlas {
         }
     }
 
-    pub fn load_ninepatch(&mut self, path: &'static str) -> NinePatch {
-        let bytes = super::read_file_bytes(path);
-        let image = image::load_from_memory(&bytes).unwrap();
-        let image_rgba = image.as_rgba8().unwrap();
+    pub fn load_ninepatch(&mut self, path: &str) -> Result<NinePatch, AssetError> {
+        let bytes = assets::read_bytes(path)?;
+        let image =
+            image::load_from_memory(&bytes).map_err(|error| AssetError::invalid(path, error))?;
+        let image_rgba = image
+            .as_rgba8()
+            .ok_or_else(|| AssetError::invalid(path, "Ninepatch is not RGBA"))?;
         let (width, height) = image.dimensions();
 
         let mut x_space: Option<(u32, u32)> = None;
@@ -360,14 +370,14 @@ impl TextureAtlas {
 
             self.add_texture(&format!("{}-{}", path, name), sub_image)
         };
-        let x_space = x_space.expect("Invalid Ninepatch: No X-Axis Marker!");
-        let y_space = y_space.expect("Invalid Ninepatch: No Y-Axis Marker!");
+        let x_space = x_space.ok_or_else(|| AssetError::invalid(path, "No X-Axis Marker"))?;
+        let y_space = y_space.ok_or_else(|| AssetError::invalid(path, "No Y-Axis Marker"))?;
         println!("NinepatchX: {:?}", x_space);
         println!("NinepatchY: {:?}", y_space);
 
         //We need to go from pixel (1, 1) to (width - 1, height -1) to
         //remove the marker pixel
-        NinePatch {
+        Ok(NinePatch {
             bottom_left: add_subtexture(
                 "BottomLeft",
                 Point2::new(1, 1),
@@ -413,15 +423,16 @@ impl TextureAtlas {
                 Point2::new(x_space.1, y_space.1),
                 Point2::new(width - 1, height - 1),
             ),
-        }
+        })
     }
 
-    pub fn load_font(&mut self, path: &'static str) -> FontMap {
+    pub fn load_font(&mut self, path: &str) -> Result<FontMap, AssetError> {
         use rusttype::{point, Rect};
 
         let padding = 2.0;
-        let bytes = super::read_file_bytes(path);
-        let font = Font::try_from_vec(bytes).unwrap();
+        let bytes = assets::read_bytes(path)?;
+        let font =
+            Font::try_from_vec(bytes).ok_or_else(|| AssetError::invalid(path, "Invalid font"))?;
         //TODO: Currently this cannot change because
         //the size for space is hard-coded into TextLayoutRenderer
         let size = 32.0;
@@ -490,15 +501,15 @@ impl TextureAtlas {
 
         let map = FONT_CHARACTERS.chars().zip(font_glyphs).collect();
 
-        FontMap { font, scale, map }
+        Ok(FontMap { font, scale, map })
     }
 
-    #[allow(dead_code)]
-    pub fn load_texture(&mut self, path: &str) -> TextureRegion2D {
-        let bytes = super::read_file_bytes(path);
-        let image = image::load_from_memory(&bytes).unwrap();
+    pub fn load_texture(&mut self, path: &str) -> Result<TextureRegion2D, AssetError> {
+        let bytes = assets::read_bytes(path)?;
+        let image =
+            image::load_from_memory(&bytes).map_err(|error| AssetError::invalid(path, error))?;
 
-        self.add_texture(path, image)
+        Ok(self.add_texture(path, image))
     }
 
     pub fn add_texture(&mut self, name: &str, image: image::DynamicImage) -> TextureRegion2D {
@@ -0,0 +1,202 @@
+//! The text that is shown to the player is looked up by a key (ex. "menu.settings") in the
+//! language files, which are `lang/<code>.txt` assets. Each line of a file is `key = text`,
+//! and lines starting with '#' are comments. The text can have arguments in braces, which are
+//! filled in by Strings::format (ex. "Collected {count} {item}").
+//!
+//! Text that is missing from a language falls back to English, and then to the key itself, so
+//! text that was never localized (ex. a formatted number) can be looked up as is.
+
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Language {
+    English,
+    German,
+}
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::English, Language::German];
+
+    /// The name of the language file, and of the language in the config
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::German => "de",
+        }
+    }
+
+    /// The name of the language in itself, so it can be found without reading the current one
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::German => "Deutsch",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Language> {
+        Language::ALL
+            .iter()
+            .find(|language| language.code() == code)
+            .copied()
+    }
+
+    /// The language after this one in the settings menu
+    pub fn next(self) -> Language {
+        let index = Language::ALL
+            .iter()
+            .position(|language| *language == self)
+            .unwrap();
+        Language::ALL[(index + 1) % Language::ALL.len()]
+    }
+}
+
+/// Looks up the player facing text in the current language
+#[derive(Clone)]
+pub struct Strings {
+    language: Language,
+    english: HashMap<String, String>,
+    /// The text of the current language, which is empty for English
+    translations: HashMap<String, String>,
+    /// Shows the keys instead of their text, so the text that was not localized stands out
+    show_keys: bool,
+    /// Counts the changes to the text, so the widgets know when to update theirs
+    revision: u32,
+}
+
+impl Strings {
+    pub fn load(language: Language) -> Strings {
+        Strings {
+            language,
+            english: load_language(Language::English),
+            translations: load_translations(language),
+            show_keys: false,
+            revision: 0,
+        }
+    }
+
+    pub fn language(&self) -> Language {
+        self.language
+    }
+
+    pub fn set_language(&mut self, language: Language) {
+        if language != self.language {
+            self.translations = load_translations(language);
+            self.language = language;
+            self.revision += 1;
+        }
+    }
+
+    pub fn show_keys(&self) -> bool {
+        self.show_keys
+    }
+
+    pub fn set_show_keys(&mut self, show_keys: bool) {
+        if show_keys != self.show_keys {
+            self.show_keys = show_keys;
+            self.revision += 1;
+        }
+    }
+
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    /// The text of the key, or the key itself if no language has it
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.get_or(key, key)
+    }
+
+    /// The text of the key, or the fallback if no language has it (ex. for content that was
+    /// added without a translation)
+    pub fn get_or<'a>(&'a self, key: &'a str, fallback: &'a str) -> &'a str {
+        if self.show_keys {
+            return key;
+        }
+
+        self.translations
+            .get(key)
+            .or_else(|| self.english.get(key))
+            .map_or(fallback, String::as_str)
+    }
+
+    /// The text of the key, with each "{name}" replaced by the argument of that name.
+    /// Placeholders without an argument are left as they are.
+    pub fn format(&self, key: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+        let text = self.get(key);
+        if self.show_keys {
+            return text.to_string();
+        }
+
+        let mut formatted = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            formatted.push_str(&rest[..start]);
+            let placeholder = &rest[start..];
+            let arg = placeholder.find('}').and_then(|end| {
+                let name = &placeholder[1..end];
+                args.iter()
+                    .find(|(arg, _)| *arg == name)
+                    .map(|(_, value)| (end, value))
+            });
+            match arg {
+                Some((end, value)) => {
+                    formatted.push_str(&value.to_string());
+                    rest = &placeholder[end + 1..];
+                }
+                None => {
+                    formatted.push('{');
+                    rest = &placeholder[1..];
+                }
+            }
+        }
+        formatted.push_str(rest);
+
+        formatted
+    }
+
+    /// "On" or "Off", for the settings and toggles
+    pub fn on_off(&self, on: bool) -> &str {
+        self.get(if on { "common.on" } else { "common.off" })
+    }
+}
+
+/// English is the fallback, so it has no translations
+fn load_translations(language: Language) -> HashMap<String, String> {
+    match language {
+        Language::English => HashMap::new(),
+        language => load_language(language),
+    }
+}
+
+/// Reads the language's file. A missing file or an invalid line is reported, and the text
+/// falls back to English.
+fn load_language(language: Language) -> HashMap<String, String> {
+    let name = format!("lang/{}.txt", language.code());
+    let text = crate::assets::read_to_string(&name).unwrap_or_else(|error| {
+        println!("[Asset Error] {}", error);
+        String::new()
+    });
+
+    let mut strings = HashMap::new();
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match line.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                strings.insert(key.trim().to_string(), value.trim().to_string());
+            }
+            _ => println!(
+                "[Asset Error] {}:{}: Expected \"key = text\": {}",
+                name,
+                line_number + 1,
+                line
+            ),
+        }
+    }
+
+    strings
+}
@@ -1,5 +1,9 @@
+use crate::block::BlockId;
+use crate::content::{canonical_number, ContentDefinition, ContentIndex, ContentKind};
+use crate::entity::damage::DamageSource;
 use crate::graphics::{TextureAtlas, TextureRegion2D};
-use cgmath::Point3;
+use crate::i18n::Strings;
+use cgmath::Vector3;
 use rand::Rng;
 use std::collections::HashMap;
 
@@ -7,54 +11,265 @@ use std::collections::HashMap;
 pub enum GameItem {
     Iron,
     Copper,
+    /// Cannon ammunition, which is crafted from Iron (see projectile::craft_slugs)
+    Slug,
 }
 
 impl GameItem {
     pub fn iter() -> core::slice::Iter<'static, GameItem> {
-        static VARIANTS: [GameItem; 2] = [GameItem::Iron, GameItem::Copper];
+        static VARIANTS: [GameItem; 3] = [GameItem::Iron, GameItem::Copper, GameItem::Slug];
 
         VARIANTS.iter()
     }
 
-    pub fn asteroid_info() -> Vec<(GameItem, Point3<f32>)> {
-        vec![
-            (GameItem::Iron, Point3::new(0.15, 0.0, 0.0)),
-            (GameItem::Copper, Point3::new(0.15, -0.08, -0.2)),
-        ]
+    /// The items that asteroids are made of, and that traders deal in
+    pub fn ores() -> core::slice::Iter<'static, GameItem> {
+        static ORES: [GameItem; 2] = [GameItem::Iron, GameItem::Copper];
+
+        ORES.iter()
+    }
+
+    /// Finds the item with the name (ignoring case)
+    pub fn from_name(name: &str) -> Option<GameItem> {
+        GameItem::iter()
+            .find(|item| format!("{:?}", item).eq_ignore_ascii_case(name))
+            .copied()
+    }
+
+    pub fn content_index() -> ContentIndex<GameItem> {
+        let mut index = ContentIndex::new(ContentKind::Item);
+        for item in GameItem::iter() {
+            index.add(
+                &format!("{:?}", item),
+                item.content_definition().hash(),
+                *item,
+            );
+        }
+        index
     }
+
+    /// Everything that defines the item, which its content hash is computed from. The name is
+    /// left out, so the item can be found in saves after it is renamed.
+    fn content_definition(&self) -> ContentDefinition {
+        let (primary, secondary) = self.asteroid_tints();
+        let color = self.color();
+        let definition = ContentDefinition::default()
+            .field("icon", self.icon_name())
+            .numbers("tint.primary", &[primary.x, primary.y, primary.z])
+            .numbers("tint.secondary", &[secondary.x, secondary.y, secondary.z])
+            .numbers("color", &[color.x, color.y, color.z]);
+        match MINING_TRAITS.iter().find(|(item, _)| item == self) {
+            Some((_, traits)) => definition
+                .number("mining.hardness", traits.hardness)
+                .field("mining.yield.min", traits.yields.0)
+                .field("mining.yield.max", traits.yields.1)
+                .list(
+                    "mining.damage_taken",
+                    traits.damage_taken.iter().map(|(source, multiplier)| {
+                        format!("{:?}:{}", source, canonical_number(*multiplier))
+                    }),
+                ),
+            None => definition,
+        }
+    }
+
+    /// The key of the item's name in the language files
+    pub fn name_key(&self) -> &'static str {
+        match self {
+            GameItem::Iron => "item.iron",
+            GameItem::Copper => "item.copper",
+            GameItem::Slug => "item.slug",
+        }
+    }
+
+    fn icon_name(&self) -> &'static str {
+        match self {
+            GameItem::Iron => "iron.png",
+            GameItem::Copper => "copper.png",
+            GameItem::Slug => "slug.png",
+        }
+    }
+
+    /// The tints of an asteroid made of this item, used for the body of the asteroid
+    /// when this is its primary resource, and for the veins when it is the secondary resource.
+    /// Every item needs a different pair, so the composition of an asteroid can be seen.
+    pub fn asteroid_tints(&self) -> (Vector3<f32>, Vector3<f32>) {
+        match self {
+            GameItem::Iron => (Vector3::new(1.3, 1.0, 1.0), Vector3::new(1.7, 0.75, 0.65)),
+            GameItem::Copper => (Vector3::new(1.3, 0.85, 0.6), Vector3::new(1.9, 1.1, 0.35)),
+            GameItem::Slug => (Vector3::new(1.1, 1.1, 1.15), Vector3::new(1.4, 1.4, 1.5)),
+        }
+    }
+
+    /// The color used when the item is shown as an effect in the world (ex. asteroid debris)
+    pub fn color(&self) -> Vector3<f32> {
+        match self {
+            GameItem::Iron => Vector3::new(0.8, 0.45, 0.4),
+            GameItem::Copper => Vector3::new(0.9, 0.5, 0.15),
+            GameItem::Slug => Vector3::new(0.7, 0.7, 0.75),
+        }
+    }
+
+    /// How the asteroids made of the item are mined. Only ores have mining traits.
+    pub fn mining(&self) -> &'static MiningTraits {
+        MINING_TRAITS
+            .iter()
+            .find(|(item, _)| item == self)
+            .map(|(_, traits)| traits)
+            .unwrap_or_else(|| panic!("No mining traits for item: {:?}", self))
+    }
+}
+
+/// How the asteroids made of an item (as their primary resource) are mined
+#[derive(Clone, Copy, Debug)]
+pub struct MiningTraits {
+    /// Scales the health of the asteroids
+    pub hardness: f32,
+    /// The fewest and most items that an asteroid drops
+    pub yields: (u32, u32),
+    /// How much of the damage from each source the asteroids take. The sources that are not
+    /// listed do full damage.
+    pub damage_taken: &'static [(DamageSource, f32)],
 }
 
-pub fn load_item_icons(atlas: &mut TextureAtlas) -> HashMap<GameItem, TextureRegion2D> {
+impl MiningTraits {
+    pub fn damage_multiplier(&self, source: DamageSource) -> f32 {
+        self.damage_taken
+            .iter()
+            .find(|(taken_from, _)| *taken_from == source)
+            .map_or(1.0, |(_, multiplier)| *multiplier)
+    }
+
+    pub fn roll_yield(&self, rng: &mut impl Rng) -> u32 {
+        let (min, max) = self.yields;
+        rng.gen_range(min..=max)
+    }
+}
+
+/// The mining traits of every item, kept together so they can be balanced in one place.
+/// Copper is hard ore, which lasers barely scratch, so it is best mined with missiles.
+static MINING_TRAITS: [(GameItem, MiningTraits); 2] = [
+    (
+        GameItem::Iron,
+        MiningTraits {
+            hardness: 1.0,
+            yields: (3, 5),
+            damage_taken: &[],
+        },
+    ),
+    (
+        GameItem::Copper,
+        MiningTraits {
+            hardness: 1.25,
+            yields: (4, 6),
+            damage_taken: &[(DamageSource::Laser, 0.6)],
+        },
+    ),
+];
+
+/// Lists the items of a cost (ex. "2 Iron, 1 Copper")
+pub fn describe_cost(cost: &[(GameItem, u32)], strings: &Strings) -> String {
+    let cost: Vec<String> = cost
+        .iter()
+        .map(|(item, amount)| describe_amount(*item, *amount, strings))
+        .collect();
+    cost.join(", ")
+}
+
+/// Ex. "2 Iron"
+pub fn describe_amount(item: GameItem, amount: u32, strings: &Strings) -> String {
+    strings.format(
+        "item.amount",
+        &[("amount", &amount), ("item", &strings.get(item.name_key()))],
+    )
+}
+
+/// Loads the icon of each item. An icon that fails to load is replaced by `placeholder`.
+pub fn load_item_icons(
+    atlas: &mut TextureAtlas,
+    placeholder: TextureRegion2D,
+) -> HashMap<GameItem, TextureRegion2D> {
     let mut map = HashMap::new();
     for item in GameItem::iter() {
-        let path = format!(
-            "assets/items/{}",
-            match item {
-                GameItem::Iron => "iron.png",
-                GameItem::Copper => "copper.png",
-            }
-        );
-        map.insert(*item, atlas.load_texture(&path));
+        let path = format!("items/{}", item.icon_name());
+        let icon = atlas.load_texture(&path).unwrap_or_else(|error| {
+            println!("[Asset Error] {}", error);
+            placeholder
+        });
+        map.insert(*item, icon);
     }
     map
 }
 
+#[derive(Clone)]
 pub struct Inventory {
     items: HashMap<GameItem, u32>,
+    /// How many discount vouchers (bought from traders) there are for each block
+    vouchers: HashMap<BlockId, u32>,
 }
 
 impl Inventory {
+    /// A voucher divides the cost of its block by this
+    const VOUCHER_DIVISOR: u32 = 2;
+    /// The slugs that a run starts with, so a cannon can be tried before crafting any
+    const STARTING_SLUGS: u32 = 6;
+
     pub fn new() -> Self {
         let mut rng = rand::thread_rng();
-        let items: HashMap<GameItem, u32> = GameItem::iter()
+        let mut items: HashMap<GameItem, u32> = GameItem::ores()
             .map(|item| (*item, rng.gen_range(10..15)))
             .collect();
+        items.insert(GameItem::Slug, Inventory::STARTING_SLUGS);
 
-        Inventory { items }
+        Inventory {
+            items,
+            vouchers: HashMap::new(),
+        }
     }
 
+    /// Adds items to the inventory. The amount stops at u32::MAX instead of overflowing.
     pub fn change_amount(&mut self, item: GameItem, delta: u32) {
-        self.items.entry(item).and_modify(|amount| *amount += delta);
+        self.items
+            .entry(item)
+            .and_modify(|amount| *amount = amount.saturating_add(delta));
+    }
+
+    /// Removes items from the inventory. Panics if there are not enough items.
+    pub fn remove_amount(&mut self, item: GameItem, amount: u32) {
+        let current = self
+            .items
+            .get_mut(&item)
+            .expect("Item is a not a valid variant!");
+        *current = current
+            .checked_sub(amount)
+            .unwrap_or_else(|| panic!("Not enough {:?} in the inventory", item));
+    }
+
+    /// Checks if the inventory has enough items to pay for the cost. An item that is listed
+    /// more than once has to cover all of its amounts.
+    pub fn has_items(&self, cost: &[(GameItem, u32)]) -> bool {
+        cost.iter().all(|(item, _)| {
+            let total: u32 = cost
+                .iter()
+                .filter(|(other, _)| other == item)
+                .map(|(_, amount)| amount)
+                .sum();
+            self.amount(item) >= total
+        })
+    }
+
+    /// Adds every item in the cost (ex. to refund it)
+    pub fn add_items(&mut self, cost: &[(GameItem, u32)]) {
+        for (item, amount) in cost {
+            self.change_amount(*item, *amount);
+        }
+    }
+
+    /// Removes every item in the cost. Panics if there are not enough items.
+    pub fn remove_items(&mut self, cost: &[(GameItem, u32)]) {
+        for (item, amount) in cost {
+            self.remove_amount(*item, *amount);
+        }
     }
 
     pub fn amount(&self, item: &GameItem) -> u32 {
@@ -63,4 +278,200 @@ impl Inventory {
             .get(item)
             .expect("Item is a not a valid variant!")
     }
+
+    /// Pays the cost and adds the items in one step. Nothing changes if there are not
+    /// enough items to pay.
+    pub fn exchange(
+        &mut self,
+        cost: &[(GameItem, u32)],
+        items: &[(GameItem, u32)],
+        strings: &Strings,
+    ) -> Result<(), String> {
+        if !self.has_items(cost) {
+            return Err(strings.format(
+                "error.not_enough_items",
+                &[("cost", &describe_cost(cost, strings))],
+            ));
+        }
+
+        self.remove_items(cost);
+        self.add_items(items);
+        Ok(())
+    }
+
+    pub fn add_voucher(&mut self, block: BlockId) {
+        *self.vouchers.entry(block).or_insert(0) += 1;
+    }
+
+    /// The blocks that there are vouchers for, and how many
+    pub fn vouchers(&self) -> impl Iterator<Item = (BlockId, u32)> + '_ {
+        self.vouchers.iter().map(|(block, count)| (*block, *count))
+    }
+
+    /// What building the block costs. A voucher for the block halves its cost.
+    pub fn block_cost(&self, block: BlockId, cost: &[(GameItem, u32)]) -> Vec<(GameItem, u32)> {
+        if self.vouchers.contains_key(&block) {
+            cost.iter()
+                .map(|(item, amount)| (*item, amount / Inventory::VOUCHER_DIVISOR))
+                .filter(|(_, amount)| *amount > 0)
+                .collect()
+        } else {
+            cost.to_vec()
+        }
+    }
+
+    /// Uses up a voucher for the block, if there is one
+    pub fn use_voucher(&mut self, block: BlockId) -> bool {
+        match self.vouchers.get_mut(&block) {
+            Some(count) => {
+                *count -= 1;
+                if *count == 0 {
+                    self.vouchers.remove(&block);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i18n::Language;
+
+    fn inventory_with(amounts: &[(GameItem, u32)]) -> Inventory {
+        let mut inventory = Inventory {
+            items: GameItem::iter().map(|item| (*item, 0)).collect(),
+            vouchers: HashMap::new(),
+        };
+        inventory.add_items(amounts);
+        inventory
+    }
+
+    /// How many seconds the laser at (0, 0, 0) takes to destroy a stationary asteroid of the
+    /// item that it was ordered to fire at
+    fn laser_time_to_kill(item: GameItem) -> f32 {
+        use crate::entity::{
+            headless::HeadlessEcs, objects::Health, selection::GadgetTarget, Tile, Time,
+        };
+        use cgmath::Point3;
+        use specs::prelude::*;
+
+        let mut headless = HeadlessEcs::with_seed(1);
+        headless.pause_asteroid_field();
+        let laser = headless
+            .with_ship(|ship| ship.tile(Point3::new(0, 0, 0)).and_then(Tile::gadget))
+            .flatten()
+            .unwrap();
+        let asteroid = headless.spawn_asteroid_of(
+            item,
+            Vector3::new(-5.0, -5.0, 2.0),
+            Vector3::new(0.0, 0.0, 0.0),
+            1.0,
+        );
+        headless
+            .ecs
+            .world
+            .write_storage::<GadgetTarget>()
+            .insert(laser, GadgetTarget(asteroid))
+            .unwrap();
+
+        let delta = headless.ecs.get_resource::<Time>().delta;
+        for tick in 1..=20 * Time::DEFAULT_RATE {
+            headless.step(1);
+            let healths = headless.ecs.world.read_storage::<Health>();
+            if healths.get(asteroid).map_or(0, Health::health) == 0 {
+                return tick as f32 * delta;
+            }
+        }
+        panic!("The {:?} asteroid was not destroyed", item);
+    }
+
+    #[test]
+    fn lasers_take_as_long_as_each_ore_was_balanced_for() {
+        // One laser does 60 damage each second, and copper takes 60% of it
+        let expected = |item: GameItem| match item {
+            GameItem::Iron => 3.0,
+            GameItem::Copper => 6.25,
+            GameItem::Slug => unreachable!(),
+        };
+        for item in GameItem::ores() {
+            let time = laser_time_to_kill(*item);
+            // The laser's first shot waits an update for its line of sight
+            assert!(
+                (time - expected(*item)).abs() <= 0.1,
+                "{:?} took {}s instead of {}s",
+                item,
+                time,
+                expected(*item)
+            );
+        }
+    }
+
+    #[test]
+    fn amounts_stop_at_the_largest_amount() {
+        let mut inventory = inventory_with(&[(GameItem::Iron, 8)]);
+        inventory.change_amount(GameItem::Iron, u32::MAX);
+        inventory.change_amount(GameItem::Iron, 1);
+        assert_eq!(inventory.amount(&GameItem::Iron), u32::MAX);
+    }
+
+    #[test]
+    fn exchange_only_pays_in_full() {
+        let strings = Strings::load(Language::English);
+        let start = [(GameItem::Iron, 8), (GameItem::Copper, 4)];
+        let mut inventory = inventory_with(&start);
+        let unchanged = |inventory: &Inventory| {
+            start
+                .iter()
+                .all(|(item, amount)| inventory.amount(item) == *amount)
+        };
+        let goods = [(GameItem::Slug, 3)];
+
+        assert!(inventory
+            .exchange(&[(GameItem::Iron, 9)], &goods, &strings)
+            .is_err());
+        // Nothing is paid when only part of the cost can be
+        assert!(inventory
+            .exchange(
+                &[(GameItem::Iron, 5), (GameItem::Copper, 5)],
+                &goods,
+                &strings
+            )
+            .is_err());
+        assert!(inventory
+            .exchange(
+                &[(GameItem::Iron, 5), (GameItem::Iron, 5)],
+                &goods,
+                &strings
+            )
+            .is_err());
+        assert!(unchanged(&inventory));
+        assert_eq!(inventory.amount(&GameItem::Slug), 0);
+
+        assert!(inventory
+            .exchange(
+                &[(GameItem::Iron, 4), (GameItem::Iron, 4)],
+                &goods,
+                &strings
+            )
+            .is_ok());
+        assert_eq!(inventory.amount(&GameItem::Iron), 0);
+        assert_eq!(inventory.amount(&GameItem::Copper), 4);
+        assert_eq!(inventory.amount(&GameItem::Slug), 3);
+    }
+
+    #[test]
+    fn ores_have_distinct_asteroid_tints() {
+        let tints: Vec<(Vector3<f32>, Vector3<f32>)> =
+            GameItem::ores().map(GameItem::asteroid_tints).collect();
+        for (i, tint) in tints.iter().enumerate() {
+            assert!(
+                !tints[i + 1..].contains(tint),
+                "Multiple items have the same asteroid tints: {:?}",
+                tint
+            );
+        }
+    }
 }
@@ -0,0 +1,217 @@
+//! Loads the meshes in the background while the loading screen is shown. The content
+//! declares the meshes it needs before the window is shown, worker threads read and parse
+//! them, and the main thread creates their GPU buffers as they arrive (see
+//! AssetLoader::upload). Once every mesh is resident, the content is finalized with the
+//! MeshIds of its meshes (ex. block::finalize_blocks).
+
+use crate::content::ContentErrors;
+use crate::graphics::{self, Mesh, MeshId, MeshManager, MeshNode, SubMesh};
+use cgmath::Matrix4;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A mesh that was parsed on a worker thread, and is waiting for its GPU buffers
+pub enum ParsedMesh {
+    /// A mesh and its sub-meshes, which are registered on their own
+    Nodes(Mesh, Vec<SubMesh>),
+    /// The levels of detail of a mesh, starting with the most detailed, and the camera
+    /// distances at which they switch (see MeshManager::add_lod_chain)
+    LodChain(Vec<Mesh>, Vec<f32>),
+}
+
+type ParseMesh = Box<dyn FnOnce(&mut ContentErrors) -> ParsedMesh + Send>;
+
+/// A mesh that is loaded in the background, by its name
+pub struct MeshJob {
+    name: String,
+    parse: ParseMesh,
+}
+
+impl MeshJob {
+    /// Loads the mesh from the assets, with its sub-meshes (see graphics::load_mesh_with_nodes)
+    pub fn mesh(name: &str) -> Self {
+        let path = name.to_string();
+        Self::new(name, move |errors| {
+            let (mesh, sub_meshes) = graphics::load_mesh_with_nodes(&path, errors);
+            ParsedMesh::Nodes(mesh, sub_meshes)
+        })
+    }
+
+    /// `parse` runs on a worker thread, so it should only read the assets
+    pub fn new(
+        name: &str,
+        parse: impl FnOnce(&mut ContentErrors) -> ParsedMesh + Send + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            parse: Box::new(parse),
+        }
+    }
+}
+
+/// A mesh that has its GPU buffers
+#[derive(Clone)]
+pub struct ResidentMesh {
+    pub id: MeshId,
+    pub nodes: Vec<MeshNode>,
+}
+
+/// The loaded meshes, by the names of their jobs
+pub struct LoadedMeshes {
+    meshes: HashMap<String, ResidentMesh>,
+}
+
+impl LoadedMeshes {
+    /// Panics if the mesh was not declared, since the content would have no mesh to draw
+    pub fn get(&self, name: &str) -> &ResidentMesh {
+        self.meshes
+            .get(name)
+            .unwrap_or_else(|| panic!("The mesh {} was not declared before loading", name))
+    }
+}
+
+/// Everything the AssetLoader loaded, which the content is finalized with
+pub struct LoadedAssets {
+    pub mesh_manager: MeshManager,
+    pub meshes: LoadedMeshes,
+    /// The errors of the declarations and of the meshes, in that order
+    pub errors: ContentErrors,
+}
+
+pub struct AssetLoader {
+    names: Vec<String>,
+    /// How many meshes the workers have parsed, which the loading screen shows
+    parsed: Arc<AtomicUsize>,
+    receiver: Receiver<(usize, ParsedMesh, ContentErrors)>,
+    /// The meshes that arrived before the meshes declared ahead of them
+    waiting: HashMap<usize, (ParsedMesh, ContentErrors)>,
+    mesh_manager: MeshManager,
+    meshes: HashMap<String, ResidentMesh>,
+    errors: ContentErrors,
+}
+
+impl AssetLoader {
+    const WORKERS: usize = 4;
+    /// The most meshes that are uploaded in a frame, so the loading screen keeps drawing
+    const UPLOADS_PER_FRAME: usize = 8;
+
+    /// Starts parsing the meshes in the background. `errors` are the errors of the
+    /// declarations, which the errors of the meshes are added to.
+    pub fn start(jobs: Vec<MeshJob>, errors: ContentErrors) -> Self {
+        let names = jobs.iter().map(|job| job.name.clone()).collect();
+        let parsed = Arc::new(AtomicUsize::new(0));
+        let queue = Arc::new(Mutex::new(
+            jobs.into_iter().enumerate().collect::<VecDeque<_>>(),
+        ));
+        let (sender, receiver) = mpsc::channel();
+        for worker in 0..AssetLoader::WORKERS {
+            let queue = Arc::clone(&queue);
+            let parsed = Arc::clone(&parsed);
+            let sender = sender.clone();
+            thread::Builder::new()
+                .name(format!("Asset Loader {}", worker))
+                .spawn(move || loop {
+                    let next = queue.lock().unwrap().pop_front();
+                    let (index, job) = match next {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    let mut errors = ContentErrors::default();
+                    let mesh = (job.parse)(&mut errors);
+                    parsed.fetch_add(1, Ordering::Relaxed);
+                    // The loader is only dropped once everything arrived, or when the
+                    // game closes during the loading screen
+                    if sender.send((index, mesh, errors)).is_err() {
+                        break;
+                    }
+                })
+                .expect("Unable to start an asset loading thread");
+        }
+
+        Self {
+            names,
+            parsed,
+            receiver,
+            waiting: HashMap::new(),
+            mesh_manager: MeshManager::new(),
+            meshes: HashMap::new(),
+            errors,
+        }
+    }
+
+    /// How many meshes have been parsed, out of all of them
+    pub fn progress(&self) -> (usize, usize) {
+        (self.parsed.load(Ordering::Relaxed), self.names.len())
+    }
+
+    /// Creates the GPU buffers of the meshes that were parsed since the last frame. They
+    /// are registered in the order they were declared, so each mesh gets the same MeshId
+    /// every time the game starts.
+    pub fn upload(&mut self, device: &wgpu::Device) {
+        self.waiting.extend(
+            self.receiver
+                .try_iter()
+                .map(|(index, mesh, errors)| (index, (mesh, errors))),
+        );
+        for _ in 0..AssetLoader::UPLOADS_PER_FRAME {
+            let index = self.meshes.len();
+            let (mesh, errors) = match self.waiting.remove(&index) {
+                Some(next) => next,
+                None => break,
+            };
+            for (asset, error) in errors.iter() {
+                self.errors.add(asset.to_string(), error.to_string());
+            }
+            let resident = self.add(device, mesh);
+            self.meshes.insert(self.names[index].clone(), resident);
+        }
+    }
+
+    fn add(&mut self, device: &wgpu::Device, mesh: ParsedMesh) -> ResidentMesh {
+        let mesh_manager = &mut self.mesh_manager;
+        match mesh {
+            ParsedMesh::Nodes(mesh, sub_meshes) => ResidentMesh {
+                id: mesh_manager.add(device, &mesh),
+                nodes: sub_meshes
+                    .iter()
+                    .map(|sub_mesh| MeshNode {
+                        name: sub_mesh.name.clone(),
+                        mesh_id: mesh_manager.add(device, &sub_mesh.mesh),
+                        local: Matrix4::from_translation(sub_mesh.pivot),
+                    })
+                    .collect(),
+            },
+            ParsedMesh::LodChain(levels, distances) => {
+                let id = mesh_manager.add_lod_chain(device, &levels, &distances);
+                // Clicks are refined against the most detailed level
+                mesh_manager.set_collision_mesh(id, &levels[0]);
+                ResidentMesh {
+                    id,
+                    nodes: Vec::new(),
+                }
+            }
+        }
+    }
+
+    /// Whether every mesh has its GPU buffers, so the content can be finalized
+    pub fn is_resident(&self) -> bool {
+        self.meshes.len() == self.names.len()
+    }
+
+    pub fn finish(self) -> LoadedAssets {
+        assert!(
+            self.is_resident(),
+            "The assets were finished before all of them were loaded"
+        );
+        LoadedAssets {
+            mesh_manager: self.mesh_manager,
+            meshes: LoadedMeshes {
+                meshes: self.meshes,
+            },
+            errors: self.errors,
+        }
+    }
+}
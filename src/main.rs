@@ -1,42 +1,123 @@
 #[macro_use]
 extern crate lazy_static;
 
-use cgmath::Point2;
-use entity::{InputManager, WindowSize, ECS};
-use graphics::{Camera, MeshManager, Renderer};
+use block::{Block, BlockDeclarations, Blocks};
+use cgmath::{Point2, Vector2};
+use content::ContentErrors;
+use entity::{HitboxOverlay, InputManager, Particles, Time, WindowSize, ECS};
+use graphics::{
+    Camera, CameraController, CameraShake, GraphicsSettings, IconParts, MeshManager, PickRequest,
+    PickResult, Projection, Renderer, ReplayCapture,
+};
+use loading::{LoadedAssets, MeshJob};
+use profiler::FrameProfiler;
 use specs::prelude::*;
-use ui::{Ui, UiAssets};
+use std::time::Instant;
+use ui::{CommandPalette, DebugConsole, SettingsMenu, ShareDialog, Ui, UiAssets};
 use winit::event;
 
 pub const WIREFRAME_MODE: bool = false;
 pub const RENDER_HITBOXES: bool = false;
 pub const RENDER_BLOCKS: bool = true;
-pub const MSAA_SAMPLE: u32 = 4; //TODO - determine this dynamically
+/// Interpolates models between fixed updates, so movement is smooth at any frame rate
+pub const INTERPOLATE_MODELS: bool = true;
+/// Prevents miners from firing when the missile could not reach the target
+pub const SUPPRESS_UNREACHABLE_SHOTS: bool = true;
+/// Disables effects that scale or shake (ex. hit markers growing)
+pub const REDUCED_MOTION: bool = false;
+/// Shows where the next wave's asteroids will come from shortly before it starts
+pub const WAVE_PREVIEW: bool = true;
+/// Slows the game down for a moment during dramatic events (see TimeEffects)
+pub const TIME_EFFECTS: bool = true;
+/// Pins how much of the HUD is shown, instead of reducing it during intense moments
+pub const HUD_VERBOSITY: Option<entity::hud::HudVerbosity> = None;
+/// Player builds are paid for immediately, but built later by drones from a fabricator
+pub const CONSTRUCTION_TIME: bool = false;
+/// Gadgets wear down as they are used, and work less well until they are serviced
+pub const BLOCK_WEAR: bool = true;
+/// Nudges the asteroid waves by how strong the ship is (see DifficultyDirector). It can also
+/// be turned off with `--fixed-difficulty`, so runs can be replayed exactly.
+pub const DIFFICULTY_DIRECTOR: bool = true;
+/// Checks every raycast against a brute force raycast, and prints any differences
+pub const VERIFY_RAYCASTS: bool = false;
+/// Compares the models kept by GPU culling with the CPU culling every frame, and prints any
+/// differences. This waits for the GPU, so it is very slow.
+pub const VERIFY_GPU_CULLING: bool = false;
+/// Radians the camera turns per pixel that the mouse is dragged with the right or middle button
+pub const CAMERA_DRAG_SENSITIVITY: f32 = 0.005;
+/// Eases the camera's movement and turning in and out, instead of starting and stopping at once
+pub const CAMERA_SMOOTHING: bool = true;
+/// Hides and grabs the cursor while the camera is rotated by dragging
+pub const GRAB_CURSOR_WHILE_ROTATING: bool = true;
 pub const PI: f32 = std::f32::consts::PI;
 
+mod achievements;
+#[cfg(feature = "count-allocations")]
+mod alloc_counter;
 mod app;
+mod assets;
+mod audio;
 mod block;
+mod content;
+mod debug;
 mod entity;
 mod floor;
+#[cfg(feature = "gamepad")]
+mod gamepad;
 mod graphics;
+mod i18n;
 mod item;
+mod loading;
+mod mods;
+mod profiler;
+mod report;
+mod save;
+mod stats;
+mod tech;
 mod ui;
 
+#[cfg(feature = "count-allocations")]
+#[global_allocator]
+static ALLOCATOR: alloc_counter::CountingAllocator = alloc_counter::CountingAllocator;
+
 struct AppState {
     renderer: Renderer,
     ecs: entity::ECS<'static>,
     ui: Ui,
+    palette: CommandPalette,
+    console: DebugConsole,
+    #[cfg(feature = "gamepad")]
+    gamepad: Option<gamepad::GamepadInput>,
 }
 
 impl app::Application for AppState {
+    type Declarations = BlockDeclarations;
+
+    fn declare_assets(errors: &mut ContentErrors) -> (BlockDeclarations, Vec<MeshJob>) {
+        let blocks = block::declare_blocks(errors);
+        let mut jobs = blocks.mesh_jobs();
+        jobs.extend(ECS::mesh_jobs());
+        (blocks, jobs)
+    }
+
     fn init(
         swapchain: &wgpu::SwapChainDescriptor,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        timestamp_period: f32,
+        settings: app::Settings,
+        blocks: BlockDeclarations,
+        assets: LoadedAssets,
     ) -> Self {
-        let mut mesh_manager = MeshManager::new();
-        let mut renderer = Renderer::new(device, &swapchain);
-        let blocks = block::load_blocks(device, &mut mesh_manager);
+        let graphics_settings = settings.graphics;
+        let LoadedAssets {
+            mut mesh_manager,
+            meshes,
+            errors: mut content_errors,
+        } = assets;
+        let mut renderer = Renderer::new(device, swapchain, graphics_settings, timestamp_period);
+        let blocks =
+            block::finalize_blocks(blocks, &mut mesh_manager, &meshes, &mut content_errors);
         let floors = floor::load_floors(device, &mut mesh_manager);
         let camera = Camera {
             position: (-18.0, 0.0, 18.0).into(),
@@ -46,18 +127,71 @@ impl app::Application for AppState {
             fov: 45.0,
             near: 0.1,
             far: 100.0,
+            projection: Projection::Perspective,
+            shake: CameraShake::NONE,
         };
         let window_size = WindowSize {
             width: swapchain.width as f32,
             height: swapchain.height as f32,
         };
 
-        let ecs = ECS::new(device, mesh_manager, blocks, floors, camera, window_size);
-        let ui_assets = UiAssets::new(device, queue, &mut renderer.ui_renderer.batch.atlas);
-        let ui = Ui::new(ui_assets);
+        let mut ecs = ECS::new(
+            device,
+            mesh_manager,
+            &meshes,
+            blocks,
+            floors,
+            camera,
+            window_size,
+            content_errors,
+            entity::module::default_modules(),
+        );
+        ecs.world.insert(graphics_settings);
+        // Only the game moves the camera with a controller, so the headless simulation
+        // stays the same
+        ecs.world.insert(CameraController::default());
+        ecs.world.insert(settings.key_bindings);
+        ecs.world.insert(settings.audio);
+        ecs.world.insert(settings.hazards);
+        ecs.get_resource_mut::<i18n::Strings>()
+            .set_language(settings.language);
+        // The settings that were applied last, which are saved in the config
+        ecs.world.insert(settings);
+        let block_icons = {
+            let blocks = ecs.get_resource::<Blocks>();
+            let parts: Vec<IconParts> = blocks.iter().map(Block::icon_parts).collect();
+            let mesh_manager = ecs.get_resource::<MeshManager>();
+            let images = renderer.render_icons(device, queue, &mesh_manager, &parts);
+            blocks.iter().map(|block| block.id).zip(images).collect()
+        };
+        let ui_assets = UiAssets::new(
+            device,
+            queue,
+            &mut renderer.ui_renderer.batch.atlas,
+            block_icons,
+        );
+        let ui = Ui::new(ui_assets, &ecs);
         queue.submit(None);
+        let palette = CommandPalette::new(&ecs);
+        let console = DebugConsole::new(&ecs);
+
+        AppState {
+            renderer,
+            ecs,
+            ui,
+            palette,
+            console,
+            #[cfg(feature = "gamepad")]
+            gamepad: gamepad::GamepadInput::new(),
+        }
+    }
+
+    fn graphics_settings(&self) -> GraphicsSettings {
+        *self.ecs.get_resource::<GraphicsSettings>()
+    }
 
-        AppState { renderer, ecs, ui }
+    fn applied_settings(&self) -> app::Settings {
+        *self.ecs.get_resource::<app::Settings>()
     }
 
     fn resize(
@@ -75,18 +209,143 @@ impl app::Application for AppState {
     }
 
     fn key_event(&mut self, key: event::VirtualKeyCode, state: event::ElementState) {
+        let typing =
+            self.palette.is_open() || self.console.is_open() || ShareDialog::is_open(&self.ui);
+        if state == event::ElementState::Pressed && !typing {
+            if key == FrameProfiler::TOGGLE_KEY {
+                let mut profiler = self.ecs.get_resource_mut::<FrameProfiler>();
+                profiler.visible = !profiler.visible;
+                return;
+            } else if key == FrameProfiler::EXPORT_KEY {
+                self.ecs.get_resource::<FrameProfiler>().export_csv();
+                return;
+            } else if key == ReplayCapture::TOGGLE_KEY {
+                self.renderer.toggle_capture();
+                return;
+            } else if key == ReplayCapture::SAVE_KEY {
+                self.renderer.save_capture();
+                return;
+            } else if key == HitboxOverlay::TOGGLE_KEY {
+                let mut overlay = self.ecs.get_resource_mut::<HitboxOverlay>();
+                overlay.visible = !overlay.visible;
+                return;
+            }
+        }
+
+        if debug::DEBUG_COMMANDS_ENABLED && state == event::ElementState::Pressed {
+            let open_palette = key == event::VirtualKeyCode::P && {
+                let keys = &self.ecs.get_resource::<InputManager>().keys;
+                keys.is_control_down() && keys.is_shift_down()
+            };
+
+            if open_palette && !self.console.is_open() {
+                self.palette.toggle(&mut self.ui, &mut self.ecs);
+                return;
+            } else if self.palette.is_open() {
+                // Typing in the palette should not control the game
+                self.palette.on_key(&mut self.ui, &mut self.ecs, key);
+                return;
+            } else if key == DebugConsole::TOGGLE_KEY {
+                self.console.toggle(&mut self.ui);
+                return;
+            } else if self.console.is_open() {
+                // Like the palette, the console swallows the keys (ex. WASD) while typing
+                self.console.on_key(&mut self.ui, &mut self.ecs, key);
+                return;
+            }
+        }
+
+        if state == event::ElementState::Pressed && ShareDialog::is_open(&self.ui) {
+            ShareDialog::on_key(&mut self.ui, &mut self.ecs, key);
+            return;
+        }
+        // The settings menu takes the key presses while it is open, but the releases are
+        // still passed on so no key stays held. Escape drops the keyboard tile cursor
+        // before it opens the menu.
+        if state == event::ElementState::Pressed && !typing {
+            if SettingsMenu::is_open(&self.ui) {
+                SettingsMenu::on_key(&mut self.ui, &mut self.ecs, key);
+                return;
+            }
+            // Once a widget has the keyboard focus, it takes the keys it uses
+            let shift = self.ecs.get_resource::<InputManager>().keys.is_shift_down();
+            if self.ui.on_key(key, shift) {
+                return;
+            }
+            let tile_cursor = self.ecs.get_resource::<InputManager>().tile_cursor;
+            if key == SettingsMenu::TOGGLE_KEY && tile_cursor.is_none() {
+                SettingsMenu::toggle(&mut self.ui, &mut self.ecs);
+                return;
+            }
+        }
+
         self.ecs
             .get_resource_mut::<InputManager>()
             .keys
             .update(key, state);
+        self.ecs.on_key(key, state);
     }
 
-    fn scroll_event(&mut self, _: f32) {}
+    fn char_event(&mut self, c: char) {
+        if ShareDialog::is_open(&self.ui) {
+            ShareDialog::on_char(&mut self.ui, &mut self.ecs, c);
+        } else if self.palette.is_open() {
+            self.palette.on_char(&mut self.ui, &mut self.ecs, c);
+        } else if self.console.is_open() {
+            self.console.on_char(&mut self.ui, c);
+        }
+    }
+
+    fn exit(&mut self) {
+        self.ecs.end_run();
+    }
+
+    fn scroll_event(&mut self, delta: f32) {
+        // The camera does not zoom behind the settings menu
+        if self.ui.modal().is_none() {
+            self.ecs.get_resource_mut::<InputManager>().scroll += delta;
+        }
+    }
 
     fn mouse_moved(&mut self, new_pos: Point2<f32>) {
-        let window_size = self.ecs.get_resource::<WindowSize>();
-        let new_pos = Point2::new(new_pos.x, window_size.height - new_pos.y);
-        self.ecs.get_resource_mut::<InputManager>().mouse_pos = new_pos;
+        let height = self.ecs.get_resource::<WindowSize>().height;
+        let new_pos = Point2::new(new_pos.x, height - new_pos.y);
+        self.ui.on_mouse_moved(new_pos);
+        let mut input = self.ecs.get_resource_mut::<InputManager>();
+        if input.mouse_pos != new_pos {
+            input.tile_cursor = None;
+        }
+        input.mouse_pos = new_pos;
+    }
+
+    fn mouse_motion(&mut self, delta: Vector2<f32>) {
+        self.ecs
+            .get_resource_mut::<InputManager>()
+            .mouse_motion(delta);
+    }
+
+    fn cursor_grabbed(&self) -> bool {
+        GRAB_CURSOR_WHILE_ROTATING && self.ecs.get_resource::<InputManager>().is_rotating()
+    }
+
+    fn focus_lost(&mut self) {
+        let mut input = self.ecs.get_resource_mut::<InputManager>();
+        input.cancel_rotate_drag();
+        // A key held while switching windows would otherwise stay down (ex. W moving the
+        // camera forever)
+        input.keys.clear();
+    }
+
+    fn title_status(&self) -> String {
+        let entities = self.ecs.world.entities().join().count();
+        let fields = self
+            .ecs
+            .world
+            .read_component::<entity::gameplay::AsteroidField>();
+        match fields.join().next() {
+            Some(field) => format!("{} entities - Wave {}", entities, field.level),
+            None => format!("{} entities", entities),
+        }
     }
 
     fn click_event(
@@ -95,18 +354,69 @@ impl app::Application for AppState {
         state: event::ElementState,
         mut pt: Point2<f32>,
     ) {
-        let window_size = self.ecs.get_resource::<WindowSize>();
-        pt.y = window_size.height - pt.y;
+        pt.y = self.ecs.get_resource::<WindowSize>().height - pt.y;
+        let rotate_button = matches!(
+            button,
+            event::MouseButton::Right | event::MouseButton::Middle
+        );
 
-        if !self.ui.on_click(button, state, pt) && button == event::MouseButton::Left {
-            let mut input_manager = self.ecs.get_resource_mut::<InputManager>();
-            input_manager.left_mb = state == event::ElementState::Pressed;
+        // The drag ends even if the button is released over the UI
+        if rotate_button && state == event::ElementState::Released {
+            let clicked = self
+                .ecs
+                .get_resource_mut::<InputManager>()
+                .end_rotate_drag(button);
+            // Only a click is passed on, so rotating the camera does nothing else
+            if !self.ui.on_click(button, state, pt) && clicked {
+                self.ecs.on_click(button, event::ElementState::Pressed);
+                self.ecs.on_click(button, state);
+            }
+            return;
+        }
+
+        if !self.ui.on_click(button, state, pt) {
+            if rotate_button {
+                // The press is passed on once the button is released, if it was a click
+                self.ecs
+                    .get_resource_mut::<InputManager>()
+                    .start_rotate_drag(button);
+                return;
+            }
+            if button == event::MouseButton::Left {
+                let mut input_manager = self.ecs.get_resource_mut::<InputManager>();
+                input_manager.left_mb = state == event::ElementState::Pressed;
+                // Clicking while the camera is rotated is part of the drag
+                input_manager.clicked |= input_manager.left_mb && !input_manager.is_rotating();
+            }
+            self.ecs.on_click(button, state);
         }
     }
 
-    fn fixed_update(&mut self, _: &wgpu::Device, _: &wgpu::Queue) {
+    fn time_scale(&self) -> f32 {
+        self.ecs
+            .get_resource::<entity::time_effects::TimeEffects>()
+            .scale()
+    }
+
+    fn frame_update(&mut self, delta: f32) {
+        #[cfg(feature = "gamepad")]
+        if let Some(gamepad) = &mut self.gamepad {
+            gamepad.poll(&mut self.ecs.get_resource_mut::<InputManager>());
+        }
+        self.ecs.update_camera(delta);
+    }
+
+    fn fixed_update(&mut self, device: &wgpu::Device, _: &wgpu::Queue, simulate: bool) {
+        let graphics_settings = self.graphics_settings();
+        // The app uses the new tick rate from the next update on
+        *self.ecs.get_resource_mut::<Time>() = Time::new(graphics_settings.tick_rate);
         self.ui.update(&mut self.ecs);
-        self.ecs.update();
+        self.ecs.update(simulate);
+
+        let graphics_settings = self.graphics_settings();
+        if graphics_settings != self.renderer.settings() {
+            self.renderer.apply_settings(device, graphics_settings);
+        }
     }
 
     fn render(
@@ -114,40 +424,317 @@ impl app::Application for AppState {
         texture: &wgpu::SwapChainTexture,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        alpha: f32,
     ) {
+        let start = Instant::now();
         let mut lines = Vec::new();
         let lines_comps = self.ecs.world.read_component::<entity::Line>();
         let entities = self.ecs.get_resource::<specs::world::EntitiesRes>();
         let camera = self.ecs.get_resource::<Camera>();
+        let particles = self.ecs.get_resource::<Particles>().as_gpu_particles();
+        let pick = *self.ecs.get_resource::<PickRequest>();
+        let mut billboards = entity::objects::build_health_bars(&self.ecs.world);
+        billboards.extend(block::build_reload_indicators(&self.ecs.world));
+        billboards.extend(
+            self.ecs
+                .get_resource::<entity::hit_markers::HitMarkers>()
+                .as_billboards(),
+        );
+        billboards.extend(entity::gameplay::build_wave_preview(&self.ecs.world));
 
         for (line, _) in (&lines_comps, &entities).join() {
             lines.push(*line);
         }
+        lines.extend_from_slice(self.ecs.get_resource::<entity::LineBatch>().lines());
 
         let mut mesh_manager = self.ecs.get_resource_mut::<MeshManager>();
         self.ui.render(&mut self.renderer.ui_renderer.batch);
 
         let mut encoder =
             device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let prepare_time = start.elapsed();
 
+        let start = Instant::now();
         self.renderer.render_world(
+            device,
             queue,
             texture,
             &mut encoder,
             &camera,
             &mut mesh_manager,
             &lines,
+            &particles,
+            &billboards,
+            &pick,
+            alpha,
         );
+        let world_time = start.elapsed();
 
+        let start = Instant::now();
         self.renderer.render_ui(queue, texture, &mut encoder);
+        let ui_time = start.elapsed();
+
+        let start = Instant::now();
         queue.submit(Some(encoder.finish()));
+        self.renderer
+            .read_culling_results(device, &mut mesh_manager);
+        *self.ecs.get_resource_mut::<PickResult>() = self.renderer.read_pick_result(device);
+        self.renderer.read_capture(device);
+        let submit_time = start.elapsed();
+
+        let gpu_times = self.renderer.read_gpu_times(device);
+
+        // The render times are spent on the CPU (ex. encoding the passes). The GPU times are
+        // only recorded when the device supports timestamps.
+        let mut profiler = self.ecs.get_resource_mut::<FrameProfiler>();
+        profiler.record("render/prepare", prepare_time);
+        profiler.record("render/world", world_time);
+        profiler.record("render/ui", ui_time);
+        profiler.record("render/submit", submit_time);
+        if let Some(times) = gpu_times {
+            profiler.record("gpu/world", times.world);
+            profiler.record("gpu/ui", times.ui);
+        }
     }
 }
 
 fn main() {
+    // `--headless <ticks>` runs the simulation without a window, and prints how the run went
+    // `--pack-assets <path>` packs the assets directory into a bundle, to ship next to the game
+    // `--bench-picking <asteroids>` times the cursor raycasts, with and without mesh refinement
+    // `--validate-save <path>` loads a saved blueprint without a window, and prints its problems
+    // `--fixed-difficulty` can be added to any of them, and is read by the DifficultyDirector
+    let args: Vec<String> = std::env::args()
+        .filter(|arg| arg != entity::difficulty::DifficultyDirector::DISABLE_FLAG)
+        .collect();
+    if let [_, flag, value] = args.as_slice() {
+        if flag == "--headless" {
+            let ticks = value.parse().expect("The tick count is not a number");
+            run_headless(ticks);
+            return;
+        }
+        if flag == "--pack-assets" {
+            pack_assets(std::path::Path::new(value));
+            return;
+        }
+        if flag == "--bench-picking" {
+            let count = value.parse().expect("The asteroid count is not a number");
+            bench_picking(count);
+            return;
+        }
+        if flag == "--validate-save" {
+            validate_save(value);
+            return;
+        }
+    }
+
     app::run::<AppState>("Spaceship Alpha");
 }
 
+fn pack_assets(out: &std::path::Path) {
+    let directory = match assets::directory() {
+        Some(directory) => directory,
+        None => {
+            println!("There is no assets directory to pack");
+            std::process::exit(1);
+        }
+    };
+
+    match assets::pack(directory, out) {
+        Ok(count) => println!("Packed {} assets into {}", count, out.display()),
+        Err(error) => {
+            println!(
+                "Unable to pack the assets into {}: {}",
+                out.display(),
+                error
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Reads a saved blueprint like the game does, and exits with an error if it can't be loaded
+/// completely (ex. it is corrupt, from a newer version, or uses blocks that are missing)
+fn validate_save(path: &str) {
+    use entity::blueprint::Blueprint;
+
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) => {
+            println!("[Save] Unable to read {}: {}", path, error);
+            std::process::exit(1);
+        }
+    };
+    match save::read(&text) {
+        Ok(save) => println!(
+            "[Save] Format {} (written by {}), the current format is {}",
+            save.version,
+            save.game_version
+                .as_deref()
+                .unwrap_or("a version without a header"),
+            save::FORMAT_VERSION
+        ),
+        Err(error) => {
+            println!("[Save] {}", error);
+            std::process::exit(1);
+        }
+    }
+
+    let headless = entity::headless::HeadlessEcs::new();
+    let blocks = headless.ecs.get_resource::<Blocks>();
+    let floors = headless.ecs.get_resource::<floor::Floors>();
+    let strings = headless.ecs.get_resource::<i18n::Strings>();
+    let (blueprint, report) = match Blueprint::parse(&text, &blocks, &floors, &strings) {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            println!("[Save] {}", error);
+            std::process::exit(1);
+        }
+    };
+    report.print("Save");
+    println!(
+        "[Save] {}x{} tiles with {} blocks",
+        blueprint.size.x,
+        blueprint.size.y,
+        blueprint.block_count()
+    );
+    if !report.unresolved.is_empty() {
+        println!(
+            "[Save] Unable to load, these blocks are missing: {}",
+            report.unresolved_names().join(", ")
+        );
+        std::process::exit(1);
+    }
+    println!("[Save] {} can be loaded", path);
+}
+
+fn run_headless(ticks: u32) {
+    let mut headless = entity::headless::HeadlessEcs::new();
+    headless.step(ticks);
+
+    let state = *headless.ecs.get_resource::<entity::gameplay::GameState>();
+    let level = headless.with_asteroid_field(|field| field.level);
+    let blocks = headless.with_ship(|ship| {
+        ship.tiles()
+            .filter(|(_, tile)| tile.block().is_some())
+            .count()
+    });
+    let heat = headless.with_ship(|ship| ship.heat().heat());
+    let shield = headless.with_ship(|ship| (ship.shield().points(), ship.shield().max()));
+    let power = headless.with_ship(|ship| (ship.power().demand(), ship.power().supply()));
+    println!("[Headless] {:?} after {} ticks", state, ticks);
+    println!(
+        "[Headless] Wave: {:?}, Ship tiles with blocks: {:?}, Heat: {:?}, Shield: {:?}, \
+         Power: {:?}",
+        level, blocks, heat, shield, power
+    );
+    {
+        let director = headless
+            .ecs
+            .get_resource::<entity::difficulty::DifficultyDirector>();
+        match director.score() {
+            Some((score, expected)) => println!(
+                "[Headless] Difficulty: score {:.0} / {:.0} ({})",
+                score,
+                expected,
+                director.modifiers().describe()
+            ),
+            None => println!("[Headless] Difficulty: fixed"),
+        }
+    }
+    let inventory = headless.inventory();
+    for item in item::GameItem::iter() {
+        println!("[Headless] {:?}: {}", item, inventory.amount(item));
+    }
+}
+
+/// Raycasts down through a grid of asteroids like the cursor picks would, to see how much
+/// refining the hits against the asteroid mesh costs. The broad phase should keep the amount
+/// of candidates per raycast small, however many asteroids there are.
+fn bench_picking(count: usize) {
+    use entity::{objects, physics::MeshRefinement, Collider, Model, RaycastWorld, Transform};
+    use rand::Rng;
+    const RAYCASTS: usize = 10_000;
+    const SPACING: f32 = 2.0;
+
+    let mut headless = entity::headless::HeadlessEcs::new();
+    let asteroid_mesh = graphics::load_mesh("asteroid", &mut ContentErrors::default());
+    let side = (count as f32).sqrt().ceil() as usize;
+    {
+        let world = &headless.ecs.world;
+        let meshes = world.read_resource::<entity::ObjectMeshes>();
+        world
+            .write_resource::<MeshManager>()
+            .set_collision_mesh(meshes.asteroid, &asteroid_mesh);
+        let lazy_update = world.read_resource::<LazyUpdate>();
+        let mut rng = rand::thread_rng();
+        for i in 0..count {
+            let asteroid = objects::Asteroid {
+                primary: item::GameItem::Iron,
+                secondary: item::GameItem::Iron,
+                volatile: false,
+                toughness: 1.0,
+                debris: false,
+                seed: rng.gen(),
+            };
+            // Far from the ship, so the asteroids don't crash into it
+            let position = cgmath::Vector3::new(
+                (i % side) as f32 * SPACING,
+                100.0 + (i / side) as f32 * SPACING,
+                0.0,
+            );
+            objects::build_asteroid(
+                &meshes,
+                lazy_update.create_entity(&world.entities()),
+                asteroid,
+                position,
+                rng.gen_range(0.0..PI * 2.0),
+            );
+        }
+    }
+    // The asteroids are added to the world at the end of the first update, and their
+    // colliders to the raycast world in the second
+    headless.step(2);
+
+    let world = &headless.ecs.world;
+    let raycaster = world.read_resource::<RaycastWorld>();
+    let meshes = world.read_resource::<MeshManager>();
+    let models = world.read_storage::<Model>();
+    let transforms = world.read_storage::<Transform>();
+    let refinement = MeshRefinement {
+        meshes: &meshes,
+        models: &models,
+        transforms: &transforms,
+    };
+    let mut rng = rand::thread_rng();
+    let rays: Vec<_> = (0..RAYCASTS)
+        .map(|_| {
+            let x = rng.gen_range(0.0..side as f32 * SPACING);
+            let y = 100.0 + rng.gen_range(0.0..side as f32 * SPACING);
+            (
+                cgmath::Vector3::new(x, y, 10.0),
+                cgmath::Vector3::new(x, y, -10.0),
+            )
+        })
+        .collect();
+
+    for refined in [false, true].iter() {
+        let start = Instant::now();
+        let hits = rays
+            .iter()
+            .filter_map(|(near, far)| {
+                let refinement = Some(&refinement).filter(|_| *refined);
+                raycaster.raycast(&[Collider::ASTEROID], *near, *far, None, refinement)
+            })
+            .count();
+        let average = start.elapsed() / RAYCASTS as u32;
+        println!(
+            "[Bench] {} asteroids, refined: {}, {} of {} raycasts hit, {:?} per raycast",
+            count, refined, hits, RAYCASTS, average
+        );
+    }
+}
+
 #[allow(dead_code)]
 pub fn print_time(title: &str) {
     use std::time::{SystemTime, UNIX_EPOCH};
@@ -0,0 +1,135 @@
+//! Mods add content to the game from the `mods` directory of the working directory, which
+//! is loaded after the built-in content. So far mods can only add blocks, each from a RON
+//! file in `mods/blocks`. For example, `mods/blocks/big_cooler.ron`:
+//!
+//! ```ron
+//! (
+//!     type_name: "BigCooler",
+//!     name: Some("Big Cooler"),
+//!     mesh: "cooler",
+//!     size: (2, 2),
+//!     height: 0.6,
+//!     hitbox: Some((shape: Cuboid(1.8, 1.8, 0.6), offset: (0.0, 0.0, 0.3))),
+//!     cost: [("Iron", 6), ("Copper", 8)],
+//!     behavior: Some("cooler"),
+//!     heat: Some((dissipation: 10.0)),
+//! )
+//! ```
+//!
+//! The mesh is loaded through the assets (see crate::assets), so a mod either reuses a
+//! built-in model or adds its own to the assets directory.
+
+use crate::content::ContentErrors;
+use serde::{de::DeserializeOwned, Deserialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const BLOCKS_DIR: &str = "mods/blocks";
+
+/// A block that a mod adds
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlockDefinition {
+    /// Identifies the block in saves, like the type names of the built-in blocks
+    pub type_name: String,
+    /// The name that is shown for the block, which is the type name if there is none.
+    /// Mods have no language files, so it is shown in every language.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The name of the mesh, without the "models/" directory and the extension
+    pub mesh: String,
+    /// The grid spaces that the block takes up (x, y)
+    pub size: (u16, u16),
+    pub height: f32,
+    /// The hitbox of the block, which fills its grid spaces if there is none
+    #[serde(default)]
+    pub hitbox: Option<HitboxDefinition>,
+    #[serde(default)]
+    pub is_gadget: bool,
+    /// The items that building the block costs, by their name (ex. "Iron")
+    pub cost: Vec<(String, u32)>,
+    /// What the block does, which is one of the behaviors of the built-in blocks ("miner",
+    /// "laser", "cannon", "cooler", "generator" or "none"). Blocks without a behavior do
+    /// nothing.
+    #[serde(default)]
+    pub behavior: Option<String>,
+    /// Changes the heat of the block, which is the heat of its behavior's built-in block
+    /// otherwise. The block's power is always the power of that block.
+    #[serde(default)]
+    pub heat: Option<HeatDefinition>,
+    /// How heavy the block is, which is the mass of a plain block if there is none
+    #[serde(default)]
+    pub mass: Option<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HitboxDefinition {
+    pub shape: ShapeDefinition,
+    pub offset: (f32, f32, f32),
+}
+
+#[derive(Deserialize)]
+pub enum ShapeDefinition {
+    /// The full size of the box
+    Cuboid(f32, f32, f32),
+    Sphere(f32),
+}
+
+/// The fields that are left out keep the heat of the behavior's built-in block
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeatDefinition {
+    #[serde(default)]
+    pub per_use: Option<f32>,
+    #[serde(default)]
+    pub idle: Option<f32>,
+    #[serde(default)]
+    pub dissipation: Option<f32>,
+}
+
+/// Reads every RON file in the mod directory, in the order of their names, so the content
+/// is added in the same order each time. Files that can't be read are added to the errors
+/// and left out. There are no errors if the directory doesn't exist, since mods are optional.
+pub fn load_definitions<T: DeserializeOwned>(
+    directory: &str,
+    errors: &mut ContentErrors,
+) -> Vec<(String, T)> {
+    let mut paths: Vec<PathBuf> = match fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| matches!(path.extension(), Some(extension) if extension == "ron"))
+            .collect(),
+        Err(_) => return Vec::new(),
+    };
+    paths.sort();
+
+    let mut definitions = Vec::new();
+    for path in paths {
+        let name = path.display().to_string();
+        let definition = fs::read_to_string(&path)
+            .map_err(|error| error.to_string())
+            .and_then(|text| ron::from_str(&text).map_err(|error| error.to_string()));
+        match definition {
+            Ok(definition) => definitions.push((name, definition)),
+            Err(error) => add_error(errors, &name, &error),
+        }
+    }
+    definitions
+}
+
+/// The name of a mod file in content hashes. The content of a mod is read from a single
+/// directory, so only the file's name is used. The hash is then the same on every platform,
+/// and wherever the game is run from.
+pub fn hash_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Reports a problem with a mod file, which is shown with the content that failed to load
+pub fn add_error(errors: &mut ContentErrors, path: &str, error: &str) {
+    println!("[Mod Error] {}: {}", path, error);
+    errors.add(path.to_string(), error.to_string());
+}
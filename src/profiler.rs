@@ -0,0 +1,163 @@
+//! Measures how long the systems and the parts of a frame take, so hitches can be traced
+//! back to what caused them. The timings are shown in an overlay (F4), and can be saved to
+//! a CSV file (F5) for a closer look.
+use specs::prelude::*;
+use std::collections::VecDeque;
+use std::fs;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use winit::event::VirtualKeyCode;
+
+pub const PROFILE_DIR: &str = "saves/profiles";
+
+/// The timings recorded over the last frames. Entries are named "<group>/<name>" (ex.
+/// "system/physics_system" or "render/world"), and are kept sorted so each group stays
+/// together.
+#[derive(Default)]
+pub struct FrameProfiler {
+    /// The latest timings of each entry in milliseconds, oldest first
+    entries: Vec<(String, VecDeque<f32>)>,
+    pub visible: bool,
+}
+
+/// The timings of an entry in milliseconds
+pub struct EntrySummary<'a> {
+    pub name: &'a str,
+    pub current: f32,
+    pub average: f32,
+    pub worst: f32,
+}
+
+impl FrameProfiler {
+    /// How many timings of each entry are kept
+    pub const HISTORY: usize = 120;
+    pub const TOGGLE_KEY: VirtualKeyCode = VirtualKeyCode::F4;
+    pub const EXPORT_KEY: VirtualKeyCode = VirtualKeyCode::F5;
+
+    pub fn record(&mut self, name: &str, duration: Duration) {
+        self.record_in("", name, duration);
+    }
+
+    /// Records a timing of the entry named `group` followed by `name`. The name is only put
+    /// together when the entry is first recorded, so recording doesn't allocate afterwards.
+    fn record_in(&mut self, group: &str, name: &str, duration: Duration) {
+        let full_name = || group.bytes().chain(name.bytes());
+        let index = match self
+            .entries
+            .binary_search_by(|(entry, _)| entry.bytes().cmp(full_name()))
+        {
+            Ok(index) => index,
+            Err(index) => {
+                let history = VecDeque::with_capacity(FrameProfiler::HISTORY);
+                self.entries
+                    .insert(index, (format!("{}{}", group, name), history));
+                index
+            }
+        };
+
+        let history = &mut self.entries[index].1;
+        if history.len() == FrameProfiler::HISTORY {
+            history.pop_front();
+        }
+        history.push_back(duration.as_secs_f32() * 1000.0);
+    }
+
+    /// Records the timings of the profiled systems from the last dispatch
+    pub fn collect(&mut self, timings: &SystemTimings) {
+        let mut timings = timings.0.lock().unwrap();
+        for (name, duration) in timings.drain(..) {
+            self.record_in("system/", name, duration);
+        }
+    }
+
+    pub fn summaries(&self) -> impl Iterator<Item = EntrySummary<'_>> {
+        self.entries.iter().map(|(name, history)| EntrySummary {
+            name,
+            current: history.back().copied().unwrap_or(0.0),
+            average: history.iter().sum::<f32>() / history.len().max(1) as f32,
+            worst: history.iter().copied().fold(0.0, f32::max),
+        })
+    }
+
+    /// Writes the history as CSV, with one row per timing
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("entry,sample,milliseconds\n");
+        for (name, history) in &self.entries {
+            for (sample, time) in history.iter().enumerate() {
+                csv.push_str(&format!("{},{},{:.4}\n", name, sample, time));
+            }
+        }
+        csv
+    }
+
+    /// Saves the history to a new CSV file on another thread, like the run reports
+    pub fn export_csv(&self) {
+        let csv = self.to_csv();
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let path = format!("{}/profile_{}.csv", PROFILE_DIR, timestamp);
+
+        std::thread::spawn(move || {
+            let result = fs::create_dir_all(PROFILE_DIR).and_then(|_| fs::write(&path, csv));
+            match result {
+                Ok(()) => println!("[Profiler] Saved {}", path),
+                Err(error) => println!("[Profiler] Unable to save {}: {}", path, error),
+            }
+        });
+    }
+}
+
+/// The timings of the profiled systems during the current dispatch. The systems run in
+/// parallel, so each one only locks it to add its own timing.
+#[derive(Default)]
+pub struct SystemTimings(Mutex<Vec<(&'static str, Duration)>>);
+
+/// Wraps a system to record how long it runs for
+pub struct Profiled<S> {
+    system: S,
+    name: &'static str,
+}
+
+impl<'a, S> System<'a> for Profiled<S>
+where
+    S: System<'a>,
+    S::SystemData: SystemData<'a>,
+{
+    type SystemData = (S::SystemData, Read<'a, SystemTimings>);
+
+    fn run(&mut self, (data, timings): Self::SystemData) {
+        let start = Instant::now();
+        self.system.run(data);
+        let duration = start.elapsed();
+        timings.0.lock().unwrap().push((self.name, duration));
+    }
+}
+
+/// Adds systems that are timed by the FrameProfiler
+pub trait AddProfiled {
+    fn add_profiled<S>(&mut self, system: S, name: &'static str, dependencies: &[&str])
+    where
+        S: for<'c> System<'c> + Send + 'static,
+        for<'c> <S as System<'c>>::SystemData: SystemData<'c>;
+
+    fn with_profiled<S>(mut self, system: S, name: &'static str, dependencies: &[&str]) -> Self
+    where
+        Self: Sized,
+        S: for<'c> System<'c> + Send + 'static,
+        for<'c> <S as System<'c>>::SystemData: SystemData<'c>,
+    {
+        self.add_profiled(system, name, dependencies);
+        self
+    }
+}
+
+impl AddProfiled for DispatcherBuilder<'_, '_> {
+    fn add_profiled<S>(&mut self, system: S, name: &'static str, dependencies: &[&str])
+    where
+        S: for<'c> System<'c> + Send + 'static,
+        for<'c> <S as System<'c>>::SystemData: SystemData<'c>,
+    {
+        self.add(Profiled { system, name }, name, dependencies);
+    }
+}
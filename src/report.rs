@@ -0,0 +1,446 @@
+use crate::block::Blocks;
+use crate::entity::{
+    blueprint::Blueprint,
+    gameplay::{AsteroidField, GameState, GameStats, WaveCheckpoint},
+    paint::Paint,
+    ship, BlockEntity, Model, Ship,
+};
+use crate::floor::Floors;
+use crate::item::{GameItem, Inventory};
+use crate::stats::RunStats;
+use cgmath::Point2;
+use specs::prelude::*;
+use std::fs;
+
+pub const REPORT_DIR: &str = "saves/reports";
+/// Changes whenever a field of the report is renamed, removed or changes its meaning, so
+/// tools reading the reports can tell the formats apart. Adding a field keeps the version.
+pub const REPORT_VERSION: u32 = 1;
+
+/// The values that a report is made of
+#[derive(Debug, PartialEq)]
+enum Json {
+    Null,
+    Number(f64),
+    Text(String),
+    Array(Vec<Json>),
+    /// The fields are written in this order
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    fn number(value: impl Into<f64>) -> Json {
+        Json::Number(value.into())
+    }
+
+    /// Rounded to milliseconds, since the updates are not more precise than that
+    fn seconds(value: f32) -> Json {
+        Json::Number((value as f64 * 1000.0).round() / 1000.0)
+    }
+
+    fn write(&self, out: &mut String) {
+        match self {
+            Json::Null => out.push_str("null"),
+            // JSON has no infinity or NaN
+            Json::Number(value) if !value.is_finite() => out.push_str("null"),
+            Json::Number(value) => out.push_str(&value.to_string()),
+            Json::Text(text) => write_text(text, out),
+            Json::Array(values) => {
+                out.push('[');
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    value.write(out);
+                }
+                out.push(']');
+            }
+            Json::Object(fields) => {
+                out.push('{');
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_text(key, out);
+                    out.push(':');
+                    value.write(out);
+                }
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn write_text(text: &str, out: &mut String) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn outcome_name(state: GameState) -> &'static str {
+    match state {
+        GameState::Playing => "in_progress",
+        GameState::Won => "won",
+        GameState::Lost => "lost",
+    }
+}
+
+/// Describes the current run as JSON, so runs can be analyzed outside of the game
+pub fn build_report(world: &World) -> String {
+    let state = *world.read_resource::<GameState>();
+    let stats = world.read_resource::<GameStats>();
+    let run_stats = world.read_resource::<RunStats>();
+    let inventory = world.read_resource::<Inventory>();
+    let blocks = world.read_resource::<Blocks>();
+    let floors = world.read_resource::<Floors>();
+    let fields = world.read_component::<AsteroidField>();
+    let level = fields.join().next().map(|field| field.level);
+
+    // Every wave but the last was survived. The last wave ends the way the run did.
+    let waves = stats
+        .wave_starts
+        .iter()
+        .enumerate()
+        .map(|(i, start)| {
+            let end = stats
+                .wave_starts
+                .get(i + 1)
+                .copied()
+                .unwrap_or(stats.time_survived);
+            let outcome = if i + 1 < stats.wave_starts.len() {
+                "survived"
+            } else {
+                outcome_name(state)
+            };
+            Json::object(vec![
+                ("level", Json::number(i as u32 + 1)),
+                ("start", Json::seconds(*start)),
+                ("duration", Json::seconds(end - start)),
+                ("outcome", Json::Text(outcome.to_string())),
+            ])
+        })
+        .collect();
+
+    let ships = world.read_component::<Ship>();
+    let ship = ship::mothership(&ships).map(|ship| {
+        let (min, max) = ship.tiles().fold(
+            (
+                Point2::new(i16::MAX, i16::MAX),
+                Point2::new(i16::MIN, i16::MIN),
+            ),
+            |(min, max), (pos, _)| {
+                (
+                    Point2::new(min.x.min(pos.x), min.y.min(pos.y)),
+                    Point2::new(max.x.max(pos.x), max.y.max(pos.y)),
+                )
+            },
+        );
+        let layout = Blueprint::capture(
+            ship,
+            &world.read_component::<BlockEntity>(),
+            &world.read_component::<Paint>(),
+            &world.read_component::<Model>(),
+            &blocks,
+            min,
+            max,
+        );
+        let counts = layout
+            .block_counts(&blocks)
+            .into_iter()
+            .map(|(name, count)| (name.to_string(), Json::number(count)))
+            .collect();
+        Json::object(vec![
+            ("block_counts", Json::Object(counts)),
+            ("layout", Json::Text(layout.to_text(&blocks, &floors))),
+        ])
+    });
+
+    let report = Json::object(vec![
+        ("version", Json::number(REPORT_VERSION)),
+        // u64s do not fit in a JSON number, so they are written as text
+        ("run_id", Json::Text(run_stats.run_id().to_string())),
+        (
+            "seed",
+            Json::Text(world.read_resource::<WaveCheckpoint>().seed.to_string()),
+        ),
+        ("outcome", Json::Text(outcome_name(state).to_string())),
+        ("wave", level.map_or(Json::Null, Json::number)),
+        ("waves", Json::Array(waves)),
+        (
+            "game_stats",
+            Json::object(vec![
+                (
+                    "asteroids_destroyed",
+                    Json::number(stats.asteroids_destroyed),
+                ),
+                ("raiders_destroyed", Json::number(stats.raiders_destroyed)),
+                ("raiders_driven_off", Json::number(stats.raiders_driven_off)),
+                ("items_mined", Json::number(stats.items_mined)),
+                ("time_survived", Json::seconds(stats.time_survived)),
+            ]),
+        ),
+        (
+            "run_stats",
+            Json::Object(
+                run_stats
+                    .counters
+                    .with_prefix("")
+                    .map(|(key, value)| (key.to_string(), Json::number(value as f64)))
+                    .collect(),
+            ),
+        ),
+        (
+            "inventory",
+            Json::Object(
+                GameItem::iter()
+                    .map(|item| (format!("{:?}", item), Json::number(inventory.amount(item))))
+                    .collect(),
+            ),
+        ),
+        ("ship", ship.unwrap_or(Json::Null)),
+    ]);
+
+    let mut text = String::new();
+    report.write(&mut text);
+    text
+}
+
+/// Saves a report of the current run. The report is built right away, but written on
+/// another thread so saving does not hold up the update.
+pub fn export_report(world: &World) {
+    let text = build_report(world);
+    let path = format!(
+        "{}/run_{}.json",
+        REPORT_DIR,
+        world.read_resource::<RunStats>().run_id()
+    );
+
+    std::thread::spawn(move || {
+        let result = fs::create_dir_all(REPORT_DIR).and_then(|_| fs::write(&path, text));
+        match result {
+            Ok(()) => println!("[Report] Saved {}", path),
+            Err(error) => println!("[Report] Unable to save {}: {}", path, error),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::headless::HeadlessEcs;
+    use std::iter::Peekable;
+    use std::str::Chars;
+
+    /// Reads the JSON that Json::write produces. Whitespace isn't handled, since none is written.
+    fn parse(text: &str) -> Json {
+        let mut chars = text.chars().peekable();
+        let value = parse_value(&mut chars);
+        assert_eq!(chars.next(), None, "Trailing text in {}", text);
+        value
+    }
+
+    fn parse_value(chars: &mut Peekable<Chars>) -> Json {
+        match chars.peek().copied() {
+            Some('n') => {
+                let word: String = chars.take(4).collect();
+                assert_eq!(word, "null");
+                Json::Null
+            }
+            Some('"') => Json::Text(parse_text(chars)),
+            Some('[') => {
+                chars.next();
+                let mut values = Vec::new();
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                    return Json::Array(values);
+                }
+                loop {
+                    values.push(parse_value(chars));
+                    match chars.next() {
+                        Some(',') => {}
+                        Some(']') => return Json::Array(values),
+                        c => panic!("Expected , or ] in an array, found {:?}", c),
+                    }
+                }
+            }
+            Some('{') => {
+                chars.next();
+                let mut fields = Vec::new();
+                if chars.peek() == Some(&'}') {
+                    chars.next();
+                    return Json::Object(fields);
+                }
+                loop {
+                    let key = parse_text(chars);
+                    assert_eq!(chars.next(), Some(':'));
+                    fields.push((key, parse_value(chars)));
+                    match chars.next() {
+                        Some(',') => {}
+                        Some('}') => return Json::Object(fields),
+                        c => panic!("Expected , or }} in an object, found {:?}", c),
+                    }
+                }
+            }
+            _ => {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_digit() || "+-.eE".contains(c)) {
+                        break;
+                    }
+                    number.push(c);
+                    chars.next();
+                }
+                Json::Number(number.parse().expect("Invalid number"))
+            }
+        }
+    }
+
+    fn parse_text(chars: &mut Peekable<Chars>) -> String {
+        assert_eq!(chars.next(), Some('"'));
+        let mut text = String::new();
+        loop {
+            match chars.next().expect("Unterminated text") {
+                '"' => return text,
+                '\\' => match chars.next() {
+                    Some('"') => text.push('"'),
+                    Some('\\') => text.push('\\'),
+                    Some('n') => text.push('\n'),
+                    Some('r') => text.push('\r'),
+                    Some('t') => text.push('\t'),
+                    Some('u') => {
+                        let code: String = chars.take(4).collect();
+                        let code = u32::from_str_radix(&code, 16).unwrap();
+                        text.push(std::char::from_u32(code).unwrap());
+                    }
+                    c => panic!("Invalid escape {:?}", c),
+                },
+                c if (c as u32) < 0x20 => panic!("Unescaped control character {:?}", c),
+                c => text.push(c),
+            }
+        }
+    }
+
+    fn to_text(value: &Json) -> String {
+        let mut text = String::new();
+        value.write(&mut text);
+        text
+    }
+
+    fn field<'a>(value: &'a Json, key: &str) -> &'a Json {
+        match value {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value)
+                .unwrap_or_else(|| panic!("Missing field {}", key)),
+            _ => panic!("Expected an object holding {}", key),
+        }
+    }
+
+    fn number(value: &Json) -> f64 {
+        match value {
+            Json::Number(value) => *value,
+            _ => panic!("Expected a number, found {:?}", value),
+        }
+    }
+
+    fn text(value: &Json) -> &str {
+        match value {
+            Json::Text(text) => text,
+            _ => panic!("Expected text, found {:?}", value),
+        }
+    }
+
+    #[test]
+    fn values_round_trip() {
+        let value = Json::object(vec![
+            ("null", Json::Null),
+            ("integer", Json::number(42)),
+            ("negative", Json::number(-3.25)),
+            ("small", Json::seconds(0.001)),
+            ("large", Json::Number(1e21)),
+            (
+                "text",
+                Json::Text("quote \" slash \\ line\n tab\t bell\u{7} é".to_string()),
+            ),
+            ("empty", Json::Array(Vec::new())),
+            (
+                "nested",
+                Json::Array(vec![Json::object(vec![]), Json::Array(vec![Json::Null])]),
+            ),
+        ]);
+        let text = to_text(&value);
+        assert_eq!(parse(&text), value);
+
+        // There is no way to write these, so they read back as null
+        assert_eq!(parse(&to_text(&Json::Number(f64::NAN))), Json::Null);
+        assert_eq!(parse(&to_text(&Json::Number(f64::INFINITY))), Json::Null);
+    }
+
+    #[test]
+    fn report_has_the_required_fields() {
+        let mut headless = HeadlessEcs::with_seed(3);
+        headless.step(600);
+
+        let written = build_report(&headless.ecs.world);
+        let report = parse(&written);
+        assert_eq!(to_text(&report), written);
+
+        assert_eq!(number(field(&report, "version")), REPORT_VERSION as f64);
+        assert!(text(field(&report, "run_id")).parse::<u64>().is_ok());
+        assert_eq!(text(field(&report, "seed")), "3");
+        assert_eq!(text(field(&report, "outcome")), "in_progress");
+        assert_eq!(number(field(&report, "wave")), 1.0);
+
+        let waves = match field(&report, "waves") {
+            Json::Array(waves) => waves,
+            value => panic!("Expected the waves, found {:?}", value),
+        };
+        assert_eq!(waves.len(), 1);
+        assert_eq!(number(field(&waves[0], "level")), 1.0);
+        assert!(number(field(&waves[0], "start")) >= 0.0);
+        assert!(number(field(&waves[0], "duration")) >= 0.0);
+        assert_eq!(text(field(&waves[0], "outcome")), "in_progress");
+
+        let game_stats = field(&report, "game_stats");
+        for key in &[
+            "asteroids_destroyed",
+            "raiders_destroyed",
+            "raiders_driven_off",
+            "items_mined",
+        ] {
+            assert!(number(field(game_stats, key)) >= 0.0);
+        }
+        assert!((number(field(game_stats, "time_survived")) - 10.0).abs() < 0.1);
+        assert!(matches!(field(&report, "run_stats"), Json::Object(_)));
+
+        let inventory = field(&report, "inventory");
+        for item in GameItem::iter() {
+            let amount = number(field(inventory, &format!("{:?}", item)));
+            assert_eq!(amount, headless.inventory().amount(item) as f64);
+        }
+
+        let ship = field(&report, "ship");
+        assert!(matches!(field(ship, "block_counts"), Json::Object(counts) if !counts.is_empty()));
+        assert!(!text(field(ship, "layout")).is_empty());
+    }
+}
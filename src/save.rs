@@ -0,0 +1,188 @@
+//! The versions of the ship saves (the saved blueprints, and the blueprints in shared ship
+//! codes). Each save starts with the version of its format and of the game that wrote it.
+//! Older saves are migrated one version at a time before they are decoded, so only the
+//! current format is ever decoded.
+
+/// The format of the saves that are written. Bump it whenever the format changes, and add
+/// the migration from the previous version to MIGRATIONS.
+pub const FORMAT_VERSION: u32 = 2;
+/// The version of the game that writes the saves
+pub const GAME_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A line of a save, split into its words
+pub type Record = Vec<String>;
+
+/// Each migration changes the records of a save from one version to the next, starting with
+/// the migration from version 1 to 2
+const MIGRATIONS: [fn(&mut Vec<Record>); FORMAT_VERSION as usize - 1] = [add_decks];
+
+/// A save that was migrated to the current format, but not decoded yet
+pub struct SaveRecords {
+    /// The format the save was written in
+    pub version: u32,
+    /// The game that wrote the save, which saves from before the header don't have
+    pub game_version: Option<String>,
+    /// The lines of the save without the header. Empty lines are left out.
+    pub records: Vec<Record>,
+}
+
+/// The first line of every save
+pub fn header() -> String {
+    format!("format {} {}\n", FORMAT_VERSION, GAME_VERSION)
+}
+
+/// Splits a save into its records, and migrates them to the current format. Saves without
+/// a header are from before there was one, which is version 1.
+pub fn read(text: &str) -> Result<SaveRecords, String> {
+    let mut records: Vec<Record> = text
+        .lines()
+        .map(|line| line.split_whitespace().map(String::from).collect())
+        .filter(|record: &Record| !record.is_empty())
+        .collect();
+
+    let (version, game_version) = match records.first().map(Vec::as_slice) {
+        Some([kind, version, game_version]) if kind == "format" => match version.parse() {
+            Ok(version) if version >= 1 => (version, Some(game_version.clone())),
+            _ => return Err(format!("Invalid save format: {}", version)),
+        },
+        _ => (1, None),
+    };
+    if game_version.is_some() {
+        records.remove(0);
+    }
+    if version > FORMAT_VERSION {
+        return Err(format!(
+            "The save was created by a newer version of the game ({}, format {}). This \
+             version ({}) can only read saves up to format {}.",
+            game_version.unwrap_or_default(),
+            version,
+            GAME_VERSION,
+            FORMAT_VERSION
+        ));
+    }
+
+    for migrate in &MIGRATIONS[version as usize - 1..] {
+        migrate(&mut records);
+    }
+    Ok(SaveRecords {
+        version,
+        game_version,
+        records,
+    })
+}
+
+/// Version 2 added the header. Some of the saves from before it are from before there were
+/// decks, and their floors and blocks are on the lowest deck.
+fn add_decks(records: &mut Vec<Record>) {
+    for record in records {
+        let without_deck = matches!(
+            record.as_slice(),
+            [kind, _, _, _] if kind == "floor" || kind == "block"
+        );
+        if without_deck {
+            record.insert(3, String::from("0"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Blocks;
+    use crate::entity::{blueprint::Blueprint, headless::HeadlessEcs, paint::Paint};
+    use crate::entity::{ship, BlockEntity, Model, Ship};
+    use crate::floor::Floors;
+    use cgmath::{Point2, Point3};
+    use specs::prelude::*;
+
+    /// A blueprint of the corner of the starting ship, saved before there was a header or
+    /// decks
+    const BLUEPRINT_V1: &str = include_str!("../tests/fixtures/blueprint_v1.txt");
+
+    /// Loads the save, and saves it again
+    fn resave(text: &str, world: &World) -> String {
+        let blocks = world.fetch::<Blocks>();
+        let floors = world.fetch::<Floors>();
+        let (blueprint, report) = Blueprint::parse(text, &blocks, &floors, &world.fetch()).unwrap();
+        assert!(report.unresolved.is_empty());
+        blueprint.to_text(&blocks, &floors)
+    }
+
+    #[test]
+    fn saves_without_a_header_are_migrated() {
+        let save = read(BLUEPRINT_V1).unwrap();
+        assert_eq!(save.version, 1);
+        assert_eq!(save.game_version, None);
+        let positioned = save
+            .records
+            .iter()
+            .filter(|record| record[0] == "floor" || record[0] == "block");
+        for record in positioned {
+            assert_eq!(record.len(), 5, "{:?}", record);
+            assert_eq!(record[3], "0");
+        }
+
+        let headless = HeadlessEcs::with_seed(1);
+        let world = &headless.ecs.world;
+        let blocks = world.fetch::<Blocks>();
+        let (blueprint, _) =
+            Blueprint::parse(BLUEPRINT_V1, &blocks, &world.fetch(), &world.fetch()).unwrap();
+        let counts = blueprint.block_counts(&blocks);
+        assert_eq!(counts.get("wall"), Some(&6));
+        assert_eq!(counts.get("Miner"), Some(&1));
+        assert_eq!(counts.get("Laser"), Some(&1));
+        assert_eq!(
+            blueprint.block_at(Point3::new(0, 3, 0), &blocks),
+            blocks.find("Miner")
+        );
+        std::mem::drop(blocks);
+
+        // It is saved in the current format
+        let text = resave(BLUEPRINT_V1, world);
+        assert!(text.starts_with(&header()));
+        assert!(text.contains("\nblock 0 3 0 Miner\n"));
+        assert_eq!(resave(&text, world), text);
+    }
+
+    #[test]
+    fn saving_a_loaded_save_changes_nothing() {
+        let mut headless = HeadlessEcs::with_seed(1);
+        headless.step(1);
+        let world = &headless.ecs.world;
+        let ships = world.read_component::<Ship>();
+        let ship = ship::mothership(&ships).unwrap();
+        let painted = ship.tile(Point3::new(0, 1, 0)).unwrap().block().unwrap();
+        world
+            .write_component::<Paint>()
+            .insert(painted, Paint::PALETTE[3])
+            .unwrap();
+
+        let blueprint = Blueprint::capture(
+            ship,
+            &world.read_component::<BlockEntity>(),
+            &world.read_component::<Paint>(),
+            &world.read_component::<Model>(),
+            &world.fetch::<Blocks>(),
+            Point2::new(-1, -2),
+            Point2::new(8, 9),
+        );
+        let text = blueprint.to_text(&world.fetch(), &world.fetch());
+        assert!(text.contains("\npaint 1 3 0 "));
+        assert_eq!(resave(&text, world), text);
+    }
+
+    #[test]
+    fn saves_from_newer_versions_are_refused() {
+        let newer = format!("format {} 9.9.9\nsize 1 1\n", FORMAT_VERSION + 1);
+        let error = read(&newer).err().unwrap();
+        assert!(error.contains("newer version"), "{}", error);
+        assert!(error.contains("9.9.9"), "{}", error);
+
+        assert!(read("format 0 0.1.0\nsize 1 1\n").is_err());
+        assert!(read("format two 0.1.0\nsize 1 1\n").is_err());
+        let current = read(&format!("{}size 1 1\n", header())).unwrap();
+        assert_eq!(current.version, FORMAT_VERSION);
+        assert_eq!(current.game_version.as_deref(), Some(GAME_VERSION));
+        assert_eq!(current.records, vec![vec!["size", "1", "1"]]);
+    }
+}
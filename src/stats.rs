@@ -0,0 +1,772 @@
+use crate::block::Blocks;
+use crate::content::{ContentHash, ContentKind, LoadReport};
+use crate::entity::{damage::DamageSource, Ship, Time};
+use crate::item::GameItem;
+use specs::prelude::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const PROFILE_PATH: &str = "saves/profile.txt";
+
+/// Where the profile and the achievements are saved. Runs without save files (ex. headless
+/// runs) start as a new player, and don't change the player's profile.
+#[derive(Clone, Copy, Debug)]
+pub struct SaveFiles {
+    pub profile: Option<&'static str>,
+    pub achievements: Option<&'static str>,
+}
+
+impl SaveFiles {
+    pub const PLAYER: SaveFiles = SaveFiles {
+        profile: Some(PROFILE_PATH),
+        achievements: Some(crate::achievements::ACHIEVEMENTS_PATH),
+    };
+    pub const NONE: SaveFiles = SaveFiles {
+        profile: None,
+        achievements: None,
+    };
+}
+
+pub enum Stat {
+    /// Counted at 60 ticks per second, whatever the tick rate is
+    PlaytimeTicks,
+    AsteroidsDestroyed(GameItem),
+    /// The asteroids destroyed by each source of damage (ex. lasers)
+    AsteroidsDestroyedBy(DamageSource),
+    ItemsMined(GameItem),
+    BlocksPlaced(&'static str),
+    /// The damage taken by the player's ships
+    DamageTaken,
+    /// The blocks and gadgets of the player's ships that were destroyed
+    BlocksLost,
+    /// The waves that were survived, including the final wave
+    WavesSurvived,
+    /// The runs where the final wave was survived
+    Wins,
+    /// The highest wave that was reached
+    PeakWave,
+    /// The most heat that a ship had
+    PeakHeat,
+}
+
+impl Stat {
+    /// The kind of content named in a key (ex. the block in "blocks_placed.Box")
+    fn content_kind(key: &str) -> Option<ContentKind> {
+        match key.split('.').next() {
+            Some("blocks_placed") => Some(ContentKind::Block),
+            Some("asteroids_destroyed") | Some("items_mined") => Some(ContentKind::Item),
+            _ => None,
+        }
+    }
+
+    /// Peak stats keep the highest value instead of adding up, in the run and in the profile
+    fn is_peak(key: &str) -> bool {
+        key.starts_with("peak_")
+    }
+
+    /// Writes the key of the stat (ex. "items_mined.Iron"), so it can be compared with the
+    /// keys of the counters without allocating
+    fn write_key(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        match self {
+            Stat::PlaytimeTicks => out.write_str("playtime_ticks"),
+            Stat::AsteroidsDestroyed(item) => write!(out, "asteroids_destroyed.{:?}", item),
+            Stat::AsteroidsDestroyedBy(source) => {
+                write!(out, "asteroids_destroyed_by.{:?}", source)
+            }
+            Stat::ItemsMined(item) => write!(out, "items_mined.{:?}", item),
+            Stat::BlocksPlaced(type_name) => write!(out, "blocks_placed.{}", type_name),
+            Stat::DamageTaken => out.write_str("damage_taken"),
+            Stat::BlocksLost => out.write_str("blocks_lost"),
+            Stat::WavesSurvived => out.write_str("waves_survived"),
+            Stat::Wins => out.write_str("wins"),
+            Stat::PeakWave => out.write_str("peak_wave"),
+            Stat::PeakHeat => out.write_str("peak_heat"),
+        }
+    }
+
+    fn key(&self) -> String {
+        let mut key = String::new();
+        self.write_key(&mut key).unwrap();
+        key
+    }
+
+    fn is_key(&self, key: &str) -> bool {
+        let mut matcher = KeyMatcher { rest: Some(key) };
+        self.write_key(&mut matcher).unwrap();
+        matcher.rest == Some("")
+    }
+}
+
+/// Compares what is written to it with the start of a key
+struct KeyMatcher<'a> {
+    /// The part of the key that is left to compare, or None if it didn't match
+    rest: Option<&'a str>,
+}
+
+impl fmt::Write for KeyMatcher<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.rest = self.rest.and_then(|rest| rest.strip_prefix(s));
+        Ok(())
+    }
+}
+
+/// Something that happened during the run. The systems push these to the StatEvents instead
+/// of changing the counters, so how each event is counted is decided in one place (`record`).
+#[derive(Clone, Copy, Debug)]
+pub enum StatEvent {
+    AsteroidDestroyed {
+        item: GameItem,
+        /// The source of the damage that destroyed the asteroid
+        source: DamageSource,
+    },
+    ItemsMined(GameItem, u32),
+    /// A block that the player built, by its type name
+    BlockPlaced(&'static str),
+    /// Damage taken by one of the player's ships
+    DamageTaken(u32),
+    /// A block or gadget of one of the player's ships was destroyed
+    BlockLost,
+    /// The current heat of one of the player's ships
+    Heat(f32),
+    WaveStarted(u16),
+    FinalWaveSurvived,
+}
+
+/// The stat events of the current update, which are counted by the StatsSystem
+#[derive(Default)]
+pub struct StatEvents {
+    events: Vec<StatEvent>,
+}
+
+impl StatEvents {
+    pub fn push(&mut self, event: StatEvent) {
+        self.events.push(event);
+    }
+
+    /// Counts the events in the counters, and clears them
+    pub fn record_all(&mut self, counters: &mut StatCounters) {
+        for event in self.events.drain(..) {
+            record(counters, event);
+        }
+    }
+}
+
+/// Counts the event in the counters
+pub fn record(counters: &mut StatCounters, event: StatEvent) {
+    match event {
+        StatEvent::AsteroidDestroyed { item, source } => {
+            counters.add(Stat::AsteroidsDestroyed(item), 1);
+            counters.add(Stat::AsteroidsDestroyedBy(source), 1);
+        }
+        StatEvent::ItemsMined(item, amount) => counters.add(Stat::ItemsMined(item), amount as u64),
+        StatEvent::BlockPlaced(type_name) => counters.add(Stat::BlocksPlaced(type_name), 1),
+        StatEvent::DamageTaken(amount) => counters.add(Stat::DamageTaken, amount as u64),
+        StatEvent::BlockLost => counters.add(Stat::BlocksLost, 1),
+        StatEvent::Heat(heat) => counters.raise(Stat::PeakHeat, heat.max(0.0).round() as u64),
+        StatEvent::WaveStarted(level) => {
+            counters.raise(Stat::PeakWave, level as u64);
+            // Reaching a wave means the one before it was survived
+            if level > 1 {
+                counters.add(Stat::WavesSurvived, 1);
+            }
+        }
+        StatEvent::FinalWaveSurvived => {
+            counters.add(Stat::WavesSurvived, 1);
+            counters.add(Stat::Wins, 1);
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct StatCounters(BTreeMap<String, u64>);
+
+impl StatCounters {
+    pub fn add(&mut self, stat: Stat, amount: u64) {
+        *self.stat_counter(&stat) += amount;
+    }
+
+    /// Keeps the highest value, for the peak stats
+    pub fn raise(&mut self, stat: Stat, value: u64) {
+        let peak = self.stat_counter(&stat);
+        *peak = (*peak).max(value);
+    }
+
+    pub fn get(&self, stat: Stat) -> u64 {
+        self.0
+            .iter()
+            .find(|(key, _)| stat.is_key(key))
+            .map_or(0, |(_, value)| *value)
+    }
+
+    /// Adds up the counters that start with `prefix` (ex. every item in "items_mined.")
+    pub fn total(&self, prefix: &str) -> u64 {
+        self.with_prefix(prefix).map(|(_, value)| value).sum()
+    }
+
+    /// Returns the counters that start with `prefix` (ex. "blocks_placed.") with the prefix removed
+    pub fn with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a str, u64)> {
+        self.0
+            .iter()
+            .filter_map(move |(key, value)| Some((key.strip_prefix(prefix)?, *value)))
+    }
+
+    fn add_key(&mut self, key: &str, amount: u64) {
+        *self.counter(key) += amount;
+    }
+
+    fn raise_key(&mut self, key: &str, value: u64) {
+        let peak = self.counter(key);
+        *peak = (*peak).max(value);
+    }
+
+    /// The counters are searched without formatting the stat's key, which is only done when
+    /// the counter is new
+    fn stat_counter(&mut self, stat: &Stat) -> &mut u64 {
+        if !self.0.keys().any(|key| stat.is_key(key)) {
+            self.0.insert(stat.key(), 0);
+        }
+        self.0
+            .iter_mut()
+            .find(|(key, _)| stat.is_key(key))
+            .map(|(_, value)| value)
+            .unwrap()
+    }
+
+    /// The key is only copied when the counter is new
+    fn counter(&mut self, key: &str) -> &mut u64 {
+        if !self.0.contains_key(key) {
+            self.0.insert(key.to_owned(), 0);
+        }
+        self.0.get_mut(key).unwrap()
+    }
+
+    fn get_key(&self, key: &str) -> u64 {
+        self.0.get(key).copied().unwrap_or(0)
+    }
+
+    /// Copies the counters of `other`. The keys that both have are kept, so copying the same
+    /// stats again doesn't allocate. The keys of `keep` are kept at 0, for the counters that
+    /// are added back right after copying.
+    fn copy_from(&mut self, other: &StatCounters, keep: Option<&StatCounters>) {
+        self.0.retain(|key, value| {
+            *value = 0;
+            other.0.contains_key(key) || keep.is_some_and(|keep| keep.0.contains_key(key))
+        });
+        for (key, value) in &other.0 {
+            match self.0.get_mut(key) {
+                Some(counter) => *counter = *value,
+                None => {
+                    self.0.insert(key.clone(), *value);
+                }
+            }
+        }
+    }
+}
+
+/// Statistics of the current run
+pub struct RunStats {
+    /// Identifies the run in the profile, so it is never merged twice
+    run_id: u64,
+    pub counters: StatCounters,
+}
+
+impl RunStats {
+    /// How often (in seconds) the run is merged into the profile
+    pub const AUTOSAVE_TIME: f32 = 60.0;
+
+    pub fn new() -> Self {
+        let run_id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_millis() as u64;
+
+        Self {
+            run_id,
+            counters: StatCounters::default(),
+        }
+    }
+
+    pub fn run_id(&self) -> u64 {
+        self.run_id
+    }
+}
+
+/// The statistics of every run, which are saved in the player's profile
+#[derive(Clone)]
+pub struct LifetimeStats {
+    pub counters: StatCounters,
+    pub runs: u64,
+    /// The last run that was merged, and how much of it was merged. If the game crashes after
+    /// an autosave, only the part of the run that was saved is counted, and merging the same
+    /// run again only adds what changed since the last merge.
+    watermark: Option<(u64, StatCounters)>,
+    /// The content hashes of the blocks and items named in the keys, so the stats can follow
+    /// renamed content. Content that could not be found is kept, so it is not lost if the
+    /// mod that adds it is only missing temporarily.
+    content: BTreeMap<(ContentKind, String), ContentHash>,
+}
+
+impl LifetimeStats {
+    /// The profile of a new player
+    pub fn new(blocks: &Blocks) -> Self {
+        let mut stats = Self {
+            counters: StatCounters::default(),
+            runs: 0,
+            watermark: None,
+            content: BTreeMap::new(),
+        };
+        for (name, hash) in blocks.content_index().iter() {
+            stats
+                .content
+                .insert((ContentKind::Block, name.to_string()), hash);
+        }
+        for (name, hash) in GameItem::content_index().iter() {
+            stats
+                .content
+                .insert((ContentKind::Item, name.to_string()), hash);
+        }
+        stats
+    }
+
+    /// Loads the profile. A missing profile is treated as a new player, and lines that can't
+    /// be read are skipped, keeping what they would have set at its default.
+    pub fn load(path: &str, blocks: &Blocks) -> Self {
+        let blocks_index = blocks.content_index();
+        let items_index = GameItem::content_index();
+        let mut stats = LifetimeStats::new(blocks);
+
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(_) => return stats,
+        };
+
+        // The content is resolved first, so the keys can be renamed while they are loaded
+        let mut report = LoadReport::default();
+        let mut renames: BTreeMap<(ContentKind, String), String> = BTreeMap::new();
+        for line in text.lines() {
+            if let ["content", kind, name, hash] = line.split_whitespace().collect::<Vec<_>>()[..] {
+                let (kind, hash) = match (ContentKind::from_name(kind), ContentHash::parse(hash)) {
+                    (Some(kind), Some(hash)) => (kind, hash),
+                    _ => {
+                        ignore_line(line);
+                        continue;
+                    }
+                };
+                let resolved = match kind {
+                    ContentKind::Block => blocks_index
+                        .resolve(name, hash, &mut report)
+                        .map(|id| blocks.get_block(id).type_name.to_string()),
+                    ContentKind::Item => items_index
+                        .resolve(name, hash, &mut report)
+                        .map(|item| format!("{:?}", item)),
+                };
+
+                match resolved {
+                    Some(new_name) if new_name != name => {
+                        renames.insert((kind, name.to_string()), new_name);
+                    }
+                    Some(_) => {}
+                    None => {
+                        stats.content.insert((kind, name.to_string()), hash);
+                    }
+                }
+            }
+        }
+        report.print("Stats");
+
+        let rename_key = |key: &str| -> String {
+            let kind = match Stat::content_kind(key) {
+                Some(kind) => kind,
+                None => return key.to_string(),
+            };
+            let (prefix, name) = key.split_at(key.find('.').unwrap() + 1);
+            match renames.get(&(kind, name.to_string())) {
+                Some(new_name) => format!("{}{}", prefix, new_name),
+                None => key.to_string(),
+            }
+        };
+
+        for line in text.lines() {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let loaded = match parts.as_slice() {
+                ["runs", runs] => parse_number(runs).map(|runs| stats.runs = runs),
+                ["stat", key, value] => {
+                    parse_number(value).map(|value| stats.counters.add_key(&rename_key(key), value))
+                }
+                ["watermark", run_id] => parse_number(run_id)
+                    .map(|run_id| stats.watermark = Some((run_id, StatCounters::default()))),
+                // A merged stat before any watermark has no run to belong to
+                ["merged", key, value] => stats
+                    .watermark
+                    .as_mut()
+                    .zip(parse_number(value))
+                    .map(|((_, merged), value)| merged.add_key(&rename_key(key), value)),
+                // The content was read above
+                ["content", _, _, _] | [] => Some(()),
+                _ => None,
+            };
+            if loaded.is_none() {
+                ignore_line(line);
+            }
+        }
+
+        stats
+    }
+
+    /// Adds everything that changed in the run since it was last merged
+    pub fn merge(&mut self, run: &RunStats) {
+        let merged = match &mut self.watermark {
+            Some((run_id, merged)) if *run_id == run.run_id => merged,
+            watermark => {
+                self.runs += 1;
+                &mut watermark.insert((run.run_id, StatCounters::default())).1
+            }
+        };
+        LifetimeStats::add_unmerged(&mut self.counters, Some(merged), run);
+        merged.copy_from(&run.counters, None);
+    }
+
+    /// The counters with the run merged into them, without merging it. They are copied into
+    /// `combined`, so they can be checked every so often without allocating.
+    pub fn merged_counters(&self, run: &RunStats, combined: &mut StatCounters) {
+        combined.copy_from(&self.counters, Some(&run.counters));
+        let merged = match &self.watermark {
+            Some((run_id, merged)) if *run_id == run.run_id => Some(merged),
+            _ => None,
+        };
+        LifetimeStats::add_unmerged(combined, merged, run);
+    }
+
+    /// Adds what changed in the run since `merged` was copied from it
+    fn add_unmerged(counters: &mut StatCounters, merged: Option<&StatCounters>, run: &RunStats) {
+        for (key, value) in &run.counters.0 {
+            if Stat::is_peak(key) {
+                counters.raise_key(key, *value);
+                continue;
+            }
+            let delta = value.saturating_sub(merged.map_or(0, |merged| merged.get_key(key)));
+            if delta > 0 {
+                counters.add_key(key, delta);
+            }
+        }
+    }
+
+    pub fn save(&self, path: &str) {
+        let mut text = format!("runs {}\n", self.runs);
+        for ((kind, name), hash) in &self.content {
+            text.push_str(&format!("content {} {} {}\n", kind.name(), name, hash));
+        }
+        for (key, value) in &self.counters.0 {
+            text.push_str(&format!("stat {} {}\n", key, value));
+        }
+        if let Some((run_id, merged)) = &self.watermark {
+            text.push_str(&format!("watermark {}\n", run_id));
+            for (key, value) in &merged.0 {
+                text.push_str(&format!("merged {} {}\n", key, value));
+            }
+        }
+
+        if let Err(error) = write_profile_file(path, &text) {
+            println!("[Stats] Unable to save profile: {}", error);
+        }
+    }
+}
+
+/// Writes to a temporary file first, so a crash while saving can't corrupt the file
+pub fn write_profile_file(path: &str, text: &str) -> std::io::Result<()> {
+    let temp_path = format!("{}.tmp", path);
+    std::path::Path::new(path)
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&temp_path, text))
+        .and_then(|_| fs::rename(&temp_path, path))
+}
+
+fn parse_number(value: &str) -> Option<u64> {
+    value.parse().ok()
+}
+
+/// The profile can be cut off by a crash or edited by hand, so lines that are not understood
+/// are skipped instead of losing the whole profile
+fn ignore_line(line: &str) {
+    println!("[Stats] Ignoring invalid line in profile: {}", line);
+}
+
+/// Counts the stat events and the playtime, and periodically merges the run into the profile
+#[derive(Default)]
+pub struct StatsSystem {
+    /// The fraction of a playtime tick that has not been counted yet
+    playtime: f32,
+    /// The seconds since the run was last merged
+    since_autosave: f32,
+}
+
+impl StatsSystem {
+    /// Profiles count the playtime in ticks at this rate, so they stay the same when the
+    /// tick rate is changed
+    const PLAYTIME_TICK_RATE: f32 = 60.0;
+}
+
+impl<'a> System<'a> for StatsSystem {
+    type SystemData = (
+        ReadExpect<'a, Time>,
+        ReadExpect<'a, SaveFiles>,
+        WriteExpect<'a, StatEvents>,
+        WriteExpect<'a, RunStats>,
+        WriteExpect<'a, LifetimeStats>,
+        ReadStorage<'a, Ship>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (time, save_files, mut events, mut run, mut lifetime, ships) = data;
+        events.record_all(&mut run.counters);
+        // The heat is sampled, since it changes every update
+        for ship in ships.join() {
+            record(&mut run.counters, StatEvent::Heat(ship.heat().heat()));
+        }
+
+        self.playtime += time.delta * StatsSystem::PLAYTIME_TICK_RATE;
+        let ticks = (self.playtime + Time::SLACK).floor();
+        self.playtime -= ticks;
+        run.counters.add(Stat::PlaytimeTicks, ticks as u64);
+
+        if time.count_up(&mut self.since_autosave, RunStats::AUTOSAVE_TIME) {
+            self.since_autosave = 0.0;
+            lifetime.merge(&run);
+            if let Some(path) = save_files.profile {
+                lifetime.save(path);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::headless::load_blocks;
+
+    fn run(run_id: u64) -> RunStats {
+        RunStats {
+            run_id,
+            counters: StatCounters::default(),
+        }
+    }
+
+    fn mine(run: &mut RunStats, amount: u32) {
+        record(
+            &mut run.counters,
+            StatEvent::ItemsMined(GameItem::Iron, amount),
+        );
+        record(&mut run.counters, StatEvent::Heat(amount as f32));
+    }
+
+    fn profile_path(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("spaceship-stats-{}", std::process::id()));
+        dir.join(name).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn events_are_counted_where_they_belong() {
+        let mut events = StatEvents::default();
+        let mut counters = StatCounters::default();
+        let laser = DamageSource::Laser;
+        for item in [GameItem::Iron, GameItem::Iron, GameItem::Copper]
+            .iter()
+            .copied()
+        {
+            events.push(StatEvent::AsteroidDestroyed {
+                item,
+                source: laser,
+            });
+        }
+        events.push(StatEvent::AsteroidDestroyed {
+            item: GameItem::Iron,
+            source: DamageSource::MissileExplosion,
+        });
+        events.push(StatEvent::ItemsMined(GameItem::Iron, 4));
+        events.push(StatEvent::ItemsMined(GameItem::Copper, 2));
+        events.push(StatEvent::BlockPlaced("wall"));
+        events.push(StatEvent::BlockPlaced("wall"));
+        events.push(StatEvent::BlockPlaced("floor"));
+        events.push(StatEvent::DamageTaken(30));
+        events.push(StatEvent::DamageTaken(12));
+        events.push(StatEvent::BlockLost);
+        events.record_all(&mut counters);
+
+        assert_eq!(counters.get(Stat::AsteroidsDestroyed(GameItem::Iron)), 3);
+        assert_eq!(counters.get(Stat::AsteroidsDestroyed(GameItem::Copper)), 1);
+        assert_eq!(counters.total("asteroids_destroyed."), 4);
+        assert_eq!(counters.get(Stat::AsteroidsDestroyedBy(laser)), 3);
+        let missile = DamageSource::MissileExplosion;
+        assert_eq!(counters.get(Stat::AsteroidsDestroyedBy(missile)), 1);
+        assert_eq!(counters.total("items_mined."), 6);
+        assert_eq!(counters.get(Stat::BlocksPlaced("wall")), 2);
+        assert_eq!(counters.get(Stat::BlocksPlaced("floor")), 1);
+        assert_eq!(counters.get(Stat::DamageTaken), 42);
+        assert_eq!(counters.get(Stat::BlocksLost), 1);
+
+        // The events are cleared once they are counted
+        events.record_all(&mut counters);
+        assert_eq!(counters.total("asteroids_destroyed."), 4);
+    }
+
+    #[test]
+    fn peaks_and_waves_are_counted_by_their_rules() {
+        let mut counters = StatCounters::default();
+        for event in [
+            StatEvent::Heat(40.0),
+            StatEvent::Heat(75.4),
+            StatEvent::Heat(-3.0),
+            StatEvent::Heat(10.0),
+            StatEvent::WaveStarted(1),
+            StatEvent::WaveStarted(2),
+            StatEvent::WaveStarted(3),
+        ]
+        .iter()
+        .copied()
+        {
+            record(&mut counters, event);
+        }
+        assert_eq!(counters.get(Stat::PeakHeat), 75);
+        assert_eq!(counters.get(Stat::PeakWave), 3);
+        // The first wave is only survived once the second one starts
+        assert_eq!(counters.get(Stat::WavesSurvived), 2);
+        assert_eq!(counters.get(Stat::Wins), 0);
+
+        record(&mut counters, StatEvent::FinalWaveSurvived);
+        assert_eq!(counters.get(Stat::WavesSurvived), 3);
+        assert_eq!(counters.get(Stat::Wins), 1);
+    }
+
+    #[test]
+    fn merging_twice_only_adds_the_difference() {
+        let mut lifetime = LifetimeStats::load(&profile_path("missing.txt"), &load_blocks());
+        let mut run = run(1);
+
+        mine(&mut run, 10);
+        lifetime.merge(&run);
+        mine(&mut run, 5);
+        lifetime.merge(&run);
+        lifetime.merge(&run);
+
+        assert_eq!(lifetime.runs, 1);
+        assert_eq!(lifetime.counters.get(Stat::ItemsMined(GameItem::Iron)), 15);
+        assert_eq!(lifetime.counters.get(Stat::PeakHeat), 10);
+    }
+
+    #[test]
+    fn merged_counters_can_be_reused() {
+        let mut lifetime = LifetimeStats::new(&load_blocks());
+        let mut run = run(1);
+        let mut combined = StatCounters::default();
+        let iron = || Stat::ItemsMined(GameItem::Iron);
+
+        mine(&mut run, 10);
+        lifetime.merged_counters(&run, &mut combined);
+        assert_eq!(combined.get(iron()), 10);
+        // The counters that are only in the run are not added twice
+        lifetime.merged_counters(&run, &mut combined);
+        assert_eq!(combined.get(iron()), 10);
+        assert_eq!(combined.get(Stat::PeakHeat), 10);
+
+        lifetime.merge(&run);
+        mine(&mut run, 5);
+        lifetime.merged_counters(&run, &mut combined);
+        assert_eq!(combined.get(iron()), 15);
+        assert_eq!(lifetime.counters.get(iron()), 10);
+    }
+
+    #[test]
+    fn crash_between_autosave_and_run_end_counts_once() {
+        let blocks = load_blocks();
+        let path = profile_path("crash.txt");
+
+        // The first run is autosaved, and then the game crashes before the run ends
+        let mut lifetime = LifetimeStats::load(&path, &blocks);
+        let mut crashed = run(1);
+        mine(&mut crashed, 10);
+        lifetime.merge(&crashed);
+        lifetime.save(&path);
+        mine(&mut crashed, 7);
+        std::mem::drop(lifetime);
+
+        // The next launch only knows the autosaved part of the crashed run
+        let mut lifetime = LifetimeStats::load(&path, &blocks);
+        assert_eq!(lifetime.runs, 1);
+        assert_eq!(lifetime.counters.get(Stat::ItemsMined(GameItem::Iron)), 10);
+
+        // The next run is autosaved and then ends normally
+        let mut next = run(2);
+        mine(&mut next, 3);
+        lifetime.merge(&next);
+        lifetime.save(&path);
+        mine(&mut next, 4);
+        lifetime.merge(&next);
+        lifetime.save(&path);
+
+        let lifetime = LifetimeStats::load(&path, &blocks);
+        assert_eq!(lifetime.runs, 2);
+        assert_eq!(lifetime.counters.get(Stat::ItemsMined(GameItem::Iron)), 17);
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn merged_watermark_survives_a_reload() {
+        let blocks = load_blocks();
+        let path = profile_path("watermark.txt");
+
+        // The autosave is loaded again (ex. after the profile was reloaded) before the run ends
+        let mut lifetime = LifetimeStats::load(&path, &blocks);
+        let mut run = run(1);
+        mine(&mut run, 10);
+        lifetime.merge(&run);
+        lifetime.save(&path);
+
+        let mut lifetime = LifetimeStats::load(&path, &blocks);
+        mine(&mut run, 5);
+        lifetime.merge(&run);
+
+        assert_eq!(lifetime.runs, 1);
+        assert_eq!(lifetime.counters.get(Stat::ItemsMined(GameItem::Iron)), 15);
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn corrupt_lines_keep_the_defaults() {
+        let blocks = load_blocks();
+        let path = profile_path("corrupt.txt");
+        let text = [
+            "runs many",
+            "content block wall 0",
+            "content gadget laser 0123456789abcdef",
+            "stat items_mined.Iron 12",
+            "stat items_mined.Copper -4",
+            "merged items_mined.Iron 3",
+            "watermark 7",
+            "merged items_mined.Iron 5",
+            "merged items_mined.Copper 1.5",
+            "fuel 3",
+            "stat damage_taken",
+        ]
+        .join("\n");
+        write_profile_file(&path, &text).unwrap();
+
+        let lifetime = LifetimeStats::load(&path, &blocks);
+        assert_eq!(lifetime.runs, 0);
+        assert_eq!(lifetime.counters.get(Stat::ItemsMined(GameItem::Iron)), 12);
+        assert_eq!(lifetime.counters.get(Stat::ItemsMined(GameItem::Copper)), 0);
+        assert_eq!(lifetime.counters.get(Stat::DamageTaken), 0);
+        let (run_id, merged) = lifetime.watermark.as_ref().unwrap();
+        assert_eq!(*run_id, 7);
+        assert_eq!(merged.get(Stat::ItemsMined(GameItem::Iron)), 5);
+        assert_eq!(merged.get(Stat::ItemsMined(GameItem::Copper)), 0);
+        // The content that was read is still there, and the broken content lines are dropped
+        assert_eq!(lifetime.content, LifetimeStats::new(&blocks).content);
+
+        let _ = fs::remove_file(&path);
+    }
+}
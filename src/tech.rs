@@ -0,0 +1,148 @@
+use crate::block::{BlockId, Blocks};
+use crate::i18n::Strings;
+use crate::item::{GameItem, Inventory};
+
+#[derive(Clone)]
+pub struct TechNode {
+    /// Identifies the node in the tech tree file and commands
+    pub name: String,
+    /// The key of the name in the language files (ex. "tech.weapons_i" for "Weapons I")
+    name_key: String,
+    pub cost: Vec<(GameItem, u32)>,
+    /// The blocks that can't be built until this node is researched
+    pub unlocks: Vec<BlockId>,
+    researched: bool,
+}
+
+impl TechNode {
+    /// The name in the current language. Nodes without a translation are shown by their name.
+    pub fn display_name<'a>(&'a self, strings: &'a Strings) -> &'a str {
+        strings.get_or(&self.name_key, &self.name)
+    }
+
+    pub fn describe_cost(&self, strings: &Strings) -> String {
+        crate::item::describe_cost(&self.cost, strings)
+    }
+}
+
+/// Research that gates which blocks are available. Blocks that are not unlocked
+/// by any node are always available.
+#[derive(Clone)]
+pub struct TechTree {
+    nodes: Vec<TechNode>,
+}
+
+impl TechTree {
+    /// Loads the tree from `assets/tech_tree.txt`. Panics if the file references
+    /// a block or item that does not exist. Without the file, every block is available.
+    pub fn load(blocks: &Blocks) -> TechTree {
+        let text = crate::assets::read_to_string("tech_tree.txt").unwrap_or_else(|error| {
+            println!("[Asset Error] {}", error);
+            String::new()
+        });
+        let mut nodes: Vec<TechNode> = Vec::new();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line.split_at(line.find(' ').unwrap_or(line.len()));
+            let value = value.trim();
+            let error = |message: &str| -> ! {
+                panic!("tech_tree.txt:{}: {}: {}", line_number + 1, message, line)
+            };
+
+            if key == "node" {
+                nodes.push(TechNode {
+                    name: value.to_string(),
+                    name_key: format!("tech.{}", value.to_lowercase().replace(' ', "_")),
+                    cost: Vec::new(),
+                    unlocks: Vec::new(),
+                    researched: false,
+                });
+                continue;
+            }
+
+            let node = nodes
+                .last_mut()
+                .unwrap_or_else(|| error("Expected a node before"));
+            match key {
+                "cost" => {
+                    let mut parts = value.split_whitespace();
+                    let item = parts
+                        .next()
+                        .and_then(GameItem::from_name)
+                        .unwrap_or_else(|| error("Invalid item"));
+                    let amount = parts
+                        .next()
+                        .and_then(|amount| amount.parse().ok())
+                        .unwrap_or_else(|| error("Invalid amount"));
+                    node.cost.push((item, amount));
+                }
+                "unlock" => node.unlocks.push(
+                    blocks
+                        .find(value)
+                        .unwrap_or_else(|| error("Unknown block type")),
+                ),
+                _ => error("Unknown key"),
+            }
+        }
+
+        TechTree { nodes }
+    }
+
+    pub fn nodes(&self) -> &[TechNode] {
+        &self.nodes
+    }
+
+    /// Returns the node that needs to be researched before the block can be built
+    pub fn required_node(&self, block: BlockId) -> Option<&TechNode> {
+        if self.is_unlocked(block) {
+            return None;
+        }
+
+        self.nodes.iter().find(|node| node.unlocks.contains(&block))
+    }
+
+    pub fn is_unlocked(&self, block: BlockId) -> bool {
+        let mut nodes = self
+            .nodes
+            .iter()
+            .filter(|node| node.unlocks.contains(&block))
+            .peekable();
+
+        nodes.peek().is_none() || nodes.any(|node| node.researched)
+    }
+
+    /// Researches the node if the inventory has enough items to pay for it
+    pub fn purchase(
+        &mut self,
+        name: &str,
+        inventory: &mut Inventory,
+        strings: &Strings,
+    ) -> Result<(), String> {
+        let node = self
+            .nodes
+            .iter_mut()
+            .find(|node| node.name == name)
+            .ok_or_else(|| strings.format("error.unknown_tech", &[("tech", &name)]))?;
+
+        let tech = node.display_name(strings);
+        if node.researched {
+            return Err(strings.format("error.already_researched", &[("tech", &tech)]));
+        }
+        if !inventory.has_items(&node.cost) {
+            return Err(strings.format(
+                "error.tech_cost",
+                &[("tech", &tech), ("cost", &node.describe_cost(strings))],
+            ));
+        }
+
+        inventory.remove_items(&node.cost);
+        node.researched = true;
+
+        Ok(())
+    }
+}
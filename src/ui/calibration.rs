@@ -0,0 +1,107 @@
+use super::*;
+use crate::graphics::{color, ColorCalibration};
+use cgmath::Vector3;
+
+/// Color swatches for checking the color pipeline, shown while ColorCalibration::visible is
+/// set (with the `color_calibration` debug command)
+struct Swatches {
+    visible: bool,
+}
+
+impl Swatches {
+    const STEPS: usize = 11;
+    const CELL_SIZE: f32 = 16.0;
+    const PADDING: f32 = 8.0;
+    /// The ramps of the grey, red, green and blue channels, from top to bottom
+    const RAMPS: [Vector3<f32>; 4] = [
+        Vector3::new(1.0, 1.0, 1.0),
+        Vector3::new(1.0, 0.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        Vector3::new(0.0, 0.0, 1.0),
+    ];
+    /// The width of the black and white stripes, and the patches beside them
+    const STRIPES_WIDTH: f32 = 48.0;
+
+    fn size() -> Point2<f32> {
+        Point2::new(
+            Swatches::STEPS as f32 * Swatches::CELL_SIZE + Swatches::PADDING * 2.0,
+            (Swatches::RAMPS.len() + 2) as f32 * Swatches::CELL_SIZE + Swatches::PADDING * 2.0,
+        )
+    }
+}
+
+pub fn create_color_calibration(ui: &mut Ui) {
+    let anchor = layout::WindowAnchor::Center.new(ui);
+    let size = Swatches::size();
+    let node = ui.new_node(
+        Some(anchor),
+        NodeGeometry {
+            pos: Point2::new(0.0, 0.0),
+            size,
+        },
+        NodeLayout { min_size: size },
+        Box::new(SwatchesRenderer),
+        Box::new(EmptyNodeHandler),
+        Some(Box::new(Swatches { visible: false })),
+    );
+    ui.set_on_update(
+        node,
+        Rc::new(move |ui, ecs| {
+            let visible = ecs.get_resource::<ColorCalibration>().visible;
+            ui.states.get_mut::<Swatches>(node).unwrap().visible = visible;
+        }),
+    );
+}
+
+struct SwatchesRenderer;
+
+impl NodeRenderer for SwatchesRenderer {
+    fn render(
+        &self,
+        ui_batch: &mut UiBatch,
+        ui: &Ui,
+        node: NodeId,
+        geometry: &NodeGeometry,
+        states: &WidgetStates,
+    ) {
+        if !states.get::<Swatches>(node).unwrap().visible {
+            return;
+        }
+        new_ninepatch_renderer(ui.assets.pane).render(ui_batch, ui, node, geometry, states);
+
+        let cell = Swatches::CELL_SIZE;
+        let left = geometry.pos.x + Swatches::PADDING;
+        let top = geometry.pos.y + Swatches::PADDING;
+        let mut rect = |x: f32, y: f32, width: f32, color: Vector3<f32>| {
+            ui_batch.draw(
+                Vector4::new(left + x, top + y, width, cell),
+                ui.assets.white,
+                color.extend(1.0),
+            );
+        };
+
+        for (row, ramp) in Swatches::RAMPS.iter().enumerate() {
+            for step in 0..Swatches::STEPS {
+                let value = step as f32 / (Swatches::STEPS - 1) as f32;
+                rect(step as f32 * cell, row as f32 * cell, cell, ramp * value);
+            }
+        }
+
+        // The stripes average to half of the light of white, which is about 73% in sRGB
+        // (and not 50%). The patches beside them show both, and the first should match.
+        let y = (Swatches::RAMPS.len() + 1) as f32 * cell;
+        for stripe in 0..Swatches::STRIPES_WIDTH as usize {
+            let value = (stripe % 2) as f32;
+            rect(stripe as f32, y, 1.0, Vector3::new(value, value, value));
+        }
+        let patches = [color::linear_to_srgb(0.5), 0.5];
+        for (i, value) in patches.iter().enumerate() {
+            rect(
+                Swatches::STRIPES_WIDTH * (i + 1) as f32,
+                y,
+                Swatches::STRIPES_WIDTH,
+                Vector3::new(*value, *value, *value),
+            );
+        }
+    }
+}
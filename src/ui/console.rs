@@ -0,0 +1,199 @@
+use super::{widgets::Label, *};
+use crate::debug::DebugCommands;
+use winit::event::VirtualKeyCode;
+
+/// A text console for the debug commands (opened with the backquote key). Commands are typed
+/// with their arguments, ex. "give_item copper 100", and their results and errors are kept
+/// in the scrollback.
+pub struct DebugConsole {
+    commands: DebugCommands,
+    root: Option<NodeId>,
+    input: String,
+    scrollback: Vec<String>,
+    /// The submitted lines, oldest first
+    history: Vec<String>,
+    /// The line of the history that is being shown, while browsing it with the arrow keys
+    history_index: Option<usize>,
+}
+
+impl DebugConsole {
+    pub const TOGGLE_KEY: VirtualKeyCode = VirtualKeyCode::Grave;
+    /// The character typed by the toggle key, which is not added to the input
+    const TOGGLE_CHAR: char = '`';
+    const SHOWN_LINES: usize = 12;
+    const MAX_SCROLLBACK: usize = 100;
+    const MAX_HISTORY: usize = 50;
+
+    pub fn new(ecs: &ECS) -> Self {
+        Self {
+            commands: DebugCommands::new(ecs),
+            root: None,
+            input: String::new(),
+            scrollback: vec![String::from("Type \"help\" to list the commands")],
+            history: Vec::new(),
+            history_index: None,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.root.is_some()
+    }
+
+    pub fn toggle(&mut self, ui: &mut Ui) {
+        if self.is_open() {
+            self.close(ui);
+        } else {
+            self.input.clear();
+            self.history_index = None;
+            self.rebuild(ui);
+        }
+    }
+
+    pub fn close(&mut self, ui: &mut Ui) {
+        if let Some(root) = self.root.take() {
+            ui.remove_node(root);
+        }
+    }
+
+    pub fn on_char(&mut self, ui: &mut Ui, c: char) {
+        if c == DebugConsole::TOGGLE_CHAR
+            || c.is_control()
+            || (c != ' ' && !ui.assets.medium_font.has_char(c))
+        {
+            return;
+        }
+
+        self.input.push(c);
+        self.rebuild(ui);
+    }
+
+    pub fn on_key(&mut self, ui: &mut Ui, ecs: &mut ECS, key: VirtualKeyCode) {
+        match key {
+            VirtualKeyCode::Escape => {
+                self.close(ui);
+                return;
+            }
+            VirtualKeyCode::Back => {
+                self.input.pop();
+            }
+            VirtualKeyCode::Up => self.browse_history(-1),
+            VirtualKeyCode::Down => self.browse_history(1),
+            VirtualKeyCode::Return => self.submit(ecs),
+            _ => {}
+        }
+
+        self.rebuild(ui);
+    }
+
+    /// Replaces the input with an older (-1) or newer (1) line of the history
+    fn browse_history(&mut self, step: isize) {
+        let index = match (self.history_index, step) {
+            (None, -1) => self.history.len().checked_sub(1),
+            (None, _) => None,
+            (Some(index), step) => {
+                let index = index as isize + step;
+                if index < 0 {
+                    Some(0)
+                } else if index as usize >= self.history.len() {
+                    None
+                } else {
+                    Some(index as usize)
+                }
+            }
+        };
+
+        self.history_index = index;
+        self.input = index
+            .map(|index| self.history[index].clone())
+            .unwrap_or_default();
+    }
+
+    fn submit(&mut self, ecs: &mut ECS) {
+        let line = std::mem::take(&mut self.input);
+        self.history_index = None;
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+
+        if self.history.last().map(String::as_str) != Some(line) {
+            self.history.push(line.to_string());
+            if self.history.len() > DebugConsole::MAX_HISTORY {
+                self.history.remove(0);
+            }
+        }
+        self.print(format!("> {}", line));
+
+        let (name, args) = match line.find(char::is_whitespace) {
+            Some(split) => line.split_at(split),
+            None => (line, ""),
+        };
+        for output in self.execute(ecs, name, args) {
+            self.print(output);
+        }
+    }
+
+    /// Runs the command, and returns the lines to print
+    fn execute(&self, ecs: &mut ECS, name: &str, args: &str) -> Vec<String> {
+        if name == "help" {
+            return self.help(args.trim());
+        }
+
+        let command = match self.commands.find(name) {
+            Some(command) => command,
+            None => return vec![format!("Unknown command: {} (try \"help\")", name)],
+        };
+        let result = command
+            .parse_args(args)
+            .and_then(|args| (command.action)(ecs, &args));
+        match (result, command.state) {
+            (Err(error), _) => vec![error],
+            (Ok(()), Some(state)) => vec![format!("{}: {}", command.name, (state)(ecs))],
+            (Ok(()), None) => vec![format!("{}: done", command.name)],
+        }
+    }
+
+    /// Lists every command, or shows the usage of a single command
+    fn help(&self, name: &str) -> Vec<String> {
+        if name.is_empty() {
+            let mut lines = vec![String::from("help <command> - Shows how a command is used")];
+            lines.extend(
+                self.commands
+                    .iter()
+                    .map(|command| format!("{} - {}", command.name, command.description)),
+            );
+            return lines;
+        }
+
+        match self.commands.find(name) {
+            Some(command) => vec![
+                format!("Usage: {}", command.usage()),
+                command.description.to_string(),
+            ],
+            None => vec![format!("Unknown command: {}", name)],
+        }
+    }
+
+    fn print(&mut self, line: String) {
+        self.scrollback.push(line);
+        if self.scrollback.len() > DebugConsole::MAX_SCROLLBACK {
+            self.scrollback.remove(0);
+        }
+    }
+
+    fn rebuild(&mut self, ui: &mut Ui) {
+        self.close(ui);
+
+        let anchor = layout::WindowAnchor::TopLeft.with_margin(ui, None, Point2::new(8.0, 8.0));
+        let vbox = layout::create_vbox(ui, Some(anchor), true);
+
+        // Boxes stack their children from the bottom, so the input is added first
+        Label::create(ui, Some(vbox), &format!("> {}_", self.input));
+        let shown = self.scrollback.len().min(DebugConsole::SHOWN_LINES);
+        for line in self.scrollback.iter().rev().take(shown) {
+            Label::create(ui, Some(vbox), line);
+        }
+
+        self.root = Some(anchor);
+    }
+}
@@ -0,0 +1,93 @@
+use super::{widgets::Button, widgets::Label, *};
+use crate::block::Blocks;
+use crate::entity::construction::{self, ConstructionQueue};
+use std::cell::RefCell;
+
+/// The most pending builds that are listed
+const MAX_ROWS: usize = 8;
+
+/// Creates a list of the pending builds on the right side of the window. Each build can be
+/// moved up the queue or cancelled. The rows are recreated whenever the amount of pending
+/// builds changes.
+pub fn create_construction_queue(ui: &mut Ui) {
+    let anchor = layout::WindowAnchor::CenterRight.with_margin(ui, None, Point2::new(8.0, 8.0));
+    let vbox = layout::create_vbox(ui, Some(anchor), true);
+    let title = Label::create(ui, Some(vbox), "");
+    let rows: Rc<RefCell<Vec<NodeId>>> = Rc::new(RefCell::new(Vec::new()));
+
+    ui.set_on_update(
+        title,
+        Rc::new(move |ui, ecs| {
+            let queue = ecs.get_resource::<ConstructionQueue>();
+            let text = match queue.pending().len() {
+                0 => String::new(),
+                _ if queue.is_paused() => ui.strings.get("construction.paused").to_string(),
+                count => ui
+                    .strings
+                    .format("construction.queue", &[("count", &count)]),
+            };
+            Label::update_text(ui, title, &text);
+
+            let count = queue.pending().len().min(MAX_ROWS);
+            let mut rows = rows.borrow_mut();
+            if rows.len() != count {
+                for row in rows.drain(..) {
+                    ui.remove_node(row);
+                }
+                for index in 0..count {
+                    rows.push(create_row(ui, vbox, index));
+                }
+            }
+        }),
+    );
+}
+
+fn create_row(ui: &mut Ui, parent: NodeId, index: usize) -> NodeId {
+    let hbox = layout::create_hbox(ui, Some(parent), false);
+    Button::create(
+        ui,
+        Some(hbox),
+        "construction.up",
+        Rc::new(move |_, ecs| ecs.get_resource_mut::<ConstructionQueue>().move_up(index)),
+    );
+    Button::create(
+        ui,
+        Some(hbox),
+        "menu.cancel",
+        Rc::new(move |_, ecs| construction::cancel(&mut ecs.world, index)),
+    );
+
+    let label = Label::create(ui, Some(hbox), "");
+    ui.set_on_update(
+        label,
+        Rc::new(move |ui, ecs| {
+            let queue = ecs.get_resource::<ConstructionQueue>();
+            let text = match queue.pending().get(index) {
+                Some(build) => {
+                    let key = if build.is_started() {
+                        "construction.building"
+                    } else {
+                        "construction.pending"
+                    };
+                    let block = ecs
+                        .get_resource::<Blocks>()
+                        .get_block(build.block_id())
+                        .name_key;
+                    ui.strings.format(
+                        key,
+                        &[
+                            ("block", &ui.strings.get(block)),
+                            ("x", &build.pos().x),
+                            ("y", &build.pos().y),
+                            ("deck", &build.pos().z),
+                        ],
+                    )
+                }
+                None => String::new(),
+            };
+            Label::update_text(ui, label, &text);
+        }),
+    );
+
+    hbox
+}
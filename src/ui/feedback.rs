@@ -0,0 +1,403 @@
+use super::{widgets::Label, *};
+use crate::block::{Blocks, Laser, LaserState};
+use crate::content::ContentErrors;
+use crate::entity::{
+    gameplay::{AsteroidField, GameLog, SpawnLane, WavePreview},
+    hazards::{EventDirector, HazardPhase},
+    hit_markers::{DamageNumber, DamageNumbers},
+    scout::ScoutDrone,
+    selection::Selection,
+    status::{StatusEffects, StatusKind},
+    BlockEntity, InputManager, Model, WindowSize,
+};
+use crate::graphics::Camera;
+use specs::{Join, WorldExt};
+
+/// How far (in pixels) damage numbers rise over their lifetime
+const DAMAGE_NUMBER_RISE: f32 = 40.0;
+const DAMAGE_NUMBER_COLOR: Color = Color {
+    r: 1.0,
+    g: 0.85,
+    b: 0.3,
+    a: 1.0,
+};
+const WAVE_PREVIEW_COLOR: Color = Color {
+    r: 0.3,
+    g: 0.8,
+    b: 1.0,
+    a: 1.0,
+};
+const CONTENT_ERROR_COLOR: Color = Color {
+    r: 1.0,
+    g: 0.4,
+    b: 1.0,
+    a: 1.0,
+};
+const BURNING_WARNING_COLOR: Color = Color {
+    r: 1.0,
+    g: 0.55,
+    b: 0.2,
+    a: 1.0,
+};
+const EMP_WARNING_COLOR: Color = Color {
+    r: 0.45,
+    g: 0.65,
+    b: 1.0,
+    a: 1.0,
+};
+const HAZARD_COLOR: Color = Color {
+    r: 1.0,
+    g: 0.4,
+    b: 0.3,
+    a: 1.0,
+};
+/// The most blocks named by a status warning, the rest are only counted
+const STATUS_WARNING_NAMES: usize = 3;
+
+/// Creates a label for each damage number that can be shown at once. The labels are
+/// reused, so damage that is applied every update does not create new nodes.
+pub fn create_damage_numbers(ui: &mut Ui) {
+    for index in 0..DamageNumbers::MAX_NUMBERS {
+        let label = Label::create(ui, None, "");
+        ui.set_on_update(
+            label,
+            Rc::new(move |ui, ecs| {
+                let numbers = ecs.get_resource::<DamageNumbers>();
+                let camera = ecs.get_resource::<Camera>();
+                let window_size = ecs.get_resource::<WindowSize>().as_point();
+                let number = numbers.get(index).and_then(|number| {
+                    Some((number, camera.project(number.position, window_size)?))
+                });
+
+                match number {
+                    Some((number, pos)) => show_damage_number(ui, label, number, pos),
+                    None => Label::update_text(ui, label, ""),
+                }
+            }),
+        );
+    }
+}
+
+fn show_damage_number(ui: &mut Ui, label: NodeId, number: &DamageNumber, pos: Point2<f32>) {
+    let progress = number.progress();
+    Label::update_text(ui, label, &number.amount.to_string());
+    Label::set_color(
+        ui,
+        label,
+        Color {
+            a: 1.0 - progress,
+            ..DAMAGE_NUMBER_COLOR
+        },
+    );
+
+    let geometry = &mut ui.geometries[label.arena_index()];
+    geometry.pos = Point2::new(
+        pos.x - geometry.size.x / 2.0,
+        pos.y + DAMAGE_NUMBER_RISE * progress,
+    );
+}
+
+/// Creates a label at each lane of the next wave, showing roughly how many asteroids
+/// will come from it
+pub fn create_wave_preview_labels(ui: &mut Ui) {
+    for lane in SpawnLane::ALL.iter().copied() {
+        let label = Label::create(ui, None, "");
+        Label::set_color(ui, label, WAVE_PREVIEW_COLOR);
+        ui.set_on_update(
+            label,
+            Rc::new(move |ui, ecs| {
+                let preview = ecs
+                    .world
+                    .read_component::<AsteroidField>()
+                    .join()
+                    .next()
+                    .and_then(AsteroidField::wave_preview)
+                    .filter(|preview| crate::WAVE_PREVIEW && preview.count(lane) > 0);
+                let camera = ecs.get_resource::<Camera>();
+                let window_size = ecs.get_resource::<WindowSize>().as_point();
+                let shown = preview.and_then(|preview| {
+                    let pos = camera.project(lane.center(WavePreview::X), window_size)?;
+                    Some((preview.count(lane), pos))
+                });
+
+                match shown {
+                    Some((count, pos)) => {
+                        Label::update_text(ui, label, &format!("~{}", count));
+                        let geometry = &mut ui.geometries[label.arena_index()];
+                        geometry.pos = Point2::new(pos.x - geometry.size.x / 2.0, pos.y);
+                    }
+                    None => Label::update_text(ui, label, ""),
+                }
+            }),
+        );
+    }
+}
+
+/// Creates a warning for each kind of status effect, listing the affected blocks and gadgets
+/// with the seconds left until the effect wears off
+pub fn create_status_warnings(ui: &mut Ui, parent: Option<NodeId>) {
+    let kinds = [
+        (StatusKind::Burning, BURNING_WARNING_COLOR),
+        (StatusKind::Emp, EMP_WARNING_COLOR),
+    ];
+    for (kind, color) in kinds.iter().copied() {
+        let label = Label::create(ui, parent, "");
+        Label::set_color(ui, label, color);
+        ui.set_on_update(
+            label,
+            Rc::new(move |ui, ecs| {
+                let blocks = ecs.get_resource::<Blocks>();
+                let statuses = ecs.world.read_component::<StatusEffects>();
+                let block_entities = ecs.world.read_component::<BlockEntity>();
+                let models = ecs.world.read_component::<Model>();
+                let entities = ecs.world.entities();
+
+                // Gadgets have no BlockEntity, so they are found by their mesh
+                let affected: Vec<String> = (&entities, &statuses)
+                    .join()
+                    .filter_map(|(entity, effects)| {
+                        let effect = effects.get(kind)?;
+                        let block_id = match block_entities.get(entity) {
+                            Some(block_entity) => block_entity.block_id(),
+                            None => blocks.find_by_mesh(models.get(entity)?.mesh_id)?,
+                        };
+                        Some(format!(
+                            "{} {}s",
+                            ui.strings.get(blocks.get_block(block_id).name_key),
+                            effect.time_left.ceil()
+                        ))
+                    })
+                    .collect();
+
+                let name = ui.strings.get(kind.name_key());
+                let text = if affected.is_empty() {
+                    String::new()
+                } else if affected.len() > STATUS_WARNING_NAMES {
+                    ui.strings.format(
+                        "hud.status_more",
+                        &[
+                            ("status", &name),
+                            ("blocks", &affected[..STATUS_WARNING_NAMES].join(", ")),
+                            ("more", &(affected.len() - STATUS_WARNING_NAMES)),
+                        ],
+                    )
+                } else {
+                    format!("{}: {}", name, affected.join(", "))
+                };
+                Label::update_text(ui, label, &text);
+            }),
+        );
+    }
+}
+
+/// Creates a label counting the lasers that are hot and on standby. It is hidden while
+/// every laser is hot, and shows the progress of the lasers that are spinning up.
+pub fn create_laser_status(ui: &mut Ui, parent: Option<NodeId>) {
+    let label = Label::create(ui, parent, "");
+    ui.set_on_update(
+        label,
+        Rc::new(move |ui, ecs| {
+            let lasers = ecs.world.read_component::<Laser>();
+            let (mut hot, mut standby) = (0, 0);
+            let mut spinning_up = Vec::new();
+            for laser in lasers.join() {
+                match laser.state() {
+                    LaserState::Hot => hot += 1,
+                    LaserState::Standby => standby += 1,
+                    state @ LaserState::SpinningUp(_) => spinning_up
+                        .push(format!("{:.0}%", state.spin_up_progress().unwrap() * 100.0)),
+                }
+            }
+
+            let text = if standby == 0 && spinning_up.is_empty() {
+                String::new()
+            } else if spinning_up.is_empty() {
+                ui.strings
+                    .format("hud.lasers", &[("hot", &hot), ("standby", &standby)])
+            } else {
+                ui.strings.format(
+                    "hud.lasers_spinning_up",
+                    &[
+                        ("hot", &hot),
+                        ("standby", &standby),
+                        ("spinning_up", &spinning_up.join(", ")),
+                    ],
+                )
+            };
+            Label::update_text(ui, label, &text);
+        }),
+    );
+}
+
+/// The amount of bars in the drone signal indicator
+const SIGNAL_BARS: usize = 5;
+/// The signal strength below which the indicator warns that the drone is about to be lost
+const WEAK_SIGNAL: f32 = 0.25;
+const WEAK_SIGNAL_COLOR: Color = Color {
+    r: 1.0,
+    g: 0.3,
+    b: 0.2,
+    a: 1.0,
+};
+
+/// Creates a label showing the signal strength and fuel of the scout drone while it is
+/// remote viewed
+pub fn create_signal_indicator(ui: &mut Ui, parent: Option<NodeId>) {
+    let label = Label::create(ui, parent, "");
+    ui.set_on_update(
+        label,
+        Rc::new(move |ui, ecs| {
+            let drones = ecs.world.read_component::<ScoutDrone>();
+            let drone = ecs
+                .get_resource::<InputManager>()
+                .remote_view
+                .and_then(|drone| drones.get(drone));
+            let text = match drone {
+                Some(drone) => {
+                    let bars = (drone.signal * SIGNAL_BARS as f32).ceil() as usize;
+                    let signal =
+                        format!("[{}{}]", "|".repeat(bars), ".".repeat(SIGNAL_BARS - bars));
+                    ui.strings.format(
+                        "hud.drone_signal",
+                        &[("signal", &signal), ("fuel", &drone.fuel.ceil())],
+                    )
+                }
+                None => String::new(),
+            };
+            let weak = matches!(drone, Some(drone) if drone.signal < WEAK_SIGNAL);
+            Label::set_color(
+                ui,
+                label,
+                if weak {
+                    WEAK_SIGNAL_COLOR
+                } else {
+                    Color::WHITE
+                },
+            );
+            Label::update_text(ui, label, &text);
+        }),
+    );
+}
+
+/// Creates a label showing the hazard that is going on, and how long it lasts. The hazards
+/// that are only announced are shown by their toast instead.
+pub fn create_hazard_indicator(ui: &mut Ui, parent: Option<NodeId>) {
+    let label = Label::create(ui, parent, "");
+    Label::set_color(ui, label, HAZARD_COLOR);
+    ui.set_on_update(
+        label,
+        Rc::new(move |ui, ecs| {
+            let text = match ecs.get_resource::<EventDirector>().current() {
+                Some((kind, HazardPhase::Active(time_left))) => ui.strings.format(
+                    "hud.hazard",
+                    &[
+                        ("hazard", &ui.strings.get(kind.name_key())),
+                        ("time", &time_left.ceil()),
+                    ],
+                ),
+                _ => String::new(),
+            };
+            Label::update_text(ui, label, &text);
+        }),
+    );
+}
+
+/// Creates a panel in the bottom right corner showing the newest messages of the GameLog
+pub fn create_event_log(ui: &mut Ui) {
+    let anchor = layout::WindowAnchor::BottomRight.with_margin(ui, None, Point2::new(8.0, 8.0));
+    let vbox = layout::create_vbox(ui, Some(anchor), true);
+
+    // The newest message is shown at the bottom
+    for age in (0..GameLog::MAX_ENTRIES).rev() {
+        let label = Label::create(ui, Some(vbox), "");
+        ui.set_on_update(
+            label,
+            Rc::new(move |ui, ecs| {
+                let log = ecs.get_resource::<GameLog>();
+                Label::update_text(ui, label, log.get_recent(age).unwrap_or_default());
+            }),
+        );
+    }
+}
+
+/// Creates a panel listing the assets that failed to load and were replaced by placeholders.
+/// Nothing is created if all of the content loaded.
+pub fn create_content_errors(ui: &mut Ui, parent: NodeId, ecs: &ECS) {
+    let errors = ecs.get_resource::<ContentErrors>();
+    if errors.is_empty() {
+        return;
+    }
+
+    let vbox = layout::create_vbox(ui, Some(parent), true);
+    let title = Label::create(ui, Some(vbox), "Some content failed to load:");
+    Label::set_color(ui, title, CONTENT_ERROR_COLOR);
+
+    for (asset, error) in errors.iter() {
+        Label::create(ui, Some(vbox), &format!("{}: {}", asset, error));
+    }
+}
+
+/// The box dragged on screen to select gadgets (see Selection). The node has no size, so it
+/// never takes the clicks.
+struct SelectionBox {
+    corners: Option<(Point2<f32>, Point2<f32>)>,
+}
+
+impl SelectionBox {
+    const FILL_COLOR: Vector4<f32> = Vector4::new(0.4, 1.0, 0.4, 0.15);
+    const EDGE_COLOR: Vector4<f32> = Vector4::new(0.4, 1.0, 0.4, 0.8);
+    const EDGE_WIDTH: f32 = 1.0;
+}
+
+pub fn create_selection_box(ui: &mut Ui) {
+    let node = ui.new_node(
+        None,
+        NodeGeometry {
+            pos: Point2::new(0.0, 0.0),
+            size: Point2::new(0.0, 0.0),
+        },
+        NodeLayout {
+            min_size: Point2::new(0.0, 0.0),
+        },
+        Box::new(SelectionBoxRenderer),
+        Box::new(EmptyNodeHandler),
+        Some(Box::new(SelectionBox { corners: None })),
+    );
+    ui.set_on_update(
+        node,
+        Rc::new(move |ui, ecs| {
+            let mouse_pos = ecs.get_resource::<InputManager>().mouse_pos;
+            let corners = ecs.get_resource::<Selection>().drag_box(mouse_pos);
+            ui.states.get_mut::<SelectionBox>(node).unwrap().corners = corners;
+        }),
+    );
+}
+
+struct SelectionBoxRenderer;
+
+impl NodeRenderer for SelectionBoxRenderer {
+    fn render(
+        &self,
+        ui_batch: &mut UiBatch,
+        ui: &Ui,
+        node: NodeId,
+        _: &NodeGeometry,
+        states: &WidgetStates,
+    ) {
+        let (min, max) = match states.get::<SelectionBox>(node).unwrap().corners {
+            Some(corners) => corners,
+            None => return,
+        };
+        let size = max - min;
+        let edge = SelectionBox::EDGE_WIDTH;
+        let mut rect = |x: f32, y: f32, width: f32, height: f32, color: Vector4<f32>| {
+            ui_batch.draw(Vector4::new(x, y, width, height), ui.assets.white, color);
+        };
+
+        rect(min.x, min.y, size.x, size.y, SelectionBox::FILL_COLOR);
+        rect(min.x, min.y, size.x, edge, SelectionBox::EDGE_COLOR);
+        rect(min.x, max.y - edge, size.x, edge, SelectionBox::EDGE_COLOR);
+        rect(min.x, min.y, edge, size.y, SelectionBox::EDGE_COLOR);
+        rect(max.x - edge, min.y, edge, size.y, SelectionBox::EDGE_COLOR);
+    }
+}
@@ -0,0 +1,65 @@
+use super::{widgets::Button, widgets::Label, *};
+use crate::entity::gameplay::{GameState, GameStats};
+
+/// Creates the overlay shown once the game has been won or lost
+pub fn create_game_over_overlay(ui: &mut Ui, ecs: &ECS) -> NodeId {
+    let anchor = layout::WindowAnchor::Center.new(ui);
+    let vbox = layout::create_box(
+        ui,
+        Some(anchor),
+        false,
+        layout::BoxStyle {
+            spacing: 8.0,
+            padding: 16.0,
+            draw_background: true,
+        },
+    );
+
+    let title = match *ecs.get_resource::<GameState>() {
+        GameState::Won => "game_over.won",
+        _ => "game_over.lost",
+    };
+    Label::create(ui, Some(vbox), title);
+
+    let stats = ecs.get_resource::<GameStats>();
+    let seconds = stats.time_survived as u32;
+    let strings = &ui.strings;
+    let lines = [
+        strings.format(
+            "game_over.asteroids",
+            &[("count", &stats.asteroids_destroyed)],
+        ),
+        strings.format(
+            "game_over.raiders",
+            &[
+                ("destroyed", &stats.raiders_destroyed),
+                ("driven_off", &stats.raiders_driven_off),
+            ],
+        ),
+        strings.format("game_over.items", &[("count", &stats.items_mined)]),
+        strings.format(
+            "game_over.time",
+            &[("time", &format!("{}:{:02}", seconds / 60, seconds % 60))],
+        ),
+    ];
+    for line in &lines {
+        Label::create(ui, Some(vbox), line);
+    }
+
+    if *ecs.get_resource::<GameState>() == GameState::Lost {
+        Button::create(
+            ui,
+            Some(vbox),
+            "game_over.retry_wave",
+            Rc::new(|_, ecs| ecs.retry_wave()),
+        );
+    }
+    Button::create(
+        ui,
+        Some(vbox),
+        "game_over.restart",
+        Rc::new(|_, ecs| ecs.restart()),
+    );
+
+    anchor
+}
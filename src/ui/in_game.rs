@@ -1,11 +1,30 @@
-use super::{widgets::Button, widgets::Label, *};
-use crate::entity::{InputAction, InputManager};
+use super::{
+    calibration, construction, feedback, game_over, minimap::Minimap, profiler, settings,
+    ship_stats, statistics, toolbar, trade, widgets::Button, widgets::Label, *,
+};
+use crate::block::{BlockId, Blocks};
+use crate::entity::{
+    drops::ItemPickups,
+    gameplay::{AsteroidField, GameState},
+    heat::ShipHeat,
+    hud::HudVerbosity,
+    objects::Asteroid,
+    power::ShipPower,
+    projectile,
+    ship::{self, SelectedShip},
+    wear, InputAction, InputManager, ObjectMeshes, Ship,
+};
+use crate::graphics::MeshManager;
+use crate::i18n::Strings;
 use crate::item::{GameItem, Inventory};
+use crate::tech::TechTree;
+use specs::{Join, WorldExt};
+use std::cell::Cell;
 
 // TODO: Create a container with no size so that
 // all of the elements of a scene can be deleted at
 // once
-pub fn create_in_game_ui(ui: &mut Ui) {
+pub fn create_in_game_ui(ui: &mut Ui, ecs: &ECS) {
     let top_left_anchor = layout::WindowAnchor::TopLeft.new(ui);
     let inventory = layout::create_vbox(ui, Some(top_left_anchor), false);
 
@@ -18,22 +37,249 @@ pub fn create_in_game_ui(ui: &mut Ui) {
             .expect(&format!("No texture for item: {:?}", item));
 
         widgets::create_texture_box(ui, Some(hbox), texture);
-        let label = Label::create(ui, Some(hbox), &format!("{:?}: 0", item));
+        let label = Label::create(ui, Some(hbox), "");
         ui.set_on_update(
             label,
             Rc::new(move |ui, ecs| {
                 let inventory = ecs.get_resource::<Inventory>();
-                Label::update_text(
-                    ui,
-                    label,
-                    &format!("{:?}: {}", item, inventory.amount(item)),
-                );
+                let name = ui.strings.get(item.name_key());
+                let amount = inventory.amount(item);
+                let text = match ecs.get_resource::<ItemPickups>().recent(*item) {
+                    Some(picked_up) => ui.strings.format(
+                        "hud.item_picked_up",
+                        &[
+                            ("item", &name),
+                            ("amount", &amount),
+                            ("picked_up", &picked_up),
+                        ],
+                    ),
+                    None => ui
+                        .strings
+                        .format("hud.item", &[("item", &name), ("amount", &amount)]),
+                };
+                Label::update_text(ui, label, &text);
             }),
         );
     }
 
+    let voucher_label = Label::create(ui, Some(inventory), "");
+    ui.set_on_update(
+        voucher_label,
+        Rc::new(move |ui, ecs| {
+            let blocks = ecs.get_resource::<Blocks>();
+            let mut vouchers: Vec<(BlockId, u32)> =
+                ecs.get_resource::<Inventory>().vouchers().collect();
+            vouchers.sort_unstable();
+            let vouchers: Vec<String> = vouchers
+                .iter()
+                .map(|(block, count)| {
+                    let name = ui.strings.get(blocks.get_block(*block).name_key);
+                    format!("{} x{}", name, count)
+                })
+                .collect();
+            let text = if vouchers.is_empty() {
+                String::new()
+            } else {
+                ui.strings
+                    .format("hud.vouchers", &[("vouchers", &vouchers.join(", "))])
+            };
+            Label::update_text(ui, voucher_label, &text);
+        }),
+    );
+
+    // The heat below is the selected ship's. A ship can also be selected by clicking its core.
+    let ship_tabs = layout::create_hbox(ui, Some(inventory), false);
+    Button::create(
+        ui,
+        Some(ship_tabs),
+        "menu.next_ship",
+        Rc::new(|_, ecs| ship::select_next_ship(&ecs.world)),
+    );
+    let ship_label = Label::create(ui, Some(ship_tabs), "");
+    ui.set_on_update(
+        ship_label,
+        Rc::new(move |ui, ecs| {
+            let entities = ecs.world.entities();
+            let ships = ecs.world.read_component::<Ship>();
+            let selected = ecs.get_resource::<SelectedShip>().find(&entities, &ships);
+            let text = match selected {
+                Some((selected, ship)) => {
+                    let index = (&entities, &ships)
+                        .join()
+                        .position(|(entity, _)| entity == selected)
+                        .unwrap_or(0);
+                    let count = (&ships).join().count();
+                    ui.strings.format(
+                        "hud.ship",
+                        &[
+                            ("ship", &ui.strings.get(ship.kind().name_key())),
+                            ("index", &(index + 1)),
+                            ("count", &count),
+                        ],
+                    )
+                }
+                None => ui.strings.get("hud.no_ship").to_string(),
+            };
+            Label::update_text(ui, ship_label, &text);
+        }),
+    );
+
+    let heat_label = Label::create(ui, Some(inventory), "");
+    ui.set_on_update(
+        heat_label,
+        Rc::new(move |ui, ecs| {
+            let blocks = ecs.get_resource::<Blocks>();
+            let ships = ecs.world.read_component::<Ship>();
+            let selected = ecs.get_resource::<SelectedShip>();
+            let heat = match selected.find(&ecs.world.entities(), &ships) {
+                Some((_, ship)) => ship.heat(),
+                None => return,
+            };
+            // The biggest sources of heat, ex. "Laser 6.0/s, Miner 1.2/s"
+            let sources: Vec<String> = heat
+                .sources()
+                .iter()
+                .take(3)
+                .map(|(block, heat)| {
+                    let name = ui.strings.get(blocks.get_block(*block).name_key);
+                    format!("{} {:.1}/s", name, heat)
+                })
+                .collect();
+            let key = if heat.is_overheated() {
+                "hud.heat_overheated"
+            } else {
+                "hud.heat"
+            };
+            let text = ui.strings.format(
+                key,
+                &[
+                    ("heat", &format!("{:.0}", heat.heat())),
+                    ("max", &format!("{:.0}", ShipHeat::MAX)),
+                    ("sources", &sources.join(", ")),
+                    ("cooling", &format!("{:.1}", heat.dissipation())),
+                ],
+            );
+            Label::update_text(ui, heat_label, &text);
+        }),
+    );
+
+    let power_label = Label::create(ui, Some(inventory), "");
+    ui.set_on_update(
+        power_label,
+        Rc::new(move |ui, ecs| {
+            let ships = ecs.world.read_component::<Ship>();
+            let selected = ecs.get_resource::<SelectedShip>();
+            let power = match selected.find(&ecs.world.entities(), &ships) {
+                Some((_, ship)) => ship.power(),
+                None => return,
+            };
+            let key = if power.browned_out_count() > 0 {
+                "hud.power_brownout"
+            } else {
+                "hud.power"
+            };
+            let text = ui.strings.format(
+                key,
+                &[
+                    ("demand", &format!("{:.1}", power.demand())),
+                    ("supply", &format!("{:.1}", power.supply())),
+                    ("meter", &power_meter(power)),
+                    ("count", &power.browned_out_count()),
+                ],
+            );
+            Label::update_text(ui, power_label, &text);
+        }),
+    );
+
+    // Only ships with shield generators have a shield
+    let shield_label = Label::create(ui, Some(inventory), "");
+    ui.set_on_update(
+        shield_label,
+        Rc::new(move |ui, ecs| {
+            let ships = ecs.world.read_component::<Ship>();
+            let selected = ecs.get_resource::<SelectedShip>();
+            let shield = match selected.find(&ecs.world.entities(), &ships) {
+                Some((_, ship)) => ship.shield(),
+                None => return,
+            };
+            let text = if shield.max() > 0.0 {
+                let key = if shield.is_broken() {
+                    "hud.shield_broken"
+                } else {
+                    "hud.shield"
+                };
+                ui.strings.format(
+                    key,
+                    &[
+                        ("points", &format!("{:.0}", shield.points())),
+                        ("max", &format!("{:.0}", shield.max())),
+                    ],
+                )
+            } else {
+                String::new()
+            };
+            Label::update_text(ui, shield_label, &text);
+        }),
+    );
+
+    let lod_label = Label::create(ui, Some(inventory), "Asteroid LODs: 0");
+    ui.set_on_update(
+        lod_label,
+        Rc::new(move |ui, ecs| {
+            let mesh_manager = ecs.get_resource::<MeshManager>();
+            let meshes = ecs.get_resource::<ObjectMeshes>();
+            let counts: Vec<String> = mesh_manager
+                .lod_instance_counts(meshes.asteroid)
+                .iter()
+                .map(|count| count.to_string())
+                .collect();
+            Label::update_text(
+                ui,
+                lod_label,
+                &format!("Asteroid LODs: {}", counts.join(" / ")),
+            );
+        }),
+    );
+
+    let culling_label = Label::create(ui, Some(inventory), "Culling: CPU");
+    ui.set_on_update(
+        culling_label,
+        Rc::new(move |ui, ecs| {
+            let stats = ecs.get_resource::<MeshManager>().culling_stats();
+            Label::update_text(
+                ui,
+                culling_label,
+                &format!(
+                    "Culling ({:?}): {} drawn, {} culled",
+                    stats.path, stats.drawn, stats.culled
+                ),
+            );
+        }),
+    );
+
+    create_block_list(ui, Some(inventory), ecs);
+    feedback::create_damage_numbers(ui);
+    feedback::create_wave_preview_labels(ui);
+    feedback::create_event_log(ui);
+    feedback::create_selection_box(ui);
+    let top_right_anchor =
+        layout::WindowAnchor::TopRight.with_margin(ui, None, Point2::new(8.0, 8.0));
+    let top_right = layout::create_vbox(ui, Some(top_right_anchor), false);
+    Minimap::create(ui, Some(top_right));
+    feedback::create_content_errors(ui, top_right, ecs);
+    ship_stats::create_ship_stats_panel(ui, top_right);
+    construction::create_construction_queue(ui);
+    toolbar::create_block_toolbar(ui, ecs);
+    toolbar::create_radial_picker(ui, ecs);
+    toast::create_achievement_toast(ui);
+    toast::create_hazard_toast(ui);
+    trade::create_trade_panel(ui);
+    profiler::create_profiler_overlay(ui);
+    calibration::create_color_calibration(ui);
+
     let top_anchor = layout::WindowAnchor::TopCenter.new(ui);
-    let action_label = Label::create(ui, Some(top_anchor), "Current Action: None");
+    let top_vbox = layout::create_vbox(ui, Some(top_anchor), false);
+    let action_label = Label::create(ui, Some(top_vbox), "Current Action: None");
     ui.set_on_update(
         action_label,
         Rc::new(move |ui, ecs| {
@@ -48,31 +294,277 @@ pub fn create_in_game_ui(ui: &mut Ui) {
         }),
     );
 
-    let button_stack = layout::create_vbox(ui, None, true);
+    let wave_label = Label::create(ui, Some(top_vbox), "");
+    ui.set_on_update(
+        wave_label,
+        Rc::new(move |ui, ecs| {
+            let fields = ecs.world.read_component::<AsteroidField>();
+            let text = match fields.join().next() {
+                Some(field) => match field.final_wave_remaining() {
+                    Some(remaining) => ui
+                        .strings
+                        .format("hud.final_wave", &[("time", &(remaining as u32))]),
+                    None => ui.strings.format(
+                        "hud.wave",
+                        &[("wave", &field.level), ("max", &AsteroidField::MAX_LEVEL)],
+                    ),
+                },
+                None => String::new(),
+            };
+            Label::update_text(ui, wave_label, &text);
+        }),
+    );
+    feedback::create_status_warnings(ui, Some(top_vbox));
+    feedback::create_hazard_indicator(ui, Some(top_vbox));
+    feedback::create_signal_indicator(ui, Some(top_vbox));
+    feedback::create_laser_status(ui, Some(top_vbox));
+
+    let game_over_overlay: Rc<Cell<Option<NodeId>>> = Rc::new(Cell::new(None));
+    ui.set_on_update(
+        top_vbox,
+        Rc::new(move |ui, ecs| {
+            let playing = *ecs.get_resource::<GameState>() == GameState::Playing;
+            match game_over_overlay.get() {
+                Some(overlay) if playing => {
+                    ui.remove_node(overlay);
+                    game_over_overlay.set(None);
+                }
+                None if !playing => {
+                    game_over_overlay.set(Some(game_over::create_game_over_overlay(ui, ecs)))
+                }
+                _ => {}
+            }
+        }),
+    );
+
+    let hover_label = Label::create(ui, Some(top_vbox), "");
+    ui.set_on_update(
+        hover_label,
+        Rc::new(move |ui, ecs| {
+            // The hovered asteroid is only described when the HUD is not decluttered
+            let hovered = match *ecs.get_resource::<HudVerbosity>() {
+                HudVerbosity::Minimal => None,
+                _ => ecs.get_resource::<InputManager>().hovered,
+            };
+            let asteroids = ecs.world.read_component::<Asteroid>();
+            let text = hovered
+                .and_then(|entity| asteroids.get(entity))
+                .map(|asteroid| asteroid.describe(&ui.strings))
+                .unwrap_or_default();
+            Label::update_text(ui, hover_label, &text);
+        }),
+    );
+
+    let bottom_left_anchor =
+        layout::WindowAnchor::BottomLeft.with_margin(ui, None, Point2::new(8.0, 8.0));
+    let button_stack = layout::create_vbox(ui, Some(bottom_left_anchor), true);
     Button::create(
         ui,
         Some(button_stack),
-        "Start Laser",
+        "menu.start_laser",
         Rc::new(|_, ecs| ecs.get_resource_mut::<InputManager>().action = InputAction::Laser),
     );
     Button::create(
         ui,
         Some(button_stack),
-        "Start Mining",
+        "menu.start_mining",
         Rc::new(|_, ecs| ecs.get_resource_mut::<InputManager>().action = InputAction::Mining),
     );
     Button::create(
         ui,
         Some(button_stack),
-        "Cancel Input",
+        "menu.start_repulsor",
+        Rc::new(|_, ecs| ecs.get_resource_mut::<InputManager>().action = InputAction::Repulsor),
+    );
+    Button::create(
+        ui,
+        Some(button_stack),
+        "menu.start_tractor",
+        Rc::new(|_, ecs| ecs.get_resource_mut::<InputManager>().action = InputAction::Tractor),
+    );
+    Button::create(
+        ui,
+        Some(button_stack),
+        "menu.start_cannon",
+        Rc::new(|_, ecs| ecs.get_resource_mut::<InputManager>().action = InputAction::Cannon),
+    );
+    Button::create(
+        ui,
+        Some(button_stack),
+        "menu.copy_blueprint",
+        Rc::new(|_, ecs| {
+            ecs.get_resource_mut::<InputManager>().action = InputAction::CopyBlueprint
+        }),
+    );
+    Button::create(
+        ui,
+        Some(button_stack),
+        "menu.stamp_blueprint",
+        Rc::new(|_, ecs| {
+            ecs.get_resource_mut::<InputManager>().action = InputAction::StampBlueprint
+        }),
+    );
+    Button::create(
+        ui,
+        Some(button_stack),
+        "menu.export_ship",
+        Rc::new(ShareDialog::export),
+    );
+    Button::create(
+        ui,
+        Some(button_stack),
+        "menu.import_ship",
+        Rc::new(ShareDialog::open),
+    );
+    Button::create(
+        ui,
+        Some(button_stack),
+        "menu.cancel_input",
         Rc::new(|_, ecs| ecs.get_resource_mut::<InputManager>().action = InputAction::None),
     );
     Button::create(
         ui,
         Some(button_stack),
-        "Delete UI",
+        "menu.service_gadgets",
+        Rc::new(|_, ecs| wear::service_worn_gadgets(&ecs.world)),
+    );
+    Button::create(
+        ui,
+        Some(button_stack),
+        "menu.craft_slugs",
+        Rc::new(|_, ecs| projectile::craft_slugs(&ecs.world)),
+    );
+    for node in ecs.get_resource::<TechTree>().nodes() {
+        let name = node.name.clone();
+        let button = Button::create(
+            ui,
+            Some(button_stack),
+            "",
+            Rc::new(move |ui, ecs| {
+                let mut inventory = ecs.get_resource_mut::<Inventory>();
+                if let Err(error) =
+                    ecs.get_resource_mut::<TechTree>()
+                        .purchase(&name, &mut inventory, &ui.strings)
+                {
+                    println!("[Research] {}", error);
+                }
+            }),
+        );
+        // The text is only laid out again when the language changes
+        let name = node.name.clone();
+        let revision = Cell::new(None);
+        ui.set_on_update(
+            button,
+            Rc::new(move |ui, ecs| {
+                if revision.get() == Some(ui.strings.revision()) {
+                    return;
+                }
+                revision.set(Some(ui.strings.revision()));
+                let tech_tree = ecs.get_resource::<TechTree>();
+                let node = match tech_tree.nodes().iter().find(|node| node.name == name) {
+                    Some(node) => node,
+                    None => return,
+                };
+                let text = ui.strings.format(
+                    "menu.research",
+                    &[("tech", &node.display_name(&ui.strings))],
+                );
+                Button::update_text(ui, button, &text);
+            }),
+        );
+    }
+    let statistics_pane: Rc<Cell<Option<NodeId>>> = Rc::new(Cell::new(None));
+    Button::create(
+        ui,
+        Some(button_stack),
+        "menu.statistics",
+        Rc::new(move |ui, _| match statistics_pane.take() {
+            Some(pane) => ui.remove_node(pane),
+            None => statistics_pane.set(Some(statistics::create_statistics_pane(ui))),
+        }),
+    );
+    Button::create(
+        ui,
+        Some(button_stack),
+        "menu.settings",
+        Rc::new(settings::SettingsMenu::toggle),
+    );
+    Button::create(
+        ui,
+        Some(button_stack),
+        "menu.delete_ui",
         Rc::new(move |ui, _| {
             ui.remove_node(button_stack);
         }),
     );
 }
+
+/// Lists the blocks that can be built. Locked blocks are greyed out and show what unlocks them.
+fn create_block_list(ui: &mut Ui, parent: Option<NodeId>, ecs: &ECS) {
+    let blocks = ecs.get_resource::<Blocks>();
+
+    for block in blocks.iter() {
+        let block_id = block.id;
+        let name_key = block.name_key;
+        let label = Label::create(ui, parent, name_key);
+        ui.set_on_update(
+            label,
+            Rc::new(move |ui, ecs| {
+                let tech_tree = ecs.get_resource::<TechTree>();
+                let name = ui.strings.get(name_key);
+                match tech_tree.required_node(block_id) {
+                    Some(node) => {
+                        let text = format!(
+                            "{} - {} ({})",
+                            name,
+                            node.display_name(&ui.strings),
+                            node.describe_cost(&ui.strings)
+                        );
+                        Label::update_text(ui, label, &text);
+                        Label::set_color(ui, label, Color::GRAY);
+                    }
+                    None => {
+                        let text = name.to_string();
+                        Label::update_text(ui, label, &text);
+                        Label::set_color(ui, label, Color::WHITE);
+                    }
+                }
+            }),
+        );
+        ui.set_tooltip(
+            label,
+            Rc::new(move |ecs| {
+                let tech_tree = ecs.get_resource::<TechTree>();
+                let strings = ecs.get_resource::<Strings>();
+                let name = strings.get(name_key);
+                match tech_tree.required_node(block_id) {
+                    Some(node) => strings.format(
+                        "tooltip.block_locked",
+                        &[
+                            ("block", &name),
+                            ("tech", &node.display_name(&strings)),
+                            ("cost", &node.describe_cost(&strings)),
+                        ],
+                    ),
+                    None => strings.format("tooltip.block_unlocked", &[("block", &name)]),
+                }
+            }),
+        );
+    }
+}
+
+/// Ex. "[||||||||..]" for a ship that uses 80% of its power. Demand over the supply fills the
+/// meter with "!" instead.
+fn power_meter(power: &ShipPower) -> String {
+    const WIDTH: usize = 10;
+    let used = if power.supply() > 0.0 {
+        power.demand() / power.supply()
+    } else if power.demand() > 0.0 {
+        f32::INFINITY
+    } else {
+        0.0
+    };
+    let filled = ((used.min(1.0) * WIDTH as f32).round() as usize).min(WIDTH);
+    let mark = if used > 1.0 { "!" } else { "|" };
+    format!("[{}{}]", mark.repeat(filled), ".".repeat(WIDTH - filled))
+}
@@ -1,6 +1,7 @@
 use super::*;
 use cgmath::Point2;
 
+#[derive(Clone, Copy)]
 enum BoxLayoutManager {
     HBox,
     VBox,
@@ -42,7 +43,33 @@ impl BoxLayoutManager {
     }
 }
 
-impl NodeHandler for BoxLayoutManager {
+/// The spacing between the children of a box, and the padding around them
+#[derive(Clone, Copy)]
+pub struct BoxStyle {
+    pub spacing: f32,
+    pub padding: f32,
+    pub draw_background: bool,
+}
+
+impl BoxStyle {
+    const DEFAULT_SPACING: f32 = 5.0;
+
+    fn new(draw_background: bool) -> Self {
+        Self {
+            spacing: BoxStyle::DEFAULT_SPACING,
+            padding: BoxStyle::DEFAULT_SPACING,
+            draw_background,
+        }
+    }
+}
+
+/// Stacks its children from the bottom left, and sizes itself to fit them
+struct BoxLayout {
+    manager: BoxLayoutManager,
+    style: BoxStyle,
+}
+
+impl NodeHandler for BoxLayout {
     fn layout<'a>(
         &self,
         layout_manager: &'a LayoutManager,
@@ -52,39 +79,49 @@ impl NodeHandler for BoxLayoutManager {
         layouts: &mut WidgetLayouts,
         states: &mut WidgetStates,
     ) {
-        let spacing: f32 = 5.0;
-        let mut major_axis = spacing;
+        let manager = self.manager;
+        let BoxStyle {
+            spacing, padding, ..
+        } = self.style;
+        let mut major_axis = padding * 2.0 - spacing;
         let mut minor_axis: f32 = 0.0;
 
         layout_manager.layout_all(children, geometries, layouts, states);
 
         for child in children {
             let min_size = layouts.get(child.index()).unwrap().min_size;
-            minor_axis = minor_axis.max(self.minor_axis(&min_size));
-            major_axis += self.major_axis(&min_size) + spacing;
+            minor_axis = minor_axis.max(manager.minor_axis(&min_size));
+            major_axis += manager.major_axis(&min_size) + spacing;
         }
+        major_axis = major_axis.max(padding * 2.0);
 
         let mut pos = geometries[node.arena_index()].pos;
-        pos.x += spacing;
-        pos.y += spacing;
+        pos.x += padding;
+        pos.y += padding;
         for child in children {
             let geometry = &mut geometries[child.arena_index()];
-            self.set_minor_axis(&mut geometry.pos, self.minor_axis(&pos));
-            self.set_major_axis(&mut geometry.pos, self.major_axis(&pos));
-            self.set_minor_axis(&mut geometry.size, minor_axis);
+            manager.set_minor_axis(&mut geometry.size, minor_axis);
+            let major_size = manager.major_axis(&geometry.size);
 
-            let major_pos = self.major_axis(&geometry.size) + self.major_axis(&pos) + spacing;
-            self.set_major_axis(&mut pos, major_pos);
+            layout_manager.move_node(*child, pos, geometries);
+            let major_pos = manager.major_axis(&pos) + major_size + spacing;
+            manager.set_major_axis(&mut pos, major_pos);
         }
 
-        minor_axis += spacing * 2.0;
-        let size = self.major_minor_to_xy(major_axis, minor_axis);
+        minor_axis += padding * 2.0;
+        let size = manager.major_minor_to_xy(major_axis, minor_axis);
         layouts[node.index()].min_size = size;
         geometries[node.arena_index()].size = size;
     }
 }
 
-pub fn create_vbox(ui: &mut Ui, parent: Option<NodeId>, draw_background: bool) -> NodeId {
+/// Creates a box with the given style, that stacks its children horizontally (or vertically)
+pub fn create_box(
+    ui: &mut Ui,
+    parent: Option<NodeId>,
+    horizontal: bool,
+    style: BoxStyle,
+) -> NodeId {
     ui.new_node(
         parent,
         NodeGeometry {
@@ -92,83 +129,168 @@ pub fn create_vbox(ui: &mut Ui, parent: Option<NodeId>, draw_background: bool) -
             size: Point2::new(0.0, 0.0),
         },
         NodeLayout::default(),
-        if draw_background {
+        if style.draw_background {
             new_ninepatch_renderer(ui.assets.pane)
         } else {
             Box::new(EmptyRenderer)
         },
-        Box::new(BoxLayoutManager::VBox),
+        Box::new(BoxLayout {
+            manager: if horizontal {
+                BoxLayoutManager::HBox
+            } else {
+                BoxLayoutManager::VBox
+            },
+            style,
+        }),
         None,
     )
 }
 
+pub fn create_vbox(ui: &mut Ui, parent: Option<NodeId>, draw_background: bool) -> NodeId {
+    create_box(ui, parent, false, BoxStyle::new(draw_background))
+}
+
 pub fn create_hbox(ui: &mut Ui, parent: Option<NodeId>, draw_background: bool) -> NodeId {
-    ui.new_node(
-        parent,
-        NodeGeometry {
-            pos: Point2::new(0.0, 0.0),
-            size: Point2::new(0.0, 0.0),
-        },
-        NodeLayout::default(),
-        if draw_background {
-            new_ninepatch_renderer(ui.assets.pane)
-        } else {
-            Box::new(EmptyRenderer)
-        },
-        Box::new(BoxLayoutManager::HBox),
-        None,
-    )
+    create_box(ui, parent, true, BoxStyle::new(draw_background))
 }
 
+/// Where the children of an anchor are placed, within its parent (or the window if it
+/// has no parent)
+#[derive(Clone, Copy)]
 pub enum WindowAnchor {
-    // TODO: Add the rest of the variants
     TopLeft,
     TopCenter,
+    TopRight,
+    CenterLeft,
+    Center,
+    CenterRight,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
 }
 
 impl WindowAnchor {
+    /// Creates an anchor to the window
     pub fn new(self, ui: &mut Ui) -> NodeId {
+        self.with_margin(ui, None, Point2::new(0.0, 0.0))
+    }
+
+    /// Creates an anchor, whose children are moved `margin` pixels away from the
+    /// edges they are anchored to. The parent should not be a box, since boxes
+    /// are sized to fit their children.
+    pub fn with_margin(self, ui: &mut Ui, parent: Option<NodeId>, margin: Point2<f32>) -> NodeId {
         ui.new_node(
-            None,
+            parent,
             NodeGeometry {
                 pos: Point2::new(0.0, 0.0),
                 size: Point2::new(0.0, 0.0),
             },
             NodeLayout::default(),
             Box::new(EmptyRenderer),
-            Box::new(self),
+            Box::new(Anchor {
+                anchor: self,
+                margin,
+            }),
             None,
         )
     }
+
+    /// Returns where (0 to 1) along the x and y axis the children are placed
+    fn alignment(self) -> (f32, f32) {
+        match self {
+            Self::TopLeft => (0.0, 1.0),
+            Self::TopCenter => (0.5, 1.0),
+            Self::TopRight => (1.0, 1.0),
+            Self::CenterLeft => (0.0, 0.5),
+            Self::Center => (0.5, 0.5),
+            Self::CenterRight => (1.0, 0.5),
+            Self::BottomLeft => (0.0, 0.0),
+            Self::BottomCenter => (0.5, 0.0),
+            Self::BottomRight => (1.0, 0.0),
+        }
+    }
+}
+
+struct Anchor {
+    anchor: WindowAnchor,
+    margin: Point2<f32>,
 }
 
-impl NodeHandler for WindowAnchor {
+impl NodeHandler for Anchor {
     fn layout<'a>(
         &self,
         layout_manager: &'a LayoutManager,
-        _: NodeId,
+        node: NodeId,
         children: &[NodeId],
         geometries: &mut WidgetGeometries,
         layouts: &mut WidgetLayouts,
         states: &mut WidgetStates,
     ) {
-        layout_manager.layout_all(children, geometries, layouts, states);
-        let mut layout = |func: fn(&mut NodeGeometry, Point2<f32>)| {
-            for child in children {
-                let geometry = &mut geometries[child.arena_index()];
-                (func)(geometry, layout_manager.window_size);
+        // The anchor covers its parent, so it moves with it
+        let (parent_pos, parent_size) = match layout_manager.parent(node) {
+            Some(parent) => {
+                let geometry = &geometries[parent.arena_index()];
+                (geometry.pos, geometry.size)
             }
+            None => (Point2::new(0.0, 0.0), layout_manager.window_size),
         };
+        let geometry = &mut geometries[node.arena_index()];
+        geometry.pos = parent_pos;
+        geometry.size = parent_size;
 
-        match self {
-            Self::TopCenter => layout(|geometry, window_size| {
-                geometry.pos.x = (window_size.x / 2.0) - (geometry.size.x / 2.0);
-                geometry.pos.y = window_size.y - geometry.size.y;
-            }),
-            Self::TopLeft => layout(|geometry, window_size| {
-                geometry.pos.x = 0.0;
-                geometry.pos.y = window_size.y - geometry.size.y;
-            }),
+        layout_manager.layout_all(children, geometries, layouts, states);
+
+        let (align_x, align_y) = self.anchor.alignment();
+        // Children are moved away from the edges they are anchored to
+        let offset = |align: f32, margin: f32| {
+            if align == 0.0 {
+                margin
+            } else if align == 1.0 {
+                -margin
+            } else {
+                0.0
+            }
+        };
+        for child in children {
+            let size = geometries[child.arena_index()].size;
+            let pos = Point2::new(
+                parent_pos.x + (parent_size.x - size.x) * align_x + offset(align_x, self.margin.x),
+                parent_pos.y + (parent_size.y - size.y) * align_y + offset(align_y, self.margin.y),
+            );
+            layout_manager.move_node(*child, pos, geometries);
         }
     }
 }
+
+/// Creates an anchor to the window, which dims everything behind it. It is opened as the
+/// modal node, so only its children can be clicked until it is removed.
+pub fn create_modal(ui: &mut Ui, anchor: WindowAnchor) -> NodeId {
+    const BACKDROP: Color = Color {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 0.5,
+    };
+
+    let node = ui.new_node(
+        None,
+        NodeGeometry {
+            pos: Point2::new(0.0, 0.0),
+            size: Point2::new(0.0, 0.0),
+        },
+        NodeLayout::default(),
+        Box::new(SpriteRenderer {
+            texture: ui.assets.white,
+            color: BACKDROP,
+            offset: Point2::new(0.0, 0.0),
+            scale: Point2::new(1.0, 1.0),
+        }),
+        Box::new(Anchor {
+            anchor,
+            margin: Point2::new(0.0, 0.0),
+        }),
+        None,
+    );
+    ui.modal = Some(node);
+    node
+}
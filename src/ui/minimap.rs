@@ -0,0 +1,223 @@
+use super::*;
+use crate::entity::{
+    faction::Faction, gameplay::AsteroidField, gameplay::IncomingThreat, objects::Asteroid,
+    ship::SelectedShip, BlockEntity, Ship, Transform,
+};
+use crate::graphics::{Camera, Projection};
+use specs::{Join, WorldExt};
+use std::f32::consts::FRAC_PI_2;
+
+/// A top-down schematic of the ship and the asteroids around it, so asteroids coming from
+/// behind the camera can be seen. Clicking it turns the camera to face that direction.
+pub struct Minimap {
+    /// The world position at the center of the map
+    center: Point2<f32>,
+    /// The world units from the center to the edge of the map
+    range: f32,
+    /// The positions of the ship's blocks
+    blocks: Vec<Point2<f32>>,
+    /// The positions of the asteroids, and whether they are going to hit the ship
+    asteroids: Vec<(Point2<f32>, bool)>,
+    camera: Point2<f32>,
+    yaw: f32,
+    /// Half of the camera's horizontal field of view (in radians)
+    half_fov: f32,
+}
+
+impl Minimap {
+    /// The width and height of the map in pixels
+    const SIZE: f32 = 160.0;
+    /// The pixels between the edge of the map and the area that is drawn on
+    const PADDING: f32 = 8.0;
+    const ASTEROID_SIZE: f32 = 4.0;
+    const ASTEROID_COLOR: Vector4<f32> = Vector4::new(0.7, 0.7, 0.7, 1.0);
+    const THREAT_COLOR: Vector4<f32> = Vector4::new(1.0, 0.25, 0.2, 1.0);
+    const CAMERA_COLOR: Vector4<f32> = Vector4::new(1.0, 1.0, 1.0, 0.8);
+    /// The wedge showing the camera's view is drawn as two lines of dots
+    const WEDGE_DOTS: usize = 5;
+    const WEDGE_SPACING: f32 = 4.0;
+
+    pub fn create(ui: &mut Ui, parent: Option<NodeId>) -> NodeId {
+        let size = Point2::new(Minimap::SIZE, Minimap::SIZE);
+        let node = ui.new_node(
+            parent,
+            NodeGeometry {
+                pos: Point2::new(0.0, 0.0),
+                size,
+            },
+            NodeLayout { min_size: size },
+            Box::new(MinimapRenderer),
+            Box::new(MinimapHandler),
+            Some(Box::new(Minimap {
+                center: Point2::new(0.0, 0.0),
+                range: 1.0,
+                blocks: Vec::new(),
+                asteroids: Vec::new(),
+                camera: Point2::new(0.0, 0.0),
+                yaw: 0.0,
+                half_fov: 0.0,
+            })),
+        );
+        ui.set_on_update(node, Rc::new(move |ui, ecs| Minimap::update(ui, ecs, node)));
+
+        node
+    }
+
+    /// Copies what is shown from the world, which is done every frame
+    fn update(ui: &mut Ui, ecs: &mut ECS, node: NodeId) {
+        let minimap = ui.states.get_mut::<Minimap>(node).unwrap();
+        let world = &ecs.world;
+        let transforms = world.read_component::<Transform>();
+        let position =
+            |transform: &Transform| Point2::new(transform.position.x, transform.position.y);
+
+        // Centered on the selected ship
+        let ships = world.read_component::<Ship>();
+        let bounds = world
+            .fetch::<SelectedShip>()
+            .find(&world.entities(), &ships)
+            .and_then(|(_, ship)| ship.bounds());
+        minimap.center = bounds.map_or(Point2::new(0.0, 0.0), |bounds| {
+            let center = (bounds.min + bounds.max) / 2.0;
+            Point2::new(center.x, center.y)
+        });
+        // The whole asteroid field fits, since asteroids are despawned outside of it
+        if let Some(field) = world.read_component::<AsteroidField>().join().next() {
+            minimap.range = field.x_range;
+        }
+
+        minimap.blocks.clear();
+        let block_entities = world.read_component::<BlockEntity>();
+        minimap.blocks.extend(
+            (&transforms, &block_entities)
+                .join()
+                .map(|(transform, _)| position(transform)),
+        );
+
+        minimap.asteroids.clear();
+        let asteroids = world.read_component::<Asteroid>();
+        let threats = world.read_component::<IncomingThreat>();
+        minimap.asteroids.extend(
+            (&transforms, &asteroids, threats.maybe())
+                .join()
+                .map(|(transform, _, threat)| (position(transform), threat.is_some())),
+        );
+
+        let camera = ecs.get_resource::<Camera>();
+        minimap.camera = Point2::new(camera.position.x, camera.position.y);
+        minimap.yaw = camera.yaw;
+        let half_height = (camera.fov.to_radians() / 2.0).tan();
+        minimap.half_fov = (half_height * camera.aspect).atan();
+    }
+
+    /// Converts a world position to pixels from the center of the map
+    fn to_map(&self, pos: Point2<f32>) -> Vector2<f32> {
+        (pos - self.center) * self.scale()
+    }
+
+    /// The pixels per world unit
+    fn scale(&self) -> f32 {
+        (Minimap::SIZE / 2.0 - Minimap::PADDING) / self.range
+    }
+}
+
+struct MinimapRenderer;
+
+impl NodeRenderer for MinimapRenderer {
+    fn render(
+        &self,
+        ui_batch: &mut UiBatch,
+        ui: &Ui,
+        node: NodeId,
+        geometry: &NodeGeometry,
+        states: &WidgetStates,
+    ) {
+        new_ninepatch_renderer(ui.assets.pane).render(ui_batch, ui, node, geometry, states);
+
+        let minimap = states.get::<Minimap>(node).unwrap();
+        let half_size = geometry.size.x / 2.0;
+        let center = Vector2::new(geometry.pos.x + half_size, geometry.pos.y + half_size);
+        let limit = half_size - Minimap::PADDING;
+        // Draws a square centered on the offset from the center of the map
+        let mut square = |offset: Vector2<f32>, size: f32, color: Vector4<f32>| {
+            if offset.x.abs() > limit || offset.y.abs() > limit {
+                return;
+            }
+            let corner = center + offset - Vector2::new(size, size) / 2.0;
+            ui_batch.draw(
+                Vector4::new(corner.x, corner.y, size, size),
+                ui.assets.white,
+                color,
+            );
+        };
+
+        let ship_color = Faction::Player.color().extend(1.0);
+        let block_size = minimap.scale().max(1.0);
+        for block in &minimap.blocks {
+            square(minimap.to_map(*block), block_size, ship_color);
+        }
+
+        for (asteroid, threat) in &minimap.asteroids {
+            let color = if *threat {
+                Minimap::THREAT_COLOR
+            } else {
+                Minimap::ASTEROID_COLOR
+            };
+            square(minimap.to_map(*asteroid), Minimap::ASTEROID_SIZE, color);
+        }
+
+        let camera = minimap.to_map(minimap.camera);
+        square(camera, 3.0, Minimap::CAMERA_COLOR);
+        for side in &[-minimap.half_fov, minimap.half_fov] {
+            let (sin, cos) = (minimap.yaw + side).sin_cos();
+            for dot in 1..=Minimap::WEDGE_DOTS {
+                let distance = dot as f32 * Minimap::WEDGE_SPACING;
+                square(
+                    camera + Vector2::new(cos, sin) * distance,
+                    2.0,
+                    Minimap::CAMERA_COLOR,
+                );
+            }
+        }
+    }
+}
+
+struct MinimapHandler;
+
+impl NodeHandler for MinimapHandler {
+    fn on_click(
+        &self,
+        _: event::MouseButton,
+        click_state: event::ElementState,
+        pt: Point2<f32>,
+        _: NodeId,
+        geometry: &mut NodeGeometry,
+        _: &mut WidgetStates,
+        events: &mut EventQueue,
+    ) -> bool {
+        if click_state != event::ElementState::Pressed {
+            return false;
+        }
+
+        // The map is not rotated, so the direction on it is the direction in the world
+        let half_size = geometry.size.x / 2.0;
+        let direction = Vector2::new(
+            pt.x - geometry.pos.x - half_size,
+            pt.y - geometry.pos.y - half_size,
+        );
+        if direction == Vector2::new(0.0, 0.0) {
+            return true;
+        }
+        events.add(Rc::new(move |_, ecs| {
+            let mut camera = ecs.get_resource_mut::<Camera>();
+            let yaw = direction.y.atan2(direction.x);
+            // The build view stays turned to a grid axis
+            camera.yaw = match camera.projection {
+                Projection::Orthographic { .. } => (yaw / FRAC_PI_2).round() * FRAC_PI_2,
+                Projection::Perspective => yaw,
+            };
+        }));
+
+        true
+    }
+}
@@ -1,7 +1,10 @@
-use cgmath::{Point2, Vector4};
+use cgmath::{Point2, Vector2, Vector4};
 
+use crate::assets::AssetError;
+use crate::block::BlockId;
 use crate::entity::ECS;
 use crate::graphics::{FontGlyph, FontMap, NinePatch, TextureAtlas, TextureRegion2D, UiBatch};
+use crate::i18n::Strings;
 use crate::item::{self, GameItem};
 use generational_arena::Arena;
 use std::any::Any;
@@ -9,10 +12,32 @@ use std::collections::HashMap;
 use std::rc::Rc;
 use winit::event;
 
+mod calibration;
+mod console;
+mod construction;
+mod feedback;
+mod game_over;
 mod in_game;
 mod layout;
+mod minimap;
+mod palette;
+mod profiler;
+mod settings;
+mod share;
+mod ship_stats;
+mod statistics;
+mod toast;
+mod toolbar;
+mod tooltip;
+mod trade;
 mod widgets;
 
+pub use console::DebugConsole;
+pub use palette::CommandPalette;
+pub use settings::SettingsMenu;
+pub use share::ShareDialog;
+use tooltip::{TooltipState, TooltipText};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct NodeId(generational_arena::Index);
 
@@ -99,14 +124,28 @@ pub struct Ui {
     handlers: WidgetHandlers,
     renderers: Vec<Box<dyn NodeRenderer>>,
     updaters: Vec<Option<EventHandler>>,
+    tooltips: Vec<Option<TooltipText>>,
+    tooltip: TooltipState,
     states: WidgetStates,
     assets: UiAssets,
     mouse_focus: Option<NodeId>,
+    /// Whether each node can get the keyboard focus (ex. buttons)
+    focusable: Vec<bool>,
+    /// The node with the keyboard focus, which gets the key presses (ex. a slider). It is
+    /// moved with Tab and the arrow keys, or by clicking a focusable node.
+    key_focus: Option<NodeId>,
+    /// While a modal node is open, only it and its children can be clicked
+    modal: Option<NodeId>,
+    settings: settings::SettingsMenu,
+    share: share::ShareDialog,
     event_queue: EventQueue,
+    /// The text that the widgets were created with. The widgets are updated when the text in
+    /// the ECS changes (ex. when the language is changed).
+    strings: Strings,
 }
 
 impl Ui {
-    pub fn new(assets: UiAssets) -> Self {
+    pub fn new(assets: UiAssets, ecs: &ECS) -> Self {
         let mut ui = Self {
             geometries: Arena::new(),
             layouts: WidgetLayouts::new(),
@@ -115,13 +154,21 @@ impl Ui {
             renderers: Vec::new(),
             handlers: Vec::new(),
             updaters: Vec::new(),
+            tooltips: Vec::new(),
+            tooltip: TooltipState::new(),
             states: WidgetStates { states: Vec::new() },
             mouse_focus: None,
+            focusable: Vec::new(),
+            key_focus: None,
+            modal: None,
+            settings: settings::SettingsMenu::default(),
+            share: share::ShareDialog::default(),
             event_queue: EventQueue(Vec::new()),
+            strings: (*ecs.get_resource::<Strings>()).clone(),
             assets,
         };
 
-        in_game::create_in_game_ui(&mut ui);
+        in_game::create_in_game_ui(&mut ui, ecs);
 
         ui
     }
@@ -142,6 +189,8 @@ impl Ui {
         insert_or_replace(&mut self.renderers, id, renderer);
         insert_or_replace(&mut self.handlers, id, handler);
         insert_or_replace(&mut self.updaters, id, None);
+        insert_or_replace(&mut self.tooltips, id, None);
+        insert_or_replace(&mut self.focusable, id, false);
         insert_or_replace(&mut self.states.states, id, state);
 
         if let Some(parent) = parent {
@@ -168,6 +217,8 @@ impl Ui {
         self.renderers[id.index()] = Box::new(EmptyRenderer);
         self.handlers[id.index()] = Box::new(EmptyNodeHandler);
         self.updaters[id.index()] = None;
+        self.tooltips[id.index()] = None;
+        self.focusable[id.index()] = false;
         self.states.states[id.index()] = None;
 
         std::mem::replace(&mut self.children[id.index()], Vec::with_capacity(0))
@@ -180,30 +231,44 @@ impl Ui {
 
         fn render_all(sprite_batch: &mut UiBatch, ui: &Ui, nodes: &[NodeId]) {
             for node in nodes {
-                ui.renderers[node.index()].render(
-                    sprite_batch,
-                    &ui,
-                    *node,
-                    &ui.geometries[node.arena_index()],
-                    &ui.states,
-                );
+                let geometry = &ui.geometries[node.arena_index()];
+                ui.renderers[node.index()].render(sprite_batch, ui, *node, geometry, &ui.states);
 
                 render_all(sprite_batch, ui, &ui.children[node.index()]);
+                if ui.focus() == Some(*node) {
+                    FocusRing.render(sprite_batch, ui, *node, geometry, &ui.states);
+                }
             }
         };
 
-        render_all(sprite_batch, &self, &self.find_parentless_nodes());
+        // The modal node is drawn over the rest of the UI, and the tooltip over everything
+        let modal = self.modal();
+        let tooltip = self.tooltip.panel();
+        let mut parentless = self.find_parentless_nodes();
+        parentless.retain(|node| Some(*node) != tooltip && Some(*node) != modal);
+        parentless.extend(modal);
+        parentless.extend(tooltip);
+        render_all(sprite_batch, self, &parentless);
     }
 
+    /// Returns whether the click was used by the UI. Every click is used while a modal
+    /// node is open, so nothing under it can be clicked.
     pub fn on_click(
         &mut self,
         button: event::MouseButton,
         state: event::ElementState,
         pt: Point2<f32>,
     ) -> bool {
+        self.tooltip.mouse_held = state == event::ElementState::Pressed;
+
+        let modal = self.modal();
         let widgets: Vec<generational_arena::Index> = self
             .geometries
             .iter()
+            .filter(|(index, _)| match modal {
+                Some(modal) => self.is_inside(NodeId(*index), modal),
+                None => true,
+            })
             .filter(|(_, geometry)| {
                 pt.x > geometry.pos.x
                     && pt.y > geometry.pos.y
@@ -243,15 +308,217 @@ impl Ui {
                 }
             }
         }
+        // Clicking anything else (ex. the world) drops the keyboard focus
+        if state == event::ElementState::Pressed {
+            self.set_focus(new_focus.filter(|focus| self.focusable[focus.index()]));
+        }
+
+        new_focus.is_some() || modal.is_some()
+    }
+
+    /// Passes the cursor's position to the node that is held down (ex. to drag a slider)
+    pub fn on_mouse_moved(&mut self, pt: Point2<f32>) {
+        if let Some(focus) = self.mouse_focus.filter(|focus| self.is_valid_id(*focus)) {
+            self.handlers[focus.index()].on_mouse_drag(
+                pt,
+                focus,
+                &mut self.geometries[focus.arena_index()],
+                &mut self.states,
+                &mut self.event_queue,
+            );
+        }
+    }
+
+    /// Passes a pressed key to the focused node, and moves the focus with the navigation
+    /// keys if the node didn't use it: Tab and Shift+Tab go through every focusable node,
+    /// the arrow keys go to the node's neighbours, Enter and Space click the node, and
+    /// Escape drops the focus. Without a focused node the keys are only used while a modal
+    /// node is open, so they still control the game. Returns whether the key was used.
+    pub fn on_key(&mut self, key: event::VirtualKeyCode, shift: bool) -> bool {
+        use event::VirtualKeyCode::*;
+
+        let focus = self.focus();
+        if focus.is_none() && self.modal().is_none() {
+            return false;
+        }
+        if let Some(focus) = focus {
+            let used = self.handlers[focus.index()].on_key(
+                key,
+                focus,
+                &self.geometries[focus.arena_index()],
+                &mut self.states,
+                &mut self.event_queue,
+            );
+            if used {
+                return true;
+            }
+        }
+
+        match key {
+            Tab => {
+                let order = self.focus_order();
+                let next = match focus.and_then(|focus| order.iter().position(|n| *n == focus)) {
+                    Some(index) if shift => order.get((index + order.len() - 1) % order.len()),
+                    Some(index) => order.get((index + 1) % order.len()),
+                    None if shift => order.last(),
+                    None => order.first(),
+                };
+                self.set_focus(next.copied());
+                true
+            }
+            Left | Up | Right | Down => {
+                match focus.and_then(|focus| self.neighbour(focus, matches!(key, Right | Down))) {
+                    Some(neighbour) => {
+                        self.set_focus(Some(neighbour));
+                        true
+                    }
+                    None => false,
+                }
+            }
+            Return | NumpadEnter | Space => match focus {
+                Some(focus) => {
+                    self.activate(focus);
+                    true
+                }
+                None => false,
+            },
+            Escape if focus.is_some() => {
+                self.set_focus(None);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The node with the keyboard focus. Nodes behind the modal node can't have it.
+    pub fn focus(&self) -> Option<NodeId> {
+        self.key_focus.filter(|focus| {
+            self.is_valid_id(*focus)
+                && match self.modal() {
+                    Some(modal) => self.is_inside(*focus, modal),
+                    None => true,
+                }
+        })
+    }
+
+    /// Lets the node get the keyboard focus
+    pub fn set_focusable(&mut self, node: NodeId, focusable: bool) {
+        self.check_id(node, "Invalid node.");
 
-        return new_focus.is_some();
+        self.focusable[node.index()] = focusable;
+    }
+
+    fn set_focus(&mut self, focus: Option<NodeId>) {
+        let previous = self.focus();
+        self.key_focus = focus;
+        if previous == focus {
+            return;
+        }
+        if let Some(previous) = previous {
+            self.handlers[previous.index()].on_focus_lost(previous, &mut self.states);
+        }
+        if let Some(focus) = focus {
+            self.handlers[focus.index()].on_focus_gained(focus, &mut self.states);
+        }
+    }
+
+    /// The focusable nodes in the order they are laid out, which is the order that Tab
+    /// goes through. While a modal node is open, only its children are included.
+    fn focus_order(&self) -> Vec<NodeId> {
+        fn collect(ui: &Ui, nodes: &[NodeId], order: &mut Vec<NodeId>) {
+            for node in nodes {
+                if ui.focusable[node.index()] {
+                    order.push(*node);
+                }
+                collect(ui, &ui.children[node.index()], order);
+            }
+        }
+
+        let roots = match self.modal() {
+            Some(modal) => vec![modal],
+            None => self.find_parentless_nodes(),
+        };
+        let mut order = Vec::new();
+        collect(self, &roots, &mut order);
+        order
+    }
+
+    /// The focusable node before or after the node, in the closest container that has
+    /// other focusable nodes (ex. the next button in a row of buttons)
+    fn neighbour(&self, node: NodeId, forward: bool) -> Option<NodeId> {
+        let order = self.focus_order();
+        let mut container = self.parents[node.index()];
+        while let Some(parent) = container {
+            let siblings: Vec<NodeId> = order
+                .iter()
+                .copied()
+                .filter(|sibling| self.is_inside(*sibling, parent))
+                .collect();
+            if siblings.len() > 1 {
+                let index = siblings.iter().position(|sibling| *sibling == node)?;
+                return if forward {
+                    siblings.get(index + 1).copied()
+                } else {
+                    index.checked_sub(1).map(|index| siblings[index])
+                };
+            }
+            container = self.parents[parent.index()];
+        }
+        None
+    }
+
+    /// Clicks the middle of the node, so it does what it does when it is clicked (ex. a
+    /// button fires its action)
+    fn activate(&mut self, node: NodeId) {
+        let geometry = &mut self.geometries[node.arena_index()];
+        let center = Point2::new(
+            geometry.pos.x + geometry.size.x / 2.0,
+            geometry.pos.y + geometry.size.y / 2.0,
+        );
+        for state in [event::ElementState::Pressed, event::ElementState::Released].iter() {
+            self.handlers[node.index()].on_click(
+                event::MouseButton::Left,
+                *state,
+                center,
+                node,
+                geometry,
+                &mut self.states,
+                &mut self.event_queue,
+            );
+        }
+    }
+
+    /// The open modal node. It is closed by removing it.
+    pub fn modal(&self) -> Option<NodeId> {
+        self.modal.filter(|modal| self.is_valid_id(*modal))
+    }
+
+    /// Whether the node is the ancestor, or one of its children
+    fn is_inside(&self, mut node: NodeId, ancestor: NodeId) -> bool {
+        loop {
+            if node == ancestor {
+                return true;
+            }
+            match self.parents[node.index()] {
+                Some(parent) => node = parent,
+                None => return false,
+            }
+        }
     }
 
     pub fn update(&mut self, ecs: &mut ECS) {
+        let revision = ecs.get_resource::<Strings>().revision();
+        if revision != self.strings.revision() {
+            self.strings = (*ecs.get_resource::<Strings>()).clone();
+            widgets::relocalize(self);
+        }
+        tooltip::update_tooltip(self, ecs);
+
         let window_size = ecs.get_resource::<crate::entity::WindowSize>().as_point();
         let parentless = self.find_parentless_nodes();
         let layout_manager = LayoutManager {
             window_size,
+            parents: &self.parents,
             children: &self.children,
             handlers: &self.handlers,
         };
@@ -267,17 +534,17 @@ impl Ui {
                 &mut self.states,
             );
         }
+        if let Some(panel) = self.tooltip.panel() {
+            tooltip::clamp_tooltip(&layout_manager, panel, &mut self.geometries);
+        }
 
-        let mut events = std::mem::replace(&mut self.event_queue.0, Vec::with_capacity(0));
-        let updaters = std::mem::replace(&mut self.updaters, Vec::with_capacity(0));
+        // The handlers are cloned, since they are allowed to create and remove nodes
+        let events = std::mem::take(&mut self.event_queue.0);
+        let updaters: Vec<EventHandler> = self.updaters.iter().flatten().cloned().collect();
         updaters
             .iter()
-            .filter_map(|x| x.as_ref())
             .chain(events.iter())
             .for_each(|event| (event)(self, ecs));
-        events.clear();
-        self.event_queue.0 = events;
-        self.updaters = updaters;
     }
 
     fn set_on_update(&mut self, node_id: NodeId, on_update: EventHandler) {
@@ -286,6 +553,13 @@ impl Ui {
         self.updaters[node_id.index()] = Some(on_update);
     }
 
+    /// Sets the text shown when the cursor rests over the node
+    fn set_tooltip(&mut self, node_id: NodeId, tooltip: TooltipText) {
+        self.check_id(node_id, "Invalid node.");
+
+        self.tooltips[node_id.index()] = Some(tooltip);
+    }
+
     fn find_parentless_nodes(&self) -> Vec<NodeId> {
         self.geometries
             .iter()
@@ -307,7 +581,7 @@ impl Ui {
 }
 
 pub fn insert_or_replace<T>(vec: &mut Vec<T>, id: NodeId, item: T) {
-    if vec.len() < id.index() {
+    if id.index() < vec.len() {
         vec[id.index()] = item;
     } else {
         vec.insert(id.index(), item);
@@ -319,22 +593,56 @@ pub struct UiAssets {
     pub button_pressed: NinePatch,
     pub medium_font: FontMap,
     pub pane: NinePatch,
+    /// A plain white region, for drawing solid rectangles in any color
+    pub white: TextureRegion2D,
     pub item_icons: HashMap<GameItem, TextureRegion2D>,
+    /// Rendered from the block meshes while loading, see Renderer::render_icons
+    pub block_icons: HashMap<BlockId, TextureRegion2D>,
 }
 
 impl UiAssets {
-    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, atlas: &mut TextureAtlas) -> Self {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        atlas: &mut TextureAtlas,
+        block_icons: Vec<(BlockId, image::RgbaImage)>,
+    ) -> Self {
+        // The UI can't be drawn without its widgets and font
+        fn required<T>(result: Result<T, AssetError>) -> T {
+            result.unwrap_or_else(|error| panic!("{}", error))
+        }
+
+        let white = UiAssets::white_region(atlas);
         let assets = UiAssets {
-            item_icons: item::load_item_icons(atlas),
-            button: atlas.load_ninepatch("assets/ui/widgets/button.9.png"),
-            button_pressed: atlas.load_ninepatch("assets/ui/widgets/button_pressed.9.png"),
-            medium_font: atlas.load_font("assets/ui/fonts/montserrat-medium.ttf"),
-            pane: atlas.load_ninepatch("assets/ui/widgets/pane.9.png"),
+            item_icons: item::load_item_icons(atlas, white),
+            block_icons: block_icons
+                .into_iter()
+                .map(|(block_id, image)| {
+                    let name = format!("block_icon_{}", block_id);
+                    let region = atlas.add_texture(&name, image::DynamicImage::ImageRgba8(image));
+                    (block_id, region)
+                })
+                .collect(),
+            button: required(atlas.load_ninepatch("ui/widgets/button.9.png")),
+            button_pressed: required(atlas.load_ninepatch("ui/widgets/button_pressed.9.png")),
+            medium_font: required(atlas.load_font("ui/fonts/montserrat-medium.ttf")),
+            pane: required(atlas.load_ninepatch("ui/widgets/pane.9.png")),
+            white,
         };
         atlas.update_gpu_texture(device, queue);
 
         assets
     }
+
+    fn white_region(atlas: &mut TextureAtlas) -> TextureRegion2D {
+        let image = image::RgbaImage::from_pixel(4, 4, image::Rgba([255; 4]));
+        let region = atlas.add_texture("white", image::DynamicImage::ImageRgba8(image));
+        // Only the middle is used, so filtering does not blend in the neighbouring textures
+        TextureRegion2D {
+            pos: Point2::new(region.pos.x + 1.0, region.pos.y + 1.0),
+            size: Point2::new(region.size.x - 2.0, region.size.y - 2.0),
+        }
+    }
 }
 
 pub fn new_sprite_renderer(texture: TextureRegion2D) -> Box<SpriteRenderer> {
@@ -402,6 +710,55 @@ impl NodeRenderer for SpriteRenderer {
     }
 }
 
+/// An outline around the node with the keyboard focus, which is drawn over the node
+struct FocusRing;
+
+impl FocusRing {
+    const COLOR: Color = Color {
+        r: 0.4,
+        g: 0.75,
+        b: 1.0,
+        a: 1.0,
+    };
+    const WIDTH: f32 = 2.0;
+    /// How far outside of the node the outline is
+    const GAP: f32 = 2.0;
+}
+
+impl NodeRenderer for FocusRing {
+    fn render(
+        &self,
+        ui_batch: &mut UiBatch,
+        ui: &Ui,
+        _: NodeId,
+        geometry: &NodeGeometry,
+        _: &WidgetStates,
+    ) {
+        let outset = FocusRing::WIDTH + FocusRing::GAP;
+        let (x, y) = (geometry.pos.x - outset, geometry.pos.y - outset);
+        let (width, height) = (
+            geometry.size.x + outset * 2.0,
+            geometry.size.y + outset * 2.0,
+        );
+        let thickness = FocusRing::WIDTH;
+        let color = Vector4::new(
+            FocusRing::COLOR.r,
+            FocusRing::COLOR.g,
+            FocusRing::COLOR.b,
+            FocusRing::COLOR.a,
+        );
+        let edges = [
+            Vector4::new(x, y, width, thickness),
+            Vector4::new(x, y + height - thickness, width, thickness),
+            Vector4::new(x, y, thickness, height),
+            Vector4::new(x + width - thickness, y, thickness, height),
+        ];
+        for edge in edges.iter() {
+            ui_batch.draw(*edge, ui.assets.white, color);
+        }
+    }
+}
+
 pub struct NinepatchRenderer {
     patch: NinePatch,
     color: Color,
@@ -563,6 +920,7 @@ impl NodeRenderer for TextLayout {
 
 pub struct LayoutManager<'a> {
     pub window_size: Point2<f32>,
+    parents: &'a [Option<NodeId>],
     children: &'a WidgetChildren,
     handlers: &'a WidgetHandlers,
 }
@@ -586,18 +944,39 @@ impl LayoutManager<'_> {
             )
         }
     }
+
+    pub fn parent(&self, node: NodeId) -> Option<NodeId> {
+        self.parents[node.index()]
+    }
+
+    /// Moves a node along with all of its children, so nodes that were already laid out
+    /// keep their position relative to the node
+    pub fn move_node(&self, node: NodeId, pos: Point2<f32>, geometries: &mut WidgetGeometries) {
+        let offset = pos - geometries[node.arena_index()].pos;
+        self.offset_node(node, offset, geometries);
+    }
+
+    fn offset_node(&self, node: NodeId, offset: Vector2<f32>, geometries: &mut WidgetGeometries) {
+        geometries[node.arena_index()].pos += offset;
+        for child in &self.children[node.index()] {
+            self.offset_node(*child, offset, geometries);
+        }
+    }
 }
 
 pub trait NodeHandler {
+    /// Sets the geometry of the node's children. By default nodes keep the absolute
+    /// geometry they were given, and their children are laid out on their own.
     fn layout<'a>(
         &self,
-        _: &'a LayoutManager,
+        layout_manager: &'a LayoutManager,
         _: NodeId,
-        _: &[NodeId],
-        _: &mut WidgetGeometries,
-        _: &mut WidgetLayouts,
-        _: &mut WidgetStates,
+        children: &[NodeId],
+        geometries: &mut WidgetGeometries,
+        layouts: &mut WidgetLayouts,
+        states: &mut WidgetStates,
     ) {
+        layout_manager.layout_all(children, geometries, layouts, states);
     }
 
     fn on_click(
@@ -614,6 +993,35 @@ pub trait NodeHandler {
     }
 
     fn on_mouse_focus_lost(&self, _: NodeId, _: &mut WidgetStates) {}
+
+    /// Called when the node gets the keyboard focus
+    fn on_focus_gained(&self, _: NodeId, _: &mut WidgetStates) {}
+
+    fn on_focus_lost(&self, _: NodeId, _: &mut WidgetStates) {}
+
+    /// Called when the cursor moves while the node is held down
+    fn on_mouse_drag(
+        &self,
+        _: Point2<f32>,
+        _: NodeId,
+        _: &mut NodeGeometry,
+        _: &mut WidgetStates,
+        _: &mut EventQueue,
+    ) {
+    }
+
+    /// Called with the keys pressed while the node has the keyboard focus, before they move
+    /// the focus. Returns whether the key was used.
+    fn on_key(
+        &self,
+        _: event::VirtualKeyCode,
+        _: NodeId,
+        _: &NodeGeometry,
+        _: &mut WidgetStates,
+        _: &mut EventQueue,
+    ) -> bool {
+        false
+    }
 }
 
 struct EmptyNodeHandler;
@@ -644,4 +1052,11 @@ impl Color {
         b: 0.0,
         a: 1.0,
     };
+
+    pub const GRAY: Self = Self {
+        r: 0.45,
+        g: 0.45,
+        b: 0.45,
+        a: 1.0,
+    };
 }
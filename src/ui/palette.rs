@@ -0,0 +1,183 @@
+use super::{widgets::Label, *};
+use crate::debug::{ArgValue, DebugCommands};
+use winit::event::VirtualKeyCode;
+
+/// A searchable list of all of the debug commands (opened with Ctrl+Shift+P)
+pub struct CommandPalette {
+    commands: DebugCommands,
+    root: Option<NodeId>,
+    query: String,
+    matches: Vec<usize>,
+    selected: usize,
+    /// The names of the most recently used commands, most recent first
+    recent: Vec<&'static str>,
+    /// The command that is waiting for its arguments to be typed
+    prompting: Option<usize>,
+    message: Option<String>,
+}
+
+impl CommandPalette {
+    const MAX_RESULTS: usize = 8;
+    const MAX_RECENT: usize = 5;
+
+    pub fn new(ecs: &ECS) -> Self {
+        Self {
+            commands: DebugCommands::new(ecs),
+            root: None,
+            query: String::new(),
+            matches: Vec::new(),
+            selected: 0,
+            recent: Vec::new(),
+            prompting: None,
+            message: None,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.root.is_some()
+    }
+
+    pub fn toggle(&mut self, ui: &mut Ui, ecs: &mut ECS) {
+        if self.is_open() {
+            self.close(ui);
+        } else {
+            self.query.clear();
+            self.prompting = None;
+            self.message = None;
+            self.search();
+            self.rebuild(ui, ecs);
+        }
+    }
+
+    pub fn close(&mut self, ui: &mut Ui) {
+        if let Some(root) = self.root.take() {
+            ui.remove_node(root);
+        }
+    }
+
+    pub fn on_char(&mut self, ui: &mut Ui, ecs: &mut ECS, c: char) {
+        if c.is_control() || (c != ' ' && !ui.assets.medium_font.has_char(c)) {
+            return;
+        }
+
+        self.query.push(c);
+        self.search();
+        self.rebuild(ui, ecs);
+    }
+
+    pub fn on_key(&mut self, ui: &mut Ui, ecs: &mut ECS, key: VirtualKeyCode) {
+        match key {
+            VirtualKeyCode::Escape if self.prompting.is_some() => {
+                self.prompting = None;
+                self.message = None;
+                self.query.clear();
+                self.search();
+            }
+            VirtualKeyCode::Escape => {
+                self.close(ui);
+                return;
+            }
+            VirtualKeyCode::Back => {
+                self.query.pop();
+                self.search();
+            }
+            VirtualKeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            VirtualKeyCode::Down => {
+                let shown = self.matches.len().min(CommandPalette::MAX_RESULTS);
+                self.selected = (self.selected + 1).min(shown.saturating_sub(1));
+            }
+            VirtualKeyCode::Return => self.submit(ui, ecs),
+            _ => {}
+        }
+
+        if self.is_open() {
+            self.rebuild(ui, ecs);
+        }
+    }
+
+    fn search(&mut self) {
+        if self.prompting.is_none() {
+            self.matches = self.commands.search(&self.query, &self.recent);
+            self.selected = 0;
+        }
+    }
+
+    fn submit(&mut self, ui: &mut Ui, ecs: &mut ECS) {
+        match self.prompting {
+            Some(index) => match self.commands.get(index).parse_args(&self.query) {
+                Ok(args) => self.execute(ui, ecs, index, &args),
+                Err(error) => self.message = Some(error),
+            },
+            None => {
+                if let Some(index) = self.matches.get(self.selected).copied() {
+                    let command = self.commands.get(index);
+                    if command.args.is_empty() {
+                        self.execute(ui, ecs, index, &[]);
+                    } else {
+                        self.message = Some(format!("Usage: {}", command.usage()));
+                        self.prompting = Some(index);
+                        self.query.clear();
+                    }
+                }
+            }
+        }
+    }
+
+    fn execute(&mut self, ui: &mut Ui, ecs: &mut ECS, index: usize, args: &[ArgValue]) {
+        let command = self.commands.get(index);
+
+        match (command.action)(ecs, args) {
+            Ok(()) => {
+                self.recent.retain(|name| *name != command.name);
+                self.recent.insert(0, command.name);
+                self.recent.truncate(CommandPalette::MAX_RECENT);
+                self.close(ui);
+            }
+            Err(error) => self.message = Some(error),
+        }
+    }
+
+    fn rebuild(&mut self, ui: &mut Ui, ecs: &ECS) {
+        self.close(ui);
+
+        let anchor = layout::WindowAnchor::Center.new(ui);
+        let vbox = layout::create_vbox(ui, Some(anchor), true);
+
+        let prompt = match self.prompting {
+            Some(index) => format!("{} > {}_", self.commands.get(index).name, self.query),
+            None => format!("> {}_", self.query),
+        };
+        Label::create(ui, Some(vbox), &prompt);
+
+        if let Some(message) = &self.message {
+            Label::create(ui, Some(vbox), message);
+        }
+
+        if self.prompting.is_none() {
+            for (i, index) in self
+                .matches
+                .iter()
+                .take(CommandPalette::MAX_RESULTS)
+                .enumerate()
+            {
+                let command = self.commands.get(*index);
+                let state = command
+                    .state
+                    .map(|state| format!(" [{}]", (state)(ecs)))
+                    .unwrap_or_default();
+                let marker = if i == self.selected { "* " } else { "" };
+
+                Label::create(
+                    ui,
+                    Some(vbox),
+                    &format!(
+                        "{}{}{} - {}",
+                        marker, command.name, state, command.description
+                    ),
+                );
+            }
+        }
+
+        self.root = Some(anchor);
+    }
+}
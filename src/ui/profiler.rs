@@ -0,0 +1,79 @@
+use super::{widgets::Label, *};
+use crate::entity::difficulty::DifficultyDirector;
+use crate::graphics::MeshManager;
+use crate::profiler::FrameProfiler;
+use specs::{Join, WorldExt};
+use std::cell::RefCell;
+
+/// The panel of the overlay while it is shown
+struct Panel {
+    vbox: NodeId,
+    rows: Vec<NodeId>,
+}
+
+/// Ex. "Difficulty: score 52 / 51 (interval x0.98, ...)"
+fn describe_difficulty(director: &DifficultyDirector) -> String {
+    if !director.enabled {
+        return String::from("Difficulty: fixed");
+    }
+    match director.score() {
+        Some((score, expected)) => format!(
+            "Difficulty: score {:.0} / {:.0} ({})",
+            score,
+            expected,
+            director.modifiers().describe()
+        ),
+        None => String::from("Difficulty: not scored yet"),
+    }
+}
+
+/// Creates the overlay listing the timings of the FrameProfiler, which is shown while
+/// FrameProfiler::visible is set. A row is added for each entry once it is first recorded.
+pub fn create_profiler_overlay(ui: &mut Ui) {
+    let anchor = layout::WindowAnchor::CenterLeft.with_margin(ui, None, Point2::new(8.0, 8.0));
+    let panel: Rc<RefCell<Option<Panel>>> = Rc::new(RefCell::new(None));
+
+    ui.set_on_update(
+        anchor,
+        Rc::new(move |ui, ecs| {
+            let profiler = ecs.get_resource::<FrameProfiler>();
+            let mut panel = panel.borrow_mut();
+            if !profiler.visible {
+                if let Some(panel) = panel.take() {
+                    ui.remove_node(panel.vbox);
+                }
+                return;
+            }
+
+            let mut lines = vec![
+                format!(
+                    "Profiler (ms: current / average / worst of {})",
+                    FrameProfiler::HISTORY
+                ),
+                format!(
+                    "Entities: {}  Models: {}",
+                    ecs.world.entities().join().count(),
+                    ecs.get_resource::<MeshManager>().model_count()
+                ),
+                describe_difficulty(&ecs.get_resource::<DifficultyDirector>()),
+            ];
+            lines.extend(profiler.summaries().map(|entry| {
+                format!(
+                    "{}: {:.2} / {:.2} / {:.2}",
+                    entry.name, entry.current, entry.average, entry.worst
+                )
+            }));
+
+            let panel = panel.get_or_insert_with(|| Panel {
+                vbox: layout::create_vbox(ui, Some(anchor), true),
+                rows: Vec::new(),
+            });
+            while panel.rows.len() < lines.len() {
+                panel.rows.push(Label::create(ui, Some(panel.vbox), ""));
+            }
+            for (row, line) in panel.rows.iter().zip(&lines) {
+                Label::update_text(ui, *row, line);
+            }
+        }),
+    );
+}
@@ -0,0 +1,499 @@
+use super::{
+    widgets::{Button, Label, Slider, TabBuilder, Tabs},
+    *,
+};
+use crate::app::Settings;
+use crate::audio::AudioSettings;
+use crate::entity::{hazards::HazardSettings, InputManager, KeyAction, KeyBindings};
+use crate::graphics::GraphicsSettings;
+use crate::i18n::{Language, Strings};
+use std::cell::{Cell, RefCell};
+use winit::event::VirtualKeyCode;
+
+/// A modal menu (opened with Escape) for changing the language, controls, graphics and
+/// audio. Changes are used right away, but only saved once they are applied. Cancelling
+/// restores the settings from when the menu was opened.
+#[derive(Default)]
+pub struct SettingsMenu {
+    root: Option<NodeId>,
+    /// The settings when the menu was opened
+    opened_with: Option<Settings>,
+    /// The action that the next pressed key is bound to
+    rebinding: Rc<Cell<Option<KeyAction>>>,
+    /// Why the last key could not be bound
+    message: Rc<RefCell<String>>,
+}
+
+impl SettingsMenu {
+    pub const TOGGLE_KEY: VirtualKeyCode = VirtualKeyCode::Escape;
+
+    pub fn is_open(ui: &Ui) -> bool {
+        ui.settings.root.is_some()
+    }
+
+    pub fn toggle(ui: &mut Ui, ecs: &mut ECS) {
+        if SettingsMenu::is_open(ui) {
+            SettingsMenu::cancel(ui, ecs);
+        } else {
+            SettingsMenu::open(ui, ecs);
+        }
+    }
+
+    /// Binds the key while rebinding, and otherwise passes it on to the UI, which moves the
+    /// keyboard focus between the widgets (ex. to a slider). Escape cancels the rebinding,
+    /// or the menu.
+    pub fn on_key(ui: &mut Ui, ecs: &mut ECS, key: VirtualKeyCode) {
+        let menu = &ui.settings;
+        match menu.rebinding.get() {
+            Some(_) if key == SettingsMenu::TOGGLE_KEY => {
+                menu.rebinding.set(None);
+                menu.message.borrow_mut().clear();
+            }
+            Some(_) if !KeyBindings::is_bindable(key) => {
+                *menu.message.borrow_mut() = ui.strings.format(
+                    "settings.not_bindable",
+                    &[("key", &KeyBindings::key_name(key))],
+                );
+            }
+            Some(action) => {
+                let mut bindings = ecs.get_resource_mut::<KeyBindings>();
+                match bindings.bind(action, key) {
+                    Ok(()) => {
+                        menu.rebinding.set(None);
+                        menu.message.borrow_mut().clear();
+                    }
+                    Err(bound) => {
+                        *menu.message.borrow_mut() = ui.strings.format(
+                            "settings.already_bound",
+                            &[
+                                ("key", &KeyBindings::key_name(key)),
+                                ("action", &ui.strings.get(bound.name_key())),
+                            ],
+                        );
+                    }
+                }
+            }
+            None if key == SettingsMenu::TOGGLE_KEY => SettingsMenu::cancel(ui, ecs),
+            None => {
+                let shift = ecs.get_resource::<InputManager>().keys.is_shift_down();
+                ui.on_key(key, shift);
+            }
+        }
+    }
+
+    fn open(ui: &mut Ui, ecs: &mut ECS) {
+        ui.settings.opened_with = Some(current_settings(ecs));
+        let rebinding = ui.settings.rebinding.clone();
+        let message = ui.settings.message.clone();
+
+        let root = layout::create_modal(ui, layout::WindowAnchor::Center);
+        let vbox = layout::create_vbox(ui, Some(root), true);
+        Label::create(ui, Some(vbox), "menu.settings");
+
+        // Switching tabs stops the rebinding
+        let tab = |builder: fn(&mut Ui, NodeId, &Rc<Cell<Option<KeyAction>>>)| -> TabBuilder {
+            let rebinding = rebinding.clone();
+            Rc::new(move |ui, parent| {
+                rebinding.set(None);
+                builder(ui, parent, &rebinding);
+            })
+        };
+        Tabs::create(
+            ui,
+            Some(vbox),
+            vec![
+                (
+                    "settings.general",
+                    tab(|ui, parent, _| create_general_tab(ui, parent)),
+                ),
+                ("settings.controls", tab(create_controls_tab)),
+                (
+                    "settings.graphics",
+                    tab(|ui, parent, _| create_graphics_tab(ui, parent)),
+                ),
+                (
+                    "settings.audio",
+                    tab(|ui, parent, _| create_audio_tab(ui, parent)),
+                ),
+            ],
+        );
+
+        let message_label = Label::create(ui, Some(vbox), "");
+        ui.set_on_update(
+            message_label,
+            Rc::new(move |ui, _| {
+                let text = message.borrow().clone();
+                Label::update_text(ui, message_label, &text);
+            }),
+        );
+
+        let buttons = layout::create_hbox(ui, Some(vbox), false);
+        Button::create(
+            ui,
+            Some(buttons),
+            "menu.apply",
+            Rc::new(SettingsMenu::apply),
+        );
+        Button::create(
+            ui,
+            Some(buttons),
+            "menu.cancel",
+            Rc::new(SettingsMenu::cancel),
+        );
+
+        ui.settings.root = Some(root);
+    }
+
+    /// Keeps the changes, and saves them in the config
+    fn apply(ui: &mut Ui, ecs: &mut ECS) {
+        *ecs.get_resource_mut::<Settings>() = current_settings(ecs);
+        SettingsMenu::close(ui);
+    }
+
+    /// Restores the settings from when the menu was opened
+    fn cancel(ui: &mut Ui, ecs: &mut ECS) {
+        if let Some(settings) = ui.settings.opened_with {
+            *ecs.get_resource_mut::<GraphicsSettings>() = settings.graphics;
+            *ecs.get_resource_mut::<KeyBindings>() = settings.key_bindings;
+            *ecs.get_resource_mut::<AudioSettings>() = settings.audio;
+            *ecs.get_resource_mut::<HazardSettings>() = settings.hazards;
+            ecs.get_resource_mut::<Strings>()
+                .set_language(settings.language);
+        }
+        SettingsMenu::close(ui);
+    }
+
+    fn close(ui: &mut Ui) {
+        if let Some(root) = ui.settings.root.take() {
+            ui.remove_node(root);
+        }
+        ui.settings.opened_with = None;
+        ui.settings.rebinding.set(None);
+        ui.settings.message.borrow_mut().clear();
+    }
+}
+
+/// The settings that are in use, which may not have been applied yet
+fn current_settings(ecs: &ECS) -> Settings {
+    Settings {
+        graphics: *ecs.get_resource::<GraphicsSettings>(),
+        key_bindings: *ecs.get_resource::<KeyBindings>(),
+        audio: *ecs.get_resource::<AudioSettings>(),
+        language: ecs.get_resource::<Strings>().language(),
+        hazards: *ecs.get_resource::<HazardSettings>(),
+    }
+}
+
+/// Cycles through the languages, and turns the hazards on or off. The language's name is not
+/// translated, so it can be found from any other language.
+fn create_general_tab(ui: &mut Ui, parent: NodeId) {
+    let hbox = layout::create_hbox(ui, Some(parent), false);
+    Button::create(
+        ui,
+        Some(hbox),
+        "settings.change",
+        Rc::new(|_, ecs| {
+            let mut strings = ecs.get_resource_mut::<Strings>();
+            let language: Language = strings.language();
+            strings.set_language(language.next());
+        }),
+    );
+
+    let label = Label::create(ui, Some(hbox), "");
+    ui.set_on_update(
+        label,
+        Rc::new(move |ui, _| {
+            let text = ui.strings.format(
+                "settings.language",
+                &[("language", &ui.strings.language().name())],
+            );
+            Label::update_text(ui, label, &text);
+        }),
+    );
+
+    create_hazard_toggle(ui, parent, "hazard.solar_flare", |settings| {
+        &mut settings.solar_flares
+    });
+    create_hazard_toggle(ui, parent, "hazard.debris_storm", |settings| {
+        &mut settings.debris_storms
+    });
+}
+
+/// Creates a row with a button that turns the hazard on or off, and a label showing whether
+/// it is on
+fn create_hazard_toggle(
+    ui: &mut Ui,
+    parent: NodeId,
+    name_key: &'static str,
+    setting: fn(&mut HazardSettings) -> &mut bool,
+) {
+    let hbox = layout::create_hbox(ui, Some(parent), false);
+    Button::create(
+        ui,
+        Some(hbox),
+        "settings.toggle",
+        Rc::new(move |_, ecs| {
+            let mut settings = ecs.get_resource_mut::<HazardSettings>();
+            let enabled = setting(&mut settings);
+            *enabled = !*enabled;
+        }),
+    );
+
+    let label = Label::create(ui, Some(hbox), "");
+    ui.set_on_update(
+        label,
+        Rc::new(move |ui, ecs| {
+            let enabled = *setting(&mut ecs.get_resource_mut::<HazardSettings>());
+            let text = ui.strings.format(
+                "settings.hazard",
+                &[
+                    ("hazard", &ui.strings.get(name_key)),
+                    ("state", &ui.strings.on_off(enabled)),
+                ],
+            );
+            Label::update_text(ui, label, &text);
+        }),
+    );
+}
+
+/// Lists the key of each action. Clicking an action's button binds it to the next key that
+/// is pressed.
+fn create_controls_tab(ui: &mut Ui, parent: NodeId, rebinding: &Rc<Cell<Option<KeyAction>>>) {
+    for action in KeyAction::ALL.iter().copied() {
+        let hbox = layout::create_hbox(ui, Some(parent), false);
+        let rebinding = rebinding.clone();
+        Button::create(
+            ui,
+            Some(hbox),
+            "settings.rebind",
+            Rc::new({
+                let rebinding = rebinding.clone();
+                move |_, _| rebinding.set(Some(action))
+            }),
+        );
+
+        let label = Label::create(ui, Some(hbox), "");
+        ui.set_on_update(
+            label,
+            Rc::new(move |ui, ecs| {
+                let name = ui.strings.get(action.name_key());
+                let text = if rebinding.get() == Some(action) {
+                    ui.strings
+                        .format("settings.press_key", &[("action", &name)])
+                } else {
+                    let key = ecs.get_resource::<KeyBindings>().key(action);
+                    format!("{}: {}", name, KeyBindings::key_name(key))
+                };
+                Label::update_text(ui, label, &text);
+            }),
+        );
+    }
+
+    Button::create(
+        ui,
+        Some(parent),
+        "settings.reset_defaults",
+        Rc::new(|_, ecs| *ecs.get_resource_mut::<KeyBindings>() = KeyBindings::default()),
+    );
+}
+
+fn create_graphics_tab(ui: &mut Ui, parent: NodeId) {
+    create_setting(
+        ui,
+        parent,
+        |settings, strings| {
+            strings.format(
+                "settings.vsync",
+                &[("state", &strings.on_off(settings.vsync))],
+            )
+        },
+        &[("settings.toggle", |settings| {
+            settings.vsync = !settings.vsync
+        })],
+    );
+    create_setting(
+        ui,
+        parent,
+        |settings, strings| {
+            let samples = match settings.msaa_samples {
+                1 => strings.on_off(false).to_string(),
+                samples => format!("x{}", samples),
+            };
+            strings.format("settings.msaa", &[("samples", &samples)])
+        },
+        &[("settings.change", |settings| {
+            settings.msaa_samples = settings.next_msaa_samples()
+        })],
+    );
+    create_slider(
+        ui,
+        parent,
+        (
+            GraphicsSettings::MIN_RENDER_SCALE,
+            GraphicsSettings::MAX_RENDER_SCALE,
+        ),
+        GraphicsSettings::RENDER_SCALE_STEP,
+        |ecs| ecs.get_resource::<GraphicsSettings>().render_scale,
+        |ecs, scale| ecs.get_resource_mut::<GraphicsSettings>().render_scale = scale,
+        |scale, strings| {
+            strings.format(
+                "settings.render_scale",
+                &[("percent", &(scale * 100.0).round())],
+            )
+        },
+    );
+    create_setting(
+        ui,
+        parent,
+        |settings, strings| {
+            let path = if settings.gpu_culling { "GPU" } else { "CPU" };
+            strings.format("settings.culling", &[("path", &path)])
+        },
+        &[("settings.toggle", |settings| {
+            settings.gpu_culling = !settings.gpu_culling
+        })],
+    );
+    create_setting(
+        ui,
+        parent,
+        |settings, strings| {
+            let path = strings.get(if settings.gpu_picking {
+                "settings.picking_gpu"
+            } else {
+                "settings.picking_colliders"
+            });
+            strings.format("settings.picking", &[("path", &path)])
+        },
+        &[("settings.toggle", |settings| {
+            settings.gpu_picking = !settings.gpu_picking
+        })],
+    );
+    create_setting(
+        ui,
+        parent,
+        |settings, strings| {
+            strings.format(
+                "settings.backdrop",
+                &[("state", &strings.on_off(settings.backdrop))],
+            )
+        },
+        &[("settings.toggle", |settings| {
+            settings.backdrop = !settings.backdrop
+        })],
+    );
+    create_setting(
+        ui,
+        parent,
+        |settings, strings| strings.format("settings.tick_rate", &[("rate", &settings.tick_rate)]),
+        &[("settings.change", |settings| {
+            settings.tick_rate = settings.next_tick_rate()
+        })],
+    );
+}
+
+fn create_audio_tab(ui: &mut Ui, parent: NodeId) {
+    create_slider(
+        ui,
+        parent,
+        (0.0, 1.0),
+        AudioSettings::VOLUME_STEP,
+        |ecs| ecs.get_resource::<AudioSettings>().master_volume,
+        |ecs, volume| ecs.get_resource_mut::<AudioSettings>().master_volume = volume,
+        |volume, strings| {
+            strings.format(
+                "settings.master_volume",
+                &[("percent", &(volume * 100.0).round())],
+            )
+        },
+    );
+    create_slider(
+        ui,
+        parent,
+        (0.0, 1.0),
+        AudioSettings::VOLUME_STEP,
+        |ecs| ecs.get_resource::<AudioSettings>().effects_volume,
+        |ecs, volume| ecs.get_resource_mut::<AudioSettings>().effects_volume = volume,
+        |volume, strings| {
+            strings.format(
+                "settings.effects_volume",
+                &[("percent", &(volume * 100.0).round())],
+            )
+        },
+    );
+    create_slider(
+        ui,
+        parent,
+        (0.0, 1.0),
+        AudioSettings::VOLUME_STEP,
+        |ecs| ecs.get_resource::<AudioSettings>().music_volume,
+        |ecs, volume| ecs.get_resource_mut::<AudioSettings>().music_volume = volume,
+        |volume, strings| {
+            strings.format(
+                "settings.music_volume",
+                &[("percent", &(volume * 100.0).round())],
+            )
+        },
+    );
+}
+
+/// A button's text, and how it changes the settings
+type SettingAction = (&'static str, fn(&mut GraphicsSettings));
+
+/// Creates a row with a label describing the setting, and buttons that change it
+fn create_setting(
+    ui: &mut Ui,
+    parent: NodeId,
+    describe: fn(&GraphicsSettings, &Strings) -> String,
+    actions: &[SettingAction],
+) {
+    let hbox = layout::create_hbox(ui, Some(parent), false);
+    for (text, action) in actions.iter().copied() {
+        Button::create(
+            ui,
+            Some(hbox),
+            text,
+            Rc::new(move |_, ecs| action(&mut ecs.get_resource_mut::<GraphicsSettings>())),
+        );
+    }
+
+    let label = Label::create(ui, Some(hbox), "");
+    ui.set_on_update(
+        label,
+        Rc::new(move |ui, ecs| {
+            let text = describe(&ecs.get_resource::<GraphicsSettings>(), &ui.strings);
+            Label::update_text(ui, label, &text);
+        }),
+    );
+}
+
+/// Creates a row with a slider for a setting, and a label describing its value. The slider
+/// follows the setting, so it also shows changes made elsewhere (ex. when cancelling).
+fn create_slider(
+    ui: &mut Ui,
+    parent: NodeId,
+    range: (f32, f32),
+    step: f32,
+    get: fn(&ECS) -> f32,
+    set: fn(&mut ECS, f32),
+    describe: fn(f32, &Strings) -> String,
+) {
+    let hbox = layout::create_hbox(ui, Some(parent), false);
+    let slider = Slider::create(
+        ui,
+        Some(hbox),
+        range,
+        step,
+        Rc::new(move |_, ecs, value| set(ecs, value)),
+    );
+
+    let label = Label::create(ui, Some(hbox), "");
+    ui.set_on_update(
+        label,
+        Rc::new(move |ui, ecs| {
+            let value = get(ecs);
+            Slider::set_value(ui, slider, value);
+            let text = describe(value, &ui.strings);
+            Label::update_text(ui, label, &text);
+        }),
+    );
+}
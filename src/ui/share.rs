@@ -0,0 +1,277 @@
+use super::{
+    widgets::{Button, Label},
+    *,
+};
+use crate::block::Blocks;
+use crate::entity::{
+    blueprint::BlueprintTool, gameplay::GameLog, share::SharedShip, InputAction, InputManager,
+};
+use crate::floor::Floors;
+use crate::i18n::Strings;
+use crate::item;
+use winit::event::VirtualKeyCode;
+
+const ERROR_COLOR: Color = Color {
+    r: 1.0,
+    g: 0.45,
+    b: 0.35,
+    a: 1.0,
+};
+
+/// A modal dialog for importing a shared ship from its code (see SharedShip). The code is
+/// pasted (or typed) in, and the ship is summarized as soon as it can be read, so the
+/// player knows what they get before it is stamped as a blueprint or starts a new game.
+#[derive(Default)]
+pub struct ShareDialog {
+    root: Option<NodeId>,
+    code: String,
+    /// The ship read from the code, or why it couldn't be read. None while there is no code.
+    imported: Option<Result<SharedShip, String>>,
+    /// Created when it is first used. On some platforms the copied text is only kept while
+    /// the clipboard is open.
+    clipboard: Option<arboard::Clipboard>,
+}
+
+impl ShareDialog {
+    /// Typed by Ctrl+V on most platforms
+    const PASTE_CHAR: char = '\u{16}';
+    /// Codes are long, so only their start and end are shown
+    const SHOWN_CHARS: usize = 24;
+
+    pub fn is_open(ui: &Ui) -> bool {
+        ui.share.root.is_some()
+    }
+
+    pub fn open(ui: &mut Ui, ecs: &mut ECS) {
+        ui.share.code.clear();
+        ui.share.imported = None;
+        ShareDialog::rebuild(ui, ecs);
+    }
+
+    /// Copies the code of the mothership to the clipboard
+    pub fn export(ui: &mut Ui, ecs: &mut ECS) {
+        let code = SharedShip::capture(&ecs.world).map(|shared| {
+            shared.export(&ecs.get_resource::<Blocks>(), &ecs.get_resource::<Floors>())
+        });
+        let message = match code {
+            Some(code) => match ui.share.clipboard().and_then(|clipboard| {
+                clipboard
+                    .set_text(code.clone())
+                    .map_err(|error| error.to_string())
+            }) {
+                Ok(()) => ui
+                    .strings
+                    .format("share.exported", &[("length", &code.len())]),
+                Err(error) => {
+                    // The code is still printed, so it can be shared by hand
+                    println!("[Share] {}", code);
+                    ui.strings
+                        .format("share.clipboard_error", &[("error", &error)])
+                }
+            },
+            None => ui.strings.get("error.no_ship").to_string(),
+        };
+        ecs.get_resource_mut::<GameLog>().add(message);
+    }
+
+    /// Adds the character to the code. Only the characters of base64 are kept, so the code
+    /// can be typed with spaces or copied with line breaks.
+    pub fn on_char(ui: &mut Ui, ecs: &mut ECS, c: char) {
+        if c == ShareDialog::PASTE_CHAR {
+            ShareDialog::paste(ui, ecs);
+        } else if c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '=') {
+            ui.share.code.push(c);
+            ShareDialog::update(ui, ecs);
+        }
+    }
+
+    pub fn on_key(ui: &mut Ui, ecs: &mut ECS, key: VirtualKeyCode) {
+        match key {
+            VirtualKeyCode::Escape => ShareDialog::close(ui),
+            VirtualKeyCode::Back => {
+                ui.share.code.pop();
+                ShareDialog::update(ui, ecs);
+            }
+            _ => {}
+        }
+    }
+
+    /// Replaces the code with the text in the clipboard
+    fn paste(ui: &mut Ui, ecs: &mut ECS) {
+        let text = ui
+            .share
+            .clipboard()
+            .and_then(|clipboard| clipboard.get_text().map_err(|error| error.to_string()));
+        match text {
+            Ok(text) => {
+                ui.share.code = text.chars().filter(|c| !c.is_whitespace()).collect();
+                ShareDialog::update(ui, ecs);
+            }
+            Err(error) => {
+                let error = ui
+                    .strings
+                    .format("share.clipboard_error", &[("error", &error)]);
+                ui.share.imported = Some(Err(error));
+                ShareDialog::rebuild(ui, ecs);
+            }
+        }
+    }
+
+    /// Reads the ship from the code again
+    fn update(ui: &mut Ui, ecs: &mut ECS) {
+        ui.share.imported = if ui.share.code.is_empty() {
+            None
+        } else {
+            Some(SharedShip::import(
+                &ui.share.code,
+                &ecs.get_resource::<Blocks>(),
+                &ecs.get_resource::<Floors>(),
+                &ecs.get_resource::<Strings>(),
+            ))
+        };
+        ShareDialog::rebuild(ui, ecs);
+    }
+
+    /// Picks the ship to be stamped like a copied blueprint
+    fn stamp(ui: &mut Ui, ecs: &mut ECS) {
+        if let Some(Ok(shared)) = &ui.share.imported {
+            ecs.get_resource_mut::<BlueprintTool>().blueprint = Some(shared.blueprint.clone());
+            ecs.get_resource_mut::<InputManager>().action = InputAction::StampBlueprint;
+            ShareDialog::close(ui);
+        }
+    }
+
+    fn new_game(ui: &mut Ui, ecs: &mut ECS) {
+        if let Some(Ok(shared)) = &ui.share.imported {
+            if shared.has_core(&ecs.get_resource::<Blocks>()) {
+                let shared = shared.clone();
+                ShareDialog::close(ui);
+                ecs.restart_with_ship(shared);
+            }
+        }
+    }
+
+    fn close(ui: &mut Ui) {
+        if let Some(root) = ui.share.root.take() {
+            ui.remove_node(root);
+        }
+        ui.share.code.clear();
+        ui.share.imported = None;
+    }
+
+    fn clipboard(&mut self) -> Result<&mut arboard::Clipboard, String> {
+        if self.clipboard.is_none() {
+            self.clipboard = Some(arboard::Clipboard::new().map_err(|error| error.to_string())?);
+        }
+        Ok(self.clipboard.as_mut().unwrap())
+    }
+
+    fn rebuild(ui: &mut Ui, ecs: &ECS) {
+        if let Some(root) = ui.share.root.take() {
+            ui.remove_node(root);
+        }
+
+        let root = layout::create_modal(ui, layout::WindowAnchor::Center);
+        let vbox = layout::create_vbox(ui, Some(root), true);
+
+        // Boxes stack their children from the bottom, so the buttons are added first
+        let buttons = layout::create_hbox(ui, Some(vbox), false);
+        Button::create(
+            ui,
+            Some(buttons),
+            "share.paste",
+            Rc::new(ShareDialog::paste),
+        );
+        let (lines, stampable, has_core) = {
+            let blocks = ecs.get_resource::<Blocks>();
+            let strings = &ui.strings;
+            match &ui.share.imported {
+                Some(Ok(shared)) => {
+                    let floors = ecs.get_resource::<Floors>();
+                    let mut lines = describe(shared, &blocks, &floors, strings);
+                    let has_core = shared.has_core(&blocks);
+                    if !has_core {
+                        lines.push((strings.get("share.no_core").to_string(), Color::GRAY));
+                    }
+                    (lines, true, has_core)
+                }
+                Some(Err(error)) => (vec![(error.clone(), ERROR_COLOR)], false, false),
+                None => (
+                    vec![(strings.get("share.hint").to_string(), Color::GRAY)],
+                    false,
+                    false,
+                ),
+            }
+        };
+        if stampable {
+            Button::create(
+                ui,
+                Some(buttons),
+                "share.stamp",
+                Rc::new(ShareDialog::stamp),
+            );
+        }
+        if has_core {
+            Button::create(
+                ui,
+                Some(buttons),
+                "share.new_game",
+                Rc::new(ShareDialog::new_game),
+            );
+        }
+        Button::create(
+            ui,
+            Some(buttons),
+            "menu.cancel",
+            Rc::new(|ui, _| ShareDialog::close(ui)),
+        );
+
+        for (text, color) in lines.iter().rev() {
+            let label = Label::create(ui, Some(vbox), text);
+            Label::set_color(ui, label, *color);
+        }
+
+        // Pasted codes may have any characters, so they are cut by character
+        let chars: Vec<char> = ui.share.code.chars().collect();
+        let shown = if chars.len() > ShareDialog::SHOWN_CHARS * 2 {
+            let start: String = chars[..ShareDialog::SHOWN_CHARS].iter().collect();
+            let end: String = chars[chars.len() - ShareDialog::SHOWN_CHARS..]
+                .iter()
+                .collect();
+            let length = ui
+                .strings
+                .format("share.length", &[("length", &chars.len())]);
+            format!("{}...{} ({})", start, end, length)
+        } else {
+            ui.share.code.clone()
+        };
+        Label::create(ui, Some(vbox), &format!("> {}_", shown));
+        Label::create(ui, Some(vbox), "share.title");
+
+        ui.share.root = Some(root);
+    }
+}
+
+/// The lines that summarize the ship: how many of each block it has, and what it costs
+fn describe(
+    shared: &SharedShip,
+    blocks: &Blocks,
+    floors: &Floors,
+    strings: &Strings,
+) -> Vec<(String, Color)> {
+    let mut lines = vec![strings.format(
+        "share.blocks",
+        &[("count", &shared.blueprint.block_count())],
+    )];
+    for (type_name, count) in shared.blueprint.block_counts(blocks) {
+        let name = match blocks.find(type_name) {
+            Some(block_id) => strings.get(blocks.get_block(block_id).name_key),
+            None => type_name,
+        };
+        lines.push(strings.format("share.block_count", &[("block", &name), ("count", &count)]));
+    }
+    let cost = item::describe_cost(&shared.blueprint.cost(blocks, floors), strings);
+    lines.push(strings.format("share.cost", &[("cost", &cost)]));
+
+    lines.into_iter().map(|line| (line, Color::WHITE)).collect()
+}
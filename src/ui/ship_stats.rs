@@ -0,0 +1,110 @@
+use super::{widgets::Label, *};
+use crate::block::Blocks;
+use crate::entity::{
+    ship,
+    ship_stats::{ShipStats, ShipStatsCache},
+    InputManager, KeyAction, KeyBindings,
+};
+use crate::i18n::Strings;
+use std::cell::RefCell;
+
+/// The panel while it is shown
+struct Panel {
+    vbox: NodeId,
+    rows: Vec<NodeId>,
+}
+
+/// The lines of the panel, one for each row
+fn describe(stats: &ShipStats, blocks: &Blocks, strings: &Strings) -> Vec<String> {
+    let counts: Vec<String> = stats
+        .block_counts
+        .iter()
+        .map(|(block, count)| {
+            strings.format(
+                "ship_stats.block_count",
+                &[
+                    ("count", count),
+                    ("block", &strings.get(blocks.get_block(*block).name_key)),
+                ],
+            )
+        })
+        .collect();
+
+    vec![
+        strings.format("ship_stats.blocks", &[("blocks", &counts.join(", "))]),
+        strings.format(
+            "ship_stats.health",
+            &[("health", &stats.health), ("max", &stats.max_health)],
+        ),
+        strings.format(
+            "ship_stats.heat",
+            &[
+                ("generated", &format!("{:.1}", stats.worst_case_heat)),
+                ("cooling", &format!("{:.1}", stats.dissipation)),
+            ],
+        ),
+        strings.format(
+            "ship_stats.power",
+            &[
+                ("demand", &format!("{:.1}", stats.power_demand)),
+                ("supply", &format!("{:.1}", stats.power_supply)),
+            ],
+        ),
+        strings.format(
+            "ship_stats.mass",
+            &[
+                ("mass", &format!("{:.1}", stats.mass)),
+                ("ratio", &format!("{:.2}", stats.thrust_to_mass())),
+            ],
+        ),
+        strings.format(
+            "ship_stats.cost",
+            &[("cost", &crate::item::describe_cost(&stats.cost, strings))],
+        ),
+    ]
+}
+
+/// Creates the panel describing the layout of the selected ship in the container. It is
+/// shown and hidden with the ShipStats key, and only measures the ship again once the ship
+/// changed (see ShipStatsCache).
+pub fn create_ship_stats_panel(ui: &mut Ui, parent: NodeId) {
+    let container = layout::create_vbox(ui, Some(parent), false);
+    let panel: Rc<RefCell<Option<Panel>>> = Rc::new(RefCell::new(None));
+
+    ui.set_on_update(
+        container,
+        Rc::new(move |ui, ecs| {
+            let toggled = ecs.get_resource::<KeyBindings>().take_press(
+                &mut ecs.get_resource_mut::<InputManager>().keys,
+                KeyAction::ShipStats,
+            );
+            let mut panel = panel.borrow_mut();
+            if toggled {
+                match panel.take() {
+                    Some(panel) => ui.remove_node(panel.vbox),
+                    None => {
+                        let vbox = layout::create_vbox(ui, Some(container), true);
+                        Label::create(ui, Some(vbox), "ship_stats.title");
+                        let rows = (0..6).map(|_| Label::create(ui, Some(vbox), "")).collect();
+                        *panel = Some(Panel { vbox, rows });
+                    }
+                }
+            }
+
+            let rows = match &*panel {
+                Some(panel) => &panel.rows,
+                None => return,
+            };
+            let lines = match ship::selected_ship(&ecs.world)
+                .and_then(|ship| ShipStatsCache::stats(&ecs.world, ship))
+            {
+                Some(stats) => describe(&stats, &ecs.get_resource::<Blocks>(), &ui.strings),
+                None => vec![ui.strings.get("hud.no_ship").to_string()],
+            };
+            for (index, row) in rows.iter().enumerate() {
+                let text = lines.get(index).map_or("", String::as_str);
+                Label::update_text(ui, *row, text);
+            }
+        }),
+    );
+}
@@ -0,0 +1,116 @@
+use super::{widgets::Label, *};
+use crate::block::Blocks;
+use crate::i18n::Strings;
+use crate::item::GameItem;
+use crate::stats::{LifetimeStats, RunStats, Stat};
+
+/// The stats are counted by the internal names of the content, which are looked up to show
+/// the names in the current language
+struct Names<'a> {
+    strings: &'a Strings,
+    blocks: &'a Blocks,
+}
+
+impl Names<'_> {
+    fn item(&self, name: &str) -> String {
+        match GameItem::iter().find(|item| format!("{:?}", item) == name) {
+            Some(item) => self.strings.get(item.name_key()).to_string(),
+            None => name.to_string(),
+        }
+    }
+
+    fn block(&self, type_name: &str) -> String {
+        match self.blocks.find(type_name) {
+            Some(block) => self
+                .strings
+                .get(self.blocks.get_block(block).name_key)
+                .to_string(),
+            None => type_name.to_string(),
+        }
+    }
+}
+
+/// Creates a pane showing the lifetime statistics (including the current run)
+pub fn create_statistics_pane(ui: &mut Ui) -> NodeId {
+    let anchor = layout::WindowAnchor::Center.new(ui);
+    let vbox = layout::create_vbox(ui, Some(anchor), true);
+    Label::create(ui, Some(vbox), "statistics.title");
+
+    let lines: Vec<fn(&LifetimeStats, &Names) -> String> = vec![
+        |stats, names| {
+            names
+                .strings
+                .format("statistics.runs", &[("count", &stats.runs)])
+        },
+        |stats, names| {
+            let minutes = stats.counters.get(Stat::PlaytimeTicks) / 60 / 60;
+            names.strings.format(
+                "statistics.playtime",
+                &[("hours", &(minutes / 60)), ("minutes", &(minutes % 60))],
+            )
+        },
+        |stats, names| {
+            let asteroids = describe_counters(stats, "asteroids_destroyed.", names);
+            names
+                .strings
+                .format("statistics.asteroids", &[("asteroids", &asteroids)])
+        },
+        |stats, names| {
+            let items = describe_counters(stats, "items_mined.", names);
+            names
+                .strings
+                .format("statistics.items", &[("items", &items)])
+        },
+        |stats, names| {
+            let favorite = stats
+                .counters
+                .with_prefix("blocks_placed.")
+                .max_by_key(|(_, count)| *count)
+                .map(|(block, count)| {
+                    names.strings.format(
+                        "statistics.placed",
+                        &[("block", &names.block(block)), ("count", &count)],
+                    )
+                })
+                .unwrap_or_else(|| names.strings.get("statistics.none").to_string());
+            names
+                .strings
+                .format("statistics.favorite_block", &[("block", &favorite)])
+        },
+    ];
+
+    for line in lines {
+        let label = Label::create(ui, Some(vbox), "");
+        ui.set_on_update(
+            label,
+            Rc::new(move |ui, ecs| {
+                // The profile is only updated on autosave, so the current run is merged into a copy
+                let mut stats = LifetimeStats::clone(&ecs.get_resource::<LifetimeStats>());
+                stats.merge(&ecs.get_resource::<RunStats>());
+                let names = Names {
+                    strings: &ui.strings,
+                    blocks: &ecs.get_resource::<Blocks>(),
+                };
+                let text = (line)(&stats, &names);
+                Label::update_text(ui, label, &text);
+            }),
+        );
+    }
+
+    anchor
+}
+
+/// The counters are of items (ex. "items_mined.Iron")
+fn describe_counters(stats: &LifetimeStats, prefix: &str, names: &Names) -> String {
+    let counters: Vec<String> = stats
+        .counters
+        .with_prefix(prefix)
+        .map(|(name, count)| format!("{} {}", count, names.item(name)))
+        .collect();
+
+    if counters.is_empty() {
+        names.strings.get("statistics.none").to_string()
+    } else {
+        counters.join(", ")
+    }
+}
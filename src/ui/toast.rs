@@ -0,0 +1,191 @@
+use super::*;
+use crate::achievements::Achievements;
+use crate::entity::{
+    hazards::{EventDirector, HazardPhase},
+    hud::HudVerbosity,
+    WindowSize,
+};
+use std::cell::Cell;
+
+const PADDING: f32 = 10.0;
+const SPACING: f32 = 4.0;
+/// The space between the toast and the edge of the window, and the toolbar below it
+const MARGIN: f32 = 8.0;
+/// How long a toast takes to slide in from the edge of the window, and to fade out at the
+/// end of its lifetime
+const SLIDE_TIME: f32 = 0.4;
+const FADE_TIME: f32 = 0.8;
+const TITLE_COLOR: Color = Color {
+    r: 1.0,
+    g: 0.8,
+    b: 0.3,
+    a: 1.0,
+};
+
+/// A pane with a title above a line of text, which fades as a whole
+pub struct Toast {
+    title: TextLayout,
+    text: TextLayout,
+    opacity: f32,
+}
+
+impl Toast {
+    pub fn create(ui: &mut Ui, parent: Option<NodeId>) -> NodeId {
+        let font = &ui.assets.medium_font;
+        let state = Toast {
+            title: TextLayout::new(Point2::new(0.0, 0.0), "", font, TITLE_COLOR),
+            text: TextLayout::new(Point2::new(0.0, 0.0), "", font, Color::WHITE),
+            opacity: 0.0,
+        };
+
+        ui.new_node(
+            parent,
+            NodeGeometry {
+                pos: Point2::new(0.0, 0.0),
+                size: Point2::new(0.0, 0.0),
+            },
+            NodeLayout::default(),
+            Box::new(ToastRenderer),
+            Box::new(EmptyNodeHandler),
+            Some(Box::new(state)),
+        )
+    }
+
+    pub fn set_text(ui: &mut Ui, node: NodeId, title: &str, text: &str) {
+        let font = &ui.assets.medium_font;
+        let mut text = TextLayout::new(Point2::new(PADDING, PADDING), text, font, Color::WHITE);
+        let title_pos = Point2::new(PADDING, PADDING + text.height + SPACING);
+        let mut title = TextLayout::new(title_pos, title, font, TITLE_COLOR);
+        let size = Point2::new(
+            title.width.max(text.width) + PADDING * 2.0,
+            title_pos.y + title.height + PADDING,
+        );
+
+        ui.layouts[node.index()].min_size = size;
+        ui.geometries[node.arena_index()].size = size;
+        let state = ui.states.get_mut::<Toast>(node).unwrap();
+        title.color.a = state.opacity;
+        text.color.a = state.opacity;
+        state.title = title;
+        state.text = text;
+    }
+
+    /// Nothing is drawn at an opacity of 0
+    pub fn set_opacity(ui: &mut Ui, node: NodeId, opacity: f32) {
+        let state = ui.states.get_mut::<Toast>(node).unwrap();
+        state.opacity = opacity;
+        state.title.color.a = opacity;
+        state.text.color.a = opacity;
+    }
+}
+
+struct ToastRenderer;
+
+impl NodeRenderer for ToastRenderer {
+    fn render(
+        &self,
+        ui_batch: &mut UiBatch,
+        ui: &Ui,
+        node: NodeId,
+        geometry: &NodeGeometry,
+        states: &WidgetStates,
+    ) {
+        let state = states.get::<Toast>(node).unwrap();
+        if state.opacity <= 0.0 {
+            return;
+        }
+
+        let pane = NinepatchRenderer {
+            patch: ui.assets.pane,
+            color: Color {
+                a: state.opacity,
+                ..Color::WHITE
+            },
+            offset: Point2::new(0.0, 0.0),
+            scale: Point2::new(1.0, 1.0),
+        };
+        pane.render(ui_batch, ui, node, geometry, states);
+        state.title.render(ui_batch, ui, node, geometry, states);
+        state.text.render(ui_batch, ui, node, geometry, states);
+    }
+}
+
+/// Creates the toast that announces unlocked achievements. It slides in from the right edge
+/// of the window, above the toolbar, and fades out at the end of its lifetime.
+pub fn create_achievement_toast(ui: &mut Ui) {
+    let toast = Toast::create(ui, None);
+    // The achievement that is shown, and the language it was shown in
+    let shown = Cell::new(None);
+    ui.set_on_update(
+        toast,
+        Rc::new(move |ui, ecs| {
+            let achievements = ecs.get_resource::<Achievements>();
+            let current = match achievements.toast() {
+                Some(current) => current,
+                None => {
+                    Toast::set_opacity(ui, toast, 0.0);
+                    return;
+                }
+            };
+
+            let achievement = current.achievement;
+            let revision = ui.strings.revision();
+            if shown.get() != Some((achievement.id, revision)) {
+                shown.set(Some((achievement.id, revision)));
+                let title = ui.strings.format(
+                    "achievement.unlocked",
+                    &[("name", &ui.strings.get(achievement.name_key))],
+                );
+                let text = ui.strings.get(achievement.description_key).to_string();
+                Toast::set_text(ui, toast, &title, &text);
+            }
+
+            let slide = 1.0 - (1.0 - (current.age / SLIDE_TIME).min(1.0)).powi(2);
+            let remaining = current.remaining(*ecs.get_resource::<HudVerbosity>());
+            Toast::set_opacity(ui, toast, (remaining / FADE_TIME).clamp(0.0, 1.0));
+
+            let window_size = ecs.get_resource::<WindowSize>().as_point();
+            let geometry = &mut ui.geometries[toast.arena_index()];
+            geometry.pos = Point2::new(
+                window_size.x - (geometry.size.x + MARGIN) * slide,
+                toolbar::HEIGHT + MARGIN,
+            );
+        }),
+    );
+}
+
+/// Creates the toast that announces a hazard until it starts. It stays at the left edge of
+/// the window, above the toolbar, so it does not cover the achievement toasts.
+pub fn create_hazard_toast(ui: &mut Ui) {
+    let toast = Toast::create(ui, None);
+    // The text that is shown, which changes every second of the countdown
+    let shown = Cell::new(None);
+    ui.set_on_update(
+        toast,
+        Rc::new(move |ui, ecs| {
+            let (kind, time_left) = match ecs.get_resource::<EventDirector>().current() {
+                Some((kind, HazardPhase::Warning(time_left))) => (kind, time_left),
+                _ => {
+                    Toast::set_opacity(ui, toast, 0.0);
+                    shown.set(None);
+                    return;
+                }
+            };
+
+            let seconds = time_left.ceil() as u32;
+            let revision = ui.strings.revision();
+            if shown.get() != Some((kind, seconds, revision)) {
+                shown.set(Some((kind, seconds, revision)));
+                let title = ui.strings.get(kind.name_key()).to_string();
+                let text = ui
+                    .strings
+                    .format("hazard.arrives_in", &[("time", &seconds)]);
+                Toast::set_text(ui, toast, &title, &text);
+            }
+            Toast::set_opacity(ui, toast, 1.0);
+
+            let geometry = &mut ui.geometries[toast.arena_index()];
+            geometry.pos = Point2::new(MARGIN, toolbar::HEIGHT + MARGIN);
+        }),
+    );
+}
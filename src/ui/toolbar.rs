@@ -0,0 +1,332 @@
+use super::*;
+use crate::block::{BlockId, Blocks};
+use crate::entity::{
+    blueprint::BlueprintTool, paint::Paint, InputAction, InputManager, InputSystem, WindowSize,
+};
+use crate::i18n::Strings;
+use crate::item::Inventory;
+use crate::tech::TechTree;
+use std::cell::RefCell;
+use winit::event;
+
+/// The width and height of a block's button
+const BUTTON_SIZE: f32 = 56.0;
+/// The padding around the icon of a block's button. Smaller buttons are padded less.
+const ICON_PADDING: f32 = 6.0;
+/// The paint swatches are stacked in two rows, as high as a block's button
+const SWATCH_SPACING: f32 = 4.0;
+const SWATCH_SIZE: f32 = (BUTTON_SIZE - SWATCH_SPACING) / 2.0;
+const SWATCH_ROWS: usize = 2;
+/// How far the blocks of the radial picker are from the center of the window
+const PICKER_RADIUS: f32 = 150.0;
+const SELECTED_COLOR: Color = Color {
+    r: 0.5,
+    g: 0.85,
+    b: 1.0,
+    a: 1.0,
+};
+
+/// How far above the bottom of the window the toolbar reaches, with room for its padding.
+/// The panels at the bottom of the window (ex. the trade panel) are placed above it.
+pub const HEIGHT: f32 = BUTTON_SIZE + 24.0;
+
+/// Creates the toolbar at the bottom of the window, with a button for each block. Clicking
+/// a button (or pressing its number key) picks the block for placement. The blocks that
+/// can't be built right now (ex. they cost more than is in the inventory) are greyed out.
+/// The paint palette is at the end of the toolbar.
+pub fn create_block_toolbar(ui: &mut Ui, ecs: &ECS) {
+    let anchor = layout::WindowAnchor::BottomCenter.with_margin(ui, None, Point2::new(8.0, 8.0));
+    let hbox = layout::create_hbox(ui, Some(anchor), true);
+    let blocks = ecs.get_resource::<Blocks>();
+
+    for (index, block) in blocks.iter().enumerate() {
+        let block_id = block.id;
+        let name_key = block.name_key;
+        let icon = ui
+            .assets
+            .block_icons
+            .get(&block_id)
+            .copied()
+            .unwrap_or(ui.assets.white);
+        let action = InputAction::PlaceBlock(block_id);
+        let button = ToolButton::create(ui, Some(hbox), BUTTON_SIZE, action, icon, Color::WHITE);
+
+        ui.set_on_update(
+            button,
+            Rc::new(move |ui, ecs| {
+                let available = can_build(ecs, block_id);
+                ToolButton::update(ui, ecs, button);
+                ui.states.get_mut::<ToolButton>(button).unwrap().available = available;
+            }),
+        );
+
+        // The number keys pick the first blocks
+        let key = InputSystem::BLOCK_KEYS
+            .get(index)
+            .map(|_| format!(" [{}]", index + 1))
+            .unwrap_or_default();
+        ui.set_tooltip(
+            button,
+            Rc::new(move |ecs| {
+                let blocks = ecs.get_resource::<Blocks>();
+                let inventory = ecs.get_resource::<Inventory>();
+                let strings = ecs.get_resource::<Strings>();
+                let cost = inventory.block_cost(block_id, &blocks.get_block(block_id).cost);
+                let cost = match item::describe_cost(&cost, &strings) {
+                    cost if cost.is_empty() => strings.get("tooltip.free").to_string(),
+                    cost => cost,
+                };
+                let name = format!("{}{}", strings.get(name_key), key);
+                match ecs.get_resource::<TechTree>().required_node(block_id) {
+                    Some(node) => strings.format(
+                        "tooltip.toolbar_locked",
+                        &[
+                            ("block", &name),
+                            ("cost", &cost),
+                            ("tech", &node.display_name(&strings)),
+                        ],
+                    ),
+                    None => strings.format("tooltip.toolbar", &[("block", &name), ("cost", &cost)]),
+                }
+            }),
+        );
+    }
+
+    create_paint_palette(ui, hbox);
+}
+
+/// Creates the radial block picker, which is shown around the center of the window while the
+/// gamepad's picker button is held (see RadialPicker). It has the blocks of the toolbar in the
+/// same order, starting at the top and going clockwise, and highlights the block the stick
+/// points at.
+pub fn create_radial_picker(ui: &mut Ui, ecs: &ECS) {
+    let anchor = layout::WindowAnchor::Center.new(ui);
+    let block_ids: Vec<BlockId> = ecs
+        .get_resource::<Blocks>()
+        .iter()
+        .map(|block| block.id)
+        .collect();
+    // The buttons are only created while the picker is open
+    let buttons: Rc<RefCell<Vec<NodeId>>> = Rc::new(RefCell::new(Vec::new()));
+
+    ui.set_on_update(
+        anchor,
+        Rc::new(move |ui, ecs| {
+            let picker = ecs.get_resource::<BlueprintTool>().picker;
+            let mut buttons = buttons.borrow_mut();
+            if !picker.open {
+                for button in buttons.drain(..) {
+                    ui.remove_node(button);
+                }
+                return;
+            }
+
+            if buttons.is_empty() {
+                for block_id in &block_ids {
+                    let icon = ui
+                        .assets
+                        .block_icons
+                        .get(block_id)
+                        .copied()
+                        .unwrap_or(ui.assets.white);
+                    let action = InputAction::PlaceBlock(*block_id);
+                    let button =
+                        ToolButton::create(ui, None, BUTTON_SIZE, action, icon, Color::WHITE);
+                    buttons.push(button);
+                }
+            }
+
+            let center = ecs.get_resource::<WindowSize>().as_point() / 2.0;
+            for (index, (button, block_id)) in buttons.iter().zip(&block_ids).enumerate() {
+                let state = ui.states.get_mut::<ToolButton>(*button).unwrap();
+                state.selected = picker.pointed == Some(index);
+                state.available = can_build(ecs, *block_id);
+
+                let angle = index as f32 / buttons.len() as f32 * std::f32::consts::PI * 2.0;
+                let (sin, cos) = angle.sin_cos();
+                ui.geometries[button.arena_index()].pos = Point2::new(
+                    center.x + sin * PICKER_RADIUS - BUTTON_SIZE / 2.0,
+                    center.y + cos * PICKER_RADIUS - BUTTON_SIZE / 2.0,
+                );
+            }
+        }),
+    );
+}
+
+/// Creates a swatch for each color of the palette, which picks the color for painting blocks
+fn create_paint_palette(ui: &mut Ui, parent: NodeId) {
+    let style = layout::BoxStyle {
+        spacing: SWATCH_SPACING,
+        padding: 0.0,
+        draw_background: false,
+    };
+    let vbox = layout::create_box(ui, Some(parent), false, style);
+    let columns = Paint::PALETTE.len() / SWATCH_ROWS;
+    let rows: Vec<NodeId> = (0..SWATCH_ROWS)
+        .map(|_| layout::create_box(ui, Some(vbox), true, style))
+        .collect();
+
+    for (index, paint) in Paint::PALETTE.iter().enumerate() {
+        // The boxes stack from the bottom, so the first row is added last
+        let row = rows[SWATCH_ROWS - 1 - index / columns];
+        let color = Color {
+            r: paint.0.x,
+            g: paint.0.y,
+            b: paint.0.z,
+            a: 1.0,
+        };
+        let swatch = ToolButton::create(
+            ui,
+            Some(row),
+            SWATCH_SIZE,
+            InputAction::Paint(index),
+            ui.assets.white,
+            color,
+        );
+        ui.set_on_update(
+            swatch,
+            Rc::new(move |ui, ecs| ToolButton::update(ui, ecs, swatch)),
+        );
+        let key = if *paint == Paint::WHITE {
+            "tooltip.remove_paint"
+        } else {
+            "tooltip.paint"
+        };
+        ui.set_tooltip(
+            swatch,
+            Rc::new(move |ecs| ecs.get_resource::<Strings>().get(key).to_string()),
+        );
+    }
+}
+
+/// Whether the block is unlocked, and the inventory can pay for it
+fn can_build(ecs: &ECS, block_id: BlockId) -> bool {
+    let blocks = ecs.get_resource::<Blocks>();
+    let inventory = ecs.get_resource::<Inventory>();
+    let cost = inventory.block_cost(block_id, &blocks.get_block(block_id).cost);
+    ecs.get_resource::<TechTree>().is_unlocked(block_id) && inventory.has_items(&cost)
+}
+
+/// A button that picks an input action, showing an icon (ex. a block's icon, or a paint
+/// swatch). It is highlighted while its action is picked.
+struct ToolButton {
+    action: InputAction,
+    icon: TextureRegion2D,
+    icon_color: Color,
+    pressed: bool,
+    /// Whether the action is picked
+    selected: bool,
+    /// Whether the action can be used (ex. the block can be built), which is shown by
+    /// greying out the icon
+    available: bool,
+}
+
+impl ToolButton {
+    fn create(
+        ui: &mut Ui,
+        parent: Option<NodeId>,
+        size: f32,
+        action: InputAction,
+        icon: TextureRegion2D,
+        icon_color: Color,
+    ) -> NodeId {
+        let min_size = Point2::new(size, size);
+        let node = ui.new_node(
+            parent,
+            NodeGeometry {
+                pos: Point2::new(0.0, 0.0),
+                size: min_size,
+            },
+            NodeLayout { min_size },
+            Box::new(ToolButtonRenderer),
+            Box::new(ToolButtonHandler),
+            Some(Box::new(ToolButton {
+                action,
+                icon,
+                icon_color,
+                pressed: false,
+                selected: false,
+                available: true,
+            })),
+        );
+        ui.set_focusable(node, true);
+        node
+    }
+
+    /// Highlights the button while its action is picked
+    fn update(ui: &mut Ui, ecs: &ECS, node: NodeId) {
+        let action = ecs.get_resource::<InputManager>().action;
+        let state = ui.states.get_mut::<ToolButton>(node).unwrap();
+        state.selected = state.action == action;
+    }
+}
+
+struct ToolButtonRenderer;
+
+impl NodeRenderer for ToolButtonRenderer {
+    fn render(
+        &self,
+        ui_batch: &mut UiBatch,
+        ui: &Ui,
+        node: NodeId,
+        geometry: &NodeGeometry,
+        states: &WidgetStates,
+    ) {
+        let button = states.get::<ToolButton>(node).unwrap();
+        let mut background = new_ninepatch_renderer(if button.pressed || button.selected {
+            ui.assets.button_pressed
+        } else {
+            ui.assets.button
+        });
+        if button.selected {
+            background.color = SELECTED_COLOR;
+        }
+        background.render(ui_batch, ui, node, geometry, states);
+
+        // The icon is square, and fills the button inside of the padding
+        let size = geometry.size.x.min(geometry.size.y);
+        let padding = ICON_PADDING * size / BUTTON_SIZE;
+        let icon_size = size - padding * 2.0;
+        let mut icon = new_sprite_renderer(button.icon);
+        icon.color = if button.available {
+            button.icon_color
+        } else {
+            Color::GRAY
+        };
+        icon.offset = Point2::new(padding, padding);
+        icon.scale = Point2::new(icon_size / geometry.size.x, icon_size / geometry.size.y);
+        icon.render(ui_batch, ui, node, geometry, states);
+    }
+}
+
+struct ToolButtonHandler;
+
+impl NodeHandler for ToolButtonHandler {
+    fn on_click(
+        &self,
+        _: event::MouseButton,
+        click_state: event::ElementState,
+        _: Point2<f32>,
+        node: NodeId,
+        _: &mut NodeGeometry,
+        states: &mut WidgetStates,
+        events: &mut EventQueue,
+    ) -> bool {
+        let focus = click_state == event::ElementState::Pressed;
+        let button = states.get_mut::<ToolButton>(node).unwrap();
+        button.pressed = focus;
+
+        if focus {
+            let action = button.action;
+            events.add(Rc::new(move |_, ecs| {
+                ecs.get_resource_mut::<InputManager>().action = action
+            }));
+        }
+
+        focus
+    }
+
+    fn on_mouse_focus_lost(&self, node: NodeId, states: &mut WidgetStates) {
+        states.get_mut::<ToolButton>(node).unwrap().pressed = false;
+    }
+}
@@ -0,0 +1,165 @@
+use super::{layout, widgets::Label, *};
+use crate::entity::{hud::HudVerbosity, power, InputManager, Time, Tooltip};
+use specs::{Entity, WorldExt};
+
+/// Creates the text of a node's tooltip. It is called every update while the tooltip is
+/// shown, so the text stays current.
+pub type TooltipText = Rc<dyn Fn(&ECS) -> String>;
+
+#[derive(Clone, Copy, PartialEq)]
+enum TooltipTarget {
+    Node(NodeId),
+    Entity(Entity),
+}
+
+/// Shows a panel near the cursor describing the node (or entity) it rests over
+pub struct TooltipState {
+    target: Option<TooltipTarget>,
+    /// How many seconds the cursor has rested over the target
+    rest_time: f32,
+    /// The panel and the label in it, while the tooltip is shown
+    panel: Option<(NodeId, NodeId)>,
+    /// Tooltips are hidden while a mouse button is held
+    pub mouse_held: bool,
+}
+
+impl TooltipState {
+    /// How many seconds the cursor needs to rest over a target before its tooltip is shown,
+    /// at Full HudVerbosity
+    const DELAY: f32 = 0.5;
+    const CURSOR_OFFSET: f32 = 16.0;
+
+    pub fn new() -> Self {
+        Self {
+            target: None,
+            rest_time: 0.0,
+            panel: None,
+            mouse_held: false,
+        }
+    }
+
+    pub fn panel(&self) -> Option<NodeId> {
+        self.panel.map(|(panel, _)| panel)
+    }
+}
+
+/// Finds what the cursor rests over, and shows its tooltip once the delay has passed.
+/// The panel is placed at the cursor, and is kept on the screen by `clamp_tooltip`
+/// after it was laid out.
+pub fn update_tooltip(ui: &mut Ui, ecs: &ECS) {
+    let (mouse_pos, hovered) = {
+        let input = ecs.get_resource::<InputManager>();
+        (input.mouse_pos, input.hovered)
+    };
+    // Nothing under a modal node shows its tooltip
+    let target = if ui.tooltip.mouse_held || ui.modal().is_some() {
+        None
+    } else {
+        find_node(ui, mouse_pos)
+            .map(TooltipTarget::Node)
+            .or_else(|| {
+                hovered
+                    .filter(|entity| ecs.world.read_component::<Tooltip>().contains(*entity))
+                    .map(TooltipTarget::Entity)
+            })
+    };
+
+    if target != ui.tooltip.target {
+        ui.tooltip.target = target;
+        ui.tooltip.rest_time = 0.0;
+    }
+    let delay = ecs
+        .get_resource::<HudVerbosity>()
+        .tooltip_delay(TooltipState::DELAY);
+    let rested = target.is_some()
+        && ecs
+            .get_resource::<Time>()
+            .count_up(&mut ui.tooltip.rest_time, delay);
+
+    let text = match target {
+        Some(target) if rested => tooltip_text(ui, ecs, target),
+        _ => None,
+    };
+    match (text, ui.tooltip.panel) {
+        (Some(text), Some((panel, label))) => {
+            Label::update_text(ui, label, &text);
+            ui.geometries[panel.arena_index()].pos = tooltip_pos(ui, panel, mouse_pos);
+        }
+        (Some(text), None) => {
+            let panel = layout::create_vbox(ui, None, true);
+            let label = Label::create(ui, Some(panel), &text);
+            ui.geometries[panel.arena_index()].pos = tooltip_pos(ui, panel, mouse_pos);
+            ui.tooltip.panel = Some((panel, label));
+        }
+        (None, Some((panel, _))) => {
+            ui.remove_node(panel);
+            ui.tooltip.panel = None;
+        }
+        (None, None) => {}
+    }
+}
+
+/// Moves the tooltip back onto the screen, now that its size is known
+pub fn clamp_tooltip(
+    layout_manager: &LayoutManager,
+    panel: NodeId,
+    geometries: &mut WidgetGeometries,
+) {
+    let window_size = layout_manager.window_size;
+    let geometry = &geometries[panel.arena_index()];
+    let pos = Point2::new(
+        geometry.pos.x.min(window_size.x - geometry.size.x).max(0.0),
+        geometry.pos.y.min(window_size.y - geometry.size.y).max(0.0),
+    );
+    layout_manager.move_node(panel, pos, geometries);
+}
+
+/// The panel is shown below and to the right of the cursor
+fn tooltip_pos(ui: &Ui, panel: NodeId, mouse_pos: Point2<f32>) -> Point2<f32> {
+    let size = ui.geometries[panel.arena_index()].size;
+    Point2::new(
+        mouse_pos.x + TooltipState::CURSOR_OFFSET,
+        mouse_pos.y - TooltipState::CURSOR_OFFSET - size.y,
+    )
+}
+
+/// The smallest node with a tooltip under the cursor, since nested nodes are inside
+/// their parents
+fn find_node(ui: &Ui, pt: Point2<f32>) -> Option<NodeId> {
+    ui.geometries
+        .iter()
+        .filter(|(index, geometry)| {
+            matches!(ui.tooltips.get(index.into_raw_parts().0), Some(Some(_)))
+                && pt.x > geometry.pos.x
+                && pt.y > geometry.pos.y
+                && pt.x < geometry.pos.x + geometry.size.x
+                && pt.y < geometry.pos.y + geometry.size.y
+        })
+        .min_by(|(_, a), (_, b)| {
+            let area_a = a.size.x * a.size.y;
+            let area_b = b.size.x * b.size.y;
+            area_a.partial_cmp(&area_b).unwrap()
+        })
+        .map(|(index, _)| NodeId(index))
+}
+
+fn tooltip_text(ui: &Ui, ecs: &ECS, target: TooltipTarget) -> Option<String> {
+    let text = match target {
+        TooltipTarget::Node(node) => {
+            let tooltip = ui.tooltips.get(node.index())?.clone()?;
+            tooltip(ecs)
+        }
+        TooltipTarget::Entity(entity) => {
+            let tooltip = *ecs.world.read_component::<Tooltip>().get(entity)?;
+            let text = (tooltip.0)(entity, &ecs.world);
+            // Every block that uses power shows how much, on top of its own tooltip
+            match power::describe(entity, &ecs.world) {
+                Some(power) if !text.is_empty() => format!("{} - {}", text, power),
+                Some(power) => power,
+                None => text,
+            }
+        }
+    };
+
+    Some(text).filter(|text| !text.is_empty())
+}
@@ -0,0 +1,98 @@
+use super::{widgets::Button, widgets::Label, *};
+use crate::block::Blocks;
+use crate::entity::trade::{self, TradeEncounter, TradeOffer};
+use crate::item::Inventory;
+use std::cell::Cell;
+
+/// Opens the trade panel above the block toolbar while a trader is open for trades, and
+/// removes it once the trader leaves
+pub fn create_trade_panel(ui: &mut Ui) {
+    let anchor =
+        layout::WindowAnchor::BottomCenter.with_margin(ui, None, Point2::new(8.0, toolbar::HEIGHT));
+    let panel: Rc<Cell<Option<NodeId>>> = Rc::new(Cell::new(None));
+
+    ui.set_on_update(
+        anchor,
+        Rc::new(move |ui, ecs| {
+            let offers = ecs
+                .get_resource::<TradeEncounter>()
+                .offers()
+                .map(<[TradeOffer]>::len);
+            match (panel.get(), offers) {
+                (Some(node), None) => {
+                    ui.remove_node(node);
+                    panel.set(None);
+                }
+                (None, Some(count)) => panel.set(Some(create_offers(ui, anchor, count))),
+                _ => {}
+            }
+        }),
+    );
+}
+
+fn create_offers(ui: &mut Ui, parent: NodeId, count: usize) -> NodeId {
+    let vbox = layout::create_vbox(ui, Some(parent), true);
+    let title = Label::create(ui, Some(vbox), "trade.title");
+    ui.set_on_update(
+        title,
+        Rc::new(move |ui, ecs| {
+            if let Some(time_left) = ecs.get_resource::<TradeEncounter>().time_left() {
+                let text = ui
+                    .strings
+                    .format("trade.leaves_in", &[("time", &(time_left.ceil() as u32))]);
+                Label::update_text(ui, title, &text);
+            }
+        }),
+    );
+
+    for index in 0..count {
+        let hbox = layout::create_hbox(ui, Some(vbox), false);
+        Button::create(
+            ui,
+            Some(hbox),
+            "trade.accept",
+            Rc::new(move |_, ecs| {
+                if let Err(error) = trade::accept_offer(&ecs.world, index) {
+                    println!("[Trade] {}", error);
+                }
+            }),
+        );
+
+        // Offers that were traded, or can't be paid for, are greyed out
+        let label = Label::create(ui, Some(hbox), "");
+        ui.set_on_update(
+            label,
+            Rc::new(move |ui, ecs| {
+                let encounter = ecs.get_resource::<TradeEncounter>();
+                let offer = match encounter.offers().and_then(|offers| offers.get(index)) {
+                    Some(offer) => offer,
+                    None => return,
+                };
+                let description = offer.describe(&ecs.get_resource::<Blocks>(), &ui.strings);
+                let text = if offer.taken {
+                    ui.strings
+                        .format("trade.traded", &[("offer", &description)])
+                } else {
+                    description
+                };
+                let available =
+                    !offer.taken && ecs.get_resource::<Inventory>().has_items(&offer.cost);
+                Label::update_text(ui, label, &text);
+                Label::set_color(
+                    ui,
+                    label,
+                    if available { Color::WHITE } else { Color::GRAY },
+                );
+            }),
+        );
+    }
+
+    Button::create(
+        ui,
+        Some(vbox),
+        "menu.close",
+        Rc::new(|_, ecs| ecs.get_resource_mut::<TradeEncounter>().close()),
+    );
+
+    vbox
+}
@@ -63,19 +63,22 @@ impl NodeHandler for ButtonHandler {
 pub struct Button {
     pressed: bool,
     text: RefCell<TextLayout>,
+    /// The key that the text is looked up with, so the text changes with the language
+    key: Option<String>,
     on_action: EventHandler,
 }
 
 impl Button {
+    /// The text is looked up with the key. Text that isn't a key is shown as it is.
     pub fn create(
         ui: &mut Ui,
         parent: Option<NodeId>,
-        text: &str,
+        key: &str,
         on_action: EventHandler,
     ) -> NodeId {
-        let (text, min_size) = new_text_layout(ui, text, BUTTON_PADDING);
+        let (text, min_size) = new_text_layout(ui, ui.strings.get(key), BUTTON_PADDING);
 
-        ui.new_node(
+        let node = ui.new_node(
             parent,
             NodeGeometry {
                 pos: Point2::new(0.0, 0.0),
@@ -88,8 +91,22 @@ impl Button {
                 on_action,
                 pressed: false,
                 text: RefCell::new(text),
+                key: Some(key.to_string()),
             })),
-        )
+        );
+        ui.set_focusable(node, true);
+        node
+    }
+
+    /// Replaces the text, which is no longer changed with the language
+    pub fn update_text(ui: &mut Ui, node: NodeId, text: &str) {
+        let (text, min_size) = new_text_layout(ui, text, BUTTON_PADDING);
+
+        ui.layouts[node.index()].min_size = min_size;
+        ui.geometries[node.arena_index()].size = min_size;
+        let state = ui.states.get_mut::<Button>(node).unwrap();
+        *state.text.borrow_mut() = text;
+        state.key = None;
     }
 }
 
@@ -110,11 +127,15 @@ pub fn create_texture_box(ui: &mut Ui, parent: Option<NodeId>, image: TextureReg
 
 pub struct Label {
     text: RefCell<TextLayout>,
+    /// The key that the text is looked up with, so the text changes with the language
+    key: Option<String>,
 }
 
 impl Label {
-    pub fn create(ui: &mut Ui, parent: Option<NodeId>, text: &str) -> NodeId {
-        let (text, min_size) = new_text_layout(ui, text, LABEL_PADDING);
+    /// The text is looked up with the key. Text that isn't a key (ex. the initial text of a
+    /// label that is updated) is shown as it is.
+    pub fn create(ui: &mut Ui, parent: Option<NodeId>, key: &str) -> NodeId {
+        let (text, min_size) = new_text_layout(ui, ui.strings.get(key), LABEL_PADDING);
 
         ui.new_node(
             parent,
@@ -127,17 +148,27 @@ impl Label {
             Box::new(EmptyNodeHandler),
             Some(Box::new(Label {
                 text: RefCell::new(text),
+                key: Some(key.to_string()),
             })),
         )
     }
 
+    /// Replaces the text, which is no longer changed with the language. The text of labels
+    /// that are updated is expected to be looked up by the updater.
     pub fn update_text(ui: &mut Ui, node: NodeId, text: &str) {
-        let (text, min_size) = new_text_layout(ui, text, LABEL_PADDING);
+        let (mut text, min_size) = new_text_layout(ui, text, LABEL_PADDING);
 
         ui.layouts[node.index()].min_size = min_size;
         ui.geometries[node.arena_index()].size = min_size;
         let state = ui.states.get_mut::<Label>(node).unwrap();
+        text.color = state.text.borrow().color;
         *state.text.borrow_mut() = text;
+        state.key = None;
+    }
+
+    pub fn set_color(ui: &mut Ui, node: NodeId, color: Color) {
+        let state = ui.states.get_mut::<Label>(node).unwrap();
+        state.text.borrow_mut().color = color;
     }
 }
 
@@ -171,3 +202,441 @@ fn new_text_layout(ui: &Ui, text: &str, padding: f32) -> (TextLayout, Point2<f32
 
     (text, min_size)
 }
+
+/// Looks up the text of the widgets again (ex. after the language changed). The labels that
+/// are updated look up their own text.
+pub(super) fn relocalize(ui: &mut Ui) {
+    let nodes: Vec<NodeId> = ui.geometries.iter().map(|(id, _)| NodeId(id)).collect();
+    for node in nodes {
+        let label_key = ui
+            .states
+            .get::<Label>(node)
+            .and_then(|label| label.key.clone());
+        let button_key = ui
+            .states
+            .get::<Button>(node)
+            .and_then(|button| button.key.clone());
+        if let Some(key) = label_key {
+            let text = ui.strings.get(&key).to_string();
+            Label::update_text(ui, node, &text);
+            ui.states.get_mut::<Label>(node).unwrap().key = Some(key);
+        } else if let Some(key) = button_key {
+            let text = ui.strings.get(&key).to_string();
+            Button::update_text(ui, node, &text);
+            ui.states.get_mut::<Button>(node).unwrap().key = Some(key);
+        } else if let Some(keys) = ui.states.get::<Tabs>(node).map(|tabs| tabs.keys.clone()) {
+            let (titles, widths, header_height) = Tabs::layout_titles(ui, &keys);
+            let tabs = ui.states.get_mut::<Tabs>(node).unwrap();
+            tabs.titles = titles;
+            tabs.widths = widths;
+            tabs.header_height = header_height;
+        }
+    }
+}
+
+/// Builds the content of a tab into the node, each time the tab is selected
+pub type TabBuilder = Rc<dyn Fn(&mut Ui, NodeId)>;
+
+/// A row of tabs above the content of the selected tab. Only the selected tab's content
+/// exists, it is rebuilt when another tab is selected. While the tabs have the keyboard
+/// focus, the arrow keys switch between them.
+pub struct Tabs {
+    /// The keys of the titles
+    keys: Vec<String>,
+    titles: Vec<RefCell<TextLayout>>,
+    widths: Vec<f32>,
+    header_height: f32,
+    builders: Vec<TabBuilder>,
+    selected: usize,
+}
+
+impl Tabs {
+    /// The titles are looked up with the keys
+    pub fn create(ui: &mut Ui, parent: Option<NodeId>, tabs: Vec<(&str, TabBuilder)>) -> NodeId {
+        let (keys, builders): (Vec<String>, Vec<TabBuilder>) = tabs
+            .into_iter()
+            .map(|(key, builder)| (key.to_string(), builder))
+            .unzip();
+        let (titles, widths, header_height) = Tabs::layout_titles(ui, &keys);
+
+        let node = ui.new_node(
+            parent,
+            NodeGeometry {
+                pos: Point2::new(0.0, 0.0),
+                size: Point2::new(0.0, 0.0),
+            },
+            NodeLayout::default(),
+            Box::new(TabsRenderer),
+            Box::new(TabsHandler),
+            Some(Box::new(Tabs {
+                keys,
+                titles,
+                widths,
+                header_height,
+                builders,
+                selected: 0,
+            })),
+        );
+        ui.set_focusable(node, true);
+        // The content is the only child of the tabs
+        layout::create_vbox(ui, Some(node), false);
+        Tabs::select(ui, node, 0);
+
+        node
+    }
+
+    /// Replaces the content with the tab's content
+    pub fn select(ui: &mut Ui, node: NodeId, index: usize) {
+        let state = ui.states.get_mut::<Tabs>(node).unwrap();
+        state.selected = index;
+        let builder = state.builders[index].clone();
+
+        let content = ui.children[node.index()][0];
+        for child in ui.children[content.index()].clone() {
+            ui.remove_node(child);
+        }
+        builder(ui, content);
+    }
+
+    /// The text of each title, their widths, and the height of the tallest one
+    fn layout_titles(ui: &Ui, keys: &[String]) -> (Vec<RefCell<TextLayout>>, Vec<f32>, f32) {
+        let mut titles = Vec::new();
+        let mut widths = Vec::new();
+        let mut header_height: f32 = 0.0;
+        for key in keys {
+            let (text, min_size) = new_text_layout(ui, ui.strings.get(key), BUTTON_PADDING);
+            widths.push(min_size.x);
+            header_height = header_height.max(min_size.y);
+            titles.push(RefCell::new(text));
+        }
+        (titles, widths, header_height)
+    }
+
+    /// The tab whose title is at the x position, relative to the left of the tabs
+    fn tab_at(&self, x: f32) -> Option<usize> {
+        let mut left = 0.0;
+        for (index, width) in self.widths.iter().enumerate() {
+            if x >= left && x < left + width {
+                return Some(index);
+            }
+            left += width;
+        }
+        None
+    }
+}
+
+struct TabsRenderer;
+
+impl NodeRenderer for TabsRenderer {
+    fn render(
+        &self,
+        ui_batch: &mut UiBatch,
+        ui: &Ui,
+        node: NodeId,
+        geometry: &NodeGeometry,
+        states: &WidgetStates,
+    ) {
+        let tabs = states.get::<Tabs>(node).unwrap();
+        let mut left = geometry.pos.x;
+        for (index, title) in tabs.titles.iter().enumerate() {
+            let width = tabs.widths[index];
+            let title_geometry = NodeGeometry {
+                pos: Point2::new(left, geometry.pos.y + geometry.size.y - tabs.header_height),
+                size: Point2::new(width, tabs.header_height),
+            };
+            // The selected tab looks held down
+            new_ninepatch_renderer(if index == tabs.selected {
+                ui.assets.button_pressed
+            } else {
+                ui.assets.button
+            })
+            .render(ui_batch, ui, node, &title_geometry, states);
+
+            let mut text = title.borrow_mut();
+            text.offset.x = (width / 2.0) - (text.width / 2.0);
+            text.render(ui_batch, ui, node, &title_geometry, states);
+            left += width;
+        }
+    }
+}
+
+struct TabsHandler;
+
+impl NodeHandler for TabsHandler {
+    /// The titles are above the content
+    fn layout(
+        &self,
+        layout_manager: &LayoutManager,
+        node: NodeId,
+        children: &[NodeId],
+        geometries: &mut WidgetGeometries,
+        layouts: &mut WidgetLayouts,
+        states: &mut WidgetStates,
+    ) {
+        layout_manager.layout_all(children, geometries, layouts, states);
+
+        let tabs = states.get::<Tabs>(node).unwrap();
+        let titles_width: f32 = tabs.widths.iter().sum();
+        let content = children[0];
+        let content_size = geometries[content.arena_index()].size;
+        let size = Point2::new(
+            titles_width.max(content_size.x),
+            content_size.y + tabs.header_height,
+        );
+
+        let pos = geometries[node.arena_index()].pos;
+        layout_manager.move_node(content, pos, geometries);
+        layouts[node.index()].min_size = size;
+        geometries[node.arena_index()].size = size;
+    }
+
+    fn on_click(
+        &self,
+        _: event::MouseButton,
+        click_state: event::ElementState,
+        pt: Point2<f32>,
+        node: NodeId,
+        geometry: &mut NodeGeometry,
+        states: &mut WidgetStates,
+        events: &mut EventQueue,
+    ) -> bool {
+        let tabs = states.get::<Tabs>(node).unwrap();
+        let on_titles = pt.y >= geometry.pos.y + geometry.size.y - tabs.header_height;
+        if click_state != event::ElementState::Pressed || !on_titles {
+            return false;
+        }
+
+        match tabs.tab_at(pt.x - geometry.pos.x) {
+            Some(index) => {
+                if index != tabs.selected {
+                    events.add(Rc::new(move |ui, _| Tabs::select(ui, node, index)));
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The left and right arrow keys select the tab next to the selected one
+    fn on_key(
+        &self,
+        key: event::VirtualKeyCode,
+        node: NodeId,
+        _: &NodeGeometry,
+        states: &mut WidgetStates,
+        events: &mut EventQueue,
+    ) -> bool {
+        let tabs = states.get::<Tabs>(node).unwrap();
+        let index = match key {
+            event::VirtualKeyCode::Left => tabs.selected.checked_sub(1),
+            event::VirtualKeyCode::Right => Some(tabs.selected + 1),
+            _ => return false,
+        };
+        match index.filter(|index| *index < tabs.builders.len()) {
+            Some(index) => {
+                events.add(Rc::new(move |ui, _| Tabs::select(ui, node, index)));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Called with the new value when a slider is moved
+pub type SliderCallback = Rc<dyn Fn(&mut Ui, &mut ECS, f32)>;
+
+/// Picks a value in a range, by dragging its thumb or with the arrow keys while it has the
+/// keyboard focus. The values are snapped to the step.
+pub struct Slider {
+    value: f32,
+    min: f32,
+    max: f32,
+    step: f32,
+    dragging: bool,
+    on_change: SliderCallback,
+}
+
+impl Slider {
+    const WIDTH: f32 = 160.0;
+    const HEIGHT: f32 = 24.0;
+    const THUMB_WIDTH: f32 = 16.0;
+    const TRACK_HEIGHT: f32 = 4.0;
+    const TRACK_COLOR: Color = Color::GRAY;
+    const FILL_COLOR: Color = Color {
+        r: 0.3,
+        g: 0.6,
+        b: 1.0,
+        a: 1.0,
+    };
+
+    pub fn create(
+        ui: &mut Ui,
+        parent: Option<NodeId>,
+        (min, max): (f32, f32),
+        step: f32,
+        on_change: SliderCallback,
+    ) -> NodeId {
+        let min_size = Point2::new(Slider::WIDTH, Slider::HEIGHT);
+        let node = ui.new_node(
+            parent,
+            NodeGeometry {
+                pos: Point2::new(0.0, 0.0),
+                size: min_size,
+            },
+            NodeLayout { min_size },
+            Box::new(SliderRenderer),
+            Box::new(SliderHandler),
+            Some(Box::new(Slider {
+                value: min,
+                min,
+                max,
+                step,
+                dragging: false,
+                on_change,
+            })),
+        );
+        ui.set_focusable(node, true);
+        node
+    }
+
+    /// Moves the slider without calling its callback (ex. to show a value that was changed
+    /// elsewhere)
+    pub fn set_value(ui: &mut Ui, node: NodeId, value: f32) {
+        let slider = ui.states.get_mut::<Slider>(node).unwrap();
+        slider.value = value.clamp(slider.min, slider.max);
+    }
+
+    /// How far (0 to 1) the value is along the slider
+    fn fraction(&self) -> f32 {
+        if self.max > self.min {
+            (self.value - self.min) / (self.max - self.min)
+        } else {
+            0.0
+        }
+    }
+
+    /// The value under the x position, with the thumb centered on it
+    fn value_at(&self, geometry: &NodeGeometry, x: f32) -> f32 {
+        let track = geometry.size.x - Slider::THUMB_WIDTH;
+        let fraction = (x - geometry.pos.x - Slider::THUMB_WIDTH / 2.0) / track;
+        self.min + fraction.clamp(0.0, 1.0) * (self.max - self.min)
+    }
+
+    /// Snaps the value to the step, and calls the callback if it changed
+    fn change(&mut self, value: f32, events: &mut EventQueue) {
+        let steps = ((value - self.min) / self.step).round();
+        let value = (self.min + steps * self.step).clamp(self.min, self.max);
+        if (value - self.value).abs() > f32::EPSILON {
+            self.value = value;
+            let on_change = self.on_change.clone();
+            events.add(Rc::new(move |ui, ecs| on_change(ui, ecs, value)));
+        }
+    }
+}
+
+struct SliderRenderer;
+
+impl NodeRenderer for SliderRenderer {
+    fn render(
+        &self,
+        ui_batch: &mut UiBatch,
+        ui: &Ui,
+        node: NodeId,
+        geometry: &NodeGeometry,
+        states: &WidgetStates,
+    ) {
+        let slider = states.get::<Slider>(node).unwrap();
+        let track_width = geometry.size.x - Slider::THUMB_WIDTH;
+        let left = geometry.pos.x + Slider::THUMB_WIDTH / 2.0;
+        let track_y = geometry.pos.y + (geometry.size.y - Slider::TRACK_HEIGHT) / 2.0;
+        let draw_track = |ui_batch: &mut UiBatch, width: f32, color: Color| {
+            ui_batch.draw(
+                Vector4::new(left, track_y, width, Slider::TRACK_HEIGHT),
+                ui.assets.white,
+                Vector4::new(color.r, color.g, color.b, color.a),
+            );
+        };
+        draw_track(ui_batch, track_width, Slider::TRACK_COLOR);
+        draw_track(
+            ui_batch,
+            track_width * slider.fraction(),
+            Slider::FILL_COLOR,
+        );
+
+        let thumb = NodeGeometry {
+            pos: Point2::new(
+                geometry.pos.x + track_width * slider.fraction(),
+                geometry.pos.y,
+            ),
+            size: Point2::new(Slider::THUMB_WIDTH, geometry.size.y),
+        };
+        new_ninepatch_renderer(if slider.dragging {
+            ui.assets.button_pressed
+        } else {
+            ui.assets.button
+        })
+        .render(ui_batch, ui, node, &thumb, states);
+    }
+}
+
+struct SliderHandler;
+
+impl NodeHandler for SliderHandler {
+    fn on_click(
+        &self,
+        _: event::MouseButton,
+        click_state: event::ElementState,
+        pt: Point2<f32>,
+        node: NodeId,
+        geometry: &mut NodeGeometry,
+        states: &mut WidgetStates,
+        events: &mut EventQueue,
+    ) -> bool {
+        let slider = states.get_mut::<Slider>(node).unwrap();
+        slider.dragging = click_state == event::ElementState::Pressed;
+        if slider.dragging {
+            let value = slider.value_at(geometry, pt.x);
+            slider.change(value, events);
+        }
+
+        slider.dragging
+    }
+
+    fn on_mouse_focus_lost(&self, node: NodeId, states: &mut WidgetStates) {
+        states.get_mut::<Slider>(node).unwrap().dragging = false;
+    }
+
+    fn on_mouse_drag(
+        &self,
+        pt: Point2<f32>,
+        node: NodeId,
+        geometry: &mut NodeGeometry,
+        states: &mut WidgetStates,
+        events: &mut EventQueue,
+    ) {
+        let slider = states.get_mut::<Slider>(node).unwrap();
+        if slider.dragging {
+            let value = slider.value_at(geometry, pt.x);
+            slider.change(value, events);
+        }
+    }
+
+    fn on_key(
+        &self,
+        key: event::VirtualKeyCode,
+        node: NodeId,
+        _: &NodeGeometry,
+        states: &mut WidgetStates,
+        events: &mut EventQueue,
+    ) -> bool {
+        let slider = states.get_mut::<Slider>(node).unwrap();
+        let direction = match key {
+            event::VirtualKeyCode::Left | event::VirtualKeyCode::Down => -1.0,
+            event::VirtualKeyCode::Right | event::VirtualKeyCode::Up => 1.0,
+            _ => return false,
+        };
+        let value = slider.value + direction * slider.step;
+        slider.change(value, events);
+        true
+    }
+}